@@ -0,0 +1,159 @@
+use crate::entry::Entry;
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use http::StatusCode;
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+};
+
+// `--after-status 401,403 --within 10m` keeps only entries from clients that got one of those
+// statuses a little earlier in the log, e.g. what an address did after failing to log in. Every
+// parsed entry is learned from, whether or not it matches, and only entries the other filters
+// let through are judged. Each client's flagged timestamps sit in a queue pruned to the window,
+// which only works for input in time order; the scanner warns when it isn't.
+
+/// `--after-status` and `--within`.
+#[derive(Clone, Debug)]
+pub struct AfterStatus {
+    pub statuses: Vec<StatusCode>,
+    pub within: TimeDelta,
+}
+
+impl AfterStatus {
+    /// The condition as --explain-filter shows it, e.g. `got 401 (Unauthorized) in the 600s before`.
+    pub fn describe(&self) -> String {
+        let statuses: Vec<String> = self
+            .statuses
+            .iter()
+            .map(|status| match status.canonical_reason() {
+                Some(reason) => format!("{} ({})", status.as_u16(), reason),
+                None => status.as_u16().to_string(),
+            })
+            .collect();
+        format!("got {} in the {}s before", statuses.join(" or "), self.within.num_seconds())
+    }
+}
+
+/// When each client recently got one of the --after-status statuses.
+pub struct StatusHistory {
+    spec: AfterStatus,
+    flagged: HashMap<IpAddr, VecDeque<DateTime<FixedOffset>>>,
+    next_prune: Option<DateTime<FixedOffset>>,
+    warned: bool,
+}
+
+impl StatusHistory {
+    pub fn new(spec: AfterStatus) -> Self {
+        StatusHistory { spec, flagged: HashMap::new(), next_prune: None, warned: false }
+    }
+
+    /// Whether `entry`'s client got a flagged status within the window before it. Entries
+    /// without a client or a timestamp never do.
+    pub fn admit(&mut self, entry: &Entry) -> bool {
+        let (Some(ip), Some(now)) = (entry.ip(), entry.timestamp) else {
+            return false;
+        };
+        if self.next_prune.is_some_and(|next| now >= next) {
+            self.prune(now);
+        }
+        self.next_prune.get_or_insert(now + self.spec.within);
+        let Some(times) = self.flagged.get_mut(&ip) else {
+            return false;
+        };
+        while times.front().is_some_and(|&time| time < now - self.spec.within) {
+            times.pop_front();
+        }
+        !times.is_empty()
+    }
+
+    /// Notes `entry` if it got one of the flagged statuses, after `admit` has judged it.
+    pub fn observe(&mut self, entry: &Entry) {
+        let flagged = entry.status_code.is_some_and(|status| self.spec.statuses.contains(&status));
+        if let (true, Some(ip), Some(time)) = (flagged, entry.ip(), entry.timestamp) {
+            self.flagged.entry(ip).or_default().push_back(time);
+        }
+    }
+
+    /// Drops timestamps that fell out of the window, so clients seen once don't accumulate.
+    fn prune(&mut self, now: DateTime<FixedOffset>) {
+        let oldest = now - self.spec.within;
+        self.flagged.retain(|_, times| times.back().is_some_and(|&time| time >= oldest));
+        self.next_prune = Some(now + self.spec.within);
+    }
+
+    /// True only for the first regression, which is all a run warns about.
+    pub fn first_regression(&mut self) -> bool {
+        !std::mem::replace(&mut self.warned, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(ip: &str, time: &str, status: u16) -> Entry {
+        Entry {
+            client: Some(ip.parse().unwrap()),
+            timestamp: Some(DateTime::parse_from_rfc3339(time).unwrap()),
+            status_code: StatusCode::from_u16(status).ok(),
+            ..Entry::default()
+        }
+    }
+
+    fn history() -> StatusHistory {
+        StatusHistory::new(AfterStatus {
+            statuses: vec![StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN],
+            within: TimeDelta::minutes(10),
+        })
+    }
+
+    /// Each request admitted or not, learning from all of them.
+    fn run(history: &mut StatusHistory, requests: &[Entry]) -> Vec<bool> {
+        requests
+            .iter()
+            .map(|entry| {
+                let admitted = history.admit(entry);
+                history.observe(entry);
+                admitted
+            })
+            .collect()
+    }
+
+    #[test]
+    fn only_clients_flagged_within_the_window_are_admitted() {
+        let requests = [
+            request("10.0.0.1", "2023-02-12T14:00:00Z", 200),
+            request("10.0.0.1", "2023-02-12T14:01:00Z", 401),
+            request("10.0.0.1", "2023-02-12T14:02:00Z", 200),
+            request("10.0.0.2", "2023-02-12T14:03:00Z", 200),
+            request("10.0.0.1", "2023-02-12T14:11:00Z", 200),
+            request("10.0.0.1", "2023-02-12T14:11:01Z", 200),
+        ];
+        assert_eq!(run(&mut history(), &requests), [false, false, true, false, true, false]);
+    }
+
+    #[test]
+    fn a_flagged_request_counts_only_for_later_ones() {
+        let requests = [request("10.0.0.1", "2023-02-12T14:00:00Z", 403), request("10.0.0.1", "2023-02-12T14:00:00Z", 403)];
+        assert_eq!(run(&mut history(), &requests), [false, true]);
+        let mut history = history();
+        assert!(!history.admit(&Entry { client: Some("10.0.0.1".parse().unwrap()), ..Entry::default() }));
+        assert!(history.first_regression());
+        assert!(!history.first_regression());
+    }
+
+    #[test]
+    fn pruning_drops_clients_whose_window_closed() {
+        let mut history = history();
+        run(
+            &mut history,
+            &[
+                request("10.0.0.1", "2023-02-12T14:00:00Z", 401),
+                request("10.0.0.2", "2023-02-12T14:05:00Z", 401),
+                request("10.0.0.3", "2023-02-12T14:20:00Z", 200),
+            ],
+        );
+        assert_eq!(history.flagged.len(), 0);
+        assert_eq!(history.spec.describe(), "got 401 (Unauthorized) or 403 (Forbidden) in the 600s before");
+    }
+}
@@ -0,0 +1,37 @@
+use crate::output::style::{style, Element};
+
+// `filter --annotate` prints every input line behind a marker, so the boundary a filter draws
+// can be checked in context. Lines are colored as --color and the theme say.
+
+/// Inputs larger than this need --force with --annotate, since every line is printed.
+pub const MAX_INPUT: u64 = 64 << 20;
+
+#[derive(Clone, Copy)]
+pub enum Marker {
+    Match,
+    /// Parsed, or blank, but not matched.
+    Miss,
+    Malformed,
+}
+
+impl Marker {
+    fn text(self) -> &'static str {
+        match self {
+            Marker::Match => "MATCH ",
+            Marker::Miss => "    . ",
+            Marker::Malformed => "ERR   ",
+        }
+    }
+
+    fn element(self) -> Element {
+        match self {
+            Marker::Match => Element::Highlight,
+            Marker::Miss => Element::Miss,
+            Marker::Malformed => Element::Malformed,
+        }
+    }
+}
+
+pub fn print(marker: Marker, line: &str) {
+    println!("{}", style().paint(marker.element(), &format!("{}{}", marker.text(), line)))
+}
@@ -0,0 +1,241 @@
+use crate::entry::Entry;
+use crate::group::{self, GroupBy};
+use crate::novel::Window;
+use crate::order::Order;
+use crate::output::{
+    schema::{Anomaly, Baseline},
+    table::Table,
+};
+use crate::stats::{Metric, StatsOutput, Welford};
+use crate::zone::Zone;
+use serde_json::json;
+use std::collections::HashMap;
+
+// `anomalies` learns the mean and standard deviation of a metric per --by group in a baseline
+// window of log time, then flags the groups whose mean in the target window lies more than
+// --sigma standard deviations away, e.g. an endpoint whose responses suddenly grew tenfold.
+// Both windows are summarized as they are read, so one pass over the input is enough; only
+// --emit-entries, which judges entries one by one, needs the baseline complete first.
+
+/// At least this many baseline values before a group's spread means anything.
+const MIN_BASELINE: usize = 2;
+
+pub struct Anomalies {
+    by: Vec<GroupBy>,
+    tz: Option<Zone>,
+    metric: Metric,
+    baseline: Window,
+    /// `None` for everything timestamped outside the baseline window.
+    target: Option<Window>,
+    sigma: f64,
+    max_groups: usize,
+    /// The metric in the baseline and in the target window, per group.
+    groups: HashMap<Vec<String>, (Welford, Welford)>,
+}
+
+/// A group whose target mean strayed from its baseline.
+pub struct Finding {
+    key: Vec<String>,
+    baseline: Welford,
+    stddev: f64,
+    observed: Welford,
+    deviation: f64,
+}
+
+/// How many standard deviations `value` lies from `mean`; infinite when a baseline that never
+/// varied is left.
+fn deviation(value: f64, mean: f64, stddev: f64) -> f64 {
+    match stddev {
+        0.0 if value == mean => 0.0,
+        0.0 => f64::INFINITY.copysign(value - mean),
+        _ => (value - mean) / stddev,
+    }
+}
+
+impl Anomalies {
+    /// Fails when a window ends before it starts or the windows overlap.
+    pub fn new(
+        by: Vec<GroupBy>,
+        tz: Option<Zone>,
+        metric: Metric,
+        baseline: Window,
+        target: Option<Window>,
+        sigma: f64,
+        max_groups: usize,
+    ) -> Result<Self, String> {
+        for (window, name) in [(Some(baseline), "baseline"), (target, "target")] {
+            if window.is_some_and(|window| window.until <= window.since) {
+                return Err(format!("--{}-until must be later than --{}-since", name, name));
+            }
+        }
+        if target.is_some_and(|target| baseline.since < target.until && target.since < baseline.until) {
+            return Err("the baseline and target windows overlap".to_string());
+        }
+        if sigma.is_nan() || sigma <= 0.0 {
+            return Err("--sigma must be greater than 0".to_string());
+        }
+        Ok(Anomalies { by, tz, metric, baseline, target, sigma, max_groups, groups: HashMap::new() })
+    }
+
+    fn in_target(&self, entry: &Entry) -> bool {
+        match self.target {
+            Some(target) => target.contains(entry),
+            None => entry.timestamp.is_some() && !self.baseline.contains(entry),
+        }
+    }
+
+    fn group(&mut self, entry: &Entry) -> Result<&mut (Welford, Welford), String> {
+        let key = group::key(&self.by, entry, self.tz);
+        if !self.groups.contains_key(&key) && self.groups.len() >= self.max_groups {
+            return Err(group::too_many(&self.by, self.max_groups));
+        }
+        Ok(self.groups.entry(key).or_default())
+    }
+
+    /// Counts an entry's metric towards its group's baseline or target.
+    pub fn add(&mut self, entry: &Entry) -> Result<(), String> {
+        let Some(value) = self.metric.value(entry) else {
+            return Ok(());
+        };
+        match (self.baseline.contains(entry), self.in_target(entry)) {
+            (true, _) => self.group(entry)?.0.add(value),
+            (false, true) => self.group(entry)?.1.add(value),
+            (false, false) => {}
+        }
+        Ok(())
+    }
+
+    /// Counts only baseline entries, the first pass of --emit-entries.
+    pub fn learn(&mut self, entry: &Entry) -> Result<(), String> {
+        match self.baseline.contains(entry) {
+            true => self.add(entry),
+            false => Ok(()),
+        }
+    }
+
+    /// How far a target entry's value lies from its group's baseline, in standard deviations,
+    /// when that's more than --sigma.
+    pub fn deviant(&self, entry: &Entry) -> Option<f64> {
+        let value = self.metric.value(entry).filter(|_| self.in_target(entry))?;
+        let (baseline, _) = self.groups.get(&group::key(&self.by, entry, self.tz))?;
+        let stddev = baseline.stddev().filter(|_| baseline.count >= MIN_BASELINE)?;
+        Some(deviation(value, baseline.mean, stddev)).filter(|deviation| deviation.abs() > self.sigma)
+    }
+
+    /// The groups beyond --sigma, in `order` by how far out they are. Groups with too little
+    /// baseline to judge are left out, as are groups with nothing in the target window.
+    pub fn finish(self, order: Order) -> Vec<Finding> {
+        let mut findings: Vec<Finding> = self
+            .groups
+            .into_iter()
+            .filter_map(|(key, (baseline, observed))| {
+                let stddev = baseline.stddev().filter(|_| baseline.count >= MIN_BASELINE && observed.count > 0)?;
+                let deviation = deviation(observed.mean, baseline.mean, stddev);
+                (deviation.abs() > self.sigma).then_some(Finding { key, baseline, stddev, observed, deviation })
+            })
+            .collect();
+        order.sort(&mut findings, |finding| (finding.deviation.abs(), &finding.key));
+        findings
+    }
+}
+
+/// A table, or one JSON object per finding. An infinite deviation is `inf` in the table and
+/// `null` in JSON.
+pub fn render(by: &[GroupBy], findings: &[Finding], output: StatsOutput) -> String {
+    let names = group::names(by);
+    match output {
+        StatsOutput::Json | StatsOutput::JsonArray => {
+            let mut out = String::new();
+            for finding in findings {
+                let anomaly = Anomaly {
+                    group: names.iter().cloned().zip(finding.key.iter().cloned()).collect(),
+                    baseline: Baseline { count: finding.baseline.count, mean: finding.baseline.mean, stddev: finding.stddev },
+                    count: finding.observed.count,
+                    observed: finding.observed.mean,
+                    deviation: finding.deviation,
+                };
+                out += &format!("{}\n", json!(anomaly));
+            }
+            output.document(out)
+        }
+        StatsOutput::Table => {
+            let mut header = names;
+            header.extend(["baseline_count", "baseline_mean", "baseline_stddev", "count", "observed", "deviation"].map(str::to_string));
+            let mut table = Table::new(&header);
+            for finding in findings {
+                let mut row = finding.key.clone();
+                row.extend([
+                    finding.baseline.count.to_string(),
+                    finding.baseline.mean.to_string(),
+                    format!("{:.2}", finding.stddev),
+                    finding.observed.count.to_string(),
+                    finding.observed.mean.to_string(),
+                    format!("{:.2}", finding.deviation),
+                ]);
+                table.row(row);
+            }
+            table.render()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, FixedOffset};
+
+    fn at(time: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(time).unwrap()
+    }
+
+    fn request(time: &str, path: &str, bytes: u64) -> Entry {
+        Entry { timestamp: Some(at(time)), path: Some(path.to_string()), bytes: Some(bytes), ..Entry::default() }
+    }
+
+    fn anomalies() -> Anomalies {
+        let baseline = Window { since: at("2023-02-12T13:00:00Z"), until: at("2023-02-12T14:00:00Z") };
+        Anomalies::new(vec![GroupBy::Path], None, Metric::Bytes, baseline, None, 3.0, 10).unwrap()
+    }
+
+    #[test]
+    fn groups_far_from_their_baseline_are_flagged() {
+        let mut anomalies = anomalies();
+        for (path, bytes) in [("/grew", 490), ("/grew", 500), ("/grew", 510), ("/steady", 100), ("/steady", 100), ("/jitter", 10), ("/jitter", 30)] {
+            anomalies.add(&request("2023-02-12T13:30:00Z", path, bytes)).unwrap();
+        }
+        for (path, bytes) in [("/grew", 5000), ("/steady", 100), ("/jitter", 40), ("/new", 1)] {
+            anomalies.add(&request("2023-02-12T14:30:00Z", path, bytes)).unwrap();
+        }
+        assert_eq!(anomalies.deviant(&request("2023-02-12T14:30:00Z", "/grew", 5000)), Some(450.0));
+        assert_eq!(anomalies.deviant(&request("2023-02-12T14:30:00Z", "/grew", 520)), None);
+        assert_eq!(anomalies.deviant(&request("2023-02-12T13:30:00Z", "/grew", 5000)), None);
+        assert_eq!(anomalies.deviant(&request("2023-02-12T14:30:00Z", "/steady", 101)), Some(f64::INFINITY));
+
+        anomalies.add(&request("2023-02-12T14:40:00Z", "/steady", 400)).unwrap();
+        let findings = anomalies.finish(Order::default());
+        assert_eq!(
+            render(&[GroupBy::Path], &findings, StatsOutput::Table),
+            "path\tbaseline_count\tbaseline_mean\tbaseline_stddev\tcount\tobserved\tdeviation\n\
+             /steady\t2\t100\t0.00\t2\t250\tinf\n/grew\t3\t500\t10.00\t1\t5000\t450.00\n"
+        );
+        assert_eq!(
+            render(&[GroupBy::Path], &findings[..1], StatsOutput::Json),
+            "{\"baseline\":{\"count\":2,\"mean\":100.0,\"stddev\":0.0},\"count\":2,\"deviation\":null,\"observed\":250.0,\"path\":\"/steady\"}\n"
+        );
+    }
+
+    #[test]
+    fn windows_must_be_ordered_and_apart() {
+        let window = |since: &str, until: &str| Window { since: at(since), until: at(until) };
+        let new = |baseline, target| Anomalies::new(vec![GroupBy::Path], None, Metric::Bytes, baseline, target, 3.0, 10);
+        let hour = window("2023-02-12T13:00:00Z", "2023-02-12T14:00:00Z");
+        assert_eq!(
+            new(hour, Some(window("2023-02-12T13:30:00Z", "2023-02-12T14:30:00Z"))).err().as_deref(),
+            Some("the baseline and target windows overlap")
+        );
+        assert_eq!(
+            new(window("2023-02-12T14:00:00Z", "2023-02-12T13:00:00Z"), None).err().as_deref(),
+            Some("--baseline-until must be later than --baseline-since")
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use crate::entry::Entry;
+use crate::order::Order;
+use crate::output::{self, table::Table};
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use clap::ValueEnum;
+use http::StatusCode;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+};
+
+// Audits basic-auth challenge flows: 401 and 407 responses are failed attempts, counted per
+// client address and per username tried (the authuser field), and a 2xx or 3xx response to a
+// client within the window after one of its failures counts as a login after failing, which
+// after many failures is what a successful dictionary attack looks like. Each client keeps just
+// its latest failure time, so timestamps are assumed to be roughly in order.
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum AuthOutput {
+    #[default]
+    Table,
+    Json,
+}
+
+/// Usernames listed per client.
+const SAMPLE: usize = 5;
+
+fn is_failure(status: StatusCode) -> bool {
+    matches!(status, StatusCode::UNAUTHORIZED | StatusCode::PROXY_AUTHENTICATION_REQUIRED)
+}
+
+fn is_success(status: StatusCode) -> bool {
+    status.is_success() || status.is_redirection()
+}
+
+#[derive(Default)]
+struct Client {
+    failures: usize,
+    logins_after_failure: usize,
+    /// Usernames tried, in the order first tried.
+    users: Vec<String>,
+    seen: HashSet<String>,
+    first_failure: Option<DateTime<FixedOffset>>,
+    last_failure: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Default)]
+struct User {
+    failures: usize,
+    logins_after_failure: usize,
+    clients: HashSet<IpAddr>,
+}
+
+/// A client that failed to authenticate at least once.
+#[derive(Serialize)]
+pub struct ClientRow {
+    pub ip: IpAddr,
+    pub failures: usize,
+    pub logins_after_failure: usize,
+    /// Distinct usernames tried.
+    pub users: usize,
+    pub sample_users: Vec<String>,
+    #[serde(serialize_with = "output::serialize_timestamp")]
+    pub first_failure: Option<DateTime<FixedOffset>>,
+    #[serde(serialize_with = "output::serialize_timestamp")]
+    pub last_failure: Option<DateTime<FixedOffset>>,
+    /// Over --max-failures.
+    pub flagged: bool,
+}
+
+/// A username tried at least once and failed.
+#[derive(Serialize)]
+pub struct UserRow {
+    pub user: String,
+    pub failures: usize,
+    pub logins_after_failure: usize,
+    /// Distinct clients that tried it.
+    pub ips: usize,
+}
+
+/// Clients, then usernames, each in the order asked for.
+pub struct Report {
+    pub clients: Vec<ClientRow>,
+    pub users: Vec<UserRow>,
+}
+
+impl Report {
+    pub fn flagged(&self) -> usize {
+        self.clients.iter().filter(|client| client.flagged).count()
+    }
+}
+
+/// Counts authentication outcomes over the matched entries.
+pub struct Audit {
+    window: TimeDelta,
+    clients: HashMap<IpAddr, Client>,
+    users: HashMap<String, User>,
+}
+
+impl Audit {
+    pub fn new(window: TimeDelta) -> Self {
+        Audit { window, clients: HashMap::new(), users: HashMap::new() }
+    }
+
+    pub fn add(&mut self, entry: &Entry) {
+        let Some(status) = entry.status_code else {
+            return;
+        };
+        let user = entry.user.as_deref().filter(|user| !user.is_empty() && *user != "-");
+        let ip = entry.ip();
+        if is_failure(status) {
+            if let Some(user) = user {
+                let counts = self.users.entry(user.to_string()).or_default();
+                counts.failures += 1;
+                counts.clients.extend(ip);
+            }
+            let Some(ip) = ip else {
+                return;
+            };
+            let client = self.clients.entry(ip).or_default();
+            client.failures += 1;
+            if let Some(user) = user.filter(|user| !client.seen.contains(*user)) {
+                client.seen.insert(user.to_string());
+                client.users.push(user.to_string());
+            }
+            if let Some(now) = entry.timestamp {
+                client.first_failure = client.first_failure.or(Some(now));
+                client.last_failure = Some(client.last_failure.map_or(now, |last| last.max(now)));
+            }
+        }
+        else if is_success(status) {
+            let (Some(ip), Some(now)) = (ip, entry.timestamp) else {
+                return;
+            };
+            let Some(client) = self.clients.get_mut(&ip) else {
+                return;
+            };
+            if !client.last_failure.is_some_and(|last| now >= last && now - last <= self.window) {
+                return;
+            }
+            client.logins_after_failure += 1;
+            if let Some(counts) = user.and_then(|user| self.users.get_mut(user)) {
+                counts.logins_after_failure += 1;
+            }
+        }
+    }
+
+    /// The report, in `order` by failures, flagging clients with more than `max_failures`.
+    pub fn finish(self, max_failures: usize, order: Order) -> Report {
+        let mut clients: Vec<ClientRow> = self
+            .clients
+            .into_iter()
+            .map(|(ip, client)| ClientRow {
+                ip,
+                failures: client.failures,
+                logins_after_failure: client.logins_after_failure,
+                users: client.users.len(),
+                sample_users: client.users.into_iter().take(SAMPLE).collect(),
+                first_failure: client.first_failure,
+                last_failure: client.last_failure,
+                flagged: client.failures > max_failures,
+            })
+            .collect();
+        order.sort(&mut clients, |client| (client.failures, &client.ip));
+        let mut users: Vec<UserRow> = self
+            .users
+            .into_iter()
+            .map(|(user, counts)| UserRow {
+                user,
+                failures: counts.failures,
+                logins_after_failure: counts.logins_after_failure,
+                ips: counts.clients.len(),
+            })
+            .collect();
+        order.sort(&mut users, |user| (user.failures, user.user.as_str()));
+        Report { clients, users }
+    }
+}
+
+/// Renders the report: a table of clients and one of usernames, or with JSON a record per
+/// client and then per username. Nothing when there were no failures.
+pub fn render(report: &Report, output: AuthOutput) -> Result<String, String> {
+    let mut out = String::new();
+    match output {
+        AuthOutput::Json => {
+            for client in &report.clients {
+                out += &serde_json::to_string(client).map_err(|e| e.to_string())?;
+                out.push('\n');
+            }
+            for user in &report.users {
+                out += &serde_json::to_string(user).map_err(|e| e.to_string())?;
+                out.push('\n');
+            }
+        }
+        AuthOutput::Table if report.clients.is_empty() && report.users.is_empty() => {}
+        AuthOutput::Table => {
+            let time = |t: Option<DateTime<FixedOffset>>| t.map_or_else(|| "-".to_string(), |t| t.to_rfc3339());
+            let mut clients = Table::new(&[
+                "ip",
+                "failures",
+                "logins_after_failure",
+                "users",
+                "sample_users",
+                "first_failure",
+                "last_failure",
+                "flagged",
+            ]);
+            for c in &report.clients {
+                clients.row(vec![
+                    c.ip.to_string(),
+                    c.failures.to_string(),
+                    c.logins_after_failure.to_string(),
+                    c.users.to_string(),
+                    c.sample_users.join(","),
+                    time(c.first_failure),
+                    time(c.last_failure),
+                    if c.flagged { "yes" } else { "no" }.to_string(),
+                ]);
+            }
+            out += &clients.render();
+            if !report.users.is_empty() {
+                let mut users = Table::new(&["user", "failures", "logins_after_failure", "ips"]);
+                for u in &report.users {
+                    users.row(vec![u.user.clone(), u.failures.to_string(), u.logins_after_failure.to_string(), u.ips.to_string()]);
+                }
+                out.push('\n');
+                out += &users.render();
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ip: &str, user: &str, status: u16, time: &str) -> Entry {
+        Entry {
+            client: Some(ip.parse().unwrap()),
+            user: Some(user.to_string()),
+            status_code: Some(StatusCode::from_u16(status).unwrap()),
+            timestamp: Some(DateTime::parse_from_rfc3339(time).unwrap()),
+            ..Entry::default()
+        }
+    }
+
+    fn audit(entries: &[(&str, &str, u16, &str)], max_failures: usize) -> Report {
+        let mut audit = Audit::new(TimeDelta::minutes(5));
+        for &(ip, user, status, time) in entries {
+            audit.add(&entry(ip, user, status, time));
+        }
+        audit.finish(max_failures, Order::default())
+    }
+
+    #[test]
+    fn logins_soon_after_a_failure_are_counted() {
+        let report = audit(
+            &[
+                ("192.0.2.1", "admin", 401, "2023-02-12T14:00:00Z"),
+                ("192.0.2.1", "root", 401, "2023-02-12T14:00:01Z"),
+                ("192.0.2.1", "root", 407, "2023-02-12T14:00:02Z"),
+                ("192.0.2.1", "root", 200, "2023-02-12T14:04:00Z"),
+                // Too long after the last failure.
+                ("192.0.2.1", "root", 200, "2023-02-12T14:10:00Z"),
+                // No failure from this client.
+                ("192.0.2.2", "alice", 200, "2023-02-12T14:00:03Z"),
+                ("192.0.2.2", "-", 401, "2023-02-12T14:00:04Z"),
+            ],
+            2,
+        );
+        let clients: Vec<_> = report.clients.iter().map(|c| (c.ip.to_string(), c.failures, c.logins_after_failure, c.flagged)).collect();
+        assert_eq!(clients, [("192.0.2.1".to_string(), 3, 1, true), ("192.0.2.2".to_string(), 1, 0, false)]);
+        assert_eq!(report.clients[0].sample_users, ["admin", "root"]);
+        assert_eq!(report.clients[1].users, 0);
+        let users: Vec<_> = report.users.iter().map(|u| (u.user.as_str(), u.failures, u.logins_after_failure, u.ips)).collect();
+        assert_eq!(users, [("root", 2, 1, 1), ("admin", 1, 0, 1)]);
+        assert_eq!(report.flagged(), 1);
+    }
+
+    #[test]
+    fn redirects_count_as_logins_but_other_errors_dont() {
+        let report = audit(
+            &[
+                ("192.0.2.1", "bob", 401, "2023-02-12T14:00:00Z"),
+                ("192.0.2.1", "bob", 403, "2023-02-12T14:00:01Z"),
+                ("192.0.2.1", "bob", 302, "2023-02-12T14:00:02Z"),
+            ],
+            10,
+        );
+        assert_eq!((report.clients[0].failures, report.clients[0].logins_after_failure, report.clients[0].flagged), (1, 1, false));
+        assert_eq!(report.flagged(), 0);
+    }
+
+    #[test]
+    fn reports_without_failures_print_nothing() {
+        let report = audit(&[("192.0.2.1", "bob", 200, "2023-02-12T14:00:00Z")], 10);
+        assert_eq!(render(&report, AuthOutput::Table).unwrap(), "");
+        assert_eq!(render(&report, AuthOutput::Json).unwrap(), "");
+    }
+}
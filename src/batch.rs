@@ -0,0 +1,348 @@
+use crate::config::{self, Line, Setting};
+use crate::diagnostics::{Error, Reporter};
+use crate::entry::Match;
+use crate::filter::{EntryFilter, FilterArgs};
+use crate::output::{self, render_match, schema::{self, BatchJob}, table::Table, FieldSelection, OutputFormat};
+use crate::parsers::LogKind;
+use crate::registry;
+use crate::rules::choices;
+use crate::sink::Sink;
+use crate::stats::StatsOutput;
+use clap::{Parser, ValueEnum};
+use rs_filter::Filterable;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+// `batch --jobs-file` runs the fixed queries of a morning over one pass of the input: each line
+// is read and parsed once, and its entry goes to every job whose filter it matches. The jobs
+// file is written like the config file, with a section per job:
+//
+//     [errors]
+//     filter = ["--status-code", "gte", "500"]    # filter options, as they'd follow `filter`
+//     output = "json"                             # line, json, json-array or combined
+//     file = "errors.json"
+//
+//     [large]
+//     where = "bytes > 1000000"
+//     fields = ["ip", "path"]                     # rows of these fields instead
+//     file = "large.tsv.gz"
+//
+// A job filters with `filter`, `where` or both and writes its matches to `file`, gzipped when
+// the name ends in .gz. A job may name the `log_type` it's written for; one pass parses one log
+// type, so a job at odds with --log-type or --format-string is rejected before any input is
+// read. With --log-type auto the jobs settle the log type, and must agree on it.
+
+const KEYS: &[&str] = &["filter", "where", "output", "fields", "file", "log_type"];
+
+/// Filter options as they'd follow `filter` on the command line.
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct FilterOptions {
+    #[command(flatten)]
+    filter: FilterArgs,
+}
+
+pub struct Job {
+    name: String,
+    /// The line of the job's `[name]` header.
+    line: usize,
+    filter: EntryFilter,
+    output: OutputFormat,
+    fields: Vec<String>,
+    file: PathBuf,
+    log_type: Option<LogKind>,
+}
+
+impl Job {
+    /// Whether the job reads the field `name`: as one it names, or with the whole entry.
+    pub fn reads(&self, name: &str) -> bool {
+        self.names(name) || (self.fields.is_empty() && matches!(self.output, OutputFormat::Json | OutputFormat::JsonArray))
+    }
+
+    /// Whether the job's filter or fields name the field `name`.
+    pub fn names(&self, name: &str) -> bool {
+        self.filter.reads(name) || self.fields.iter().any(|field| field == name)
+    }
+}
+
+/// A job as written: its header's line and its settings.
+struct Section {
+    name: String,
+    line: usize,
+    settings: Vec<Setting>,
+}
+
+fn kind_name(kind: LogKind) -> String {
+    kind.to_possible_value().map_or_else(String::new, |value| value.get_name().to_string())
+}
+
+impl Section {
+    fn get(&self, key: &str) -> Option<&Setting> {
+        self.settings.iter().find(|setting| setting.key == key)
+    }
+
+    fn text(&self, key: &str) -> Result<Option<&str>, (usize, String)> {
+        self.get(key).map(|setting| setting.text().map_err(|message| (setting.line, message))).transpose()
+    }
+
+    fn list(&self, key: &str) -> Result<&[String], (usize, String)> {
+        self.get(key).map_or(Ok(&[]), |setting| setting.list().map_err(|message| (setting.line, message)))
+    }
+
+    /// The job, or the line of its first mistake and what it is.
+    fn job(&self, reporter: &Reporter) -> Result<Job, (usize, String)> {
+        let line_of = |key: &str| self.get(key).map_or(self.line, |setting| setting.line);
+        let filter_line = if self.get("filter").is_some() { line_of("filter") } else { line_of("where") };
+        let mut args = self.list("filter")?.to_vec();
+        if let Some(condition) = self.text("where")? {
+            args.extend(["--where".to_string(), condition.to_string()]);
+        }
+        if args.is_empty() {
+            let message = "no filter; give filter = [...] or where = \"...\", or filter = [\"--all\"] for every entry";
+            return Err((self.line, message.to_string()));
+        }
+        let options = FilterOptions::try_parse_from(&args).map_err(|e| {
+            let message = e.to_string();
+            (filter_line, message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string())
+        })?;
+        if options.filter.explain_filter {
+            return Err((filter_line, "--explain-filter doesn't apply to a job; run filter --explain-filter".to_string()));
+        }
+        options.filter.require_filters().map_err(|message| (filter_line, message))?;
+        let (filter, explanation) = EntryFilter::build(options.filter).map_err(|errors| {
+            (filter_line, errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))
+        })?;
+        if filter.after_status.is_some() {
+            let message = "--after-status follows each client across the whole input; run it with filter";
+            return Err((filter_line, message.to_string()));
+        }
+        for notice in explanation.notices {
+            reporter.notice(notice);
+        }
+        let output = match self.text("output")? {
+            Some(name) => OutputFormat::from_str(name, true)
+                .map_err(|_| (line_of("output"), format!("output = {:?}: {}", name, choices::<OutputFormat>())))?,
+            None => OutputFormat::default(),
+        };
+        let fields: Vec<String> = self.list("fields")?.iter().map(|name| registry::canonical(name)).collect();
+        if let (Some(setting), false) = (self.get("output"), fields.is_empty()) {
+            return Err((setting.line, "a job writes rows of its fields or entries in its output, not both".to_string()));
+        }
+        let file = self.text("file")?.ok_or_else(|| {
+            (self.line, "no file; name the file to write the job's matches to, e.g. file = \"errors.json\"".to_string())
+        })?;
+        let log_type = match self.text("log_type")? {
+            Some(name) => match LogKind::from_str(name, true) {
+                Ok(LogKind::Auto) => None,
+                Ok(kind) => Some(kind),
+                Err(_) => return Err((line_of("log_type"), format!("log_type = {:?}: {}", name, choices::<LogKind>()))),
+            },
+            None => None,
+        };
+        Ok(Job { name: self.name.clone(), line: self.line, filter, output, fields, file: PathBuf::from(file), log_type })
+    }
+}
+
+/// Reads the jobs in `path`, reporting every mistake at once, and settles the log type of the
+/// pass: --log-type, or with --log-type auto the one the jobs name, if any. `custom` is whether --format-string
+/// was given.
+pub fn load(path: &Path, log_type: LogKind, custom: bool, reporter: &Reporter) -> Result<(Vec<Job>, LogKind), Vec<Error>> {
+    let source = std::fs::read_to_string(path).map_err(|e| vec![Error::Io { file: path.to_path_buf(), message: e.to_string() }])?;
+    let at = |line: usize, message: String| Error::Line { file: path.to_path_buf(), line, message };
+    let mut sections: Vec<Section> = Vec::new();
+    let mut errors = Vec::new();
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        match config::parse_line(text) {
+            Ok(Line::Blank) => {}
+            Ok(Line::Section(name)) => {
+                if sections.iter().any(|section| section.name == name) {
+                    errors.push(at(line, format!("[{}]: a job of that name comes earlier", name)));
+                }
+                sections.push(Section { name: name.to_string(), line, settings: Vec::new() });
+            }
+            Ok(Line::Setting(key, value)) => match sections.last_mut() {
+                None => errors.push(at(line, format!("{} is outside any job; put it under a [name] header", key))),
+                Some(_) if !KEYS.contains(&key) => {
+                    errors.push(at(line, format!("unknown key {}; jobs take {}", key, KEYS.join(", "))))
+                }
+                Some(section) if section.get(key).is_some() => {
+                    errors.push(at(line, format!("{} sets {} twice", section.name, key)))
+                }
+                Some(section) => section.settings.push(Setting { line, key: key.to_string(), value }),
+            },
+            Err(message) => errors.push(at(line, message)),
+        }
+    }
+    let mut jobs: Vec<Job> = Vec::new();
+    for section in &sections {
+        match section.job(reporter) {
+            Ok(job) => {
+                if let Some(earlier) = jobs.iter().find(|earlier| earlier.file == job.file) {
+                    let file = job.file.display();
+                    let message = format!("{}: {} is job {}'s file too", job.name, file, earlier.name);
+                    errors.push(at(job.line, format!("{}; give each job a file of its own", message)));
+                }
+                jobs.push(job);
+            }
+            Err((line, message)) => errors.push(at(line, format!("{}: {}", section.name, message))),
+        }
+    }
+    let mut settled: Option<&Job> = None;
+    for job in &jobs {
+        let Some(wanted) = job.log_type else {
+            continue;
+        };
+        let conflict = match (custom, log_type, settled) {
+            (true, _, _) => Some("--format-string".to_string()),
+            (false, LogKind::Auto, Some(earlier)) => {
+                let settled = earlier.log_type.unwrap_or_default();
+                (settled != wanted).then(|| format!("job {}'s {}", earlier.name, kind_name(settled)))
+            }
+            (false, LogKind::Auto, None) => None,
+            (false, given, _) => (given != wanted).then(|| format!("--log-type {}", kind_name(given))),
+        };
+        match conflict {
+            Some(other) => {
+                let message = format!("{}: log_type {} conflicts with {}", job.name, kind_name(wanted), other);
+                let message = format!("{}; one pass parses one log type, so run the jobs in separate batches", message);
+                errors.push(at(job.line, message))
+            }
+            None => settled = settled.or(Some(job)),
+        }
+    }
+    let log_type = settled.and_then(|job| job.log_type).filter(|_| log_type == LogKind::Auto).unwrap_or(log_type);
+    match (errors.is_empty(), jobs.is_empty()) {
+        (true, false) => Ok((jobs, log_type)),
+        (true, true) => Err(vec![Error::Other { message: format!("{}: no jobs", path.display()) }]),
+        (false, _) => Err(errors),
+    }
+}
+
+/// A job with its file open and the matches written to it so far.
+struct Running {
+    job: Job,
+    out: Sink,
+    selection: Option<FieldSelection>,
+    matched: u64,
+}
+
+/// Every job, fed each entry of the pass once.
+pub struct Batch {
+    running: Vec<Running>,
+    checked: bool,
+}
+
+impl Batch {
+    /// Creates every job's file; JSON output starts with the schema version when `announce`.
+    pub fn open(jobs: Vec<Job>, announce: bool) -> Result<Self, Error> {
+        let mut running = Vec::with_capacity(jobs.len());
+        for job in jobs {
+            let mut out = Sink::create(&job.file)?.framed(job.output);
+            if announce && job.fields.is_empty() && matches!(job.output, OutputFormat::Json) {
+                out.prologue(schema::preamble())?;
+            }
+            let selection = (!job.fields.is_empty()).then(|| FieldSelection::new(job.fields.clone()));
+            running.push(Running { job, out, selection, matched: 0 });
+        }
+        Ok(Batch { running, checked: false })
+    }
+
+    /// Writes `found` to each job whose filter it matches. The fields every filter reads are
+    /// checked against the first entry.
+    pub fn add(&mut self, found: &Match) -> Result<(), String> {
+        for running in &mut self.running {
+            let job = &running.job;
+            if !self.checked {
+                job.filter.check_fields(&found.entry).map_err(|message| format!("{}: {}", job.name, message))?;
+            }
+            if !found.entry.is_match(&job.filter) {
+                continue;
+            }
+            running.matched += 1;
+            let (line, end) = match &mut running.selection {
+                Some(selection) => match selection.render(found, None).map_err(|message| format!("{}: {}", job.name, message))? {
+                    Some(row) => (row, output::delimiter().terminator()),
+                    None => continue,
+                },
+                None => (render_match(found, job.output)?, '\n'),
+            };
+            running.out.write_line(&line, end).map_err(|e| e.to_string())?;
+        }
+        self.checked = true;
+        Ok(())
+    }
+
+    /// Closes every job's file, with a record per job of what it matched of the `lines` read.
+    pub fn finish(self, lines: usize) -> Result<Vec<BatchJob>, Error> {
+        let finish = |mut running: Running| {
+            running.out.finish()?;
+            let file = running.job.file.display().to_string();
+            Ok(BatchJob { job: running.job.name, lines: lines as u64, matched: running.matched, file })
+        };
+        self.running.into_iter().map(finish).collect()
+    }
+}
+
+/// A row, or with JSON a record, per job.
+pub fn render(jobs: &[BatchJob], output: StatsOutput) -> String {
+    let mut out = String::new();
+    match output {
+        StatsOutput::Json | StatsOutput::JsonArray => {
+            for job in jobs {
+                out += &format!("{}\n", json!(job));
+            }
+        }
+        StatsOutput::Table => {
+            let mut table = Table::new(&["job", "lines", "matched", "file"]);
+            for job in jobs {
+                table.row(vec![job.job.clone(), job.lines.to_string(), job.matched.to_string(), job.file.clone()]);
+            }
+            out += &table.render();
+        }
+    }
+    output.document(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::ErrorFormat;
+
+    fn load_jobs(name: &str, text: &str, log_type: LogKind) -> Result<(Vec<Job>, LogKind), Vec<String>> {
+        let path = std::env::temp_dir().join(format!("log-parser-jobs-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let loaded = load(&path, log_type, false, &Reporter::new(ErrorFormat::default()));
+        std::fs::remove_file(&path).unwrap();
+        let line = |error: &Error| error.to_string().rsplit(": line ").next().unwrap().to_string();
+        loaded.map_err(|errors| errors.iter().map(line).collect())
+    }
+
+    #[test]
+    fn jobs_agree_on_one_log_type() {
+        let jobs = concat!(
+            "[errors]\nfilter = [\"-s\", \"gte\", \"500\"]\nfile = \"errors.log\"\n\n",
+            "[slow]\nwhere = \"bytes > 1000\"\nlog_type = \"alb\"\nfile = \"slow.log\"\n",
+        );
+        let (loaded, log_type) = load_jobs("agree", jobs, LogKind::Auto).unwrap();
+        assert_eq!((loaded.len(), log_type), (2, LogKind::Alb));
+        assert_eq!(load_jobs("given", jobs, LogKind::Alb).unwrap().1, LogKind::Alb);
+
+        let more = "\n[backends]\nfilter = [\"--backend\", \"eq\", \"api\"]\nlog_type = \"haproxy\"\nfile = \"slow.log\"\n";
+        let more = format!("{}format = \"json\"\n", more);
+        assert_eq!(
+            load_jobs("conflict", &format!("{}{}", jobs, more), LogKind::Auto).err().unwrap(),
+            [
+                "14: unknown key format; jobs take filter, where, output, fields, file, log_type",
+                "10: backends: slow.log is job slow's file too; give each job a file of its own",
+                concat!(
+                    "10: backends: log_type haproxy conflicts with job slow's alb; ",
+                    "one pass parses one log type, so run the jobs in separate batches",
+                ),
+            ]
+        );
+        let errors = load_jobs("mismatch", jobs, LogKind::Combined).err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("5: slow: log_type alb conflicts with --log-type combined;"), "{}", errors[0]);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::output::{schema::BenchReport, table::Table};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy)]
+pub enum Stage {
+    Read,
+    Parse,
+    Match,
+}
+
+/// Hooks `scan` calls around each pipeline stage. Normal runs use `NoTiming`, which the
+/// compiler reduces to calling the stage directly.
+pub trait StageTimer {
+    fn time<R>(&mut self, stage: Stage, f: impl FnOnce() -> R) -> R;
+}
+
+pub struct NoTiming;
+
+impl StageTimer for NoTiming {
+    #[inline(always)]
+    fn time<R>(&mut self, _: Stage, f: impl FnOnce() -> R) -> R {
+        f()
+    }
+}
+
+#[derive(Default)]
+pub struct StageTimes {
+    read: Duration,
+    parse: Duration,
+    matching: Duration,
+}
+
+impl StageTimer for StageTimes {
+    fn time<R>(&mut self, stage: Stage, f: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        match stage {
+            Stage::Read => self.read += elapsed,
+            Stage::Parse => self.parse += elapsed,
+            Stage::Match => self.matching += elapsed,
+        }
+        result
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum BenchOutput {
+    #[default]
+    Table,
+    Json,
+}
+
+/// Measurements from one pass over the input.
+#[derive(Serialize)]
+pub struct Iteration {
+    iteration: usize,
+    lines: usize,
+    bytes: u64,
+    matched: usize,
+    wall_secs: f64,
+    lines_per_sec: f64,
+    mb_per_sec: f64,
+    read_secs: f64,
+    parse_secs: f64,
+    match_secs: f64,
+}
+
+impl Iteration {
+    pub fn new(iteration: usize, lines: usize, bytes: u64, matched: usize, wall: Duration, times: StageTimes) -> Self {
+        let wall_secs = wall.as_secs_f64().max(f64::MIN_POSITIVE);
+        Iteration {
+            iteration,
+            lines,
+            bytes,
+            matched,
+            wall_secs,
+            lines_per_sec: lines as f64 / wall_secs,
+            mb_per_sec: bytes as f64 / 1_000_000.0 / wall_secs,
+            read_secs: times.read.as_secs_f64(),
+            parse_secs: times.parse.as_secs_f64(),
+            match_secs: times.matching.as_secs_f64(),
+        }
+    }
+}
+
+pub fn render(iterations: &[Iteration], output: BenchOutput) -> Result<String, String> {
+    match output {
+        BenchOutput::Json => {
+            let json = serde_json::to_string(&BenchReport { iterations });
+            json.map(|json| json + "\n").map_err(|e| e.to_string())
+        }
+        BenchOutput::Table => {
+            let header = ["iteration", "lines", "bytes", "matched", "wall_s", "lines/s", "MB/s", "read_s", "parse_s", "match_s"];
+            let mut table = Table::new(&header);
+            for i in iterations {
+                table.row(vec![
+                    i.iteration.to_string(),
+                    i.lines.to_string(),
+                    i.bytes.to_string(),
+                    i.matched.to_string(),
+                    format!("{:.3}", i.wall_secs),
+                    format!("{:.0}", i.lines_per_sec),
+                    format!("{:.2}", i.mb_per_sec),
+                    format!("{:.3}", i.read_secs),
+                    format!("{:.3}", i.parse_secs),
+                    format!("{:.3}", i.match_secs),
+                ]);
+            }
+            Ok(table.render())
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::size::format_size;
+use std::{cell::Cell, rc::Rc};
+
+// What a run holds on to grows with its input in --sort, --unique, --latest-per/--oldest-per,
+// `stats --by` and `top`, so they share one --max-memory budget. Each keeps an account of
+// roughly what it holds, charged with an estimate per item as it's kept rather than a measure
+// of the heap, and a charge that takes the run past the budget fails naming the feature and
+// what to do instead, before the OOM killer gets to it. --sort spills to temporary files
+// instead of failing, releasing what it had buffered.
+
+/// The memory all buffering in a run may take.
+pub struct MemoryBudget {
+    limit: usize,
+    used: Cell<usize>,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Rc<Self> {
+        Rc::new(MemoryBudget { limit, used: Cell::new(0) })
+    }
+
+    /// An account for `feature`, e.g. `--unique`, whose failures suggest `instead`.
+    pub fn account(self: &Rc<Self>, feature: &'static str, instead: &'static str) -> Account {
+        Account { budget: Rc::clone(self), feature, instead, held: 0 }
+    }
+}
+
+/// What one feature holds of a budget.
+#[derive(Clone)]
+pub struct Account {
+    budget: Rc<MemoryBudget>,
+    feature: &'static str,
+    instead: &'static str,
+    held: usize,
+}
+
+impl Account {
+    /// Adds `bytes` to what's held, failing when that takes the run past its budget. The bytes
+    /// stay charged either way, for `release` to give back.
+    pub fn charge(&mut self, bytes: usize) -> Result<(), String> {
+        self.held += bytes;
+        let used = self.budget.used.get() + bytes;
+        self.budget.used.set(used);
+        if used <= self.budget.limit {
+            return Ok(());
+        }
+        Err(format!(
+            "{} holds about {}, taking the run past --max-memory {}; {}",
+            self.feature,
+            format_size(self.held as u64),
+            format_size(self.budget.limit as u64),
+            self.instead
+        ))
+    }
+
+    /// Gives back everything held, once it's been let go.
+    pub fn release(&mut self) {
+        self.budget.used.set(self.budget.used.get() - self.held);
+        self.held = 0;
+    }
+}
+
+/// A rough footprint of kept strings: their bytes plus each one's own size.
+pub fn strings<'a>(values: impl IntoIterator<Item = &'a String>) -> usize {
+    values.into_iter().map(|value| value.len() + std::mem::size_of::<String>()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accounts_share_the_budget_and_name_themselves() {
+        let budget = MemoryBudget::new(100);
+        let mut unique = budget.account("--unique", "narrow the filter");
+        let mut sort = budget.account("--sort", "");
+        unique.charge(40).unwrap();
+        sort.charge(60).unwrap();
+        assert_eq!(budget.used.get(), 100);
+        assert_eq!(
+            unique.charge(1024),
+            Err("--unique holds about 1.0 KiB, taking the run past --max-memory 100 B; narrow the filter".to_string())
+        );
+        sort.release();
+        unique.release();
+        assert_eq!(budget.used.get(), 0);
+        unique.charge(100).unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+use crate::entry::Entry;
+use crate::group::{self, GroupBy};
+use crate::order::Order;
+use crate::output::{schema, table::Table};
+use crate::stats::StatsOutput;
+use crate::zone::Zone;
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+// `cardinality` counts the distinct values of one key, in total or per --by group, e.g. how many
+// client addresses hit each endpoint. Sets of the values themselves would hold every one of them
+// in memory, so by default each group gets a HyperLogLog sketch of 2^--precision one-byte
+// registers instead, and the count is an estimate printed with its standard error. The estimate
+// is Ertl's improved one ("New cardinality estimation algorithms for HyperLogLog sketches",
+// 2017), which stays unbiased from empty sketches to huge ones without tables of corrections.
+// --exact keeps the sets, for inputs small enough to afford them.
+
+pub const MIN_PRECISION: u8 = 4;
+pub const MAX_PRECISION: u8 = 18;
+
+/// A HyperLogLog sketch: each value's hash picks a register by its first `precision` bits, and
+/// the register keeps the most leading zeros, plus one, seen in the rest.
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    pub fn new(precision: u8) -> Self {
+        assert!((MIN_PRECISION..=MAX_PRECISION).contains(&precision), "precision out of range");
+        HyperLogLog { precision, registers: vec![0; 1 << precision] }
+    }
+
+    pub fn insert(&mut self, value: &impl Hash) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - self.precision)) as usize;
+        // A marker bit below the remaining bits caps the count at 64 - precision zeros.
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// The relative standard error of the estimate.
+    pub fn standard_error(precision: u8) -> f64 {
+        1.04 / ((1u64 << precision) as f64).sqrt()
+    }
+
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let q = 64 - self.precision as usize;
+        let mut counts = vec![0.0; q + 2];
+        for &register in &self.registers {
+            counts[register as usize] += 1.0;
+        }
+        let mut z = m * tau(1.0 - counts[q + 1] / m);
+        for k in (1..=q).rev() {
+            z = 0.5 * (z + counts[k]);
+        }
+        z += m * sigma(counts[0] / m);
+        m * m / (2.0 * std::f64::consts::LN_2 * z)
+    }
+}
+
+/// σ(x) = x + Σ x^(2^k) 2^(k-1), the correction for empty registers.
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+/// τ(x) = (1 - x - Σ (1 - x^(2^-k))² 2^-k) / 3, the correction for saturated registers.
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+/// The distinct values of one group.
+enum Distinct {
+    Exact(HashSet<String>),
+    Sketch(HyperLogLog),
+}
+
+pub struct Cardinality {
+    of: GroupBy,
+    by: Vec<GroupBy>,
+    tz: Option<Zone>,
+    /// `None` for --exact.
+    precision: Option<u8>,
+    max_groups: usize,
+    groups: BTreeMap<Vec<String>, Distinct>,
+}
+
+impl Cardinality {
+    pub fn new(of: GroupBy, by: Vec<GroupBy>, tz: Option<Zone>, precision: Option<u8>, max_groups: usize) -> Self {
+        Cardinality { of, by, tz, precision, max_groups, groups: BTreeMap::new() }
+    }
+
+    /// Counts `entry`'s value of --of in its group, failing once there would be more than
+    /// --max-groups.
+    pub fn add(&mut self, entry: &Entry) -> Result<(), String> {
+        let key = group::key(&self.by, entry, self.tz);
+        if !self.groups.contains_key(&key) && self.groups.len() >= self.max_groups {
+            return Err(group::too_many(&self.by, self.max_groups));
+        }
+        let precision = self.precision;
+        let distinct = self.groups.entry(key).or_insert_with(|| match precision {
+            Some(precision) => Distinct::Sketch(HyperLogLog::new(precision)),
+            None => Distinct::Exact(HashSet::new()),
+        });
+        let value = self.of.value(entry, self.tz);
+        match distinct {
+            Distinct::Exact(values) => {
+                values.insert(value);
+            }
+            Distinct::Sketch(sketch) => sketch.insert(&value),
+        }
+        Ok(())
+    }
+
+    /// Each group's distinct count, rounded, and its standard error, 0 for exact counts.
+    fn counts(&self) -> impl Iterator<Item = (&Vec<String>, u64, u64)> {
+        let error = self.precision.map_or(0.0, HyperLogLog::standard_error);
+        self.groups.iter().map(move |(key, distinct)| {
+            let count = match distinct {
+                Distinct::Exact(values) => values.len() as f64,
+                Distinct::Sketch(sketch) => sketch.estimate(),
+            };
+            (key, count.round() as u64, (count * error).round() as u64)
+        })
+    }
+
+    /// A row, or with JSON a record, per group in `order`: the key values, the distinct count and
+    /// its standard error.
+    pub fn render(&self, output: StatsOutput, order: Order) -> String {
+        let names = group::names(&self.by);
+        let mut counts: Vec<_> = self.counts().collect();
+        order.sort(&mut counts, |&(key, distinct, _)| (distinct, key));
+        let mut out = String::new();
+        match output {
+            StatsOutput::Json | StatsOutput::JsonArray => {
+                for (key, distinct, error) in &counts {
+                    let (distinct, error) = (*distinct, *error);
+                    let group = names.iter().cloned().zip(key.iter().cloned()).collect();
+                    out += &format!("{}\n", json!(schema::Cardinality { group, distinct, error }));
+                }
+            }
+            StatsOutput::Table => {
+                let mut header = names;
+                header.extend(["distinct".to_string(), "error".to_string()]);
+                let mut table = Table::new(&header);
+                for (key, distinct, error) in counts {
+                    let mut row = key.clone();
+                    row.extend([distinct.to_string(), format!("±{}", error)]);
+                    table.row(row);
+                }
+                out += &table.render();
+            }
+        }
+        output.document(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The sketch's estimate of `count` distinct values, each inserted twice.
+    fn estimate(precision: u8, count: u64) -> f64 {
+        let mut sketch = HyperLogLog::new(precision);
+        for value in (0..count).chain(0..count) {
+            sketch.insert(&value);
+        }
+        sketch.estimate()
+    }
+
+    #[test]
+    fn estimates_stay_within_three_standard_errors() {
+        for precision in [MIN_PRECISION, 10, 14] {
+            let bound = 3.0 * HyperLogLog::standard_error(precision);
+            for count in [1, 10, 100, 1_000, 10_000, 100_000, 1_000_000] {
+                let estimate = estimate(precision, count);
+                let error = (estimate - count as f64).abs() / count as f64;
+                assert!(error <= bound, "precision {} count {}: estimated {:.0}, off by {:.4}", precision, count, estimate, error);
+            }
+        }
+        assert_eq!(estimate(14, 0), 0.0);
+    }
+
+    #[test]
+    fn sketches_depend_on_the_values_alone() {
+        // A HashSet iterates in an order its random keys pick; the sketch's hash has fixed keys,
+        // so it comes out the same from any order, in any process.
+        let values: HashSet<String> = (0..5_000).map(|i| format!("/item/{}", i)).collect();
+        let mut sorted: Vec<&String> = values.iter().collect();
+        sorted.sort();
+        let mut shuffled = HyperLogLog::new(10);
+        let mut ordered = HyperLogLog::new(10);
+        values.iter().for_each(|value| shuffled.insert(value));
+        sorted.iter().rev().for_each(|value| ordered.insert(value));
+        assert_eq!(shuffled.registers, ordered.registers);
+        assert_eq!(shuffled.estimate(), ordered.estimate());
+    }
+
+    #[test]
+    fn exact_counts_have_no_error() {
+        let mut cardinality = Cardinality::new(GroupBy::Ip, vec![GroupBy::Status], None, None, 10);
+        for (ip, status) in [("10.0.0.1", 200), ("10.0.0.2", 200), ("10.0.0.1", 200), ("10.0.0.1", 404)] {
+            let entry = Entry {
+                client: Some(ip.parse().unwrap()),
+                status_code: Some(http::StatusCode::from_u16(status).unwrap()),
+                ..Entry::default()
+            };
+            cardinality.add(&entry).unwrap();
+        }
+        assert_eq!(cardinality.counts().map(|(key, distinct, error)| (key[0].as_str(), distinct, error)).collect::<Vec<_>>(), [
+            ("200", 2, 0),
+            ("404", 1, 0)
+        ]);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::config::Config;
+use crate::diagnostics::Error;
+use crate::entry::{Entry, FieldValue};
+use crate::gap;
+use crate::referrer;
+use crate::registry;
+use crate::suspicious;
+use clap::ValueEnum;
+use std::sync::OnceLock;
+
+// Two fields derived from the path: `class`, `static` for a request for an asset such as a
+// stylesheet or image and `dynamic` for the rest, and `path_depth`, the number of segments, e.g.
+// 3 for `/api/users/7?page=2`. A path is static when its last segment has an asset extension or
+// it's under an asset prefix such as `/static/`; the config file's `[classify]` section can
+// replace either list:
+//
+//     [classify]
+//     static_prefixes = ["/static/", "/cdn/"]
+//     static_extensions = ["css", "js", "png", "woff2"]
+//
+// Both look at the path filters see, normalized with --normalize-paths, without its query
+// string, and are computed only when the filter or the command reads them. So are the third,
+// `suspicious`, which looks at the path as logged, see the suspicious module, and the referrer's
+// `referrer_class` and `referrer_domain`, see the referrer module.
+
+/// The fields derived here, which an extraction can't take the name of.
+pub const FIELDS: &[&str] = &["class", "path_depth", suspicious::FIELD, referrer::CLASS_FIELD, referrer::DOMAIN_FIELD];
+
+const STATIC_PREFIXES: &[&str] =
+    &["/static/", "/assets/", "/_next/", "/public/", "/dist/", "/build/", "/images/", "/img/", "/css/", "/js/", "/fonts/", "/media/"];
+
+const STATIC_EXTENSIONS: &[&str] = &[
+    "css", "js", "mjs", "map", "png", "jpg", "jpeg", "gif", "svg", "ico", "webp", "avif", "bmp", "woff", "woff2", "ttf", "otf",
+    "eot", "mp3", "mp4", "webm", "ogg", "wav", "pdf", "txt", "wasm",
+];
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Class {
+    /// Assets: an asset extension or a path under an asset prefix
+    Static,
+    /// Everything else
+    Dynamic,
+}
+
+impl Class {
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::Static => "static",
+            Class::Dynamic => "dynamic",
+        }
+    }
+}
+
+/// The rules telling static requests from dynamic ones.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Classifier {
+    prefixes: Vec<String>,
+    /// Lowercase, without the dot.
+    extensions: Vec<String>,
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Classifier {
+            prefixes: STATIC_PREFIXES.iter().map(|prefix| prefix.to_string()).collect(),
+            extensions: STATIC_EXTENSIONS.iter().map(|extension| extension.to_string()).collect(),
+        }
+    }
+}
+
+impl Classifier {
+    /// The built-in rules with the lists the config file's `[classify]` section gives instead.
+    pub fn configured(config: &Config) -> Result<Classifier, Vec<Error>> {
+        let mut classifier = Classifier::default();
+        let mut errors = Vec::new();
+        for setting in &config.classify {
+            let list = match setting.list() {
+                Ok(list) => list,
+                Err(message) => {
+                    errors.push(config.error(setting, message));
+                    continue;
+                }
+            };
+            match setting.key.as_str() {
+                "static_prefixes" => classifier.prefixes = list.to_vec(),
+                "static_extensions" => {
+                    classifier.extensions =
+                        list.iter().map(|extension| extension.trim_start_matches('.').to_ascii_lowercase()).collect()
+                }
+                key => errors.push(config.error(
+                    setting,
+                    format!("unknown setting {} in [classify]; expected static_prefixes or static_extensions", key),
+                )),
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(classifier),
+            false => Err(errors),
+        }
+    }
+
+    pub fn classify(&self, path: &str) -> Class {
+        let path = path_part(path);
+        let name = path.rsplit('/').next().unwrap_or_default();
+        let extension = name.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+        let by_extension = extension.is_some_and(|extension| self.extensions.contains(&extension));
+        match by_extension || self.prefixes.iter().any(|prefix| path.starts_with(prefix.as_str())) {
+            true => Class::Static,
+            false => Class::Dynamic,
+        }
+    }
+}
+
+/// The path without its query string or fragment, nor the scheme and host of a proxy's full URL.
+fn path_part(path: &str) -> &str {
+    let path = &path[..path.find(['?', '#']).unwrap_or(path.len())];
+    match path.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |at| &rest[at..]),
+        None => path,
+    }
+}
+
+/// The number of non-empty segments of the path, 0 for `/`; `None` for one that isn't a path,
+/// such as `*`.
+pub fn depth(path: &str) -> Option<usize> {
+    let path = path_part(path);
+    path.starts_with('/').then(|| path.split('/').filter(|segment| !segment.is_empty()).count())
+}
+
+static CLASSIFIER: OnceLock<Classifier> = OnceLock::new();
+
+/// Sets the rules for the rest of the run; only the first call has an effect.
+pub fn set_classifier(classifier: Classifier) {
+    let _ = CLASSIFIER.set(classifier);
+}
+
+/// The rules: the built-in ones until `set_classifier`.
+pub fn classifier() -> &'static Classifier {
+    CLASSIFIER.get_or_init(Classifier::default)
+}
+
+/// Whether `name` is a field derived here, or `gap`. Such a field can be missing from the first
+/// entry, one without a path or a client's first, so checks against the first entry let it pass.
+pub fn derives(name: &str) -> bool {
+    FIELDS.contains(&name) || name == gap::FIELD
+}
+
+/// The field a derived one is computed from, as the registry has it: `referrer` for the
+/// referrer's, else `path`.
+pub fn source(name: &str) -> &'static str {
+    registry::find(name).map_or("path", |field| field.source)
+}
+
+/// Which of the fields to add to entries.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Derived {
+    class: bool,
+    depth: bool,
+    suspicious: bool,
+    referrer_class: bool,
+    referrer_domain: bool,
+}
+
+impl Derived {
+    /// The fields `reads`.
+    pub fn used(reads: impl Fn(&str) -> bool) -> Self {
+        Derived {
+            class: reads("class"),
+            depth: reads("path_depth"),
+            suspicious: reads(suspicious::FIELD),
+            referrer_class: reads(referrer::CLASS_FIELD),
+            referrer_domain: reads(referrer::DOMAIN_FIELD),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !(self.class || self.depth || self.suspicious || self.referrer_class || self.referrer_domain)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        [
+            (self.class, "class"),
+            (self.depth, "path_depth"),
+            (self.suspicious, suspicious::FIELD),
+            (self.referrer_class, referrer::CLASS_FIELD),
+            (self.referrer_domain, referrer::DOMAIN_FIELD),
+        ]
+        .into_iter()
+        .filter_map(|(used, name)| used.then_some(name))
+    }
+
+    pub fn apply(&self, entry: &mut Entry) {
+        if self.referrer_class && entry.referrer.is_some() {
+            let class = referrer::class(entry).name().to_string();
+            entry.extras.insert(referrer::CLASS_FIELD.to_string(), FieldValue::Text(class));
+        }
+        if let Some(domain) = entry.referrer.as_deref().filter(|_| self.referrer_domain).and_then(referrer::domain) {
+            entry.extras.insert(referrer::DOMAIN_FIELD.to_string(), FieldValue::Text(domain));
+        }
+        let reasons = entry.path.as_deref().filter(|_| self.suspicious).map(suspicious::detect).unwrap_or_default();
+        if !reasons.is_empty() {
+            let reasons = reasons.into_iter().map(|reason| reason.name().to_string()).collect();
+            entry.extras.insert(suspicious::FIELD.to_string(), FieldValue::List(reasons));
+        }
+        let Some(path) = entry.match_path().clone() else {
+            return;
+        };
+        if self.class {
+            entry.extras.insert("class".to_string(), FieldValue::Text(classifier().classify(&path).name().to_string()));
+        }
+        if let Some(depth) = depth(&path).filter(|_| self.depth) {
+            entry.extras.insert("path_depth".to_string(), FieldValue::Integer(depth as i64));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Setting, Value};
+
+    #[test]
+    fn paths_are_static_by_extension_or_prefix() {
+        let cases = [
+            ("/", Class::Dynamic),
+            ("/index.html", Class::Dynamic),
+            ("/api/users/7", Class::Dynamic),
+            ("/app.js", Class::Static),
+            ("/css/site.CSS", Class::Static),
+            ("/logo.png?v=3", Class::Static),
+            ("/fonts/inter.woff2#x", Class::Static),
+            ("/static/bundle", Class::Static),
+            ("/assets/", Class::Static),
+            ("/_next/data/build/page.json", Class::Static),
+            // The extension is the last segment's, and the query string isn't part of it.
+            ("/files.css/report", Class::Dynamic),
+            ("/search?q=style.css", Class::Dynamic),
+            ("/v1.2/orders", Class::Dynamic),
+            // A prefix is a whole leading segment.
+            ("/statics/report", Class::Dynamic),
+            ("/api/static/", Class::Dynamic),
+            ("http://example.com/assets/a", Class::Static),
+            ("*", Class::Dynamic),
+        ];
+        let classifier = Classifier::default();
+        for (path, class) in cases {
+            assert_eq!(classifier.classify(path), class, "classifying {:?}", path);
+        }
+    }
+
+    #[test]
+    fn depth_counts_the_segments_of_the_path() {
+        let cases = [
+            ("/", Some(0)),
+            ("/api", Some(1)),
+            ("/api/", Some(1)),
+            ("/api/users/7?page=2", Some(3)),
+            ("/a//b///c", Some(3)),
+            ("/files/a%2Fb", Some(2)),
+            ("/page#a/b/c", Some(1)),
+            ("http://example.com/a/b", Some(2)),
+            ("http://example.com", Some(0)),
+            ("*", None),
+            ("", None),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(depth(path), expected, "depth of {:?}", path);
+        }
+    }
+
+    #[test]
+    fn config_lists_replace_the_built_in_ones() {
+        let setting = |line, key: &str, value| Setting { line, key: key.to_string(), value };
+        let list = |items: &[&str]| Value::List(items.iter().map(|item| item.to_string()).collect());
+        let config = Config {
+            classify: vec![setting(2, "static_prefixes", list(&["/cdn/"])), setting(3, "static_extensions", list(&[".PNG"]))],
+            ..Config::default()
+        };
+        let classifier = Classifier::configured(&config).ok().unwrap();
+        assert_eq!(classifier.classify("/cdn/app"), Class::Static);
+        assert_eq!(classifier.classify("/static/app.js"), Class::Dynamic);
+        assert_eq!(classifier.classify("/logo.png"), Class::Static);
+
+        let config = Config {
+            classify: vec![setting(2, "static_prefixes", Value::Text("/cdn/".to_string())), setting(3, "prefixes", list(&[]))],
+            ..Config::default()
+        };
+        let errors: Vec<String> = Classifier::configured(&config).err().unwrap().iter().map(Error::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                ": line 2: static_prefixes takes a list of strings, e.g. [\"a\", \"b\"]",
+                ": line 3: unknown setting prefixes in [classify]; expected static_prefixes or static_extensions",
+            ]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use crate::diagnostics::Error;
+use std::path::{Path, PathBuf};
+
+// The config file holds settings for every run, so they needn't be repeated on the command
+// line. It's the part of TOML the settings need: `[section]` headers, `key = value` pairs with
+// a quoted string, a whole number or a one-line list of strings for a value, and `#` comments,
+// e.g.
+//
+//     [theme]
+//     base = "light"        # the palette to start from, unless --theme picks one
+//     status_5xx = "bright-red"
+//     header = 33           # a 256-color index
+//
+//     [classify]
+//     static_prefixes = ["/static/", "/cdn/"]
+//
+//     [referrers]
+//     search_domains = ["kagi.com"]
+//
+// It's read from --config, or else from log-parser/config.toml under $XDG_CONFIG_HOME or
+// ~/.config when there's one.
+
+const SECTIONS: &[&str] = &["theme", "classify", "referrers"];
+
+/// A setting's value, with quotes taken off strings; a number is kept as its digits.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Text(String),
+    List(Vec<String>),
+}
+
+/// One `key = value` line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Setting {
+    pub line: usize,
+    pub key: String,
+    pub value: Value,
+}
+
+impl Setting {
+    pub fn text(&self) -> Result<&str, String> {
+        match &self.value {
+            Value::Text(text) => Ok(text),
+            Value::List(_) => Err(format!("{} takes a string, not a list", self.key)),
+        }
+    }
+
+    pub fn list(&self) -> Result<&[String], String> {
+        match &self.value {
+            Value::List(list) => Ok(list),
+            Value::Text(_) => Err(format!("{} takes a list of strings, e.g. [\"a\", \"b\"]", self.key)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    pub path: PathBuf,
+    pub theme: Vec<Setting>,
+    pub classify: Vec<Setting>,
+    pub referrers: Vec<Setting>,
+}
+
+impl Config {
+    /// An error about the setting, pointing at its line.
+    pub fn error(&self, setting: &Setting, message: String) -> Error {
+        Error::Line { file: self.path.clone(), line: setting.line, message }
+    }
+}
+
+/// Where the config file is unless --config says otherwise.
+pub fn default_path() -> Option<PathBuf> {
+    let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let dir = var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))?;
+    Some(dir.join("log-parser").join("config.toml"))
+}
+
+/// Reads `path`, or the default path if there's a file there, or else nothing.
+pub fn load(path: Option<&Path>) -> Result<Config, Vec<Error>> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match default_path().filter(|path| path.is_file()) {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        },
+    };
+    let source = std::fs::read_to_string(&path).map_err(|e| vec![Error::Io { file: path.clone(), message: e.to_string() }])?;
+    let at = |line: usize, message: String| Error::Line { file: path.clone(), line, message };
+    let mut config = Config { path: path.clone(), ..Config::default() };
+    let mut errors = Vec::new();
+    let mut section = None;
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        match parse_line(text) {
+            Ok(Line::Blank) => {}
+            Ok(Line::Section(name)) if SECTIONS.contains(&name) => section = Some(name),
+            Ok(Line::Section(name)) => {
+                section = None;
+                errors.push(at(line, format!("unknown section [{}]; expected [{}]", name, SECTIONS.join("], ["))));
+            }
+            Ok(Line::Setting(key, value)) => {
+                let setting = Setting { line, key: key.to_string(), value };
+                match section {
+                    Some("theme") => config.theme.push(setting),
+                    Some("referrers") => config.referrers.push(setting),
+                    Some(_) => config.classify.push(setting),
+                    None => {
+                        let message = format!("{} is outside any section; put it under [{}]", key, SECTIONS.join("] or ["));
+                        errors.push(at(line, message))
+                    }
+                }
+            }
+            Err(message) => errors.push(at(line, message)),
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(config),
+        false => Err(errors),
+    }
+}
+
+pub enum Line<'a> {
+    Blank,
+    Section(&'a str),
+    Setting(&'a str, Value),
+}
+
+/// A line of the file, without its comment.
+pub fn parse_line(text: &str) -> Result<Line<'_>, String> {
+    let text = text.trim();
+    if text.is_empty() || text.starts_with('#') {
+        return Ok(Line::Blank);
+    }
+    if let Some(rest) = text.strip_prefix('[') {
+        let (name, after) = rest.split_once(']').ok_or_else(|| format!("{:?} has no closing ]", text))?;
+        return match comment(after) {
+            true => Ok(Line::Section(name.trim())),
+            false => Err(format!("unexpected {:?} after [{}]", after.trim(), name.trim())),
+        };
+    }
+    let (key, rest) = text.split_once('=').ok_or_else(|| format!("expected key = value, not {:?}", text))?;
+    let key = key.trim();
+    if key.is_empty() || !key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-') {
+        return Err(format!("{:?} isn't a key", key));
+    }
+    let rest = rest.trim();
+    let (value, after) = match rest.strip_prefix('[') {
+        Some(mut rest) => {
+            let mut list = Vec::new();
+            loop {
+                rest = rest.trim_start();
+                if rest.is_empty() || rest.starts_with('#') {
+                    return Err(format!("{}: the list has no closing ]", key));
+                }
+                if let Some(after) = rest.strip_prefix(']') {
+                    break (Value::List(list), after);
+                }
+                let (item, after) = string(key, rest)?;
+                list.push(item);
+                rest = after.trim_start();
+                rest = match (rest.strip_prefix(','), rest.starts_with(']')) {
+                    (Some(after), _) => after,
+                    (None, true) => rest,
+                    (None, false) => return Err(format!("{}: expected , or ] after {:?} in the list", key, list[list.len() - 1])),
+                };
+            }
+        }
+        None if rest.starts_with(['"', '\'']) => {
+            let (text, after) = string(key, rest)?;
+            (Value::Text(text), after)
+        }
+        None => {
+            let end = rest.find(|c: char| c.is_whitespace() || c == '#').unwrap_or(rest.len());
+            let number = &rest[..end];
+            if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(format!("{}: {:?} needs quotes, unless it's a whole number", key, number));
+            }
+            (Value::Text(number.to_string()), &rest[end..])
+        }
+    };
+    match comment(after) {
+        true => Ok(Line::Setting(key, value)),
+        false => Err(format!("{}: unexpected {:?} after the value", key, after.trim())),
+    }
+}
+
+/// The quoted string `rest` starts with, without its quotes, and what follows it.
+fn string<'a>(key: &str, rest: &'a str) -> Result<(String, &'a str), String> {
+    let quote = rest.chars().next().filter(|c| matches!(c, '"' | '\''));
+    let quote = quote.ok_or_else(|| format!("{}: {:?} needs quotes", key, rest.split([',', ']']).next().unwrap_or(rest).trim()))?;
+    let end = rest[1..].find(quote).ok_or_else(|| format!("{}: the string has no closing {}", key, quote))?;
+    Ok((rest[1..end + 1].to_string(), &rest[end + 2..]))
+}
+
+/// Whether what's left of a line is blank or a comment.
+fn comment(rest: &str) -> bool {
+    let rest = rest.trim();
+    rest.is_empty() || rest.starts_with('#')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_sections_settings_or_comments() {
+        let setting = |text| match parse_line(text) {
+            Ok(Line::Setting(key, value)) => (key.to_string(), value),
+            _ => panic!("{:?} isn't a setting", text),
+        };
+        let text = |value: &str| Value::Text(value.to_string());
+        let list = |items: &[&str]| Value::List(items.iter().map(|item| item.to_string()).collect());
+        assert_eq!(setting(r#"status_5xx = "bright-red""#), ("status_5xx".to_string(), text("bright-red")));
+        assert_eq!(setting("header = 33  # yellow"), ("header".to_string(), text("33")));
+        assert_eq!(setting("base='light'#"), ("base".to_string(), text("light")));
+        assert_eq!(setting(r#"static_prefixes = ["/static/", '/cdn/'] # assets"#), ("static_prefixes".to_string(), list(&["/static/", "/cdn/"])));
+        assert_eq!(setting(r#"static_extensions = [ "css" , ]"#), ("static_extensions".to_string(), list(&["css"])));
+        assert_eq!(setting("static_prefixes = []"), ("static_prefixes".to_string(), list(&[])));
+        assert!(matches!(parse_line("  [theme]  # colors"), Ok(Line::Section("theme"))));
+        assert!(matches!(parse_line("   # a comment"), Ok(Line::Blank)));
+
+        let error = |text| parse_line(text).err().unwrap();
+        assert_eq!(error("header = yellow"), r#"header: "yellow" needs quotes, unless it's a whole number"#);
+        assert_eq!(error(r#"header = "yellow"#), "header: the string has no closing \"");
+        assert_eq!(error(r#"header = "a" "b""#), r#"header: unexpected "\"b\"" after the value"#);
+        assert_eq!(error("[theme"), r#""[theme" has no closing ]"#);
+        assert_eq!(error(r#"static_prefixes = ["/static/" "/cdn/"]"#), r#"static_prefixes: expected , or ] after "/static/" in the list"#);
+        assert_eq!(error("static_prefixes = [/static/]"), r#"static_prefixes: "/static/" needs quotes"#);
+        assert_eq!(error(r#"static_prefixes = ["/static/","#), "static_prefixes: the list has no closing ]");
+        assert_eq!(error("header"), r#"expected key = value, not "header""#);
+    }
+}
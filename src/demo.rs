@@ -0,0 +1,12 @@
+use std::{io, path::Path};
+
+/// A day of combined-format traffic built into the binary: browsers over IPv4 and IPv6,
+/// crawlers and scanners, missing referrers and user agents, and every status class.
+pub const SAMPLE: &str = include_str!("../raw/sample.log");
+
+/// The name the sample is reported under in summaries and errors.
+pub const NAME: &str = "demo.log";
+
+pub fn write(path: &Path) -> io::Result<()> {
+    std::fs::write(path, SAMPLE)
+}
@@ -0,0 +1,176 @@
+use crate::diagnostics::{Error, Reporter, Warning};
+use crate::input::{self, Source};
+use crate::parsers::{LineParser, LogKind, Wrapping};
+use clap::ValueEnum;
+use std::io::BufRead;
+
+// Log type detection from the first lines of each file. `--log-type auto` parses them with each
+// built-in log type in turn and takes the first that parses all of them. A log type given
+// explicitly, or combined by default, is checked the same way: when nearly every line of the
+// sample fails to parse and another log type parses them all, the run warns which one, or with
+// --strict stops, rather than reporting the whole file as malformed. Inputs that can only be
+// read once, such as pipes, aren't sampled.
+
+/// Non-blank lines read from the start of each file.
+pub const SAMPLE_LINES: usize = 100;
+
+/// The share of the sample failing to parse above which the log type looks wrong.
+const MISMATCH: f64 = 0.95;
+
+/// Tried in order; combined comes before common, which parses a prefix of its lines.
+const CANDIDATES: &[LogKind] = &[
+    LogKind::Combined,
+    LogKind::Common,
+    LogKind::Alb,
+    LogKind::Haproxy,
+    LogKind::NginxError,
+    LogKind::ApacheError,
+    LogKind::W3c,
+];
+
+pub fn name(kind: LogKind) -> String {
+    kind.to_possible_value().map_or_else(|| format!("{:?}", kind), |value| value.get_name().to_string())
+}
+
+/// The first `SAMPLE_LINES` non-blank lines of `source`.
+pub fn sample(source: &Source) -> Result<Vec<String>, Error> {
+    let io_error = |e: std::io::Error| Error::Io { file: source.path().to_path_buf(), message: e.to_string() };
+    let mut lines = Vec::new();
+    for line in source.open().map_err(io_error)?.lines() {
+        let mut line = match line {
+            Ok(line) => line,
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => continue,
+            Err(e) => return Err(io_error(e)),
+        };
+        input::trim_line_ending(&mut line);
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        if lines.len() == SAMPLE_LINES {
+            break;
+        }
+    }
+    Ok(lines)
+}
+
+/// How many of `lines` parse to an entry and how many fail to parse. Lines without an entry,
+/// such as W3C directives, are neither.
+fn parse_counts(kind: LogKind, wrapping: Wrapping, lines: &[String]) -> (usize, usize) {
+    let mut parser = wrapping.wrap(LineParser::from(kind));
+    let (mut parsed, mut failed) = (0, 0);
+    for line in lines {
+        match parser.parse(line) {
+            Ok(Some(_)) => parsed += 1,
+            Ok(None) => {}
+            Err(_) => failed += 1,
+        }
+    }
+    (parsed, failed)
+}
+
+/// The first log type that parses every line of the sample, if any does.
+pub fn detect(lines: &[String], wrapping: Wrapping) -> Option<LogKind> {
+    CANDIDATES.iter().copied().find(|&kind| matches!(parse_counts(kind, wrapping, lines), (1.., 0)))
+}
+
+/// Whether so much of the sample fails to parse as `kind` that it's likely the wrong log type.
+pub fn mismatched(kind: LogKind, wrapping: Wrapping, lines: &[String]) -> bool {
+    let (parsed, failed) = parse_counts(kind, wrapping, lines);
+    failed > 0 && failed as f64 > MISMATCH * (parsed + failed) as f64
+}
+
+/// The log type to parse `source` with: the one detected under `--log-type auto`, otherwise
+/// `selected`, warning first if another log type fits the file where it doesn't.
+pub fn resolve(source: &Source, selected: LogKind, wrapping: Wrapping, strict: bool, reporter: &Reporter) -> Result<LogKind, Error> {
+    match (source.rereadable(), selected) {
+        (true, _) => {}
+        (false, LogKind::Auto) => {
+            return Err(format!("{}: --log-type auto can't read a pipe twice; pass --log-type", source.path().display()).into())
+        }
+        (false, selected) => return Ok(selected),
+    }
+    let lines = sample(source)?;
+    if selected == LogKind::Auto {
+        return match detect(&lines, wrapping) {
+            Some(kind) => Ok(kind),
+            None if lines.is_empty() => Ok(LogKind::default()),
+            None => Err(format!(
+                "{}: no log type parses its first lines; pass --log-type or --format-string",
+                source.path().display()
+            )
+            .into()),
+        };
+    }
+    if !mismatched(selected, wrapping, &lines) {
+        return Ok(selected);
+    }
+    let Some(detected) = detect(&lines, wrapping) else {
+        return Ok(selected);
+    };
+    let message = format!(
+        "input looks like {} log format, but {} was selected \u{2014} use --log-type {} or --log-type auto",
+        name(detected),
+        name(selected),
+        name(detected)
+    );
+    if strict {
+        return Err(format!("{}: {}", source.path().display(), message).into());
+    }
+    let file = source.path().to_path_buf();
+    reporter.warning(Warning::LogTypeMismatch { file, selected: name(selected), detected: name(detected), message });
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::Wrapper;
+
+    fn lines(fixture: &str) -> Vec<String> {
+        fixture.lines().filter(|line| !line.is_empty()).take(SAMPLE_LINES).map(str::to_string).collect()
+    }
+
+    const COMBINED: &str = include_str!("../tests/fixtures/combined.log");
+    const COMMON: &str = include_str!("../tests/fixtures/common.log");
+    const NGINX_ERROR: &str = include_str!("../tests/fixtures/nginx-error.log");
+    const W3C: &str = include_str!("../tests/fixtures/w3c.log");
+    const HAPROXY: &str = include_str!("../tests/fixtures/haproxy.log");
+    const CRI: &str = include_str!("../tests/fixtures/cri.log");
+
+    #[test]
+    fn log_types_are_detected_from_their_first_lines() {
+        let plain = Wrapping::default();
+        assert_eq!(detect(&lines(COMBINED), plain), Some(LogKind::Combined));
+        assert_eq!(detect(&lines(COMMON), plain), Some(LogKind::Common));
+        assert_eq!(detect(&lines(NGINX_ERROR), plain), Some(LogKind::NginxError));
+        assert_eq!(detect(&lines(W3C), plain), Some(LogKind::W3c));
+        assert_eq!(detect(&lines(HAPROXY), plain), Some(LogKind::Haproxy));
+        assert_eq!(detect(&["not a log line".to_string()], plain), None);
+        assert_eq!(detect(&[], plain), None);
+    }
+
+    #[test]
+    fn a_log_type_that_fails_nearly_every_line_is_mismatched() {
+        let plain = Wrapping::default();
+        assert!(mismatched(LogKind::Combined, plain, &lines(COMMON)));
+        assert!(mismatched(LogKind::Alb, plain, &lines(COMBINED)));
+        assert!(!mismatched(LogKind::Combined, plain, &lines(COMBINED)));
+        assert!(!mismatched(LogKind::Combined, plain, &[]));
+
+        // One line in twenty parsing is enough to leave the log type alone.
+        let mut mixed = vec!["garbage".to_string(); 19];
+        mixed.push(lines(COMBINED).remove(0));
+        assert!(!mismatched(LogKind::Combined, plain, &mixed));
+        mixed.insert(0, "garbage".to_string());
+        assert!(mismatched(LogKind::Combined, plain, &mixed));
+    }
+
+    #[test]
+    fn wrapped_lines_are_detected_by_their_payload() {
+        // Without the fixture's stderr record and the one missing its time.
+        let records: Vec<String> = lines(CRI).into_iter().filter(|line| line.starts_with("2023") && !line.contains("[warn]")).collect();
+        let cri = Wrapping { unwrap: Wrapper::Cri, ..Wrapping::default() };
+        assert_eq!(detect(&records, cri), Some(LogKind::Combined));
+        assert_eq!(detect(&records, Wrapping::default()), None);
+    }
+}
@@ -0,0 +1,592 @@
+use crate::filter::FilterError;
+use crate::output::schema::{Preamble, Snapshot, SCHEMA_VERSION};
+use crate::stats::{describe_matches, Breakdown, MatchedBytes};
+use clap::ValueEnum;
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::{cell::RefCell, collections::BTreeMap, fmt, path::PathBuf};
+
+// Errors, warnings, the end-of-run summary and --snapshot-every's partial results, written to stderr either as text or, with
+// `--errors json`, as one JSON object per line tagged with `type` and `kind`.
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum ErrorFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Error {
+    /// A filter option that could not be parsed.
+    Filter {
+        option: String,
+        #[serde(flatten)]
+        error: FilterError,
+    },
+    /// The input could not be opened.
+    Io { file: PathBuf, message: String },
+    /// A line that failed to parse under --strict.
+    Line { file: PathBuf, line: usize, message: String },
+    Other { message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Filter { option, error } => f.write_str(&error.render(option)),
+            Error::Io { file, message } => write!(f, "{}: {}", file.display(), message),
+            Error::Line { file, line, message } => write!(f, "{}: line {}: {}", file.display(), line, message),
+            Error::Other { message } => f.write_str(message),
+        }
+    }
+}
+
+impl Error {
+    /// Moves a line number on by `lines`, for a --jobs range read from the middle of its file.
+    pub fn shift_lines(&mut self, lines: usize) {
+        if let Error::Line { line, .. } = self {
+            *line += lines;
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Other { message }
+    }
+}
+
+/// Every error that stopped a run; filter options report all of theirs at once.
+pub struct Errors(pub Vec<Error>);
+
+impl<E: Into<Error>> From<E> for Errors {
+    fn from(error: E) -> Self {
+        Errors(vec![error.into()])
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// A line skipped because it failed to parse. Only reported individually in JSON.
+    MalformedLine { file: PathBuf, line: usize, reason: String },
+    /// --duration was given for a log type that records no duration.
+    NoDuration { message: String },
+    /// --scheme, --port or --tls-protocol was given for a log type without those fields.
+    MissingFields { fields: Vec<String>, message: String },
+    /// An entry timestamped earlier than the one before it, reported with --check-order.
+    TimestampRegression {
+        file: PathBuf,
+        line: usize,
+        timestamp: DateTime<FixedOffset>,
+        previous_line: usize,
+        previous: DateTime<FixedOffset>,
+    },
+    /// A match timestamped before one --interleave already printed, beyond --order-slack.
+    Interleave { file: PathBuf, line: usize, timestamp: DateTime<FixedOffset>, previous: DateTime<FixedOffset> },
+    /// The first timestamp regression while an option that assumes time order is in use.
+    OrderAssumed { file: PathBuf, line: usize, option: String },
+    /// Matches without a timestamp, left out of a histogram.
+    Untimed { count: u64 },
+    /// --approximate may have lost values with as many matches as some it printed.
+    Uncertain { bound: usize, message: String },
+    /// Nearly every sampled line failed to parse as the selected log type, but all of them
+    /// parse as the detected one.
+    LogTypeMismatch { file: PathBuf, selected: String, detected: String, message: String },
+    /// Decoding a gzipped file stopped after `consumed` of its `size` bytes, at something that
+    /// isn't a gzip member, so the rest of it wasn't read.
+    GzipIncomplete { file: PathBuf, consumed: u64, size: u64 },
+    /// Entries left without a `gap`: earlier than their client's previous request, as the input
+    /// isn't in time order, or of clients past --max-clients.
+    GapUnknown { file: PathBuf, out_of_order: usize, untracked: usize, max_clients: usize },
+    /// A --filter-file changed to something that doesn't parse, so --reload-on-change kept the
+    /// conditions read before.
+    FilterFileInvalid { file: PathBuf, reason: String },
+}
+
+impl Warning {
+    /// The line the warning is about, if it's about one line.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Warning::MalformedLine { line, .. }
+            | Warning::TimestampRegression { line, .. }
+            | Warning::Interleave { line, .. }
+            | Warning::OrderAssumed { line, .. } => Some(*line),
+            Warning::NoDuration { .. }
+            | Warning::MissingFields { .. }
+            | Warning::Untimed { .. }
+            | Warning::Uncertain { .. }
+            | Warning::LogTypeMismatch { .. }
+            | Warning::GzipIncomplete { .. }
+            | Warning::GapUnknown { .. }
+            | Warning::FilterFileInvalid { .. } => None,
+        }
+    }
+
+    /// Moves line numbers on by `lines`, see `Error::shift_lines`.
+    pub fn shift_lines(&mut self, lines: usize) {
+        match self {
+            Warning::TimestampRegression { line, previous_line, .. } => {
+                *line += lines;
+                *previous_line += lines;
+            }
+            Warning::MalformedLine { line, .. } | Warning::Interleave { line, .. } | Warning::OrderAssumed { line, .. } => {
+                *line += lines
+            }
+            Warning::NoDuration { .. }
+            | Warning::MissingFields { .. }
+            | Warning::Untimed { .. }
+            | Warning::Uncertain { .. }
+            | Warning::LogTypeMismatch { .. }
+            | Warning::GzipIncomplete { .. }
+            | Warning::GapUnknown { .. }
+            | Warning::FilterFileInvalid { .. } => {}
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Notice {
+    /// Values for an `in` filter read from an `@file`.
+    ValuesLoaded { option: String, file: PathBuf, count: usize },
+    /// Matches --throttle held back for one key value during a window.
+    Suppressed { key: String, value: String, count: usize },
+    /// Lines written to --output-file, --unmatched-file or --malformed-file.
+    Written { sink: String, file: PathBuf, lines: usize },
+    /// Lines at the start of a file skipped by --dedupe-overlap as repeats of the previous file.
+    Deduplicated { file: PathBuf, previous: PathBuf, lines: usize },
+    /// A file --follow-glob switched to, and the file it was following before, under the name
+    /// that file had when it was opened.
+    Following { file: PathBuf, previous: Option<PathBuf> },
+    /// --group-by holds every group until the input ends, as it can't tell when one is complete.
+    Buffering { key: String, by_time: bool, assume_sorted: bool },
+    /// A --fail-on condition that held once the run finished, and the value that met it.
+    Failed { condition: String, counter: String, value: f64 },
+    /// The conditions --reload-on-change took out of the filter and put in when the --filter-file
+    /// changed.
+    FilterReloaded { file: PathBuf, removed: Vec<String>, added: Vec<String> },
+}
+
+#[derive(Serialize)]
+pub struct RunSummary {
+    pub file: PathBuf,
+    pub lines: usize,
+    pub bytes: u64,
+    /// Size on disk of a gzipped file, whose `bytes` are those decompressed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compressed_bytes: Option<u64>,
+    pub matched: usize,
+    /// Response size of the matches, tracked with --show-bytes.
+    #[serde(flatten)]
+    pub matched_bytes: Option<MatchedBytes>,
+    pub malformed: usize,
+    /// Of the malformed lines, those that weren't --unwrap records, counted with --unwrap; the
+    /// rest failed to parse as the log type.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wrapper_errors: Option<usize>,
+    /// Entries, not among the malformed lines, whose request line isn't `METHOD PATH PROTOCOL`.
+    pub malformed_requests: usize,
+    /// Entries timestamped earlier than the entry before them, beyond --order-slack.
+    pub regressions: usize,
+    /// Entries with a numeric field, such as bytes, whose value isn't a number; they're kept,
+    /// without the field, unless --strict-numbers makes them malformed.
+    pub invalid_numbers: usize,
+    /// Lines skipped as repeating one just before them, with --skip-consecutive-duplicates or
+    /// --skip-duplicates-window.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicates: Option<usize>,
+    /// Parsed entries that didn't match, by the first filter that rejected them, with --summary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<BTreeMap<String, usize>>,
+    /// Entries with a hostname for a client, which --ip never matches, counted with --ip.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname_clients: Option<usize>,
+    /// Reading stopped early on Ctrl-C.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+}
+
+impl RunSummary {
+    /// Adds the counts of a later part of the same file, see --jobs.
+    pub fn merge(&mut self, other: RunSummary) {
+        self.lines += other.lines;
+        self.bytes += other.bytes;
+        self.compressed_bytes = self.compressed_bytes.or(other.compressed_bytes);
+        self.matched += other.matched;
+        if let (Some(bytes), Some(other)) = (&mut self.matched_bytes, other.matched_bytes) {
+            bytes.merge(other);
+        }
+        self.malformed += other.malformed;
+        if let (Some(count), Some(other)) = (&mut self.wrapper_errors, other.wrapper_errors) {
+            *count += other;
+        }
+        self.malformed_requests += other.malformed_requests;
+        self.regressions += other.regressions;
+        self.invalid_numbers += other.invalid_numbers;
+        if let (Some(count), Some(other)) = (&mut self.duplicates, other.duplicates) {
+            *count += other;
+        }
+        if let (Some(rejected), Some(other)) = (&mut self.rejected, other.rejected) {
+            for (filter, count) in other {
+                *rejected.entry(filter).or_default() += count;
+            }
+        }
+        if let (Some(count), Some(other)) = (&mut self.hostname_clients, other.hostname_clients) {
+            *count += other;
+        }
+        self.interrupted |= other.interrupted;
+    }
+
+    pub fn reject(&mut self, filter: &str) {
+        if let Some(rejected) = &mut self.rejected {
+            match rejected.get_mut(filter) {
+                Some(count) => *count += 1,
+                None => {
+                    rejected.insert(filter.to_string(), 1);
+                }
+            }
+        }
+    }
+}
+
+/// `12003` as `12,003`.
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+/// `rejected by: timestamp 12,003; user_agent 4,410`, busiest filter first.
+fn describe_rejections(rejected: &BTreeMap<String, usize>) -> String {
+    if rejected.is_empty() {
+        return "rejected nothing".to_string();
+    }
+    let mut counts: Vec<(&String, &usize)> = rejected.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let counts: Vec<String> = counts.iter().map(|(filter, count)| format!("{} {}", filter, thousands(**count))).collect();
+    format!("rejected by: {}", counts.join("; "))
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Diagnostic<'a> {
+    Error(&'a Error),
+    Warning(&'a Warning),
+    Notice(&'a Notice),
+    Summary(&'a RunSummary),
+    Breakdown(&'a Breakdown),
+    Snapshot(&'a Snapshot),
+    Schema(&'a Preamble),
+}
+
+/// A snapshot as a JSON line, the same as on stderr with `--errors json`, for --snapshot-file.
+pub fn snapshot_record(snapshot: &Snapshot) -> String {
+    serde_json::to_string(&Diagnostic::Snapshot(snapshot)).expect("snapshots serialize")
+}
+
+pub struct Reporter {
+    format: ErrorFormat,
+    /// Warnings kept back rather than written, see `holding`.
+    held: Option<RefCell<Vec<Warning>>>,
+}
+
+impl Reporter {
+    pub fn new(format: ErrorFormat) -> Self {
+        Reporter { format, held: None }
+    }
+
+    /// A reporter that keeps warnings for `take_held` and drops summaries, for a --jobs thread
+    /// whose line numbers are only known once the ranges before it are read.
+    pub fn holding() -> Self {
+        Reporter { format: ErrorFormat::default(), held: Some(RefCell::default()) }
+    }
+
+    pub fn take_held(&self) -> Vec<Warning> {
+        self.held.as_ref().map(RefCell::take).unwrap_or_default()
+    }
+
+    fn emit_json(&self, diagnostic: Diagnostic) {
+        match serde_json::to_string(&diagnostic) {
+            Ok(json) => eprintln!("{}", json),
+            Err(e) => eprintln!("{{\"type\":\"error\",\"kind\":\"other\",\"message\":{}}}", serde_json::Value::from(e.to_string())),
+        }
+    }
+
+    pub fn error(&self, error: &Error) {
+        match self.format {
+            ErrorFormat::Human => eprintln!("Error: {}", error),
+            ErrorFormat::Json => self.emit_json(Diagnostic::Error(error)),
+        }
+    }
+
+    pub fn warning(&self, warning: Warning) {
+        if let Some(held) = &self.held {
+            held.borrow_mut().push(warning);
+            return;
+        }
+        match (self.format, &warning) {
+            (ErrorFormat::Human, Warning::MalformedLine { .. }) => {}
+            (
+                ErrorFormat::Human,
+                Warning::NoDuration { message } | Warning::MissingFields { message, .. } | Warning::Uncertain { message, .. },
+            ) => {
+                eprintln!("Warning: {}", message)
+            }
+            (ErrorFormat::Human, Warning::TimestampRegression { file, line, timestamp, previous_line, previous }) => eprintln!(
+                "Warning: {}: line {}: timestamp {} is earlier than {} on line {}",
+                file.display(),
+                line,
+                timestamp.to_rfc3339(),
+                previous.to_rfc3339(),
+                previous_line
+            ),
+            (ErrorFormat::Human, Warning::Interleave { file, line, timestamp, previous }) => eprintln!(
+                "Warning: {}: line {}: timestamp {} is earlier than {}, already printed; --interleave output \
+                 from this file is out of order",
+                file.display(),
+                line,
+                timestamp.to_rfc3339(),
+                previous.to_rfc3339()
+            ),
+            (ErrorFormat::Human, Warning::OrderAssumed { file, line, option }) => eprintln!(
+                "Warning: {}: line {}: timestamps go backwards, but {} assumes the input is in time order and \
+                 may miss matches",
+                file.display(),
+                line,
+                option
+            ),
+            (ErrorFormat::Human, Warning::Untimed { count }) => {
+                let plural = if *count == 1 { "" } else { "es" };
+                eprintln!("Warning: left out {} match{} without a timestamp", count, plural)
+            }
+            (ErrorFormat::Human, Warning::LogTypeMismatch { file, message, .. }) => {
+                eprintln!("Warning: {}: {}", file.display(), message)
+            }
+            (ErrorFormat::Human, Warning::GzipIncomplete { file, consumed, size }) => eprintln!(
+                "Warning: {}: gzip decoding stopped after {} of {} compressed bytes; the rest of the file wasn't read",
+                file.display(),
+                consumed,
+                size
+            ),
+            (ErrorFormat::Human, Warning::GapUnknown { file, out_of_order, untracked, max_clients }) => {
+                let entries = |n: usize| format!("{} {}", thousands(n), if n == 1 { "entry" } else { "entries" });
+                let mut reasons = Vec::new();
+                if *out_of_order > 0 {
+                    reasons.push(format!("{} earlier than their client's previous request", entries(*out_of_order)));
+                }
+                if *untracked > 0 {
+                    reasons.push(format!("{} of clients past --max-clients {}", entries(*untracked), max_clients));
+                }
+                eprintln!("Warning: {}: no gap for {}", file.display(), reasons.join(", nor for "))
+            }
+            (ErrorFormat::Human, Warning::FilterFileInvalid { reason, .. }) => {
+                eprintln!("Warning: --filter-file {}; still filtering with the conditions read before", reason)
+            }
+            (ErrorFormat::Json, _) => self.emit_json(Diagnostic::Warning(&warning)),
+        }
+    }
+
+    pub fn notice(&self, notice: Notice) {
+        match (self.format, &notice) {
+            (ErrorFormat::Human, Notice::ValuesLoaded { option, file, count }) => {
+                let plural = if *count == 1 { "" } else { "s" };
+                eprintln!("Loaded {} value{} for {} from {}", count, plural, option, file.display())
+            }
+            (ErrorFormat::Human, Notice::Suppressed { value, count, .. }) => {
+                let plural = if *count == 1 { "" } else { "es" };
+                eprintln!("... suppressed {} further match{} from {}", count, plural, value)
+            }
+            (ErrorFormat::Human, Notice::Written { sink, file, lines }) => {
+                let plural = if *lines == 1 { "" } else { "s" };
+                eprintln!("Wrote {} {} line{} to {}", lines, sink, plural, file.display())
+            }
+            (ErrorFormat::Human, Notice::Deduplicated { file, previous, lines }) => {
+                let plural = if *lines == 1 { "" } else { "s" };
+                eprintln!(
+                    "Skipping {} line{} at the start of {} that repeat the end of {}",
+                    lines,
+                    plural,
+                    file.display(),
+                    previous.display()
+                )
+            }
+            (ErrorFormat::Human, Notice::Following { file, previous: Some(previous) }) => {
+                eprintln!("Following {}; reading what was {} to its end first", file.display(), previous.display())
+            }
+            (ErrorFormat::Human, Notice::Following { file, previous: None }) => eprintln!("Following {}", file.display()),
+            (ErrorFormat::Human, Notice::Buffering { key, by_time, assume_sorted }) => {
+                let why = match (by_time, assume_sorted) {
+                    (true, _) => "; pass --assume-sorted if the input is in time order to print each as it completes",
+                    (false, true) => ": --assume-sorted only lets day and hour groups print as they complete",
+                    (false, false) => "",
+                };
+                eprintln!("Holding every --group-by {} group until the input ends{}", key, why)
+            }
+            (ErrorFormat::Human, Notice::Failed { condition, counter, value }) => {
+                eprintln!("--fail-on {} holds: {} is {}", condition, counter, value)
+            }
+            (ErrorFormat::Human, Notice::FilterReloaded { file, removed, added }) => {
+                let changes: Vec<String> =
+                    removed.iter().map(|c| format!("- {}", c)).chain(added.iter().map(|c| format!("+ {}", c))).collect();
+                match changes.is_empty() {
+                    true => eprintln!("Reloaded {}: the conditions are the same", file.display()),
+                    false => eprintln!("Reloaded {}:\n  {}", file.display(), changes.join("\n  ")),
+                }
+            }
+            (ErrorFormat::Json, _) => self.emit_json(Diagnostic::Notice(&notice)),
+        }
+    }
+
+    pub fn summary(&self, summary: &RunSummary) {
+        if self.held.is_some() {
+            return;
+        }
+        match self.format {
+            ErrorFormat::Human => {
+                let marker = if summary.interrupted { " (interrupted)" } else { "" };
+                match (&summary.rejected, &summary.matched_bytes) {
+                    (Some(rejected), bytes) => eprintln!(
+                        "{}: {} lines, {}; {}{}",
+                        summary.file.display(),
+                        thousands(summary.lines),
+                        describe_matches(summary.matched, bytes.as_ref()),
+                        describe_rejections(rejected),
+                        marker
+                    ),
+                    (None, bytes) if summary.interrupted => eprintln!(
+                        "{}: {} lines, {}{}",
+                        summary.file.display(),
+                        thousands(summary.lines),
+                        describe_matches(summary.matched, bytes.as_ref()),
+                        marker
+                    ),
+                    (None, Some(bytes)) => {
+                        eprintln!("{}: {}", summary.file.display(), describe_matches(summary.matched, Some(bytes)))
+                    }
+                    (None, None) => {}
+                }
+                if let (Some(compressed), Some(_)) = (summary.compressed_bytes, &summary.rejected) {
+                    eprintln!(
+                        "{}: {} bytes decompressed from {} gzip bytes",
+                        summary.file.display(),
+                        summary.bytes,
+                        compressed
+                    );
+                }
+                match summary.wrapper_errors {
+                    _ if summary.malformed == 0 => {}
+                    Some(wrapper) => eprintln!(
+                        "Skipped {} malformed lines ({} wrapper errors, {} payload parse errors)",
+                        summary.malformed,
+                        wrapper,
+                        summary.malformed.saturating_sub(wrapper)
+                    ),
+                    None => eprintln!("Skipped {} malformed lines", summary.malformed),
+                }
+                if let Some(count @ 1..) = summary.hostname_clients {
+                    let plural = if count == 1 { "entry has" } else { "entries have" };
+                    eprintln!(
+                        "{}: {} {} a hostname for a client, which --ip never matches (see --client)",
+                        summary.file.display(),
+                        thousands(count),
+                        plural
+                    );
+                }
+                if summary.malformed_requests > 0 {
+                    let plural = if summary.malformed_requests == 1 { "entry has" } else { "entries have" };
+                    eprintln!(
+                        "{}: {} {} a request line that isn't METHOD PATH PROTOCOL (see --only-malformed-requests)",
+                        summary.file.display(),
+                        thousands(summary.malformed_requests),
+                        plural
+                    );
+                }
+                if summary.invalid_numbers > 0 {
+                    let plural = if summary.invalid_numbers == 1 { "entry has" } else { "entries have" };
+                    eprintln!(
+                        "{}: {} {} a bytes value that isn't a number, kept without bytes (see --strict-numbers)",
+                        summary.file.display(),
+                        thousands(summary.invalid_numbers),
+                        plural
+                    );
+                }
+                if let Some(count @ 1..) = summary.duplicates {
+                    let plural = if count == 1 { "line" } else { "lines" };
+                    eprintln!(
+                        "{}: skipped {} duplicate {} repeating a line just before",
+                        summary.file.display(),
+                        thousands(count),
+                        plural
+                    );
+                }
+                if summary.regressions > 0 {
+                    let plural = if summary.regressions == 1 { "" } else { "s" };
+                    eprintln!(
+                        "{}: timestamps went backwards {} time{} (see --check-order)",
+                        summary.file.display(),
+                        summary.regressions,
+                        plural
+                    );
+                }
+            }
+            ErrorFormat::Json => self.emit_json(Diagnostic::Summary(summary)),
+        }
+    }
+
+    /// Announces the schema version ahead of the JSON diagnostics, for `--schema-version`.
+    pub fn schema(&self) {
+        if let ErrorFormat::Json = self.format {
+            self.emit_json(Diagnostic::Schema(&Preamble { schema_version: SCHEMA_VERSION }));
+        }
+    }
+
+    pub fn breakdown(&self, breakdown: &Breakdown) {
+        match self.format {
+            ErrorFormat::Human => eprint!("{}", breakdown.render()),
+            ErrorFormat::Json => self.emit_json(Diagnostic::Breakdown(breakdown)),
+        }
+    }
+
+    /// A partial result, `text` as the run would print it.
+    pub fn snapshot(&self, snapshot: &Snapshot, text: &str) {
+        match self.format {
+            ErrorFormat::Human => {
+                let marker = if snapshot.interrupted { ", interrupted" } else { "" };
+                eprint!(
+                    "Partial result after {} lines, {:.1}s (snapshot {}{}):\n{}",
+                    thousands(snapshot.lines),
+                    snapshot.elapsed_secs,
+                    snapshot.sequence,
+                    marker,
+                    text
+                )
+            }
+            ErrorFormat::Json => self.emit_json(Diagnostic::Snapshot(snapshot)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejections_are_listed_busiest_first() {
+        assert_eq!(thousands(0), "0");
+        assert_eq!(thousands(999), "999");
+        assert_eq!(thousands(12003), "12,003");
+        assert_eq!(thousands(1234567), "1,234,567");
+        let rejected = BTreeMap::from([("ip".to_string(), 2), ("timestamp".to_string(), 12003), ("user_agent".to_string(), 4410)]);
+        assert_eq!(describe_rejections(&rejected), "rejected by: timestamp 12,003; user_agent 4,410; ip 2");
+        assert_eq!(describe_rejections(&BTreeMap::new()), "rejected nothing");
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+// --skip-consecutive-duplicates and --skip-duplicates-window: log shippers that retry a write
+// can log the same line twice in a row, which counts the request twice. A line exactly like
+// one of the lines just before it in the same file is skipped before it's parsed, malformed or
+// not, and counted in the summary as a duplicate. Consecutive duplicates are found by keeping
+// the line before; a window of N keeps a hash of each of the last N lines, so a line is very
+// rarely taken for a duplicate of a different one, and counts how often each hash is in the
+// window so a line is checked in constant time however large it is. Empty lines are never
+// duplicates. Lines keep their numbers in the file.
+
+/// The lines just read from one file, to tell when a line repeats one of them.
+pub struct Duplicates {
+    window: usize,
+    previous: String,
+    hashes: VecDeque<u64>,
+    /// How many of `hashes` are each hash.
+    counts: HashMap<u64, u32>,
+}
+
+impl Duplicates {
+    /// Remembers the last `window` lines, 1 for only the line before.
+    pub fn new(window: usize) -> Self {
+        let capacity = window.min(1 << 16);
+        let (hashes, counts) = (VecDeque::with_capacity(capacity), HashMap::with_capacity(capacity));
+        Duplicates { window, previous: String::new(), hashes, counts }
+    }
+
+    /// Whether `line` repeats one of the lines read before it, which it then becomes one of.
+    pub fn repeats(&mut self, line: &str) -> bool {
+        if self.window <= 1 {
+            let repeated = !self.previous.is_empty() && self.previous == line;
+            if !repeated {
+                self.previous.clear();
+                self.previous.push_str(line);
+            }
+            return repeated;
+        }
+        let mut hasher = DefaultHasher::new();
+        line.hash(&mut hasher);
+        let hash = hasher.finish();
+        let repeated = !line.is_empty() && self.counts.contains_key(&hash);
+        if self.hashes.len() == self.window {
+            if let Some(Entry::Occupied(mut count)) = self.hashes.pop_front().map(|oldest| self.counts.entry(oldest)) {
+                *count.get_mut() -= 1;
+                if *count.get() == 0 {
+                    count.remove();
+                }
+            }
+        }
+        self.hashes.push_back(hash);
+        *self.counts.entry(hash).or_default() += 1;
+        repeated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skipped(window: usize, lines: &[&str]) -> Vec<usize> {
+        let mut duplicates = Duplicates::new(window);
+        (1..).zip(lines).filter(|(_, line)| duplicates.repeats(line)).map(|(line_no, _)| line_no).collect()
+    }
+
+    #[test]
+    fn lines_repeating_those_just_before_them_are_skipped() {
+        let lines = ["a", "a", "a", "b", "a", "c", "b", "a"];
+        assert_eq!(skipped(1, &lines), [2, 3]);
+        assert_eq!(skipped(2, &lines), [2, 3, 5]);
+        assert_eq!(skipped(3, &lines), [2, 3, 5, 7, 8]);
+        // Blank lines aren't duplicates in either mode, but take their place in the window.
+        let lines = ["", "", "a", "", "a"];
+        assert_eq!(skipped(1, &lines), Vec::<usize>::new());
+        assert_eq!(skipped(2, &lines), [5]);
+    }
+
+    #[test]
+    fn a_line_leaving_the_window_only_forgets_its_own_copy() {
+        // The first "a" leaves a window of 3 when "c" comes in, while the second is still in it.
+        assert_eq!(skipped(3, &["a", "b", "a", "c", "a", "d", "e", "f", "a"]), [3, 5]);
+    }
+}
@@ -0,0 +1,144 @@
+use serde::{Serialize, Serializer};
+use std::{fmt, str::FromStr, time::Duration};
+
+/// A duration that parses from human-friendly values such as `250ms`, `1.5s`, `2m`, `1h30m`
+/// or `90` (seconds), so latencies logged in different units compare correctly and every
+/// duration option takes the same syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HumanDuration(pub Duration);
+
+/// Each unit with its length in nanoseconds.
+const UNITS: &[(&str, f64)] =
+    &[("h", 3.6e12), ("m", 6e10), ("s", 1e9), ("ms", 1e6), ("us", 1e3), ("µs", 1e3), ("ns", 1.0)];
+
+fn is_numeric(c: char) -> bool {
+    c.is_ascii_digit() || c == '.'
+}
+
+impl HumanDuration {
+    pub fn from_secs_f64(secs: f64) -> Option<Self> {
+        Duration::try_from_secs_f64(secs).ok().map(HumanDuration)
+    }
+
+    pub fn from_millis_f64(millis: f64) -> Option<Self> {
+        Self::from_secs_f64(millis / 1_000.0)
+    }
+
+    pub fn from_micros_f64(micros: f64) -> Option<Self> {
+        Self::from_secs_f64(micros / 1_000_000.0)
+    }
+
+    pub fn as_secs_f64(&self) -> f64 {
+        self.0.as_secs_f64()
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = String;
+
+    /// A number and unit, several of them added up as in `1h30m`, or a bare number of seconds.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| format!("invalid duration {:?}: {}", s, reason);
+        let number = |number: &str| number.parse::<f64>().map_err(|_| invalid(format!("{:?} isn't a number", number)));
+        if s.starts_with('-') {
+            return Err(invalid("durations can't be negative".to_string()));
+        }
+        if !s.is_empty() && s.chars().all(is_numeric) {
+            return Self::from_secs_f64(number(s)?).ok_or_else(|| invalid("too long".to_string()));
+        }
+        let mut rest = s;
+        let mut total = Duration::ZERO;
+        while !rest.is_empty() {
+            let (value, tail) = rest.split_at(rest.find(|c| !is_numeric(c)).unwrap_or(rest.len()));
+            let (unit, next) = tail.split_at(tail.find(is_numeric).unwrap_or(tail.len()));
+            let nanos = UNITS.iter().find(|(name, _)| *name == unit).map(|&(_, nanos)| nanos);
+            let nanos = match (value.is_empty(), nanos) {
+                (true, Some(_)) => return Err(invalid(format!("expected a number before {:?}", unit))),
+                (true, None) => return Err(invalid("expected a number and unit such as 250ms, 1.5s, 2m or 1h30m".to_string())),
+                (false, None) if unit.is_empty() => return Err(invalid(format!("{:?} needs a unit: h, m, s, ms, us or ns", value))),
+                (false, None) => return Err(invalid(format!("unknown unit {:?}, expected h, m, s, ms, us or ns", unit))),
+                (false, Some(nanos)) => nanos,
+            };
+            let part = Duration::try_from_secs_f64(number(value)? * nanos / 1e9).map_err(|_| invalid("too long".to_string()))?;
+            total = total.checked_add(part).ok_or_else(|| invalid("too long".to_string()))?;
+            rest = next;
+        }
+        match s.is_empty() {
+            true => Err(invalid("expected a number and unit such as 250ms, 1.5s, 2m or 1h30m".to_string())),
+            false => Ok(HumanDuration(total)),
+        }
+    }
+}
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}s", self.as_secs_f64())
+    }
+}
+
+impl Serialize for HumanDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_units_and_compound_forms() {
+        let millis = |millis| Ok(HumanDuration(Duration::from_millis(millis)));
+        for (input, expected) in [
+            ("2", millis(2_000)),
+            ("90", millis(90_000)),
+            ("1.5", millis(1_500)),
+            ("0", millis(0)),
+            (".5", millis(500)),
+            ("1.5s", millis(1_500)),
+            ("250ms", millis(250)),
+            ("2m", millis(120_000)),
+            ("1h", millis(3_600_000)),
+            ("1h30m", millis(5_400_000)),
+            ("1.5h", millis(5_400_000)),
+            ("2m30s", millis(150_000)),
+            ("1m500ms", millis(60_500)),
+            ("0s", millis(0)),
+            ("40us", Ok(HumanDuration(Duration::from_micros(40)))),
+            ("40µs", Ok(HumanDuration(Duration::from_micros(40)))),
+            ("1500ns", Ok(HumanDuration(Duration::from_nanos(1_500)))),
+        ] {
+            assert_eq!(input.parse::<HumanDuration>(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_negatives_and_garbage_with_the_reason() {
+        for (input, reason) in [
+            ("-1s", "durations can't be negative"),
+            ("-5", "durations can't be negative"),
+            ("", "expected a number and unit such as 250ms, 1.5s, 2m or 1h30m"),
+            ("soon", "expected a number and unit such as 250ms, 1.5s, 2m or 1h30m"),
+            ("ms", "expected a number before \"ms\""),
+            ("5x", "unknown unit \"x\", expected h, m, s, ms, us or ns"),
+            ("5 s", "unknown unit \" s\", expected h, m, s, ms, us or ns"),
+            ("5M", "unknown unit \"M\", expected h, m, s, ms, us or ns"),
+            ("1h30", "\"30\" needs a unit: h, m, s, ms, us or ns"),
+            ("1.2.3", "\"1.2.3\" isn't a number"),
+            ("1..5s", "\"1..5\" isn't a number"),
+            ("1e3", "unknown unit \"e\", expected h, m, s, ms, us or ns"),
+            ("99999999999999999999h", "too long"),
+        ] {
+            assert_eq!(input.parse::<HumanDuration>(), Err(format!("invalid duration {:?}: {}", input, reason)));
+        }
+    }
+
+    #[test]
+    fn units_compare_by_length() {
+        let ms: HumanDuration = "1500ms".parse().unwrap();
+        let s: HumanDuration = "1.5".parse().unwrap();
+        assert_eq!(ms, s);
+        assert!("999ms".parse::<HumanDuration>().unwrap() < s);
+        assert!("59s".parse::<HumanDuration>().unwrap() < "1m".parse().unwrap());
+    }
+}
@@ -0,0 +1,452 @@
+use access_log_parser::{CombinedLogEntry, CommonLogEntry, RequestResult};
+use crate::duration::HumanDuration;
+use crate::tls::TlsVersion;
+use chrono::{DateTime, FixedOffset};
+use http::StatusCode;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use std::{collections::BTreeMap, fmt, net::IpAddr, path::PathBuf, str::FromStr, sync::OnceLock};
+
+/// Error log severity, ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Trace,
+    Debug,
+    Info,
+    Notice,
+    Warn,
+    Error,
+    Crit,
+    Alert,
+    Emerg,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            level if level.starts_with("trace") => Ok(Severity::Trace),
+            "debug" => Ok(Severity::Debug),
+            "info" => Ok(Severity::Info),
+            "notice" => Ok(Severity::Notice),
+            "warn" | "warning" => Ok(Severity::Warn),
+            "error" | "err" => Ok(Severity::Error),
+            "crit" | "critical" => Ok(Severity::Crit),
+            "alert" => Ok(Severity::Alert),
+            "emerg" | "emergency" => Ok(Severity::Emerg),
+            _ => Err(format!("Invalid severity: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl Serialize for Severity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Who sent a request: its address, or with Apache's `HostnameLookups On` the name the address
+/// resolved to. Which one is decided per line, since a lookup that fails logs the address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Client {
+    Ip(IpAddr),
+    Host(String),
+}
+
+impl Client {
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            Client::Ip(ip) => Some(*ip),
+            Client::Host(_) => None,
+        }
+    }
+
+    pub fn is_host(&self) -> bool {
+        matches!(self, Client::Host(_))
+    }
+}
+
+impl FromStr for Client {
+    type Err = String;
+
+    /// An address, or else a fully qualified hostname: labels of letters, digits and `-`, at
+    /// least two, the last not a number, so neither a word nor a mangled address passes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(ip) = s.parse() {
+            return Ok(Client::Ip(ip));
+        }
+        let labels: Vec<&str> = s.split('.').collect();
+        let hostname = labels.len() > 1
+            && !labels[labels.len() - 1].bytes().all(|b| b.is_ascii_digit())
+            && labels.iter().all(|label| {
+                !label.is_empty() && !label.starts_with('-') && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+            });
+        match hostname {
+            true => Ok(Client::Host(s.to_string())),
+            false => Err(format!("Invalid client {:?}: expected an IP address or a hostname", s)),
+        }
+    }
+}
+
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Client::Ip(ip) => ip.fmt(f),
+            Client::Host(host) => f.write_str(host),
+        }
+    }
+}
+
+/// The client as `ip`, null for a hostname, and `hostname` when there is one, so `ip` keeps
+/// holding only addresses.
+fn serialize_client<S: Serializer>(client: &Option<Client>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(None)?;
+    map.serialize_entry("ip", &client.as_ref().and_then(Client::ip))?;
+    if let Some(Client::Host(host)) = client {
+        map.serialize_entry("hostname", host)?;
+    }
+    map.end()
+}
+
+/// Value of a field that has no dedicated slot in `Entry`, e.g. nginx's `$request_time`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Integer(i64),
+    Number(f64),
+    Text(String),
+    /// Several values, such as the reasons a path is suspicious; as text, joined by commas.
+    List(Vec<String>),
+}
+
+impl FieldValue {
+    /// Parses a raw captured value, treating `-` and empty strings as absent.
+    pub fn parse(raw: &str) -> Option<FieldValue> {
+        if raw.is_empty() || raw == "-" {
+            return None;
+        }
+        if let Ok(n) = raw.parse::<i64>() {
+            return Some(FieldValue::Integer(n));
+        }
+        match raw.parse::<f64>() {
+            Ok(n) if n.is_finite() => Some(FieldValue::Number(n)),
+            _ => Some(FieldValue::Text(raw.to_string())),
+        }
+    }
+
+    pub fn as_text(&self) -> String {
+        match self {
+            FieldValue::Integer(n) => n.to_string(),
+            FieldValue::Number(n) => n.to_string(),
+            FieldValue::Text(s) => s.clone(),
+            FieldValue::List(values) => values.join(","),
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            FieldValue::Integer(n) => Some(*n as f64),
+            FieldValue::Number(n) => Some(*n),
+            FieldValue::Text(_) | FieldValue::List(_) => None,
+        }
+    }
+}
+
+/// A parsed log line, independent of the format it was read from.
+#[derive(Debug, Default, Serialize)]
+pub struct Entry {
+    #[serde(flatten, serialize_with = "serialize_client")]
+    pub client: Option<Client>,
+    pub user: Option<String>,
+    #[serde(serialize_with = "crate::output::serialize_timestamp")]
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub method: Option<String>,
+    pub path: Option<String>,
+    /// With --normalize-paths, the path that filters and keys see.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_normalized: Option<String>,
+    pub protocol: Option<String>,
+    /// The request line as logged, when it isn't `METHOD TARGET PROTOCOL`; method, path and
+    /// protocol are then absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_request: Option<String>,
+    #[serde(serialize_with = "serialize_status")]
+    pub status_code: Option<StatusCode>,
+    pub bytes: Option<u64>,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<HumanDuration>,
+    /// `http` or `https`, for formats that log how the request came in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    /// The port the request came in on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_protocol: Option<TlsVersion>,
+    /// Numeric fields logged with a value that isn't one, such as a negative byte count, as
+    /// logged; the fields themselves are absent.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub invalid: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub extras: BTreeMap<String, FieldValue>,
+}
+
+static STRICT_NUMBERS: OnceLock<bool> = OnceLock::new();
+
+/// Makes numeric fields that don't parse fail their line for the rest of the run, with
+/// --strict-numbers; only the first call has an effect.
+pub fn set_strict_numbers(strict: bool) {
+    let _ = STRICT_NUMBERS.set(strict);
+}
+
+/// Where a line was read from. For gzip-compressed files the offset counts decompressed
+/// bytes, the ones `zcat` prints.
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance {
+    pub source_file: PathBuf,
+    /// 1-based.
+    pub line_no: usize,
+    /// Where the line starts.
+    pub byte_offset: u64,
+}
+
+/// A line that passed the filter, as read and as parsed.
+#[derive(Debug)]
+pub struct Match {
+    pub line: String,
+    pub entry: Entry,
+    pub provenance: Provenance,
+}
+
+impl Entry {
+    /// The client's address, `None` for a hostname.
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.client.as_ref().and_then(Client::ip)
+    }
+
+    /// The path as filters and keys see it: normalized with --normalize-paths, else as logged.
+    pub fn match_path(&self) -> &Option<String> {
+        match self.path_normalized {
+            Some(_) => &self.path_normalized,
+            None => &self.path,
+        }
+    }
+
+    /// Sets `bytes` from its logged value. One that isn't a byte count, such as `-5`, a count
+    /// past 2^64 or text, leaves it absent and is kept in `invalid`, unless --strict-numbers
+    /// makes it fail the line with `error`.
+    pub fn set_bytes(&mut self, value: &str, error: impl FnOnce() -> String) -> Result<(), String> {
+        match value.parse() {
+            Ok(bytes) => self.bytes = Some(bytes),
+            Err(_) if STRICT_NUMBERS.get().copied().unwrap_or_default() => return Err(error()),
+            Err(_) => {
+                self.bytes = None;
+                self.invalid.insert("bytes".to_string(), value.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits a raw request line such as `GET /index.html HTTP/1.1` into method, path and protocol.
+    /// A line that doesn't split that way, such as the raw bytes of a TLS handshake sent to a
+    /// plain HTTP port, is kept whole in `raw_request` instead; `-` or nothing means no request.
+    pub fn set_request(&mut self, request: &str) {
+        let request = request.trim();
+        self.method = None;
+        self.path = None;
+        self.protocol = None;
+        self.raw_request = None;
+        if request.split_whitespace().all(|part| part == "-") {
+            return;
+        }
+        match decompose(request) {
+            Some((method, target, protocol)) => {
+                self.method = Some(method.to_string());
+                self.path = Some(target.to_string());
+                self.protocol = protocol.map(str::to_string);
+            }
+            None => self.raw_request = Some(request.to_string()),
+        }
+    }
+
+    fn set_request_result(&mut self, request: RequestResult<'_>) {
+        match request {
+            RequestResult::Valid(request) => {
+                self.method = Some(request.method().to_string());
+                self.path = Some(request.uri().to_string());
+                self.protocol = Some(format!("{:?}", request.version()));
+            }
+            RequestResult::InvalidPath(raw, _) | RequestResult::InvalidRequest(raw) => self.set_request(raw),
+        }
+    }
+}
+
+/// Longer request lines are kept whole; servers refuse them too, nginx at 8k by default.
+const MAX_REQUEST: usize = 8192;
+
+/// Splits `METHOD TARGET PROTOCOL`, or HTTP/0.9's `METHOD TARGET`, into its parts, or `None`
+/// when `request` isn't one. The method is upper case letters, `-` and `_`; the target a path,
+/// `*`, an absolute URL, or `host:port` after CONNECT; the protocol `HTTP/` and a version.
+fn decompose(request: &str) -> Option<(&str, &str, Option<&str>)> {
+    if request.len() > MAX_REQUEST {
+        return None;
+    }
+    let mut parts = request.split_whitespace();
+    let (method, target, protocol) = (parts.next()?, parts.next()?, parts.next());
+    if parts.next().is_some() {
+        return None;
+    }
+    let method_ok = method.len() <= 32
+        && method.starts_with(|c: char| c.is_ascii_uppercase())
+        && method.bytes().all(|b| b.is_ascii_uppercase() || b == b'-' || b == b'_');
+    let scheme = |(scheme, _): (&str, &str)| !scheme.is_empty() && scheme.bytes().all(|b| b.is_ascii_alphabetic());
+    let authority = |(host, port): (&str, &str)| !host.is_empty() && port.parse::<u16>().is_ok();
+    let target_ok = target.starts_with('/')
+        || target == "*"
+        || target.split_once("://").is_some_and(scheme)
+        || (method == "CONNECT" && target.rsplit_once(':').is_some_and(authority));
+    let protocol_ok = protocol.is_none_or(|protocol| {
+        protocol.strip_prefix("HTTP/").is_some_and(|version| {
+            let (major, minor) = version.split_once('.').unwrap_or((version, "0"));
+            [major, minor].iter().all(|n| !n.is_empty() && n.len() <= 2 && n.bytes().all(|b| b.is_ascii_digit()))
+        })
+    });
+    (method_ok && target_ok && protocol_ok).then_some((method, target, protocol))
+}
+
+fn serialize_status<S: Serializer>(status: &Option<StatusCode>, serializer: S) -> Result<S::Ok, S::Error> {
+    match status {
+        Some(status) => serializer.serialize_u16(status.as_u16()),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl From<CombinedLogEntry<'_>> for Entry {
+    fn from(value: CombinedLogEntry<'_>) -> Self {
+        let mut entry = Entry {
+            client: Some(Client::Ip(value.ip)),
+            user: value.user.map(str::to_string),
+            timestamp: Some(value.timestamp),
+            status_code: Some(value.status_code),
+            bytes: Some(value.bytes),
+            referrer: value.referrer.map(|r| r.to_string()),
+            user_agent: value.user_agent.map(str::to_string),
+            ..Default::default()
+        };
+        entry.set_request_result(value.request);
+        entry
+    }
+}
+
+impl From<CommonLogEntry<'_>> for Entry {
+    fn from(value: CommonLogEntry<'_>) -> Self {
+        let mut entry = Entry {
+            client: Some(Client::Ip(value.ip)),
+            user: value.user.map(str::to_string),
+            timestamp: Some(value.timestamp),
+            status_code: Some(value.status_code),
+            bytes: Some(value.bytes),
+            ..Default::default()
+        };
+        entry.set_request_result(value.request);
+        entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clients_are_addresses_or_qualified_hostnames() {
+        assert_eq!("10.0.0.1".parse(), Ok(Client::Ip("10.0.0.1".parse().unwrap())));
+        assert_eq!("2001:db8::1".parse(), Ok(Client::Ip("2001:db8::1".parse().unwrap())));
+        assert_eq!("crawler.example.com".parse(), Ok(Client::Host("crawler.example.com".to_string())));
+        assert_eq!("dsl-77-12.isp.example.net".parse::<Client>().map(|c| c.to_string()), Ok("dsl-77-12.isp.example.net".to_string()));
+        for text in ["this", "10.0.0.256", "-bad.example.com", "a..example.com", "under_score.example.com", ""] {
+            assert!(text.parse::<Client>().is_err(), "{}", text);
+        }
+    }
+
+    fn request(line: &str) -> Entry {
+        let mut entry = Entry::default();
+        entry.set_request(line);
+        entry
+    }
+
+    #[test]
+    fn request_lines_split_into_method_path_and_protocol() {
+        for (line, method, path, protocol) in [
+            ("GET /index.html HTTP/1.1", "GET", "/index.html", Some("HTTP/1.1")),
+            ("POST /api/v1/login?next=/ HTTP/2.0", "POST", "/api/v1/login?next=/", Some("HTTP/2.0")),
+            ("GET / HTTP/2", "GET", "/", Some("HTTP/2")),
+            ("GET /legacy", "GET", "/legacy", None),
+            ("OPTIONS * HTTP/1.1", "OPTIONS", "*", Some("HTTP/1.1")),
+            ("GET http://example.com/proxy HTTP/1.1", "GET", "http://example.com/proxy", Some("HTTP/1.1")),
+            ("CONNECT example.com:443 HTTP/1.1", "CONNECT", "example.com:443", Some("HTTP/1.1")),
+            ("M-SEARCH * HTTP/1.1", "M-SEARCH", "*", Some("HTTP/1.1")),
+            (" PROPFIND /dav/  HTTP/1.1 ", "PROPFIND", "/dav/", Some("HTTP/1.1")),
+        ] {
+            let entry = request(line);
+            assert_eq!(
+                (entry.method.as_deref(), entry.path.as_deref(), entry.protocol.as_deref(), entry.raw_request),
+                (Some(method), Some(path), protocol, None),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn garbage_request_lines_are_kept_whole() {
+        let overlong = format!("GET /{} HTTP/1.1", "A".repeat(MAX_REQUEST));
+        let corpus = [
+            // A TLS ClientHello sent to a plain HTTP port, as nginx escapes it.
+            r"\x16\x03\x01\x00\xA5\x01\x00\x00\xA1\x03\x03",
+            // An RDP connection request.
+            r"\x03\x00\x00/*\xE0\x00\x00\x00\x00\x00Cookie: mstshash=Administr",
+            "OPTIONS sip:nm SIP/2.0",
+            "MGLNDD_203.0.113.1_443",
+            "SSH-2.0-Go",
+            "GET /a b HTTP/1.1",
+            "get / HTTP/1.1",
+            "GET index.html HTTP/1.1",
+            "GET / HTTP/1.1 extra",
+            "GET / FTP/1.0",
+            "GET / HTTP/one",
+            "CONNECT example.com HTTP/1.1",
+            "GET example.com:443 HTTP/1.1",
+            "<BADREQ>",
+            "t3 12.2.1",
+            "- /index.html HTTP/1.1",
+            "GET",
+            &overlong,
+        ];
+        for line in corpus {
+            let entry = request(line);
+            assert_eq!((entry.method, entry.path, entry.protocol), (None, None, None), "{}", line);
+            assert_eq!(entry.raw_request.as_deref(), Some(line.trim()));
+        }
+    }
+
+    #[test]
+    fn a_dash_or_nothing_is_no_request() {
+        for line in ["", "-", "- - -", "  "] {
+            let entry = request(line);
+            assert_eq!((entry.method, entry.path, entry.protocol, entry.raw_request), (None, None, None, None), "{:?}", line);
+        }
+        let mut entry = request("\\x16\\x03");
+        entry.set_request("GET / HTTP/1.1");
+        assert_eq!((entry.method.as_deref(), entry.raw_request), (Some("GET"), None));
+    }
+}
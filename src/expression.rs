@@ -0,0 +1,513 @@
+use crate::entry::{Entry, FieldValue};
+use crate::classify;
+use crate::extract;
+use std::fmt;
+
+// `--where` conditions compare arithmetic over numeric fields, e.g.
+// `target_processing_time / (request_processing_time + target_processing_time) > 0.8`.
+// Operators, loosest first: `or`, `and`, the comparisons `== != < <= > >=`, `+ -`, `* /`, and
+// unary `-`. An expression is type-checked when parsed, so `path + 1` or a bare number is
+// rejected up front; format-specific fields are checked against the first parsed entry, like
+// `--field`. An entry missing a field, or with a text value where the first had a number,
+// simply doesn't match, and neither does division by zero.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Neq,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// Every operator, for `--list-operators`.
+pub const OPERATORS: &[&str] = &["==", "!=", "<", "<=", ">", ">=", "+", "-", "*", "/", "and", "or"];
+
+impl Op {
+    fn parse(token: &str) -> Option<Op> {
+        Some(match token {
+            "or" => Op::Or,
+            "and" => Op::And,
+            "==" => Op::Eq,
+            "!=" => Op::Neq,
+            "<" => Op::Lt,
+            "<=" => Op::Lte,
+            ">" => Op::Gt,
+            ">=" => Op::Gte,
+            "+" => Op::Add,
+            "-" => Op::Sub,
+            "*" => Op::Mul,
+            "/" => Op::Div,
+            _ => return None,
+        })
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Or => "or",
+            Op::And => "and",
+            Op::Eq => "==",
+            Op::Neq => "!=",
+            Op::Lt => "<",
+            Op::Lte => "<=",
+            Op::Gt => ">",
+            Op::Gte => ">=",
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+        }
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Or => 1,
+            Op::And => 2,
+            Op::Eq | Op::Neq | Op::Lt | Op::Lte | Op::Gt | Op::Gte => 3,
+            Op::Add | Op::Sub => 4,
+            Op::Mul | Op::Div => 5,
+        }
+    }
+
+    /// The type of the operands and of the result.
+    fn types(self) -> (Type, Type) {
+        match self {
+            Op::Or | Op::And => (Type::Condition, Type::Condition),
+            Op::Eq | Op::Neq | Op::Lt | Op::Lte | Op::Gt | Op::Gte => (Type::Number, Type::Condition),
+            Op::Add | Op::Sub | Op::Mul | Op::Div => (Type::Number, Type::Number),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Type {
+    Number,
+    Condition,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Type::Number => "a number",
+            Type::Condition => "a condition",
+        })
+    }
+}
+
+/// A numeric field an expression can refer to.
+#[derive(Debug, PartialEq)]
+enum Field {
+    Bytes,
+    Status,
+    /// In seconds.
+    Duration,
+    /// Seconds since the epoch.
+    Timestamp,
+    /// A format-specific field, e.g. ALB's `target_processing_time`.
+    Extra(String),
+}
+
+/// Entry fields that hold text, which arithmetic can't use.
+const TEXT_FIELDS: &[&str] = &["ip", "hostname", "user", "method", "path", "protocol", "referrer", "user_agent", "severity"];
+
+impl Field {
+    fn parse(name: &str) -> Result<Field, String> {
+        Ok(match name {
+            "bytes" => Field::Bytes,
+            "status" | "status_code" => Field::Status,
+            "duration" => Field::Duration,
+            "timestamp" => Field::Timestamp,
+            _ if TEXT_FIELDS.contains(&name) => return Err(format!("{} is text; arithmetic and comparisons need numbers", name)),
+            _ => Field::Extra(name.to_string()),
+        })
+    }
+
+    /// The field's name in JSON output.
+    fn name(&self) -> &str {
+        match self {
+            Field::Bytes => "bytes",
+            Field::Status => "status_code",
+            Field::Duration => "duration",
+            Field::Timestamp => "timestamp",
+            Field::Extra(name) => name,
+        }
+    }
+
+    fn value(&self, entry: &Entry) -> Option<f64> {
+        match self {
+            Field::Bytes => entry.bytes.map(|bytes| bytes as f64),
+            Field::Status => entry.status_code.map(|status| status.as_u16() as f64),
+            Field::Duration => entry.duration.map(|duration| duration.as_secs_f64()),
+            Field::Timestamp => entry.timestamp.map(|t| t.timestamp_micros() as f64 / 1e6),
+            Field::Extra(name) => entry.extras.get(name).and_then(FieldValue::as_number),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Number(f64),
+    Field(Field),
+    Negate(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+}
+
+impl fmt::Display for Node {
+    /// Every operation in parentheses, so --explain-filter shows how the expression grouped.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Number(n) => write!(f, "{}", n),
+            Node::Field(Field::Extra(name)) => f.write_str(name),
+            Node::Field(field) => write!(f, "{}", format!("{:?}", field).to_lowercase()),
+            Node::Negate(node) => write!(f, "-{}", node),
+            Node::Binary(op, left, right) => write!(f, "({} {} {})", left, op.symbol(), right),
+        }
+    }
+}
+
+impl Node {
+    fn number(&self, entry: &Entry) -> Option<f64> {
+        match self {
+            Node::Number(n) => Some(*n),
+            Node::Field(field) => field.value(entry),
+            Node::Negate(node) => node.number(entry).map(|n| -n),
+            Node::Binary(op, left, right) => {
+                let (left, right) = (left.number(entry)?, right.number(entry)?);
+                match op {
+                    Op::Add => Some(left + right),
+                    Op::Sub => Some(left - right),
+                    Op::Mul => Some(left * right),
+                    Op::Div if right == 0.0 => None,
+                    Op::Div => Some(left / right),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn holds(&self, entry: &Entry) -> bool {
+        let Node::Binary(op, left, right) = self else {
+            return false;
+        };
+        match op {
+            Op::Or => left.holds(entry) || right.holds(entry),
+            Op::And => left.holds(entry) && right.holds(entry),
+            _ => {
+                let (Some(left), Some(right)) = (left.number(entry), right.number(entry)) else {
+                    return false;
+                };
+                match op {
+                    Op::Eq => left == right,
+                    Op::Neq => left != right,
+                    Op::Lt => left < right,
+                    Op::Lte => left <= right,
+                    Op::Gt => left > right,
+                    Op::Gte => left >= right,
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    fn check(&self) -> Result<Type, String> {
+        match self {
+            Node::Number(_) | Node::Field(_) => Ok(Type::Number),
+            Node::Negate(node) => match node.check()? {
+                Type::Number => Ok(Type::Number),
+                found => Err(format!("`-` needs a number, not {} such as `{}`", found, node)),
+            },
+            Node::Binary(op, left, right) => {
+                let (operands, result) = op.types();
+                for side in [left, right] {
+                    let found = side.check()?;
+                    if found != operands {
+                        return Err(format!("`{}` needs {} on each side, not {} such as `{}`", op.symbol(), operands, found, side));
+                    }
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    fn extras<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Node::Field(Field::Extra(name)) => names.push(name),
+            Node::Negate(node) => node.extras(names),
+            Node::Binary(_, left, right) => {
+                left.extras(names);
+                right.extras(names);
+            }
+            _ => {}
+        }
+    }
+
+    fn fields<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Node::Field(field) if !names.contains(&field.name()) => names.push(field.name()),
+            Node::Negate(node) => node.fields(names),
+            Node::Binary(_, left, right) => {
+                left.fields(names);
+                right.fields(names);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Splits an expression into tokens, each with the column it starts at.
+fn tokenize(source: &str) -> Result<Vec<(usize, String)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        let accept: fn(char) -> bool = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            c if c.is_ascii_digit() || c == '.' => |c| c.is_ascii_digit() || c == '.',
+            c if c.is_alphabetic() || c == '_' => |c| c.is_alphanumeric() || c == '_',
+            '<' | '>' | '=' | '!' => |c| matches!(c, '<' | '>' | '=' | '!'),
+            '+' | '-' | '*' | '/' | '(' | ')' => |_| false,
+            _ => return Err(format!("unexpected {:?} at column {}", c, start + 1)),
+        };
+        let mut end = start + c.len_utf8();
+        chars.next();
+        while let Some(&(i, c)) = chars.peek().filter(|(_, c)| accept(*c)) {
+            end = i + c.len_utf8();
+            chars.next();
+        }
+        tokens.push((start + 1, source[start..end].to_string()));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, String)>,
+    next: usize,
+    /// Column just past the end, for errors at the end of the input.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.next).map(|(_, token)| token.as_str())
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |(column, _)| *column)
+    }
+
+    fn expected(&self, what: &str) -> String {
+        match self.peek() {
+            Some(token) => format!("expected {} at column {}, found `{}`", what, self.column(), token),
+            None => format!("expected {} at the end", what),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        let Some(token) = self.peek().map(str::to_string) else {
+            return Err(self.expected("a field, a number or `(`"));
+        };
+        let column = self.column();
+        self.next += 1;
+        match token.as_str() {
+            "(" => {
+                let node = self.binary(0)?;
+                if self.peek() != Some(")") {
+                    return Err(self.expected("`)`"));
+                }
+                self.next += 1;
+                Ok(node)
+            }
+            "-" => Ok(Node::Negate(Box::new(self.primary()?))),
+            _ if token.starts_with(|c: char| c.is_ascii_digit() || c == '.') => {
+                token.parse().map(Node::Number).map_err(|_| format!("invalid number `{}` at column {}", token, column))
+            }
+            _ if token.starts_with(|c: char| c.is_alphabetic() || c == '_') && Op::parse(&token).is_none() => {
+                Field::parse(&token).map(Node::Field).map_err(|e| format!("{} (column {})", e, column))
+            }
+            _ => {
+                self.next -= 1;
+                Err(self.expected("a field, a number or `(`"))
+            }
+        }
+    }
+
+    /// Parses operations binding at least as tightly as `precedence`, left to right.
+    fn binary(&mut self, precedence: u8) -> Result<Node, String> {
+        let mut left = self.primary()?;
+        while let Some(op) = self.peek().and_then(Op::parse).filter(|op| op.precedence() > precedence) {
+            self.next += 1;
+            let right = self.binary(op.precedence())?;
+            left = Node::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+}
+
+/// A parsed `--where` condition.
+#[derive(Debug)]
+pub struct Expression {
+    root: Node,
+}
+
+impl std::str::FromStr for Expression {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { tokens: tokenize(source)?, next: 0, end: source.len() + 1 };
+        let root = parser.binary(0)?;
+        if parser.peek().is_some() {
+            return Err(parser.expected("an operator"));
+        }
+        match root.check()? {
+            Type::Condition => Ok(Expression { root }),
+            Type::Number => Err(format!("`{}` is a number, not a condition; compare it, e.g. `{} > 0`", root, root)),
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rendered = self.root.to_string();
+        // The outermost parentheses say nothing.
+        f.write_str(rendered.strip_prefix('(').and_then(|r| r.strip_suffix(')')).unwrap_or(&rendered))
+    }
+}
+
+impl Expression {
+    pub fn is_match(&self, entry: &Entry) -> bool {
+        self.root.holds(entry)
+    }
+
+    /// Whether the expression reads the format-specific field `name`.
+    pub fn reads(&self, name: &str) -> bool {
+        let mut names = Vec::new();
+        self.root.extras(&mut names);
+        names.contains(&name)
+    }
+
+    /// Every field the expression reads, once each, in the order they appear.
+    pub fn fields(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.root.fields(&mut names);
+        names
+    }
+
+    /// Checks that the format-specific fields exist in `entry`, the first parsed one, and hold
+    /// numbers.
+    pub fn check_fields(&self, entry: &Entry) -> Result<(), String> {
+        let mut names = Vec::new();
+        self.root.extras(&mut names);
+        for name in names {
+            match entry.extras.get(name) {
+                None if extract::defines(name) || classify::derives(name) => {}
+                None => {
+                    return Err(format!(
+                        "--where: unknown field {}; fields in the first parsed line: {}",
+                        name,
+                        entry.extras.keys().cloned().collect::<Vec<_>>().join(", ")
+                    ))
+                }
+                Some(FieldValue::Text(text)) => {
+                    return Err(format!(
+                        "--where: {} is text ({:?}) in the first parsed line; arithmetic and comparisons need numbers",
+                        name, text
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(fields: &[(&str, &str)]) -> Entry {
+        let mut entry = Entry { bytes: Some(1000), ..Entry::default() };
+        for (name, value) in fields {
+            entry.extras.insert(name.to_string(), FieldValue::parse(value).unwrap());
+        }
+        entry
+    }
+
+    fn holds(expression: &str, entry: &Entry) -> bool {
+        expression.parse::<Expression>().unwrap().is_match(entry)
+    }
+
+    #[test]
+    fn every_listed_operator_parses() {
+        for &op in OPERATORS {
+            assert_eq!(Op::parse(op).map(Op::symbol), Some(op));
+        }
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        let empty = Entry::default();
+        assert!(holds("1 + 2 * 3 == 7", &empty));
+        assert!(holds("(1 + 2) * 3 == 9", &empty));
+        assert!(holds("10 - 4 - 3 == 3", &empty));
+        assert!(holds("8 / 4 / 2 == 1", &empty));
+        assert!(holds("-2 * -3 == 6", &empty));
+        assert!(holds("1 > 2 or 2 > 1 and 3 > 2", &empty));
+        assert!(!holds("(1 > 2 or 2 > 1) and 3 < 2", &empty));
+        assert_eq!(
+            "a / (b + c) > 0.8 and bytes >= 1".parse::<Expression>().unwrap().to_string(),
+            "((a / (b + c)) > 0.8) and (bytes >= 1)"
+        );
+    }
+
+    #[test]
+    fn fields_are_listed_once_by_their_json_names() {
+        let expression = "status >= 500 and tr / (bytes + tr) > 0.5 or -status_code < 0".parse::<Expression>().unwrap();
+        assert_eq!(expression.fields(), ["status_code", "tr", "bytes"]);
+    }
+
+    #[test]
+    fn integer_and_float_fields_mix() {
+        let alb = entry(&[("request", "1"), ("target", "8.5"), ("response", "0.5")]);
+        assert!(holds("target / (request + target + response) > 0.8", &alb));
+        assert!(holds("target / (request + target + response) == 0.85", &alb));
+        assert!(holds("bytes / 1000 + request == 2", &alb));
+        assert!(!holds("target / (request + target + response) > 0.9", &alb));
+    }
+
+    #[test]
+    fn missing_values_and_division_by_zero_never_match() {
+        let zero = entry(&[("a", "1"), ("b", "0"), ("text", "n/a")]);
+        assert!(!holds("a / b > 0", &zero));
+        assert!(!holds("a / b <= 0", &zero));
+        assert!(holds("a / b > 0 or a == 1", &zero));
+        assert!(!holds("missing + 1 > 0", &zero));
+        assert!(!holds("text != 0", &zero));
+        assert!(!holds("duration >= 0", &zero));
+    }
+
+    #[test]
+    fn non_numeric_operands_are_explained() {
+        let error = |source: &str| source.parse::<Expression>().unwrap_err();
+        assert_eq!(error("path + 1 > 0"), "path is text; arithmetic and comparisons need numbers (column 1)");
+        assert_eq!(error("bytes * 2"), "`(bytes * 2)` is a number, not a condition; compare it, e.g. `(bytes * 2) > 0`");
+        assert_eq!(error("(a > 1) + 2 > 0"), "`+` needs a number on each side, not a condition such as `(a > 1)`");
+        assert_eq!(error("a > 1 and 2"), "`and` needs a condition on each side, not a number such as `2`");
+        assert_eq!(error("a / (b + c > 1"), "expected `)` at the end");
+        assert_eq!(error("a > > 1"), "expected a field, a number or `(` at column 5, found `>`");
+        assert_eq!(error("a > 1 $"), "unexpected '$' at column 7");
+
+        let text = entry(&[("a", "1"), ("target", "10.0.0.1:80")]);
+        let check = |source: &str| source.parse::<Expression>().unwrap().check_fields(&text).unwrap_err();
+        assert!(check("target > 1").contains("target is text (\"10.0.0.1:80\")"));
+        assert!(check("a + nope > 1").contains("unknown field nope; fields in the first parsed line: a, target"));
+    }
+}
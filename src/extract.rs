@@ -0,0 +1,146 @@
+use crate::entry::{Entry, FieldValue};
+use crate::registry;
+use regex::Regex;
+use std::{str::FromStr, sync::OnceLock};
+
+// `--extract FIELD:REGEX` pulls values out of a field with the regex's named capture groups, e.g.
+// `path:^/api/v(?P<version>\d+)/` makes `version` a field like the ones a log format brings,
+// there for --field, --where, --fields, templates, JSON output and `--by version`. The regex
+// is compiled once, when the options are read, and run only for extractions whose fields the
+// command reads; where it doesn't match, the fields are absent.
+
+/// One `--extract` option.
+#[derive(Clone, Debug)]
+pub struct Extraction {
+    field: String,
+    regex: Regex,
+    names: Vec<String>,
+}
+
+impl FromStr for Extraction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, pattern) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected FIELD:REGEX, e.g. 'path:^/api/v(?P<version>\\d+)/', not {:?}", s))?;
+        let regex = Regex::new(pattern).map_err(|e| format!("{:?} isn't a valid regex: {}", pattern, e))?;
+        let names: Vec<String> = regex.capture_names().flatten().map(str::to_string).collect();
+        if names.is_empty() {
+            return Err(format!("{:?} has no named group to take a field name from; write one as (?P<name>...)", pattern));
+        }
+        // Fields only a few log formats bring are fair game, the way a format's own names are.
+        let hidden = names.iter().find_map(|name| Some((name, registry::find(name).filter(|field| !field.is_format())?)));
+        if let Some((name, field)) = hidden {
+            return Err(format!("(?P<{}>...) would hide the {} field; pick another name", name, field.name));
+        }
+        Ok(Extraction { field: registry::canonical(field), regex, names })
+    }
+}
+
+impl Extraction {
+    /// The text of the field the regex runs on: any the registry knows, the client's address or
+    /// hostname, or else a format-specific one.
+    fn source(&self, entry: &Entry) -> Option<String> {
+        match self.field.as_str() {
+            "client" => entry.client.as_ref().map(ToString::to_string),
+            name => registry::value(entry, name).map(|value| value.as_text()),
+        }
+    }
+}
+
+/// The extractions a command runs, in the order given.
+#[derive(Clone, Debug, Default)]
+pub struct Extractions(Vec<Extraction>);
+
+impl Extractions {
+    /// Fails when two extractions define the same field.
+    pub fn new(extractions: Vec<Extraction>) -> Result<Self, String> {
+        let mut names: Vec<&str> = Vec::new();
+        for name in extractions.iter().flat_map(|extraction| &extraction.names) {
+            if names.contains(&name.as_str()) {
+                return Err(format!("--extract: {} is defined twice", name));
+            }
+            names.push(name);
+        }
+        Ok(Extractions(extractions))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().flat_map(|extraction| extraction.names.iter().map(String::as_str))
+    }
+
+    /// Drops the extractions none of whose fields `reads`.
+    pub fn used(mut self, reads: impl Fn(&str) -> bool) -> Self {
+        self.0.retain(|extraction| extraction.names.iter().any(|name| reads(name)));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Adds the fields the regexes capture; a number is kept as one, so it compares as one.
+    pub fn apply(&self, entry: &mut Entry) {
+        for extraction in &self.0 {
+            let Some(source) = extraction.source(entry) else {
+                continue;
+            };
+            let Some(captures) = extraction.regex.captures(&source) else {
+                continue;
+            };
+            for name in &extraction.names {
+                if let Some(value) = captures.name(name).and_then(|value| FieldValue::parse(value.as_str())) {
+                    entry.extras.insert(name.clone(), value);
+                }
+            }
+        }
+    }
+}
+
+static DEFINED: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Records the fields --extract defines for the rest of the run; only the first call has an effect.
+pub fn set_defined(extractions: &Extractions) {
+    let _ = DEFINED.set(extractions.names().map(str::to_string).collect());
+}
+
+/// Whether --extract defines `name`. Such a field can be missing from the first entry, where
+/// a field the format brings can't, so checks against the first entry let it pass.
+pub fn defines(name: &str) -> bool {
+    DEFINED.get().is_some_and(|names| names.iter().any(|defined| defined == name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_groups_become_fields_where_the_regex_matches() {
+        let extractions = Extractions::new(vec![
+            r"path:^/api/v(?P<version>\d+)/(?P<resource>[a-z]+)".parse().unwrap(),
+            "user_agent:^(?P<agent>[^/]+)/".parse().unwrap(),
+        ])
+        .unwrap();
+        let mut entry = Entry { path: Some("/api/v2/orders/7".to_string()), ..Entry::default() };
+        extractions.apply(&mut entry);
+        assert_eq!(entry.extras.get("version"), Some(&FieldValue::Integer(2)));
+        assert_eq!(entry.extras.get("resource"), Some(&FieldValue::Text("orders".to_string())));
+        assert_eq!(entry.extras.get("agent"), None);
+
+        let mut entry = Entry { path: Some("/health".to_string()), ..Entry::default() };
+        extractions.apply(&mut entry);
+        assert!(entry.extras.is_empty());
+    }
+
+    #[test]
+    fn extractions_need_a_named_group_of_a_new_name() {
+        let error = |spec: &str| spec.parse::<Extraction>().unwrap_err();
+        assert_eq!(error("^/api/"), r#"expected FIELD:REGEX, e.g. 'path:^/api/v(?P<version>\d+)/', not "^/api/""#);
+        assert_eq!(error(r"path:^/api/v(\d+)/"), r#""^/api/v(\\d+)/" has no named group to take a field name from; write one as (?P<name>...)"#);
+        assert_eq!(error("path:(?P<path>.*)"), "(?P<path>...) would hide the path field; pick another name");
+        assert!(error("path:(?P<v>[").starts_with(r#""(?P<v>[" isn't a valid regex: "#));
+        let twice = Extractions::new(vec!["path:(?P<v>.)".parse().unwrap(), "user:(?P<v>.)".parse().unwrap()]);
+        assert_eq!(twice.unwrap_err(), "--extract: v is defined twice");
+    }
+}
@@ -0,0 +1,195 @@
+use crate::diagnostics::RunSummary;
+use std::fmt;
+use std::str::FromStr;
+
+// The exit statuses of a filter run, which scripts gate on: 0 when something matched,
+// NO_MATCHES when nothing did, ERROR when the run failed, and FAILED when it finished but one of
+// the --fail-on conditions held. check exits with FAILED too when a rule fails. Ctrl-C exits
+// with interrupt::EXIT_CODE.
+
+/// Exit status when a filter run finished without a match.
+pub const NO_MATCHES: u8 = 1;
+/// Exit status when the run failed, the same clap uses for invalid arguments.
+pub const ERROR: u8 = 2;
+/// Exit status when a --fail-on condition held, or a check rule failed.
+pub const FAILED: u8 = 3;
+
+const FORMS: &str = "expected matches, malformed or match-rate, optionally compared to a number with >, >=, <, <= or =, \
+                     e.g. matches, 'matches>100' or 'match-rate>0.05'";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Counter {
+    Matches,
+    Malformed,
+    /// Matches over the lines that parsed.
+    MatchRate,
+}
+
+impl Counter {
+    pub fn name(self) -> &'static str {
+        match self {
+            Counter::Matches => "matches",
+            Counter::Malformed => "malformed",
+            Counter::MatchRate => "match-rate",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Eq,
+}
+
+impl Comparison {
+    const SYMBOLS: [(&'static str, Comparison); 5] =
+        [(">=", Comparison::Gte), ("<=", Comparison::Lte), (">", Comparison::Gt), ("<", Comparison::Lt), ("=", Comparison::Eq)];
+
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Gt => value > threshold,
+            Comparison::Gte => value >= threshold,
+            Comparison::Lt => value < threshold,
+            Comparison::Lte => value <= threshold,
+            Comparison::Eq => value == threshold,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        Comparison::SYMBOLS.iter().find(|(_, comparison)| *comparison == self).map_or("", |(symbol, _)| symbol)
+    }
+}
+
+/// A --fail-on condition such as `matches>100`; a counter on its own means above 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Condition {
+    pub counter: Counter,
+    pub comparison: Comparison,
+    pub threshold: f64,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid condition {:?}: {}", s, FORMS);
+        let at = s.find(['<', '>', '=']).unwrap_or(s.len());
+        let (name, rest) = s.split_at(at);
+        let counter = match name.trim() {
+            "matches" => Counter::Matches,
+            "malformed" => Counter::Malformed,
+            "match-rate" => Counter::MatchRate,
+            _ => return Err(invalid()),
+        };
+        if rest.is_empty() {
+            return Ok(Condition { counter, comparison: Comparison::Gt, threshold: 0.0 });
+        }
+        let (symbol, comparison) =
+            Comparison::SYMBOLS.into_iter().find(|(symbol, _)| rest.starts_with(symbol)).ok_or_else(invalid)?;
+        let number = rest[symbol.len()..].trim();
+        let threshold: f64 = number.parse().ok().filter(|n: &f64| n.is_finite() && *n >= 0.0).ok_or_else(invalid)?;
+        match counter {
+            Counter::MatchRate if threshold > 1.0 => {
+                Err(format!("invalid condition {:?}: a match rate is a fraction between 0 and 1", s))
+            }
+            Counter::Matches | Counter::Malformed if threshold.fract() != 0.0 => {
+                Err(format!("invalid condition {:?}: {} is a count of lines", s, counter.name()))
+            }
+            _ => Ok(Condition { counter, comparison, threshold }),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.counter.name(), self.comparison.symbol(), self.threshold)
+    }
+}
+
+/// The counters of a whole run, over every file, that the conditions are checked against.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tally {
+    pub lines: usize,
+    pub matched: usize,
+    pub malformed: usize,
+}
+
+impl Tally {
+    pub fn add(&mut self, summary: &RunSummary) {
+        self.lines += summary.lines;
+        self.matched += summary.matched;
+        self.malformed += summary.malformed;
+    }
+
+    /// Matches over the lines that parsed, 0 when none did.
+    pub fn match_rate(&self) -> f64 {
+        match self.lines.saturating_sub(self.malformed) {
+            0 => 0.0,
+            parsed => self.matched as f64 / parsed as f64,
+        }
+    }
+
+    pub fn value(&self, counter: Counter) -> f64 {
+        match counter {
+            Counter::Matches => self.matched as f64,
+            Counter::Malformed => self.malformed as f64,
+            Counter::MatchRate => self.match_rate(),
+        }
+    }
+
+    /// The first of `conditions` that holds, with the value that met it.
+    pub fn failed<'a>(&self, conditions: &'a [Condition]) -> Option<(&'a Condition, f64)> {
+        conditions.iter().map(|condition| (condition, self.value(condition.counter))).find(|(condition, value)| {
+            condition.comparison.holds(*value, condition.threshold)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(s: &str) -> Condition {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn conditions_name_a_counter_and_an_optional_threshold() {
+        assert_eq!(condition("matches"), Condition { counter: Counter::Matches, comparison: Comparison::Gt, threshold: 0.0 });
+        assert_eq!(condition("matches>100"), Condition { counter: Counter::Matches, comparison: Comparison::Gt, threshold: 100.0 });
+        assert_eq!(condition("malformed >= 3"), Condition { counter: Counter::Malformed, comparison: Comparison::Gte, threshold: 3.0 });
+        assert_eq!(
+            condition("match-rate>0.05"),
+            Condition { counter: Counter::MatchRate, comparison: Comparison::Gt, threshold: 0.05 }
+        );
+        assert_eq!(condition("matches<=2").comparison, Comparison::Lte);
+        assert_eq!(condition("matches<2").comparison, Comparison::Lt);
+        assert_eq!(condition("matches=0").comparison, Comparison::Eq);
+        assert_eq!(condition("match-rate>0.05").to_string(), "match-rate>0.05");
+        assert_eq!(condition("malformed").to_string(), "malformed>0");
+    }
+
+    #[test]
+    fn malformed_conditions_are_rejected() {
+        for s in ["", "lines>3", "matches>", "matches>>3", "matches>-1", "matches>1.5", "match-rate>5", "matches>NaN", "matches 3"] {
+            assert!(s.parse::<Condition>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn conditions_are_checked_against_the_run_counters() {
+        let tally = Tally { lines: 10, matched: 2, malformed: 2 };
+        assert_eq!(tally.match_rate(), 0.25);
+        assert_eq!(Tally::default().match_rate(), 0.0);
+        let conditions = [condition("matches>2"), condition("malformed"), condition("match-rate>0.2")];
+        let (failed, value) = tally.failed(&conditions).unwrap();
+        assert_eq!((failed.to_string(), value), ("malformed>0".to_string(), 2.0));
+        assert_eq!(tally.failed(&conditions[..1]), None);
+        assert_eq!(tally.failed(&[condition("match-rate>0.25")]), None);
+        assert!(tally.failed(&[condition("matches>=2")]).is_some());
+        assert!(tally.failed(&[]).is_none());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::diagnostics::Error;
+use crate::filter::FILTER_KINDS;
+use crate::input::{self, Source};
+use crate::output::{schema, table::Table};
+use crate::parsers::LineParser;
+use crate::registry::{self, Access, Field as Registered};
+use crate::stats::StatsOutput;
+use serde_json::{json, Value};
+use std::io::BufRead;
+
+// `fields` lists the fields entries of the selected log type can have and the option that
+// filters on each; `--list-operators` lists the kinds of filter option and the operators each
+// takes. The fields come from the parser: built-in log types declare theirs, custom formats
+// name theirs in the format string, and W3C logs in the input's `#Fields:` header. Fields the
+// first lines of the input turn up besides, such as nginx error log context, follow those.
+
+/// Lines read from the start of the input for fields the log type doesn't declare.
+pub const SAMPLE_LINES: usize = 100;
+
+/// The option that filters on a field and the kind of filter it is: its own for the fields
+/// the registry gives one, none for where a line was read, else `--field`.
+pub fn describe(field: &str) -> schema::Field {
+    let (filter, kind) = match registry::find(field) {
+        Some(Registered { option: Some((option, kind)), .. }) => (Some(option.to_string()), Some(*kind)),
+        Some(Registered { access: Access::Provenance, .. }) => (None, None),
+        _ => (Some(format!("--field {}", field)), Some("field")),
+    };
+    schema::Field { field: field.to_string(), filter, kind }
+}
+
+/// The fields of the entries parsed from the first `SAMPLE_LINES` lines of `source`, in the
+/// order `parser` declares them, `None` if it doesn't, and then the rest by name. Lines that
+/// fail to parse are passed over.
+pub fn sample(source: &Source, parser: &mut LineParser) -> Result<Vec<String>, Error> {
+    let io_error = |e: std::io::Error| Error::Io { file: source.path().to_path_buf(), message: e.to_string() };
+    let mut found = Vec::new();
+    for line in source.open().map_err(io_error)?.lines().take(SAMPLE_LINES) {
+        let mut line = match line {
+            Ok(line) => line,
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => continue,
+            Err(e) => return Err(io_error(e)),
+        };
+        input::trim_line_ending(&mut line);
+        let Ok(Some(entry)) = parser.parse(&line) else {
+            continue;
+        };
+        if let Ok(Value::Object(values)) = serde_json::to_value(&entry) {
+            found.extend(values.into_iter().filter(|(_, value)| !value.is_null()).map(|(name, _)| name));
+        }
+    }
+    let mut fields = parser.fields().unwrap_or_default();
+    found.sort();
+    found.dedup();
+    found.retain(|name| !fields.contains(name));
+    fields.extend(found);
+    Ok(fields)
+}
+
+fn text(value: Option<&str>) -> String {
+    value.unwrap_or("-").to_string()
+}
+
+/// A row, or with JSON a record, per field: its name, the option that filters on it and the
+/// kind of filter that is.
+pub fn render(fields: &[String], output: StatsOutput) -> String {
+    let mut out = String::new();
+    match output {
+        StatsOutput::Json | StatsOutput::JsonArray => {
+            for field in fields {
+                out += &format!("{}\n", json!(describe(field)));
+            }
+        }
+        StatsOutput::Table => {
+            let mut table = Table::new(&["field", "filter", "kind"]);
+            for field in fields.iter().map(|field| describe(field)) {
+                table.row(vec![field.field, text(field.filter.as_deref()), text(field.kind)]);
+            }
+            out += &table.render();
+        }
+    }
+    output.document(out)
+}
+
+/// A row, or with JSON a record, per kind of filter option.
+pub fn render_operators(output: StatsOutput) -> String {
+    let mut out = String::new();
+    match output {
+        StatsOutput::Json | StatsOutput::JsonArray => {
+            for kind in FILTER_KINDS {
+                out += &format!("{}\n", json!(kind));
+            }
+        }
+        StatsOutput::Table => {
+            let mut table = Table::new(&["kind", "options", "operators", "example"]);
+            for kind in FILTER_KINDS {
+                table.row(vec![
+                    kind.kind.to_string(),
+                    kind.options.join(" "),
+                    kind.operators.join(" "),
+                    kind.example.to_string(),
+                ]);
+            }
+            out += &table.render();
+        }
+    }
+    output.document(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_name_the_option_that_filters_them() {
+        let described: Vec<_> = ["ip", "user", "request_time", "line_no"].iter().map(|field| describe(field)).collect();
+        assert_eq!(described[0].filter.as_deref(), Some("--ip"));
+        assert_eq!(described[1].filter.as_deref(), Some("--field user"));
+        assert_eq!(described[2].filter.as_deref(), Some("--field request_time"));
+        assert_eq!((described[3].filter.as_deref(), described[3].kind), (None, None));
+        for field in registry::FIELDS {
+            let Some((option, kind)) = field.option else {
+                continue;
+            };
+            let listed = FILTER_KINDS.iter().any(|listed| listed.kind == kind && listed.options.contains(&option));
+            assert!(listed, "{} is filtered with {}, which isn't listed as {}", field.name, option, kind);
+        }
+    }
+}
@@ -0,0 +1,2012 @@
+use crate::after_status::AfterStatus;
+use crate::classify::Class;
+use crate::diagnostics::{Error, Notice};
+use crate::duration::HumanDuration;
+use crate::entry::{Client, Entry, FieldValue, Severity};
+use crate::expression::Expression;
+use crate::extract;
+use crate::filter_file::Conditions;
+use crate::fuzzy::Fuzzy;
+use crate::gap;
+use crate::ip::{IpRange, IpSet};
+use crate::log_time::LogTime;
+use crate::output::schema::FilterKind;
+use crate::referrer;
+use crate::registry;
+use crate::size::{format_size, ByteSize};
+use crate::status::Status;
+use crate::suspicious;
+use crate::tls::TlsVersion;
+use aho_corasick::AhoCorasick;
+use chrono::{DateTime, FixedOffset, SecondsFormat, TimeDelta, Utc};
+use clap::{Args, ValueEnum};
+use http::StatusCode;
+use rs_filter::{Filterable, OrdFilter, StringFilter};
+use serde::{Serialize, Serializer};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fmt::Display,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{PoisonError, RwLock, RwLockReadGuard},
+};
+
+#[derive(Args, Debug, Default)]
+pub struct FilterArgs {
+    /// Print the effective filter and exit without reading the input
+    #[arg(long)]
+    pub explain_filter: bool,
+
+    /// Pass every parsed entry through, e.g. to convert with --output
+    #[arg(long, conflicts_with_all = FILTER_FLAGS)]
+    pub all: bool,
+
+    /// Status code by number or name, e.g. `eq 404`, `eq not_found` or
+    /// `gte internal_server_error`
+    #[arg(short, long, num_args = 1..=2)]
+    status_code: Option<Vec<String>>,
+    
+    /// User agent, e.g. `contains bot`, `in @agents.txt`, `not_contains_any @blocklist.txt` or
+    /// `fuzzy Mozilla`, within an edit
+    #[arg(short, long, num_args = 1..=2)]
+    user_agent: Option<Vec<String>>,
+
+    /// Referrer, e.g. `none` for entries without one
+    #[arg(short, long, num_args = 1..=2)]
+    referrer: Option<Vec<String>>,
+    
+    /// The request line of entries whose request isn't `METHOD PATH PROTOCOL`, kept whole as
+    /// `raw_request`, e.g. `contains \x16\x03` for TLS handshakes sent to a plain HTTP port
+    #[arg(long, num_args = 1..=2)]
+    raw_request: Option<Vec<String>>,
+
+    /// Keep only entries whose request line isn't `METHOD PATH PROTOCOL`; the same as
+    /// `--raw-request some`
+    #[arg(long, conflicts_with_all = ["raw_request", "all"])]
+    only_malformed_requests: bool,
+
+    /// Keep only entries whose path as logged looks like a traversal or encoding attack: a `..`
+    /// segment, plain, percent-encoded or double-encoded, a null byte, an overlong UTF-8
+    /// encoding or bytes outside ASCII. The `suspicious` field lists the reasons
+    #[arg(long, conflicts_with = "all")]
+    suspicious_paths: bool,
+
+    /// Client address, CIDR block or comma-separated list, e.g. `in 10.0.0.0/8,2001:db8::/32`
+    /// or `in @bad_ips.txt` for one per line
+    #[arg(short, long, num_args = 1..=2)]
+    ip: Option<Vec<String>>,
+
+    /// Client as logged, address or hostname, e.g. `eq crawler.example.com` or `ends_with
+    /// .example.com` for logs written with Apache's `HostnameLookups On`
+    #[arg(long, num_args = 1..=2)]
+    client: Option<Vec<String>>,
+
+    /// Request path, e.g. `starts_with /api/`, `in @paths.txt` or `fuzzy wp-admin:2`, within 2 edits
+    #[arg(short, long, num_args = 1..=2)]
+    path: Option<Vec<String>>,
+
+    /// Log time, e.g. `gte 2023-02-12T14:00:00Z` or `lt 12/Feb/2023:15:00:00 +0000`; an Apache
+    /// time without an offset is UTC, and without a time of day midnight
+    #[arg(short, long, num_args = 1..=2)]
+    timestamp: Option<Vec<String>>,
+
+    /// Request duration for formats that record one, e.g. `gt 250ms` or `lte 1.5s`
+    #[arg(short, long, num_args = 1..=2)]
+    duration: Option<Vec<String>>,
+
+    /// Error log severity, e.g. `gte error`
+    #[arg(long, num_args = 1..=2)]
+    severity: Option<Vec<String>>,
+
+    /// Response size, e.g. `gt 1.5M`, `lte 512k` or `eq 1,048,576`. k, M and G alone or as KiB,
+    /// MiB and GiB are powers of 1024; kB, MB and GB are powers of 1000
+    #[arg(long, num_args = 1..=2)]
+    bytes: Option<Vec<String>>,
+
+    /// Operator for a text filter given only a value, e.g. `-u Googlebot`. Prefix the value with
+    /// `literal:` when it starts with an operator name
+    #[arg(long, value_enum, default_value_t)]
+    implicit_operator: ImplicitOperator,
+
+    /// HAProxy backend name
+    #[arg(long, num_args = 1..=2)]
+    backend: Option<Vec<String>>,
+
+    /// HAProxy server response time (Tr) in milliseconds
+    #[arg(long, num_args = 1..=2)]
+    response_time: Option<Vec<String>>,
+
+    /// HAProxy session termination state, e.g. `contains sH`
+    #[arg(long, num_args = 1..=2)]
+    termination_state: Option<Vec<String>>,
+
+    /// Hostname from the syslog prefix, requires --strip-syslog
+    #[arg(long, num_args = 1..=2)]
+    host: Option<Vec<String>>,
+
+    /// Scheme the request came in with, e.g. `eq https`, for formats that log one such as ALB
+    /// or nginx's `$scheme`
+    #[arg(long, num_args = 1..=2)]
+    scheme: Option<Vec<String>>,
+
+    /// Port the request came in on, e.g. `eq 80`, for formats that log one such as ALB or
+    /// Apache's `%p`
+    #[arg(long, num_args = 1..=2)]
+    port: Option<Vec<String>>,
+
+    /// TLS protocol version, e.g. `lt TLSv1.2` for clients on deprecated versions or `none` for
+    /// plain HTTP. Versions order SSLv3, TLSv1, TLSv1.1, TLSv1.2, TLSv1.3
+    #[arg(long, num_args = 1..=2)]
+    tls_protocol: Option<Vec<String>>,
+
+    /// Static requests for assets, by extension or path prefix, or dynamic ones for the rest
+    #[arg(long, value_enum)]
+    class: Option<Class>,
+
+    /// Where the referrer points: the site's own pages (see --site-host), a search engine, a
+    /// social network, anywhere else, or none at all
+    #[arg(long, value_enum)]
+    referrer_class: Option<referrer::Class>,
+
+    /// Filter on any field by name, e.g. `--field request_time gt 1.5`, `--field upstream_addr contains 10.0.` or
+    /// `--field ua contains curl`
+    #[arg(long, num_args = 3, value_names = ["NAME", "OP", "VALUE"])]
+    field: Vec<String>,
+
+    /// Keep entries for which this holds, e.g. `--where 'target_processing_time /
+    /// (request_processing_time + target_processing_time) > 0.8'`. Numeric fields and numbers
+    /// with `+ - * /` and parentheses, compared with `== != < <= > >=` and joined with `and`
+    /// and `or`; an entry missing a field, or dividing by zero, doesn't match. Repeat to require
+    /// several
+    #[arg(long, value_name = "EXPRESSION")]
+    r#where: Vec<String>,
+
+    /// Keep entries for which every `--where` expression in this file holds, one per line;
+    /// blank lines and lines starting with `#` are skipped. See --reload-on-change
+    #[arg(long, value_name = "FILE", conflicts_with = "all")]
+    pub filter_file: Option<PathBuf>,
+
+    /// Keep only entries whose client address got one of these comma-separated statuses earlier
+    /// in the log, at most --within before, e.g. `401,403` or `unauthorized,forbidden`. Assumes
+    /// the input is in time order
+    #[arg(long, value_name = "STATUSES", value_delimiter = ',', requires = "within", conflicts_with = "all")]
+    after_status: Vec<Status>,
+
+    /// How far back --after-status looks, e.g. `10m`
+    #[arg(long, requires = "after_status", conflicts_with = "all")]
+    within: Option<HumanDuration>,
+
+    /// Drop entries matching all of these comma-separated conditions, e.g.
+    /// `--exclude "path starts_with /static, status_code eq 200"`. Repeat to drop entries
+    /// matching any group.
+    #[arg(long, value_name = "CONDITIONS")]
+    exclude: Vec<String>,
+}
+
+/// How `--user-agent`, `--referrer` and `--path` match a lone value that isn't an operator.
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum ImplicitOperator {
+    #[default]
+    Contains,
+    Eq,
+}
+
+impl ImplicitOperator {
+    fn name(self) -> &'static str {
+        match self {
+            ImplicitOperator::Contains => "contains",
+            ImplicitOperator::Eq => "eq",
+        }
+    }
+}
+
+const FILTER_FLAGS: [&str; 22] = [
+    "status_code",
+    "user_agent",
+    "referrer",
+    "raw_request",
+    "ip",
+    "client",
+    "path",
+    "timestamp",
+    "duration",
+    "severity",
+    "bytes",
+    "backend",
+    "response_time",
+    "termination_state",
+    "host",
+    "scheme",
+    "port",
+    "tls_protocol",
+    "class",
+    "referrer_class",
+    "field",
+    "where",
+];
+
+impl FilterArgs {
+    /// Every filter option, by field name, and whether it was given.
+    fn filters(&self) -> [(&'static str, bool); 27] {
+        [
+            ("status_code", self.status_code.is_some()),
+            ("user_agent", self.user_agent.is_some()),
+            ("referrer", self.referrer.is_some()),
+            ("raw_request", self.raw_request.is_some()),
+            ("ip", self.ip.is_some()),
+            ("client", self.client.is_some()),
+            ("path", self.path.is_some()),
+            ("timestamp", self.timestamp.is_some()),
+            ("duration", self.duration.is_some()),
+            ("severity", self.severity.is_some()),
+            ("bytes", self.bytes.is_some()),
+            ("backend", self.backend.is_some()),
+            ("response_time", self.response_time.is_some()),
+            ("termination_state", self.termination_state.is_some()),
+            ("host", self.host.is_some()),
+            ("scheme", self.scheme.is_some()),
+            ("port", self.port.is_some()),
+            ("tls_protocol", self.tls_protocol.is_some()),
+            ("class", self.class.is_some()),
+            ("referrer_class", self.referrer_class.is_some()),
+            ("field", !self.field.is_empty()),
+            ("where", !self.r#where.is_empty()),
+            ("only_malformed_requests", self.only_malformed_requests),
+            ("suspicious_paths", self.suspicious_paths),
+            ("filter_file", self.filter_file.is_some()),
+            ("exclude", !self.exclude.is_empty()),
+            ("after_status", !self.after_status.is_empty()),
+        ]
+    }
+
+    fn has_filters(&self) -> bool {
+        self.filters().iter().any(|&(_, given)| given)
+    }
+
+    /// Parses an `--exclude` group into the options it stands for.
+    fn from_conditions(group: &str) -> Result<FilterArgs, String> {
+        let mut args = FilterArgs::default();
+        for condition in group.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            let mut parts = condition.splitn(3, char::is_whitespace).map(str::trim);
+            let (Some(field), Some(op)) = (parts.next(), parts.next()) else {
+                return Err(format!("expected `field op value`, got {:?}", condition));
+            };
+            let value = parts.next().filter(|v| !v.is_empty());
+            let field = registry::canonical(&field.replace('-', "_"));
+            let slot = match field.as_str() {
+                "status_code" => &mut args.status_code,
+                "user_agent" => &mut args.user_agent,
+                "referrer" => &mut args.referrer,
+                "raw_request" => &mut args.raw_request,
+                "ip" => &mut args.ip,
+                "client" => &mut args.client,
+                "path" => &mut args.path,
+                "timestamp" => &mut args.timestamp,
+                "duration" => &mut args.duration,
+                "severity" => &mut args.severity,
+                "bytes" => &mut args.bytes,
+                "backend" => &mut args.backend,
+                "response_time" => &mut args.response_time,
+                "termination_state" => &mut args.termination_state,
+                "host" => &mut args.host,
+                "scheme" => &mut args.scheme,
+                "port" => &mut args.port,
+                "tls_protocol" => &mut args.tls_protocol,
+                _ => {
+                    args.field.extend([field, op.to_string(), value.unwrap_or_default().to_string()]);
+                    continue;
+                }
+            };
+            if slot.is_some() {
+                return Err(format!("{} appears twice in {:?}", field, group));
+            }
+            *slot = Some(std::iter::once(op).chain(value).map(str::to_string).collect());
+        }
+        Ok(args)
+    }
+
+    /// Errors unless at least one filter or `--all` was given.
+    pub fn require_filters(&self) -> Result<(), String> {
+        if self.all || self.has_filters() {
+            return Ok(());
+        }
+        let flags: Vec<String> = self.filters().iter().map(|(f, _)| format!("--{}", f.replace('_', "-"))).collect();
+        Err(format!(
+            "no filters specified; use --all to pass all entries (available filters: {})",
+            flags.join(", ")
+        ))
+    }
+}
+
+const ORD_OPERATORS: &[&str] = &["eq", "neq", "gt", "lt", "gte", "lte"];
+const IP_OPERATORS: &[&str] = &["eq", "neq", "in"];
+const STRING_OPERATORS: &[&str] = &["eq", "contains", "starts_with", "ends_with"];
+const TEXT_OPERATORS: &[&str] =
+    &["eq", "contains", "starts_with", "ends_with", "in", "contains_any", "not_contains_any", "fuzzy", "fuzzy_eq"];
+/// Operators whose value may be `@file`, read one value per line.
+const LIST_OPERATORS: &[&str] = &["in", "contains_any", "not_contains_any"];
+const FIELD_OPERATORS: &[&str] = &["eq", "neq", "gt", "lt", "gte", "lte", "contains", "starts_with", "ends_with"];
+const PRESENCE_OPERATORS: &[&str] = &["none", "some"];
+
+/// The filter options by the kind of filter `LogFilter::build` parses them into, for
+/// `--list-operators`.
+pub const FILTER_KINDS: &[FilterKind] = &[
+    FilterKind {
+        kind: "text",
+        options: &["--user-agent", "--referrer", "--path", "--raw-request"],
+        operators: TEXT_OPERATORS,
+        example: "--path starts_with /api/",
+    },
+    FilterKind {
+        kind: "string",
+        options: &["--backend", "--termination-state", "--host", "--scheme", "--client"],
+        operators: STRING_OPERATORS,
+        example: "--backend eq static",
+    },
+    FilterKind {
+        kind: "ord",
+        options: &[
+            "--status-code", "--timestamp", "--duration", "--severity", "--bytes", "--response-time", "--port",
+            "--tls-protocol",
+        ],
+        operators: ORD_OPERATORS,
+        example: "--duration gt 250ms",
+    },
+    FilterKind { kind: "ip", options: &["--ip"], operators: IP_OPERATORS, example: "--ip in 10.0.0.0/8" },
+    FilterKind { kind: "choice", options: &["--class", "--referrer-class"], operators: &[], example: "--class static" },
+    FilterKind { kind: "flag", options: &["--suspicious-paths"], operators: &[], example: "--suspicious-paths" },
+    FilterKind { kind: "field", options: &["--field"], operators: FIELD_OPERATORS, example: "--field request_time gt 1.5" },
+    FilterKind {
+        kind: "presence",
+        options: &[
+            "--user-agent", "--referrer", "--path", "--duration", "--severity", "--bytes", "--backend", "--response-time",
+            "--termination-state", "--host", "--scheme", "--port", "--tls-protocol", "--client", "--raw-request",
+            "--field",
+        ],
+        operators: PRESENCE_OPERATORS,
+        example: "--referrer none",
+    },
+    FilterKind {
+        kind: "expression",
+        options: &["--where"],
+        operators: crate::expression::OPERATORS,
+        example: "--where 'bytes / 1024 > 512'",
+    },
+];
+
+#[derive(Debug)]
+enum Problem {
+    UnknownOperator,
+    MissingValue,
+    InvalidValue { value: String, cause: String },
+    AlwaysPresent,
+    /// A lone value that reads as an operator as well as text to match with `implicit`.
+    Ambiguous { as_operator: String, implicit: ImplicitOperator },
+}
+
+/// A filter option that failed to parse, rendered once the option it came from is known.
+#[derive(Debug)]
+pub struct FilterError {
+    op: String,
+    allowed: &'static [&'static str],
+    /// Whether the field can be absent, which adds the `none`/`some` operators.
+    optional: bool,
+    problem: Problem,
+}
+
+impl FilterError {
+    fn new(op: &str, allowed: &'static [&'static str], problem: Problem) -> FilterError {
+        let problem = match problem {
+            Problem::UnknownOperator if PRESENCE_OPERATORS.contains(&op) => Problem::AlwaysPresent,
+            problem => problem,
+        };
+        FilterError { op: op.to_string(), allowed, optional: false, problem }
+    }
+
+    fn operators(&self) -> Vec<&'static str> {
+        let presence = if self.optional { PRESENCE_OPERATORS } else { &[] };
+        self.allowed.iter().chain(presence).copied().collect()
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.problem {
+            Problem::UnknownOperator => "unknown_operator",
+            Problem::MissingValue => "missing_value",
+            Problem::InvalidValue { .. } => "invalid_value",
+            Problem::AlwaysPresent => "always_present",
+            Problem::Ambiguous { .. } => "ambiguous",
+        }
+    }
+
+    fn suggestion(&self) -> Option<&'static str> {
+        let op = self.op.to_lowercase().replace(['-', '_'], "");
+        let symbolic = match op.as_str() {
+            "=" | "==" => Some("eq"),
+            "!=" | "<>" | "ne" => Some("neq"),
+            ">" => Some("gt"),
+            "<" => Some("lt"),
+            ">=" | "ge" => Some("gte"),
+            "<=" | "le" => Some("lte"),
+            _ => None,
+        };
+        let candidates = self.operators().into_iter();
+        match symbolic {
+            Some(symbolic) => candidates.into_iter().find(|&c| c == symbolic),
+            None => candidates
+                .map(|c| (edit_distance(&op, &c.replace('_', "")), c))
+                .filter(|&(distance, _)| distance <= 2 && distance < op.len())
+                .min_by_key(|&(distance, _)| distance)
+                .map(|(_, c)| c),
+        }
+    }
+
+    pub fn render(&self, source: &str) -> String {
+        let mut message = match &self.problem {
+            Problem::UnknownOperator => format!("{}: unknown operator {:?}", source, self.op),
+            Problem::MissingValue => format!("{}: operator {} needs a value", source, self.op),
+            Problem::AlwaysPresent => {
+                format!("{}: operator {} is not supported, this field is present in every entry", source, self.op)
+            }
+            Problem::InvalidValue { value, cause } => {
+                format!("{}: invalid value {:?} for {}: {}", source, value, self.op, cause)
+            }
+            Problem::Ambiguous { as_operator, implicit } => {
+                return format!(
+                    "{}: {:?} is ambiguous\n  as an operator: {}\n  as text: {} {:?}, prefix it with literal:",
+                    source,
+                    self.op,
+                    as_operator,
+                    implicit.name(),
+                    self.op
+                );
+            }
+        };
+        if let (Problem::UnknownOperator, Some(suggestion)) = (&self.problem, self.suggestion()) {
+            message.push_str(&format!("\n  did you mean {}?", suggestion));
+        }
+        if !self.allowed.is_empty() {
+            message.push_str(&format!("\n  valid operators: {}", self.operators().join(", ")));
+        }
+        message
+    }
+}
+
+impl Serialize for FilterError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Fields<'a> {
+            operator: &'a str,
+            reason: &'static str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            value: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            cause: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            suggestion: Option<&'static str>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            valid_operators: Vec<&'static str>,
+        }
+        let (value, cause) = match &self.problem {
+            Problem::InvalidValue { value, cause } => (Some(value.as_str()), Some(cause.as_str())),
+            _ => (None, None),
+        };
+        Fields {
+            operator: &self.op,
+            reason: self.reason(),
+            value,
+            cause,
+            suggestion: matches!(self.problem, Problem::UnknownOperator).then(|| self.suggestion()).flatten(),
+            valid_operators: match self.problem {
+                Problem::Ambiguous { .. } => Vec::new(),
+                _ => self.operators(),
+            },
+        }
+        .serialize(serializer)
+    }
+}
+
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+fn value<'a>(args: &'a [String], allowed: &'static [&'static str]) -> Result<&'a str, FilterError> {
+    args.get(1).map(String::as_str).ok_or_else(|| FilterError::new(&args[0], allowed, Problem::MissingValue))
+}
+
+fn parse_or_err<T: FromStr>(args: &[String], allowed: &'static [&'static str]) -> Result<T, FilterError>
+where
+    T::Err: Display,
+{
+    let raw = value(args, allowed)?;
+    raw.parse().map_err(|e: T::Err| {
+        FilterError::new(&args[0], allowed, Problem::InvalidValue { value: raw.to_string(), cause: e.to_string() })
+    })
+}
+
+fn parse_string_filter(args: Vec<String>) -> Result<StringFilter, FilterError> {
+    let allowed = STRING_OPERATORS;
+    match args[0].as_str() {
+        "contains" => Ok(StringFilter::Contains(value(&args, allowed)?.to_string())),
+        "eq" => Ok(StringFilter::Eq(value(&args, allowed)?.to_string())),
+        "starts_with" => Ok(StringFilter::StartsWith(value(&args, allowed)?.to_string())),
+        "ends_with" => Ok(StringFilter::EndsWith(value(&args, allowed)?.to_string())),
+        op => Err(FilterError::new(op, allowed, Problem::UnknownOperator))
+    }
+}
+
+/// Substrings searched for in one pass, so long block lists stay fast.
+pub struct Patterns {
+    automaton: AhoCorasick,
+    patterns: Vec<String>,
+}
+
+impl Patterns {
+    fn new(patterns: Vec<String>) -> Result<Patterns, String> {
+        let automaton = AhoCorasick::new(&patterns).map_err(|e| e.to_string())?;
+        Ok(Patterns { automaton, patterns })
+    }
+
+    fn quoted(&self) -> Vec<String> {
+        self.patterns.iter().map(|p| format!("{:?}", p)).collect()
+    }
+}
+
+/// String filter that also accepts `in`, matching any of a set of exact values,
+/// `contains_any` / `not_contains_any` over a list of substrings, and `fuzzy` / `fuzzy_eq`
+/// within an edit distance.
+pub enum TextFilter {
+    Match(StringFilter),
+    In(HashSet<String>),
+    ContainsAny(Patterns),
+    /// Like `neq`, only entries that have a value can match.
+    NotContainsAny(Patterns),
+    Fuzzy(Fuzzy),
+}
+
+impl Default for TextFilter {
+    fn default() -> Self {
+        TextFilter::Match(StringFilter::Any)
+    }
+}
+
+impl Filterable<TextFilter> for Option<String> {
+    fn is_match(&self, filter: &TextFilter) -> bool {
+        match filter {
+            TextFilter::Match(filter) => self.is_match(filter),
+            TextFilter::In(values) => self.as_ref().is_some_and(|value| values.contains(value)),
+            TextFilter::ContainsAny(patterns) => self.as_ref().is_some_and(|value| patterns.automaton.is_match(value)),
+            TextFilter::NotContainsAny(patterns) => self.as_ref().is_some_and(|value| !patterns.automaton.is_match(value)),
+            TextFilter::Fuzzy(fuzzy) => self.as_ref().is_some_and(|value| fuzzy.distance(value).is_some()),
+        }
+    }
+}
+
+/// Spells out the operator for a lone value: `literal:` or `raw:` makes the rest the value,
+/// and anything that isn't an operator is matched with `implicit`. Values that could also be
+/// an operator and its value, or a misspelled operator, are rejected rather than guessed at.
+fn with_implicit_operator(args: Vec<String>, implicit: ImplicitOperator) -> Result<Vec<String>, FilterError> {
+    let [lone] = args.as_slice() else {
+        return Ok(args);
+    };
+    if let Some(literal) = lone.strip_prefix("literal:").or_else(|| lone.strip_prefix("raw:")) {
+        return Ok(vec![implicit.name().to_string(), literal.to_string()]);
+    }
+    if TEXT_OPERATORS.contains(&lone.as_str()) {
+        return Ok(args);
+    }
+    let ambiguous = |as_operator| FilterError::new(lone, &[], Problem::Ambiguous { as_operator, implicit });
+    if let Some((word, rest)) = lone.split_once(char::is_whitespace) {
+        if TEXT_OPERATORS.contains(&word) {
+            return Err(ambiguous(format!("{} {:?}, pass them as two arguments", word, rest.trim_start())));
+        }
+    }
+    else if let Some(operator) = FilterError::new(lone, TEXT_OPERATORS, Problem::UnknownOperator).suggestion() {
+        return Err(ambiguous(format!("did you mean {}?", operator)));
+    }
+    Ok(vec![implicit.name().to_string(), lone.clone()])
+}
+
+fn parse_text_filter(args: Vec<String>, implicit: ImplicitOperator) -> Result<TextFilter, FilterError> {
+    let args = with_implicit_operator(args, implicit)?;
+    match args[0].as_str() {
+        "in" => {
+            value(&args, TEXT_OPERATORS)?;
+            Ok(TextFilter::In(args[1..].iter().cloned().collect()))
+        }
+        op @ ("contains_any" | "not_contains_any") => {
+            value(&args, TEXT_OPERATORS)?;
+            let patterns = Patterns::new(args[1..].to_vec()).map_err(|cause| {
+                FilterError::new(op, TEXT_OPERATORS, Problem::InvalidValue { value: args[1..].join(","), cause })
+            })?;
+            Ok(match op {
+                "contains_any" => TextFilter::ContainsAny(patterns),
+                _ => TextFilter::NotContainsAny(patterns),
+            })
+        }
+        op @ ("fuzzy" | "fuzzy_eq") => {
+            let raw = value(&args, TEXT_OPERATORS)?;
+            let fuzzy = Fuzzy::parse(raw, op == "fuzzy_eq").map_err(|cause| {
+                FilterError::new(op, TEXT_OPERATORS, Problem::InvalidValue { value: raw.to_string(), cause })
+            })?;
+            Ok(TextFilter::Fuzzy(fuzzy))
+        }
+        _ => parse_string_filter(args).map(TextFilter::Match).map_err(|e| FilterError { allowed: TEXT_OPERATORS, ..e }),
+    }
+}
+
+fn parse_ord_filter<T: PartialOrd + FromStr>(args: Vec<String>) -> Result<OrdFilter<T>, FilterError>
+where
+    T::Err: Display,
+{
+    let allowed = ORD_OPERATORS;
+    match args[0].as_str() {
+        "eq" => Ok(OrdFilter::Eq(parse_or_err(&args, allowed)?)),
+        "neq" => Ok(OrdFilter::Neq(parse_or_err(&args, allowed)?)),
+        "gt" => Ok(OrdFilter::Gt(parse_or_err(&args, allowed)?)),
+        "lt" => Ok(OrdFilter::Lt(parse_or_err(&args, allowed)?)),
+        "gte" => Ok(OrdFilter::Gte(parse_or_err(&args, allowed)?)),
+        "lte" => Ok(OrdFilter::Lte(parse_or_err(&args, allowed)?)),
+        op => Err(FilterError::new(op, allowed, Problem::UnknownOperator))
+    }
+}
+
+/// The same comparison of values converted with `f`.
+fn map_ord<T: PartialOrd, U: PartialOrd>(filter: OrdFilter<T>, f: impl Fn(T) -> U) -> OrdFilter<U> {
+    match filter {
+        OrdFilter::Any => OrdFilter::Any,
+        OrdFilter::None => OrdFilter::None,
+        OrdFilter::Eq(v) => OrdFilter::Eq(f(v)),
+        OrdFilter::Neq(v) => OrdFilter::Neq(f(v)),
+        OrdFilter::Gt(v) => OrdFilter::Gt(f(v)),
+        OrdFilter::Gte(v) => OrdFilter::Gte(f(v)),
+        OrdFilter::Lt(v) => OrdFilter::Lt(f(v)),
+        OrdFilter::Lte(v) => OrdFilter::Lte(f(v)),
+    }
+}
+
+/// Response size filter, with values such as `1.5M` or `1,048,576`, see `size::parse_size`.
+fn parse_bytes_filter(args: Vec<String>) -> Result<OrdFilter<u64>, FilterError> {
+    Ok(map_ord(parse_ord_filter(args)?, |ByteSize(v)| v))
+}
+
+/// Timestamp filter, with times such as `2023-02-12T14:00:00Z` or `12/Feb/2023`, see
+/// `log_time::parse_option`.
+fn parse_timestamp_filter(args: Vec<String>) -> Result<OrdFilter<DateTime<FixedOffset>>, FilterError> {
+    Ok(map_ord(parse_ord_filter(args)?, |LogTime(v)| v))
+}
+
+/// Status code filter, with codes such as `404` or `not_found`, see `status::Status`.
+fn parse_status_filter(args: Vec<String>) -> Result<OrdFilter<StatusCode>, FilterError> {
+    Ok(map_ord(parse_ord_filter(args)?, |Status(v)| v))
+}
+
+/// Client address filter. `eq` and `neq` take an address or CIDR block, `in` a comma-separated
+/// list of them, IPv4 and IPv6 mixed.
+#[derive(Default)]
+pub enum IpFilter {
+    #[default]
+    Any,
+    Eq(IpRange),
+    Neq(IpRange),
+    In(IpSet),
+}
+
+impl Filterable<IpFilter> for Option<IpAddr> {
+    fn is_match(&self, filter: &IpFilter) -> bool {
+        match filter {
+            IpFilter::Any => true,
+            IpFilter::Eq(range) => self.is_some_and(|addr| range.contains(&addr)),
+            IpFilter::Neq(range) => self.is_some_and(|addr| !range.contains(&addr)),
+            IpFilter::In(set) => self.is_some_and(|addr| set.contains(&addr)),
+        }
+    }
+}
+
+/// --ip and --client, which both look at the entry's client: --ip only ever matches an
+/// address, and --client the client as logged, either one.
+#[derive(Default)]
+pub struct ClientFilter {
+    ip: IpFilter,
+    name: Presence<StringFilter>,
+}
+
+impl ClientFilter {
+    /// Whether --ip was given, which no hostname client matches.
+    pub fn filters_ip(&self) -> bool {
+        !matches!(self.ip, IpFilter::Any)
+    }
+
+    /// The field of the filter that rejects `client`, if one does.
+    fn rejection(&self, client: &Option<Client>) -> Option<&'static str> {
+        if !client.as_ref().and_then(Client::ip).is_match(&self.ip) {
+            return Some("ip");
+        }
+        let named = matches!(self.name, Presence::Value(StringFilter::Any))
+            || client.as_ref().map(Client::to_string).is_match(&self.name);
+        (!named).then_some("client")
+    }
+}
+
+impl Filterable<ClientFilter> for Option<Client> {
+    fn is_match(&self, filter: &ClientFilter) -> bool {
+        filter.rejection(self).is_none()
+    }
+}
+
+fn parse_ip_filter(args: Vec<String>) -> Result<IpFilter, FilterError> {
+    let allowed = IP_OPERATORS;
+    match args[0].as_str() {
+        "eq" => Ok(IpFilter::Eq(parse_or_err(&args, allowed)?)),
+        "neq" => Ok(IpFilter::Neq(parse_or_err(&args, allowed)?)),
+        "in" => {
+            value(&args, allowed)?;
+            let set = args[1..]
+                .iter()
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| {
+                    item.parse::<IpRange>().map_err(|cause| {
+                        FilterError::new("in", allowed, Problem::InvalidValue { value: item.to_string(), cause })
+                    })
+                })
+                .collect::<Result<IpSet, _>>()?;
+            Ok(IpFilter::In(set))
+        }
+        op => Err(FilterError::new(op, allowed, Problem::UnknownOperator))
+    }
+}
+
+/// Filter on a field that can be missing from an entry. `none` matches absent, empty and `-`
+/// values, `some` matches everything else; other operators go to the wrapped filter.
+pub enum Presence<F> {
+    Missing,
+    Present,
+    Value(F),
+}
+
+impl<F: Default> Default for Presence<F> {
+    fn default() -> Self {
+        Presence::Value(F::default())
+    }
+}
+
+/// Values that count as missing even though the field was captured.
+trait Blank {
+    fn is_blank(&self) -> bool {
+        false
+    }
+}
+
+impl Blank for String {
+    fn is_blank(&self) -> bool {
+        self.is_empty() || self == "-"
+    }
+}
+
+impl Blank for Severity {}
+impl Blank for HumanDuration {}
+impl Blank for u64 {}
+impl Blank for u16 {}
+impl Blank for TlsVersion {}
+
+impl<T: Blank, F> Filterable<Presence<F>> for Option<T>
+where
+    Option<T>: Filterable<F>,
+{
+    fn is_match(&self, filter: &Presence<F>) -> bool {
+        let missing = self.as_ref().is_none_or(Blank::is_blank);
+        match filter {
+            Presence::Missing => missing,
+            Presence::Present => !missing,
+            Presence::Value(filter) => self.is_match(filter),
+        }
+    }
+}
+
+fn parse_presence<F>(
+    args: Vec<String>,
+    parse: impl Fn(Vec<String>) -> Result<F, FilterError>,
+) -> Result<Presence<F>, FilterError> {
+    match args[0].as_str() {
+        "none" => Ok(Presence::Missing),
+        "some" => Ok(Presence::Present),
+        _ => parse(args).map(Presence::Value).map_err(|e| FilterError { optional: true, ..e }),
+    }
+}
+
+/// String operators apply to the field's text; everything else compares numerically when both
+/// the field and the filter value are numbers, and lexicographically otherwise.
+fn parse_field_filter(args: Vec<String>) -> Result<ExtraFilter, FilterError> {
+    parse_extra(args, |args| {
+        let filter = match args[0].as_str() {
+            "contains" | "starts_with" | "ends_with" => parse_string_filter(args).map(ExtraFilter::Text),
+            _ => parse_ord_filter(args.clone()).map(|text| ExtraFilter::Compare {
+                number: parse_ord_filter(args).ok(),
+                text,
+            }),
+        };
+        filter.map_err(|e| FilterError { allowed: FIELD_OPERATORS, ..e })
+    })
+}
+
+/// `gap` filter, with durations such as `100ms` or `2s`, compared in seconds.
+fn parse_gap_filter(args: Vec<String>) -> Result<ExtraFilter, FilterError> {
+    parse_extra(args, |args| Ok(ExtraFilter::Number(map_ord(parse_ord_filter(args)?, |gap: HumanDuration| gap.as_secs_f64()))))
+}
+
+fn parse_extra(
+    args: Vec<String>,
+    parse: impl Fn(Vec<String>) -> Result<ExtraFilter, FilterError>,
+) -> Result<ExtraFilter, FilterError> {
+    parse_presence(args, parse).map(|presence| match presence {
+        Presence::Value(filter) => filter,
+        Presence::Missing => ExtraFilter::Missing,
+        Presence::Present => ExtraFilter::Present,
+    })
+}
+
+enum ExtraFilter {
+    Missing,
+    Present,
+    Number(OrdFilter<f64>),
+    Text(StringFilter),
+    Compare {
+        number: Option<OrdFilter<f64>>,
+        text: OrdFilter<String>,
+    },
+}
+
+#[derive(Default)]
+pub struct ExtraFilters {
+    filters: Vec<(String, ExtraFilter)>,
+    /// Field names given through `--field`, validated against the first parsed entry.
+    generic: Vec<String>,
+}
+
+impl ExtraFilters {
+    pub fn check_fields(&self, entry: &Entry) -> Result<(), String> {
+        let unknown = |name: &&String| !entry.extras.contains_key(*name) && !registry::known(name) && !extract::defines(name);
+        match self.generic.iter().find(unknown) {
+            Some(name) => Err(registry::unknown(name, entry.extras.keys().map(String::as_str))),
+            None => Ok(()),
+        }
+    }
+
+    fn reads(&self, name: &str) -> bool {
+        self.filters.iter().any(|(field, _)| field == name)
+    }
+}
+
+impl Filterable<ExtraFilter> for Option<&FieldValue> {
+    fn is_match(&self, filter: &ExtraFilter) -> bool {
+        let value = *self;
+        match filter {
+            ExtraFilter::Missing => value.is_none(),
+            ExtraFilter::Present => value.is_some(),
+            ExtraFilter::Number(filter) => value.and_then(FieldValue::as_number).is_match(filter),
+            ExtraFilter::Text(filter) => value.map(FieldValue::as_text).is_match(filter),
+            ExtraFilter::Compare { number: Some(number), .. } if value.and_then(FieldValue::as_number).is_some() => {
+                value.and_then(FieldValue::as_number).is_match(number)
+            }
+            ExtraFilter::Compare { text, .. } => value.map(FieldValue::as_text).is_match(text),
+        }
+    }
+}
+
+pub struct LogFilter {
+    user_agent: Presence<TextFilter>,
+    referrer: Presence<TextFilter>,
+    raw_request: Presence<TextFilter>,
+    status_code: OrdFilter<StatusCode>,
+    pub client: ClientFilter,
+    path: Presence<TextFilter>,
+    timestamp: OrdFilter<DateTime<FixedOffset>>,
+    severity: Presence<OrdFilter<Severity>>,
+    pub duration: Presence<OrdFilter<HumanDuration>>,
+    bytes: Presence<OrdFilter<u64>>,
+    scheme: Presence<StringFilter>,
+    port: Presence<OrdFilter<u16>>,
+    tls_protocol: Presence<OrdFilter<TlsVersion>>,
+    pub extras: ExtraFilters,
+}
+
+impl Filterable<LogFilter> for Entry {
+    fn is_match(&self, filter: &LogFilter) -> bool {
+        filter.rejection(self).is_none()
+    }
+}
+
+
+/// Renders a parsed filter value for --explain-filter, normalized the way it is compared.
+trait Explain {
+    fn explain(&self) -> String;
+}
+
+impl Explain for DateTime<FixedOffset> {
+    fn explain(&self) -> String {
+        self.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    }
+}
+
+impl Explain for StatusCode {
+    fn explain(&self) -> String {
+        format!("{} ({})", self.as_u16(), self.canonical_reason().unwrap_or("unknown"))
+    }
+}
+
+macro_rules! explain_display {
+    ($($t:ty),*) => {
+        $(impl Explain for $t {
+            fn explain(&self) -> String {
+                self.to_string()
+            }
+        })*
+    };
+}
+
+explain_display!(IpRange, Severity, HumanDuration, f64, String, u16, TlsVersion);
+
+impl Explain for u64 {
+    fn explain(&self) -> String {
+        match *self < 1 << 10 {
+            true => self.to_string(),
+            false => format!("{} ({})", self, format_size(*self)),
+        }
+    }
+}
+
+fn describe_ord<T: PartialOrd + Explain>(filter: &OrdFilter<T>) -> Option<String> {
+    let (op, v) = match filter {
+        OrdFilter::Any => return None,
+        OrdFilter::None => return Some("none".to_string()),
+        OrdFilter::Eq(v) => ("eq", v),
+        OrdFilter::Neq(v) => ("neq", v),
+        OrdFilter::Gt(v) => ("gt", v),
+        OrdFilter::Gte(v) => ("gte", v),
+        OrdFilter::Lt(v) => ("lt", v),
+        OrdFilter::Lte(v) => ("lte", v),
+    };
+    Some(format!("{} {}", op, v.explain()))
+}
+
+fn describe_string(filter: &StringFilter) -> Option<String> {
+    let (op, v) = match filter {
+        StringFilter::Any => return None,
+        StringFilter::None => return Some("none".to_string()),
+        StringFilter::Eq(v) => ("eq", v),
+        StringFilter::Neq(v) => ("neq", v),
+        StringFilter::Contains(v) => ("contains", v),
+        StringFilter::StartsWith(v) => ("starts_with", v),
+        StringFilter::EndsWith(v) => ("ends_with", v),
+    };
+    Some(format!("{} {:?}", op, v))
+}
+
+/// Lists set members, eliding all but the first few of a large set.
+fn describe_set(op: &str, values: Vec<String>) -> String {
+    const SHOWN: usize = 5;
+    match values.len() {
+        n if n > SHOWN => format!("{} {},... ({} values)", op, values[..SHOWN].join(","), n),
+        _ => format!("{} {}", op, values.join(",")),
+    }
+}
+
+fn describe_text(filter: &TextFilter) -> Option<String> {
+    match filter {
+        TextFilter::Match(filter) => describe_string(filter),
+        TextFilter::In(values) => {
+            let mut values: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+            values.sort();
+            Some(describe_set("in", values))
+        }
+        TextFilter::ContainsAny(patterns) => Some(describe_set("contains_any", patterns.quoted())),
+        TextFilter::NotContainsAny(patterns) => Some(describe_set("not_contains_any", patterns.quoted())),
+        TextFilter::Fuzzy(fuzzy) => Some(format!("{} {}", fuzzy.operator(), fuzzy)),
+    }
+}
+
+fn describe_ip(filter: &IpFilter) -> Option<String> {
+    match filter {
+        IpFilter::Any => None,
+        IpFilter::Eq(range) => Some(format!("eq {}", range)),
+        IpFilter::Neq(range) => Some(format!("neq {}", range)),
+        IpFilter::In(set) => Some(describe_set("in", set.ranges().iter().map(IpRange::to_string).collect())),
+    }
+}
+
+fn describe_presence<F>(filter: &Presence<F>, describe: impl Fn(&F) -> Option<String>) -> Option<String> {
+    match filter {
+        Presence::Missing => Some("none (absent, empty or -)".to_string()),
+        Presence::Present => Some("some (present and not -)".to_string()),
+        Presence::Value(filter) => describe(filter),
+    }
+}
+
+fn describe_extra(filter: &ExtraFilter) -> Option<String> {
+    match filter {
+        ExtraFilter::Missing => describe_presence::<()>(&Presence::Missing, |_| None),
+        ExtraFilter::Present => describe_presence::<()>(&Presence::Present, |_| None),
+        ExtraFilter::Number(filter) => describe_ord(filter),
+        ExtraFilter::Text(filter) => describe_string(filter),
+        ExtraFilter::Compare { number: Some(number), .. } => {
+            describe_ord(number).map(|d| format!("{} (numeric, text if the field isn't a number)", d))
+        }
+        ExtraFilter::Compare { text, .. } => describe_ord(text).map(|d| format!("{} (text)", d)),
+    }
+}
+
+/// One resolved filter condition and the option it came from.
+struct Explained {
+    field: String,
+    condition: String,
+    source: String,
+}
+
+/// A filter given on the command line, which --verbose-matches names next to the value that
+/// satisfied it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Given {
+    /// The field as JSON output names it; `client` for the --client name.
+    pub field: String,
+    pub condition: String,
+    /// A `fuzzy` or `fuzzy_eq` condition, whose distance from the value is shown with it.
+    pub fuzzy: Option<Fuzzy>,
+}
+
+/// A condition without the note --explain-filter adds, e.g. `gte 500` for
+/// `gte 500 (Internal Server Error)`; quoted values end in a quote, so theirs are kept.
+fn brief(condition: &str) -> &str {
+    match condition.strip_suffix(')').and_then(|rest| rest.rfind(" (")) {
+        Some(at) => &condition[..at],
+        None => condition,
+    }
+}
+
+/// The effective filter as shown by --explain-filter, plus the value files it loaded.
+#[derive(Default)]
+pub struct Explanation {
+    conditions: Vec<Explained>,
+    /// One list of conditions per `--exclude` group.
+    exclusions: Vec<Vec<Explained>>,
+    pub notices: Vec<Notice>,
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.conditions.is_empty() && self.exclusions.is_empty() {
+            return writeln!(f, "No filters: every parsed entry matches");
+        }
+        let all = || self.conditions.iter().chain(self.exclusions.iter().flatten());
+        let width = all().map(|e| e.field.len()).max().unwrap_or(0);
+        let condition_width = all().map(|e| e.condition.len()).max().unwrap_or(0);
+        let mut block = |header: &str, conditions: &[Explained]| {
+            writeln!(f, "{}", header)?;
+            for e in conditions {
+                writeln!(f, "  {:width$}  {:condition_width$}  from {}", e.field, e.condition, e.source)?;
+            }
+            Ok(())
+        };
+        match self.conditions.is_empty() {
+            true => block("Every parsed entry matches", &[])?,
+            false => block("Entries must match all of:", &self.conditions)?,
+        }
+        for group in &self.exclusions {
+            block("Except entries matching all of:", group)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses filter options while recording provenance and collecting every error.
+#[derive(Default)]
+struct Builder {
+    explanation: Explanation,
+    errors: Vec<Error>,
+}
+
+impl Builder {
+    fn record(&mut self, field: &str, condition: Option<String>, source: &str) {
+        if let Some(condition) = condition {
+            self.explanation.conditions.push(Explained {
+                field: field.to_string(),
+                condition,
+                source: source.to_string(),
+            });
+        }
+    }
+
+    /// Replaces the `@file` value of a list operator such as `in` with the file's lines, skipping
+    /// blank lines and `#` comments. Returns the name the values came from, for provenance.
+    fn load_values(&mut self, source: &str, args: Vec<String>) -> Result<(Vec<String>, String), FilterError> {
+        let file = match args.as_slice() {
+            [op, value] if LIST_OPERATORS.contains(&op.as_str()) && value.starts_with('@') => PathBuf::from(&value[1..]),
+            _ => return Ok((args, source.to_string())),
+        };
+        let invalid = |cause: String| FilterError::new(&args[0], &[], Problem::InvalidValue { value: args[1].clone(), cause });
+        let content = std::fs::read_to_string(&file).map_err(|e| invalid(e.to_string()))?;
+        let values: Vec<String> = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+        if values.is_empty() {
+            return Err(invalid("the file lists no values".to_string()));
+        }
+        self.explanation.notices.push(Notice::ValuesLoaded {
+            option: source.to_string(),
+            file: file.clone(),
+            count: values.len(),
+        });
+        let loaded_from = format!("{} {}", source, args[1]);
+        Ok((std::iter::once(args[0].clone()).chain(values).collect(), loaded_from))
+    }
+
+    fn add<F: Default>(
+        &mut self,
+        field: &str,
+        source: &str,
+        args: Option<Vec<String>>,
+        parse: impl Fn(Vec<String>) -> Result<F, FilterError>,
+        describe: impl Fn(&F) -> Option<String>,
+    ) -> F {
+        let Some(args) = args else {
+            return F::default();
+        };
+        let parsed = self.load_values(source, args).and_then(|(args, loaded_from)| Ok((parse(args)?, loaded_from)));
+        match parsed {
+            Ok((filter, loaded_from)) => {
+                self.record(field, describe(&filter), &loaded_from);
+                filter
+            }
+            Err(error) => {
+                self.errors.push(Error::Filter { option: source.to_string(), error });
+                F::default()
+            }
+        }
+    }
+
+    fn add_extra(
+        &mut self,
+        filters: &mut Vec<(String, ExtraFilter)>,
+        field: &str,
+        source: &str,
+        args: Option<Vec<String>>,
+        parse: impl Fn(Vec<String>) -> Result<ExtraFilter, FilterError>,
+    ) {
+        let Some(args) = args else {
+            return;
+        };
+        match parse(args) {
+            Ok(filter) => {
+                self.record(field, describe_extra(&filter), source);
+                filters.push((field.to_string(), filter));
+            }
+            Err(error) => self.errors.push(Error::Filter { option: source.to_string(), error }),
+        }
+    }
+}
+
+impl LogFilter {
+    /// Builds the filter and its explanation, reporting all invalid options at once.
+    pub fn build(value: FilterArgs) -> Result<(LogFilter, Explanation), Vec<Error>> {
+        let mut builder = Builder::default();
+        let implicit = value.implicit_operator;
+
+        let status_code = builder.add("status_code", "--status-code", value.status_code, parse_status_filter, describe_ord);
+        let user_agent = builder.add(
+            "user_agent",
+            "--user-agent",
+            value.user_agent,
+            |a| parse_presence(a, |a| parse_text_filter(a, implicit)),
+            |f| describe_presence(f, describe_text),
+        );
+        let referrer = builder.add(
+            "referrer",
+            "--referrer",
+            value.referrer,
+            |a| parse_presence(a, |a| parse_text_filter(a, implicit)),
+            |f| describe_presence(f, describe_text),
+        );
+        let (raw_request_args, raw_request_source) = match value.only_malformed_requests {
+            true => (Some(vec!["some".to_string()]), "--only-malformed-requests"),
+            false => (value.raw_request, "--raw-request"),
+        };
+        let raw_request = builder.add(
+            "raw_request",
+            raw_request_source,
+            raw_request_args,
+            |a| parse_presence(a, |a| parse_text_filter(a, implicit)),
+            |f| describe_presence(f, describe_text),
+        );
+        let ip = builder.add("ip", "--ip", value.ip, parse_ip_filter, describe_ip);
+        let name = builder.add(
+            "client",
+            "--client",
+            value.client,
+            |a| parse_presence(a, parse_string_filter),
+            |f| describe_presence(f, describe_string),
+        );
+        let path = builder.add(
+            "path",
+            "--path",
+            value.path,
+            |a| parse_presence(a, |a| parse_text_filter(a, implicit)),
+            |f| describe_presence(f, describe_text),
+        );
+        let timestamp = builder.add("timestamp", "--timestamp", value.timestamp, parse_timestamp_filter, describe_ord);
+        let severity = builder.add(
+            "severity",
+            "--severity",
+            value.severity,
+            |a| parse_presence(a, parse_ord_filter),
+            |f| describe_presence(f, describe_ord),
+        );
+        let duration = builder.add(
+            "duration",
+            "--duration",
+            value.duration,
+            |a| parse_presence(a, parse_ord_filter),
+            |f| describe_presence(f, describe_ord),
+        );
+        let bytes = builder.add(
+            "bytes",
+            "--bytes",
+            value.bytes,
+            |a| parse_presence(a, parse_bytes_filter),
+            |f| describe_presence(f, describe_ord),
+        );
+        let scheme = builder.add(
+            "scheme",
+            "--scheme",
+            value.scheme,
+            |a| parse_presence(a, parse_string_filter),
+            |f| describe_presence(f, describe_string),
+        );
+        let port = builder.add(
+            "port",
+            "--port",
+            value.port,
+            |a| parse_presence(a, parse_ord_filter),
+            |f| describe_presence(f, describe_ord),
+        );
+        let tls_protocol = builder.add(
+            "tls_protocol",
+            "--tls-protocol",
+            value.tls_protocol,
+            |a| parse_presence(a, parse_ord_filter),
+            |f| describe_presence(f, describe_ord),
+        );
+
+        let mut filters = Vec::new();
+        builder.add_extra(&mut filters, "backend", "--backend", value.backend, |a| {
+            parse_extra(a, |a| parse_string_filter(a).map(ExtraFilter::Text))
+        });
+        builder.add_extra(&mut filters, "tr", "--response-time", value.response_time, |a| {
+            parse_extra(a, |a| parse_ord_filter(a).map(ExtraFilter::Number))
+        });
+        builder.add_extra(&mut filters, "syslog_host", "--host", value.host, |a| {
+            parse_extra(a, |a| parse_string_filter(a).map(ExtraFilter::Text))
+        });
+        builder.add_extra(&mut filters, "termination_state", "--termination-state", value.termination_state, |a| {
+            parse_extra(a, |a| parse_string_filter(a).map(ExtraFilter::Text))
+        });
+        let class = value.class.map(|class| vec!["eq".to_string(), class.name().to_string()]);
+        builder.add_extra(&mut filters, "class", "--class", class, |a| {
+            parse_extra(a, |a| parse_string_filter(a).map(ExtraFilter::Text))
+        });
+        // Entries without a referrer have no referrer_class.
+        let referrer_class = value.referrer_class.map(|class| match class {
+            referrer::Class::Direct => vec!["none".to_string()],
+            class => vec!["eq".to_string(), class.name().to_string()],
+        });
+        builder.add_extra(&mut filters, referrer::CLASS_FIELD, "--referrer-class", referrer_class, |a| {
+            parse_extra(a, |a| parse_string_filter(a).map(ExtraFilter::Text))
+        });
+        let suspicious = value.suspicious_paths.then(|| vec!["some".to_string()]);
+        builder.add_extra(&mut filters, suspicious::FIELD, "--suspicious-paths", suspicious, |a| {
+            parse_extra(a, |_| Ok(ExtraFilter::Present))
+        });
+        let generic: Vec<String> = value.field.chunks(3).map(|args| registry::canonical(&args[0])).collect();
+        for (name, args) in generic.iter().zip(value.field.chunks(3)) {
+            let parse = match name == gap::FIELD {
+                true => parse_gap_filter,
+                false => parse_field_filter,
+            };
+            builder.add_extra(&mut filters, name, "--field", Some(args[1..].to_vec()), parse);
+        }
+
+        let filter = LogFilter {
+            status_code,
+            user_agent,
+            referrer,
+            raw_request,
+            client: ClientFilter { ip, name },
+            path,
+            timestamp,
+            severity,
+            duration,
+            bytes,
+            scheme,
+            port,
+            tls_protocol,
+            extras: ExtraFilters { filters, generic },
+        };
+        match builder.errors.is_empty() {
+            true => Ok((filter, builder.explanation)),
+            false => Err(builder.errors),
+        }
+    }
+}
+
+impl LogFilter {
+    /// The fields, and their options, of the `--scheme`, `--port` and `--tls-protocol` filters
+    /// that only match entries with a value. Many formats don't log them.
+    pub fn connection_filters(&self) -> Vec<(&'static str, &'static str)> {
+        [
+            ("scheme", "--scheme", !matches!(self.scheme, Presence::Missing | Presence::Value(StringFilter::Any))),
+            ("port", "--port", !matches!(self.port, Presence::Missing | Presence::Value(OrdFilter::Any))),
+            (
+                "tls_protocol",
+                "--tls-protocol",
+                !matches!(self.tls_protocol, Presence::Missing | Presence::Value(OrdFilter::Any)),
+            ),
+        ]
+        .into_iter()
+        .filter(|&(.., given)| given)
+        .map(|(field, option, _)| (field, option))
+        .collect()
+    }
+
+    /// The `fuzzy` or `fuzzy_eq` filter on the text field `field`, if it has one.
+    fn fuzzy(&self, field: &str) -> Option<Fuzzy> {
+        let filter = match field {
+            "user_agent" => &self.user_agent,
+            "referrer" => &self.referrer,
+            "raw_request" => &self.raw_request,
+            "path" => &self.path,
+            _ => return None,
+        };
+        match filter {
+            Presence::Value(TextFilter::Fuzzy(fuzzy)) => Some(fuzzy.clone()),
+            _ => None,
+        }
+    }
+
+    /// The first field filter, in declaration order, that rejects the entry, which is what
+    /// `is_match` checks; one at a time, so a rejection can be attributed.
+    pub fn rejection<'a>(&'a self, entry: &Entry) -> Option<&'a str> {
+        if !entry.user_agent.is_match(&self.user_agent) {
+            return Some("user_agent");
+        }
+        if !entry.referrer.is_match(&self.referrer) {
+            return Some("referrer");
+        }
+        if !entry.raw_request.is_match(&self.raw_request) {
+            return Some("raw_request");
+        }
+        if !entry.status_code.is_match(&self.status_code) {
+            return Some("status_code");
+        }
+        if let Some(field) = self.client.rejection(&entry.client) {
+            return Some(field);
+        }
+        if !entry.match_path().is_match(&self.path) {
+            return Some("path");
+        }
+        if !entry.timestamp.is_match(&self.timestamp) {
+            return Some("timestamp");
+        }
+        if !entry.severity.is_match(&self.severity) {
+            return Some("severity");
+        }
+        if !entry.duration.is_match(&self.duration) {
+            return Some("duration");
+        }
+        if !entry.bytes.is_match(&self.bytes) {
+            return Some("bytes");
+        }
+        if !entry.scheme.is_match(&self.scheme) {
+            return Some("scheme");
+        }
+        if !entry.port.is_match(&self.port) {
+            return Some("port");
+        }
+        if !entry.tls_protocol.is_match(&self.tls_protocol) {
+            return Some("tls_protocol");
+        }
+        let extras = &self.extras.filters;
+        let failed = |(name, filter): &&(String, ExtraFilter)| !registry::value(entry, name).as_deref().is_match(filter);
+        extras.iter().find(failed).map(|(name, _)| name.as_str())
+    }
+}
+
+/// The full filter: entries must pass `include`, every `--where` and the --filter-file, and
+/// match none of the `--exclude` groups.
+pub struct EntryFilter {
+    pub include: LogFilter,
+    conditions: Vec<Expression>,
+    /// The --filter-file and its conditions, swapped for new ones by --reload-on-change.
+    file: Option<(PathBuf, RwLock<Conditions>)>,
+    exclude: Vec<LogFilter>,
+    /// Judged by the scanner, which keeps the history it needs.
+    pub after_status: Option<AfterStatus>,
+    given: Vec<Given>,
+}
+
+impl Filterable<EntryFilter> for Entry {
+    fn is_match(&self, filter: &EntryFilter) -> bool {
+        self.is_match(&filter.include)
+            && filter.conditions.iter().all(|condition| condition.is_match(self))
+            && filter.file().is_none_or(|file| file.0.iter().all(|condition| condition.is_match(self)))
+            && !filter.exclude.iter().any(|group| self.is_match(group))
+    }
+}
+
+impl EntryFilter {
+    pub fn build(mut value: FilterArgs) -> Result<(EntryFilter, Explanation), Vec<Error>> {
+        let groups = std::mem::take(&mut value.exclude);
+        let statuses: Vec<StatusCode> = std::mem::take(&mut value.after_status).into_iter().map(|Status(status)| status).collect();
+        let after_status = match (statuses.is_empty(), value.within.take()) {
+            (false, Some(within)) => Some(AfterStatus { statuses, within: TimeDelta::from_std(within.0).unwrap_or(TimeDelta::max_value()) }),
+            _ => None,
+        };
+        let mut errors = Vec::new();
+        let mut conditions = Vec::new();
+        for source in std::mem::take(&mut value.r#where) {
+            match source.parse::<Expression>() {
+                Ok(condition) => conditions.push(condition),
+                Err(message) => errors.push(Error::Other { message: format!("--where {:?}: {}", source, message) }),
+            }
+        }
+        let file = match value.filter_file.take().map(|path| Conditions::read(&path).map(|file| (path, file))).transpose() {
+            Ok(file) => file,
+            Err(message) => {
+                errors.push(Error::Other { message: format!("--filter-file {}", message) });
+                None
+            }
+        };
+        let mut exclude = Vec::new();
+        let mut exclusions = Vec::new();
+        let mut notices = Vec::new();
+        for (i, group) in groups.iter().enumerate() {
+            let source = match groups.len() {
+                1 => "--exclude".to_string(),
+                _ => format!("--exclude #{}", i + 1),
+            };
+            let args = match FilterArgs::from_conditions(group) {
+                Ok(args) => FilterArgs { implicit_operator: value.implicit_operator, ..args },
+                Err(message) => {
+                    errors.push(Error::Other { message: format!("{}: {}", source, message) });
+                    continue;
+                }
+            };
+            match LogFilter::build(args) {
+                Ok((filter, explanation)) => {
+                    exclude.push(filter);
+                    notices.extend(explanation.notices);
+                    exclusions.push(
+                        explanation.conditions.into_iter().map(|e| Explained { source: source.clone(), ..e }).collect(),
+                    );
+                }
+                Err(group_errors) => errors.extend(group_errors.into_iter().map(|error| match error {
+                    Error::Filter { option, error } => {
+                        Error::Filter { option: format!("{} {}", source, option.trim_start_matches('-')), error }
+                    }
+                    error => error,
+                })),
+            }
+        }
+        let include = LogFilter::build(value);
+        match (include, errors.is_empty()) {
+            (Ok((include, mut explanation)), true) => {
+                let mut given: Vec<Given> = explanation
+                    .conditions
+                    .iter()
+                    .map(|e| {
+                        let condition = brief(&e.condition).to_string();
+                        Given { field: e.field.clone(), condition, fuzzy: include.fuzzy(&e.field) }
+                    })
+                    .collect();
+                for condition in &conditions {
+                    let given_by =
+                        |field: &str| Given { field: field.to_string(), condition: format!("where {}", condition), fuzzy: None };
+                    given.extend(condition.fields().into_iter().map(given_by));
+                }
+                let after =
+                    |after_status: &AfterStatus| Given { field: "ip".to_string(), condition: after_status.describe(), fuzzy: None };
+                given.extend(after_status.iter().map(after));
+                explanation.conditions.extend(conditions.iter().map(|condition| Explained {
+                    field: "where".to_string(),
+                    condition: condition.to_string(),
+                    source: "--where".to_string(),
+                }));
+                explanation.conditions.extend(file.iter().flat_map(|(_, file)| file.describe()).map(|condition| Explained {
+                    field: "where".to_string(),
+                    condition,
+                    source: "--filter-file".to_string(),
+                }));
+                explanation.conditions.extend(after_status.iter().map(|after_status| Explained {
+                    field: "ip".to_string(),
+                    condition: after_status.describe(),
+                    source: "--after-status".to_string(),
+                }));
+                explanation.exclusions = exclusions;
+                explanation.notices.extend(notices);
+                let file = file.map(|(path, file)| (path, RwLock::new(file)));
+                Ok((EntryFilter { include, conditions, file, exclude, after_status, given }, explanation))
+            }
+            (Ok(_), false) => Err(errors),
+            (Err(include_errors), _) => Err(include_errors.into_iter().chain(errors).collect()),
+        }
+    }
+
+    /// What rejected an entry that doesn't match: the first failing field filter, a `--where`,
+    /// or the `--exclude` group it matched.
+    pub fn rejection(&self, entry: &Entry) -> Option<Cow<'_, str>> {
+        if let Some(field) = self.include.rejection(entry) {
+            return Some(Cow::Borrowed(field));
+        }
+        if !self.conditions.iter().all(|condition| condition.is_match(entry)) {
+            return Some(Cow::Borrowed("where"));
+        }
+        if self.file().is_some_and(|file| !file.0.iter().all(|condition| condition.is_match(entry))) {
+            return Some(Cow::Borrowed("filter-file"));
+        }
+        let group = self.exclude.iter().position(|group| entry.is_match(group))?;
+        Some(match self.exclude.len() {
+            1 => Cow::Borrowed("exclude"),
+            _ => Cow::Owned(format!("exclude #{}", group + 1)),
+        })
+    }
+
+    pub fn check_fields(&self, entry: &Entry) -> Result<(), String> {
+        std::iter::once(&self.include).chain(&self.exclude).try_for_each(|filter| filter.extras.check_fields(entry))?;
+        self.conditions.iter().try_for_each(|condition| condition.check_fields(entry))?;
+        self.file().map_or(Ok(()), |file| file.0.iter().try_for_each(|condition| condition.check_fields(entry)))
+    }
+
+    /// Whether any part of the filter reads the format-specific field `name`.
+    pub fn reads(&self, name: &str) -> bool {
+        std::iter::once(&self.include).chain(&self.exclude).any(|filter| filter.extras.reads(name))
+            || self.conditions.iter().any(|condition| condition.reads(name))
+            || self.file().is_some_and(|file| file.0.iter().any(|condition| condition.reads(name)))
+    }
+
+    /// The filters a match satisfied, as given: every field filter, each field a `--where` or
+    /// the --filter-file reads and `--after-status`, but not the `--exclude` groups it escaped.
+    pub fn given(&self) -> Vec<Given> {
+        let mut given = self.given.clone();
+        for condition in self.file().iter().flat_map(|file| &file.0) {
+            let given_by =
+                        |field: &str| Given { field: field.to_string(), condition: format!("where {}", condition), fuzzy: None };
+            given.extend(condition.fields().into_iter().map(given_by));
+        }
+        given
+    }
+
+    fn file(&self) -> Option<RwLockReadGuard<'_, Conditions>> {
+        self.file.as_ref().map(|(_, file)| file.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn filter_file(&self) -> Option<&Path> {
+        self.file.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    /// Replaces the --filter-file's conditions, returning the ones replaced.
+    pub fn reload(&self, conditions: Conditions) -> Conditions {
+        match &self.file {
+            Some((_, file)) => std::mem::replace(&mut *file.write().unwrap_or_else(PoisonError::into_inner), conditions),
+            None => Conditions(Vec::new()),
+        }
+    }
+}
+
+impl TryFrom<FilterArgs> for LogFilter {
+    type Error = String;
+
+    fn try_from(value: FilterArgs) -> Result<Self, Self::Error> {
+        LogFilter::build(value)
+            .map(|(filter, _)| filter)
+            .map_err(|errors| errors.iter().map(Error::to_string).collect::<Vec<_>>().join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        filter: FilterArgs,
+    }
+
+    fn args(ops: &[&str]) -> Vec<String> {
+        ops.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn filter_kinds_cover_every_option_and_their_examples_parse() {
+        for flag in FILTER_FLAGS {
+            let option = format!("--{}", flag.replace('_', "-"));
+            let listed = FILTER_KINDS.iter().any(|kind| kind.kind != "presence" && kind.options.contains(&option.as_str()));
+            assert!(listed, "{} has no kind", option);
+        }
+        for kind in FILTER_KINDS {
+            let (option, value) = kind.example.split_once(' ').unwrap_or((kind.example, ""));
+            let mut argv = vec!["test", option];
+            match option {
+                "--where" => argv.push(value.trim_matches('\'')),
+                _ => argv.extend(value.split_whitespace()),
+            }
+            let cli = TestCli::try_parse_from(argv).unwrap();
+            assert!(EntryFilter::build(cli.filter).is_ok(), "{}", kind.example);
+        }
+    }
+
+    #[test]
+    fn no_filters_error_lists_every_filter_option() {
+        let error = TestCli::try_parse_from(["test"]).unwrap().filter.require_filters().unwrap_err();
+        let listed: Vec<&str> = error.split_once("available filters: ").unwrap().1.trim_end_matches(')').split(", ").collect();
+        let switches = ["only_malformed_requests", "suspicious_paths", "filter_file", "exclude", "after_status"];
+        for flag in FILTER_FLAGS.iter().chain(&switches) {
+            let option = format!("--{}", flag.replace('_', "-"));
+            assert!(listed.contains(&option.as_str()), "{} isn't in {}", option, error);
+        }
+    }
+
+    fn build(argv: &[&str]) -> Result<(LogFilter, Explanation), String> {
+        let cli = TestCli::try_parse_from(std::iter::once("test").chain(argv.iter().copied())).unwrap();
+        LogFilter::build(cli.filter).map_err(|errors| errors.iter().map(Error::to_string).collect::<Vec<_>>().join("\n"))
+    }
+
+    fn error(argv: &[&str]) -> String {
+        build(argv).err().expect("filter should be rejected")
+    }
+
+    #[test]
+    fn operators_round_trip_through_explanations() {
+        for op in ORD_OPERATORS {
+            let filter: OrdFilter<f64> = parse_ord_filter(args(&[op, "1.5"])).unwrap();
+            assert_eq!(describe_ord(&filter), Some(format!("{} 1.5", op)));
+        }
+        for op in ORD_OPERATORS {
+            let filter = parse_status_filter(args(&[op, "not_found"])).unwrap();
+            assert_eq!(describe_ord(&filter), Some(format!("{} 404 (Not Found)", op)));
+        }
+        for op in STRING_OPERATORS {
+            let filter = parse_string_filter(args(&[op, "bot"])).unwrap();
+            assert_eq!(describe_string(&filter), Some(format!("{} \"bot\"", op)));
+        }
+        for op in TEXT_OPERATORS {
+            let filter = parse_text_filter(args(&[op, "bot"]), ImplicitOperator::Contains).unwrap();
+            let distance = if op.starts_with("fuzzy") { ":1" } else { "" };
+            assert_eq!(describe_text(&filter), Some(format!("{} \"bot\"{}", op, distance)));
+        }
+        for op in IP_OPERATORS {
+            let filter = parse_ip_filter(args(&[op, "2001:db8::/32"])).unwrap();
+            assert_eq!(describe_ip(&filter), Some(format!("{} 2001:db8::/32", op)));
+        }
+    }
+
+    #[test]
+    fn contains_any_checks_every_pattern() {
+        let filter = parse_text_filter(args(&["contains_any", "bot", "python-", "curl/"]), ImplicitOperator::Contains).unwrap();
+        let agent = |s: &str| Some(s.to_string());
+        assert!(agent("Googlebot/2.1").is_match(&filter));
+        assert!(agent("curl/8.0").is_match(&filter));
+        assert!(!agent("Mozilla/5.0").is_match(&filter));
+        assert!(!None.is_match(&filter));
+        let filter = parse_text_filter(args(&["not_contains_any", "bot", "python-"]), ImplicitOperator::Contains).unwrap();
+        assert!(agent("Mozilla/5.0").is_match(&filter));
+        assert!(!agent("python-requests/2.28.1").is_match(&filter));
+        assert!(!None.is_match(&filter));
+    }
+
+    #[test]
+    fn lone_values_use_the_implicit_operator() {
+        let describe = |argv: &[&str]| build(argv).map(|(_, explanation)| explanation.conditions[0].condition.clone());
+        assert_eq!(describe(&["-u", "Googlebot"]).unwrap(), "contains \"Googlebot\"");
+        assert_eq!(describe(&["-u", "Googlebot", "--implicit-operator", "eq"]).unwrap(), "eq \"Googlebot\"");
+        assert_eq!(describe(&["-u", "literal:contains bot"]).unwrap(), "contains \"contains bot\"");
+        assert_eq!(describe(&["-u", "raw:eq", "--implicit-operator", "eq"]).unwrap(), "eq \"eq\"");
+        assert_eq!(describe(&["-u", "eq", "literal:x"]).unwrap(), "eq \"literal:x\"");
+        assert_eq!(describe(&["-u", "none"]).unwrap(), "none (absent, empty or -)");
+        let (filter, _) = build(&["-u", "literal:eq scanner"]).unwrap();
+        let entry = |agent: &str| Entry { user_agent: Some(agent.to_string()), ..Entry::default() };
+        assert!(entry("eq scanner/1.0").is_match(&filter));
+        assert!(!entry("scanner/1.0").is_match(&filter));
+
+        assert_eq!(
+            describe(&["-u", "contains bot"]).unwrap_err(),
+            "--user-agent: \"contains bot\" is ambiguous\n  as an operator: contains \"bot\", pass them as two arguments\n  \
+             as text: contains \"contains bot\", prefix it with literal:"
+        );
+        assert_eq!(
+            describe(&["-u", "eq Mozilla/5.0", "--implicit-operator", "eq"]).unwrap_err(),
+            "--user-agent: \"eq Mozilla/5.0\" is ambiguous\n  as an operator: eq \"Mozilla/5.0\", pass them as two arguments\n  \
+             as text: eq \"eq Mozilla/5.0\", prefix it with literal:"
+        );
+        assert_eq!(
+            describe(&["-p", "startswith"]).unwrap_err(),
+            "--path: \"startswith\" is ambiguous\n  as an operator: did you mean starts_with?\n  \
+             as text: contains \"startswith\", prefix it with literal:"
+        );
+    }
+
+    #[test]
+    fn large_sets_are_abbreviated() {
+        let values: Vec<String> = (1..=7).map(|i| format!("10.0.0.{}", i)).collect();
+        let filter = parse_ip_filter([vec!["in".to_string()], values].concat()).unwrap();
+        assert_eq!(describe_ip(&filter), Some("in 10.0.0.1,10.0.0.2,10.0.0.3,10.0.0.4,10.0.0.5,... (7 values)".to_string()));
+    }
+
+    fn build_entry_filter(argv: &[&str]) -> Result<(EntryFilter, Explanation), String> {
+        let cli = TestCli::try_parse_from(std::iter::once("test").chain(argv.iter().copied())).unwrap();
+        EntryFilter::build(cli.filter).map_err(|errors| errors.iter().map(Error::to_string).collect::<Vec<_>>().join("\n"))
+    }
+
+    #[test]
+    fn exclude_groups_parse_into_filter_args() {
+        let parsed = FilterArgs::from_conditions("status eq 200, user-agent contains bot (compatible), referrer none, upstream_addr some").unwrap();
+        assert_eq!(parsed.status_code, Some(args(&["eq", "200"])));
+        assert_eq!(parsed.user_agent, Some(args(&["contains", "bot (compatible)"])));
+        assert_eq!(parsed.referrer, Some(args(&["none"])));
+        assert_eq!(parsed.field, args(&["upstream_addr", "some", ""]));
+        assert_eq!(FilterArgs::from_conditions("path").unwrap_err(), "expected `field op value`, got \"path\"");
+    }
+
+    #[test]
+    fn exclusions_are_explained_and_errors_name_the_group() {
+        let (_, explanation) = build_entry_filter(&["-s", "neq", "404", "--exclude", "path starts_with /static/, status eq 200"]).unwrap();
+        assert_eq!(
+            explanation.to_string(),
+            "Entries must match all of:\n\
+             \x20 status_code  neq 404 (Not Found)     from --status-code\n\
+             Except entries matching all of:\n\
+             \x20 status_code  eq 200 (OK)             from --exclude\n\
+             \x20 path         starts_with \"/static/\"  from --exclude\n"
+        );
+        assert_eq!(
+            build_entry_filter(&["--all", "--exclude", "referrer none", "--exclude", "ip eq 1.2.3"]).err().unwrap(),
+            "--exclude #2 ip: invalid value \"1.2.3\" for eq: invalid IP address syntax\n  valid operators: eq, neq, in"
+        );
+    }
+
+    #[test]
+    fn rejections_name_the_first_failing_filter() {
+        let (filter, _) = build_entry_filter(&[
+            "-s", "eq", "404", "-u", "contains", "python", "--field", "upstream_addr", "some", "", "--exclude", "path eq /.env",
+        ])
+        .unwrap();
+        let entry = |status: u16, agent: &str, path: &str, upstream: bool| {
+            let mut entry = Entry {
+                status_code: StatusCode::from_u16(status).ok(),
+                user_agent: Some(agent.to_string()),
+                path: Some(path.to_string()),
+                ..Entry::default()
+            };
+            if upstream {
+                entry.extras.insert("upstream_addr".to_string(), FieldValue::Text("10.0.0.1:80".to_string()));
+            }
+            entry
+        };
+        for (entry, rejection) in [
+            (entry(200, "curl", "/", true), Some("user_agent")),
+            (entry(200, "python", "/", true), Some("status_code")),
+            (entry(404, "python", "/", false), Some("upstream_addr")),
+            (entry(404, "python", "/.env", true), Some("exclude")),
+            (entry(404, "python", "/", true), None),
+        ] {
+            assert_eq!(filter.rejection(&entry).as_deref(), rejection);
+            assert_eq!(entry.is_match(&filter), rejection.is_none());
+        }
+    }
+
+    #[test]
+    fn every_listed_field_operator_parses() {
+        for op in FIELD_OPERATORS.iter().chain(PRESENCE_OPERATORS) {
+            assert!(parse_field_filter(args(&[op, "x"])).is_ok(), "{}", op);
+        }
+    }
+
+    #[test]
+    fn try_from_filter_args() {
+        let cli = TestCli::try_parse_from(["test", "-s", "eq", "500", "--field", "upstream", "gt", "2"]).unwrap();
+        let filter = LogFilter::try_from(cli.filter).unwrap();
+        assert_eq!(filter.extras.generic, vec!["upstream".to_string()]);
+        assert!(matches!(filter.status_code, OrdFilter::Eq(status) if status == StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    #[test]
+    fn status_code_errors() {
+        let operators = "valid operators: eq, neq, gt, lt, gte, lte";
+        assert_eq!(error(&["-s", "in", "500"]), format!("--status-code: unknown operator \"in\"\n  {}", operators));
+        assert_eq!(
+            error(&["-s", "eq", "5000"]),
+            format!("--status-code: invalid value \"5000\" for eq: invalid status code\n  {}", operators)
+        );
+        assert_eq!(
+            error(&["-s", "gte", "internal_server_eror"]),
+            format!(
+                "--status-code: invalid value \"internal_server_eror\" for gte: unknown status name \"internal_server_eror\"; \
+                 did you mean internal_server_error, already_reported, bad_request?\n  {}",
+                operators
+            )
+        );
+        assert_eq!(error(&["-s", "eq"]), format!("--status-code: operator eq needs a value\n  {}", operators));
+    }
+
+    #[test]
+    fn ip_errors() {
+        assert_eq!(
+            error(&["-i", "eq", "1.2.3"]),
+            "--ip: invalid value \"1.2.3\" for eq: invalid IP address syntax\n  valid operators: eq, neq, in"
+        );
+        assert_eq!(
+            error(&["-i", "none"]),
+            "--ip: operator none is not supported, this field is present in every entry\n  valid operators: eq, neq, in"
+        );
+    }
+
+    #[test]
+    fn timestamp_errors() {
+        assert_eq!(
+            error(&["-t", ">=", "2023-02-12"]),
+            "--timestamp: unknown operator \">=\"\n  did you mean gte?\n  valid operators: eq, neq, gt, lt, gte, lte"
+        );
+        assert_eq!(
+            error(&["-t", "gt", "2023-02-12"]),
+            "--timestamp: invalid value \"2023-02-12\" for gt: premature end of input\n  valid operators: eq, neq, gt, lt, gte, lte"
+        );
+    }
+
+    #[test]
+    fn user_agent_errors() {
+        assert_eq!(
+            error(&["-u", "startswith", "Mozilla"]),
+            "--user-agent: unknown operator \"startswith\"\n  did you mean starts_with?\n  valid operators: eq, contains, starts_with, ends_with, in, contains_any, not_contains_any, fuzzy, fuzzy_eq, none, some"
+        );
+    }
+
+    #[test]
+    fn severity_and_duration_errors() {
+        assert_eq!(
+            error(&["--severity", "gte", "loud"]),
+            "--severity: invalid value \"loud\" for gte: Invalid severity: loud\n  valid operators: eq, neq, gt, lt, gte, lte, none, some"
+        );
+        assert_eq!(
+            error(&["-d", "gt", "5mins"]),
+            "--duration: invalid value \"5mins\" for gt: invalid duration \"5mins\": unknown unit \"mins\", expected h, m, s, ms, us or ns\n  valid operators: eq, neq, gt, lt, gte, lte, none, some"
+        );
+    }
+
+    #[test]
+    fn text_errors() {
+        let operators =
+            "valid operators: eq, contains, starts_with, ends_with, in, contains_any, not_contains_any, fuzzy, fuzzy_eq, none, some";
+        assert_eq!(error(&["--path", "eq"]), format!("--path: operator eq needs a value\n  {}", operators));
+        assert_eq!(error(&["--referrer", "in"]), format!("--referrer: operator in needs a value\n  {}", operators));
+        assert_eq!(
+            error(&["--raw-request", "contain", "\\x16"]),
+            format!("--raw-request: unknown operator \"contain\"\n  did you mean contains?\n  {}", operators)
+        );
+    }
+
+    #[test]
+    fn string_errors() {
+        let operators = "valid operators: eq, contains, starts_with, ends_with, none, some";
+        assert_eq!(error(&["--backend", "gt", "x"]), format!("--backend: unknown operator \"gt\"\n  {}", operators));
+        assert_eq!(
+            error(&["--host", "startswith", "web"]),
+            format!("--host: unknown operator \"startswith\"\n  did you mean starts_with?\n  {}", operators)
+        );
+        assert_eq!(
+            error(&["--client", "neq", "x"]),
+            format!("--client: unknown operator \"neq\"\n  did you mean eq?\n  {}", operators)
+        );
+    }
+
+    #[test]
+    fn numeric_errors() {
+        let operators = "valid operators: eq, neq, gt, lt, gte, lte, none, some";
+        assert_eq!(
+            error(&["--port", "eq", "99999"]),
+            format!("--port: invalid value \"99999\" for eq: number too large to fit in target type\n  {}", operators)
+        );
+        assert_eq!(
+            error(&["--bytes", "gt", "lots"]),
+            format!(
+                "--bytes: invalid value \"lots\" for gt: invalid size \"lots\": expected a number of bytes with an optional \
+                 suffix: k, M, G or T (powers of 1024, also written KiB, MiB, GiB, TiB) or kB, MB, GB, TB (powers of 1000), \
+                 e.g. 512k, 1.5M, 100MB or 1,048,576\n  {}",
+                operators
+            )
+        );
+        assert_eq!(
+            error(&["--tls-protocol", "gte", "tls9"]),
+            format!(
+                "--tls-protocol: invalid value \"tls9\" for gte: Invalid TLS protocol: tls9, \
+                 expected one of SSLv3, TLSv1, TLSv1.1, TLSv1.2, TLSv1.3\n  {}",
+                operators
+            )
+        );
+    }
+
+    #[test]
+    fn field_errors() {
+        assert_eq!(
+            error(&["--field", "upstream", "contians", "x"]),
+            "--field: unknown operator \"contians\"\n  did you mean contains?\n  valid operators: eq, neq, gt, lt, gte, lte, contains, starts_with, ends_with, none, some"
+        );
+        assert_eq!(
+            error(&["--response-time", "gt", "fast"]),
+            "--response-time: invalid value \"fast\" for gt: invalid float literal\n  valid operators: eq, neq, gt, lt, gte, lte, none, some"
+        );
+    }
+
+    #[test]
+    fn far_off_operators_get_no_suggestion() {
+        assert!(!error(&["-u", "xyz", "a"]).contains("did you mean"));
+    }
+
+    #[test]
+    fn explanation_lists_sources() {
+        let (_, explanation) = build(&["-s", "eq", "404", "--referrer", "none"]).unwrap();
+        assert_eq!(
+            explanation.to_string(),
+            "Entries must match all of:\n  status_code  eq 404 (Not Found)         from --status-code\n  referrer     none (absent, empty or -)  from --referrer\n"
+        );
+        let (_, explanation) = build(&[]).unwrap();
+        assert_eq!(explanation.to_string(), "No filters: every parsed entry matches\n");
+    }
+
+    #[test]
+    fn given_filters_drop_the_explanatory_notes() {
+        let argv = [
+            "-s", "gte", "500", "--ip", "in", "10.0.0.0/8", "--user-agent", "eq", "a (b)", "--bytes", "gt", "2048",
+            "--referrer", "none", "--where", "tr > 1 and bytes > 0", "--exclude", "path contains /health",
+        ];
+        let (filter, _) = build_entry_filter(&argv).ok().unwrap();
+        let given = filter.given();
+        let given: Vec<(&str, &str)> = given.iter().map(|g| (g.field.as_str(), g.condition.as_str())).collect();
+        assert_eq!(
+            given,
+            [
+                ("status_code", "gte 500"),
+                ("user_agent", "eq \"a (b)\""),
+                ("referrer", "none"),
+                ("ip", "in 10.0.0.0/8"),
+                ("bytes", "gt 2048"),
+                ("tr", "where (tr > 1) and (bytes > 0)"),
+                ("bytes", "where (tr > 1) and (bytes > 0)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn presence_treats_dash_and_empty_as_missing() {
+        let missing: Presence<StringFilter> = Presence::Missing;
+        assert!(None::<String>.is_match(&missing));
+        assert!(Some("-".to_string()).is_match(&missing));
+        assert!(Some(String::new()).is_match(&missing));
+        assert!(!Some("curl".to_string()).is_match(&missing));
+        assert!(Some("curl".to_string()).is_match(&Presence::<StringFilter>::Present));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::expression::Expression;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+// --filter-file keeps `--where` expressions in a file, one per line, which entries must all
+// satisfy; blank lines and lines starting with `#` are skipped, e.g.
+//
+//     # slow or failing API calls
+//     status >= 500 or duration > 2
+//     bytes > 0
+//
+// With --follow-glob, --reload-on-change looks at the file's modification time and size before
+// each poll, and once they change and every expression in it parses, they replace the ones
+// read before for the lines that follow. While it doesn't parse, as when an editor has saved
+// half of it, the ones read before stay in use, so the session and its place in the files
+// carry on.
+
+/// The expressions of a filter file, in the order of its lines.
+pub struct Conditions(pub Vec<Expression>);
+
+impl Conditions {
+    pub fn read(path: &Path) -> Result<Conditions, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+        let expressions = lines
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line_no, line)| line.parse().map_err(|e| format!("{}: line {}: {}", path.display(), line_no, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Conditions(expressions))
+    }
+
+    /// The expressions as --explain-filter prints them.
+    pub fn describe(&self) -> Vec<String> {
+        self.0.iter().map(Expression::to_string).collect()
+    }
+}
+
+/// What a filter file looked like when it was last read, to tell when it changes.
+pub struct Watch {
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+}
+
+impl Watch {
+    pub fn new(path: PathBuf) -> Self {
+        let stamp = stamp(&path);
+        Watch { path, stamp }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The file's conditions if it changed since it was last read, or why they can't be used.
+    pub fn changed(&mut self) -> Option<Result<Conditions, String>> {
+        let stamp = stamp(&self.path);
+        if stamp.is_none() || stamp == self.stamp {
+            return None;
+        }
+        self.stamp = stamp;
+        Some(Conditions::read(&self.path))
+    }
+}
+
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_read_once_and_bad_ones_reported() {
+        let dir = std::env::temp_dir().join(format!("log-parser-filter-file-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("filters.txt");
+        fs::write(&path, "# server errors\nstatus >= 500\n").unwrap();
+        assert_eq!(Conditions::read(&path).unwrap().describe(), ["status >= 500"]);
+        let mut watch = Watch::new(path.clone());
+        assert!(watch.changed().is_none());
+
+        fs::write(&path, "status >= 400\n\nbytes > 0\n").unwrap();
+        let conditions = watch.changed().unwrap().unwrap();
+        assert_eq!(conditions.describe(), ["status >= 400", "bytes > 0"]);
+        assert!(watch.changed().is_none());
+
+        fs::write(&path, "status >=\n").unwrap();
+        let error = watch.changed().unwrap().err().unwrap();
+        assert!(error.ends_with("filters.txt: line 1: expected a field, a number or `(` at the end"), "{}", error);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
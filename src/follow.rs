@@ -0,0 +1,317 @@
+use crate::diagnostics::{Error, Notice, Reporter};
+use crate::entry::Provenance;
+use crate::input;
+use std::{
+    collections::HashMap,
+    fs::{self, File, Metadata},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+// --follow-glob tails the newest file matching a pattern, polled rather than watched so it works
+// the same everywhere. Following starts at the end of the newest file. A file that starts
+// matching later, such as the access.log logrotate creates after renaming the old one, is
+// followed from its start, and the file followed until then is read to its end first, and again
+// on each poll until it stops growing, for what its writer logs before reopening. Files are told
+// apart by identity rather than name, so a renamed file isn't mistaken for a new one, and each
+// one's offset is kept so no line is passed on twice.
+
+/// A file's device and inode, which survive renames.
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+/// Without inodes, a file is known by its name.
+#[cfg(not(unix))]
+type FileId = PathBuf;
+
+#[cfg(unix)]
+fn file_id(_path: &Path, metadata: &Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(path: &Path, _metadata: &Metadata) -> FileId {
+    path.to_path_buf()
+}
+
+/// Bytes read from a file at a time.
+const CHUNK: usize = 64 * 1024;
+
+/// An open file read from where the last read stopped.
+struct Tailed {
+    /// The name the file had when it was opened.
+    path: PathBuf,
+    id: FileId,
+    file: File,
+    /// Bytes read so far, including the start of an unfinished line.
+    offset: u64,
+    /// Where the unfinished line starts.
+    start: u64,
+    /// Complete lines read so far.
+    line: usize,
+    /// The unfinished line.
+    partial: Vec<u8>,
+    /// Whether the unfinished line ran past the longest line kept, and is being skipped.
+    too_long: bool,
+}
+
+impl Tailed {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = File::open(&path)?;
+        let id = file_id(&path, &file.metadata()?);
+        Ok(Tailed { path, id, file, offset: 0, start: 0, line: 0, partial: Vec::new(), too_long: false })
+    }
+
+    fn io_error(&self, e: io::Error) -> Error {
+        Error::Io { file: self.path.clone(), message: e.to_string() }
+    }
+
+    /// Reads what was appended since the last read, passing on each complete line, or why it
+    /// couldn't be kept; returns how many bytes were read. A file that shrank was truncated in
+    /// place, as copytruncate does, and is read again from its start.
+    fn read(
+        &mut self,
+        max_line_length: usize,
+        on_line: &mut impl FnMut(Provenance, Result<String, String>) -> Result<(), Error>,
+    ) -> Result<u64, Error> {
+        let length = self.file.metadata().map_err(|e| self.io_error(e))?.len();
+        if length < self.offset {
+            (self.offset, self.start, self.line, self.too_long) = (0, 0, 0, false);
+            self.partial.clear();
+        }
+        self.file.seek(SeekFrom::Start(self.offset)).map_err(|e| self.io_error(e))?;
+        let mut chunk = vec![0; CHUNK];
+        let mut read = 0;
+        loop {
+            let n = match self.file.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(self.io_error(e)),
+            };
+            read += n as u64;
+            for piece in chunk[..n].split_inclusive(|&b| b == b'\n') {
+                self.offset += piece.len() as u64;
+                if !self.too_long {
+                    self.partial.extend_from_slice(piece);
+                    if self.partial.len() > max_line_length + 1 {
+                        self.too_long = true;
+                    }
+                }
+                if piece.last() != Some(&b'\n') {
+                    continue;
+                }
+                self.line += 1;
+                let provenance = Provenance { source_file: self.path.clone(), line_no: self.line, byte_offset: self.start };
+                let line = match self.too_long {
+                    true => Err(format!(
+                        "line at byte offset {} is {} bytes, over --max-line-length {}",
+                        self.start,
+                        self.offset - self.start - 1,
+                        max_line_length
+                    )),
+                    false => {
+                        self.partial.pop();
+                        let mut line = String::from_utf8(std::mem::take(&mut self.partial))
+                            .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+                        input::trim_line_ending(&mut line);
+                        Ok(line)
+                    }
+                };
+                self.partial.clear();
+                (self.start, self.too_long) = (self.offset, false);
+                on_line(provenance, line)?;
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Follows the newest file matching a pattern, across rotations.
+pub struct FollowGlob {
+    pattern: String,
+    max_line_length: usize,
+    current: Option<Tailed>,
+    /// The file rotated away from, read until a poll finds nothing new in it.
+    draining: Option<Tailed>,
+    /// How far every other matching file seen so far was read, or how long it was when it was
+    /// passed over. A matching file that isn't here is new.
+    offsets: HashMap<FileId, u64>,
+}
+
+impl FollowGlob {
+    /// Starts at the end of the newest matching file, if any matches yet; the others are left
+    /// alone.
+    pub fn new(pattern: &str, max_line_length: usize) -> Result<Self, Error> {
+        let mut follow =
+            FollowGlob { pattern: pattern.to_string(), max_line_length, current: None, draining: None, offsets: HashMap::new() };
+        if let Some(path) = follow.fresh()? {
+            let mut newest = Tailed::open(path.clone()).map_err(|e| Error::Io { file: path, message: e.to_string() })?;
+            newest.read(max_line_length, &mut |_, _| Ok(()))?;
+            follow.current = Some(newest);
+        }
+        Ok(follow)
+    }
+
+    /// The newest matching file not seen before. Any others are passed over, and remembered as
+    /// seen at their current length.
+    fn fresh(&mut self) -> Result<Option<PathBuf>, Error> {
+        let mut fresh: Vec<(Option<SystemTime>, PathBuf, FileId, u64)> = Vec::new();
+        for path in input::glob(&self.pattern)? {
+            // A file removed since the directory was listed is simply gone.
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let id = file_id(&path, &metadata);
+            let followed = [&self.current, &self.draining].into_iter().flatten().any(|tailed| tailed.id == id);
+            // A file shorter than it was read can only be a new one given a freed inode.
+            let seen = self.offsets.get(&id).is_some_and(|&offset| metadata.len() >= offset);
+            if metadata.is_file() && !followed && !seen {
+                fresh.push((metadata.modified().ok(), path, id, metadata.len()));
+            }
+        }
+        fresh.sort();
+        let newest = fresh.pop();
+        for (_, _, id, length) in fresh {
+            self.offsets.insert(id, length);
+        }
+        Ok(newest.map(|(_, path, ..)| path))
+    }
+
+    /// Passes on the lines appended since the last poll: what's left of a rotated file first,
+    /// then the followed file's, switching to a new matching file when one appears.
+    pub fn poll(
+        &mut self,
+        reporter: &Reporter,
+        mut on_line: impl FnMut(Provenance, Result<String, String>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if let Some(mut draining) = self.draining.take() {
+            match draining.read(self.max_line_length, &mut on_line)? {
+                0 => {
+                    self.offsets.insert(draining.id, draining.offset);
+                }
+                _ => self.draining = Some(draining),
+            }
+        }
+        if let Some(current) = &mut self.current {
+            current.read(self.max_line_length, &mut on_line)?;
+        }
+        let Some(path) = self.fresh()? else {
+            return Ok(());
+        };
+        let next = match Tailed::open(path.clone()) {
+            Ok(next) => next,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(Error::Io { file: path, message: e.to_string() }),
+        };
+        let previous = self.current.replace(next);
+        reporter.notice(Notice::Following { file: path, previous: previous.as_ref().map(|tailed| tailed.path.clone()) });
+        if let Some(done) = previous.and_then(|previous| self.draining.replace(previous)) {
+            self.offsets.insert(done.id, done.offset);
+        }
+        if let Some(current) = &mut self.current {
+            current.read(self.max_line_length, &mut on_line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::ErrorFormat;
+    use std::{
+        fs::OpenOptions,
+        io::Write,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc, Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    fn append(path: &Path, text: &str) {
+        OpenOptions::new().create(true).append(true).open(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("log-parser-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn lines_are_passed_on_once_they_end() {
+        let dir = temp_dir("follow-lines");
+        let path = dir.join("access.log");
+        append(&path, "one\r\ntw");
+        let mut tailed = Tailed::open(path.clone()).unwrap();
+        let mut lines = Vec::new();
+        let mut collect = |provenance: Provenance, line: Result<String, String>| {
+            lines.push((provenance.line_no, provenance.byte_offset, line));
+            Ok(())
+        };
+        tailed.read(8, &mut collect).map_err(|e| e.to_string()).unwrap();
+        append(&path, "o\nmuch too long\nthree\n");
+        tailed.read(8, &mut collect).map_err(|e| e.to_string()).unwrap();
+        assert_eq!(lines[0], (1, 0, Ok("one".to_string())));
+        assert_eq!(lines[1], (2, 5, Ok("two".to_string())));
+        assert!(lines[2].2.as_ref().is_err_and(|reason| reason.contains("is 13 bytes")));
+        assert_eq!(lines[3], (4, 23, Ok("three".to_string())));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotated_and_recreated_files_are_followed_without_repeats() {
+        let dir = temp_dir("follow-rotate");
+        let path = dir.join("access.log");
+        append(&path, "before\n");
+        append(&dir.join("access.log.1"), "older\n");
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        File::options().append(true).open(dir.join("access.log.1")).unwrap().set_modified(an_hour_ago).unwrap();
+        let mut follow = FollowGlob::new(&format!("{}/access.log*", dir.display()), 1024).map_err(|e| e.to_string()).unwrap();
+
+        let (send, receive) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let follower = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let reporter = Reporter::new(ErrorFormat::Human);
+                while !stop.load(Ordering::Relaxed) {
+                    follow
+                        .poll(&reporter, |_, line| {
+                            send.send(line.unwrap()).unwrap();
+                            Ok(())
+                        })
+                        .map_err(|e| e.to_string())
+                        .unwrap();
+                    thread::sleep(Duration::from_millis(5));
+                }
+            })
+        };
+        let next = || receive.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        append(&path, "a\n");
+        assert_eq!(next(), "a");
+        // Rotation: the old file is renamed and logged to once more before the new one appears.
+        fs::rename(&path, dir.join("access.log.2")).unwrap();
+        append(&dir.join("access.log.2"), "b\n");
+        append(&path, "c\n");
+        assert_eq!([next(), next()], ["b", "c"]);
+        // Removed and created again under the same name.
+        fs::remove_file(&path).unwrap();
+        append(&path, "d\n");
+        assert_eq!(next(), "d");
+        append(&path, "e\n");
+        assert_eq!(next(), "e");
+
+        stop.store(true, Ordering::Relaxed);
+        follower.join().unwrap();
+        assert!(receive.try_recv().is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
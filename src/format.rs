@@ -0,0 +1,510 @@
+use crate::duration::HumanDuration;
+use crate::entry::{Client, Entry, FieldValue};
+use crate::log_time;
+use crate::tls::TlsVersion;
+use chrono::DateTime;
+use std::str::FromStr;
+
+// Compiles nginx-style (`$remote_addr [$time_local] "$request"`) or Apache-style
+// (`%h [%t] "%r"`) log format strings into a line parser. Literal text between
+// variables anchors the match; each variable captures a typed field of `Entry`
+// or, when it has no dedicated slot, an entry in `Entry::extras`.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Ip,
+    Ident,
+    User,
+    TimeLocal,
+    TimeBracketed,
+    TimeIso8601,
+    Request,
+    Method,
+    Path,
+    Protocol,
+    Status,
+    Bytes,
+    Referrer,
+    UserAgent,
+    Scheme,
+    Extra(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone)]
+pub struct LogFormat {
+    tokens: Vec<Token>,
+}
+
+fn nginx_field(name: &str) -> Field {
+    match name {
+        "remote_addr" => Field::Ip,
+        "remote_user" => Field::User,
+        "time_local" => Field::TimeLocal,
+        "time_iso8601" => Field::TimeIso8601,
+        "request" => Field::Request,
+        "request_method" => Field::Method,
+        "request_uri" | "uri" => Field::Path,
+        "server_protocol" => Field::Protocol,
+        "status" => Field::Status,
+        "body_bytes_sent" => Field::Bytes,
+        "http_referer" => Field::Referrer,
+        "http_user_agent" => Field::UserAgent,
+        "scheme" => Field::Scheme,
+        other => Field::Extra(other.to_string()),
+    }
+}
+
+fn header_name(name: &str) -> String {
+    name.to_lowercase().replace('-', "_")
+}
+
+fn apache_field(directive: char, arg: Option<&str>) -> Result<Field, String> {
+    let field = match (directive, arg) {
+        ('h' | 'a', None) => Field::Ip,
+        ('l', None) => Field::Ident,
+        ('u', None) => Field::User,
+        ('t', None) => Field::TimeBracketed,
+        ('r', None) => Field::Request,
+        ('m', None) => Field::Method,
+        ('U', None) => Field::Path,
+        ('H', None) => Field::Protocol,
+        ('s', None) => Field::Status,
+        ('b' | 'B', None) => Field::Bytes,
+        ('q', None) => Field::Extra("query_string".to_string()),
+        ('D', None) => Field::Extra("request_time_us".to_string()),
+        ('T', None) => Field::Extra("request_time".to_string()),
+        ('v' | 'V', None) => Field::Extra("server_name".to_string()),
+        ('p', None) => Field::Extra("server_port".to_string()),
+        ('P', None) => Field::Extra("pid".to_string()),
+        ('X', None) => Field::Extra("connection_status".to_string()),
+        ('I', None) => Field::Extra("bytes_received".to_string()),
+        ('O', None) => Field::Extra("bytes_sent".to_string()),
+        ('i', Some(name)) if name.eq_ignore_ascii_case("referer") => Field::Referrer,
+        ('i', Some(name)) if name.eq_ignore_ascii_case("user-agent") => Field::UserAgent,
+        ('i', Some(name)) => Field::Extra(format!("http_{}", header_name(name))),
+        ('o', Some(name)) => Field::Extra(format!("sent_http_{}", header_name(name))),
+        ('C', Some(name)) => Field::Extra(format!("cookie_{}", header_name(name))),
+        ('e', Some(name)) => Field::Extra(header_name(name)),
+        (d, Some(arg)) => return Err(format!("Unsupported format directive %{{{}}}{}", arg, d)),
+        (d, None) => return Err(format!("Unsupported format directive %{}", d)),
+    };
+    Ok(field)
+}
+
+fn is_var_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn is_nginx_syntax(format: &str) -> bool {
+    format
+        .split('$')
+        .skip(1)
+        .any(|rest| rest.starts_with(|c: char| is_var_char(c) || c == '{'))
+}
+
+fn push_literal(tokens: &mut Vec<Token>, c: char) {
+    if let Some(Token::Literal(literal)) = tokens.last_mut() {
+        literal.push(c);
+    } else {
+        tokens.push(Token::Literal(c.to_string()));
+    }
+}
+
+fn tokenize_nginx(format: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = format.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != '$' {
+            push_literal(&mut tokens, c);
+            continue;
+        }
+        let name = if format[i + 1..].starts_with('{') {
+            let end = format[i + 2..]
+                .find('}')
+                .ok_or_else(|| format!("Unterminated variable in format at offset {}", i))?;
+            let name = &format[i + 2..i + 2 + end];
+            while chars.next_if(|&(j, _)| j <= i + 2 + end).is_some() {}
+            name
+        } else {
+            let len = format[i + 1..].find(|c: char| !is_var_char(c)).unwrap_or(format.len() - i - 1);
+            while chars.next_if(|&(j, _)| j <= i + len).is_some() {}
+            &format[i + 1..i + 1 + len]
+        };
+        if name.is_empty() {
+            push_literal(&mut tokens, '$');
+        } else {
+            tokens.push(Token::Field(nginx_field(name)));
+        }
+    }
+    Ok(tokens)
+}
+
+fn tokenize_apache(format: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            push_literal(&mut tokens, c);
+            continue;
+        }
+        if chars.next_if_eq(&'%').is_some() {
+            push_literal(&mut tokens, '%');
+            continue;
+        }
+        while chars.next_if(|&c| c == '>' || c == '<').is_some() {}
+        let arg = if chars.next_if_eq(&'{').is_some() {
+            let arg: String = chars.by_ref().take_while(|&c| c != '}').collect();
+            Some(arg)
+        } else {
+            None
+        };
+        let directive = chars.next().ok_or("Format string ends with an incomplete % directive")?;
+        tokens.push(Token::Field(apache_field(directive, arg.as_deref())?));
+    }
+    Ok(tokens)
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        let tokens = if is_nginx_syntax(format) {
+            tokenize_nginx(format)?
+        } else {
+            tokenize_apache(format)?
+        };
+        if !tokens.iter().any(|t| matches!(t, Token::Field(_))) {
+            return Err("Format string contains no variables".to_string());
+        }
+        if let Some(pair) = tokens
+            .windows(2)
+            .find(|pair| matches!(pair, [Token::Field(_), Token::Field(_)]))
+        {
+            return Err(format!(
+                "Format variables {:?} and {:?} must be separated by literal text",
+                pair[0], pair[1]
+            ));
+        }
+        Ok(LogFormat { tokens })
+    }
+}
+
+/// Finds where a captured value ends: the first occurrence of the following literal,
+/// skipping backslash-escaped quotes when the literal starts with a quote. A quote is escaped
+/// when an odd number of backslashes precede it, so `\\\"` is an escaped backslash and quote.
+fn value_end(rest: &str, next: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(pos) = rest[from..].find(next).map(|p| p + from) {
+        let backslashes = rest[..pos].bytes().rev().take_while(|&b| b == b'\\').count();
+        if !next.starts_with('"') || backslashes % 2 == 0 {
+            return Some(pos);
+        }
+        from = pos + 1;
+    }
+    None
+}
+
+/// Undoes `\"` and `\\` in one pass; other backslashes are kept as logged.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match chars.clone().next() {
+            Some(next @ ('"' | '\\')) if c == '\\' => {
+                unescaped.push(next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn optional(value: &str) -> Option<String> {
+    (!value.is_empty() && value != "-").then(|| value.to_string())
+}
+
+fn invalid(field: &Field, value: &str) -> String {
+    format!("Invalid value for {:?}: {}", field, value)
+}
+
+impl Field {
+    /// The entry fields this variable sets.
+    fn names(&self) -> Vec<String> {
+        let names: &[&str] = match self {
+            Field::Ip => &["ip"],
+            Field::Ident => &[],
+            Field::User => &["user"],
+            Field::TimeLocal | Field::TimeBracketed | Field::TimeIso8601 => &["timestamp"],
+            Field::Request => &["method", "path", "protocol"],
+            Field::Method => &["method"],
+            Field::Path => &["path"],
+            Field::Protocol => &["protocol"],
+            Field::Status => &["status_code"],
+            Field::Bytes => &["bytes"],
+            Field::Referrer => &["referrer"],
+            Field::UserAgent => &["user_agent"],
+            Field::Scheme => &["scheme"],
+            Field::Extra(name) if name == "request_time" || name == "request_time_us" => {
+                return vec![name.clone(), "duration".to_string()]
+            }
+            Field::Extra(name) if name == "server_port" => return vec![name.clone(), "port".to_string()],
+            Field::Extra(name) if name == "ssl_protocol" => return vec![name.clone(), "tls_protocol".to_string()],
+            Field::Extra(name) => return vec![name.clone()],
+        };
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn apply(&self, value: &str, entry: &mut Entry) -> Result<(), String> {
+        match self {
+            Field::Ip => entry.client = Some(value.parse::<Client>().map_err(|_| invalid(self, value))?),
+            Field::Ident => {}
+            Field::User => entry.user = optional(value),
+            Field::TimeLocal => {
+                entry.timestamp = Some(log_time::parse(value).map_err(|_| invalid(self, value))?)
+            }
+            Field::TimeBracketed => {
+                let inner = value
+                    .strip_prefix('[')
+                    .and_then(|v| v.strip_suffix(']'))
+                    .ok_or_else(|| invalid(self, value))?;
+                Field::TimeLocal.apply(inner, entry)?
+            }
+            Field::TimeIso8601 => {
+                entry.timestamp = Some(DateTime::parse_from_rfc3339(value).map_err(|_| invalid(self, value))?)
+            }
+            Field::Request => entry.set_request(value),
+            Field::Method => entry.method = optional(value),
+            Field::Path => entry.path = optional(value),
+            Field::Protocol => entry.protocol = optional(value),
+            Field::Status => entry.status_code = Some(value.parse().map_err(|_| invalid(self, value))?),
+            Field::Bytes if value == "-" => entry.bytes = None,
+            Field::Bytes => entry.set_bytes(value, || invalid(self, value))?,
+            Field::Referrer => entry.referrer = optional(value),
+            Field::UserAgent => entry.user_agent = optional(value),
+            Field::Scheme => entry.scheme = optional(value),
+            Field::Extra(name) => {
+                if let Some(value) = FieldValue::parse(value) {
+                    let seconds = value.as_number();
+                    match name.as_str() {
+                        "request_time" => entry.duration = seconds.and_then(HumanDuration::from_secs_f64),
+                        "request_time_us" => entry.duration = seconds.and_then(HumanDuration::from_micros_f64),
+                        "server_port" => entry.port = value.as_text().parse().ok(),
+                        "ssl_protocol" => entry.tls_protocol = TlsVersion::parse_logged(&value.as_text()),
+                        _ => {}
+                    }
+                    entry.extras.insert(name.clone(), value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LogFormat {
+    /// The fields entries parsed with this format can have, in the order the format sets them.
+    pub fn fields(&self) -> Vec<String> {
+        let mut fields = Vec::new();
+        for token in &self.tokens {
+            let Token::Field(field) = token else {
+                continue;
+            };
+            for name in field.names() {
+                if !fields.contains(&name) {
+                    fields.push(name);
+                }
+            }
+        }
+        fields
+    }
+
+    pub fn records_duration(&self) -> bool {
+        self.tokens.iter().any(|t| {
+            matches!(t, Token::Field(Field::Extra(name)) if name == "request_time" || name == "request_time_us")
+        })
+    }
+
+    pub fn parse(&self, line: &str) -> Result<Entry, String> {
+        let mut entry = Entry::default();
+        let mut rest = line;
+        for (i, token) in self.tokens.iter().enumerate() {
+            match token {
+                Token::Literal(literal) => {
+                    rest = rest
+                        .strip_prefix(literal.as_str())
+                        .ok_or_else(|| format!("Expected {:?} at: {}", literal, rest))?;
+                }
+                Token::Field(field) => {
+                    let quoted = i > 0 && matches!(&self.tokens[i - 1], Token::Literal(l) if l.ends_with('"'));
+                    let end = match (field, self.tokens.get(i + 1)) {
+                        (Field::TimeBracketed, _) => rest.find(']').map(|p| p + 1),
+                        (_, Some(Token::Literal(next))) => value_end(rest, next),
+                        _ => Some(rest.len()),
+                    }
+                    .ok_or_else(|| format!("Could not find end of {:?} in: {}", field, rest))?;
+                    let value = &rest[..end];
+                    if quoted {
+                        field.apply(&unescape(value), &mut entry)?;
+                    } else {
+                        field.apply(value, &mut entry)?;
+                    }
+                    rest = &rest[end..];
+                }
+            }
+        }
+        if !rest.trim_end().is_empty() {
+            return Err(format!("Unexpected trailing input: {}", rest));
+        }
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn nginx_and_apache_syntax_compile_to_the_same_tokens() {
+        let nginx: LogFormat = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent"#.parse().unwrap();
+        let apache: LogFormat = r#"%h - %u %t "%r" %>s %b"#.parse().unwrap();
+        let line = r#"10.0.0.1 - bob [12/Feb/2023:14:03:45 +0000] "GET /a HTTP/1.1" 200 123"#;
+        let (a, b) = (nginx.parse(line).unwrap(), apache.parse(line).unwrap());
+        assert_eq!(a.user.as_deref(), Some("bob"));
+        assert_eq!(a.path, b.path);
+        assert_eq!(a.timestamp, b.timestamp);
+        assert_eq!(a.bytes, Some(123));
+    }
+
+    #[test]
+    fn extras_and_durations() {
+        let format: LogFormat = r#"$remote_addr "${http_x_forwarded_for}" %D $request_time"#.parse().unwrap();
+        let entry = format.parse(r#"10.0.0.1 "203.0.113.9" %D 0.250"#).unwrap();
+        assert_eq!(entry.extras["http_x_forwarded_for"], FieldValue::Text("203.0.113.9".to_string()));
+        assert_eq!(entry.duration, HumanDuration::from_millis_f64(250.0));
+        assert!(format.records_duration());
+        assert_eq!(format.fields(), ["ip", "http_x_forwarded_for", "request_time", "duration"]);
+
+        let format: LogFormat = "%h %D".parse().unwrap();
+        assert_eq!(format.parse("10.0.0.1 1500").unwrap().duration, HumanDuration::from_micros_f64(1500.0));
+    }
+
+    #[test]
+    fn scheme_port_and_tls_protocol() {
+        let format: LogFormat = "$remote_addr $scheme $server_port $ssl_protocol".parse().unwrap();
+        let entry = format.parse("10.0.0.1 https 443 TLSv1.3").unwrap();
+        assert_eq!(entry.scheme.as_deref(), Some("https"));
+        assert_eq!(entry.port, Some(443));
+        assert_eq!(entry.tls_protocol, Some(TlsVersion::Tls1_3));
+        assert_eq!(format.fields(), ["ip", "scheme", "server_port", "port", "ssl_protocol", "tls_protocol"]);
+        assert_eq!(format.parse("10.0.0.1 http 80 -").unwrap().tls_protocol, None);
+
+        let vhost_combined: LogFormat = "%v:%p %h".parse().unwrap();
+        let entry = vhost_combined.parse("www.example.com:8080 10.0.0.1").unwrap();
+        assert_eq!(entry.port, Some(8080));
+        assert!(entry.scheme.is_none());
+    }
+
+    #[test]
+    fn escaped_quotes_inside_quoted_values() {
+        let format: LogFormat = r#"$remote_addr "$http_user_agent""#.parse().unwrap();
+        let entry = format.parse(r#"10.0.0.1 "a \"b\" \\c""#).unwrap();
+        assert_eq!(entry.user_agent.as_deref(), Some(r#"a "b" \c"#));
+        let entry = format.parse(r#"10.0.0.1 "\\\"x\\\" \d \\""#).unwrap();
+        assert_eq!(entry.user_agent.as_deref(), Some(r#"\"x\" \d \"#));
+    }
+
+    #[test]
+    fn invalid_formats() {
+        assert!("no variables here".parse::<LogFormat>().is_err());
+        assert!("$remote_addr$status".parse::<LogFormat>().is_err());
+        assert!("%h %Z".parse::<LogFormat>().is_err());
+        assert!("%h ${unterminated".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn format_matrix() {
+        // (format, line, fields of the parsed entry as JSON)
+        let cases: &[(&str, &str, Value)] = &[
+            (
+                concat!(
+                    r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "#,
+                    r#""$http_referer" "$http_user_agent" $request_time $upstream_addr"#
+                ),
+                r#"10.0.0.1 - bob [12/Feb/2023:14:03:45 +0000] "GET /a HTTP/1.1" 200 123 "-" "curl/8" 0.250 10.1.0.5:80"#,
+                json!({"ip": "10.0.0.1", "user": "bob", "timestamp": "2023-02-12T14:03:45Z", "method": "GET", "path": "/a",
+                       "status_code": 200, "bytes": 123, "referrer": null, "user_agent": "curl/8", "duration": 0.25,
+                       "request_time": 0.25, "upstream_addr": "10.1.0.5:80"}),
+            ),
+            (
+                r#"%h %l %u %t "%r" %>s %b "%{Referer}i" "%{User-agent}i""#,
+                r#"10.0.0.2 - - [12/Feb/2023:14:04:45 +0100] "POST /b HTTP/1.0" 500 - "http://x/" "Mozilla""#,
+                json!({"ip": "10.0.0.2", "user": null, "timestamp": "2023-02-12T14:04:45+01:00", "method": "POST",
+                       "protocol": "HTTP/1.0", "status_code": 500, "bytes": null, "referrer": "http://x/",
+                       "user_agent": "Mozilla"}),
+            ),
+            (
+                "${remote_addr}|${status}|${time_iso8601}",
+                "::1|404|2023-02-12T14:03:45+00:00",
+                json!({"ip": "::1", "status_code": 404, "timestamp": "2023-02-12T14:03:45Z"}),
+            ),
+            (
+                "$remote_addr\t$request_method $request_uri $server_protocol\t$status",
+                "10.0.0.3\tHEAD /c?x=1 HTTP/2.0\t304",
+                json!({"ip": "10.0.0.3", "method": "HEAD", "path": "/c?x=1", "protocol": "HTTP/2.0", "status_code": 304}),
+            ),
+            (
+                r#"%a %m %U %q %D "%{X-Forwarded-For}i" %{session}C 100%%"#,
+                r#"10.0.0.4 GET /d ?y=2 1500 "203.0.113.9" abc 100%"#,
+                json!({"ip": "10.0.0.4", "path": "/d", "query_string": "?y=2", "request_time_us": 1500, "duration": 0.0015,
+                       "http_x_forwarded_for": "203.0.113.9", "cookie_session": "abc"}),
+            ),
+            (
+                "%v:%p %h %T %B",
+                "www.example.com:8443 10.0.0.5 2 0",
+                json!({"server_name": "www.example.com", "server_port": 8443, "port": 8443, "ip": "10.0.0.5",
+                       "request_time": 2, "duration": 2.0, "bytes": 0}),
+            ),
+        ];
+        for (format, line, expected) in cases {
+            let parsed: LogFormat = format.parse().unwrap_or_else(|e| panic!("{}: {}", format, e));
+            let entry = parsed.parse(line).unwrap_or_else(|e| panic!("{}: {}", format, e));
+            let actual = serde_json::to_value(&entry).unwrap();
+            for (field, value) in expected.as_object().unwrap() {
+                assert_eq!(&actual[field], value, "{} of {:?} parsed with {:?}", field, line, format);
+            }
+        }
+    }
+
+    #[test]
+    fn format_matrix_rejections() {
+        // (format, line that doesn't fit it)
+        let cases = [
+            ("$remote_addr $status", "10.0.0.1 ok"),
+            ("$remote_addr $status", "10.0.0.1"),
+            ("$remote_addr [$time_local]", "10.0.0.1 [yesterday]"),
+            (r#"%h "%r" %>s"#, r#"10.0.0.1 "GET / HTTP/1.1" 2OO"#),
+            ("%h %t", "10.0.0.1 12/Feb/2023:14:03:45 +0000"),
+            ("$remote_addr|$time_iso8601", "10.0.0.1|12/Feb/2023:14:03:45 +0000"),
+            ("$remote_addr - $status", "10.0.0.1 200"),
+        ];
+        for (format, line) in cases {
+            let parsed: LogFormat = format.parse().unwrap();
+            assert!(parsed.parse(line).is_err(), "{:?} accepted {:?}", format, line);
+        }
+    }
+
+    #[test]
+    fn mismatched_lines() {
+        let format: LogFormat = "$remote_addr [$time_local]".parse().unwrap();
+        assert!(format.parse("10.0.0.1 12/Feb/2023").is_err());
+        assert!(format.parse("10.0.0.1 [12/Feb/2023:14:03:45 +0000] trailing").is_err());
+        assert!(format.parse("not-an-ip [12/Feb/2023:14:03:45 +0000]").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt::{self, Display};
+
+// The `fuzzy` and `fuzzy_eq` text operators, for the near-misses logs are full of: user agents
+// that almost spell Mozilla, probes for `/wp-adminn/`. `fuzzy` matches a value with a substring
+// within the Levenshtein distance of the pattern, `fuzzy_eq` one whose whole value is. The
+// distance follows the pattern after a colon, `wp-admin:2`, and is 1 without one.
+//
+// Most values are hopeless, so the edit distance is only computed for those that pass a cheap
+// screen: one within the distance of the pattern holds at least one of any `distance + 1`
+// pieces the pattern is cut into exactly, since each edit touches at most one piece (Wu and
+// Manber's pigeonhole filter), and for `fuzzy_eq` its length is within the distance of the
+// pattern's. The distance itself is computed column by column over the value, only down to the
+// last row still within the distance (Ukkonen's cutoff), or for `fuzzy_eq` within a band of
+// diagonals as wide as twice the distance, so a line costs at most its length times that width.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fuzzy {
+    pattern: Vec<char>,
+    /// The pattern cut into `max + 1` pieces, one of which a value within `max` must hold.
+    pieces: Vec<String>,
+    max: usize,
+    /// Whether the whole value is compared, for `fuzzy_eq`, rather than its best substring.
+    whole: bool,
+}
+
+impl Fuzzy {
+    /// Parses `pattern:N`, or a pattern alone for a distance of 1. A pattern ending in a colon
+    /// and digits of its own is given with its distance, e.g. `port:80:1`.
+    pub fn parse(value: &str, whole: bool) -> Result<Fuzzy, String> {
+        let (pattern, max) = match value.rsplit_once(':') {
+            Some((pattern, max)) if !max.is_empty() && max.bytes().all(|b| b.is_ascii_digit()) => {
+                (pattern, max.parse().map_err(|_| format!("{} is too far a distance", max))?)
+            }
+            _ => (value, 1),
+        };
+        let pattern: Vec<char> = pattern.chars().collect();
+        if max >= pattern.len() {
+            return Err(format!(
+                "a distance of {} leaves nothing of {:?} to match; use one less than its {} characters",
+                max,
+                pattern.iter().collect::<String>(),
+                pattern.len()
+            ));
+        }
+        // Cut into pieces as even as they come, the first ones longer by a character.
+        let (size, longer) = (pattern.len() / (max + 1), pattern.len() % (max + 1));
+        let mut start = 0;
+        let pieces = (0..=max)
+            .map(|piece| {
+                let end = start + size + usize::from(piece < longer);
+                let piece = pattern[start..end].iter().collect();
+                start = end;
+                piece
+            })
+            .collect();
+        Ok(Fuzzy { pattern, pieces, max, whole })
+    }
+
+    pub fn operator(&self) -> &'static str {
+        if self.whole { "fuzzy_eq" } else { "fuzzy" }
+    }
+
+    /// The distance of `value`, or of its nearest substring, from the pattern when it's within
+    /// the maximum.
+    pub fn distance(&self, value: &str) -> Option<usize> {
+        if !self.screen(value) {
+            return None;
+        }
+        match self.whole {
+            true => self.banded(&value.chars().collect::<Vec<char>>()),
+            false => self.nearest(value),
+        }
+    }
+
+    /// Whether `value` may be within the distance: the cheap test every value goes through.
+    fn screen(&self, value: &str) -> bool {
+        // A character is at most 4 bytes, so a short value can be ruled out without counting them.
+        let fewest = self.pattern.len() - self.max;
+        if value.len() < fewest || (self.whole && value.len() > 4 * (self.pattern.len() + self.max)) {
+            return false;
+        }
+        if self.whole && value.chars().count().abs_diff(self.pattern.len()) > self.max {
+            return false;
+        }
+        self.pieces.iter().any(|piece| value.contains(piece.as_str()))
+    }
+
+    /// The least distance of any substring of `value` from the pattern (Sellers), where a
+    /// column's rows past the last within the maximum aren't computed.
+    fn nearest(&self, value: &str) -> Option<usize> {
+        let (m, max) = (self.pattern.len(), self.max);
+        // Row i of the column is the least distance of the pattern's first i characters from a
+        // substring ending at the current character; it may start anywhere, so row 0 stays 0.
+        let mut column: Vec<usize> = (0..=m).collect();
+        let mut last = max;
+        let mut best = None;
+        for c in value.chars() {
+            let rows = (last + 1).min(m);
+            let mut diagonal = 0;
+            for i in 1..=rows {
+                // Rows past the last one within the maximum only count as over it.
+                let left = if i <= last { column[i] } else { max + 1 };
+                let cell = (diagonal + usize::from(self.pattern[i - 1] != c)).min(left + 1).min(column[i - 1] + 1);
+                diagonal = left;
+                column[i] = cell;
+            }
+            last = rows;
+            while column[last] > max {
+                last -= 1;
+            }
+            if last == m {
+                best = Some(best.map_or(column[m], |best: usize| best.min(column[m])));
+                if column[m] == 0 {
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    /// The distance of the whole of `value` from the pattern, computing only the cells within
+    /// `max` diagonals of the main one and stopping once a row is wholly over the maximum.
+    fn banded(&self, value: &[char]) -> Option<usize> {
+        let (m, n, max) = (self.pattern.len(), value.len(), self.max);
+        let over = max + 1;
+        // Row i holds the distance of the pattern's first i characters from each prefix of value.
+        let mut previous: Vec<usize> = (0..=n).map(|j| j.min(over)).collect();
+        let mut row = vec![over; n + 1];
+        for i in 1..=m {
+            let (from, to) = (i.saturating_sub(max), (i + max).min(n));
+            row.fill(over);
+            row[0] = i.min(over);
+            let mut least = row[0];
+            for j in from.max(1)..=to {
+                let substitute = previous[j - 1] + usize::from(self.pattern[i - 1] != value[j - 1]);
+                let cell = substitute.min(previous[j] + 1).min(row[j - 1] + 1);
+                row[j] = cell.min(over);
+                least = least.min(row[j]);
+            }
+            if least > max {
+                return None;
+            }
+            std::mem::swap(&mut previous, &mut row);
+        }
+        Some(previous[n]).filter(|&distance| distance <= max)
+    }
+}
+
+/// The value as the filter was given, e.g. `"wp-admin":2`.
+impl Display for Fuzzy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}:{}", self.pattern.iter().collect::<String>(), self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(value: &str, pattern: &str, whole: bool) -> Option<usize> {
+        Fuzzy::parse(pattern, whole).unwrap().distance(value)
+    }
+
+    #[test]
+    fn distances_count_edits_down_to_an_exact_match() {
+        assert_eq!(distance("/wp-admin/", "wp-admin:2", false), Some(0));
+        assert_eq!(distance("/wp-adminn/", "wp-admin", false), Some(0));
+        assert_eq!(distance("/wp-admn/", "wp-admin", false), Some(1));
+        assert_eq!(distance("/wp-dmin/setup.php", "wp-admin", false), Some(1));
+        assert_eq!(distance("/wordpress/", "wp-admin:2", false), None);
+        assert_eq!(distance("Mozilla/5.0", "Mozilla", true), None);
+        assert_eq!(distance("Mozila", "Mozilla", true), Some(1));
+        assert_eq!(distance("Mozilla", "Mozilla:0", true), Some(0));
+        // Levenshtein counts a transposition as two edits.
+        assert_eq!(distance("Mozlila/5.0", "Mozilla", false), None);
+        assert_eq!(distance("Mozlila/5.0", "Mozilla:2", false), Some(2));
+        assert_eq!(distance("Mzoilla", "Mozilla:2", true), Some(2));
+        assert_eq!(distance("Mzoilla", "Mozilla:1", true), None);
+    }
+
+    #[test]
+    fn distances_are_counted_in_characters() {
+        // "é" and "ü" are two bytes each, "日本" three each; each is one edit.
+        assert_eq!(distance("/café/menu", "cafe", false), Some(1));
+        assert_eq!(distance("Müller", "Muller", true), Some(1));
+        assert_eq!(distance("/docs/日本語/", "日本人", false), Some(1));
+        assert_eq!(distance("日本語", "日本人:1", true), Some(1));
+        assert_eq!(distance("日本", "日本人:1", true), Some(1));
+        assert_eq!(distance("日", "日本人:1", true), None);
+    }
+
+    #[test]
+    fn patterns_take_their_distance_after_a_colon() {
+        assert_eq!(Fuzzy::parse("wp-admin:2", false).unwrap().to_string(), "\"wp-admin\":2");
+        assert_eq!(Fuzzy::parse("wp-admin", false).unwrap().to_string(), "\"wp-admin\":1");
+        assert_eq!(Fuzzy::parse("port:80:1", false).unwrap().to_string(), "\"port:80\":1");
+        assert_eq!(Fuzzy::parse("http://a:b", false).unwrap().to_string(), "\"http://a:b\":1");
+        assert!(Fuzzy::parse("abc:3", false).unwrap_err().starts_with("a distance of 3 leaves nothing of \"abc\""));
+        assert_eq!(Fuzzy::parse("wp-admin:3", false).unwrap().pieces, ["wp", "-a", "dm", "in"]);
+        assert_eq!(Fuzzy::parse("Mozilla:2", false).unwrap().pieces, ["Moz", "il", "la"]);
+    }
+
+    #[test]
+    fn hopeless_values_are_screened_out_before_any_distance() {
+        let fuzzy = Fuzzy::parse("wp-admin:1", false).unwrap();
+        let long = "/static/".repeat(10_000);
+        assert!(!fuzzy.screen(&long));
+        assert!(!fuzzy.screen("/wp"));
+        assert!(fuzzy.screen("/wp-axxxx/"));
+        assert_eq!(fuzzy.distance("/wp-axxxx/"), None);
+        let whole = Fuzzy::parse("Mozilla:1", true).unwrap();
+        assert!(!whole.screen("Mozilla/5.0 (X11; Linux x86_64)"));
+        assert!(!whole.screen("Moz"));
+        assert!(whole.screen("Mozilxx"));
+        // A value past the screen still stops as soon as a row is wholly over the distance.
+        assert_eq!(whole.banded(&"Mozilxx".chars().collect::<Vec<char>>()), None);
+    }
+}
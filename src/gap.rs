@@ -0,0 +1,123 @@
+use crate::entry::{Entry, FieldValue};
+use chrono::{DateTime, FixedOffset};
+use std::collections::{hash_map::Entry as Slot, HashMap};
+use std::net::IpAddr;
+
+// The `gap` field: seconds since the previous request from the same client address in the same
+// file, absent for the client's first. Scripts send requests in quick succession and people
+// don't, so a client whose median gap is under 100ms is almost certainly automated:
+// `stats --by ip --metric gap`. Every entry that parses counts as a request, matched or not.
+// Each client keeps only its latest timestamp, so the input is assumed to be roughly in time
+// order: an entry earlier than its client's latest has no gap. At most --max-clients are
+// tracked at once, and clients beyond them have no gaps either. It's computed only when the
+// filter or the command names the field, since it keeps every client in memory and the file
+// has to be read on one thread.
+
+pub const FIELD: &str = "gap";
+
+/// The latest request of each client read so far.
+pub struct Gaps {
+    max_clients: usize,
+    latest: HashMap<IpAddr, DateTime<FixedOffset>>,
+    /// Entries earlier than their client's latest request.
+    out_of_order: usize,
+    /// Entries of clients that weren't tracked, past `max_clients`.
+    untracked: usize,
+}
+
+impl Gaps {
+    pub fn new(max_clients: usize) -> Self {
+        Gaps { max_clients, latest: HashMap::new(), out_of_order: 0, untracked: 0 }
+    }
+
+    pub fn max_clients(&self) -> usize {
+        self.max_clients
+    }
+
+    /// Entries without a gap because they were earlier than their client's latest request.
+    pub fn out_of_order(&self) -> usize {
+        self.out_of_order
+    }
+
+    /// Entries without a gap because their client was past --max-clients.
+    pub fn untracked(&self) -> usize {
+        self.untracked
+    }
+
+    /// Sets `gap` on `entry` when its client was seen before, and records it as the client's
+    /// latest request.
+    pub fn apply(&mut self, entry: &mut Entry) {
+        let (Some(ip), Some(now)) = (entry.ip(), entry.timestamp) else {
+            return;
+        };
+        let tracked = self.latest.len();
+        match self.latest.entry(ip) {
+            Slot::Occupied(latest) if now < *latest.get() => self.out_of_order += 1,
+            Slot::Occupied(mut latest) => {
+                let previous = latest.insert(now);
+                let gap = (now - previous).to_std().map_or(0.0, |gap| gap.as_secs_f64());
+                entry.extras.insert(FIELD.to_string(), FieldValue::Number(gap));
+            }
+            Slot::Vacant(slot) if tracked < self.max_clients => {
+                slot.insert(now);
+            }
+            Slot::Vacant(_) => self.untracked += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gaps(requests: &[(&str, &str)], max_clients: usize) -> (Vec<Option<f64>>, Gaps) {
+        let mut gaps = Gaps::new(max_clients);
+        let found = requests
+            .iter()
+            .map(|(ip, time)| {
+                let mut entry = Entry {
+                    client: Some(ip.parse().unwrap()),
+                    timestamp: Some(DateTime::parse_from_rfc3339(time).unwrap()),
+                    ..Entry::default()
+                };
+                gaps.apply(&mut entry);
+                entry.extras.get(FIELD).and_then(FieldValue::as_number)
+            })
+            .collect();
+        (found, gaps)
+    }
+
+    #[test]
+    fn gaps_are_per_client() {
+        let (found, gaps) = gaps(
+            &[
+                ("192.0.2.1", "2023-02-12T14:00:00Z"),
+                ("192.0.2.2", "2023-02-12T14:00:00.250Z"),
+                ("192.0.2.1", "2023-02-12T14:00:00.050Z"),
+                ("192.0.2.1", "2023-02-12T14:00:00.050Z"),
+                ("192.0.2.2", "2023-02-12T16:00:01.250+02:00"),
+                // Earlier than the client's latest: no gap, and the latest stays.
+                ("192.0.2.1", "2023-02-12T14:00:00Z"),
+                ("192.0.2.1", "2023-02-12T14:00:00.150Z"),
+            ],
+            10,
+        );
+        assert_eq!(found, [None, None, Some(0.05), Some(0.0), Some(1.0), None, Some(0.1)]);
+        assert_eq!((gaps.out_of_order(), gaps.untracked()), (1, 0));
+    }
+
+    #[test]
+    fn clients_past_the_limit_are_not_tracked() {
+        let (found, gaps) = gaps(
+            &[
+                ("192.0.2.1", "2023-02-12T14:00:00Z"),
+                ("192.0.2.2", "2023-02-12T14:00:01Z"),
+                ("192.0.2.1", "2023-02-12T14:00:02Z"),
+                ("192.0.2.2", "2023-02-12T14:00:03Z"),
+            ],
+            1,
+        );
+        assert_eq!(found, [None, None, Some(2.0), None]);
+        assert_eq!((gaps.out_of_order(), gaps.untracked()), (0, 2));
+    }
+}
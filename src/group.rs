@@ -0,0 +1,566 @@
+use crate::budget::{self, Account};
+use crate::classify;
+use crate::entry::Entry;
+use crate::extract;
+use crate::order::Order;
+use crate::output::{schema::GroupLeaf, table::Table};
+use crate::referrer;
+use crate::registry;
+use crate::sample::{Reservoir, Rng};
+use crate::space_saving::SpaceSaving;
+use crate::stats::percentile;
+use crate::zone::Zone;
+use clap::{
+    builder::{EnumValueParser, PossibleValue, TypedValueParser},
+    Arg, Command, ValueEnum,
+};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsStr,
+};
+
+// `stats --by` counts matches per combination of key values, e.g. per path prefix and status.
+// Each group is keyed by the tuple of its values, one per key, so any number of keys share the
+// same map, ordered by the first key, then the second and so on. With --examples each group
+// also keeps a reservoir sample of its lines, so memory stays at groups × N lines. With
+// --approximate there's no map at all, only a SpaceSaving summary of the most frequent groups.
+
+/// What to do instead when the groups take the run past --max-memory.
+pub const OVER_BUDGET: &str = "count only the most frequent with --approximate, or raise --max-memory";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum GroupBy {
+    Status,
+    Method,
+    Path,
+    /// The first segment of the path, e.g. `/api` for `/api/users/7?page=2`
+    PathPrefix,
+    /// `static` for assets, by extension or path prefix, or `dynamic`
+    Class,
+    /// The client's address, or `-` for a hostname
+    Ip,
+    /// The client's address or, with Apache's `HostnameLookups On`, hostname
+    Client,
+    /// The authenticated user
+    User,
+    UserAgent,
+    /// The calendar day of the timestamp, in --tz or else the offset it was logged with
+    Day,
+    /// The hour of the timestamp, e.g. `2023-02-12T09`, in --tz or else the offset it was logged with
+    Hour,
+    /// `http` or `https`, for formats that log it
+    Scheme,
+    /// e.g. `TLSv1.2`, or `-` for plain HTTP, for formats that log it
+    TlsProtocol,
+    /// Where the referrer points: `internal` (a --site-host or the virtual host), `search`,
+    /// `social`, `external`, or `none` without one
+    ReferrerClass,
+    /// The referrer's host without a leading `www.`, e.g. `google.com`
+    ReferrerDomain,
+    /// A field --extract defines, e.g. `version`, or another the registry knows, such as
+    /// `path_depth` or `status_code`
+    #[value(skip)]
+    Field(&'static str),
+}
+
+/// The calendar day of an entry's timestamp, or `-` without one.
+pub fn day(entry: &Entry, tz: Option<Zone>) -> String {
+    match (entry.timestamp, tz) {
+        (Some(t), Some(tz)) => tz.local(t).date_naive().to_string(),
+        (Some(t), None) => t.date_naive().to_string(),
+        (None, _) => "-".to_string(),
+    }
+}
+
+/// The hour of an entry's timestamp, e.g. `2023-02-12T09`, or `-` without one.
+pub fn hour(entry: &Entry, tz: Option<Zone>) -> String {
+    match (entry.timestamp, tz) {
+        (Some(t), Some(tz)) => tz.local(t).format("%Y-%m-%dT%H").to_string(),
+        (Some(t), None) => t.format("%Y-%m-%dT%H").to_string(),
+        (None, _) => "-".to_string(),
+    }
+}
+
+fn path_prefix(path: &str) -> &str {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    match path.get(1..).and_then(|rest| rest.find('/')) {
+        Some(end) => &path[..end + 1],
+        None => path,
+    }
+}
+
+impl GroupBy {
+    pub fn name(self) -> String {
+        match self {
+            GroupBy::Field(name) => name.to_string(),
+            key => key.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default(),
+        }
+    }
+
+    /// Whether the key is a bucket of log time, so time-ordered input brings its values in order.
+    pub fn is_time(self) -> bool {
+        matches!(self, GroupBy::Day | GroupBy::Hour)
+    }
+
+    pub fn value(self, entry: &Entry, tz: Option<Zone>) -> String {
+        let text = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+        match self {
+            GroupBy::Status => entry.status_code.map_or_else(|| "-".to_string(), |s| s.as_u16().to_string()),
+            GroupBy::Method => text(&entry.method),
+            GroupBy::Path => text(entry.match_path()),
+            GroupBy::PathPrefix => entry.match_path().as_deref().map_or("-", path_prefix).to_string(),
+            GroupBy::Class => {
+                entry.match_path().as_deref().map_or("-", |path| classify::classifier().classify(path).name()).to_string()
+            }
+            GroupBy::Ip => entry.ip().map_or_else(|| "-".to_string(), |ip| ip.to_string()),
+            GroupBy::Client => entry.client.as_ref().map_or_else(|| "-".to_string(), |client| client.to_string()),
+            GroupBy::User => text(&entry.user),
+            GroupBy::UserAgent => text(&entry.user_agent),
+            GroupBy::Day => day(entry, tz),
+            GroupBy::Hour => hour(entry, tz),
+            GroupBy::Scheme => text(&entry.scheme),
+            GroupBy::TlsProtocol => entry.tls_protocol.map_or_else(|| "-".to_string(), |v| v.to_string()),
+            GroupBy::ReferrerClass => referrer::class(entry).name().to_string(),
+            GroupBy::ReferrerDomain => entry.referrer.as_deref().and_then(referrer::domain).unwrap_or_else(|| "-".to_string()),
+            GroupBy::Field(name) => registry::value(entry, name).map_or_else(|| "-".to_string(), |value| value.as_text()),
+        }
+    }
+}
+
+/// Parses a key, or the name of a field, by any name the registry knows it by, or one --extract
+/// defines, which is checked once the options have been read. Names are leaked: there are only
+/// as many as the command line has.
+#[derive(Clone)]
+pub struct KeyParser;
+
+impl TypedValueParser for KeyParser {
+    type Value = GroupBy;
+
+    fn parse_ref(&self, cmd: &Command, arg: Option<&Arg>, value: &OsStr) -> Result<GroupBy, clap::Error> {
+        let error = match EnumValueParser::<GroupBy>::new().parse_ref(cmd, arg, value) {
+            Ok(key) => return Ok(key),
+            Err(error) => error,
+        };
+        let name = match value.to_str() {
+            Some(name) if !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-') => {
+                registry::canonical(name)
+            }
+            _ => return Err(error),
+        };
+        let key = match name.as_str() {
+            "status_code" => Some(GroupBy::Status),
+            "user_agent" => Some(GroupBy::UserAgent),
+            name => GroupBy::from_str(&name.replace('_', "-"), false).ok(),
+        };
+        Ok(key.unwrap_or_else(|| GroupBy::Field(Box::leak(name.into_boxed_str()))))
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(GroupBy::value_variants().iter().filter_map(ValueEnum::to_possible_value)))
+    }
+}
+
+/// Fails for a key that names neither a field the registry knows nor one --extract defines.
+pub fn check_fields(by: &[GroupBy]) -> Result<(), String> {
+    match by.iter().find(|key| matches!(key, GroupBy::Field(name) if !extract::defines(name) && registry::find(name).is_none())) {
+        Some(key) => Err(format!(
+            "{} is neither a key ({}) nor a field --extract defines",
+            key.name(),
+            GroupBy::value_variants().iter().map(|key| key.name()).collect::<Vec<_>>().join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// The values of `by` for an entry, which together name its group.
+pub fn key(by: &[GroupBy], entry: &Entry, tz: Option<Zone>) -> Vec<String> {
+    by.iter().map(|by| by.value(entry, tz)).collect()
+}
+
+/// `path-prefix,status`, as --by was given.
+pub fn names(by: &[GroupBy]) -> Vec<String> {
+    by.iter().map(|by| by.name()).collect()
+}
+
+/// Matches in one group, the --metric values among them and, with --examples, some of their
+/// lines.
+#[derive(Clone, Default)]
+struct Group {
+    count: usize,
+    values: Vec<f64>,
+    examples: Option<Reservoir>,
+}
+
+impl Group {
+    /// Count, mean, median, p95 and max, the latter four absent without values.
+    fn stats(&mut self) -> (usize, Option<[f64; 4]>) {
+        if self.values.is_empty() {
+            return (self.count, None);
+        }
+        self.values.sort_by(f64::total_cmp);
+        let mean = self.values.iter().sum::<f64>() / self.values.len() as f64;
+        let max = self.values[self.values.len() - 1];
+        (self.count, Some([mean, percentile(&self.values, 50.0), percentile(&self.values, 95.0), max]))
+    }
+}
+
+pub fn too_many(by: &[GroupBy], max: usize) -> String {
+    format!(
+        "--by {} makes more than {} groups; narrow the filter, use fewer keys or raise --max-groups",
+        names(by).join(","),
+        max
+    )
+}
+
+const METRIC_COLUMNS: [&str; 4] = ["mean", "p50", "p95", "max"];
+
+#[derive(Clone)]
+pub struct Groups {
+    by: Vec<GroupBy>,
+    tz: Option<Zone>,
+    max: usize,
+    /// How many example lines each group keeps, and what picks them.
+    examples: Option<(usize, Rng)>,
+    groups: BTreeMap<Vec<String>, Group>,
+    /// With --approximate, the counts instead of `groups`.
+    sketch: Option<SpaceSaving<Vec<String>>>,
+    /// What `groups` holds of the run's --max-memory.
+    memory: Option<Account>,
+}
+
+impl Groups {
+    pub fn new(by: Vec<GroupBy>, tz: Option<Zone>, max: usize) -> Self {
+        Groups { by, tz, max, examples: None, groups: BTreeMap::new(), sketch: None, memory: None }
+    }
+
+    /// Charges each group and metric value kept to `memory`.
+    pub fn budgeted(self, memory: Account) -> Self {
+        Groups { memory: Some(memory), ..self }
+    }
+
+    /// Counts only the most frequent groups, in `capacity` counters, with no cap on how many
+    /// groups there are.
+    pub fn approximate(self, capacity: usize) -> Self {
+        Groups { sketch: Some(SpaceSaving::new(capacity)), ..self }
+    }
+
+    /// Keeps up to `count` lines of each group, picked by `rng`.
+    pub fn with_examples(self, count: usize, rng: Rng) -> Self {
+        Groups { examples: Some((count, rng)), ..self }
+    }
+
+    /// Counts `entry`, read from `line`, in its group, failing once there would be more than
+    /// --max-groups.
+    pub fn add(&mut self, entry: &Entry, line: &str, value: Option<f64>) -> Result<(), String> {
+        let key = key(&self.by, entry, self.tz);
+        if let Some(sketch) = &mut self.sketch {
+            sketch.insert(key);
+            return Ok(());
+        }
+        if !self.groups.contains_key(&key) && self.groups.len() >= self.max {
+            return Err(format!(
+                "--by {} makes more than {} groups; narrow the filter, use fewer keys, raise --max-groups or count only \
+                 the most frequent with --approximate",
+                names(&self.by).join(","),
+                self.max
+            ));
+        }
+        if let Some(memory) = &mut self.memory {
+            let kept = match self.groups.contains_key(&key) {
+                true => 0,
+                false => budget::strings(&key) + std::mem::size_of::<Group>(),
+            };
+            memory.charge(kept + value.map_or(0, |_| std::mem::size_of::<f64>()))?;
+        }
+        let group = self.groups.entry(key).or_default();
+        group.count += 1;
+        group.values.extend(value);
+        if let Some((count, rng)) = &mut self.examples {
+            group.examples.get_or_insert_with(|| Reservoir::new(*count)).offer(rng, || line.to_string());
+        }
+        Ok(())
+    }
+
+    /// Each group's key values, count and how much more that may be than the true count: in
+    /// key order and exact, or with --approximate only the most frequent groups, most first.
+    pub fn counts(&self) -> impl Iterator<Item = (&[String], usize, usize)> {
+        let exact = self.groups.iter().map(|(key, group)| (key.as_slice(), group.count, 0));
+        let approximate = self.sketch.iter().flat_map(|sketch| sketch.ranked());
+        exact.chain(approximate.map(|counted| (counted.key.as_slice(), counted.count, counted.error)))
+    }
+
+    /// The example lines kept of the group with `key`, with --examples.
+    pub fn examples(&self, key: &[String]) -> Option<Vec<String>> {
+        self.groups.get(key)?.examples.clone().map(Reservoir::into_items)
+    }
+
+    /// Matches counted, in every group or, with --approximate, counted by the summary or not.
+    pub fn total(&self) -> usize {
+        match &self.sketch {
+            Some(sketch) => sketch.total(),
+            None => self.groups.values().map(|group| group.count).sum(),
+        }
+    }
+
+    /// With --approximate, the most matches a group it didn't count can have, and the most any
+    /// count is over by.
+    pub fn bound(&self) -> usize {
+        self.sketch.as_ref().map_or(0, SpaceSaving::bound)
+    }
+
+    pub fn approximated(&self) -> bool {
+        self.sketch.is_some()
+    }
+
+    /// One row per group, in `order`: its key values, the count and, with a metric, its summary,
+    /// followed by the group's example lines, indented. With --approximate, only the most frequent
+    /// groups, each with how much its count may be over.
+    pub fn table(self, metric: bool, order: Order) -> Table {
+        let mut header = names(&self.by);
+        header.push("count".to_string());
+        if self.approximated() {
+            header.push("error".to_string());
+            let mut table = Table::new(&header);
+            let mut counts: Vec<_> = self.counts().collect();
+            order.sort(&mut counts, |&(key, count, _)| (count, key));
+            for (key, count, error) in counts {
+                let mut row = key.to_vec();
+                row.extend([count.to_string(), error.to_string()]);
+                table.row(row);
+            }
+            return table;
+        }
+        if metric {
+            header.extend(METRIC_COLUMNS.map(str::to_string));
+        }
+        let mut table = Table::new(&header);
+        let mut groups: Vec<(Vec<String>, Group)> = self.groups.into_iter().collect();
+        order.sort(&mut groups, |(key, group)| (group.count, key));
+        for (key, mut group) in groups {
+            let (count, stats) = group.stats();
+            let mut row = key;
+            row.push(count.to_string());
+            if metric {
+                match stats {
+                    Some(stats) => row.extend(stats.map(|v| v.to_string())),
+                    None => row.extend(["-"; 4].map(str::to_string)),
+                }
+            }
+            table.row(row);
+            for example in group.examples.take().map(Reservoir::into_items).unwrap_or_default() {
+                table.note(format!("  {}", example));
+            }
+        }
+        table
+    }
+
+    /// Counts with the first key's values as rows, in `order` by their totals, and the second's as
+    /// columns, by value, plus totals. Needs exactly two keys.
+    pub fn pivot(&self, order: Order) -> Table {
+        let columns: BTreeSet<&str> = self.groups.keys().map(|key| key[1].as_str()).collect();
+        let mut rows: BTreeMap<&str, BTreeMap<&str, usize>> = BTreeMap::new();
+        for (key, group) in &self.groups {
+            rows.entry(key[0].as_str()).or_default().insert(key[1].as_str(), group.count);
+        }
+
+        let mut header = vec![self.by[0].name()];
+        header.extend(columns.iter().map(|c| c.to_string()));
+        header.push("total".to_string());
+        let mut table = Table::new(&header);
+        let mut totals: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut rows: Vec<(&str, usize, BTreeMap<&str, usize>)> =
+            rows.into_iter().map(|(key, counts)| (key, counts.values().sum(), counts)).collect();
+        order.sort(&mut rows, |(key, total, _)| (*total, *key));
+        for (row_key, total, counts) in &rows {
+            let mut row = vec![row_key.to_string()];
+            for column in &columns {
+                let count = counts.get(column).copied().unwrap_or(0);
+                *totals.entry(column).or_default() += count;
+                row.push(count.to_string());
+            }
+            row.push(total.to_string());
+            table.row(row);
+        }
+        let mut row = vec!["total".to_string()];
+        row.extend(columns.iter().map(|column| totals.get(column).copied().unwrap_or(0).to_string()));
+        row.push(totals.values().sum::<usize>().to_string());
+        table.row(row);
+        table
+    }
+
+    /// Nested objects keyed by the first key's values, then the second's and so on, holding the
+    /// count or, with a metric, the count and summary, with --examples the example lines and with
+    /// --approximate the count and its error.
+    pub fn into_json(mut self, metric: bool) -> Value {
+        let mut leaves = Vec::new();
+        for counted in self.sketch.iter().flat_map(|sketch| sketch.counted()) {
+            leaves.push((counted.key.clone(), GroupLeaf::Approximate { count: counted.count, error: counted.error }));
+        }
+        for (key, group) in &mut self.groups {
+            let (count, stats) = group.stats();
+            let examples = group.examples.take().map(Reservoir::into_items);
+            let leaf = match (metric, stats, examples) {
+                (false, _, None) => GroupLeaf::Count(count),
+                (false, _, Some(examples)) => GroupLeaf::Examples { count, examples },
+                (true, stats, examples) => GroupLeaf::Metric {
+                    count,
+                    mean: stats.map(|s| s[0]),
+                    p50: stats.map(|s| s[1]),
+                    p95: stats.map(|s| s[2]),
+                    max: stats.map(|s| s[3]),
+                    examples,
+                },
+            };
+            leaves.push((key.clone(), leaf));
+        }
+        let mut root = Map::new();
+        for (key, leaf) in leaves {
+            let leaf = serde_json::to_value(leaf).expect("group leaves serialize");
+            let (last, parents) = key.split_last().expect("at least one key");
+            let mut node = &mut root;
+            for part in parents {
+                node = match node.entry(part.clone()).or_insert_with(|| Value::Object(Map::new())) {
+                    Value::Object(map) => map,
+                    _ => unreachable!("inner nodes are objects"),
+                };
+            }
+            node.insert(last.clone(), leaf);
+        }
+        Value::Object(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::SortBy;
+    use http::StatusCode;
+    use serde_json::json;
+
+    fn request(path: &str, status: u16, bytes: Option<u64>) -> Entry {
+        Entry {
+            path: Some(path.to_string()),
+            status_code: StatusCode::from_u16(status).ok(),
+            bytes,
+            ..Entry::default()
+        }
+    }
+
+    fn groups() -> Groups {
+        let mut groups = Groups::new(vec![GroupBy::PathPrefix, GroupBy::Status], None, 10);
+        for (path, status, bytes) in [
+            ("/api/users/7?page=2", 200, Some(100)),
+            ("/api/login", 401, None),
+            ("/api", 200, Some(300)),
+            ("/static/app.js", 200, Some(5000)),
+            ("/static/app.css", 404, Some(10)),
+        ] {
+            groups.add(&request(path, status, bytes), path, bytes.map(|b| b as f64)).unwrap();
+        }
+        groups
+    }
+
+    #[test]
+    fn path_prefixes() {
+        assert_eq!(path_prefix("/api/users/7"), "/api");
+        assert_eq!(path_prefix("/api?x=/y"), "/api");
+        assert_eq!(path_prefix("/"), "/");
+        assert_eq!(path_prefix("*"), "*");
+    }
+
+    #[test]
+    fn tables_list_each_combination() {
+        assert_eq!(
+            groups().table(false, Order::default()).render(),
+            "path-prefix\tstatus\tcount\n/api\t200\t2\n/api\t401\t1\n/static\t200\t1\n/static\t404\t1\n"
+        );
+        assert_eq!(
+            groups().table(true, Order::default()).render().lines().take(3).collect::<Vec<_>>(),
+            ["path-prefix\tstatus\tcount\tmean\tp50\tp95\tmax", "/api\t200\t2\t200\t100\t300\t300", "/api\t401\t1\t-\t-\t-\t-"]
+        );
+    }
+
+    #[test]
+    fn rows_follow_the_order() {
+        let mut groups = groups();
+        for _ in 0..2 {
+            groups.add(&request("/static/logo.png", 200, None), "", None).unwrap();
+        }
+        let rows =
+            |order: Order| groups.clone().table(false, order).render().lines().skip(1).map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(rows(Order::default()), ["/static\t200\t3", "/api\t200\t2", "/api\t401\t1", "/static\t404\t1"]);
+        assert_eq!(
+            rows(Order { sort: SortBy::Key, reverse: false }),
+            ["/api\t200\t2", "/api\t401\t1", "/static\t200\t3", "/static\t404\t1"]
+        );
+        assert_eq!(
+            rows(Order { sort: SortBy::Count, reverse: true }),
+            ["/static\t404\t1", "/api\t401\t1", "/api\t200\t2", "/static\t200\t3"]
+        );
+        assert_eq!(
+            groups.pivot(Order { sort: SortBy::Count, reverse: false }).render(),
+            "path-prefix\t200\t401\t404\ttotal\n/static\t3\t0\t1\t4\n/api\t2\t1\t0\t3\ntotal\t5\t1\t1\t7\n"
+        );
+    }
+
+    #[test]
+    fn pivots_put_the_second_key_across() {
+        assert_eq!(
+            groups().pivot(Order::default()).render(),
+            "path-prefix\t200\t401\t404\ttotal\n/api\t2\t1\t0\t3\n/static\t1\t0\t1\t2\ntotal\t3\t1\t1\t5\n"
+        );
+    }
+
+    #[test]
+    fn json_nests_by_key() {
+        assert_eq!(groups().into_json(false), json!({"/api": {"200": 2, "401": 1}, "/static": {"200": 1, "404": 1}}));
+        assert_eq!(
+            groups().into_json(true)["/api"]["401"],
+            json!({"count": 1, "mean": null, "p50": null, "p95": null, "max": null})
+        );
+    }
+
+    #[test]
+    fn approximate_groups_keep_the_most_frequent() {
+        // Two counters for three statuses: 500 takes over 404's, then 404 takes it back over.
+        let mut groups = Groups::new(vec![GroupBy::Status], None, 1).approximate(2);
+        for status in [200, 200, 200, 404, 500, 404] {
+            groups.add(&request("/", status, None), "", None).unwrap();
+        }
+        assert_eq!((groups.total(), groups.bound()), (6, 3));
+        assert_eq!(groups.clone().table(false, Order::default()).render(), "status\tcount\terror\n200\t3\t0\n404\t3\t2\n");
+        assert_eq!(groups.into_json(false), json!({"200": {"count": 3, "error": 0}, "404": {"count": 3, "error": 2}}));
+    }
+
+    #[test]
+    fn groups_keep_example_lines() {
+        let mut groups = Groups::new(vec![GroupBy::Status], None, 10).with_examples(2, Rng::new(1));
+        for (i, status) in [200, 404, 200, 200, 404].into_iter().enumerate() {
+            groups.add(&request("/", status, None), &format!("line {}", i + 1), None).unwrap();
+        }
+        let table = groups.table(false, Order::default()).render();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[..2], ["status\tcount", "200\t3"]);
+        assert!(lines[2..4].iter().all(|line| ["  line 1", "  line 3", "  line 4"].contains(line)));
+        assert_eq!(lines[4..], ["404\t2", "  line 2", "  line 5"]);
+
+        let mut groups = Groups::new(vec![GroupBy::Status], None, 10).with_examples(1, Rng::new(1));
+        groups.add(&request("/", 404, Some(10)), "only", Some(10.0)).unwrap();
+        assert_eq!(groups.into_json(true)["404"]["examples"], json!(["only"]));
+    }
+
+    #[test]
+    fn group_count_is_capped() {
+        let mut groups = Groups::new(vec![GroupBy::Path, GroupBy::Status], None, 2);
+        groups.add(&request("/a", 200, None), "", None).unwrap();
+        groups.add(&request("/b", 200, None), "", None).unwrap();
+        groups.add(&request("/a", 200, None), "", None).unwrap();
+        assert_eq!(
+            groups.add(&request("/c", 200, None), "", None),
+            Err("--by path,status makes more than 2 groups; narrow the filter, use fewer keys, raise --max-groups or count \
+                 only the most frequent with --approximate"
+                .to_string())
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use crate::budget::{self, Account, MemoryBudget};
+use crate::entry::Match;
+use crate::group::GroupBy;
+use crate::sink::Sink;
+use crate::zone::Zone;
+use std::{collections::BTreeMap, rc::Rc};
+
+// `filter --group-by` prints the matches group by group, each behind a header line with its
+// key value and count, ordered by value with entries that lack one last. That takes holding
+// every match until the input ends, unless the key is a bucket of log time and --assume-sorted
+// says the input is in time order: then a group is complete as soon as an entry of a later
+// bucket turns up, so it's printed and let go there and then, and memory is one bucket's
+// matches. Either way the output is the same; only entries without a timestamp are held to
+// the end in both, as they can turn up anywhere.
+
+/// What to do instead when buffered groups take the run past --max-memory.
+const OVER_BUDGET: &str = "narrow the filter or raise --max-memory";
+const OVER_BUDGET_TIMED: &str = "add --assume-sorted if the input is in time order, narrow the filter or raise --max-memory";
+
+pub struct Grouped {
+    key: GroupBy,
+    tz: Option<Zone>,
+    streaming: bool,
+    /// Rendered matches per key value: every group when buffering, the current bucket's when
+    /// streaming.
+    groups: BTreeMap<String, Vec<String>>,
+    /// Matches without a value for the key, printed last.
+    missing: Vec<String>,
+    /// The bucket held while streaming, which no later entry may come before.
+    bucket: Option<String>,
+    /// What `groups` holds of the run's --max-memory.
+    memory: Account,
+    /// What `missing` holds.
+    missing_memory: Account,
+}
+
+impl Grouped {
+    /// Streams when `assume_sorted` and the key is a bucket of log time; `budget` names an
+    /// account whose advice fits the mode.
+    pub fn new(key: GroupBy, tz: Option<Zone>, assume_sorted: bool, budget: &Rc<MemoryBudget>) -> Self {
+        let streaming = assume_sorted && key.is_time();
+        let memory = budget.account("--group-by", if key.is_time() && !streaming { OVER_BUDGET_TIMED } else { OVER_BUDGET });
+        Grouped {
+            key,
+            tz,
+            streaming,
+            groups: BTreeMap::new(),
+            missing: Vec::new(),
+            bucket: None,
+            missing_memory: memory.clone(),
+            memory,
+        }
+    }
+
+    pub fn streaming(&self) -> bool {
+        self.streaming
+    }
+
+    /// Holds `rendered`, the output of `found`, in its group. Streaming, an entry of a later
+    /// bucket first prints the current one; one of an earlier bucket fails, as that bucket has
+    /// been printed already.
+    pub fn add(&mut self, found: &Match, rendered: String, out: &mut Sink, end: char) -> Result<(), String> {
+        let value = self.key.value(&found.entry, self.tz);
+        let size = budget::strings([&rendered]);
+        if value == "-" {
+            self.missing.push(rendered);
+            return self.missing_memory.charge(size);
+        }
+        if self.streaming {
+            match &self.bucket {
+                Some(bucket) if value < *bucket => {
+                    return Err(format!(
+                        "--assume-sorted: {}:{} has --group-by {} {}, before {}, so the input isn't in time order; drop --assume-sorted",
+                        found.provenance.source_file.display(),
+                        found.provenance.line_no,
+                        self.key.name(),
+                        value,
+                        bucket
+                    ))
+                }
+                Some(bucket) if value == *bucket => {}
+                _ => {
+                    self.flush(out, end)?;
+                    self.bucket = Some(value.clone());
+                }
+            }
+        }
+        let new = if self.groups.contains_key(&value) { 0 } else { budget::strings([&value]) + std::mem::size_of::<Vec<String>>() };
+        self.memory.charge(size + new)?;
+        self.groups.entry(value).or_default().push(rendered);
+        Ok(())
+    }
+
+    /// Prints the groups held, then those without a value.
+    pub fn finish(mut self, out: &mut Sink, end: char) -> Result<(), String> {
+        self.flush(out, end)?;
+        print(self.key, "-", &self.missing, out, end)
+    }
+
+    /// Prints and lets go of the groups held.
+    fn flush(&mut self, out: &mut Sink, end: char) -> Result<(), String> {
+        for (value, lines) in std::mem::take(&mut self.groups) {
+            print(self.key, &value, &lines, out, end)?;
+        }
+        self.memory.release();
+        Ok(())
+    }
+}
+
+/// A group's header, e.g. `== day 2023-02-12 (3 matches) ==`, and its lines.
+fn print(key: GroupBy, value: &str, lines: &[String], out: &mut Sink, end: char) -> Result<(), String> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let plural = if lines.len() == 1 { "" } else { "es" };
+    out.write_line(&format!("== {} {} ({} match{}) ==", key.name(), value, lines.len(), plural), end)
+        .map_err(|e| e.to_string())?;
+    lines.iter().try_for_each(|line| out.write_line(line, end).map_err(|e| e.to_string()))
+}
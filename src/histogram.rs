@@ -0,0 +1,324 @@
+use crate::entry::Entry;
+use crate::output::{
+    html::Page,
+    json_array,
+    schema::{ClassCounts, HistogramBucket},
+    style::{style, Element},
+    table::Table,
+    Delimiter, DelimiterEscape,
+};
+use crate::zone::Zone;
+use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+// `histogram` counts matches per bucket of log time and draws each count as a bar, so a burst
+// of traffic stands out. With --split-status each bucket also counts its responses per status
+// class and the bar is stacked from one character per class; --only-ratio prints just the share
+// of 5xx responses, the number that matters during an incident. Buckets start at multiples of
+// --bucket since the epoch, labelled in --tz or else UTC, and empty buckets between the first
+// and last are printed too so gaps show.
+
+/// Width of the longest bar.
+const BAR_WIDTH: usize = 40;
+
+/// The stacked bar's character for each class, in `StatusClasses::classes` order.
+const CLASS_BARS: [char; 5] = ['=', '~', '?', '!', '.'];
+
+/// How each class's piece of the stacked bar is painted; other statuses aren't.
+const CLASS_ELEMENTS: [Option<Element>; 5] =
+    [Some(Element::Status2xx), Some(Element::Status3xx), Some(Element::Status4xx), Some(Element::Status5xx), None];
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum HistogramOutput {
+    /// Columns separated by --delimiter, with a bar per bucket
+    #[default]
+    Table,
+    /// Comma-separated columns without bars, for plotting elsewhere
+    Csv,
+    /// One object per bucket
+    Json,
+    /// The buckets' objects as one JSON array
+    JsonArray,
+    /// A self-contained HTML page with the counts drawn as columns over the table
+    Html,
+}
+
+/// One bucket's matches, in total and per status class.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StatusClasses {
+    pub total: u64,
+    pub success: u64,
+    pub redirect: u64,
+    pub client_error: u64,
+    pub server_error: u64,
+    /// 1xx, and entries without a status.
+    pub other: u64,
+}
+
+impl StatusClasses {
+    pub fn add(&mut self, entry: &Entry) {
+        self.total += 1;
+        let class = match entry.status_code.map(|status| status.as_u16() / 100) {
+            Some(2) => &mut self.success,
+            Some(3) => &mut self.redirect,
+            Some(4) => &mut self.client_error,
+            Some(5) => &mut self.server_error,
+            _ => &mut self.other,
+        };
+        *class += 1;
+    }
+
+    fn counts(&self) -> ClassCounts {
+        ClassCounts {
+            success: self.success,
+            redirect: self.redirect,
+            client_error: self.client_error,
+            server_error: self.server_error,
+            other: self.other,
+        }
+    }
+
+    fn classes(&self) -> [u64; 5] {
+        [self.success, self.redirect, self.client_error, self.server_error, self.other]
+    }
+
+    /// The percentage of 5xx responses, `None` for an empty bucket.
+    pub fn server_error_ratio(&self) -> Option<f64> {
+        (self.total > 0).then(|| self.server_error as f64 * 100.0 / self.total as f64)
+    }
+}
+
+/// `count` of `max` as a bar of `fill`, at least one character for any count at all.
+fn bar(count: u64, max: u64, fill: char) -> String {
+    let width = match count {
+        0 => 0,
+        _ => ((count as f64 / max.max(1) as f64 * BAR_WIDTH as f64).round() as usize).max(1),
+    };
+    fill.to_string().repeat(width)
+}
+
+/// A bar of `classes.total` of `max`, each class's share drawn with its own character. The
+/// class boundaries are rounded from running totals, so the pieces add up to the whole bar.
+fn stacked_bar(classes: &StatusClasses, max: u64) -> String {
+    let whole = bar(classes.total, max, ' ').len();
+    let mut out = String::new();
+    let (mut running, mut drawn) = (0, 0);
+    for ((count, fill), element) in classes.classes().into_iter().zip(CLASS_BARS).zip(CLASS_ELEMENTS) {
+        running += count;
+        let end = (running as f64 / classes.total.max(1) as f64 * whole as f64).round() as usize;
+        let piece = fill.to_string().repeat(end - drawn);
+        match element {
+            Some(element) => out += &style().paint(element, &piece),
+            None => out += &piece,
+        }
+        drawn = end;
+    }
+    out
+}
+
+pub struct Histogram {
+    /// Bucket width in seconds.
+    width: i64,
+    tz: Zone,
+    /// Keyed by each bucket's start in seconds since the epoch.
+    buckets: BTreeMap<i64, StatusClasses>,
+    /// Matches without a timestamp, which fall in no bucket.
+    untimed: u64,
+}
+
+impl Histogram {
+    /// Fails for buckets shorter than a second.
+    pub fn new(width: std::time::Duration, tz: Option<Zone>) -> Result<Self, String> {
+        let width = width.as_secs() as i64;
+        if width == 0 {
+            return Err("--bucket must be at least 1s".to_string());
+        }
+        Ok(Histogram { width, tz: tz.unwrap_or_else(Zone::utc), buckets: BTreeMap::new(), untimed: 0 })
+    }
+
+    pub fn add(&mut self, entry: &Entry) {
+        match entry.timestamp {
+            Some(timestamp) => {
+                let start = timestamp.timestamp().div_euclid(self.width) * self.width;
+                self.buckets.entry(start).or_default().add(entry);
+            }
+            None => self.untimed += 1,
+        }
+    }
+
+    /// Matches that had no timestamp.
+    pub fn untimed(&self) -> u64 {
+        self.untimed
+    }
+
+    /// Every bucket from the first to the last, empty ones included; fails when there would be
+    /// more than `max_buckets`.
+    fn rows(&self, max_buckets: usize) -> Result<Vec<(DateTime<FixedOffset>, StatusClasses)>, String> {
+        let (Some((&first, _)), Some((&last, _))) = (self.buckets.first_key_value(), self.buckets.last_key_value()) else {
+            return Ok(Vec::new());
+        };
+        let count = ((last - first) / self.width + 1) as usize;
+        if count > max_buckets {
+            return Err(format!(
+                "the matches span {} buckets of {}s, over --max-buckets {}; pick a wider --bucket",
+                count, self.width, max_buckets
+            ));
+        }
+        Ok((0..count as i64)
+            .map(|i| first + i * self.width)
+            .map(|start| {
+                (self.tz.at(start), self.buckets.get(&start).copied().unwrap_or_default())
+            })
+            .collect())
+    }
+
+    /// Renders the buckets with --split-status columns, --only-ratio's percentage, or just the
+    /// count. The HTML page charts the count, or with --only-ratio the percentage out of 100.
+    pub fn render(&self, split_status: bool, only_ratio: bool, output: HistogramOutput, max_buckets: usize) -> Result<String, String> {
+        let rows = self.rows(max_buckets)?;
+        let max = rows.iter().map(|(_, classes)| classes.total).max().unwrap_or_default();
+        let ratio = |classes: &StatusClasses| classes.server_error_ratio().map_or_else(|| "-".to_string(), |r| format!("{:.1}", r));
+        if matches!(output, HistogramOutput::Json | HistogramOutput::JsonArray) {
+            let mut out = String::new();
+            for (start, classes) in &rows {
+                let bucket = HistogramBucket {
+                    bucket: start.to_rfc3339(),
+                    total: classes.total,
+                    classes: split_status.then(|| classes.counts()),
+                    server_error_pct: only_ratio.then(|| classes.server_error_ratio()),
+                };
+                out += &format!("{}\n", json!(bucket));
+            }
+            return Ok(match output {
+                HistogramOutput::JsonArray => json_array::collect(&out),
+                _ => out,
+            });
+        }
+        let bars = output == HistogramOutput::Table;
+        let mut header = vec!["bucket", "count"];
+        if split_status {
+            header.extend(["2xx", "3xx", "4xx", "5xx", "other"]);
+        }
+        if only_ratio {
+            header.push("5xx_pct");
+        }
+        if bars {
+            header.push("bar");
+        }
+        let mut table = Table::new(&header);
+        for (start, classes) in &rows {
+            let mut row = vec![start.to_rfc3339(), classes.total.to_string()];
+            if split_status {
+                row.extend(classes.classes().map(|count| count.to_string()));
+            }
+            if only_ratio {
+                row.push(ratio(classes));
+            }
+            if bars {
+                row.push(match (split_status, only_ratio) {
+                    (true, _) => stacked_bar(classes, max),
+                    // The bar of a ratio is out of 100%, so buckets compare however busy they were.
+                    (_, true) => style().paint(
+                        Element::Status5xx,
+                        &bar(classes.server_error_ratio().unwrap_or_default().round() as u64, 100, '!'),
+                    ),
+                    _ => style().paint(Element::Bar, &bar(classes.total, max, '#')),
+                });
+            }
+            table.row(row);
+        }
+        Ok(match output {
+            HistogramOutput::Csv => table.delimited(&Delimiter::new(",".to_string(), DelimiterEscape::Quote)),
+            HistogramOutput::Html => {
+                let mut page = Page::default();
+                let label = |start: &DateTime<FixedOffset>| start.to_rfc3339();
+                match only_ratio {
+                    true => {
+                        let ratios: Vec<(String, f64)> = rows
+                            .iter()
+                            .map(|(start, classes)| (label(start), classes.server_error_ratio().unwrap_or_default()))
+                            .collect();
+                        page.columns(&ratios, 100.0);
+                    }
+                    false => {
+                        let counts: Vec<(String, f64)> =
+                            rows.iter().map(|(start, classes)| (label(start), classes.total as f64)).collect();
+                        page.columns(&counts, max as f64);
+                    }
+                }
+                page.table(&table);
+                page.render(&format!("Matches per bucket of {}s", self.width))
+            }
+            _ => table.render(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn request(time: &str, status: Option<u16>) -> Entry {
+        Entry {
+            timestamp: Some(DateTime::parse_from_rfc3339(time).unwrap()),
+            status_code: status.map(|status| http::StatusCode::from_u16(status).unwrap()),
+            ..Entry::default()
+        }
+    }
+
+    fn histogram(requests: &[(&str, Option<u16>)]) -> Histogram {
+        let mut histogram = Histogram::new(Duration::from_secs(60), None).unwrap();
+        for (time, status) in requests {
+            histogram.add(&request(time, *status));
+        }
+        histogram
+    }
+
+    #[test]
+    fn statuses_are_counted_in_their_class() {
+        let mut classes = StatusClasses::default();
+        for status in [Some(200), Some(204), Some(301), Some(404), Some(499), Some(500), Some(503), Some(101), None] {
+            classes.add(&request("2023-02-12T14:00:00Z", status));
+        }
+        assert_eq!(
+            classes,
+            StatusClasses { total: 9, success: 2, redirect: 1, client_error: 2, server_error: 2, other: 2 }
+        );
+        assert_eq!(StatusClasses::default().server_error_ratio(), None);
+    }
+
+    #[test]
+    fn buckets_are_split_by_class_with_gaps_filled() {
+        let histogram = histogram(&[
+            ("2023-02-12T14:00:05Z", Some(200)),
+            ("2023-02-12T14:00:59+00:00", Some(500)),
+            // The same minute, logged from another timezone.
+            ("2023-02-12T16:00:30+02:00", Some(404)),
+            ("2023-02-12T14:02:00Z", Some(200)),
+        ]);
+        assert_eq!(
+            histogram.render(true, false, HistogramOutput::Csv, 10).unwrap(),
+            "bucket,count,2xx,3xx,4xx,5xx,other\n\
+             2023-02-12T14:00:00+00:00,3,1,0,1,1,0\n\
+             2023-02-12T14:01:00+00:00,0,0,0,0,0,0\n\
+             2023-02-12T14:02:00+00:00,1,1,0,0,0,0\n"
+        );
+        assert_eq!(
+            histogram.render(false, true, HistogramOutput::Json, 10).unwrap().lines().next(),
+            Some("{\"5xx_pct\":33.333333333333336,\"bucket\":\"2023-02-12T14:00:00+00:00\",\"total\":3}")
+        );
+        assert!(histogram.render(false, false, HistogramOutput::Table, 2).unwrap_err().contains("span 3 buckets"));
+    }
+
+    #[test]
+    fn stacked_bars_add_up_to_the_total_bar() {
+        let classes = StatusClasses { total: 4, success: 2, redirect: 0, client_error: 1, server_error: 1, other: 0 };
+        assert_eq!(stacked_bar(&classes, 4).len(), BAR_WIDTH);
+        assert_eq!(stacked_bar(&classes, 4), format!("{}{}{}", "=".repeat(20), "?".repeat(10), "!".repeat(10)));
+        assert_eq!(stacked_bar(&classes, 400), "=");
+        assert_eq!(bar(0, 10, '#'), "");
+    }
+}
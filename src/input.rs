@@ -0,0 +1,554 @@
+use crate::{demo, mapped::Map};
+use clap::Args;
+use flate2::bufread::GzDecoder;
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
+    ops::{Deref, Range},
+    path::{self, Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Opens a log file for reading lines, decompressing it when it starts with the gzip magic bytes.
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    open_counted(path).map(|(reader, _)| reader)
+}
+
+/// Like `open`, with what the decoder of a gzipped file makes of it.
+pub fn open_counted(path: &Path) -> io::Result<(Box<dyn BufRead>, Option<Gzip>)> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut reader = BufReader::new(Counting { inner: file, count: 0 });
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        let gzip = Gzip { size, consumed: Arc::new(OnceLock::new()) };
+        let decoder = GzipReader { member: Some(GzDecoder::new(reader)), gzip: gzip.clone() };
+        return Ok((Box::new(BufReader::new(decoder)), Some(gzip)));
+    }
+    Ok((Box::new(reader), None))
+}
+
+/// How much of a gzipped file its decoder read. Logrotate's `compresscmd` can write a file of
+/// several gzip members one after another, all of which are decoded, so a decoder that stops
+/// before the end of the file has met something that isn't a gzip member and left it unread.
+#[derive(Clone, Debug)]
+pub struct Gzip {
+    /// The file's size, compressed.
+    pub size: u64,
+    consumed: Arc<OnceLock<u64>>,
+}
+
+impl Gzip {
+    /// The compressed bytes decoded, once the decoder has ended.
+    pub fn consumed(&self) -> Option<u64> {
+        self.consumed.get().copied()
+    }
+}
+
+/// Counts the bytes read through it.
+struct Counting<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+/// Decodes the members of a gzipped file one after another, up to the end of the file or to
+/// anything after a member that doesn't start like one, recording where that was. A member that
+/// fails to decode is an error, as it is for a file of one member.
+struct GzipReader {
+    /// The member being decoded, `None` once the last one ended.
+    member: Option<GzDecoder<BufReader<Counting<File>>>>,
+    gzip: Gzip,
+}
+
+impl Read for GzipReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Some(member) = &mut self.member else {
+                return Ok(0);
+            };
+            let read = member.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            let mut reader = self.member.take().map(GzDecoder::into_inner).expect("a member was being decoded");
+            // The buffer may end partway into the next header, so only its first byte is checked.
+            if reader.fill_buf()?.first() == Some(&GZIP_MAGIC[0]) {
+                self.member = Some(GzDecoder::new(reader));
+                continue;
+            }
+            let _ = self.gzip.consumed.set(reader.get_ref().count - reader.buffer().len() as u64);
+            return Ok(0);
+        }
+    }
+}
+
+/// Maps the file at `path` for reading when it's a plain file of at least `threshold` bytes and
+/// isn't gzipped; `None` when it should be streamed.
+pub fn map(path: &Path, threshold: u64) -> io::Result<Option<Arc<Map>>> {
+    // Looked up before opening, which would block on a pipe until it has a writer.
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_file() || metadata.len() < threshold {
+        return Ok(None);
+    }
+    let map = Map::open(File::open(path)?)?;
+    Ok(map.filter(|map| !map.bytes().starts_with(&GZIP_MAGIC)).map(Arc::new))
+}
+
+/// A line's text: read into its own buffer, or a slice of a mapped file.
+pub enum Text {
+    Owned(String),
+    /// Bytes of the map checked to be UTF-8.
+    Mapped(Arc<Map>, Range<usize>),
+}
+
+impl Text {
+    /// Removes a `\r` left at the end of the line, as `trim_line_ending` does.
+    pub fn trim_line_ending(&mut self) {
+        match self {
+            Text::Owned(line) => trim_line_ending(line),
+            Text::Mapped(map, range) => {
+                if map.bytes()[range.clone()].ends_with(b"\r") {
+                    range.end -= 1;
+                }
+            }
+        }
+    }
+
+    pub fn into_string(self) -> String {
+        match self {
+            Text::Owned(line) => line,
+            Text::Mapped(..) => self.to_string(),
+        }
+    }
+}
+
+impl Deref for Text {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            Text::Owned(line) => line,
+            // SAFETY: `MappedLines` only makes a `Mapped` of bytes that are valid UTF-8.
+            Text::Mapped(map, range) => unsafe { std::str::from_utf8_unchecked(&map.bytes()[range.clone()]) },
+        }
+    }
+}
+
+impl From<String> for Text {
+    fn from(line: String) -> Self {
+        Text::Owned(line)
+    }
+}
+
+/// One line from `LineReader`, or where a line too long to keep was skipped.
+pub enum ReadLine {
+    Line { text: Text, offset: u64 },
+    TooLong { offset: u64, length: u64 },
+}
+
+/// Reads lines like `BufRead::lines`, but never buffers more than `max` bytes of one line, so a
+/// corrupt file with megabytes between newlines can't balloon memory. Longer lines are skipped
+/// up to the next newline and reported as `TooLong`. Bytes that aren't valid UTF-8 are replaced
+/// by U+FFFD instead of failing the read.
+pub struct LineReader {
+    reader: Box<dyn BufRead>,
+    max: usize,
+    offset: u64,
+}
+
+impl LineReader {
+    pub fn new(reader: Box<dyn BufRead>, max: usize) -> Self {
+        LineReader { reader, max, offset: 0 }
+    }
+
+    /// Counts byte offsets from `offset`, for a reader that starts partway into its file.
+    pub fn starting_at(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Consumes input up to and including the next newline, returning the bytes before it.
+    fn skip_line(&mut self) -> io::Result<u64> {
+        let mut skipped = 0;
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if available.is_empty() {
+                return Ok(skipped);
+            }
+            let (length, newline) = match available.iter().position(|&b| b == b'\n') {
+                Some(i) => (i, true),
+                None => (available.len(), false),
+            };
+            self.reader.consume(length + usize::from(newline));
+            self.offset += (length + usize::from(newline)) as u64;
+            skipped += length as u64;
+            if newline {
+                return Ok(skipped);
+            }
+        }
+    }
+}
+
+impl Iterator for LineReader {
+    type Item = io::Result<ReadLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.offset;
+        let mut line = Vec::new();
+        let read = (&mut self.reader).take(self.max as u64 + 1).read_until(b'\n', &mut line);
+        match read {
+            Ok(0) => return None,
+            Ok(n) => self.offset += n as u64,
+            Err(e) => return Some(Err(e)),
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        } else if line.len() > self.max {
+            return Some(self.skip_line().map(|rest| ReadLine::TooLong { offset: start, length: line.len() as u64 + rest }));
+        }
+        let text = String::from_utf8(line).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+        Some(Ok(ReadLine::Line { text: Text::Owned(text), offset: start }))
+    }
+}
+
+/// The lines of a byte range of a mapped file, read like `LineReader` reads them, but as slices
+/// of the map. A line that isn't valid UTF-8 is copied with its invalid bytes replaced by U+FFFD,
+/// as `LineReader` does.
+pub struct MappedLines {
+    map: Arc<Map>,
+    max: usize,
+    offset: usize,
+    end: usize,
+}
+
+impl MappedLines {
+    /// Fails when the file has shrunk since it was mapped, e.g. truncated by log rotation, since
+    /// reading the pages past its new end would kill the process with SIGBUS.
+    pub fn new(map: Arc<Map>, range: Range<u64>, max: usize) -> io::Result<Self> {
+        if map.shrunk()? {
+            return Err(io::Error::other("the file shrank while it was mapped; was it truncated by log rotation?"));
+        }
+        let (offset, end) = (range.start as usize, range.end as usize);
+        Ok(MappedLines { map, max, offset, end })
+    }
+}
+
+impl Iterator for MappedLines {
+    type Item = io::Result<ReadLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.offset;
+        let rest = &self.map.bytes()[start..self.end];
+        if rest.is_empty() {
+            return None;
+        }
+        let length = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+        self.offset += (length + 1).min(rest.len());
+        if length > self.max {
+            return Some(Ok(ReadLine::TooLong { offset: start as u64, length: length as u64 }));
+        }
+        let mut line = &rest[..length];
+        if length < rest.len() {
+            line = line.strip_suffix(b"\r").unwrap_or(line);
+        }
+        let text = match std::str::from_utf8(line) {
+            Ok(_) => Text::Mapped(self.map.clone(), start..start + line.len()),
+            Err(_) => Text::Owned(String::from_utf8_lossy(line).into_owned()),
+        };
+        Some(Ok(ReadLine::Line { text, offset: start as u64 }))
+    }
+}
+
+/// The lines of one input: read through a map for large plain files, else streamed.
+pub enum InputSource {
+    /// Streamed lines, and for a gzipped file what its decoder made of it.
+    Stream(LineReader, Option<Gzip>),
+    Mapped(MappedLines),
+}
+
+impl InputSource {
+    pub fn gzip(&self) -> Option<Gzip> {
+        match self {
+            InputSource::Stream(_, gzip) => gzip.clone(),
+            InputSource::Mapped(_) => None,
+        }
+    }
+}
+
+impl Iterator for InputSource {
+    type Item = io::Result<ReadLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            InputSource::Stream(lines, _) => lines.next(),
+            InputSource::Mapped(lines) => lines.next(),
+        }
+    }
+}
+
+/// Removes a `\r` left at the end of a line, e.g. from a CR CR LF ending, so logs written on
+/// Windows filter the same as any other. `LineReader` already drops CR LF itself.
+pub fn trim_line_ending(line: &mut String) {
+    if line.ends_with('\r') {
+        line.pop();
+    }
+}
+
+fn has_wildcard(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+/// Splits a pattern into its directory, without the trailing separator, and file name.
+fn split_pattern(pattern: &str, is_separator: impl Fn(char) -> bool) -> (&str, &str) {
+    match pattern.rfind(is_separator) {
+        Some(0) => (&pattern[..1], &pattern[1..]),
+        Some(i) => (&pattern[..i], &pattern[i + 1..]),
+        None => ("", pattern),
+    }
+}
+
+/// Matches a file name against `*` (any run of characters) and `?` (any one character).
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    // The last `*` seen and the name position it currently stands in for, to backtrack to.
+    let (mut p, mut n, mut star) = (0, 0, None);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The files in a pattern's directory whose name matches its `*` and `?`, unsorted. Separators
+/// follow the platform, so `logs\access.log.*` works on Windows.
+pub fn glob(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let (dir, name) = split_pattern(pattern, path::is_separator);
+    if has_wildcard(dir) {
+        return Err(format!("{}: wildcards are only supported in the file name", pattern));
+    }
+    let parent = Path::new(dir);
+    let dir = if dir.is_empty() { Path::new(".") } else { parent };
+    let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    Ok(entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_str().is_some_and(|n| wildcard_match(name, n)))
+        .map(|entry| parent.join(entry.file_name()))
+        .collect())
+}
+
+/// Expands `*` and `?` in the file name of each argument that doesn't name an existing file,
+/// for shells such as cmd.exe that pass patterns through. Matches are sorted by name.
+pub fn expand(files: Vec<PathBuf>) -> Result<Vec<PathBuf>, String> {
+    let mut expanded = Vec::new();
+    for file in files {
+        let pattern = file.to_string_lossy();
+        if file.exists() || !has_wildcard(&pattern) {
+            expanded.push(file);
+            continue;
+        }
+        let mut matches = glob(&pattern)?;
+        if matches.is_empty() {
+            return Err(format!("{}: no files match", pattern));
+        }
+        matches.sort();
+        expanded.extend(matches);
+    }
+    Ok(expanded)
+}
+
+// Input files given after the command, for `log-parser filter access.log ...`.
+#[derive(Args, Debug, Default)]
+pub struct Inputs {
+    /// Log files to read, after any given before the command
+    #[arg(value_name = "FILE")]
+    files: Vec<PathBuf>,
+    /// Another log file to read, for when an option would take a trailing file name as its value
+    #[arg(long = "input", value_name = "FILE")]
+    input: Vec<PathBuf>,
+}
+
+impl Inputs {
+    pub fn into_files(self) -> Vec<PathBuf> {
+        self.files.into_iter().chain(self.input).collect()
+    }
+}
+
+/// The first of `args` that names an existing file other than the `inputs`, most likely a file
+/// name an option took as its value.
+pub fn stray_file(args: impl IntoIterator<Item = String>, inputs: &[PathBuf]) -> Option<String> {
+    args.into_iter().find(|arg| Path::new(arg).is_file() && !inputs.iter().any(|input| input == Path::new(arg)))
+}
+
+/// Where log lines come from: a file, or the sample built into the binary.
+pub enum Source {
+    File(PathBuf),
+    Demo,
+}
+
+impl Source {
+    pub fn path(&self) -> &Path {
+        match self {
+            Source::File(path) => path,
+            Source::Demo => Path::new(demo::NAME),
+        }
+    }
+
+    /// Size on disk, compressed for gzipped files.
+    pub fn size(&self) -> io::Result<u64> {
+        match self {
+            Source::File(path) => Ok(std::fs::metadata(path)?.len()),
+            Source::Demo => Ok(demo::SAMPLE.len() as u64),
+        }
+    }
+
+    /// Whether the input can be read more than once; pipes such as `<(zcat access.log.gz)`
+    /// can't.
+    pub fn rereadable(&self) -> bool {
+        match self {
+            Source::File(path) => std::fs::metadata(path).is_ok_and(|metadata| metadata.is_file()),
+            Source::Demo => true,
+        }
+    }
+
+    pub fn open(&self) -> io::Result<Box<dyn BufRead>> {
+        match self {
+            Source::File(path) => open(path),
+            Source::Demo => Ok(Box::new(demo::SAMPLE.as_bytes())),
+        }
+    }
+
+    /// Its lines, skipping those over `max` bytes; a plain file of at least `map_from` bytes is
+    /// read through a map.
+    pub fn lines(&self, max: usize, map_from: u64) -> io::Result<InputSource> {
+        if let Source::File(path) = self {
+            if let Some(map) = map(path, map_from)? {
+                let len = map.bytes().len() as u64;
+                return Ok(InputSource::Mapped(MappedLines::new(map, 0..len, max)?));
+            }
+        }
+        let (reader, gzip) = match self {
+            Source::File(path) => open_counted(path)?,
+            Source::Demo => (self.open()?, None),
+        };
+        Ok(InputSource::Stream(LineReader::new(reader, max), gzip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show(line: io::Result<ReadLine>) -> String {
+        match line {
+            Ok(ReadLine::Line { text, .. }) => text.into_string(),
+            Ok(ReadLine::TooLong { offset, length }) => format!("<{} bytes at {}>", length, offset),
+            Err(e) => format!("<{:?}: {}>", e.kind(), e),
+        }
+    }
+
+    fn read_lines(input: &'static str, max: usize) -> Vec<String> {
+        LineReader::new(Box::new(input.as_bytes()), max).map(show).collect()
+    }
+
+    #[test]
+    fn long_lines_are_skipped_to_the_next_newline() {
+        assert_eq!(read_lines("a\r\nbb\n\nccc", 3), vec!["a", "bb", "", "ccc"]);
+        assert_eq!(read_lines("abc\nabcd\nok\n", 3), vec!["abc", "<4 bytes at 4>", "ok"]);
+        assert_eq!(read_lines("ok\nxxxxxxxxxx", 3), vec!["ok", "<10 bytes at 3>"]);
+        assert_eq!(read_lines("", 3), Vec::<String>::new());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mapped_lines_read_like_streamed_lines() {
+        let path = std::env::temp_dir().join(format!("log-parser-mapped-{}.log", std::process::id()));
+        let inputs: [&[u8]; 5] = [b"a\r\nbb\n\nccc", b"abc\nabcd\nok\n", b"ok\nxxxxxxxxxx", b"\xff\xfeok\nfine\n", b"x\r"];
+        for input in inputs {
+            fs::write(&path, input).unwrap();
+            let map = map(&path, 0).unwrap().unwrap();
+            let mapped: Vec<String> = MappedLines::new(map.clone(), 0..input.len() as u64, 3).unwrap().map(show).collect();
+            let streamed: Vec<String> = LineReader::new(Box::new(input), 3).map(show).collect();
+            assert_eq!(mapped, streamed, "{:?}", String::from_utf8_lossy(input));
+        }
+        // A range reads just its own lines, with offsets from the start of the file.
+        fs::write(&path, "aaaa\nbbbb\ncc").unwrap();
+        let map = map(&path, 0).unwrap().unwrap();
+        let offsets: Vec<u64> = MappedLines::new(map, 5..12, 4)
+            .unwrap()
+            .map(|line| match line.unwrap() {
+                ReadLine::Line { offset, .. } | ReadLine::TooLong { offset, .. } => offset,
+            })
+            .collect();
+        assert_eq!(offsets, [5, 10]);
+        assert!(super::map(&path, 13).unwrap().is_none());
+        // A range of a file truncated since it was mapped isn't read.
+        let map = super::map(&path, 0).unwrap().unwrap();
+        fs::write(&path, "aaaa\n").unwrap();
+        assert!(MappedLines::new(map, 5..12, 4).is_err());
+        fs::write(&path, "").unwrap();
+        assert!(super::map(&path, 0).unwrap().is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn crlf_endings_are_trimmed_once() {
+        for (line, trimmed) in [("GET / \"curl\"\r", "GET / \"curl\""), ("plain", "plain"), ("\r", ""), ("a\r\r", "a\r")] {
+            let mut line = line.to_string();
+            trim_line_ending(&mut line);
+            assert_eq!(line, trimmed);
+        }
+    }
+
+    #[test]
+    fn patterns_split_on_either_separator() {
+        let windows = |c: char| c == '/' || c == '\\';
+        assert_eq!(split_pattern("C:\\logs\\access.log.*", windows), ("C:\\logs", "access.log.*"));
+        assert_eq!(split_pattern("logs/app\\*.log", windows), ("logs/app", "*.log"));
+        assert_eq!(split_pattern("\\*.log", windows), ("\\", "*.log"));
+        assert_eq!(split_pattern("*.log", windows), ("", "*.log"));
+        assert_eq!(split_pattern("logs\\*.log", |c| c == '/'), ("", "logs\\*.log"));
+    }
+
+    #[test]
+    fn wildcards_match_file_names() {
+        assert!(wildcard_match("access.log.*", "access.log.1"));
+        assert!(wildcard_match("access.log.*", "access.log."));
+        assert!(!wildcard_match("access.log.*", "access.log"));
+        assert!(wildcard_match("*.log.?.gz", "error.log.2.gz"));
+        assert!(!wildcard_match("*.log.?.gz", "error.log.12.gz"));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("a*b*c", "aXbYbZ"));
+    }
+}
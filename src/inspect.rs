@@ -0,0 +1,89 @@
+use crate::entry::Match;
+use std::collections::VecDeque;
+
+// `filter --inspect N` prints one match instead of all of them, a labeled field per line with
+// its line as logged and where it was read at the bottom, for a line too long to read across.
+// N counts matches from 1, or back from the last with -1. Counting forwards only the Nth match
+// is kept, and reading stops there; counting back the last |N| are, until the input ends.
+
+/// Parses an --inspect index, any whole number but 0.
+pub fn parse_index(s: &str) -> Result<i64, String> {
+    match s.parse::<i64>() {
+        Ok(0) => Err("matches count from 1, or back from the last with -1".to_string()),
+        Ok(index) => Ok(index),
+        Err(_) => Err(format!("expected a match number such as 3 or -1, got {:?}", s)),
+    }
+}
+
+/// The match --inspect picks, while the matches are read.
+pub struct Inspector {
+    index: i64,
+    seen: usize,
+    kept: VecDeque<Match>,
+}
+
+impl Inspector {
+    pub fn new(index: i64) -> Self {
+        Inspector { index, seen: 0, kept: VecDeque::new() }
+    }
+
+    /// Matches to read before the picked one is known, when counting forwards.
+    pub fn limit(&self) -> Option<usize> {
+        usize::try_from(self.index).ok()
+    }
+
+    pub fn add(&mut self, found: Match) {
+        self.seen += 1;
+        match usize::try_from(self.index) {
+            Ok(index) if self.seen == index => self.kept.push_back(found),
+            Ok(_) => {}
+            Err(_) => {
+                if self.kept.len() as u64 == self.index.unsigned_abs() {
+                    self.kept.pop_front();
+                }
+                self.kept.push_back(found);
+            }
+        }
+    }
+
+    /// The picked match and its number counting from 1, or why there's none.
+    pub fn finish(mut self) -> Result<(usize, Match), String> {
+        let plural = if self.seen == 1 { "match" } else { "matches" };
+        let position = match usize::try_from(self.index) {
+            Ok(index) => index,
+            Err(_) => (self.seen + 1).saturating_sub(self.index.unsigned_abs() as usize),
+        };
+        match self.kept.pop_front().filter(|_| position > 0) {
+            Some(found) => Ok((position, found)),
+            None => Err(format!("--inspect {} is out of range, there were {} {}", self.index, self.seen, plural)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::{Entry, Provenance};
+
+    fn pick(index: i64, matches: usize) -> Result<(usize, String), String> {
+        let mut inspector = Inspector::new(index);
+        for line_no in 1..=matches {
+            let provenance = Provenance { source_file: "access.log".into(), line_no, byte_offset: 0 };
+            inspector.add(Match { line: format!("line {}", line_no), entry: Entry::default(), provenance });
+        }
+        inspector.finish().map(|(position, found)| (position, found.line))
+    }
+
+    #[test]
+    fn matches_are_picked_from_either_end() {
+        assert_eq!(pick(1, 3), Ok((1, "line 1".to_string())));
+        assert_eq!(pick(3, 3), Ok((3, "line 3".to_string())));
+        assert_eq!(pick(-1, 3), Ok((3, "line 3".to_string())));
+        assert_eq!(pick(-3, 3), Ok((1, "line 1".to_string())));
+        assert_eq!(pick(4, 3), Err("--inspect 4 is out of range, there were 3 matches".to_string()));
+        assert_eq!(pick(-4, 3), Err("--inspect -4 is out of range, there were 3 matches".to_string()));
+        assert_eq!(pick(-1, 0), Err("--inspect -1 is out of range, there were 0 matches".to_string()));
+        assert!(parse_index("0").is_err());
+        assert_eq!(parse_index("-2"), Ok(-2));
+    }
+}
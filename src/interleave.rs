@@ -0,0 +1,97 @@
+use chrono::{DateTime, FixedOffset};
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+// `filter --interleave` merges the matches of several inputs by timestamp. Each input holds at
+// most one pending match here, so memory doesn't grow with the inputs, and the earliest is
+// printed next. Entries without a timestamp keep their place within their own input.
+
+struct Pending<T> {
+    timestamp: Option<DateTime<FixedOffset>>,
+    source: usize,
+    item: T,
+}
+
+impl<T> Pending<T> {
+    fn key(&self) -> (Option<DateTime<FixedOffset>>, usize) {
+        (self.timestamp, self.source)
+    }
+}
+
+impl<T> PartialEq for Pending<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl<T> Eq for Pending<T> {}
+
+impl<T> PartialOrd for Pending<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Reversed, so the max-heap pops the earliest match, and the first input on ties.
+impl<T> Ord for Pending<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key().cmp(&self.key())
+    }
+}
+
+pub struct Interleave<T> {
+    pending: BinaryHeap<Pending<T>>,
+    /// Each input's latest timestamp, standing in for its entries without one.
+    latest: Vec<Option<DateTime<FixedOffset>>>,
+}
+
+impl<T> Interleave<T> {
+    pub fn new(sources: usize) -> Self {
+        Interleave { pending: BinaryHeap::with_capacity(sources), latest: vec![None; sources] }
+    }
+
+    /// Queues the next match of `source`, which must not have one pending.
+    pub fn push(&mut self, source: usize, timestamp: Option<DateTime<FixedOffset>>, item: T) {
+        if timestamp.is_some() {
+            self.latest[source] = timestamp;
+        }
+        self.pending.push(Pending { timestamp: self.latest[source], source, item });
+    }
+
+    /// Takes the earliest pending match, with the input it came from and its effective timestamp.
+    pub fn pop(&mut self) -> Option<(usize, Option<DateTime<FixedOffset>>, T)> {
+        self.pending.pop().map(|pending| (pending.source, pending.timestamp, pending.item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> Option<DateTime<FixedOffset>> {
+        Some(DateTime::parse_from_rfc3339(time).unwrap())
+    }
+
+    #[test]
+    fn pops_the_earliest_and_the_first_input_on_ties() {
+        let mut merge = Interleave::new(3);
+        merge.push(2, at("2023-02-12T14:00:00Z"), "c");
+        merge.push(0, at("2023-02-12T14:00:01Z"), "a");
+        merge.push(1, at("2023-02-12T15:00:00+01:00"), "b");
+        let order: Vec<_> = std::iter::from_fn(|| merge.pop()).map(|(source, _, item)| (source, item)).collect();
+        assert_eq!(order, vec![(1, "b"), (2, "c"), (0, "a")]);
+    }
+
+    #[test]
+    fn entries_without_a_timestamp_follow_the_one_before() {
+        let mut merge = Interleave::new(2);
+        merge.push(0, at("2023-02-12T14:00:05Z"), "a");
+        merge.push(1, None, "untimed first");
+        assert_eq!(merge.pop().map(|(_, _, item)| item), Some("untimed first"));
+        merge.push(1, at("2023-02-12T14:00:01Z"), "b");
+        assert_eq!(merge.pop().map(|(_, _, item)| item), Some("b"));
+        merge.push(1, None, "untimed after b");
+        assert_eq!(merge.pop(), Some((1, at("2023-02-12T14:00:01Z"), "untimed after b")));
+        assert_eq!(merge.pop().map(|(_, _, item)| item), Some("a"));
+        assert!(merge.pop().is_none());
+    }
+}
@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Ctrl-C stops reading instead of killing the process, so matches buffered for --sort, the
+// remaining output and the summary still come out, the summary marked as interrupted. A second
+// Ctrl-C exits at once, unless a pager still has the terminal. Quitting the pager stops reading
+// the same way, but the run still succeeds.
+
+/// Exit status after an interruption, the one shells report for SIGINT.
+pub const EXIT_CODE: u8 = 130;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static STOPPED: AtomicBool = AtomicBool::new(false);
+static PAGING: AtomicBool = AtomicBool::new(false);
+
+pub fn install() {
+    let _ = ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) && !PAGING.load(Ordering::SeqCst) {
+            crate::sort::abandon();
+        }
+    });
+}
+
+/// Whether to stop reading, checked before each line is read.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed) || STOPPED.load(Ordering::Relaxed)
+}
+
+/// Whether Ctrl-C was pressed, which sets the exit status.
+pub fn pressed() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Stops reading because nothing more of the output will be seen.
+pub fn stop() {
+    STOPPED.store(true, Ordering::Relaxed);
+}
+
+/// Tells a second Ctrl-C not to exit while a pager runs, since the pager would be left drawing
+/// over the shell.
+pub fn set_paging(paging: bool) {
+    PAGING.store(paging, Ordering::SeqCst);
+}
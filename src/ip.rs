@@ -0,0 +1,180 @@
+use std::{collections::HashSet, fmt, net::IpAddr, str::FromStr};
+
+/// A single address or a CIDR block, e.g. `10.0.0.1`, `2001:db8::/32`.
+///
+/// IPv4-mapped IPv6 addresses (`::ffff:1.2.3.4`) are treated as their IPv4 equivalent on both
+/// sides of a comparison, so `--ip eq 1.2.3.4` matches a client logged as `::ffff:1.2.3.4`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+fn max_prefix(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn bits(addr: &IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(v4) => u32::from(*v4) as u128,
+        IpAddr::V6(v6) => u128::from(*v6),
+    }
+}
+
+impl IpRange {
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        let addr = addr.to_canonical();
+        if addr.is_ipv4() != self.network.is_ipv4() {
+            return false;
+        }
+        let host_bits = max_prefix(&addr) - self.prefix;
+        let mask = u128::MAX.checked_shl(host_bits as u32).unwrap_or(0);
+        bits(&addr) & mask == bits(&self.network) & mask
+    }
+
+    /// The address, if this range is a single host.
+    fn address(&self) -> Option<IpAddr> {
+        (self.prefix == max_prefix(&self.network)).then_some(self.network)
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let parsed: IpAddr = addr.parse().map_err(|e| format!("{}", e))?;
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max_prefix(&parsed))
+                .ok_or_else(|| format!("invalid prefix length /{}", prefix))?,
+            None => max_prefix(&parsed),
+        };
+        let network = parsed.to_canonical();
+        let prefix = match (parsed, network) {
+            (IpAddr::V6(_), IpAddr::V4(_)) if prefix >= 96 => prefix - 96,
+            (IpAddr::V6(_), IpAddr::V4(_)) => return Ok(IpRange { network: parsed, prefix }),
+            _ => prefix,
+        };
+        Ok(IpRange { network, prefix })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.prefix == max_prefix(&self.network) {
+            write!(f, "{}", self.network)
+        } else {
+            write!(f, "{}/{}", self.network, self.prefix)
+        }
+    }
+}
+
+/// Addresses and CIDR blocks for `--ip in`. Single addresses are hashed, so lists of thousands
+/// of clients loaded from a file cost one lookup per entry.
+#[derive(Debug, Default)]
+pub struct IpSet {
+    addresses: HashSet<IpAddr>,
+    blocks: Vec<IpRange>,
+}
+
+impl IpSet {
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        self.addresses.contains(&addr.to_canonical()) || self.blocks.iter().any(|block| block.contains(addr))
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len() + self.blocks.len()
+    }
+
+    /// Blocks in the order given, then addresses sorted.
+    pub fn ranges(&self) -> Vec<IpRange> {
+        let mut addresses: Vec<IpAddr> = self.addresses.iter().copied().collect();
+        addresses.sort();
+        let addresses = addresses.into_iter().map(|network| IpRange { network, prefix: max_prefix(&network) });
+        self.blocks.iter().copied().chain(addresses).collect()
+    }
+}
+
+impl FromIterator<IpRange> for IpSet {
+    fn from_iter<I: IntoIterator<Item = IpRange>>(iter: I) -> Self {
+        let mut set = IpSet::default();
+        for range in iter {
+            match range.address() {
+                Some(address) => {
+                    set.addresses.insert(address);
+                }
+                None => set.blocks.push(range),
+            }
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(s: &str) -> IpRange {
+        s.parse().unwrap()
+    }
+
+    fn addr(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn single_addresses_match_any_spelling() {
+        assert!(range("2001:db8::1").contains(&addr("2001:0db8:0000:0000:0000:0000:0000:0001")));
+        assert!(!range("2001:db8::1").contains(&addr("2001:db8::2")));
+        assert!(range("192.0.2.1").contains(&addr("192.0.2.1")));
+    }
+
+    #[test]
+    fn cidr_blocks() {
+        assert!(range("10.0.0.0/8").contains(&addr("10.200.1.1")));
+        assert!(!range("10.0.0.0/8").contains(&addr("11.0.0.1")));
+        assert!(range("2001:db8::/32").contains(&addr("2001:db8:ffff::1")));
+        assert!(!range("2001:db8::/32").contains(&addr("2001:db9::1")));
+        assert!(range("0.0.0.0/0").contains(&addr("203.0.113.7")));
+        assert!(!range("0.0.0.0/0").contains(&addr("2001:db8::1")));
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_are_ipv4() {
+        assert!(range("192.0.2.4").contains(&addr("::ffff:192.0.2.4")));
+        assert!(range("::ffff:192.0.2.4").contains(&addr("192.0.2.4")));
+        assert_eq!(range("::ffff:192.0.2.0/120"), range("192.0.2.0/24"));
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        assert_eq!("10.0.0.0/33".parse::<IpRange>(), Err("invalid prefix length /33".to_string()));
+        assert_eq!("1.2.3".parse::<IpRange>(), Err("invalid IP address syntax".to_string()));
+    }
+
+    #[test]
+    fn sets_mix_addresses_and_blocks() {
+        let set: IpSet = ["192.0.2.7", "2001:db8::/32", "::ffff:198.51.100.1", "192.0.2.7"].iter().map(|s| range(s)).collect();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&addr("192.0.2.7")));
+        assert!(set.contains(&addr("198.51.100.1")));
+        assert!(set.contains(&addr("2001:db8:1::9")));
+        assert!(!set.contains(&addr("192.0.2.8")));
+        assert_eq!(set.ranges(), vec![range("2001:db8::/32"), range("192.0.2.7"), range("198.51.100.1")]);
+    }
+
+    #[test]
+    fn display_omits_full_prefix() {
+        assert_eq!(range("2001:0db8::0001").to_string(), "2001:db8::1");
+        assert_eq!(range("10.1.2.3/8").to_string(), "10.1.2.3/8");
+    }
+}
@@ -0,0 +1,130 @@
+use chrono::{DateTime, Datelike, FixedOffset};
+use std::str::FromStr;
+
+// Timestamps in the Apache access log form, `12/Feb/2023:14:03:45 +0000`, read and written with
+// our own table of English month abbreviations rather than strftime's `%b`, so what `convert`
+// writes always parses again whatever the locale. Shared by the parsers, the combined output and
+// the options that take a time, which accept the form as well as RFC 3339.
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+const APACHE_NUMERIC: &str = "%d/%m/%Y:%H:%M:%S %z";
+
+/// The month, 1 to 12, of an English abbreviation in any case, e.g. `feb`.
+pub fn month(name: &str) -> Option<u32> {
+    MONTHS.iter().position(|month| month.eq_ignore_ascii_case(name)).map(|index| index as u32 + 1)
+}
+
+/// The date of an Apache timestamp in numbers, `12/Feb/2023:14:03:45` as `12/02/2023:14:03:45`,
+/// with a one-digit day padded, for chrono to parse with `%d/%m/%Y`. `None` unless the value
+/// starts with a day and a month.
+pub fn numeric(value: &str) -> Option<String> {
+    let (day, rest) = value.split_once('/')?;
+    let (name, rest) = rest.split_once('/')?;
+    if day.is_empty() || day.len() > 2 || !day.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(format!("{:0>2}/{:02}/{}", day, month(name)?, rest))
+}
+
+/// `12/Feb/2023:14:03:45 +0000`.
+pub fn format(timestamp: &DateTime<FixedOffset>) -> String {
+    format!("{:02}/{}/{}", timestamp.day(), MONTHS[timestamp.month0() as usize], timestamp.format("%Y:%H:%M:%S %z"))
+}
+
+/// Parses an Apache timestamp such as `12/Feb/2023:14:03:45 +0000`, with the month in any case
+/// and a day of one or two digits.
+pub fn parse(value: &str) -> Result<DateTime<FixedOffset>, String> {
+    let numeric = numeric(value).ok_or_else(|| invalid(value))?;
+    DateTime::parse_from_str(&numeric, APACHE_NUMERIC).map_err(|_| invalid(value))
+}
+
+fn invalid(value: &str) -> String {
+    format!("invalid timestamp {:?}: expected RFC 3339 or e.g. 12/Feb/2023:14:03:45 +0000", value)
+}
+
+/// Parses a time given to an option: RFC 3339 such as `2023-02-12T14:03:45Z`, or an Apache
+/// timestamp that may leave out the offset for UTC and the time of day for midnight, e.g.
+/// `12/Feb/2023`.
+pub fn parse_option(value: &str) -> Result<DateTime<FixedOffset>, String> {
+    if !value.contains('/') {
+        return value.parse::<DateTime<FixedOffset>>().map_err(|e| e.to_string());
+    }
+    let numeric = numeric(value).ok_or_else(|| invalid(value))?;
+    let complete = match numeric.split(':').count() {
+        1 => format!("{}:00:00:00 +0000", numeric),
+        4 if !numeric.contains(' ') => format!("{} +0000", numeric),
+        _ => numeric,
+    };
+    DateTime::parse_from_str(&complete, APACHE_NUMERIC).map_err(|_| invalid(value))
+}
+
+/// A time in a filter value, see `parse_option`.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct LogTime(pub DateTime<FixedOffset>);
+
+impl FromStr for LogTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_option(s).map(LogTime)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [(&str, u32); 12] = [
+        ("Jan", 1),
+        ("Feb", 2),
+        ("Mar", 3),
+        ("Apr", 4),
+        ("May", 5),
+        ("Jun", 6),
+        ("Jul", 7),
+        ("Aug", 8),
+        ("Sep", 9),
+        ("Oct", 10),
+        ("Nov", 11),
+        ("Dec", 12),
+    ];
+
+    #[test]
+    fn every_month_round_trips() {
+        for (name, number) in ALL {
+            let text = format!("05/{}/2023:14:03:45 +0100", name);
+            let parsed = parse(&text).unwrap();
+            assert_eq!((parsed.month(), parsed.day()), (number, 5), "{}", text);
+            assert_eq!(format(&parsed), text);
+
+            let written = DateTime::parse_from_rfc3339(&format!("2023-{:02}-28T23:59:59-05:00", number)).unwrap();
+            assert_eq!(format(&written), format!("28/{}/2023:23:59:59 -0500", name));
+            assert_eq!(parse(&format(&written)), Ok(written));
+        }
+    }
+
+    #[test]
+    fn months_in_any_case_and_one_digit_days() {
+        let expected = DateTime::parse_from_rfc3339("2023-02-02T14:03:45Z").unwrap();
+        for text in ["02/Feb/2023:14:03:45 +0000", "2/Feb/2023:14:03:45 +0000", "02/FEB/2023:14:03:45 +0000", "2/feb/2023:14:03:45 +0000"] {
+            assert_eq!(parse(text), Ok(expected), "{}", text);
+        }
+        assert_eq!(format(&expected), "02/Feb/2023:14:03:45 +0000");
+        assert_eq!(month("sEp"), Some(9));
+        for text in ["12/Febr/2023:14:03:45 +0000", "123/Feb/2023:14:03:45 +0000", "/Feb/2023:14:03:45 +0000", "12-Feb-2023"] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn options_take_rfc_3339_or_apache_times() {
+        let parse = |value: &str| parse_option(value).map(|t| t.to_rfc3339());
+        assert_eq!(parse("2023-02-12T14:03:45+01:00"), Ok("2023-02-12T14:03:45+01:00".to_string()));
+        assert_eq!(parse("12/Feb/2023:14:03:45 +0100"), Ok("2023-02-12T14:03:45+01:00".to_string()));
+        assert_eq!(parse("12/feb/2023:14:03:45"), Ok("2023-02-12T14:03:45+00:00".to_string()));
+        assert_eq!(parse("1/Mar/2023"), Ok("2023-03-01T00:00:00+00:00".to_string()));
+        assert_eq!(parse("2023-02-12"), Err("premature end of input".to_string()));
+        assert!(parse("12/Feb/2023:14").is_err());
+    }
+}
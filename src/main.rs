@@ -1,138 +1,2794 @@
-use access_log_parser::{parse, CombinedLogEntry, LogEntry, LogType};
-use chrono::{DateTime, FixedOffset};
-use http::StatusCode;
-use std::{fs::File, io::BufRead, net::IpAddr, str::FromStr};
-use rs_filter::{Filterable, filter_for, EqFilter, OrdFilter, StringFilter};
-use std::path::PathBuf;
-use clap::{Args, Parser, Subcommand};
+mod after_status;
+mod annotate;
+mod anomalies;
+mod auth;
+mod batch;
+mod bench;
+mod budget;
+mod cardinality;
+mod classify;
+mod config;
+mod demo;
+mod detect;
+mod diagnostics;
+mod duplicates;
+mod duration;
+mod entry;
+mod expression;
+mod extract;
+mod fail_on;
+mod fields;
+mod filter;
+mod filter_file;
+mod follow;
+mod format;
+mod fuzzy;
+mod gap;
+mod group;
+mod grouped;
+mod histogram;
+mod inspect;
+mod input;
+mod interleave;
+mod interrupt;
+mod ip;
+mod log_time;
+mod mapped;
+mod normalize;
+mod novel;
+mod order;
+mod overlap;
+mod partition;
+mod referrer;
+mod registry;
+mod report;
+mod retain;
+mod rules;
+mod sample;
+mod output;
+mod parsers;
+mod scanners;
+mod self_test;
+mod sink;
+mod size;
+mod snapshot;
+mod sort;
+mod space_saving;
+mod stats;
+mod status;
+mod suspicious;
+mod throttle;
+mod tls;
+mod top;
+mod zone;
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    io::IsTerminal,
+    num::NonZeroUsize,
+    ops::Range,
+    process::ExitCode,
+    rc::Rc,
+    sync::{mpsc, Arc},
+    thread,
+    time::Instant,
+};
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use rs_filter::{Filterable, OrdFilter};
+use std::{iter::Enumerate, path::{Path, PathBuf}};
+use clap::{Parser, Subcommand, ValueEnum};
+use after_status::StatusHistory;
+use annotate::Marker;
+use anomalies::Anomalies;
+use auth::{Audit, AuthOutput};
+use cardinality::Cardinality;
+use classify::{Classifier, Derived};
+use referrer::Referrers;
+use report::{Report, ReportOutput};
+use config::Config;
+use budget::MemoryBudget;
+use batch::{Batch, Job};
+use bench::{BenchOutput, Iteration, NoTiming, Stage, StageTimer, StageTimes};
+use duration::HumanDuration;
+use diagnostics::{Error, ErrorFormat, Errors, Notice, Reporter, RunSummary, Warning};
+use entry::{Client, Entry, Match, Provenance};
+use extract::{Extraction, Extractions};
+use fail_on::{Condition, Tally};
+use filter::{EntryFilter, FilterArgs, Presence};
+use duplicates::Duplicates;
+use inspect::Inspector;
+use filter_file::Watch;
+use follow::FollowGlob;
+use input::{Gzip, InputSource, Inputs, LineReader, MappedLines, ReadLine, Source, Text};
+use interleave::Interleave;
+use format::LogFormat;
+use gap::Gaps;
+use parsers::{LineParser, LogKind, Wrapper, Wrapping};
+use novel::{Novel, NovelKey, Place, Window};
+use order::Order;
+use output::{dictionary::Dictionary, escape::Escape, html::Page, pager::{self, PagerMode}, render_match, schema, style::{self, ColorMode, Style, Theme}, table::{self, Align, Layout}, template::Template, Conversion, Delimiter, DelimiterEscape, FieldSelection, OutputFormat, TimeFormat};
+use scanners::{Detector, ScannerOutput, Thresholds};
+use retain::{Keep, Retained};
+use rules::{CheckOutput, Checks};
+use sample::Rng;
+use sink::{ChunkLimits, Sink};
+use snapshot::{SnapshotArgs, Snapshots};
+use sort::ExternalSorter;
+use group::{GroupBy, Groups, KeyParser};
+use grouped::Grouped;
+use histogram::{Histogram, HistogramOutput};
+use stats::{Breakdown, BreakdownKey, Count, MatchedBytes, Metric, StatsOutput, Summary, SummaryOutput};
+use throttle::{Throttle, ThrottleSpec};
+use top::{Top, TopOutput};
+use zone::Zone;
 
 // desired syntax:
+// log-filter demo filter --status-code eq 404 --user-agent contains bot
+// log-filter demo --write sample.log
 // log-filter <file> filter --user-agent contains "Chrome"
+// log-filter filter access.log --ip eq 1.2.3.4
+// log-filter filter --user-agent Googlebot --input access.log
 // log-filter <file> filter --ip eq "193.105.7.171"
+// log-filter <file> filter --ip in "10.0.0.0/8,2001:db8::/32"
+// log-filter <file> filter --status-code eq 500 --fields ip --unique > bad_ips.txt
+// log-filter access.log.* filter --status-code eq 500 --fields ip,path --with-provenance
+// log-filter <file> filter --ip in @bad_ips.txt
+// log-filter <file> filter --user-agent not_contains_any @blocklist.txt
+// log-filter <file> filter --bytes gt 1.5M
+// log-filter <file> filter --user-agent Googlebot --implicit-operator eq
+// log-filter <file> filter --user-agent "literal:contains weird"
+// log-filter access.log.* filter --status-code eq 500 --count-by day --tz +02:00
+// log-filter web1.log web2.log filter --status-code eq 500 --interleave --limit 20
+// log-filter access.log.1 access.log --dedupe-overlap stats --metric bytes
+// log-filter <file> stats --by ip --metric gap
+// log-filter <file> filter --referrer none --user-agent some
+// log-filter <file> stats --by suspicious --suspicious-paths
+// log-filter <file> filter --status-code eq 404 --annotate --limit 20
+// log-filter <file> filter --status-code gte 500 --fail-on 'match-rate>0.05' --fail-on malformed --count
+// log-filter <file> filter --status-code gte 500 --ip in 193.105.7.0/24 --verbose-matches
+// log-filter <file> filter --status-code eq 404 --output-file 404.log --unmatched-file rest.log.gz
+// PAGER='less -RS' log-filter <file> filter --all --pager always
+// log-filter <file> filter --all --exclude "path starts_with /static/, status_code eq 200" --exclude "user_agent contains bot"
+// log-filter <file> filter --status-code eq 500 --throttle ip:60s
+// log-filter <file> filter --ip eq "193.105.7.171" --count --show-bytes
 // log-filter <file> filter --timestamp gt "2023-02-12T14:34:20+00:00" --ip eq "193.105.7.171"
+// log-filter <file> --log-type alb filter --field target_processing_time gt 1
+// log-filter <file> --log-type alb filter --where 'target_processing_time / (request_processing_time + target_processing_time + response_processing_time) > 0.8'
+// log-filter <file> --log-type haproxy filter --backend eq api_servers --response-time gt 2000
+// log-filter <file> --strip-syslog filter --host eq web01
+// log-filter <file> --unwrap cri filter --status-code gte 500
+// log-filter <file> --log-type nginx-error filter --severity gte error
+// log-filter <file> --log-type w3c filter --field time-taken gt 1.5
+// log-filter export.csv --log-type csv --csv-map ip=src_addr,status=http_status stats --by status
+// log-filter <file> --log-type haproxy filter --duration gt 250ms --sort duration
+// log-filter <file> --log-type alb stats --metric duration --status-code eq 200
+// log-filter <file> stats --by path-prefix,status --pivot
+// log-filter <file> top path --baseline-since 2023-02-12T13:00:00Z --baseline-until 2023-02-12T14:00:00Z
+// log-filter <file> top ip --relative-file last-week.log -o csv
+// log-filter <file> top user-agent --approximate --sketch-size 100000
+// log-filter <file> histogram --bucket 300s --split-status
+// log-filter <file> histogram --bucket 60s --only-ratio -o csv > errors.csv
+// log-filter <file> --log-type alb filter --all --output combined
+// log-filter <file> --time-format 'custom:%Y-%m-%d %H:%M' filter --all --fields timestamp,path
+// log-filter <file> --delimiter '\0' filter --status-code eq 404 --fields path | xargs -0 -n1 echo
+// log-filter <file> filter --all -o json --dict-fields user_agent,referrer --output-file matches.json --dict-file dictionary.json
+// log-filter <file> bench --iterations 3 --status-code eq 500
+// log-filter <file> novel --baseline-since 2023-02-12T13:00:00Z --baseline-until 2023-02-12T14:00:00Z --target-since 2023-02-12T14:00:00Z --target-until 2023-02-12T15:00:00Z --key ip+path
+// log-filter <file> anomalies --metric bytes --by path-prefix --baseline-since 2023-02-12T00:00:00Z --baseline-until 2023-02-12T12:00:00Z --sigma 3
+// log-filter <file> scanners --min-404 50 --min-distinct-paths 30 --window 600s
+// log-filter <file> auth --path starts_with /admin --window 5m --max-failures 20
+// log-filter <file> filter --status-code eq 503 --user-agent contains bot --explain-filter
+// log-filter <file> --summary filter --status-code eq 503 --user-agent contains bot
+// log-filter <file> --format-string '$remote_addr [$time_local] "$request" $status $request_time' filter --field request_time gt 1.5
+// log-filter <file> filter --only-malformed-requests --output json
+// log-filter <file> top path -n 100 --sort key > today.tsv && diff yesterday.tsv today.tsv
+// log-filter big.log --mmap-threshold 0 --jobs 4 filter --status-code gte 500 --count
+// log-filter week/*.log.gz report --tz Europe/Berlin --site-host example.com -o markdown
+// log-filter week/*.log.gz report --tz Europe/Berlin -o html --output-file week.html
 
 #[derive(Parser, Debug)]
-#[command(about = "Parse logs from a given file", name = "log-parser")]
+#[command(about = "Parse logs from the given files", name = "log-parser", subcommand_precedence_over_arg = true)]
 struct Cli {
-    file: PathBuf,
+    /// Log files to read in order, e.g. a week of rotated logs. They may also follow the command
+    files: Vec<PathBuf>,
+    #[arg(long, value_enum, global = true, default_value_t)]
+    log_type: LogKind,
+    /// Custom log format, in nginx (`$remote_addr`) or Apache (`%h`) syntax
+    #[arg(long, global = true, conflicts_with = "log_type")]
+    format_string: Option<String>,
+    /// With --log-type csv, the column to read a field from when the export names it otherwise,
+    /// e.g. `ip=src_addr,status=http_status`
+    #[arg(long, global = true, value_name = "FIELD=COLUMN", value_parser = parsers::parse_csv_mapping, value_delimiter = ',')]
+    csv_map: Vec<(&'static str, String)>,
+    /// Remove RFC3164/RFC5424 syslog prefixes before parsing
+    #[arg(long, global = true)]
+    strip_syslog: bool,
+    /// Decode each line from the record a container runtime logged it in before parsing,
+    /// joining lines the runtime split over several records
+    #[arg(long, value_enum, global = true, default_value_t)]
+    unwrap: Wrapper,
+    /// Give entries without a timestamp the time of their --unwrap record
+    #[arg(long, global = true, requires = "unwrap")]
+    wrapper_time: bool,
+    /// Abort on the first line that fails to parse instead of skipping it
+    #[arg(long, global = true)]
+    strict: bool,
+    /// Count a line whose bytes aren't a byte count, e.g. `-5` or text, as malformed, instead of
+    /// keeping the entry without them
+    #[arg(long, global = true)]
+    strict_numbers: bool,
+    /// Report each entry whose timestamp is earlier than the previous entry's
+    #[arg(long, global = true)]
+    check_order: bool,
+    /// How far timestamps may go backwards before it counts as a regression, e.g. `2s`
+    #[arg(long, global = true, default_value = "0s")]
+    order_slack: HumanDuration,
+    /// Timezone for per-day breakdowns: `UTC`, an offset such as `+02:00`, or a zone such as
+    /// `Europe/Berlin` whose offset follows daylight saving time
+    #[arg(long, global = true, value_parser = zone::parse, allow_hyphen_values = true)]
+    tz: Option<Zone>,
+    /// Timestamps in JSON and --fields output: rfc3339, unix, unix-ms, apache or custom:<strftime>
+    #[arg(long, global = true, default_value = "rfc3339")]
+    time_format: TimeFormat,
+    /// Column separator for --fields rows and tables; `\t` and `\0` (NUL, also ending each
+    /// row) are understood
+    #[arg(long, global = true, value_parser = output::parse_delimiter, default_value = "\\t")]
+    delimiter: String,
+    /// How values containing the delimiter are written
+    #[arg(long, value_enum, global = true, default_value_t)]
+    delimiter_escape: DelimiterEscape,
+    /// Whether tables are padded into aligned columns rather than joined with --delimiter
+    #[arg(long, value_enum, global = true, default_value_t)]
+    align: Align,
+    /// Print long values in aligned tables whole instead of shortening them with `…`
+    #[arg(long, global = true)]
+    no_truncate: bool,
+    /// Whether to color filter lines by status class, table headers, histogram bars and
+    /// --annotate markers
+    #[arg(long, value_enum, global = true, default_value_t)]
+    color: ColorMode,
+    /// The palette to color with, before the config file's `[theme]` overrides
+    #[arg(long, value_enum, global = true)]
+    theme: Option<Theme>,
+    /// Read settings from this file instead of log-parser/config.toml under $XDG_CONFIG_HOME
+    /// or ~/.config
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// The site's own hosts, comma-separated, e.g. `example.com`: referrers on them or their
+    /// subdomains are `internal` in referrer_class
+    #[arg(long, global = true, value_delimiter = ',', value_name = "HOST")]
+    site_host: Vec<String>,
+    /// Print the end-of-run summary for every file, including how many entries each filter
+    /// rejected. A rejection counts against the first failing filter, checked in the order
+    /// user agent, referrer, status code, ip, path, timestamp, severity, duration, other fields
+    #[arg(long, global = true)]
+    summary: bool,
+    /// Skip lines longer than this as malformed, e.g. `1M`; --strict stops at the first one
+    #[arg(long, global = true, value_parser = size::parse_size::<usize>, default_value = "64K")]
+    max_line_length: usize,
+    /// Read plain files at least this large through a memory map rather than read calls, e.g.
+    /// `1G`; `0` maps every plain file. Builds without the mmap feature, the default, always use
+    /// read calls
+    #[arg(long, global = true, value_parser = size::parse_size::<u64>, default_value = "16M")]
+    mmap_threshold: u64,
+    /// Memory a run may take for what it holds on to, e.g. `512M`: past it --sort spills to
+    /// temporary files, and --unique, --latest-per/--oldest-per and --by groups fail
+    #[arg(long, global = true, value_parser = size::parse_size::<usize>, default_value = "1G")]
+    max_memory: usize,
+    /// Clients to keep the latest request of for the `gap` field, per file; requests of clients
+    /// past them have no gap
+    #[arg(long, global = true, default_value = "1000000")]
+    max_clients: usize,
+    /// Capture fields out of another field with a regex's named groups, e.g.
+    /// `path:^/api/v(?P<version>\d+)/` for a `version` field to filter, print and group by like
+    /// any other; absent where the regex doesn't match. Repeat for more
+    #[arg(long, global = true, value_name = "FIELD:REGEX")]
+    extract: Vec<Extraction>,
+    /// Filter and group by each path in one spelling: duplicate slashes collapsed, `.` and `..`
+    /// segments resolved, escaped unreserved characters decoded and any trailing slash dropped.
+    /// Lines are printed as logged; --fields and JSON have the result as path_normalized
+    #[arg(long, global = true)]
+    normalize_paths: bool,
+    /// With --normalize-paths, lowercase paths too
+    #[arg(long, global = true, requires = "normalize_paths")]
+    normalize_case: bool,
+    /// Skip lines at the start of each file that repeat the end of the file before it, as
+    /// logrotate's copytruncate can leave; up to 1000 lines are compared at each boundary
+    #[arg(long, global = true)]
+    dedupe_overlap: bool,
+    /// Skip a line exactly like the line before it in the same file, as a log shipper that
+    /// retries its writes can leave, before it's parsed; the summary counts the lines skipped
+    #[arg(long, global = true, conflicts_with = "skip_duplicates_window")]
+    skip_consecutive_duplicates: bool,
+    /// Skip a line exactly like one of the N lines before it in the same file, compared by hash
+    #[arg(long, global = true, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=1_000_000))]
+    skip_duplicates_window: Option<u32>,
+    /// Threads to read each large plain file with, each parsing its own byte range; matches
+    /// are still printed in file order. Compressed files and pipes are read on one thread
+    #[arg(long, global = true, default_value = "1")]
+    jobs: NonZeroUsize,
+    /// How errors, warnings and the run summary are written to stderr
+    #[arg(long, value_enum, global = true, default_value_t)]
+    errors: ErrorFormat,
+    /// Start each JSON stream, on stdout and with `--errors json` on stderr, with a record of
+    /// the schema version; `log-parser schema` prints the schemas
+    #[arg(long, global = true)]
+    schema_version: bool,
+    /// Escaping for --format placeholders that don't name their own, e.g. `sh` to harden a
+    /// template written for a shell script
+    #[arg(long, value_enum, global = true, default_value_t)]
+    escape: Escape,
     #[command(subcommand)]
-    command: Commands,
+    mode: Mode,
+}
+
+impl Cli {
+    /// What to take off each line before the log type parses it.
+    fn wrapping(&self) -> Wrapping {
+        Wrapping { unwrap: self.unwrap, wrapper_time: self.wrapper_time, strip_syslog: self.strip_syslog }
+    }
+
+    /// Whether the command's output is printed to a terminal, to be aligned and colored for one.
+    fn to_terminal(&self) -> bool {
+        let output_file = match &self.mode {
+            Mode::Run(command) | Mode::Demo { command: Some(command), .. } => command.output_file(),
+            Mode::Demo { command: None, .. } | Mode::Schema { .. } | Mode::Operators { .. } | Mode::SelfTest { .. } => None,
+        };
+        output_file.is_none() && std::io::stdout().is_terminal()
+    }
 }
 
+#[derive(Subcommand, Debug)]
+enum Mode {
+    #[command(flatten)]
+    Run(Commands),
+    /// Run a command against a built-in sample of combined logs, no input files needed
+    #[command(arg_required_else_help = true)]
+    Demo {
+        /// Write the sample to this file instead, to experiment with
+        #[arg(long)]
+        write: Option<PathBuf>,
+        #[command(subcommand)]
+        command: Option<Commands>,
+    },
+    /// Print the JSON Schema of an output kind, or of every kind keyed by name
+    Schema {
+        #[arg(value_enum)]
+        kind: Option<schema::Kind>,
+    },
+    /// Print each kind of filter option with the operators it takes and an example
+    #[command(long_flag = "list-operators")]
+    Operators {
+        #[arg(short, long, value_enum, default_value_t)]
+        output: StatsOutput,
+    },
+    /// Check this build against built-in samples of every log type, filter operator and output
+    /// format; fails if any case does. `-o json` makes a report to attach to a bug report
+    SelfTest {
+        #[arg(short, long, value_enum, default_value_t)]
+        output: CheckOutput,
+    },
+}
+
+// Parsed once per run, so the size of the largest variant costs nothing worth boxing it for.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 enum Commands {
-    Filter(FilterArgs)
+    /// Print the entries that match the filter options
+    Filter {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+        /// Print these comma-separated fields of each match, tab-separated, instead of --output
+        #[arg(long, value_delimiter = ',', value_parser = registry::parse, conflicts_with = "output")]
+        fields: Vec<String>,
+        /// Print each match through a template such as `{ip} {user_agent:sh}`, instead of
+        /// --output. A placeholder may name its escaping: raw, sh (a single-quoted shell word),
+        /// html or json; `{{` and `}}` are literal braces
+        #[arg(long, conflicts_with_all = ["output", "fields"])]
+        format: Option<String>,
+        /// Add where each match was read to the --fields row: source_file, line_no and
+        /// byte_offset, in decompressed bytes for gzip inputs
+        #[arg(long, requires = "fields")]
+        with_provenance: bool,
+        /// Print the --fields names as a first row, e.g. `--delimiter , filter --header --fields
+        /// ...` for CSV that --log-type csv reads back; at the top of each part with --chunk-rows
+        #[arg(long, requires = "fields")]
+        header: bool,
+        /// Follow each match with the filters it satisfied and the values that did, e.g.
+        /// `[matched: status_code=503 (gte 500)]`, or a `matched_by` object in JSON
+        #[arg(long, conflicts_with_all = ["fields", "format", "annotate", "count", "count_by"])]
+        verbose_matches: bool,
+        /// Print each distinct output line only once
+        #[arg(long)]
+        unique: bool,
+        /// Print only the first match per key value in each window of log time, e.g. `ip:60s`
+        /// (keys: ip, path, status, ip+path)
+        #[arg(long)]
+        throttle: Option<ThrottleSpec>,
+        /// Also print match counts per file or per day to stderr
+        #[arg(long, value_enum)]
+        summary_by: Vec<BreakdownKey>,
+        /// Print match counts per file or per day instead of the matches, as JSON with `-o json`
+        #[arg(long, value_enum)]
+        count_by: Vec<BreakdownKey>,
+        /// Print only the number of matches, as JSON with `-o json`
+        #[arg(long, conflicts_with_all = ["count_by", "fields", "unique", "throttle", "sort"])]
+        count: bool,
+        /// Add the total response size of the matches to --count and the end-of-run summary
+        #[arg(long)]
+        show_bytes: bool,
+        /// Print every line behind a marker: `MATCH `, `    . ` when it doesn't match, or `ERR   `
+        /// when it can't be parsed
+        #[arg(long, conflicts_with_all = ["output", "fields", "unique", "throttle", "count", "count_by", "sort"])]
+        annotate: bool,
+        /// Allow --annotate on inputs over 64 MiB
+        #[arg(long, requires = "annotate")]
+        force: bool,
+        /// Stop reading after this many matching entries
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Exit with 3 once the run finishes if a condition holds: `matches`, `malformed` or
+        /// `match-rate` (matches over parsed lines), optionally compared with >, >=, <, <= or =,
+        /// e.g. `matches>100`; repeat it to fail on any of several. Otherwise a run exits with 0
+        /// if anything matched, 1 if nothing did and 2 on an error
+        #[arg(long, value_name = "CONDITION")]
+        fail_on: Vec<Condition>,
+        /// Print the matches of several files merged in timestamp order instead of file by file
+        #[arg(long, conflicts_with = "annotate")]
+        interleave: bool,
+        /// Buffer matches and print them ordered by this field, entries without it last: a time
+        /// or a number, such as `timestamp`, `duration`, `bytes` or the log format's `request_time`
+        #[arg(long, value_name = "FIELD", value_parser = SortKey::parse)]
+        sort: Option<SortKey>,
+        /// Print the matches group by group, each behind a `== KEY VALUE (N matches) ==` line,
+        /// ordered by value with matches that lack one last; held until the input ends unless
+        /// --assume-sorted lets them stream
+        #[arg(long, value_parser = KeyParser, conflicts_with_all = ["count", "count_by", "annotate", "sort", "follow_glob", "latest_per", "oldest_per"])]
+        group_by: Option<GroupBy>,
+        /// The input is in time order, so --group-by day or hour prints each group as soon as
+        /// the next one starts, holding only one in memory; fails if an entry turns up late
+        #[arg(long, requires = "group_by")]
+        assume_sorted: bool,
+        /// Write what would go to stdout to this file instead, gzip-compressed if it ends in `.gz`
+        #[arg(long, conflicts_with = "annotate")]
+        output_file: Option<PathBuf>,
+        /// Write --output-file in parts of at most this much output before compression, e.g.
+        /// `100M`, named `results.00001.json` and so on, each whole for its format
+        #[arg(long, requires = "output_file", value_parser = size::parse_size::<u64>)]
+        chunk_size: Option<u64>,
+        /// Write --output-file in parts of at most this many lines, like --chunk-size
+        #[arg(long, requires = "output_file")]
+        chunk_rows: Option<NonZeroUsize>,
+        /// Also write every parsed line that doesn't match to this file, e.g. to check both sides
+        /// of a filter in one pass
+        #[arg(long)]
+        unmatched_file: Option<PathBuf>,
+        /// Also write every line that fails to parse to this file
+        #[arg(long)]
+        malformed_file: Option<PathBuf>,
+        /// Show matches printed to a terminal through $PAGER, `less -R` unless set: `auto` once
+        /// they run past a screenful ($LINES rows, or 24), `always` however few there are
+        #[arg(long, value_enum, default_value_t)]
+        pager: PagerMode,
+        /// Instead of reading files, keep reading the newest file matching this pattern, e.g.
+        /// `'/var/log/nginx/access.log*'`, until Ctrl-C. A file that starts matching later is
+        /// followed from its start once the one before has been read to its end
+        #[arg(long, value_name = "PATTERN", conflicts_with_all = ["annotate", "interleave", "sort"])]
+        follow_glob: Option<String>,
+        /// How often --follow-glob looks for new lines and files
+        #[arg(long, requires = "follow_glob", default_value = "1s")]
+        poll_interval: HumanDuration,
+        /// While --follow-glob runs, read the --filter-file again whenever it changes and filter
+        /// the lines that follow with its new conditions, printing what changed; a file that
+        /// doesn't parse keeps the conditions before
+        #[arg(long, requires_all = ["follow_glob", "filter_file"])]
+        reload_on_change: bool,
+        /// Print only the newest match per key, e.g. each client's last request, once the input
+        /// ends; ordered by key, or by --sort. Keys are joined by `+`, e.g. `ip+path`
+        #[arg(long, value_parser = KeyParser, value_delimiter = '+', conflicts_with_all = ["annotate", "count", "follow_glob"])]
+        latest_per: Vec<GroupBy>,
+        /// Print only the oldest match per key, like --latest-per
+        #[arg(long, value_parser = KeyParser, value_delimiter = '+', conflicts_with_all = ["annotate", "count", "follow_glob", "latest_per"])]
+        oldest_per: Vec<GroupBy>,
+        /// Fail rather than keep more keys than this for --latest-per or --oldest-per
+        #[arg(long, default_value_t = 100_000)]
+        max_keys: usize,
+        /// Write each value of these comma-separated fields, e.g. `user_agent,referrer`, as an ID
+        /// in JSON output or --fields rows, and each distinct value once in a dictionary: a
+        /// `{"dictionary":{...}}` record after the last match, or --dict-file
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_parser = registry::parse,
+            conflicts_with_all = ["format", "verbose_matches", "count", "count_by", "annotate"]
+        )]
+        dict_fields: Vec<String>,
+        /// Write user agents as IDs, like --dict-fields user_agent
+        #[arg(long, conflicts_with_all = ["format", "verbose_matches", "count", "count_by", "annotate"])]
+        ua_dictionary: bool,
+        /// Write the dictionary of --dict-fields to this file instead of after the last match:
+        /// JSON with `-o json`, `field id value` rows with --fields
+        #[arg(long, alias = "ua-dictionary-file")]
+        dict_file: Option<PathBuf>,
+        /// Print only this match, counting from 1 or back from the last with -1: a field per
+        /// line, then the line as logged, to read one entry of a long log line
+        #[arg(
+            long,
+            value_name = "N",
+            allow_negative_numbers = true,
+            value_parser = inspect::parse_index,
+            conflicts_with_all = [
+                "output", "fields", "format", "verbose_matches", "unique", "throttle", "count", "count_by", "annotate", "limit",
+                "sort", "group_by", "follow_glob", "latest_per", "oldest_per", "dict_fields", "ua_dictionary",
+            ]
+        )]
+        inspect: Option<i64>,
+    },
+    /// Summarize a metric over matching entries
+    Stats {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Summarize this metric; with --by, per group next to the count
+        #[arg(short, long, value_enum, required_unless_present = "by")]
+        metric: Option<Metric>,
+        /// Count matches per combination of these comma-separated keys, e.g. `path-prefix,status`
+        #[arg(long, value_parser = KeyParser, value_delimiter = ',')]
+        by: Vec<GroupBy>,
+        /// With two --by keys, print the first as rows and the second as columns
+        #[arg(long, requires = "by", conflicts_with = "metric")]
+        pivot: bool,
+        /// Fail rather than track more groups than this with --by
+        #[arg(long, default_value_t = 10_000)]
+        max_groups: usize,
+        /// Print up to this many lines of each --by group under its row, picked at random so
+        /// they're representative of the whole group
+        #[arg(long, value_name = "N", requires = "by", conflicts_with = "pivot")]
+        examples: Option<usize>,
+        /// Seed for picking --examples, to pick the same ones from the same input again
+        #[arg(long, requires = "examples")]
+        seed: Option<u64>,
+        /// Count only the most frequent --by groups, in bounded memory, each with how much its
+        /// count may be over; for more groups than --max-groups allows
+        #[arg(long, requires = "by", conflicts_with_all = ["metric", "pivot", "examples"])]
+        approximate: bool,
+        /// Counters --approximate keeps: groups with more matches than 1/N of all are sure to be
+        /// found, and no count is off by more than that
+        #[arg(long, requires = "approximate", default_value_t = 100_000, value_parser = clap::value_parser!(u64).range(1..))]
+        sketch_size: u64,
+        /// JSON nests --by groups by the first key's values, then the second's
+        #[arg(short, long, value_enum, default_value_t)]
+        output: SummaryOutput,
+        /// Write the output to this file instead of stdout, e.g. `report.html`; compressed if the
+        /// name ends in `.gz`
+        #[arg(long)]
+        output_file: Option<PathBuf>,
+        #[command(flatten)]
+        order: Order,
+        #[command(flatten)]
+        snapshot: SnapshotArgs,
+    },
+    /// Count matches per bucket of log time, drawn as bars
+    Histogram {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Length of each bucket of log time, e.g. `5m`
+        #[arg(long, default_value = "1h")]
+        bucket: HumanDuration,
+        /// Count each bucket's 2xx, 3xx, 4xx and 5xx responses too, stacking the bar from `=`,
+        /// `~`, `?` and `!` (`.` for anything else)
+        #[arg(long)]
+        split_status: bool,
+        /// Print each bucket's percentage of 5xx responses, with a bar out of 100%
+        #[arg(long, conflicts_with = "split_status")]
+        only_ratio: bool,
+        /// Fail rather than print more buckets than this
+        #[arg(long, default_value_t = 10_000)]
+        max_buckets: usize,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: HistogramOutput,
+        /// Write the output to this file instead of stdout, e.g. `report.html`; compressed if the
+        /// name ends in `.gz`
+        #[arg(long)]
+        output_file: Option<PathBuf>,
+        #[command(flatten)]
+        snapshot: SnapshotArgs,
+    },
+    /// Print the values of a key with the most matches or, against a baseline, whose share of
+    /// the matches grew the most, e.g. paths that became popular during an incident
+    Top {
+        /// The key whose values are ranked, e.g. `path`; it precedes any input files
+        #[arg(value_parser = KeyParser)]
+        key: GroupBy,
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Print this many values
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        /// Compare with the matches of this log, e.g. the same hour a week earlier
+        #[arg(long, conflicts_with = "baseline_since")]
+        relative_file: Option<PathBuf>,
+        /// Compare with the matches from here, inclusive, to --baseline-until in the input
+        #[arg(long, requires = "baseline_until", value_parser = log_time::parse_option)]
+        baseline_since: Option<DateTime<FixedOffset>>,
+        /// End of the baseline window, exclusive
+        #[arg(long, requires = "baseline_since", value_parser = log_time::parse_option)]
+        baseline_until: Option<DateTime<FixedOffset>>,
+        /// Values with fewer baseline matches than this are new rather than given a ratio
+        #[arg(long, default_value_t = 10)]
+        min_baseline: usize,
+        /// Fail rather than track more values than this
+        #[arg(long, default_value_t = 10_000)]
+        max_groups: usize,
+        /// Print up to this many lines of each value under its row, picked at random so they're
+        /// representative of all its matches
+        #[arg(long, value_name = "N")]
+        examples: Option<usize>,
+        /// Seed for picking --examples, to pick the same ones from the same input again
+        #[arg(long, requires = "examples")]
+        seed: Option<u64>,
+        /// Count only the most frequent values, in bounded memory, each with how much its count
+        /// may be over; for more values than --max-groups allows
+        #[arg(long, conflicts_with = "examples")]
+        approximate: bool,
+        /// Counters --approximate keeps: values with more matches than 1/N of all are sure to be
+        /// found, and no count is off by more than that
+        #[arg(long, requires = "approximate", default_value_t = 100_000, value_parser = clap::value_parser!(u64).range(1..))]
+        sketch_size: u64,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: TopOutput,
+        /// Write the output to this file instead of stdout, e.g. `report.html`; compressed if the
+        /// name ends in `.gz`
+        #[arg(long)]
+        output_file: Option<PathBuf>,
+        #[command(flatten)]
+        order: Order,
+        #[command(flatten)]
+        snapshot: SnapshotArgs,
+    },
+    /// Print the entries of a target window of log time whose key never occurred in a baseline
+    /// window, e.g. paths first requested after a deploy
+    Novel {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// What makes an entry new: its path, client address, both, or user agent
+        #[arg(long, value_enum, default_value = "path")]
+        key: NovelKey,
+        /// Start of the baseline window, inclusive
+        #[arg(long, value_parser = log_time::parse_option)]
+        baseline_since: DateTime<FixedOffset>,
+        /// End of the baseline window, exclusive
+        #[arg(long, value_parser = log_time::parse_option)]
+        baseline_until: DateTime<FixedOffset>,
+        /// Start of the target window, inclusive
+        #[arg(long, value_parser = log_time::parse_option)]
+        target_since: DateTime<FixedOffset>,
+        /// End of the target window, exclusive
+        #[arg(long, value_parser = log_time::parse_option)]
+        target_until: DateTime<FixedOffset>,
+        /// The input is in time order, so read it once instead of twice; fails if a baseline
+        /// entry turns up after target entries
+        #[arg(long)]
+        ordered: bool,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+        /// Print only the number of novel entries, as JSON with `-o json`
+        #[arg(long)]
+        count: bool,
+    },
+    /// Count the distinct values of a key, in total or per --by group, estimated from a
+    /// HyperLogLog sketch with its standard error unless --exact
+    Cardinality {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// The key whose distinct values are counted, e.g. `ip`
+        #[arg(long, value_parser = KeyParser)]
+        of: GroupBy,
+        /// Count per combination of these comma-separated keys, e.g. `path-prefix`
+        #[arg(long, value_parser = KeyParser, value_delimiter = ',')]
+        by: Vec<GroupBy>,
+        /// Sketch 2^N one-byte registers per group, for a standard error of 1.04/sqrt(2^N):
+        /// 14 gives 0.8% in 16 KiB
+        #[arg(
+            long,
+            default_value_t = 14,
+            value_parser = clap::value_parser!(u8).range(cardinality::MIN_PRECISION as i64..=cardinality::MAX_PRECISION as i64)
+        )]
+        precision: u8,
+        /// Count with sets of the values themselves: exact, but every distinct value is kept
+        /// in memory
+        #[arg(long, conflicts_with = "precision")]
+        exact: bool,
+        /// Fail rather than track more groups than this
+        #[arg(long, default_value_t = 10_000)]
+        max_groups: usize,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: StatsOutput,
+        #[command(flatten)]
+        order: Order,
+    },
+    /// Flag --by groups whose mean metric outside a baseline window of log time, or in a target
+    /// window, lies more than --sigma standard deviations from the baseline mean
+    Anomalies {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(short, long, value_enum)]
+        metric: Metric,
+        /// Compare per combination of these comma-separated keys, e.g. `path-prefix`
+        #[arg(long, value_parser = KeyParser, value_delimiter = ',', required = true)]
+        by: Vec<GroupBy>,
+        /// Start of the baseline window, inclusive
+        #[arg(long, value_parser = log_time::parse_option)]
+        baseline_since: DateTime<FixedOffset>,
+        /// End of the baseline window, exclusive
+        #[arg(long, value_parser = log_time::parse_option)]
+        baseline_until: DateTime<FixedOffset>,
+        /// Start of the target window, inclusive; without a target window, everything outside
+        /// the baseline is compared
+        #[arg(long, requires = "target_until", value_parser = log_time::parse_option)]
+        target_since: Option<DateTime<FixedOffset>>,
+        /// End of the target window, exclusive
+        #[arg(long, requires = "target_since", value_parser = log_time::parse_option)]
+        target_until: Option<DateTime<FixedOffset>>,
+        /// Standard deviations from the baseline mean that count as an anomaly
+        #[arg(long, default_value_t = 3.0)]
+        sigma: f64,
+        /// Print the target entries beyond --sigma of their group's baseline instead of the
+        /// groups; reads the input twice
+        #[arg(long)]
+        emit_entries: bool,
+        /// Fail rather than track more groups than this
+        #[arg(long, default_value_t = 10_000)]
+        max_groups: usize,
+        /// With --emit-entries, JSON prints entries as `filter -o json` does
+        #[arg(short, long, value_enum, default_value_t)]
+        output: StatsOutput,
+        #[command(flatten)]
+        order: Order,
+    },
+    /// Flag client addresses that request many missing paths in a short time; exits with 1 if any
+    /// are found
+    Scanners {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// 404 responses a client needs within one window
+        #[arg(long = "min-404", default_value_t = 50)]
+        min_not_found: usize,
+        /// Distinct paths among those 404s
+        #[arg(long, default_value_t = 30)]
+        min_distinct_paths: usize,
+        /// Length of the sliding window of log time, e.g. `10m`
+        #[arg(long, default_value = "10m")]
+        window: HumanDuration,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: ScannerOutput,
+    },
+    /// Audit basic-auth outcomes: 401 and 407 responses per client and per username tried, and
+    /// logins that soon followed a failure; exits with 1 if any client is over --max-failures
+    Auth {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// How soon after a client's last failure a 2xx or 3xx response counts as a login after
+        /// failing, e.g. `5m`
+        #[arg(long, default_value = "5m")]
+        window: HumanDuration,
+        /// Flag clients with more failures than this
+        #[arg(long, default_value_t = 10)]
+        max_failures: usize,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: AuthOutput,
+        #[command(flatten)]
+        order: Order,
+    },
+    /// Check the matches against the rules in a rules file, e.g. a ceiling on the share of 5xx
+    /// responses, and print each rule's result; exits with 3 if any fail
+    Check {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// The rules file; see examples/rules.toml
+        #[arg(long)]
+        rules: PathBuf,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: CheckOutput,
+    },
+    /// Summarize a day or a week of matches: requests, bytes and the 5xx rate per day, the top
+    /// pages, external referrers and user-agent families, and the peak hour, in --tz
+    Report {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: ReportOutput,
+        /// Write the output to this file instead of stdout, e.g. `report.html`; compressed if the
+        /// name ends in `.gz`
+        #[arg(long)]
+        output_file: Option<PathBuf>,
+    },
+    /// Print the fields entries of the log type can have, e.g. `--log-type alb fields`, and the
+    /// option that filters on each. Given a file, fields its first lines have besides are added,
+    /// and W3C logs and CSV are read for their header
+    #[command(long_flag = "list-fields")]
+    Fields {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: StatsOutput,
+    },
+    /// Rewrite each entry in another log format, e.g. ALB, W3C or CSV logs as combined lines for
+    /// tools that only read those. Fields the input lacks are written as `-`
+    Convert {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// The format to write
+        #[arg(long, value_enum)]
+        to: Conversion,
+    },
+    /// Run the jobs of a jobs file, e.g. a morning's fixed queries, over one pass of the input:
+    /// each job filters the same entries and writes its matches to its own file. Prints each
+    /// job's count of matches; see examples/jobs.toml
+    Batch {
+        #[command(flatten)]
+        inputs: Inputs,
+        /// The jobs file: a [name] section per job with its filter, output and file
+        #[arg(long)]
+        jobs_file: PathBuf,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: StatsOutput,
+    },
+    /// Time the parse and filter pipeline over the input, discarding matches
+    Bench {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, default_value_t = 3)]
+        iterations: usize,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: BenchOutput,
+    },
+}
+
+impl Commands {
+    /// Takes the input files given after the command.
+    fn take_inputs(&mut self) -> Vec<PathBuf> {
+        let (Commands::Filter { inputs, .. }
+        | Commands::Stats { inputs, .. }
+        | Commands::Histogram { inputs, .. }
+        | Commands::Top { inputs, .. }
+        | Commands::Novel { inputs, .. }
+        | Commands::Cardinality { inputs, .. }
+        | Commands::Anomalies { inputs, .. }
+        | Commands::Scanners { inputs, .. }
+        | Commands::Auth { inputs, .. }
+        | Commands::Check { inputs, .. }
+        | Commands::Report { inputs, .. }
+        | Commands::Fields { inputs, .. }
+        | Commands::Convert { inputs, .. }
+        | Commands::Batch { inputs, .. }
+        | Commands::Bench { inputs, .. }) = self;
+        std::mem::take(inputs).into_files()
+    }
+
+    /// Takes the filter options; commands without any pass everything.
+    fn take_filter(&mut self) -> FilterArgs {
+        match self {
+            Commands::Filter { filter, .. }
+            | Commands::Stats { filter, .. }
+            | Commands::Histogram { filter, .. }
+            | Commands::Top { filter, .. }
+            | Commands::Novel { filter, .. }
+            | Commands::Cardinality { filter, .. }
+            | Commands::Anomalies { filter, .. }
+            | Commands::Scanners { filter, .. }
+            | Commands::Auth { filter, .. }
+            | Commands::Check { filter, .. }
+            | Commands::Report { filter, .. }
+            | Commands::Convert { filter, .. }
+            | Commands::Bench { filter, .. } => std::mem::take(filter),
+            Commands::Fields { .. } | Commands::Batch { .. } => FilterArgs::default(),
+        }
+    }
+
+    /// The mistakes in the command's own options that clap can't catch, given whether input
+    /// files were named and whether the log type is to be detected from them.
+    fn check(&self, files: bool, detects: bool, tz: Option<Zone>) -> Vec<String> {
+        let mut errors = Vec::new();
+        match self {
+            Commands::Filter {
+                filter,
+                follow_glob,
+                output,
+                fields,
+                dict_fields,
+                ua_dictionary,
+                dict_file,
+                chunk_size,
+                chunk_rows,
+                ..
+            } => {
+                if !filter.explain_filter {
+                    errors.extend(filter.require_filters().err());
+                }
+                let dictionary = !dict_fields.is_empty() || *ua_dictionary;
+                let json = matches!(output, OutputFormat::Json | OutputFormat::JsonArray);
+                if dictionary && !json && fields.is_empty() {
+                    errors.push("--dict-fields writes IDs in JSON output or --fields rows; pass -o json or --fields".to_string());
+                }
+                if dictionary && !fields.is_empty() && dict_file.is_none() {
+                    errors.push("--dict-fields with --fields writes the dictionary to --dict-file; name one".to_string());
+                }
+                if dictionary && dict_file.is_none() && (chunk_size.is_some() || chunk_rows.is_some()) {
+                    errors.push(
+                        "--dict-fields writes the dictionary after the last match, in the last part only; name a --dict-file for it"
+                            .to_string(),
+                    );
+                }
+                if !dictionary && dict_file.is_some() {
+                    errors.push("--dict-file needs --dict-fields or --ua-dictionary to have a dictionary to write".to_string());
+                }
+                if follow_glob.is_some() && files {
+                    errors.push("--follow-glob reads the files matching its pattern; remove the file arguments".to_string());
+                }
+                if follow_glob.is_some() && detects {
+                    errors.push("--follow-glob can't detect the log type of files yet to be written; pass --log-type".to_string());
+                }
+            }
+            Commands::Stats { by, pivot, .. } => {
+                if *pivot && by.len() != 2 {
+                    errors.push("--pivot needs exactly two --by keys, e.g. --by path-prefix,status".to_string());
+                }
+            }
+            Commands::Histogram { bucket, .. } => errors.extend(Histogram::new(bucket.0, tz).err()),
+            Commands::Top { key, relative_file, baseline_since, baseline_until, max_groups, .. } => {
+                let window = baseline_since.zip(*baseline_until).map(|(since, until)| Window { since, until });
+                errors.extend(Top::new(*key, tz, *max_groups, None, relative_file.is_some(), window).err());
+            }
+            Commands::Novel { key, baseline_since, baseline_until, target_since, target_until, ordered, .. } => {
+                let baseline = Window { since: *baseline_since, until: *baseline_until };
+                let target = Window { since: *target_since, until: *target_until };
+                match Novel::new(*key, baseline, target) {
+                    Ok(novel) if *ordered && !novel.baseline_first() => {
+                        errors.push("--ordered needs the baseline window to end before the target window starts".to_string())
+                    }
+                    Ok(_) => {}
+                    Err(message) => errors.push(message),
+                }
+            }
+            Commands::Anomalies {
+                metric,
+                by,
+                baseline_since,
+                baseline_until,
+                target_since,
+                target_until,
+                sigma,
+                max_groups,
+                ..
+            } => {
+                let baseline = Window { since: *baseline_since, until: *baseline_until };
+                let target = target_since.zip(*target_until).map(|(since, until)| Window { since, until });
+                errors.extend(Anomalies::new(by.clone(), tz, *metric, baseline, target, *sigma, *max_groups).err());
+            }
+            Commands::Scanners { window, .. } | Commands::Auth { window, .. } => {
+                errors.extend(TimeDelta::from_std(window.0).err().map(|e| format!("--window: {}", e)));
+            }
+            Commands::Cardinality { .. }
+            | Commands::Check { .. }
+            | Commands::Report { .. }
+            | Commands::Fields { .. }
+            | Commands::Convert { .. }
+            | Commands::Batch { .. }
+            | Commands::Bench { .. } => {}
+        }
+        errors
+    }
+
+    /// The file stats, histogram, top or report write to instead of stdout.
+    fn output_file(&self) -> Option<&Path> {
+        match self {
+            Commands::Stats { output_file, .. }
+            | Commands::Histogram { output_file, .. }
+            | Commands::Top { output_file, .. }
+            | Commands::Report { output_file, .. } => output_file.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The files the command writes besides its output to stdout, with the option naming each.
+    fn sinks(&self) -> Vec<(&'static str, &Path)> {
+        let (output_file, snapshot) = match self {
+            Commands::Stats { output_file, snapshot, .. }
+            | Commands::Histogram { output_file, snapshot, .. }
+            | Commands::Top { output_file, snapshot, .. } => (output_file.as_deref(), snapshot.file()),
+            Commands::Filter { output_file, .. } => (output_file.as_deref(), None),
+            _ => (self.output_file(), None),
+        };
+        let mut sinks = vec![("--output-file", output_file), ("--snapshot-file", snapshot)];
+        if let Commands::Filter { unmatched_file, malformed_file, dict_file, .. } = self {
+            sinks.extend([
+                ("--unmatched-file", unmatched_file.as_deref()),
+                ("--malformed-file", malformed_file.as_deref()),
+                ("--dict-file", dict_file.as_deref()),
+            ]);
+        }
+        sinks.into_iter().filter_map(|(option, path)| Some((option, path?))).collect()
+    }
+
+    /// Whether the command reads files matching --follow-glob rather than input files.
+    fn follows(&self) -> bool {
+        matches!(self, Commands::Filter { follow_glob: Some(_), .. })
+    }
+
+    /// Whether the command has something to do without input files.
+    fn needs_input(&self) -> bool {
+        !(self.follows() || matches!(self, Commands::Fields { .. }))
+    }
+
+    /// The keys the command groups, ranks or keeps matches by.
+    fn keys(&self) -> Vec<GroupBy> {
+        match self {
+            Commands::Filter { latest_per, oldest_per, group_by, .. } => {
+                [latest_per.as_slice(), oldest_per, group_by.as_slice()].concat()
+            }
+            Commands::Stats { by, .. } | Commands::Anomalies { by, .. } => by.clone(),
+            Commands::Top { key, .. } => vec![*key],
+            Commands::Cardinality { of, by, .. } => [std::slice::from_ref(of), by].concat(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether the command reads the field `name` other than through its filter, given the
+    /// fields of the --format template: as one it names, or with the whole entry.
+    fn reads(&self, name: &str, template: &[String]) -> bool {
+        let whole = |output: &OutputFormat| matches!(output, OutputFormat::Json | OutputFormat::JsonArray);
+        self.names(name, template)
+            || match self {
+                Commands::Filter { output, .. } => whole(output),
+                Commands::Novel { output, .. } => whole(output),
+                Commands::Anomalies { emit_entries, output, .. } => *emit_entries && !matches!(output, StatsOutput::Table),
+                _ => false,
+            }
+    }
+
+    /// Whether the command names the field `name`: as a key, in --fields or the template, or
+    /// as the metric it summarizes.
+    fn names(&self, name: &str, template: &[String]) -> bool {
+        let metric = |metric: Option<Metric>| matches!(metric, Some(Metric::Gap)) && name == gap::FIELD;
+        self.keys().iter().any(|key| matches!(key, GroupBy::Field(field) if *field == name))
+            || match self {
+                Commands::Filter { fields, .. } => fields.iter().chain(template).any(|field| field == name),
+                Commands::Stats { metric: stats, .. } => metric(*stats),
+                Commands::Anomalies { metric: anomalies, .. } => metric(Some(*anomalies)),
+                // The rules file is read once the options have been, and its filters may read any field.
+                Commands::Fields { .. } | Commands::Check { .. } => true,
+                _ => false,
+            }
+    }
+}
+
+/// The field --sort orders by: one of the registry's whose values have an order, or one of
+/// the log format's, taken as a number.
+#[derive(Clone, Debug)]
+struct SortKey(String);
+
+impl SortKey {
+    fn parse(name: &str) -> Result<SortKey, String> {
+        let Some(field) = registry::find(name) else {
+            return Ok(SortKey(name.to_string()));
+        };
+        match field.kind.is_ordered() && !matches!(field.access, registry::Access::Provenance) {
+            true => Ok(SortKey(field.name.to_string())),
+            false => {
+                let ordered = registry::FIELDS.iter().filter(|field| field.kind.is_ordered() && !field.source.is_empty());
+                let names: Vec<&str> = ordered.map(|field| field.name).collect();
+                Err(format!("{} has no order to sort by; sort by a time or a number: {}", field.name, names.join(", ")))
+            }
+        }
+    }
+
+    /// Times and durations in nanoseconds; other numbers as integers in the order of the numbers.
+    fn value(&self, entry: &Entry) -> Option<i64> {
+        match self.0.as_str() {
+            "timestamp" => entry.timestamp.and_then(|t| t.timestamp_nanos_opt()),
+            "duration" => entry.duration.map(|d| d.0.as_nanos().min(i64::MAX as u128) as i64),
+            name => {
+                let bits = registry::value(entry, name)?.as_number()?.to_bits() as i64;
+                Some(if bits < 0 { bits ^ i64::MAX } else { bits })
+            }
+        }
+    }
 }
 
-#[derive(Args, Debug)]
-struct FilterArgs {
-    #[arg(short, long, num_args = 1..=2)]
-    status_code: Option<Vec<String>>,
-    
-    #[arg(short, long, num_args = 1..=2)]
-    user_agent: Option<Vec<String>>,
-    
-    #[arg(short, long, num_args = 1..=2)]
-    ip: Option<Vec<String>>,
+/// How `scan` treats lines that fail to parse and timestamps that go backwards, and what else
+/// it tracks or prints along the way.
+#[derive(Clone)]
+struct ScanOptions {
+    strict: bool,
+    check_order: bool,
+    order_slack: TimeDelta,
+    show_bytes: bool,
+    /// Print the lines that don't match too, see `annotate`.
+    annotate: bool,
+    /// Stop reading after this many matches.
+    limit: Option<usize>,
+    /// Count which filter rejected each entry that doesn't match.
+    count_rejections: bool,
+    /// Longer lines are skipped as malformed without being read into memory.
+    max_line_length: usize,
+    /// Plain files at least this large are read through a memory map.
+    mmap_threshold: u64,
+    /// Where parsed lines that don't match are written, see --unmatched-file.
+    unmatched: Option<Rc<RefCell<Sink>>>,
+    /// Where lines that fail to parse are written, see --malformed-file.
+    malformed: Option<Rc<RefCell<Sink>>>,
+    /// Lines to skip at the start of each file, see --dedupe-overlap.
+    overlaps: Rc<HashMap<PathBuf, usize>>,
+    /// How many lines before each a repeated line is skipped within, see --skip-duplicates-window.
+    duplicates: Option<usize>,
+    /// Threads to read a large plain file with, see `partitions`.
+    jobs: usize,
+    /// What --after-status has seen so far, across every file.
+    history: Option<Rc<RefCell<StatusHistory>>>,
+}
+
+/// Reads one source up to each matching entry in turn. Matches are only counted in the
+/// summary once the caller keeps them with `record_match`.
+struct Scanner<'a> {
+    path: &'a Path,
+    parser: LineParser,
+    options: &'a ScanOptions,
+    filter: &'a EntryFilter,
+    reporter: &'a Reporter,
+    lines: Enumerate<InputSource>,
+    /// What the decoder of a gzipped file made of it.
+    gzip: Option<Gzip>,
+    /// The line last read.
+    line: usize,
+    /// Leading lines that repeat the end of the previous file.
+    repeated: usize,
+    /// The lines just read, with --skip-consecutive-duplicates or --skip-duplicates-window.
+    duplicates: Option<Duplicates>,
+    summary: RunSummary,
+    checked_fields: bool,
+    /// The previous timestamp, its line, and the earliest timestamp that isn't a regression.
+    previous: Option<(DateTime<FixedOffset>, usize, DateTime<FixedOffset>)>,
+    /// The first timestamp and its line.
+    first: Option<(DateTime<FixedOffset>, usize)>,
+    /// Every line was read.
+    ended: bool,
+}
+
+/// Warns when --duration is given for a log type that records none, and likewise for the
+/// connection filters when the log type declares its fields and they aren't among them.
+fn check_recorded(parser: &LineParser, filter: &EntryFilter, reporter: &Reporter) {
+    if !matches!(filter.include.duration, Presence::Missing | Presence::Value(OrdFilter::Any)) && !parser.records_duration() {
+        reporter.warning(Warning::NoDuration {
+            message: "--duration never matches, the selected log type records no request duration".to_string(),
+        });
+    }
+    let Some(recorded) = parser.fields() else {
+        return;
+    };
+    let (fields, options): (Vec<&str>, Vec<&str>) =
+        filter.include.connection_filters().into_iter().filter(|(field, _)| !recorded.iter().any(|r| r == field)).unzip();
+    if !fields.is_empty() {
+        let verb = if options.len() == 1 { "matches" } else { "match" };
+        reporter.warning(Warning::MissingFields {
+            message: format!(
+                "{} never {}, the selected log type records no {}",
+                options.join(", "),
+                verb,
+                fields.join(", ")
+            ),
+            fields: fields.into_iter().map(str::to_string).collect(),
+        });
+    }
+}
+
+impl<'a> Scanner<'a> {
+    fn open(
+        source: &'a Source,
+        parser: LineParser,
+        options: &'a ScanOptions,
+        filter: &'a EntryFilter,
+        reporter: &'a Reporter,
+    ) -> Result<Self, Error> {
+        check_recorded(&parser, filter, reporter);
+        let path = source.path();
+        let lines = source
+            .lines(options.max_line_length, options.mmap_threshold)
+            .map_err(|e| Error::Io { file: path.to_path_buf(), message: e.to_string() })?;
+        Ok(Scanner::new(path, lines, parser, options, filter, reporter))
+    }
+
+    /// Reads `lines` of `path`, which may start partway into the file; lines are numbered from
+    /// where they start.
+    fn new(
+        path: &'a Path,
+        lines: InputSource,
+        parser: LineParser,
+        options: &'a ScanOptions,
+        filter: &'a EntryFilter,
+        reporter: &'a Reporter,
+    ) -> Self {
+        let summary = RunSummary {
+            file: path.to_path_buf(),
+            lines: 0,
+            bytes: 0,
+            compressed_bytes: None,
+            matched: 0,
+            matched_bytes: options.show_bytes.then(MatchedBytes::default),
+            malformed: 0,
+            wrapper_errors: parser.unwrapper().map(|_| 0),
+            malformed_requests: 0,
+            regressions: 0,
+            invalid_numbers: 0,
+            duplicates: options.duplicates.map(|_| 0),
+            rejected: options.count_rejections.then(BTreeMap::new),
+            hostname_clients: filter.include.client.filters_ip().then_some(0),
+            interrupted: false,
+        };
+        Scanner {
+            path,
+            parser,
+            options,
+            filter,
+            reporter,
+            gzip: lines.gzip(),
+            lines: lines.enumerate(),
+            line: 0,
+            repeated: options.overlaps.get(path).copied().unwrap_or(0),
+            duplicates: options.duplicates.map(Duplicates::new),
+            summary,
+            checked_fields: false,
+            previous: None,
+            first: None,
+            ended: false,
+        }
+    }
+
+    /// Reads up to the next entry that passes the filter, or `None` at the end of the input.
+    fn next_match(&mut self, timer: &mut impl StageTimer) -> Result<Option<Match>, Error> {
+        let options = self.options;
+        while let Some((index, line)) = timer.time(Stage::Read, || self.lines.next()) {
+            if interrupt::requested() {
+                self.summary.interrupted = true;
+                return Ok(None);
+            }
+            self.line = index + 1;
+            self.summary.lines += 1;
+            let mut byte_offset = 0;
+            let line = match line {
+                Ok(ReadLine::Line { text: mut line, offset }) => {
+                    self.summary.bytes += line.len() as u64 + 1;
+                    byte_offset = offset;
+                    line.trim_line_ending();
+                    Ok(line)
+                }
+                Ok(ReadLine::TooLong { offset, length }) => {
+                    self.summary.bytes += length + 1;
+                    Err(format!(
+                        "line at byte offset {} is {} bytes, over --max-line-length {}",
+                        offset, length, options.max_line_length
+                    ))
+                }
+                Err(e) => Err(e.to_string()),
+            };
+            if self.line <= self.repeated {
+                continue;
+            }
+            if line.as_ref().is_ok_and(|line| line.is_empty()) {
+                self.set_aside("")?;
+                continue;
+            }
+            if let (Some(duplicates), Ok(text)) = (&mut self.duplicates, &line) {
+                if duplicates.repeats(text) {
+                    self.summary.duplicates = self.summary.duplicates.map(|count| count + 1);
+                    continue;
+                }
+            }
+            // Errors keep the line's text, if it could be read, for --annotate.
+            let parser = &mut self.parser;
+            let parsed = line.map_err(|message| (Text::from(String::new()), message)).and_then(|line| {
+                match timer.time(Stage::Parse, || parser.parse(&line)) {
+                    Ok(entry) => Ok((unwrapped(parser, line), entry)),
+                    Err(message) => Err((line, message)),
+                }
+            });
+            let (line, entry) = match parsed {
+                Ok((line, Some(entry))) => (line, entry),
+                Ok((line, None)) => {
+                    self.set_aside(&line)?;
+                    continue;
+                }
+                Err((_, message)) if options.strict => {
+                    return Err(Error::Line { file: self.path.to_path_buf(), line: self.line, message })
+                }
+                Err((text, reason)) => {
+                    if options.annotate {
+                        annotate::print(Marker::Malformed, if text.is_empty() { &reason } else { &text });
+                    }
+                    if let Some(sink) = options.malformed.as_ref().filter(|_| !text.is_empty()) {
+                        sink.borrow_mut().write_line(&text, '\n')?;
+                    }
+                    self.summary.malformed += 1;
+                    self.reporter.warning(Warning::MalformedLine { file: self.path.to_path_buf(), line: self.line, reason });
+                    continue;
+                }
+            };
+            if let Some(timestamp) = entry.timestamp {
+                if let Some((earlier, earlier_line, threshold)) = self.previous {
+                    if timestamp < threshold {
+                        self.summary.regressions += 1;
+                        if options.history.as_ref().is_some_and(|history| history.borrow_mut().first_regression()) {
+                            self.reporter.warning(Warning::OrderAssumed {
+                                file: self.path.to_path_buf(),
+                                line: self.line,
+                                option: "--after-status".to_string(),
+                            });
+                        }
+                        if options.check_order {
+                            self.reporter.warning(Warning::TimestampRegression {
+                                file: self.path.to_path_buf(),
+                                line: self.line,
+                                timestamp,
+                                previous_line: earlier_line,
+                                previous: earlier,
+                            });
+                        }
+                    }
+                }
+                self.previous = Some((timestamp, self.line, timestamp - options.order_slack));
+                self.first = self.first.or(Some((timestamp, self.line)));
+            }
+            if entry.raw_request.is_some() {
+                self.summary.malformed_requests += 1;
+            }
+            if !entry.invalid.is_empty() {
+                self.summary.invalid_numbers += 1;
+            }
+            let hostname = entry.client.as_ref().is_some_and(Client::is_host);
+            if let (Some(count), true) = (&mut self.summary.hostname_clients, hostname) {
+                *count += 1;
+            }
+            if !self.checked_fields {
+                self.filter.check_fields(&entry)?;
+                self.checked_fields = true;
+            }
+            let mut matched = timer.time(Stage::Match, || entry.is_match(self.filter));
+            // --after-status learns from every entry but only judges those that otherwise match.
+            let mut recent = true;
+            if let Some(history) = &options.history {
+                let mut history = history.borrow_mut();
+                recent = !matched || history.admit(&entry);
+                history.observe(&entry);
+                matched &= recent;
+            }
+            if matched {
+                let provenance = Provenance { source_file: self.path.to_path_buf(), line_no: self.line, byte_offset };
+                return Ok(Some(Match { line: line.into_string(), entry, provenance }));
+            }
+            if options.count_rejections {
+                let rejection = self.filter.rejection(&entry);
+                self.summary.reject(rejection.as_deref().unwrap_or(if recent { "-" } else { "after_status" }));
+            }
+            self.set_aside(&line)?;
+        }
+        // Ctrl-C reaches the whole pipeline, so a writer such as `tail -f` may end the input
+        // before another line arrives to be turned away above.
+        if interrupt::requested() {
+            self.summary.interrupted = true;
+        }
+        self.ended = true;
+        Ok(None)
+    }
+
+    /// Passes on a line that parsed, or was blank, but didn't match: to --annotate, and unless
+    /// blank to --unmatched-file.
+    fn set_aside(&self, line: &str) -> Result<(), Error> {
+        if self.options.annotate {
+            annotate::print(Marker::Miss, line);
+        }
+        match &self.options.unmatched {
+            Some(sink) if !line.is_empty() => sink.borrow_mut().write_line(line, '\n'),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_match(&mut self, entry: &Entry) {
+        self.summary.matched += 1;
+        if let Some(bytes) = &mut self.summary.matched_bytes {
+            bytes.add(entry.bytes);
+        }
+    }
 
-    #[arg(short, long, num_args = 1..=2)]
-    timestamp: Option<Vec<String>>,
+    fn finish(mut self) -> RunSummary {
+        if let Some(unwrapper) = self.parser.unwrapper() {
+            // A line split over records that the input ends in the middle of, once it was all read.
+            let unfinished = if self.ended { unwrapper.unfinished() } else { 0 };
+            if unfinished > 0 {
+                self.summary.malformed += unfinished;
+                let reason = format!("the input ends before the last part of {} split line(s)", unfinished);
+                self.reporter.warning(Warning::MalformedLine { file: self.path.to_path_buf(), line: self.line, reason });
+            }
+            self.summary.wrapper_errors = Some(unwrapper.errors() + unfinished);
+        }
+        if let Some(gaps) = self.parser.gaps().filter(|gaps| gaps.out_of_order() + gaps.untracked() > 0) {
+            self.reporter.warning(Warning::GapUnknown {
+                file: self.path.to_path_buf(),
+                out_of_order: gaps.out_of_order(),
+                untracked: gaps.untracked(),
+                max_clients: gaps.max_clients(),
+            });
+        }
+        if let Some(gzip) = &self.gzip {
+            self.summary.compressed_bytes = Some(gzip.size);
+            // Once every line was read the decoder has ended, at the end of the file unless it
+            // stopped at something it couldn't decode.
+            if let Some(consumed) = gzip.consumed().filter(|&consumed| self.ended && consumed < gzip.size) {
+                self.reporter.warning(Warning::GzipIncomplete { file: self.path.to_path_buf(), consumed, size: gzip.size });
+            }
+        }
+        self.reporter.summary(&self.summary);
+        self.summary
+    }
 }
 
-fn parse_or_err<T: FromStr>(value: &str) -> Result<T, String> {
-    value.parse().map_err(|_| format!("Invalid value for filter: {}", value))
+/// The line `parser` just parsed, as matches and --unmatched-file pass it on: with --unwrap the
+/// line the container wrote, all its parts joined, rather than the record.
+fn unwrapped(parser: &LineParser, line: Text) -> Text {
+    match parser.unwrapper() {
+        Some(unwrapper) => Text::from(unwrapper.payload().to_string()),
+        None => line,
+    }
 }
 
-fn parse_string_filter(args: Vec<String>) -> Result<StringFilter, String> {
-    if args[0] == "none" {
-        Ok(StringFilter::None)
+/// Reads `source`, calling `on_match` with each line that passes `filter`.
+fn scan(
+    source: &Source,
+    new_parser: &impl Fn(&Source) -> Result<LineParser, Error>,
+    options: &ScanOptions,
+    filter: &EntryFilter,
+    reporter: &Reporter,
+    timer: &mut impl StageTimer,
+    mut on_match: impl FnMut(Match) -> Result<(), String>,
+) -> Result<RunSummary, Error> {
+    let parser = new_parser(source)?;
+    if let Some(ranges) = partitions(source, &parser, options)? {
+        return scan_partitioned(source, ranges, parser, new_parser, options, filter, reporter, on_match);
+    }
+    let mut scanner = Scanner::open(source, parser, options, filter, reporter)?;
+    while let Some(found) = scanner.next_match(timer)? {
+        scanner.record_match(&found.entry);
+        on_match(found)?;
+        if options.limit.is_some_and(|limit| scanner.summary.matched >= limit) {
+            break;
+        }
     }
-    else {
-        match args[0].as_str() {
-            "contains" => Ok(StringFilter::Contains(args[1].clone())),
-            "eq" => Ok(StringFilter::Eq(args[1].clone())),
-            "starts_with" => Ok(StringFilter::StartsWith(args[1].clone())),
-            "ends_with" => Ok(StringFilter::EndsWith(args[1].clone())),
-            _ => Err(format!("Invalid filter {}", args[0]))
+    Ok(scanner.finish())
+}
+
+/// The byte ranges to read `source` in with --jobs, or `None` to read it on one thread: for
+/// compressed files, pipes and small files, parsers that carry state across lines, and options
+/// that follow the lines in one sequence, such as --limit, --annotate, --after-status and the
+/// sinks.
+fn partitions(source: &Source, parser: &LineParser, options: &ScanOptions) -> Result<Option<Vec<Range<u64>>>, Error> {
+    let sequential = options.annotate
+        || options.limit.is_some()
+        || options.unmatched.is_some()
+        || options.malformed.is_some()
+        || options.history.is_some()
+        || options.overlaps.get(source.path()).is_some_and(|&lines| lines > 0)
+        || options.duplicates.is_some()
+        || parser.carries_state();
+    match source {
+        Source::File(path) if options.jobs > 1 && !sequential => {
+            partition::ranges(path, options.jobs).map_err(|e| Error::Io { file: path.clone(), message: e.to_string() })
         }
+        _ => Ok(None),
     }
 }
 
-fn parse_eq_filter<T: PartialEq + FromStr>(args: Vec<String>) -> Result<EqFilter<T>, String> {
-    if args[0] == "none" {
-        Ok(EqFilter::None)
+/// What a --jobs thread read of its range, besides its matches.
+struct Part {
+    summary: RunSummary,
+    /// Warnings, with lines numbered from the start of the range.
+    warnings: Vec<Warning>,
+    /// The first timestamp and its line, and the scanner's `previous` at the end of the range.
+    first: Option<(DateTime<FixedOffset>, usize)>,
+    last: Option<(DateTime<FixedOffset>, usize, DateTime<FixedOffset>)>,
+}
+
+/// Reads `ranges` of `source` on a thread each, with a parser and counts of its own, and calls
+/// `on_match` with their matches in file order; matches of later ranges wait in memory until
+/// the ranges before them are passed on. Each thread numbers lines from the start of its range,
+/// so line numbers, warnings and a timestamp going backwards across a boundary are settled as
+/// the ranges are joined.
+#[allow(clippy::too_many_arguments)]
+fn scan_partitioned(
+    source: &Source,
+    ranges: Vec<Range<u64>>,
+    parser: LineParser,
+    new_parser: &impl Fn(&Source) -> Result<LineParser, Error>,
+    options: &ScanOptions,
+    filter: &EntryFilter,
+    reporter: &Reporter,
+    mut on_match: impl FnMut(Match) -> Result<(), String>,
+) -> Result<RunSummary, Error> {
+    let path = source.path();
+    check_recorded(&parser, filter, reporter);
+    let mut parsers = vec![parser];
+    while parsers.len() < ranges.len() {
+        parsers.push(new_parser(source)?);
+    }
+    // The sinks and overlaps can't be shared between threads, and `partitions` leaves them out.
+    let ScanOptions { strict, check_order, order_slack, show_bytes, count_rejections, max_line_length, mmap_threshold, .. } = *options;
+    // A mapped file is mapped once for all the threads.
+    let map = input::map(path, mmap_threshold).map_err(|e| Error::Io { file: path.to_path_buf(), message: e.to_string() })?;
+    thread::scope(|scope| {
+        let mut workers = Vec::new();
+        for (range, parser) in ranges.into_iter().zip(parsers) {
+            let (sender, receiver) = mpsc::channel();
+            let map = map.clone();
+            let worker = scope.spawn(move || -> Result<Part, Error> {
+                let options = ScanOptions {
+                    strict,
+                    check_order,
+                    order_slack,
+                    show_bytes,
+                    annotate: false,
+                    limit: None,
+                    count_rejections,
+                    max_line_length,
+                    mmap_threshold,
+                    unmatched: None,
+                    malformed: None,
+                    overlaps: Rc::default(),
+                    duplicates: None,
+                    jobs: 1,
+                    history: None,
+                };
+                let reporter = Reporter::holding();
+                let lines = match &map {
+                    Some(map) => InputSource::Mapped(
+                        MappedLines::new(map.clone(), range, max_line_length)
+                            .map_err(|e| Error::Io { file: path.to_path_buf(), message: e.to_string() })?,
+                    ),
+                    None => {
+                        let reader = partition::open(path, &range).map_err(|e| Error::Io { file: path.to_path_buf(), message: e.to_string() })?;
+                        InputSource::Stream(LineReader::new(reader, max_line_length).starting_at(range.start), None)
+                    }
+                };
+                let mut scanner = Scanner::new(path, lines, parser, &options, filter, &reporter);
+                while let Some(found) = scanner.next_match(&mut NoTiming)? {
+                    scanner.record_match(&found.entry);
+                    // The receiver is gone once an earlier range failed.
+                    if sender.send(found).is_err() {
+                        break;
+                    }
+                }
+                let (first, last) = (scanner.first, scanner.previous);
+                Ok(Part { summary: scanner.finish(), warnings: reporter.take_held(), first, last })
+            });
+            workers.push((receiver, worker));
+        }
+
+        let mut summary: Option<RunSummary> = None;
+        let mut last = None;
+        for (receiver, worker) in workers {
+            let before = summary.as_ref().map_or(0, |summary| summary.lines);
+            for mut found in receiver {
+                found.provenance.line_no += before;
+                on_match(found)?;
+            }
+            let mut part = worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)).map_err(|mut e| {
+                e.shift_lines(before);
+                e
+            })?;
+            for warning in &mut part.warnings {
+                warning.shift_lines(before);
+            }
+            if let (Some((timestamp, line)), Some((previous, previous_line, threshold))) = (part.first, last) {
+                if timestamp < threshold {
+                    part.summary.regressions += 1;
+                    if check_order {
+                        let line = line + before;
+                        let at = part.warnings.iter().position(|warning| warning.line().is_some_and(|l| l > line));
+                        let warning = Warning::TimestampRegression { file: path.to_path_buf(), line, timestamp, previous_line, previous };
+                        part.warnings.insert(at.unwrap_or(part.warnings.len()), warning);
+                    }
+                }
+            }
+            last = part.last.map(|(timestamp, line, threshold)| (timestamp, line + before, threshold)).or(last);
+            for warning in part.warnings {
+                reporter.warning(warning);
+            }
+            let interrupted = part.summary.interrupted;
+            match &mut summary {
+                Some(summary) => summary.merge(part.summary),
+                None => summary = Some(part.summary),
+            }
+            if interrupted {
+                break;
+            }
+        }
+        let summary = summary.expect("a file is split into at least one range");
+        reporter.summary(&summary);
+        Ok(summary)
+    })
+}
+
+/// Reads all `sources` at once, calling `on_match` with the matches of all of them in timestamp
+/// order. A match earlier than one already passed on, beyond --order-slack, means its input is
+/// out of order; it's passed on as it comes, with a warning once per input.
+fn scan_interleaved(
+    sources: &[Source],
+    new_parser: impl Fn(&Source) -> Result<LineParser, Error>,
+    options: &ScanOptions,
+    filter: &EntryFilter,
+    reporter: &Reporter,
+    mut on_match: impl FnMut(Match) -> Result<(), String>,
+) -> Result<Vec<RunSummary>, Error> {
+    let mut scanners = Vec::new();
+    for source in sources {
+        scanners.push(Scanner::open(source, new_parser(source)?, options, filter, reporter)?);
     }
-    else {
-        match args[0].as_str() {
-            "eq" => Ok(EqFilter::Eq(parse_or_err(args[1].as_str())?)),
-            "neq" => Ok(EqFilter::Neq(parse_or_err(args[1].as_str())?)),
-            _ => Err(format!("Invalid filter {}", args[0]))
+    let mut merge = Interleave::new(scanners.len());
+    for (i, scanner) in scanners.iter_mut().enumerate() {
+        if let Some(found) = scanner.next_match(&mut NoTiming)? {
+            merge.push(i, found.entry.timestamp, found);
         }
     }
+    let mut matched = 0;
+    let mut latest: Option<DateTime<FixedOffset>> = None;
+    let mut warned = HashSet::new();
+    while let Some((i, timestamp, found)) = merge.pop() {
+        let scanner = &mut scanners[i];
+        match (timestamp, latest) {
+            (Some(timestamp), Some(previous)) if timestamp < previous - options.order_slack => {
+                if warned.insert(i) {
+                    reporter.warning(Warning::Interleave {
+                        file: scanner.path.to_path_buf(),
+                        line: found.provenance.line_no,
+                        timestamp,
+                        previous,
+                    });
+                }
+            }
+            _ => latest = latest.max(timestamp),
+        }
+        scanner.record_match(&found.entry);
+        on_match(found)?;
+        matched += 1;
+        if options.limit.is_some_and(|limit| matched >= limit) {
+            break;
+        }
+        if let Some(found) = scanner.next_match(&mut NoTiming)? {
+            merge.push(i, found.entry.timestamp, found);
+        }
+    }
+    Ok(scanners.into_iter().map(Scanner::finish).collect())
 }
 
-fn parse_ord_filter<T: PartialOrd + FromStr>(args: Vec<String>) -> Result<OrdFilter<T>, String> {
-    if args[0] == "none" {
-        Ok(OrdFilter::None)
+/// Mistakes in naming the input files: none given to a command that needs them, or a file taken
+/// as the value of an option, which likely broke that option too. Inputs are only looked up,
+/// not opened.
+fn check_inputs(files: &[PathBuf], command: &Commands) -> Result<(), String> {
+    if files.is_empty() {
+        if !command.needs_input() {
+            return Ok(());
+        }
+        return Err(match input::stray_file(std::env::args().skip(1), &[]) {
+            Some(stray) => format!(
+                "no input files given; {} was taken as the value of an option, pass it before the command or with --input",
+                stray
+            ),
+            None => "no input files given; try the built-in sample with `log-parser demo filter --all`".to_string(),
+        });
     }
-    else {
-        match args[0].as_str() {
-            "eq" => Ok(OrdFilter::Eq(parse_or_err(args[1].as_str())?)),
-            "neq" => Ok(OrdFilter::Neq(parse_or_err(args[1].as_str())?)),
-            "gt" => Ok(OrdFilter::Gt(parse_or_err(args[1].as_str())?)),
-            "lt" => Ok(OrdFilter::Lt(parse_or_err(args[1].as_str())?)),
-            "gte" => Ok(OrdFilter::Gte(parse_or_err(args[1].as_str())?)),
-            "lte" => Ok(OrdFilter::Lte(parse_or_err(args[1].as_str())?)),
-            _ => Err(format!("Invalid filter {}", args[0]))
+    let files = input::expand(files.to_vec())?;
+    if let Some(missing) = files.iter().find(|file| !file.exists()) {
+        if let Some(stray) = input::stray_file(std::env::args().skip(1), &files) {
+            return Err(format!(
+                "{}: no such file\n  did you mean {}? It was taken as the value of an option; pass it with --input",
+                missing.display(),
+                stray
+            ));
         }
     }
+    Ok(())
 }
 
-impl TryFrom<FilterArgs> for LogFilter {
-    type Error = String;
+/// What the options build before any input is opened.
+struct Plan {
+    /// The command's filter; passes everything for commands without filter options.
+    filter: EntryFilter,
+    /// The --format template.
+    template: Option<Template>,
+    order_slack: TimeDelta,
+    /// The --extract options whose fields the command reads.
+    extractions: Arc<Extractions>,
+    /// The fields derived from the path that the command reads.
+    derived: Derived,
+    /// The filter or the command names `gap`.
+    gaps: bool,
+    /// The jobs of `batch`.
+    jobs: Vec<Job>,
+}
 
-    fn try_from(value: FilterArgs) -> Result<Self, Self::Error> {
-        Ok(LogFilter {
-            status_code: value.status_code.map_or(Ok(EqFilter::Any), parse_eq_filter)?,
-            user_agent: value.user_agent.map_or(Ok(StringFilter::Any),parse_string_filter)?,
-            ip: value.ip.map_or(Ok(EqFilter::Any), parse_eq_filter)?,
-            timestamp: value.timestamp.map_or(Ok(OrdFilter::Any), parse_ord_filter)?,
+/// Checks every option and builds what can be built from them alone, so a mistake fails the run
+/// before any input is read, together with every other mistake. `None` when --explain-filter
+/// printed the filter instead.
+fn validate_and_build(cli: &mut Cli, reporter: &Reporter) -> Result<Option<Plan>, Errors> {
+    let mut errors: Vec<Error> = Vec::new();
+    if let Mode::Run(command) = &cli.mode {
+        errors.extend(check_inputs(&cli.files, command).err().map(Error::from));
+    }
+    // The jobs of a batch may settle the log type, which the parser is built for.
+    let mut jobs = Vec::new();
+    if let Mode::Run(Commands::Batch { jobs_file, .. }) | Mode::Demo { command: Some(Commands::Batch { jobs_file, .. }), .. } =
+        &cli.mode
+    {
+        match batch::load(jobs_file, cli.log_type, cli.format_string.is_some(), reporter) {
+            Ok((loaded, log_type)) => (jobs, cli.log_type) = (loaded, log_type),
+            Err(job_errors) => errors.extend(job_errors),
+        }
+    }
+    match build_parser(cli.format_string.as_deref(), cli.log_type, cli.wrapping()) {
+        Ok(parser) => registry::set_declared(parser.fields()),
+        Err(message) => errors.push(message.into()),
+    }
+    let order_slack = TimeDelta::from_std(cli.order_slack.0).unwrap_or_else(|e| {
+        errors.push(format!("--order-slack: {}", e).into());
+        TimeDelta::zero()
+    });
+    let extractions = Extractions::new(std::mem::take(&mut cli.extract)).unwrap_or_else(|message| {
+        errors.push(message.into());
+        Extractions::default()
+    });
+    extract::set_defined(&extractions);
+    let (args, template) = match &mut cli.mode {
+        Mode::Run(command) | Mode::Demo { command: Some(command), .. } => {
+            let detects = cli.log_type == LogKind::Auto && cli.format_string.is_none();
+            errors.extend(command.check(!cli.files.is_empty(), detects, cli.tz).into_iter().map(Error::from));
+            errors.extend(group::check_fields(&command.keys()).err().map(Error::from));
+            errors.extend(command.sinks().into_iter().filter_map(|(option, path)| sink::check_writable(option, path).err()));
+            let template = match command {
+                Commands::Filter { format: Some(format), .. } => {
+                    Template::compile(format, cli.escape).map_err(|message| errors.push(message.into())).ok()
+                }
+                _ => None,
+            };
+            (command.take_filter(), template)
+        }
+        Mode::Demo { command: None, .. } | Mode::Schema { .. } | Mode::Operators { .. } | Mode::SelfTest { .. } => (FilterArgs::default(), None),
+    };
+    let explain = args.explain_filter;
+    let built = EntryFilter::build(args).map_err(|filter_errors| errors.extend(filter_errors)).ok();
+    let Some((filter, mut explanation)) = built.filter(|_| errors.is_empty()) else {
+        return Err(Errors(errors));
+    };
+    for notice in explanation.notices.drain(..) {
+        reporter.notice(notice);
+    }
+    if explain {
+        print!("{}", explanation);
+        return Ok(None);
+    }
+    let fields = template.as_ref().map(Template::names).unwrap_or_default();
+    let (extractions, derived, gaps) = match &cli.mode {
+        Mode::Run(command) | Mode::Demo { command: Some(command), .. } => {
+            let reads = |name: &str| filter.reads(name) || command.reads(name, &fields) || jobs.iter().any(|job| job.reads(name));
+            let gaps =
+                filter.reads(gap::FIELD) || command.names(gap::FIELD, &fields) || jobs.iter().any(|job| job.names(gap::FIELD));
+            (extractions.used(reads), Derived::used(reads), gaps)
+        }
+        Mode::Demo { command: None, .. } | Mode::Schema { .. } | Mode::Operators { .. } | Mode::SelfTest { .. } => {
+            (Extractions::default(), Derived::default(), false)
+        }
+    };
+    Ok(Some(Plan { filter, template, order_slack, extractions: Arc::new(extractions), derived, gaps, jobs }))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let reporter = Reporter::new(cli.errors);
+    if cli.schema_version {
+        reporter.schema();
+    }
+    interrupt::install();
+    match run(cli, &reporter) {
+        Ok(_) if interrupt::pressed() => ExitCode::from(interrupt::EXIT_CODE),
+        Ok(code) => code,
+        Err(Errors(errors)) => {
+            for error in &errors {
+                reporter.error(error);
+            }
+            ExitCode::from(fail_on::ERROR)
+        }
+    }
+}
+
+/// The style --color, --theme and the config file's `[theme]` section make up: the base palette
+/// is --theme's, else the config's `base`, else dark, and the config's elements override it.
+fn themed(cli: &Cli, config: &Config) -> Result<Style, Vec<Error>> {
+    let base = config.theme.iter().find(|setting| setting.key == "base");
+    let theme = match (cli.theme, base) {
+        (Some(theme), _) => theme,
+        (None, Some(base)) => {
+            let value = base.text().map_err(|message| vec![config.error(base, message)])?;
+            Theme::from_str(value, false).map_err(|_| {
+                let names: Vec<String> = Theme::value_variants()
+                    .iter()
+                    .filter_map(|theme| Some(theme.to_possible_value()?.get_name().to_string()))
+                    .collect();
+                vec![config.error(base, format!("base: {:?} isn't a theme; expected one of {}", value, names.join(", ")))]
+            })?
+        }
+        (None, None) => Theme::default(),
+    };
+    let enabled = style::enabled(cli.color, cli.to_terminal(), |name| std::env::var_os(name));
+    let mut style = Style::new(theme, enabled);
+    let errors: Vec<Error> = config
+        .theme
+        .iter()
+        .filter(|setting| setting.key != "base")
+        .filter_map(|setting| {
+            let set = setting.text().and_then(|value| style.set(&setting.key, value));
+            set.err().map(|message| config.error(setting, message))
         })
+        .collect();
+    match errors.is_empty() {
+        true => Ok(style),
+        false => Err(errors),
     }
 }
 
-#[filter_for(CombinedLogEntry<'a>)]
-struct LogFilter {
-    user_agent: StringFilter,
-    status_code: EqFilter<StatusCode>,
-    ip: EqFilter<IpAddr>,
-    timestamp: OrdFilter<DateTime<FixedOffset>>,
+fn build_parser(format_string: Option<&str>, log_type: LogKind, wrapping: Wrapping) -> Result<LineParser, String> {
+    let parser = match format_string {
+        Some(format) => LineParser::Custom(format.parse::<LogFormat>()?),
+        None => LineParser::from(log_type),
+    };
+    Ok(wrapping.wrap(parser))
 }
 
-fn main() -> Result<(), String> {
-    let cli = Cli::parse();
+fn run(mut cli: Cli, reporter: &Reporter) -> Result<ExitCode, Errors> {
+    let later = match &mut cli.mode {
+        Mode::Run(command) | Mode::Demo { command: Some(command), .. } => command.take_inputs(),
+        Mode::Demo { command: None, .. } | Mode::Schema { .. } | Mode::Operators { .. } | Mode::SelfTest { .. } => Vec::new(),
+    };
+    cli.files.extend(later);
+    entry::set_strict_numbers(cli.strict_numbers);
+    parsers::set_csv_map(cli.csv_map.clone());
+    let plan = validate_and_build(&mut cli, reporter)?;
+    let Some(Plan { filter, template, order_slack, extractions, derived, gaps, jobs }) = plan else {
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    // A fresh parser per file and pass, since W3C parsing carries state across lines.
+    // The log type is checked against each file's first lines once, on the first pass.
+    let wrapping = cli.wrapping();
+    let build = |log_type| {
+        let mut parser = build_parser(cli.format_string.as_deref(), log_type, wrapping)?;
+        if cli.normalize_paths {
+            parser = LineParser::NormalizePaths(Box::new(parser), cli.normalize_case);
+        }
+        if !derived.is_empty() {
+            parser = LineParser::Derive(Box::new(parser), derived);
+        }
+        if gaps {
+            parser = LineParser::Gaps(Box::new(parser), Gaps::new(cli.max_clients));
+        }
+        Ok::<_, String>(match extractions.is_empty() {
+            true => parser,
+            false => LineParser::Extract(Box::new(parser), Arc::clone(&extractions)),
+        })
+    };
+    let resolved = RefCell::new(HashMap::new());
+    let new_parser = |source: &Source| -> Result<LineParser, Error> {
+        if cli.format_string.is_some() {
+            return Ok(build(cli.log_type)?);
+        }
+        let cached = resolved.borrow().get(source.path()).copied();
+        let log_type = match cached {
+            Some(log_type) => log_type,
+            None => {
+                let log_type = detect::resolve(source, cli.log_type, wrapping, cli.strict, reporter)?;
+                resolved.borrow_mut().insert(source.path().to_path_buf(), log_type);
+                log_type
+            }
+        };
+        Ok(build(log_type)?)
+    };
+    output::set_time_format(cli.time_format.clone());
+    output::set_delimiter(Delimiter::new(cli.delimiter.clone(), cli.delimiter_escape));
+    let aligned = match cli.align {
+        Align::Auto => cli.delimiter == "\t" && cli.to_terminal(),
+        Align::Always => true,
+        Align::Never => false,
+    };
+    table::set_layout(Layout { aligned, truncate: !cli.no_truncate });
+    let config = config::load(cli.config.as_deref()).map_err(Errors)?;
+    match (themed(&cli, &config), Classifier::configured(&config), Referrers::configured(&config, &cli.site_host)) {
+        (Ok(style), Ok(classifier), Ok(referrers)) => {
+            style::set_style(style);
+            classify::set_classifier(classifier);
+            referrer::set_referrers(referrers);
+        }
+        (style, classifier, referrers) => {
+            return Err(Errors(style.err().into_iter().chain(classifier.err()).chain(referrers.err()).flatten().collect()))
+        }
+    }
+    let budget = MemoryBudget::new(cli.max_memory);
+    let options = ScanOptions {
+        strict: cli.strict,
+        check_order: cli.check_order,
+        order_slack,
+        show_bytes: false,
+        annotate: false,
+        limit: None,
+        count_rejections: cli.summary,
+        max_line_length: cli.max_line_length,
+        mmap_threshold: cli.mmap_threshold,
+        unmatched: None,
+        malformed: None,
+        overlaps: Rc::default(),
+        duplicates: match cli.skip_consecutive_duplicates {
+            true => Some(1),
+            false => cli.skip_duplicates_window.map(|window| window as usize),
+        },
+        jobs: cli.jobs.get(),
+        history: filter.after_status.clone().map(|spec| Rc::new(RefCell::new(StatusHistory::new(spec)))),
+    };
+
+    let files = cli.files;
+    let (sources, command) = match (cli.mode, files.is_empty()) {
+        (Mode::Run(command), false) => (input::expand(files)?.into_iter().map(Source::File).collect::<Vec<_>>(), command),
+        // Commands that need input files were stopped by `check_inputs`.
+        (Mode::Run(command), true) => (Vec::new(), command),
+        (Mode::Demo { .. }, false) => {
+            return Err("demo reads the built-in sample; remove the file arguments".to_string().into())
+        }
+        (Mode::Demo { write: Some(_), command: Some(_) }, true) => {
+            return Err("demo --write only writes the sample; run commands on the written file".to_string().into())
+        }
+        (Mode::Demo { write: Some(path), command: None }, true) => {
+            demo::write(&path).map_err(|e| Error::Io { file: path.clone(), message: e.to_string() })?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        (Mode::Demo { command: Some(command), .. }, true) => (vec![Source::Demo], command),
+        (Mode::Demo { write: None, command: None }, true) => {
+            return Err("demo needs a command such as `filter --all`, or --write <FILE>".to_string().into())
+        }
+        (Mode::Schema { .. }, false) => return Err("schema takes no input files".to_string().into()),
+        (Mode::Schema { kind }, true) => {
+            print!("{}", schema::render(kind)?);
+            return Ok(ExitCode::SUCCESS);
+        }
+        (Mode::Operators { .. }, false) => return Err("--list-operators takes no input files".to_string().into()),
+        (Mode::Operators { output }, true) => {
+            if cli.schema_version && matches!(output, StatsOutput::Json) {
+                print!("{}", schema::preamble());
+            }
+            print!("{}", fields::render_operators(output));
+            return Ok(ExitCode::SUCCESS);
+        }
+        (Mode::SelfTest { .. }, false) => return Err("self-test takes no input files".to_string().into()),
+        (Mode::SelfTest { output }, true) => {
+            if cli.schema_version && matches!(output, CheckOutput::Json) {
+                print!("{}", schema::preamble());
+            }
+            let report = self_test::run();
+            print!("{}", self_test::render(&report, output)?);
+            return Ok(if report.failed == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE });
+        }
+    };
 
-    match cli.command {
-        Commands::Filter(args) => {
-            let filter: LogFilter = args.try_into()?;
+    let options = match cli.dedupe_overlap && sources.len() > 1 {
+        true => ScanOptions { overlaps: Rc::new(overlap::find(&sources, cli.max_line_length, reporter)?), ..options },
+        false => options,
+    };
 
-            let file = File::open(cli.file).map_err(|e| e.to_string())?;
-            let reader = std::io::BufReader::new(file);
-            let lines = reader
-                .lines()
-                .filter(|l| l.as_ref().is_ok_and(|l| !l.is_empty()))
-                .map(|l| l.unwrap());
+    // With --schema-version, JSON output starts by naming its schema version.
+    let announce = |json: bool| {
+        if cli.schema_version && json {
+            print!("{}", schema::preamble());
+        }
+    };
+    // Stats, histogram, top and report print their result, or write it to --output-file.
+    let emit = |output_file: Option<PathBuf>, json: bool, rendered: String| -> Result<(), Error> {
+        let mut sink = Sink::create_or_stdout(output_file.as_deref(), ChunkLimits::default())?;
+        if cli.schema_version && json {
+            sink.write_str(&schema::preamble())?;
+        }
+        sink.write_str(&rendered)?;
+        sink.finish()
+    };
+    match command {
+        Commands::Filter {
+            output,
+            mut fields,
+            with_provenance,
+            verbose_matches,
+            unique,
+            throttle,
+            summary_by,
+            count_by,
+            count,
+            show_bytes,
+            annotate,
+            force,
+            limit,
+            fail_on: conditions,
+            interleave,
+            sort,
+            output_file,
+            chunk_size,
+            chunk_rows,
+            unmatched_file,
+            malformed_file,
+            pager,
+            follow_glob,
+            poll_interval,
+            reload_on_change,
+            latest_per,
+            oldest_per,
+            max_keys,
+            group_by,
+            assume_sorted,
+            mut dict_fields,
+            ua_dictionary,
+            dict_file,
+            header,
+            inspect,
+            ..
+        } => {
+            // Sorted matches are buffered pre-rendered as (missing key, key, input order, output).
+            let mut sorter = ExternalSorter::<(bool, i64, u64, String)>::new(budget.account("--sort", ""));
+            let mut order = 0;
+            if group_by.is_some() && matches!(output, OutputFormat::Json | OutputFormat::JsonArray) {
+                return Err("--group-by prints a header line before each group, which JSON output has no place for; print with --fields or --format instead".to_string().into());
+            }
+            let mut grouped = group_by.map(|key| Grouped::new(key, cli.tz, assume_sorted, &budget));
+            if let Some(key) = group_by.filter(|_| !grouped.as_ref().is_some_and(Grouped::streaming)) {
+                reporter.notice(Notice::Buffering { key: key.name(), by_time: key.is_time(), assume_sorted })
+            }
+            if with_provenance {
+                fields.extend(["source_file", "line_no", "byte_offset"].map(str::to_string));
+            }
+            if ua_dictionary && !dict_fields.iter().any(|field| field == "user_agent") {
+                dict_fields.push("user_agent".to_string());
+            }
+            let dictionary_memory = budget.account("--dict-fields", "write fewer --dict-fields or raise --max-memory");
+            let mut dictionary = (!dict_fields.is_empty()).then(|| Dictionary::new(dict_fields, dictionary_memory));
+            let mut selection = (!fields.is_empty()).then(|| FieldSelection::new(fields));
+            let mut template = template;
+            // Log lines printed to the terminal, or its pager, take the color of their status.
+            let colored = output_file.is_none() && matches!(output, OutputFormat::Line | OutputFormat::Combined);
+            // --fields rows end like table rows, so NUL-separated output stays NUL-separated.
+            let end = if selection.is_some() { output::delimiter().terminator() } else { '\n' };
+            let mut seen = HashSet::new();
+            let mut seen_memory = budget.account("--unique", "narrow the filter or raise --max-memory");
+            let mut throttle = throttle.map(Throttle::new);
+            let mut inspector = inspect.map(Inspector::new);
+            let limit = inspector.as_ref().and_then(Inspector::limit).or(limit);
+            let mut retained = match (latest_per, oldest_per) {
+                (by, _) if !by.is_empty() => Some((Retained::new(Keep::Latest, by, cli.tz, max_keys), "--latest-per")),
+                (_, by) if !by.is_empty() => Some((Retained::new(Keep::Oldest, by, cli.tz, max_keys), "--oldest-per")),
+                _ => None,
+            }
+            .map(|(retained, flag)| retained.budgeted(budget.account(flag, "narrow the filter, use fewer keys or raise --max-memory")));
+            let new_breakdowns = || summary_by.iter().chain(&count_by).map(|&by| Breakdown::new(by, cli.tz)).collect::<Vec<_>>();
+            let mut breakdowns = new_breakdowns();
+            let mut matches = Count { matched: 0, bytes: show_bytes.then(MatchedBytes::default) };
+            let mut tally = Tally::default();
+            if annotate && !force {
+                let size: u64 = sources.iter().filter_map(|source| source.size().ok()).sum();
+                if size > annotate::MAX_INPUT {
+                    return Err(format!(
+                        "--annotate prints every line of {} of input; pass --force to run it anyway",
+                        size::format_size(size)
+                    )
+                    .into());
+                }
+            }
+            // Counts are short, and output to a file or a pipe is meant for another program.
+            let paged = pager != PagerMode::Never
+                && output_file.is_none()
+                && !(count || annotate || !count_by.is_empty() || follow_glob.is_some())
+                && std::io::stdout().is_terminal();
+            let mut out = match paged.then(pager::command).flatten() {
+                Some(command) => Sink::paged(command, if pager == PagerMode::Always { 0 } else { pager::rows() }),
+                None => Sink::create_or_stdout(output_file.as_deref(), ChunkLimits { bytes: chunk_size, lines: chunk_rows.map(NonZeroUsize::get) })?,
+            }
+            .framed(output);
+            let open = |path: Option<PathBuf>| path.map(|path| Sink::create(&path).map(|sink| Rc::new(RefCell::new(sink))));
+            let unmatched = open(unmatched_file).transpose()?;
+            let malformed = open(malformed_file).transpose()?;
+            let options =
+                ScanOptions { show_bytes, annotate, unmatched: unmatched.clone(), malformed: malformed.clone(), ..options };
+            if cli.schema_version && matches!(output, OutputFormat::Json) && selection.is_none() && template.is_none() && !annotate {
+                out.prologue(schema::preamble())?;
+            }
+            if let Some(selection) = selection.as_ref().filter(|_| header) {
+                out.prologue(selection.header())?;
+            }
+
+            // Prints a match, or buffers it for --sort or --group-by.
+            let mut emit = |found: Match| {
+                let rendered = match (&mut selection, &mut template) {
+                    (Some(selection), _) => selection.render(&found, dictionary.as_mut())?,
+                    (None, Some(template)) => Some(template.render(&found)?),
+                    (None, None) => {
+                        let rendered = match (verbose_matches, &mut dictionary) {
+                            (true, _) => output::render_verbose(&found, output, &filter.given())?,
+                            (false, Some(dictionary)) => dictionary.encode_json(&render_match(&found, output)?)?,
+                            (false, None) => render_match(&found, output)?,
+                        };
+                        Some(match colored {
+                            true => style::style().paint_status(found.entry.status_code, &rendered),
+                            false => rendered,
+                        })
+                    }
+                };
+                let Some(rendered) = rendered else {
+                    return Ok(());
+                };
+                if unique {
+                    if seen.contains(&rendered) {
+                        return Ok(());
+                    }
+                    seen_memory.charge(rendered.len() + std::mem::size_of::<String>())?;
+                    seen.insert(rendered.clone());
+                }
+                match (&sort, &mut grouped) {
+                    (Some(key), _) => {
+                        let value = key.value(&found.entry);
+                        let size = rendered.len();
+                        order += 1;
+                        sorter.push((value.is_none(), value.unwrap_or_default(), order, rendered), size)
+                    }
+                    (None, Some(grouped)) => grouped.add(&found, rendered, &mut out, end),
+                    (None, None) => out.write_line(&rendered, end).map_err(|e| e.to_string()),
+                }
+            };
+            let mut on_match = |breakdowns: &mut Vec<Breakdown>, found: Match| {
+                let entry = &found.entry;
+                for breakdown in breakdowns {
+                    breakdown.add(&found.provenance.source_file, entry);
+                }
+                if count || !count_by.is_empty() {
+                    return Ok(());
+                }
+                if annotate {
+                    annotate::print(Marker::Match, &found.line);
+                    return Ok(());
+                }
+                if throttle.as_mut().is_some_and(|throttle| !throttle.admit(entry, reporter)) {
+                    return Ok(());
+                }
+                if let Some(inspector) = &mut inspector {
+                    inspector.add(found);
+                    return Ok(());
+                }
+                match &mut retained {
+                    Some(retained) => retained.add(found),
+                    None => emit(found),
+                }
+            };
 
-            for line in lines {
-                let entry = parse(LogType::CombinedLog, &line).map_err(|e| e.to_string())?;
-                if let LogEntry::CombinedLog(entry) = entry {
-                    if entry.is_match(&filter) {
-                        println!("{}", line);
+            if let Some(pattern) = &follow_glob {
+                let mut follow = FollowGlob::new(pattern, cli.max_line_length)?;
+                let mut parser = build(cli.log_type)?;
+                let mut checked_fields = false;
+                let mut watch = filter.filter_file().filter(|_| reload_on_change).map(|path| Watch::new(path.to_path_buf()));
+                while !interrupt::requested() && limit.is_none_or(|limit| matches.matched < limit) {
+                    if let Some(watch) = &mut watch {
+                        match watch.changed() {
+                            Some(Ok(conditions)) => {
+                                let added = conditions.describe();
+                                let removed = filter.reload(conditions).describe();
+                                reporter.notice(Notice::FilterReloaded {
+                                    file: watch.path().to_path_buf(),
+                                    removed: removed.iter().filter(|condition| !added.contains(condition)).cloned().collect(),
+                                    added: added.iter().filter(|condition| !removed.contains(condition)).cloned().collect(),
+                                });
+                            }
+                            Some(Err(reason)) => {
+                                reporter.warning(Warning::FilterFileInvalid { file: watch.path().to_path_buf(), reason })
+                            }
+                            None => {}
+                        }
                     }
+                    follow.poll(reporter, |provenance, line| {
+                        if limit.is_some_and(|limit| matches.matched >= limit) {
+                            return Ok(());
+                        }
+                        tally.lines += 1;
+                        let parsed = line.and_then(|line| Ok(parser.parse(&line)?.map(|entry| (unwrapped(&parser, line.into()).into_string(), entry))));
+                        let (line, entry) = match parsed {
+                            Ok(Some(parsed)) => parsed,
+                            Ok(None) => return Ok(()),
+                            Err(message) if options.strict => {
+                                return Err(Error::Line { file: provenance.source_file, line: provenance.line_no, message })
+                            }
+                            Err(reason) => {
+                                tally.malformed += 1;
+                                let (file, line) = (provenance.source_file, provenance.line_no);
+                                reporter.warning(Warning::MalformedLine { file, line, reason });
+                                return Ok(());
+                            }
+                        };
+                        if !checked_fields {
+                            filter.check_fields(&entry)?;
+                            checked_fields = true;
+                        }
+                        if !entry.is_match(&filter) {
+                            return Ok(());
+                        }
+                        matches.matched += 1;
+                        tally.matched += 1;
+                        Ok(on_match(&mut breakdowns, Match { line, entry, provenance })?)
+                    })?;
+                    thread::sleep(poll_interval.0);
                 }
             }
+            else if interleave {
+                for source in &sources {
+                    breakdowns.iter_mut().for_each(|breakdown| breakdown.start(source.path()));
+                }
+                let options = ScanOptions { limit, ..options };
+                let summaries = scan_interleaved(&sources, new_parser, &options, &filter, reporter, |found| {
+                    on_match(&mut breakdowns, found)
+                })?;
+                for summary in &summaries {
+                    matches.add(summary);
+                    tally.add(summary);
+                }
+            }
+            else {
+                for source in &sources {
+                    let remaining = limit.map(|limit| limit.saturating_sub(matches.matched));
+                    if remaining == Some(0) || interrupt::requested() {
+                        break;
+                    }
+                    let options = ScanOptions { limit: remaining, ..options.clone() };
+                    let mut file_breakdowns = new_breakdowns();
+                    file_breakdowns.iter_mut().for_each(|breakdown| breakdown.start(source.path()));
+                    let summary = scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| {
+                        on_match(&mut file_breakdowns, found)
+                    })?;
+                    matches.add(&summary);
+                    tally.add(&summary);
+                    for (total, breakdown) in breakdowns.iter_mut().zip(file_breakdowns) {
+                        total.merge(breakdown);
+                    }
+                }
+            }
+
+            if let Some(retained) = retained {
+                retained.into_matches().try_for_each(&mut emit)?;
+            }
+            if let Some(inspector) = inspector {
+                let (position, found) = inspector.finish()?;
+                out.write_line(&output::render_inspect(&found, position)?, '\n')?;
+            }
+            if sort.is_some() {
+                sorter.finish(|(_, _, _, rendered)| out.write_line(&rendered, end).map_err(|e| e.to_string()))?;
+            }
+            if let Some(grouped) = grouped {
+                grouped.finish(&mut out, end)?;
+            }
+            let dictionary = match (dictionary, dict_file) {
+                (Some(dictionary), None) => {
+                    out.write_line(&dictionary.to_json(), '\n')?;
+                    None
+                }
+                (Some(dictionary), Some(path)) => {
+                    let mut sink = Sink::create(&path)?;
+                    match selection {
+                        Some(_) => {
+                            let delimiter = output::delimiter();
+                            for row in dictionary.rows() {
+                                sink.write_line(&delimiter.join(&row), delimiter.terminator())?;
+                            }
+                        }
+                        None => sink.write_line(&dictionary.to_json(), '\n')?,
+                    }
+                    Some(sink)
+                }
+                (None, _) => None,
+            };
+
+            if let Some(throttle) = throttle {
+                throttle.finish(reporter);
+            }
+            let (to_stderr, to_stdout) = breakdowns.split_at(summary_by.len());
+            for breakdown in to_stderr {
+                reporter.breakdown(breakdown);
+            }
+            for breakdown in to_stdout {
+                match output {
+                    OutputFormat::Json | OutputFormat::JsonArray => out.write_line(&serde_json::to_string(breakdown).map_err(|e| e.to_string())?, '\n')?,
+                    _ => out.write_str(&breakdown.render())?,
+                }
+            }
+            if count {
+                match output {
+                    OutputFormat::Json | OutputFormat::JsonArray => out.write_line(&serde_json::to_string(&matches).map_err(|e| e.to_string())?, '\n')?,
+                    _ => out.write_str(&matches.render())?,
+                }
+            }
+            let finish = |sink: &mut Sink, name: &str| -> Result<(), Error> {
+                sink.finish()?;
+                for (file, lines) in sink.parts() {
+                    reporter.notice(Notice::Written { sink: name.to_string(), file, lines });
+                }
+                Ok(())
+            };
+            finish(&mut out, "output")?;
+            if let Some(mut sink) = dictionary {
+                finish(&mut sink, "dictionary")?;
+            }
+            for (sink, name) in [(unmatched, "unmatched"), (malformed, "malformed")] {
+                if let Some(sink) = sink {
+                    finish(&mut sink.borrow_mut(), name)?;
+                }
+            }
+            if let Some((condition, value)) = tally.failed(&conditions) {
+                let counter = condition.counter.name().to_string();
+                reporter.notice(Notice::Failed { condition: condition.to_string(), counter, value });
+                return Ok(ExitCode::from(fail_on::FAILED));
+            }
+            if matches.matched == 0 {
+                return Ok(ExitCode::from(fail_on::NO_MATCHES));
+            }
+        }
+        Commands::Stats {
+            metric,
+            by,
+            pivot,
+            max_groups,
+            examples,
+            seed,
+            approximate,
+            sketch_size,
+            output,
+            output_file,
+            order,
+            snapshot,
+            ..
+        } => {
+            let metric_name = metric.and_then(|metric| Some(metric.to_possible_value()?.get_name().to_string()));
+            let title = match by.is_empty() {
+                true => format!("Summary of {}", metric_name.unwrap_or_default()),
+                false => format!("Matches by {}", group::names(&by).join(", ")),
+            };
+            let mut summary = Summary::default();
+            let mut groups = (!by.is_empty()).then(|| {
+                let groups = Groups::new(by, cli.tz, max_groups).budgeted(budget.account("--by", group::OVER_BUDGET));
+                match (examples, approximate) {
+                    (Some(count), _) => groups.with_examples(count, seed.map_or_else(Rng::from_time, Rng::new)),
+                    (None, true) => groups.approximate(sketch_size as usize),
+                    (None, false) => groups,
+                }
+            });
+            let render = |groups: Option<Groups>, summary: Summary, output: SummaryOutput| {
+                let json = |value: serde_json::Value| format!("{}\n", value);
+                let table = match (groups, output) {
+                    (Some(groups), SummaryOutput::Json | SummaryOutput::JsonArray) => {
+                        return json(groups.into_json(metric.is_some()))
+                    }
+                    (None, SummaryOutput::Json | SummaryOutput::JsonArray) => return json(serde_json::json!(summary.into_json())),
+                    (Some(groups), _) if pivot => groups.pivot(order),
+                    (Some(groups), _) => groups.table(metric.is_some(), order),
+                    (None, _) => summary.table(),
+                };
+                match output {
+                    SummaryOutput::Html => {
+                        let mut page = Page::default();
+                        page.table(&table);
+                        page.render(&title)
+                    }
+                    _ => table.render(),
+                }
+            };
+            // A snapshot renders copies, leaving the groups to go on counting. It goes to stderr,
+            // as a table rather than a page.
+            let partial = |groups: &Option<Groups>, summary: &Summary, json: bool| {
+                let output = match (json, output) {
+                    (true, _) => SummaryOutput::Json,
+                    (false, SummaryOutput::Html) => SummaryOutput::Table,
+                    (false, output) => output,
+                };
+                Ok(render(groups.clone(), summary.clone(), output))
+            };
+            let mut snapshots = Snapshots::new(snapshot)?;
+            let mut lines = 0;
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                let run = scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| {
+                    let value = metric.and_then(|metric| metric.value(&found.entry));
+                    match &mut groups {
+                        Some(groups) => groups.add(&found.entry, &found.line, value)?,
+                        None => summary.add(value),
+                    }
+                    let read = lines + found.provenance.line_no;
+                    match &mut snapshots {
+                        Some(snapshots) if snapshots.due(read) => {
+                            snapshots.take(read, false, reporter, |json| partial(&groups, &summary, json)).map_err(|e| e.to_string())
+                        }
+                        _ => Ok(()),
+                    }
+                })?;
+                lines += run.lines;
+            }
+            if let Some(snapshots) = snapshots.as_mut().filter(|_| interrupt::pressed()) {
+                snapshots.take(lines, true, reporter, |json| partial(&groups, &summary, json))?;
+            }
+            emit(output_file, output == SummaryOutput::Json, render(groups, summary, output))?;
+        }
+        Commands::Histogram { bucket, split_status, only_ratio, max_buckets, output, output_file, snapshot, .. } => {
+            let mut histogram = Histogram::new(bucket.0, cli.tz)?;
+            let partial = |histogram: &Histogram, json: bool| {
+                let output = match (json, output) {
+                    (true, _) => HistogramOutput::Json,
+                    (false, HistogramOutput::Html) => HistogramOutput::Table,
+                    (false, output) => output,
+                };
+                histogram.render(split_status, only_ratio, output, max_buckets)
+            };
+            let mut snapshots = Snapshots::new(snapshot)?;
+            let mut lines = 0;
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                let run = scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| {
+                    histogram.add(&found.entry);
+                    let read = lines + found.provenance.line_no;
+                    match &mut snapshots {
+                        Some(snapshots) if snapshots.due(read) => {
+                            snapshots.take(read, false, reporter, |json| partial(&histogram, json)).map_err(|e| e.to_string())
+                        }
+                        _ => Ok(()),
+                    }
+                })?;
+                lines += run.lines;
+            }
+            if let Some(snapshots) = snapshots.as_mut().filter(|_| interrupt::pressed()) {
+                snapshots.take(lines, true, reporter, |json| partial(&histogram, json))?;
+            }
+            if histogram.untimed() > 0 {
+                reporter.warning(Warning::Untimed { count: histogram.untimed() });
+            }
+            let rendered = histogram.render(split_status, only_ratio, output, max_buckets)?;
+            emit(output_file, output == HistogramOutput::Json, rendered)?;
+        }
+        Commands::Top {
+            key,
+            limit,
+            relative_file,
+            baseline_since,
+            baseline_until,
+            min_baseline,
+            max_groups,
+            examples,
+            seed,
+            approximate,
+            sketch_size,
+            output,
+            output_file,
+            order,
+            snapshot,
+            ..
+        } => {
+            let window = baseline_since.zip(baseline_until).map(|(since, until)| Window { since, until });
+            let sketch = approximate.then_some(sketch_size as usize);
+            let mut top =
+                Top::new(key, cli.tz, max_groups, sketch, relative_file.is_some(), window)?.budgeted(budget.account("top", group::OVER_BUDGET));
+            if let Some(count) = examples {
+                top = top.with_examples(count, seed.map_or_else(Rng::from_time, Rng::new));
+            }
+            // The baseline file is filtered as the input is, so both shares are of the same kind
+            // of match.
+            if let Some(path) = relative_file {
+                let baseline = Source::File(path);
+                scan(&baseline, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| top.add_baseline(&found.entry))?;
+            }
+            // A snapshot ranks what's been counted so far, as a table rather than a page. Until a
+            // baseline window has matches there's nothing to compare with, and so nothing to show.
+            let partial = |top: &Top, json: bool| {
+                let output = match (json, output) {
+                    (true, _) => TopOutput::Json,
+                    (false, TopOutput::Html) => TopOutput::Table,
+                    (false, output) => output,
+                };
+                Ok(top.rank(limit, min_baseline, order).map(|ranked| top.render(ranked, output)).unwrap_or_default())
+            };
+            let mut snapshots = Snapshots::new(snapshot)?;
+            let mut lines = 0;
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                let run = scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| {
+                    top.add(&found.entry, &found.line)?;
+                    let read = lines + found.provenance.line_no;
+                    match &mut snapshots {
+                        Some(snapshots) if snapshots.due(read) => {
+                            snapshots.take(read, false, reporter, |json| partial(&top, json)).map_err(|e| e.to_string())
+                        }
+                        _ => Ok(()),
+                    }
+                })?;
+                lines += run.lines;
+            }
+            if let Some(snapshots) = snapshots.as_mut().filter(|_| interrupt::pressed()) {
+                snapshots.take(lines, true, reporter, |json| partial(&top, json))?;
+            }
+            let ranked = top.rank(limit, min_baseline, order)?;
+            if let Some(bound) = top.uncertain(&ranked) {
+                let message = format!(
+                    "--approximate may have lost values with up to {} matches, as many as some printed; raise --sketch-size \
+                     for a certain ranking",
+                    bound
+                );
+                reporter.warning(Warning::Uncertain { bound, message });
+            }
+            emit(output_file, output == TopOutput::Json, top.render(ranked, output))?;
+        }
+        Commands::Novel {
+            key,
+            baseline_since,
+            baseline_until,
+            target_since,
+            target_until,
+            ordered,
+            output,
+            count,
+            ..
+        } => {
+            let baseline = Window { since: baseline_since, until: baseline_until };
+            let target = Window { since: target_since, until: target_until };
+            let mut novel = Novel::new(key, baseline, target)?;
+            // The first of two passes only learns the baseline, without the summary the second
+            // pass reports.
+            if !ordered {
+                for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                    let mut scanner = Scanner::open(source, new_parser(source)?, &options, &filter, reporter)?;
+                    while let Some(found) = scanner.next_match(&mut NoTiming)? {
+                        novel.learn(&found.entry);
+                    }
+                }
+            }
+            let mut matches = Count::default();
+            let mut in_target = false;
+            let mut out = Sink::stdout().framed(output);
+            announce(matches!(output, OutputFormat::Json));
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| {
+                    let place = if ordered { novel.add(&found.entry) } else { novel.check(&found.entry) };
+                    match place {
+                        Place::Baseline if ordered && in_target => {
+                            return Err(format!(
+                                "the entry at {} is in the baseline window but follows target entries, so the input \
+                                 isn't in time order; leave out --ordered to read it twice",
+                                found.entry.timestamp.map(|t| t.to_rfc3339()).unwrap_or_default()
+                            ))
+                        }
+                        Place::Baseline | Place::Outside => {}
+                        Place::Seen => in_target = true,
+                        Place::Novel => {
+                            in_target = true;
+                            matches.matched += 1;
+                            if !count {
+                                out.write_line(&render_match(&found, output)?, '\n').map_err(|e| e.to_string())?;
+                            }
+                        }
+                    }
+                    Ok(())
+                })?;
+            }
+            if count {
+                match output {
+                    OutputFormat::Json | OutputFormat::JsonArray => {
+                        out.write_line(&serde_json::to_string(&matches).map_err(|e| e.to_string())?, '\n')?
+                    }
+                    _ => out.write_str(&matches.render())?,
+                }
+            }
+            out.finish()?;
+        }
+        Commands::Cardinality { of, by, precision, exact, max_groups, output, order, .. } => {
+            let mut cardinality = Cardinality::new(of, by, cli.tz, (!exact).then_some(precision), max_groups);
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| cardinality.add(&found.entry))?;
+            }
+            announce(matches!(output, StatsOutput::Json));
+            print!("{}", cardinality.render(output, order));
+        }
+        Commands::Anomalies {
+            metric,
+            by,
+            baseline_since,
+            baseline_until,
+            target_since,
+            target_until,
+            sigma,
+            emit_entries,
+            max_groups,
+            output,
+            order,
+            ..
+        } => {
+            let baseline = Window { since: baseline_since, until: baseline_until };
+            let target = target_since.zip(target_until).map(|(since, until)| Window { since, until });
+            let mut anomalies = Anomalies::new(by.clone(), cli.tz, metric, baseline, target, sigma, max_groups)?;
+            announce(matches!(output, StatsOutput::Json));
+            if !emit_entries {
+                for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                    scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| anomalies.add(&found.entry))?;
+                }
+                print!("{}", anomalies::render(&by, &anomalies.finish(order), output));
+                return Ok(ExitCode::SUCCESS);
+            }
+            // As with novel, the first pass only learns the baseline.
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                let mut scanner = Scanner::open(source, new_parser(source)?, &options, &filter, reporter)?;
+                while let Some(found) = scanner.next_match(&mut NoTiming)? {
+                    anomalies.learn(&found.entry)?;
+                }
+            }
+            let output = match output {
+                StatsOutput::Table => OutputFormat::Line,
+                StatsOutput::Json => OutputFormat::Json,
+                StatsOutput::JsonArray => OutputFormat::JsonArray,
+            };
+            let mut out = Sink::stdout().framed(output);
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| {
+                    if anomalies.deviant(&found.entry).is_some() {
+                        out.write_line(&render_match(&found, output)?, '\n').map_err(|e| e.to_string())?;
+                    }
+                    Ok(())
+                })?;
+            }
+            out.finish()?;
+        }
+        Commands::Scanners { min_not_found, min_distinct_paths, window, output, .. } => {
+            let window = TimeDelta::from_std(window.0).map_err(|e| format!("--window: {}", e))?;
+            let mut detector =
+                Detector::new(Thresholds { not_found: min_not_found, distinct_paths: min_distinct_paths, window });
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| {
+                    detector.add(&found.entry);
+                    Ok(())
+                })?;
+            }
+            let findings = detector.finish();
+            announce(matches!(output, ScannerOutput::Json));
+            print!("{}", scanners::render(&findings, output)?);
+            if !findings.is_empty() {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Auth { window, max_failures, output, order, .. } => {
+            let window = TimeDelta::from_std(window.0).map_err(|e| format!("--window: {}", e))?;
+            let mut audit = Audit::new(window);
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| {
+                    audit.add(&found.entry);
+                    Ok(())
+                })?;
+            }
+            let report = audit.finish(max_failures, order);
+            announce(matches!(output, AuthOutput::Json));
+            print!("{}", auth::render(&report, output)?);
+            if report.flagged() > 0 {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Check { rules, output, .. } => {
+            let mut checks = Checks::new(rules::load(&rules).map_err(Errors)?, cli.tz, budget.account("check", "group by fewer keys or narrow the filter, or raise --max-memory"));
+            let mut checked_fields = false;
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| {
+                    if !checked_fields {
+                        checks.check_fields(&found.entry)?;
+                        checked_fields = true;
+                    }
+                    checks.add(&found.entry)
+                })?;
+            }
+            let outcomes = checks.finish();
+            announce(matches!(output, CheckOutput::Json));
+            print!("{}", rules::render(&outcomes, output)?);
+            if outcomes.iter().any(|outcome| !outcome.passed) {
+                return Ok(ExitCode::from(fail_on::FAILED));
+            }
+        }
+        Commands::Report { output, output_file, .. } => {
+            let mut report = Report::new(cli.tz, budget.account("report", "narrow the filter, or raise --max-memory"));
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| report.add(&found.entry))?;
+            }
+            emit(output_file, matches!(output, ReportOutput::Json), report::render(&report.finish(), output)?)?;
+        }
+        Commands::Fields { output, .. } => {
+            let fields = match sources.first() {
+                Some(source) => fields::sample(source, &mut new_parser(source)?)?,
+                None if cli.log_type == LogKind::Auto && cli.format_string.is_none() => {
+                    return Err("--log-type auto detects the log type from a file; pass one to read it from".to_string().into())
+                }
+                None => build(cli.log_type)?
+                    .fields()
+                    .ok_or_else(|| {
+                        "W3C logs name their fields in a #Fields: header and CSV in its first row; pass a file to read it from"
+                            .to_string()
+                    })?,
+            };
+            announce(matches!(output, StatsOutput::Json));
+            print!("{}", fields::render(&fields, output));
+        }
+        Commands::Convert { to, .. } => {
+            let mut out = Sink::stdout();
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| {
+                    out.write_line(&to.format(&found.entry), '\n').map_err(|e| e.to_string())
+                })?;
+            }
+            out.finish()?;
+        }
+        Commands::Batch { output, .. } => {
+            let mut batch = Batch::open(jobs, cli.schema_version)?;
+            let mut lines = 0;
+            for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                lines += scan(source, &new_parser, &options, &filter, reporter, &mut NoTiming, |found| batch.add(&found))?.lines;
+            }
+            let jobs = batch.finish(lines)?;
+            announce(matches!(output, StatsOutput::Json));
+            print!("{}", batch::render(&jobs, output));
+        }
+        Commands::Bench { iterations, output, .. } => {
+            // Stage times are measured on one thread.
+            let options = ScanOptions { jobs: 1, ..options };
+            let mut results = Vec::new();
+            for iteration in (1..=iterations).take_while(|_| !interrupt::requested()) {
+                let mut times = StageTimes::default();
+                let (mut lines, mut bytes, mut matched) = (0, 0, 0);
+                let start = Instant::now();
+                for source in sources.iter().take_while(|_| !interrupt::requested()) {
+                    let summary = scan(source, &new_parser, &options, &filter, reporter, &mut times, |_| Ok(()))?;
+                    lines += summary.lines;
+                    bytes += summary.bytes;
+                    matched += summary.matched;
+                }
+                let wall = start.elapsed();
+                results.push(Iteration::new(iteration, lines, bytes, matched, wall, times));
+            }
+            announce(matches!(output, BenchOutput::Json));
+            print!("{}", bench::render(&results, output)?);
         }
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,73 @@
+use std::{fs::File, io};
+
+// Large plain files are read through a read-only memory map instead of read calls, so lines are
+// slices of the map rather than copies and repeated runs over the same file come from the page
+// cache. Only builds with the `mmap` feature, which is off by default, map files; elsewhere `Map`
+// has no values and every file is streamed. Truncating a file while it's mapped, as logrotate's
+// `copytruncate` does, makes reading past the new end fail with SIGBUS, so each range checks
+// `shrunk` before it's read; --follow-glob streams, and is the way to read a live log.
+
+#[cfg(feature = "mmap")]
+pub use sys::Map;
+
+#[cfg(not(feature = "mmap"))]
+pub use stub::Map;
+
+#[cfg(feature = "mmap")]
+mod sys {
+    use super::*;
+    use memmap2::Mmap;
+
+    /// A whole file mapped read-only, unmapped when dropped, with the file kept open to check
+    /// its length against.
+    pub struct Map {
+        file: File,
+        map: Mmap,
+    }
+
+    impl Map {
+        /// Maps `file`, or `None` when it's empty, which can't be mapped.
+        pub fn open(file: File) -> io::Result<Option<Map>> {
+            if file.metadata()?.len() == 0 {
+                return Ok(None);
+            }
+            // SAFETY: the map is read-only. Another process truncating the file can still make
+            // reads past its new end fault, which callers guard against with `shrunk`.
+            let map = unsafe { Mmap::map(&file)? };
+            #[cfg(unix)]
+            map.advise(memmap2::Advice::Sequential)?;
+            Ok(Some(Map { file, map }))
+        }
+
+        pub fn bytes(&self) -> &[u8] {
+            &self.map
+        }
+
+        /// Whether the file is now shorter than when it was mapped.
+        pub fn shrunk(&self) -> io::Result<bool> {
+            Ok(self.file.metadata()?.len() < self.map.len() as u64)
+        }
+    }
+}
+
+#[cfg(not(feature = "mmap"))]
+mod stub {
+    use super::*;
+
+    /// Never made: files are always streamed without the `mmap` feature.
+    pub enum Map {}
+
+    impl Map {
+        pub fn open(_file: File) -> io::Result<Option<Map>> {
+            Ok(None)
+        }
+
+        pub fn bytes(&self) -> &[u8] {
+            match *self {}
+        }
+
+        pub fn shrunk(&self) -> io::Result<bool> {
+            match *self {}
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::entry::Entry;
+use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
+use std::{
+    collections::HashSet,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+// `novel` compares two windows of log time, e.g. the hour before a deploy and the hour after,
+// and passes on the entries of the later window whose key never occurred in the earlier one.
+// The baseline only keeps a 64-bit hash of each key value, so its memory doesn't depend on how
+// long paths or user agents are; a collision, vanishingly rare, hides one novel value.
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum NovelKey {
+    Path,
+    Ip,
+    #[value(name = "ip+path")]
+    IpPath,
+    UserAgent,
+}
+
+impl NovelKey {
+    fn hash(self, entry: &Entry) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match self {
+            NovelKey::Path => entry.match_path().hash(&mut hasher),
+            NovelKey::Ip => entry.client.hash(&mut hasher),
+            NovelKey::IpPath => (&entry.client, entry.match_path()).hash(&mut hasher),
+            NovelKey::UserAgent => entry.user_agent.hash(&mut hasher),
+        }
+        hasher.finish()
+    }
+}
+
+/// Log time from `since` up to, but not including, `until`.
+#[derive(Clone, Copy, Debug)]
+pub struct Window {
+    pub since: DateTime<FixedOffset>,
+    pub until: DateTime<FixedOffset>,
+}
+
+impl Window {
+    pub fn contains(&self, entry: &Entry) -> bool {
+        entry.timestamp.is_some_and(|t| self.since <= t && t < self.until)
+    }
+}
+
+/// Where an entry falls for `Novel`.
+#[derive(Debug, PartialEq)]
+pub enum Place {
+    /// In the baseline window, and now remembered.
+    Baseline,
+    /// In the target window with a key the baseline has.
+    Seen,
+    /// In the target window with a key the baseline lacks.
+    Novel,
+    /// In neither window, or without a timestamp.
+    Outside,
+}
+
+pub struct Novel {
+    key: NovelKey,
+    baseline: Window,
+    target: Window,
+    seen: HashSet<u64>,
+}
+
+impl Novel {
+    /// Fails when a window ends before it starts, or when the windows overlap and an entry
+    /// could belong to both.
+    pub fn new(key: NovelKey, baseline: Window, target: Window) -> Result<Self, String> {
+        for (window, name) in [(baseline, "baseline"), (target, "target")] {
+            if window.until <= window.since {
+                return Err(format!("--{}-until must be later than --{}-since", name, name));
+            }
+        }
+        if baseline.since < target.until && target.since < baseline.until {
+            return Err("the baseline and target windows overlap".to_string());
+        }
+        Ok(Novel { key, baseline, target, seen: HashSet::new() })
+    }
+
+    /// Remembers the key of a baseline entry, ignoring everything else; the first of two passes.
+    pub fn learn(&mut self, entry: &Entry) {
+        if self.baseline.contains(entry) {
+            self.seen.insert(self.key.hash(entry));
+        }
+    }
+
+    /// Judges a target entry against the baseline learned so far, without learning anything.
+    pub fn check(&self, entry: &Entry) -> Place {
+        match self.target.contains(entry) {
+            true if self.seen.contains(&self.key.hash(entry)) => Place::Seen,
+            true => Place::Novel,
+            false if self.baseline.contains(entry) => Place::Baseline,
+            false => Place::Outside,
+        }
+    }
+
+    /// `learn` and `check` at once, for a single pass over time-ordered input.
+    pub fn add(&mut self, entry: &Entry) -> Place {
+        self.learn(entry);
+        self.check(entry)
+    }
+
+    /// Whether every baseline entry precedes every target entry in log time, which a single
+    /// pass over ordered input needs.
+    pub fn baseline_first(&self) -> bool {
+        self.baseline.until <= self.target.since
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(time).unwrap()
+    }
+
+    fn request(time: &str, ip: &str, path: &str) -> Entry {
+        Entry {
+            timestamp: Some(at(time)),
+            client: ip.parse().ok(),
+            path: Some(path.to_string()),
+            ..Entry::default()
+        }
+    }
+
+    fn novel(key: NovelKey) -> Novel {
+        let baseline = Window { since: at("2023-02-12T13:00:00Z"), until: at("2023-02-12T14:00:00Z") };
+        let target = Window { since: at("2023-02-12T14:00:00Z"), until: at("2023-02-12T15:00:00Z") };
+        Novel::new(key, baseline, target).unwrap()
+    }
+
+    #[test]
+    fn target_entries_with_new_keys_are_novel() {
+        let mut by_path = novel(NovelKey::Path);
+        assert_eq!(by_path.add(&request("2023-02-12T13:30:00Z", "10.0.0.1", "/a")), Place::Baseline);
+        assert_eq!(by_path.add(&request("2023-02-12T12:59:59Z", "10.0.0.1", "/b")), Place::Outside);
+        assert_eq!(by_path.add(&request("2023-02-12T14:00:00Z", "10.0.0.2", "/a")), Place::Seen);
+        assert_eq!(by_path.add(&request("2023-02-12T14:10:00Z", "10.0.0.1", "/b")), Place::Novel);
+        assert_eq!(by_path.add(&request("2023-02-12T15:00:00Z", "10.0.0.1", "/c")), Place::Outside);
+        assert_eq!(by_path.add(&Entry::default()), Place::Outside);
+
+        let mut by_pair = novel(NovelKey::IpPath);
+        by_pair.learn(&request("2023-02-12T13:30:00Z", "10.0.0.1", "/a"));
+        assert_eq!(by_pair.check(&request("2023-02-12T14:10:00Z", "10.0.0.1", "/a")), Place::Seen);
+        assert_eq!(by_pair.check(&request("2023-02-12T14:10:00Z", "10.0.0.2", "/a")), Place::Novel);
+    }
+
+    #[test]
+    fn windows_must_be_ordered_and_apart() {
+        let window = |since: &str, until: &str| Window { since: at(since), until: at(until) };
+        let hour = window("2023-02-12T13:00:00Z", "2023-02-12T14:00:00Z");
+        let backwards = window("2023-02-12T15:00:00Z", "2023-02-12T14:00:00Z");
+        let overlapping = window("2023-02-12T13:30:00Z", "2023-02-12T14:30:00Z");
+        let before = window("2023-02-12T12:00:00Z", "2023-02-12T13:00:00Z");
+        assert_eq!(
+            Novel::new(NovelKey::Ip, hour, backwards).err().as_deref(),
+            Some("--target-until must be later than --target-since")
+        );
+        assert_eq!(Novel::new(NovelKey::Ip, hour, overlapping).err().as_deref(), Some("the baseline and target windows overlap"));
+        assert!(Novel::new(NovelKey::Ip, hour, before).is_ok_and(|novel| !novel.baseline_first()));
+    }
+}
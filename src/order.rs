@@ -0,0 +1,69 @@
+use clap::{Args, ValueEnum};
+use std::cmp::Ordering;
+
+// The order aggregations print their rows in, the same for every command so the output of the
+// same input is the same byte for byte from run to run, and yesterday's can be diffed against
+// today's: by the figure the command ranks by, largest first, with ties by the key ascending.
+// Keys compare byte-wise, keys of several values by the first value and then the next, and
+// client addresses numerically. `--sort key` orders by the key alone and `--reverse` turns
+// either order around. The figure is a count of matches for `stats --by` and `top`, with a
+// baseline `top`'s change in share, the distinct count for `cardinality`, failures for `auth`
+// and the deviation for `anomalies`. What is keyed rather than listed stays in key order: JSON
+// objects nested by key, such as `stats --by -o json`, and buckets of log time.
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortBy {
+    /// By the figure the rows are ranked by, largest first, ties by key
+    #[default]
+    Count,
+    /// By key, ascending
+    Key,
+}
+
+#[derive(Args, Clone, Copy, Debug, Default)]
+pub struct Order {
+    /// Order the rows by `count`, largest first with ties by key, or by `key`
+    #[arg(long, value_enum, default_value_t)]
+    pub sort: SortBy,
+    /// Print the rows in the opposite order
+    #[arg(long)]
+    pub reverse: bool,
+}
+
+impl Order {
+    /// Compares two rows by their figure and key.
+    pub fn compare<C: PartialOrd, K: Ord + ?Sized>(self, a: (C, &K), b: (C, &K)) -> Ordering {
+        let order = match self.sort {
+            SortBy::Count => b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal).then_with(|| a.1.cmp(b.1)),
+            SortBy::Key => a.1.cmp(b.1),
+        };
+        match self.reverse {
+            true => order.reverse(),
+            false => order,
+        }
+    }
+
+    /// Sorts `rows` by the figure and key `rank` gives for each.
+    pub fn sort<T, C: PartialOrd, K: Ord + ?Sized>(self, rows: &mut [T], rank: impl Fn(&T) -> (C, &K)) {
+        rows.sort_by(|a, b| self.compare(rank(a), rank(b)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(order: Order) -> Vec<&'static str> {
+        let mut rows = vec![("/b", 2), ("/a", 1), ("/c", 2), ("/B", 2), ("/ä", 1)];
+        order.sort(&mut rows, |(key, count)| (*count, *key));
+        rows.into_iter().map(|(key, _)| key).collect()
+    }
+
+    #[test]
+    fn rows_rank_largest_first_with_ties_by_key() {
+        assert_eq!(sorted(Order::default()), ["/B", "/b", "/c", "/a", "/ä"]);
+        assert_eq!(sorted(Order { sort: SortBy::Key, reverse: false }), ["/B", "/a", "/b", "/c", "/ä"]);
+        assert_eq!(sorted(Order { sort: SortBy::Count, reverse: true }), ["/ä", "/a", "/c", "/b", "/B"]);
+        assert_eq!(sorted(Order { sort: SortBy::Key, reverse: true }), ["/ä", "/c", "/b", "/a", "/B"]);
+    }
+}
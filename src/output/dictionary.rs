@@ -0,0 +1,158 @@
+use crate::budget::Account;
+use serde::{
+    de::{MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_json::Value;
+use std::{collections::HashMap, fmt};
+
+// `filter --dict-fields user_agent,referrer` writes each distinct value of those fields once:
+// the matches carry a small integer ID in its place, the index of the value in the dictionary
+// written after the last match, as a `{"dictionary":{"user_agent":[...],...}}` record or, with
+// --dict-file, to a file of its own. With --fields the dictionary always goes to --dict-file, a
+// `field id value` row per value under a header. Only string values are replaced; a field an
+// entry lacks stays null. The IDs count from 0 per field in the order values first turn up, so
+// the same input gives the same IDs. To get the original records back from `-o json`:
+//
+//     jq -c -s 'last.dictionary as $d | .[:-1][]
+//         | reduce ($d | keys[]) as $f (.; .[$f] |= if type == "number" then $d[$f][.] else . end)'
+
+/// The values of one field, by ID.
+struct Values {
+    field: String,
+    by_id: Vec<String>,
+    ids: HashMap<String, u64>,
+}
+
+/// The values of the --dict-fields written so far.
+pub struct Dictionary {
+    fields: Vec<Values>,
+    memory: Account,
+}
+
+impl Dictionary {
+    pub fn new(fields: Vec<String>, memory: Account) -> Self {
+        let fields = fields.into_iter().map(|field| Values { field, by_id: Vec::new(), ids: HashMap::new() }).collect();
+        Dictionary { fields, memory }
+    }
+
+    /// Replaces `value` with its ID when it's a string of one of the fields.
+    pub fn encode(&mut self, field: &str, value: &mut Value) -> Result<(), String> {
+        let (Some(values), Value::String(text)) = (self.fields.iter_mut().find(|values| values.field == field), &*value) else {
+            return Ok(());
+        };
+        let id = match values.ids.get(text) {
+            Some(&id) => id,
+            None => {
+                self.memory.charge(2 * (text.len() + std::mem::size_of::<String>()) + std::mem::size_of::<u64>())?;
+                let id = values.by_id.len() as u64;
+                values.by_id.push(text.clone());
+                values.ids.insert(text.clone(), id);
+                id
+            }
+        };
+        *value = Value::from(id);
+        Ok(())
+    }
+
+    /// Replaces the fields' values in a JSON record, keeping its fields in order.
+    pub fn encode_json(&mut self, record: &str) -> Result<String, String> {
+        let mut ordered: Ordered = serde_json::from_str(record).map_err(|e| e.to_string())?;
+        for (field, value) in &mut ordered.0 {
+            self.encode(field, value)?;
+        }
+        serde_json::to_string(&ordered).map_err(|e| e.to_string())
+    }
+
+    /// The dictionary as a JSON record: each field's values, the index of each its ID.
+    pub fn to_json(&self) -> String {
+        let dictionary = self.fields.iter().map(|values| (values.field.clone(), Value::from(values.by_id.clone()))).collect();
+        serde_json::json!({ "dictionary": Value::Object(dictionary) }).to_string()
+    }
+
+    /// The dictionary as rows: a `field id value` header, then a row per value.
+    pub fn rows(&self) -> impl Iterator<Item = [String; 3]> + '_ {
+        let header = ["field", "id", "value"].map(str::to_string);
+        let values = self.fields.iter().flat_map(|values| {
+            values.by_id.iter().enumerate().map(|(id, value)| [values.field.clone(), id.to_string(), value.clone()])
+        });
+        std::iter::once(header).chain(values)
+    }
+}
+
+/// A JSON object with its fields in the order they were written, which `Map` doesn't keep.
+pub(super) struct Ordered(pub(super) Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for Ordered {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Fields;
+
+        impl<'de> Visitor<'de> for Fields {
+            type Value = Ordered;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Ordered, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(Ordered(fields))
+            }
+        }
+
+        deserializer.deserialize_map(Fields)
+    }
+}
+
+impl Serialize for Ordered {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (field, value) in &self.0 {
+            map.serialize_entry(field, value)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::MemoryBudget;
+
+    fn dictionary(fields: &[&str]) -> Dictionary {
+        let memory = MemoryBudget::new(1 << 20).account("--dict-fields", "");
+        Dictionary::new(fields.iter().map(|field| field.to_string()).collect(), memory)
+    }
+
+    #[test]
+    fn values_are_numbered_per_field_in_order_of_appearance() {
+        let mut dictionary = dictionary(&["user_agent", "referrer"]);
+        let records = [
+            r#"{"ip":"192.0.2.1","referrer":"-x","user_agent":"curl/8.0","bytes":5}"#,
+            r#"{"ip":"192.0.2.2","referrer":null,"user_agent":"Mozilla/5.0","bytes":6}"#,
+            r#"{"ip":"192.0.2.3","referrer":"curl/8.0","user_agent":"curl/8.0","bytes":7}"#,
+        ];
+        let encoded: Vec<String> = records.iter().map(|record| dictionary.encode_json(record).unwrap()).collect();
+        assert_eq!(
+            encoded,
+            [
+                r#"{"ip":"192.0.2.1","referrer":0,"user_agent":0,"bytes":5}"#,
+                r#"{"ip":"192.0.2.2","referrer":null,"user_agent":1,"bytes":6}"#,
+                r#"{"ip":"192.0.2.3","referrer":1,"user_agent":0,"bytes":7}"#,
+            ]
+        );
+        assert_eq!(
+            dictionary.to_json(),
+            r#"{"dictionary":{"referrer":["-x","curl/8.0"],"user_agent":["curl/8.0","Mozilla/5.0"]}}"#
+        );
+        let rows: Vec<String> = dictionary.rows().map(|row| row.join(",")).collect();
+        assert_eq!(
+            rows,
+            ["field,id,value", "user_agent,0,curl/8.0", "user_agent,1,Mozilla/5.0", "referrer,0,-x", "referrer,1,curl/8.0"]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use clap::ValueEnum;
+
+// Escaping for values substituted into --format templates, so a user agent of `$(rm -rf /)` or
+// `<script>` stays inert in whatever the output is pasted into.
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Escape {
+    /// The value as it is
+    #[default]
+    Raw,
+    /// A POSIX shell word in single quotes
+    Sh,
+    /// Text with HTML's special characters as entities, safe in elements and quoted attributes
+    Html,
+    /// A JSON value: strings quoted and escaped, numbers as they are, a missing value as null
+    Json,
+}
+
+impl Escape {
+    /// Parses the modifier of a `{field:modifier}` placeholder.
+    pub fn modifier(name: &str) -> Result<Escape, String> {
+        <Escape as ValueEnum>::from_str(name, false)
+            .map_err(|_| format!("unknown escaping :{}; expected :raw, :sh, :html or :json", name))
+    }
+
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Escape::Raw => text.to_string(),
+            Escape::Sh => sh(text),
+            Escape::Html => html(text),
+            Escape::Json => json(text),
+        }
+    }
+}
+
+/// Wraps `text` in single quotes, inside which the shell expands nothing; each quote in it ends
+/// the quoting, adds an escaped quote and starts it again.
+pub fn sh(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Replaces `&`, `<`, `>`, `"` and `'` with entities.
+pub fn html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// `text` as a quoted JSON string.
+pub fn json(text: &str) -> String {
+    serde_json::Value::from(text).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sh_quotes_everything_the_shell_would_expand() {
+        assert_eq!(sh("curl/8.0"), "'curl/8.0'");
+        assert_eq!(sh(""), "''");
+        assert_eq!(sh("$(rm -rf /)"), "'$(rm -rf /)'");
+        assert_eq!(sh("`id` $HOME \\ \"x\" *"), "'`id` $HOME \\ \"x\" *'");
+        assert_eq!(sh("it's"), "'it'\\''s'");
+        assert_eq!(sh("''"), "''\\'''\\'''");
+        assert_eq!(sh("a\nb"), "'a\nb'");
+    }
+
+    #[test]
+    fn sh_quoting_survives_a_real_shell() {
+        for text in ["$(echo pwned)", "it's \"quoted\"", "; ls *", "back\\slash `x`", "'"] {
+            let output = std::process::Command::new("sh").arg("-c").arg(format!("printf %s {}", sh(text))).output().unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), text);
+        }
+    }
+
+    #[test]
+    fn html_escapes_markup_and_quotes() {
+        assert_eq!(html("<script>alert('x')</script>"), "&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;");
+        assert_eq!(html("a & b \"c\""), "a &amp; b &quot;c&quot;");
+        assert_eq!(html("&amp;"), "&amp;amp;");
+        assert_eq!(html("/index.html?q=é"), "/index.html?q=é");
+    }
+
+    #[test]
+    fn json_makes_a_string_literal() {
+        assert_eq!(json("Mozilla/5.0"), "\"Mozilla/5.0\"");
+        assert_eq!(json("say \"hi\"\\"), "\"say \\\"hi\\\"\\\\\"");
+        assert_eq!(json("a\nb\t\u{1}"), "\"a\\nb\\t\\u0001\"");
+        assert_eq!(json("</script>"), "\"</script>\"");
+    }
+
+    #[test]
+    fn modifiers_are_checked() {
+        assert_eq!(Escape::modifier("sh"), Ok(Escape::Sh));
+        assert_eq!(Escape::modifier("raw"), Ok(Escape::Raw));
+        assert!(Escape::modifier("SH").is_err());
+        assert!(Escape::modifier("url").unwrap_err().contains(":url"));
+        assert_eq!(Escape::Raw.apply("<$x>"), "<$x>");
+    }
+}
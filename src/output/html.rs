@@ -0,0 +1,153 @@
+use super::{
+    escape,
+    table::{shorten, Table},
+};
+use chrono::Utc;
+
+// `-o html` of stats, histogram, top and report: one page to hand to someone who won't run the
+// tool, with no script and nothing fetched from elsewhere, so it opens the same from a mail
+// attachment as from a web server. Its tables are the ones the text output prints, a ranking is
+// drawn as horizontal bars above its table and counts over time as columns, both inline SVG.
+// Every value is HTML-escaped, since paths and user agents are whatever clients sent, `<script>`
+// and all. The footer says when the page was made and by what command, to run again later.
+
+const STYLE: &str = "\
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; }
+h2 { font-size: 1.1em; margin-top: 2em; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { padding: 0.2em 0.8em; border-bottom: 1px solid #ddd; text-align: left; }
+th { background: #f3f3f3; }
+.number { text-align: right; font-variant-numeric: tabular-nums; }
+tr.note td { font-family: monospace; color: #555; }
+svg rect { fill: #4a78b0; }
+svg text { font: 12px system-ui, sans-serif; fill: #222; }
+svg.columns { display: block; width: 100%; height: 160px; }
+footer { margin-top: 2em; color: #777; font-size: 0.9em; }
+";
+
+/// Height of a bar and the space around it.
+const BAR_HEIGHT: usize = 20;
+
+/// Width of the labels left of the bars, and the most characters of a label shown in it.
+const LABEL_WIDTH: usize = 240;
+const LABEL_CHARACTERS: usize = 36;
+
+/// Width of the longest bar.
+const BAR_WIDTH: usize = 360;
+
+/// A page's sections, added in order.
+#[derive(Default)]
+pub struct Page {
+    body: String,
+}
+
+impl Page {
+    pub fn heading(&mut self, text: &str) {
+        self.body += &format!("<h2>{}</h2>\n", escape::html(text));
+    }
+
+    pub fn table(&mut self, table: &Table) {
+        self.body += &table.html();
+    }
+
+    /// A bar per label, longest for the largest count, with the count after it; for a ranking.
+    pub fn bars(&mut self, bars: &[(String, u64)]) {
+        let Some(max) = bars.iter().map(|&(_, count)| count).max().filter(|&max| max > 0) else {
+            return;
+        };
+        let (width, height) = (LABEL_WIDTH + BAR_WIDTH + 80, bars.len() * BAR_HEIGHT);
+        let mut svg =
+            format!("<svg class=\"bars\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" role=\"img\">\n", width, height);
+        for (index, (label, count)) in bars.iter().enumerate() {
+            let (top, length) = (index * BAR_HEIGHT, (*count as f64 * BAR_WIDTH as f64 / max as f64).round() as usize);
+            svg += &format!(
+                "<g><title>{}: {}</title><text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\
+                 <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/><text x=\"{}\" y=\"{}\">{}</text></g>\n",
+                escape::html(label),
+                count,
+                LABEL_WIDTH - 8,
+                top + 14,
+                escape::html(&shorten(label, LABEL_CHARACTERS)),
+                LABEL_WIDTH,
+                top + 3,
+                length,
+                BAR_HEIGHT - 6,
+                LABEL_WIDTH + length + 6,
+                top + 14,
+                count
+            );
+        }
+        self.body += &(svg + "</svg>\n");
+    }
+
+    /// A column per label, left to right, as tall as its value is of `full`; for counts over
+    /// time. The chart stretches to the page's width, so each column names its label and value
+    /// only on hover, and the table under it has them all.
+    pub fn columns(&mut self, columns: &[(String, f64)], full: f64) {
+        if columns.is_empty() || full <= 0.0 {
+            return;
+        }
+        let mut svg = format!(
+            "<svg class=\"columns\" viewBox=\"0 0 {} 100\" preserveAspectRatio=\"none\" role=\"img\">\n",
+            columns.len() * 10
+        );
+        for (index, (label, value)) in columns.iter().enumerate() {
+            let height = (value / full * 100.0).clamp(0.0, 100.0);
+            svg += &format!(
+                "<rect x=\"{}\" y=\"{:.2}\" width=\"8\" height=\"{:.2}\"><title>{}: {}</title></rect>\n",
+                index * 10 + 1,
+                100.0 - height,
+                height,
+                escape::html(label),
+                value
+            );
+        }
+        self.body += &(svg + "</svg>\n");
+    }
+
+    /// The whole document, headed `title`, with the footer.
+    pub fn render(self, title: &str) -> String {
+        let title = escape::html(title);
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n\
+             <style>\n{1}</style>\n</head>\n<body>\n<h1>{0}</h1>\n{2}<footer>Generated {3} by <code>{4}</code></footer>\n\
+             </body>\n</html>\n",
+            title,
+            STYLE,
+            self.body,
+            Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
+            escape::html(&command_line())
+        )
+    }
+}
+
+/// The arguments the tool was run with, each quoted if the shell would need it to run them again.
+fn command_line() -> String {
+    let plain = |arg: &str| !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c));
+    let args: Vec<String> = std::env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .map(|arg| if plain(&arg) { arg } else { escape::sh(&arg) })
+        .collect();
+    args.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charts_escape_their_labels() {
+        let mut page = Page::default();
+        page.bars(&[("<script>alert(1)</script>".to_string(), 4), ("/".to_string(), 2)]);
+        assert_eq!(page.body.matches("<rect").count(), 2);
+        assert!(page.body.contains("&lt;script&gt;alert(1)&lt;/script&gt;: 4"));
+        assert!(page.body.contains("width=\"360\"") && page.body.contains("width=\"180\""));
+        assert!(!page.body.contains("<script>"));
+
+        page.columns(&[("a&b".to_string(), 50.0)], 100.0);
+        assert!(page.body.contains("y=\"50.00\" width=\"8\" height=\"50.00\"><title>a&amp;b: 50</title>"));
+        let empty = Page::default();
+        assert!(!empty.render("<none>").contains("<none>"));
+    }
+}
@@ -0,0 +1,77 @@
+// `-o json-array` writes one JSON document where `-o json` writes a record per line, for
+// consumers that insist on well-formed JSON. Elements are still written as they're found: the
+// array opens before the first, and whoever writes it closes it once at the end, after Ctrl-C
+// or an error as well, so even a run with no matches leaves `[]`.
+
+/// Framing for a JSON array written one element at a time.
+#[derive(Debug, Default)]
+pub struct JsonArray {
+    elements: usize,
+    closed: bool,
+}
+
+impl JsonArray {
+    /// What goes before the next element: the opening bracket, or a comma after the last one.
+    pub fn separator(&mut self) -> &'static str {
+        self.elements += 1;
+        match self.elements {
+            1 => "[\n",
+            _ => ",\n",
+        }
+    }
+
+    /// What closes the array, which is nothing once it's closed.
+    pub fn close(&mut self) -> &'static str {
+        match (std::mem::replace(&mut self.closed, true), self.elements) {
+            (true, _) => "",
+            (false, 0) => "[]\n",
+            (false, _) => "\n]\n",
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// JSON records, one per line, as one array.
+pub fn collect(records: &str) -> String {
+    let mut array = JsonArray::default();
+    let mut out = String::new();
+    for record in records.lines() {
+        out.push_str(array.separator());
+        out.push_str(record);
+    }
+    out.push_str(array.close());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn parse(text: &str) -> Value {
+        serde_json::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn empty_single_and_many() {
+        assert_eq!(collect(""), "[]\n");
+        assert_eq!(collect("{\"a\":1}\n"), "[\n{\"a\":1}\n]\n");
+        assert_eq!(parse(&collect("{\"a\":1}\n{\"a\":2}\n{\"a\":3}\n")), json!([{"a": 1}, {"a": 2}, {"a": 3}]));
+    }
+
+    #[test]
+    fn an_interrupted_stream_closes_once() {
+        let mut array = JsonArray::default();
+        let mut out = String::new();
+        out.push_str(array.separator());
+        out.push_str("{\"a\":1}");
+        // Stopped here: the writer's finish closes the array, and dropping it doesn't again.
+        out.push_str(array.close());
+        assert!(array.is_closed());
+        out.push_str(array.close());
+        assert_eq!(parse(&out), json!([{"a": 1}]));
+    }
+}
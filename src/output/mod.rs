@@ -0,0 +1,573 @@
+pub mod dictionary;
+pub mod escape;
+pub mod html;
+pub mod json_array;
+pub mod pager;
+pub mod schema;
+pub mod style;
+pub mod table;
+pub mod template;
+
+use crate::entry::{Entry, Match, Provenance};
+use crate::extract;
+use crate::filter::Given;
+use crate::log_time;
+use crate::registry;
+use dictionary::{Dictionary, Ordered};
+use chrono::{format::StrftimeItems, DateTime, FixedOffset};
+use clap::ValueEnum;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::{borrow::Cow, str::FromStr, sync::OnceLock};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// The raw input line
+    #[default]
+    Line,
+    Json,
+    /// The entries as one JSON array instead of one per line, still written as they're found
+    JsonArray,
+    /// The parsed entry re-rendered as an Apache/nginx combined log line
+    Combined,
+}
+
+/// Log formats `convert --to` rewrites entries in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Conversion {
+    /// Apache/nginx combined lines, for tools such as goaccess and awstats that read nothing else
+    Combined,
+}
+
+impl Conversion {
+    pub fn format(self, entry: &Entry) -> String {
+        match self {
+            Conversion::Combined => format_combined(entry),
+        }
+    }
+}
+
+/// How parsed timestamps are written in JSON and `--fields` output.
+#[derive(Clone, Debug, Default)]
+pub enum TimeFormat {
+    #[default]
+    Rfc3339,
+    /// Integer seconds since the epoch
+    Unix,
+    /// Integer milliseconds since the epoch
+    UnixMs,
+    /// `10/Oct/2000:13:55:36 -0700`, as in access logs
+    Apache,
+    /// A chrono strftime pattern, validated when parsed
+    Custom(String),
+}
+
+impl FromStr for TimeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rfc3339" => Ok(TimeFormat::Rfc3339),
+            "unix" => Ok(TimeFormat::Unix),
+            "unix-ms" => Ok(TimeFormat::UnixMs),
+            "apache" => Ok(TimeFormat::Apache),
+            _ => match s.strip_prefix("custom:") {
+                Some(pattern) => match StrftimeItems::new(pattern).parse() {
+                    Ok(_) => Ok(TimeFormat::Custom(pattern.to_string())),
+                    Err(_) => Err(format!("invalid strftime pattern {:?}", pattern)),
+                },
+                None => Err("expected rfc3339, unix, unix-ms, apache or custom:<strftime>".to_string()),
+            },
+        }
+    }
+}
+
+static TIME_FORMAT: OnceLock<TimeFormat> = OnceLock::new();
+
+/// Sets the timestamp format for the rest of the run; only the first call has an effect.
+pub fn set_time_format(format: TimeFormat) {
+    let _ = TIME_FORMAT.set(format);
+}
+
+pub fn serialize_timestamp<S: Serializer>(
+    timestamp: &Option<DateTime<FixedOffset>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let Some(timestamp) = timestamp else {
+        return serializer.serialize_none();
+    };
+    match TIME_FORMAT.get().unwrap_or(&TimeFormat::Rfc3339) {
+        TimeFormat::Rfc3339 => timestamp.serialize(serializer),
+        TimeFormat::Unix => serializer.serialize_i64(timestamp.timestamp()),
+        TimeFormat::UnixMs => serializer.serialize_i64(timestamp.timestamp_millis()),
+        TimeFormat::Apache => serializer.collect_str(&log_time::format(timestamp)),
+        TimeFormat::Custom(pattern) => serializer.collect_str(&timestamp.format(pattern)),
+    }
+}
+
+/// An entry's fields followed by where its line was read, as JSON output and `--fields` see it.
+#[derive(Serialize)]
+struct Located<'a> {
+    #[serde(flatten)]
+    entry: &'a Entry,
+    #[serde(flatten)]
+    provenance: &'a Provenance,
+}
+
+impl<'a> From<&'a Match> for Located<'a> {
+    fn from(found: &'a Match) -> Self {
+        Located { entry: &found.entry, provenance: &found.provenance }
+    }
+}
+
+pub fn render_match(found: &Match, output: OutputFormat) -> Result<String, String> {
+    match output {
+        OutputFormat::Line => Ok(found.line.clone()),
+        OutputFormat::Json | OutputFormat::JsonArray => {
+            serde_json::to_string(&Located::from(found)).map_err(|e| e.to_string())
+        }
+        OutputFormat::Combined => Ok(format_combined(&found.entry)),
+    }
+}
+
+/// A match with the filters it satisfied, as --verbose-matches writes it in JSON.
+#[derive(Serialize)]
+struct Verbose<'a> {
+    #[serde(flatten)]
+    located: Located<'a>,
+    matched_by: MatchedBy,
+}
+
+/// A filtered field's value and the conditions, joined by `and`, it satisfied, with its edit
+/// distance from the pattern of a `fuzzy` or `fuzzy_eq` one.
+#[derive(Serialize)]
+struct Evidence {
+    value: Value,
+    condition: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance: Option<usize>,
+}
+
+/// `matched_by`: the evidence by field, in the order the filters were given.
+struct MatchedBy(Vec<(String, Evidence)>);
+
+impl Serialize for MatchedBy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (field, evidence) in &self.0 {
+            map.serialize_entry(field, evidence)?;
+        }
+        map.end()
+    }
+}
+
+impl MatchedBy {
+    fn new(values: &Map<String, Value>, given: &[Given]) -> Self {
+        let mut matched: Vec<(String, Evidence)> = Vec::new();
+        for given in given {
+            match matched.iter_mut().find(|(field, _)| *field == given.field) {
+                Some((_, evidence)) => {
+                    evidence.condition.push_str(" and ");
+                    evidence.condition.push_str(&given.condition);
+                }
+                None => {
+                    let value = filtered(values, &given.field).cloned().unwrap_or(Value::Null);
+                    matched.push((given.field.clone(), Evidence { value, condition: given.condition.clone(), distance: None }));
+                }
+            }
+            let Some(fuzzy) = &given.fuzzy else {
+                continue;
+            };
+            if let Some((_, evidence)) = matched.iter_mut().find(|(field, _)| *field == given.field) {
+                evidence.distance = evidence.value.as_str().and_then(|value| fuzzy.distance(value)).or(evidence.distance);
+            }
+        }
+        MatchedBy(matched)
+    }
+
+    /// ` [matched: status_code=503 (gte 500), ...]`, on one line whatever the values hold.
+    fn annotation(&self) -> String {
+        let evidence: Vec<String> = self
+            .0
+            .iter()
+            .map(|(field, evidence)| {
+                let value = text(Some(&evidence.value)).unwrap_or_else(|| "-".to_string());
+                let distance = evidence.distance.map(|distance| format!(", distance {}", distance)).unwrap_or_default();
+                format!("{}={} ({}{})", field, value.replace('\n', "\\n").replace('\r', "\\r"), evidence.condition, distance)
+            })
+            .collect();
+        format!(" [matched: {}]", evidence.join(", "))
+    }
+}
+
+/// A filtered field's value as the filter saw it: the path it matched and the client by name or
+/// address.
+fn filtered<'a>(values: &'a Map<String, Value>, field: &str) -> Option<&'a Value> {
+    let value = |name: &str| values.get(name).filter(|value| !value.is_null());
+    match field {
+        "path" => value("path_normalized").or_else(|| value("path")),
+        "client" => value("hostname").or_else(|| value("ip")),
+        _ => value(field),
+    }
+}
+
+/// The match with the filters it satisfied, for --verbose-matches: after the line, or as a
+/// `matched_by` object in JSON.
+pub fn render_verbose(found: &Match, output: OutputFormat, given: &[Given]) -> Result<String, String> {
+    let matched_by = MatchedBy::new(&fields(found)?, given);
+    match output {
+        OutputFormat::Json | OutputFormat::JsonArray => {
+            serde_json::to_string(&Verbose { located: Located::from(found), matched_by }).map_err(|e| e.to_string())
+        }
+        OutputFormat::Line | OutputFormat::Combined => Ok(render_match(found, output)? + &matched_by.annotation()),
+    }
+}
+
+/// A match as --inspect prints it: after its number, a labeled field per line in the order JSON
+/// output has them, missing ones left out, then the line as logged.
+pub fn render_inspect(found: &Match, position: usize) -> Result<String, String> {
+    let record = serde_json::to_string(&Located::from(found)).map_err(|e| e.to_string())?;
+    let Ordered(values) = serde_json::from_str(&record).map_err(|e| e.to_string())?;
+    let mut rows: Vec<(String, String)> =
+        values.into_iter().filter_map(|(field, value)| Some((field, text(Some(&value))?))).collect();
+    rows.push(("line".to_string(), found.line.clone()));
+    let width = rows.iter().map(|(field, _)| field.len()).max().unwrap_or(0);
+    let mut rendered = format!("match {}", position);
+    for (field, value) in rows {
+        let value = value.replace('\n', "\\n").replace('\r', "\\r");
+        rendered.push_str(&format!("\n  {:width$}  {}", field, value, width = width));
+    }
+    Ok(rendered)
+}
+
+/// How a value containing the delimiter is written.
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum DelimiterEscape {
+    /// Wrap it in double quotes, doubling any quotes inside, as in CSV
+    #[default]
+    Quote,
+    /// Replace the delimiter with a space, or `_` when the delimiter contains a space
+    Replace,
+}
+
+/// Parses `--delimiter`, accepting `\t` and `\0` (NUL, for `xargs -0`) as escapes.
+pub fn parse_delimiter(s: &str) -> Result<String, String> {
+    let separator = match s {
+        "\\t" => "\t",
+        "\\0" => "\0",
+        _ => s,
+    };
+    if separator.is_empty() || separator.contains(['\n', '\r', '"']) {
+        return Err("the delimiter must be non-empty and can't contain newlines or double quotes".to_string());
+    }
+    Ok(separator.to_string())
+}
+
+/// The separator between columns in `--fields` rows and tables. With a NUL separator rows
+/// end in NUL too, so every value arrives as its own item.
+#[derive(Clone, Debug)]
+pub struct Delimiter {
+    separator: String,
+    escape: DelimiterEscape,
+}
+
+impl Default for Delimiter {
+    fn default() -> Self {
+        Delimiter { separator: "\t".to_string(), escape: DelimiterEscape::Quote }
+    }
+}
+
+impl Delimiter {
+    pub fn new(separator: String, escape: DelimiterEscape) -> Self {
+        Delimiter { separator, escape }
+    }
+
+    pub fn terminator(&self) -> char {
+        match self.separator.as_str() {
+            "\0" => '\0',
+            _ => '\n',
+        }
+    }
+
+    /// Escapes a value that would otherwise split its column or row.
+    fn cell<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        let hostile = |c: char| c == '\n' || c == '\r' || c == self.terminator();
+        if !value.contains(self.separator.as_str()) && !value.contains(hostile) && !value.starts_with('"') {
+            return Cow::Borrowed(value);
+        }
+        match self.escape {
+            DelimiterEscape::Quote => Cow::Owned(format!("\"{}\"", value.replace('"', "\"\""))),
+            DelimiterEscape::Replace => {
+                let replacement = if self.separator.contains(' ') { "_" } else { " " };
+                Cow::Owned(value.replace(self.separator.as_str(), replacement).replace(hostile, replacement))
+            }
+        }
+    }
+
+    pub fn join<S: AsRef<str>>(&self, values: &[S]) -> String {
+        values.iter().map(|v| self.cell(v.as_ref())).collect::<Vec<_>>().join(&self.separator)
+    }
+
+    /// A joined row followed by the row terminator.
+    pub fn row<S: AsRef<str>>(&self, values: &[S]) -> String {
+        let mut row = self.join(values);
+        row.push(self.terminator());
+        row
+    }
+}
+
+static DELIMITER: OnceLock<Delimiter> = OnceLock::new();
+
+/// Sets the column delimiter for the rest of the run; only the first call has an effect.
+pub fn set_delimiter(delimiter: Delimiter) {
+    let _ = DELIMITER.set(delimiter);
+}
+
+pub fn delimiter() -> &'static Delimiter {
+    DELIMITER.get_or_init(Delimiter::default)
+}
+
+/// A match's fields by name, as JSON output has them.
+fn fields(found: &Match) -> Result<Map<String, Value>, String> {
+    match serde_json::to_value(Located::from(found)).map_err(|e| e.to_string())? {
+        Value::Object(values) => Ok(values),
+        _ => Err("entry did not serialize to an object".to_string()),
+    }
+}
+
+/// A field's value as text, `None` when it's missing; a list's values are joined by commas.
+fn text(value: Option<&Value>) -> Option<String> {
+    match value {
+        None | Some(Value::Null) => None,
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(Value::Array(values)) => Some(values.iter().filter_map(|value| text(Some(value))).collect::<Vec<_>>().join(",")),
+        Some(value) => Some(value.to_string()),
+    }
+}
+
+/// Fails for the first of `names` that isn't among the first match's `values`, a field of the
+/// registry's that the log type has or one --extract defines; which fields an entry has beyond
+/// those depends on the log format.
+fn check_fields(names: &[String], values: &Map<String, Value>) -> Result<(), String> {
+    match names.iter().find(|name| !values.contains_key(*name) && !registry::known(name) && !extract::defines(name)) {
+        Some(name) => {
+            let found = values.iter().filter(|(_, value)| !value.is_null()).map(|(name, _)| name.as_str());
+            Err(registry::unknown(name, found))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Renders the `--fields` of each match separated by `--delimiter`, e.g. to build a file for
+/// `--ip in @file`. Besides the entry's fields, `source_file`, `line_no` and `byte_offset` say
+/// where its line was read.
+pub struct FieldSelection {
+    fields: Vec<String>,
+    checked: bool,
+}
+
+impl FieldSelection {
+    pub fn new(fields: Vec<String>) -> Self {
+        FieldSelection { fields, checked: false }
+    }
+
+    /// The field names as a row, for --header.
+    pub fn header(&self) -> String {
+        delimiter().row(&self.fields)
+    }
+
+    /// Missing values are written as `-`; entries missing every field produce no row. With a
+    /// dictionary, the values of its fields are written as their IDs.
+    pub fn render(&mut self, found: &Match, mut dictionary: Option<&mut Dictionary>) -> Result<Option<String>, String> {
+        let values = fields(found)?;
+        if !self.checked {
+            check_fields(&self.fields, &values)?;
+            self.checked = true;
+        }
+        let mut row: Vec<Option<String>> = Vec::with_capacity(self.fields.len());
+        for name in &self.fields {
+            let mut value = values.get(name).cloned().unwrap_or(Value::Null);
+            if let Some(dictionary) = dictionary.as_deref_mut() {
+                dictionary.encode(name, &mut value)?;
+            }
+            row.push(text(Some(&value)));
+        }
+        if row.iter().all(Option::is_none) {
+            return Ok(None);
+        }
+        let row: Vec<String> = row.into_iter().map(|v| v.unwrap_or_else(|| "-".to_string())).collect();
+        Ok(Some(delimiter().join(&row)))
+    }
+}
+
+fn or_dash<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
+
+/// Quotes a field the way the combined parser expects, escaping backslashes and quotes.
+fn quoted(value: Option<&str>) -> String {
+    match value {
+        Some(value) if !value.is_empty() => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        _ => "\"-\"".to_string(),
+    }
+}
+
+/// Renders an entry as a combined log line that parses back to the same field values.
+/// Fields the entry lacks are written as `-`. Timestamps are always in the access log format.
+pub fn format_combined(entry: &Entry) -> String {
+    let request: Vec<&str> = [&entry.method, &entry.path, &entry.protocol]
+        .into_iter()
+        .filter_map(|part| part.as_deref())
+        .collect();
+    let request = entry.raw_request.clone().or_else(|| (!request.is_empty()).then(|| request.join(" ")));
+    format!(
+        "{} - {} [{}] {} {} {} {} {}",
+        or_dash(entry.client.as_ref()),
+        or_dash(entry.user.as_deref()),
+        or_dash(entry.timestamp.map(|t| log_time::format(&t))),
+        quoted(request.as_deref()),
+        or_dash(entry.status_code.map(|s| s.as_u16())),
+        or_dash(entry.bytes),
+        quoted(entry.referrer.as_deref()),
+        quoted(entry.user_agent.as_deref()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::{LineParser, LogKind};
+
+    #[test]
+    fn delimiters_parse_escapes() {
+        assert_eq!(parse_delimiter("\\t"), Ok("\t".to_string()));
+        assert_eq!(parse_delimiter("\\0"), Ok("\0".to_string()));
+        assert_eq!(parse_delimiter(" | "), Ok(" | ".to_string()));
+        assert!(parse_delimiter("").is_err());
+        assert!(parse_delimiter("\n").is_err());
+    }
+
+    #[test]
+    fn values_containing_the_delimiter_keep_their_column() {
+        let values = ["/a\tb", "say \"hi\"", "\"quoted\"", "two\nlines", "plain"];
+        let quote = Delimiter::default();
+        assert_eq!(quote.join(&values), "\"/a\tb\"\tsay \"hi\"\t\"\"\"quoted\"\"\"\t\"two\nlines\"\tplain");
+        let replace = Delimiter::new("\t".to_string(), DelimiterEscape::Replace);
+        assert_eq!(replace.row(&values), "/a b\tsay \"hi\"\t\"quoted\"\ttwo lines\tplain\n");
+        let spaces = Delimiter::new(" ".to_string(), DelimiterEscape::Replace);
+        assert_eq!(spaces.join(&["a b", "c"]), "a_b c");
+        let nul = Delimiter::new("\0".to_string(), DelimiterEscape::Replace);
+        assert_eq!(nul.row(&["a\0b", "c\nd"]), "a b\0c d\0");
+    }
+
+    #[test]
+    fn field_rows_quote_hostile_values() {
+        let entry = Entry {
+            path: Some("/search?q=a\tb".to_string()),
+            user_agent: Some("\"quoted\" agent".to_string()),
+            ..Entry::default()
+        };
+        let found = Match {
+            line: String::new(),
+            entry,
+            provenance: Provenance { source_file: "access.log".into(), line_no: 3, byte_offset: 512 },
+        };
+        let mut selection = FieldSelection::new(vec!["path".to_string(), "user_agent".to_string(), "ip".to_string()]);
+        assert_eq!(selection.render(&found, None), Ok(Some("\"/search?q=a\tb\"\t\"\"\"quoted\"\" agent\"\t-".to_string())));
+        let mut provenance = FieldSelection::new(vec!["ip".to_string(), "source_file".to_string(), "line_no".to_string(), "byte_offset".to_string()]);
+        assert_eq!(provenance.render(&found, None), Ok(Some("-\taccess.log\t3\t512".to_string())));
+    }
+
+    #[test]
+    fn verbose_matches_keep_to_one_line() {
+        let found = Match {
+            line: "line".to_string(),
+            entry: Entry { user_agent: Some("evil\r\nagent".to_string()), path_normalized: Some("/users/:id".to_string()), ..Entry::default() },
+            provenance: Provenance { source_file: "access.log".into(), line_no: 1, byte_offset: 0 },
+        };
+        let given =
+            |field: &str, condition: &str| Given { field: field.to_string(), condition: condition.to_string(), fuzzy: None };
+        let given = [given("user_agent", "contains \"agent\""), given("path", "eq \"/users/:id\""), given("user_agent", "neq \"x\"")];
+        assert_eq!(
+            render_verbose(&found, OutputFormat::Line, &given),
+            Ok("line [matched: user_agent=evil\\r\\nagent (contains \"agent\" and neq \"x\"), path=/users/:id (eq \"/users/:id\")]".to_string())
+        );
+    }
+
+    /// The fields a combined line holds, as JSON, to compare entries read from different lines.
+    fn combined_fields(entry: &Entry) -> Vec<serde_json::Value> {
+        let json = serde_json::to_value(entry).unwrap();
+        ["ip", "user", "timestamp", "method", "path", "protocol", "raw_request", "status_code", "bytes", "referrer", "user_agent"]
+            .iter()
+            .map(|field| json[field].clone())
+            .collect()
+    }
+
+    fn reparse(line: &str) -> Entry {
+        let mut parser = LineParser::Kind(LogKind::Combined);
+        parser.parse(line).unwrap_or_else(|e| panic!("{:?} doesn't parse: {}", line, e)).expect("an entry")
+    }
+
+    #[test]
+    fn fixture_lines_round_trip_through_format_combined() {
+        let fixtures = [
+            include_str!("../../tests/fixtures/combined.log"),
+            include_str!("../../tests/fixtures/quoted.log"),
+            include_str!("../../tests/fixtures/months.log"),
+            include_str!("../../tests/fixtures/ipv6.log"),
+            include_str!("../../tests/fixtures/hostile-agents.log"),
+        ];
+        for line in fixtures.iter().flat_map(|fixture| fixture.lines()) {
+            let entry = reparse(line);
+            let rendered = format_combined(&entry);
+            let again = reparse(&rendered);
+            assert_eq!(combined_fields(&again), combined_fields(&entry), "{:?} re-rendered as {:?}", line, rendered);
+            assert_eq!(format_combined(&again), rendered, "{:?} isn't rendered the same twice", line);
+        }
+    }
+
+    #[test]
+    fn generated_entries_round_trip_through_format_combined() {
+        let users = [None, Some("bob"), Some("d.o-e")];
+        let agents = [
+            None,
+            Some("Mozilla/5.0 (X11; Linux x86_64)"),
+            Some("say \"hi\""),
+            Some("back\\slash"),
+            Some("ends with \\"),
+            Some("\"\""),
+            Some("already \\\"escaped\\\""),
+        ];
+        let referrers = [None, Some("https://example.com/"), Some("http://x/?q=\"y\"&z=1")];
+        let times =
+            ["2023-02-12T14:03:45+00:00", "2023-12-31T23:59:59-09:30", "2024-02-29T00:00:00+14:00", "2023-07-04T09:05:01+05:45"];
+        let requests: [(Option<&str>, Option<&str>, Option<&str>); 4] = [
+            (Some("GET"), Some("/a"), Some("HTTP/1.1")),
+            (Some("POST"), Some("/search?q=a%20b&x=\"1\""), Some("HTTP/2.0")),
+            (Some("OPTIONS"), Some("*"), Some("HTTP/1.0")),
+            (None, None, None),
+        ];
+        // A missing byte count is written `-`, which reads back as 0, as Apache's %b means.
+        let bytes = [Some(0), Some(1), Some(u64::MAX)];
+        let statuses = [200, 304, 404, 503];
+        let clients = ["10.0.0.1", "2001:db8::1", "::ffff:192.0.2.4"];
+        for i in 0..420 {
+            let (method, path, protocol) = requests[i % requests.len()];
+            let entry = Entry {
+                client: Some(clients[i % clients.len()].parse().unwrap()),
+                user: users[i % users.len()].map(str::to_string),
+                timestamp: Some(DateTime::parse_from_rfc3339(times[i % times.len()]).unwrap()),
+                method: method.map(str::to_string),
+                path: path.map(str::to_string),
+                protocol: protocol.map(str::to_string),
+                status_code: Some(http::StatusCode::from_u16(statuses[i / 3 % statuses.len()]).unwrap()),
+                bytes: bytes[i / 5 % bytes.len()],
+                referrer: referrers[i / 7 % referrers.len()].map(str::to_string),
+                user_agent: agents[i % agents.len()].map(str::to_string),
+                ..Entry::default()
+            };
+            let rendered = format_combined(&entry);
+            assert_eq!(combined_fields(&reparse(&rendered)), combined_fields(&entry), "{:?}", rendered);
+        }
+    }
+}
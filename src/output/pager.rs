@@ -0,0 +1,249 @@
+use crate::interrupt;
+use clap::ValueEnum;
+use std::{
+    env,
+    io::{self, ErrorKind, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+// With `--pager`, output to a terminal is held back until it runs past a screenful and then
+// piped through $PAGER, so short results print as before. Quitting the pager early isn't an
+// error: whatever follows is dropped, and the caller can stop reading. Ctrl-C reaches the pager
+// and us alike, so there's nothing to forward; a second Ctrl-C just mustn't exit while the pager
+// still has the terminal, which `interrupt` checks through `interrupt::set_paging`.
+
+/// The pager when $PAGER isn't set; `-R` lets colors through.
+const DEFAULT_PAGER: &str = "less -R";
+
+/// Rows assumed when $LINES doesn't say.
+const DEFAULT_ROWS: usize = 24;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum PagerMode {
+    /// Page output to a terminal that runs past a screenful
+    #[default]
+    Auto,
+    /// Page all output to a terminal, however short
+    Always,
+    Never,
+}
+
+/// $PAGER, split at whitespace, or `less -R`. `None` when $PAGER is empty or `cat`, which ask
+/// for no pager.
+pub fn command() -> Option<String> {
+    match env::var("PAGER") {
+        Ok(pager) if pager.trim().is_empty() || pager.trim() == "cat" => None,
+        Ok(pager) => Some(pager),
+        Err(_) => Some(DEFAULT_PAGER.to_string()),
+    }
+}
+
+/// The terminal's height from $LINES, or 24.
+pub fn rows() -> usize {
+    env::var("LINES").ok().and_then(|lines| lines.trim().parse().ok()).filter(|&rows| rows > 0).unwrap_or(DEFAULT_ROWS)
+}
+
+/// A running pager reading from a pipe.
+struct Pager {
+    child: Child,
+    /// `None` once closed.
+    stdin: Option<ChildStdin>,
+    /// Whether the pager exited before reading everything.
+    quit: bool,
+}
+
+impl Pager {
+    fn spawn(command: &str, stdout: Stdio) -> io::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "empty pager command"))?;
+        let mut child = Command::new(program).args(words).stdin(Stdio::piped()).stdout(stdout).spawn()?;
+        interrupt::set_paging(true);
+        let stdin = child.stdin.take();
+        Ok(Pager { child, stdin, quit: false })
+    }
+
+    /// Writes to the pager, dropping everything once it has quit.
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let Some(stdin) = self.stdin.as_mut().filter(|_| !self.quit) else {
+            return Ok(());
+        };
+        match stdin.write_all(bytes).and_then(|_| stdin.flush()) {
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+                self.quit = true;
+                Ok(())
+            }
+            written => written,
+        }
+    }
+
+    /// Ends the pager's input and waits for the user to leave it.
+    fn close(&mut self) -> io::Result<()> {
+        drop(self.stdin.take());
+        let waited = self.child.wait();
+        interrupt::set_paging(false);
+        waited.map(|_| ())
+    }
+}
+
+impl Drop for Pager {
+    /// Waits even when the run ends in an error, so the shell prompt doesn't come back while
+    /// the pager still draws on the terminal.
+    fn drop(&mut self) {
+        if self.stdin.is_some() {
+            let _ = self.close();
+        }
+    }
+}
+
+enum State<W> {
+    /// Fewer than a screenful of lines so far.
+    Holding,
+    Paging(Pager),
+    /// Past a screenful, but the pager wouldn't start.
+    Direct(W),
+}
+
+/// Output that goes to `direct`, usually stdout, when it ends within `rows` lines, and through
+/// the pager `command` once it doesn't.
+pub struct Paged<W: Write> {
+    command: String,
+    rows: usize,
+    held: Vec<u8>,
+    direct: Option<W>,
+    state: State<W>,
+    /// Where the pager writes, the terminal outside tests.
+    pager_stdout: fn() -> Stdio,
+}
+
+impl<W: Write> Paged<W> {
+    /// With `rows` 0, the pager starts with the first write.
+    pub fn new(command: String, rows: usize, direct: W) -> Self {
+        Paged { command, rows, held: Vec::new(), direct: Some(direct), state: State::Holding, pager_stdout: Stdio::inherit }
+    }
+
+    /// Whether the pager was quit before the output ended, so nothing more will be seen.
+    pub fn has_quit(&self) -> bool {
+        matches!(&self.state, State::Paging(pager) if pager.quit)
+    }
+
+    /// Starts the pager with what was held, or falls back to `direct` if it can't be spawned.
+    fn start(&mut self) -> io::Result<()> {
+        let held = std::mem::take(&mut self.held);
+        let mut direct = self.direct.take().expect("output is held until the pager starts");
+        match Pager::spawn(&self.command, (self.pager_stdout)()) {
+            Ok(mut pager) => {
+                pager.write_all(&held)?;
+                self.state = State::Paging(pager);
+            }
+            Err(_) => {
+                direct.write_all(&held)?;
+                self.state = State::Direct(direct);
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints what was held when the output fit on a screen, otherwise closes the pager and
+    /// waits for it to exit.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match &mut self.state {
+            State::Holding => {
+                let held = std::mem::take(&mut self.held);
+                let direct = self.direct.as_mut().expect("output is held until the pager starts");
+                direct.write_all(&held).and_then(|_| direct.flush())
+            }
+            State::Paging(pager) => pager.close(),
+            State::Direct(direct) => direct.flush(),
+        }
+    }
+}
+
+impl<W: Write> Write for Paged<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        match &mut self.state {
+            State::Holding => {
+                self.held.extend_from_slice(bytes);
+                if self.held.iter().filter(|&&b| b == b'\n').count() >= self.rows {
+                    self.start()?;
+                }
+                Ok(bytes.len())
+            }
+            State::Paging(pager) => pager.write_all(bytes).map(|_| bytes.len()),
+            State::Direct(direct) => direct.write(bytes),
+        }
+    }
+
+    /// Held output stays held; it's only a screenful or the end that lets it out.
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.state {
+            State::Direct(direct) => direct.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn lines(count: usize) -> String {
+        (1..=count).map(|n| format!("line {}\n", n)).collect()
+    }
+
+    #[test]
+    fn cat_receives_everything_written() {
+        let mut pager = Pager::spawn("cat", Stdio::piped()).unwrap();
+        let mut stdout = pager.child.stdout.take().unwrap();
+        // Read alongside, since cat blocks once its own output pipe is full.
+        let reader = std::thread::spawn(move || {
+            let mut text = String::new();
+            stdout.read_to_string(&mut text).map(|_| text)
+        });
+        let text = lines(20_000);
+        pager.write_all(text.as_bytes()).unwrap();
+        pager.close().unwrap();
+        assert_eq!(reader.join().unwrap().unwrap(), text);
+        assert!(!pager.quit);
+    }
+
+    #[test]
+    fn a_pager_that_quits_early_takes_no_more() {
+        // `true` exits without reading, as when less is quit on its first screen.
+        let mut pager = Pager::spawn("true", Stdio::null()).unwrap();
+        let chunk = lines(1000);
+        while !pager.quit {
+            pager.write_all(chunk.as_bytes()).unwrap();
+        }
+        assert!(pager.write_all(chunk.as_bytes()).is_ok());
+        pager.close().unwrap();
+    }
+
+    #[test]
+    fn output_within_a_screenful_skips_the_pager() {
+        let mut paged = Paged::new("cat".to_string(), 5, Vec::new());
+        paged.write_all(lines(4).as_bytes()).unwrap();
+        assert!(matches!(paged.state, State::Holding));
+        paged.finish().unwrap();
+        assert_eq!(paged.direct.as_deref(), Some(lines(4).as_bytes()));
+    }
+
+    #[test]
+    fn longer_output_goes_to_the_pager_or_else_straight_through() {
+        let mut paged = Paged::new("cat".to_string(), 5, Vec::new());
+        paged.pager_stdout = Stdio::null;
+        paged.write_all(lines(5).as_bytes()).unwrap();
+        assert!(matches!(paged.state, State::Paging(_)));
+        paged.write_all(lines(2).as_bytes()).unwrap();
+        paged.finish().unwrap();
+
+        let mut paged = Paged::new("no-such-pager-anywhere".to_string(), 5, Vec::new());
+        paged.write_all(lines(7).as_bytes()).unwrap();
+        paged.write_all(b"tail\n").unwrap();
+        paged.finish().unwrap();
+        let State::Direct(direct) = &paged.state else {
+            panic!("expected a fallback to direct output");
+        };
+        assert_eq!(String::from_utf8_lossy(direct), lines(7) + "tail\n");
+    }
+}
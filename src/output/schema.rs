@@ -0,0 +1,903 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+// The shape of every JSON output, as records and as JSON Schema documents that `log-parser
+// schema` prints for consumers to validate against. The documents are kept as snapshots under
+// tests/snapshots, so changing a serialized field fails the tests until the document and its
+// snapshot are updated, and SCHEMA_VERSION with them. Types that carry their own logic, such as
+// `Entry` or `RunSummary`, stay where they are and are only described here; the records below
+// used to be put together with `json!` at each output.
+
+/// Version of the JSON outputs, announced by `--schema-version`; bump it with any change to the
+/// documents below.
+pub const SCHEMA_VERSION: u32 = 27;
+
+const META_SCHEMA: &str = "https://json-schema.org/draft/2020-12/schema";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    /// Matched entries, from `filter -o json` and the entries novel and anomalies print
+    Entry,
+    /// `filter --dict-fields -o json`, after the entries or in --dict-file
+    Dictionary,
+    /// `filter --count -o json` and `novel --count -o json`
+    Count,
+    /// `filter --count-by -o json`
+    Breakdown,
+    /// `stats -o json` without --by
+    Stats,
+    /// `stats --by -o json`
+    Groups,
+    /// `histogram -o json`, one record per bucket
+    Histogram,
+    /// `anomalies -o json`, one record per group
+    Anomaly,
+    /// `scanners -o json`, one record per client
+    Scanner,
+    /// `auth -o json`, one record per client that failed to authenticate
+    AuthClient,
+    /// `auth -o json`, after the clients, one record per username tried
+    AuthUser,
+    /// `check -o json`, one record per rule
+    Check,
+    /// `cardinality -o json`, one record per group
+    Cardinality,
+    /// `top -o json`, one record per value
+    Top,
+    /// `report -o json`, one record per row of each section
+    Report,
+    /// `bench -o json`
+    Bench,
+    /// `batch -o json`, one record per job
+    Batch,
+    /// `fields -o json`, one record per field
+    Field,
+    /// `--list-operators -o json`, one record per kind of filter option
+    Operators,
+    /// `self-test -o json`
+    SelfTest,
+    /// Errors, warnings, notices, summaries and snapshots on stderr with `--errors json`
+    Diagnostic,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Entry => "entry",
+            Kind::Dictionary => "dictionary",
+            Kind::Count => "count",
+            Kind::Breakdown => "breakdown",
+            Kind::Stats => "stats",
+            Kind::Groups => "groups",
+            Kind::Histogram => "histogram",
+            Kind::Anomaly => "anomaly",
+            Kind::Scanner => "scanner",
+            Kind::AuthClient => "auth-client",
+            Kind::AuthUser => "auth-user",
+            Kind::Check => "check",
+            Kind::Cardinality => "cardinality",
+            Kind::Top => "top",
+            Kind::Report => "report",
+            Kind::Bench => "bench",
+            Kind::Batch => "batch",
+            Kind::Field => "field",
+            Kind::Operators => "operators",
+            Kind::SelfTest => "self-test",
+            Kind::Diagnostic => "diagnostic",
+        }
+    }
+}
+
+/// The record `--schema-version` puts first in a JSON stream.
+#[derive(Serialize)]
+pub struct Preamble {
+    pub schema_version: u32,
+}
+
+/// The preamble as a line of output.
+pub fn preamble() -> String {
+    format!("{}\n", json!(Preamble { schema_version: SCHEMA_VERSION }))
+}
+
+/// `stats -o json` without --by. The figures are left out when no entry had the metric.
+#[derive(Serialize)]
+pub struct MetricSummary {
+    pub count: usize,
+    pub missing: usize,
+    #[serde(flatten)]
+    pub figures: Option<Figures>,
+}
+
+#[derive(Serialize)]
+pub struct Figures {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+/// A `stats --by` group: the count alone, or with a metric its summary too, `null` when no
+/// entry in the group had the metric. --examples adds the group's example lines, and
+/// --approximate gives the count with how far over the true count it may be.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum GroupLeaf {
+    Count(usize),
+    Examples {
+        count: usize,
+        examples: Vec<String>,
+    },
+    Metric {
+        count: usize,
+        mean: Option<f64>,
+        p50: Option<f64>,
+        p95: Option<f64>,
+        max: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        examples: Option<Vec<String>>,
+    },
+    Approximate {
+        count: usize,
+        error: usize,
+    },
+}
+
+/// A histogram bucket. The class counts come with --split-status and the 5xx share with
+/// --only-ratio, `null` for an empty bucket.
+#[derive(Serialize)]
+pub struct HistogramBucket {
+    pub bucket: String,
+    pub total: u64,
+    #[serde(flatten)]
+    pub classes: Option<ClassCounts>,
+    #[serde(rename = "5xx_pct", skip_serializing_if = "Option::is_none")]
+    pub server_error_pct: Option<Option<f64>>,
+}
+
+#[derive(Serialize)]
+pub struct ClassCounts {
+    #[serde(rename = "2xx")]
+    pub success: u64,
+    #[serde(rename = "3xx")]
+    pub redirect: u64,
+    #[serde(rename = "4xx")]
+    pub client_error: u64,
+    #[serde(rename = "5xx")]
+    pub server_error: u64,
+    pub other: u64,
+}
+
+/// A group flagged by `anomalies`, keyed by its --by values. An infinite deviation is `null`.
+#[derive(Serialize)]
+pub struct Anomaly {
+    #[serde(flatten)]
+    pub group: BTreeMap<String, String>,
+    pub baseline: Baseline,
+    pub count: usize,
+    pub observed: f64,
+    pub deviation: f64,
+}
+
+#[derive(Serialize)]
+pub struct Baseline {
+    pub count: usize,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+/// A group's distinct count from `cardinality`, keyed by its --by values, and the standard
+/// error of the count, 0 with --exact.
+#[derive(Serialize)]
+pub struct Cardinality {
+    #[serde(flatten)]
+    pub group: BTreeMap<String, String>,
+    pub distinct: u64,
+    pub error: u64,
+}
+
+/// A value ranked by `top`, keyed by the key it's a value of: its matches, with --approximate
+/// how far over that may be, their share of all of them in percent, and with a baseline how
+/// that share changed.
+#[derive(Serialize)]
+pub struct Top {
+    #[serde(flatten)]
+    pub group: BTreeMap<String, String>,
+    pub count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<usize>,
+    pub share: f64,
+    #[serde(flatten)]
+    pub change: Option<ShareChange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub examples: Option<Vec<String>>,
+}
+
+/// The baseline's matches and share of a value, and the change to the target's share in
+/// percentage points and as a ratio. Values with fewer than --min-baseline baseline matches are
+/// `new`, with no ratio.
+#[derive(Serialize)]
+pub struct ShareChange {
+    pub baseline_count: usize,
+    pub baseline_share: f64,
+    pub delta: f64,
+    pub ratio: Option<f64>,
+    pub new: bool,
+}
+
+/// A row of a `report` section, named by its key: a day with its bytes, 5xx responses and
+/// their share, `null` for a day without requests, or a value ranked by its share of the
+/// requests in percent.
+#[derive(Serialize)]
+pub struct ReportRow {
+    pub section: &'static str,
+    pub key: String,
+    pub requests: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_rate: Option<Option<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share: Option<f64>,
+}
+
+/// A job of `batch`: the lines of the one pass over the input, the entries its filter matched
+/// and the file it wrote them to.
+#[derive(Serialize)]
+pub struct BatchJob {
+    pub job: String,
+    pub lines: u64,
+    pub matched: u64,
+    pub file: String,
+}
+
+/// A field `fields` lists, with the option that filters on it and the kind of filter that is,
+/// both `null` for fields no option filters on.
+#[derive(Serialize)]
+pub struct Field {
+    pub field: String,
+    pub filter: Option<String>,
+    pub kind: Option<&'static str>,
+}
+
+/// A kind of filter option, the operators it takes and an example.
+#[derive(Serialize)]
+pub struct FilterKind {
+    pub kind: &'static str,
+    pub options: &'static [&'static str],
+    pub operators: &'static [&'static str],
+    pub example: &'static str,
+}
+
+/// A partial `stats`, `histogram` or `top` result from --snapshot-every: the records `-o json` would
+/// print had the input ended after `lines` lines.
+#[derive(Serialize)]
+pub struct Snapshot {
+    pub sequence: usize,
+    pub lines: usize,
+    pub elapsed_secs: f64,
+    pub partial: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+    pub result: Vec<Value>,
+}
+
+#[derive(Serialize)]
+pub struct BenchReport<'a> {
+    pub iterations: &'a [crate::bench::Iteration],
+}
+
+fn nullable(kind: &str) -> Value {
+    json!({"type": [kind, "null"]})
+}
+
+/// Timestamps are strings or, with `--time-format unix` and `unix-ms`, integers.
+fn timestamp() -> Value {
+    json!({"type": ["string", "integer", "null"]})
+}
+
+/// Strings or, when named in --dict-fields, their IDs in the dictionary.
+fn dictionary_id() -> Value {
+    json!({"type": ["string", "integer", "null"], "description": "with --dict-fields, the value's ID in the dictionary"})
+}
+
+fn counter() -> Value {
+    json!({"type": "integer", "minimum": 0})
+}
+
+/// An object with exactly these properties, of which `required` are always present.
+fn record(properties: Value, required: &[&str]) -> Value {
+    json!({"type": "object", "properties": properties, "required": required, "additionalProperties": false})
+}
+
+fn body(kind: Kind) -> Value {
+    match kind {
+        Kind::Entry => {
+            let distance = "the value's edit distance from a fuzzy or fuzzy_eq pattern";
+            let distance = json!({"description": distance, "type": "integer", "minimum": 0});
+            let evidence = json!({"value": {}, "condition": {"type": "string"}, "distance": distance});
+            let evidence = record(evidence, &["value", "condition"]);
+            json!({
+                "type": "object",
+                "properties": {
+                    "ip": nullable("string"),
+                    "hostname": {"type": "string", "description": "the client, when it was logged by name"},
+                    "user": nullable("string"),
+                    "timestamp": timestamp(),
+                    "method": nullable("string"),
+                    "path": nullable("string"),
+                    "path_normalized": {"type": "string", "description": "the path as filters and keys see it, with --normalize-paths"},
+                    "class": {"enum": ["static", "dynamic"], "description": "static for assets, by extension or path prefix"},
+                    "path_depth": {"type": "integer", "minimum": 0, "description": "the number of segments of the path"},
+                    "gap": {"type": "number", "minimum": 0, "description": "seconds since the client's previous request"},
+                    "suspicious": {
+                        "type": "array",
+                        "items": {
+                            "enum": ["dot-dot", "dot-dot-encoded", "double-encoded", "null-byte", "overlong-encoding", "non-ascii"],
+                        },
+                        "description": "why the path as logged looks like a traversal or encoding attack, when it does",
+                    },
+                    "referrer_class": {
+                        "enum": ["internal", "search", "social", "external", "none"],
+                        "description": "where the referrer points: the site itself, a search engine, social network or elsewhere",
+                    },
+                    "referrer_domain": {"type": "string", "description": "the referrer's host, without a leading www."},
+                    "protocol": nullable("string"),
+                    "raw_request": {
+                        "type": "string",
+                        "description": "the request line as logged, when it isn't METHOD PATH PROTOCOL; method, path and protocol are null",
+                    },
+                    "status_code": nullable("integer"),
+                    "bytes": nullable("integer"),
+                    "invalid": {
+                        "type": "object",
+                        "additionalProperties": {"type": "string"},
+                        "description": "numeric fields, such as bytes, whose value isn't a number, as logged; the fields are null",
+                    },
+                    "referrer": dictionary_id(),
+                    "user_agent": dictionary_id(),
+                    "severity": {"enum": ["trace", "debug", "info", "notice", "warn", "error", "crit", "alert", "emerg"]},
+                    "duration": {"type": "number", "description": "seconds"},
+                    "scheme": {"type": "string"},
+                    "port": {"type": "integer", "minimum": 0, "maximum": 65535},
+                    "tls_protocol": {"enum": ["SSLv3", "TLSv1", "TLSv1.1", "TLSv1.2", "TLSv1.3"]},
+                    "source_file": {"type": "string"},
+                    "line_no": {"type": "integer", "minimum": 1},
+                    "byte_offset": counter(),
+                    "matched_by": {
+                        "type": "object",
+                        "description": "with --verbose-matches, each filtered field's value and the conditions it satisfied",
+                        "additionalProperties": evidence,
+                    },
+                },
+                "required": [
+                    "ip", "user", "timestamp", "method", "path", "protocol", "status_code", "bytes", "referrer",
+                    "user_agent", "source_file", "line_no", "byte_offset",
+                ],
+                "additionalProperties": {
+                    "description": "fields particular to the log format, such as nginx's request_time",
+                    "type": ["integer", "number", "string"],
+                },
+            })
+        }
+        Kind::Dictionary => record(
+            json!({
+                "dictionary": {
+                    "type": "object",
+                    "description": "the values of each of --dict-fields",
+                    "additionalProperties": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "each value at the index of its ID",
+                    },
+                },
+            }),
+            &["dictionary"],
+        ),
+        Kind::Count => record(
+            json!({"matched": counter(), "matched_bytes": counter(), "missing_bytes": counter()}),
+            &["matched"],
+        ),
+        Kind::Breakdown => record(
+            json!({
+                "by": {"enum": ["file", "day"]},
+                "counts": {"type": "object", "additionalProperties": counter()},
+            }),
+            &["by", "counts"],
+        ),
+        Kind::Stats => record(
+            json!({
+                "count": counter(),
+                "missing": counter(),
+                "min": {"type": "number"},
+                "max": {"type": "number"},
+                "mean": {"type": "number"},
+                "p50": {"type": "number"},
+                "p90": {"type": "number"},
+                "p95": {"type": "number"},
+                "p99": {"type": "number"},
+            }),
+            &["count", "missing"],
+        ),
+        Kind::Groups => json!({
+            "description": "objects nested by the value of each --by key in turn",
+            "$ref": "#/$defs/node",
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "additionalProperties": {
+                        "anyOf": [
+                            counter(),
+                            {"$ref": "#/$defs/leaf"},
+                            {"$ref": "#/$defs/sampled"},
+                            {"$ref": "#/$defs/approximate"},
+                            {"$ref": "#/$defs/node"},
+                        ],
+                    },
+                },
+                "leaf": record(
+                    json!({
+                        "count": counter(),
+                        "mean": nullable("number"),
+                        "p50": nullable("number"),
+                        "p95": nullable("number"),
+                        "max": nullable("number"),
+                        "examples": {"$ref": "#/$defs/examples"},
+                    }),
+                    &["count", "mean", "p50", "p95", "max"],
+                ),
+                "sampled": record(json!({"count": counter(), "examples": {"$ref": "#/$defs/examples"}}), &["count", "examples"]),
+                "examples": {"description": "lines picked at random from the group's, in input order", "type": "array", "items": {"type": "string"}},
+                "approximate": record(
+                    json!({"count": counter(), "error": {"description": "how much more the count may be than the true one", "type": "integer", "minimum": 0}}),
+                    &["count", "error"],
+                ),
+            },
+        }),
+        Kind::Histogram => record(
+            json!({
+                "bucket": {"type": "string"},
+                "total": counter(),
+                "2xx": counter(),
+                "3xx": counter(),
+                "4xx": counter(),
+                "5xx": counter(),
+                "other": counter(),
+                "5xx_pct": nullable("number"),
+            }),
+            &["bucket", "total"],
+        ),
+        Kind::Anomaly => json!({
+            "type": "object",
+            "properties": {
+                "baseline": record(
+                    json!({"count": counter(), "mean": {"type": "number"}, "stddev": {"type": "number"}}),
+                    &["count", "mean", "stddev"],
+                ),
+                "count": counter(),
+                "observed": {"type": "number"},
+                "deviation": nullable("number"),
+            },
+            "required": ["baseline", "count", "observed", "deviation"],
+            "additionalProperties": {"description": "the group's value of each --by key", "type": "string"},
+        }),
+        Kind::Scanner => record(
+            json!({
+                "ip": {"type": "string"},
+                "not_found": counter(),
+                "distinct_paths": counter(),
+                "window_start": timestamp(),
+                "window_end": timestamp(),
+                "sample_paths": {"type": "array", "items": {"type": "string"}},
+            }),
+            &["ip", "not_found", "distinct_paths", "window_start", "window_end", "sample_paths"],
+        ),
+        Kind::AuthClient => record(
+            json!({
+                "ip": {"type": "string"},
+                "failures": counter(),
+                "logins_after_failure": counter(),
+                "users": {"description": "distinct usernames tried", "type": "integer", "minimum": 0},
+                "sample_users": {"type": "array", "items": {"type": "string"}},
+                "first_failure": timestamp(),
+                "last_failure": timestamp(),
+                "flagged": {"description": "more failures than --max-failures", "type": "boolean"},
+            }),
+            &["ip", "failures", "logins_after_failure", "users", "sample_users", "first_failure", "last_failure", "flagged"],
+        ),
+        Kind::AuthUser => record(
+            json!({
+                "user": {"type": "string"},
+                "failures": counter(),
+                "logins_after_failure": counter(),
+                "ips": {"description": "distinct clients that tried it", "type": "integer", "minimum": 0},
+            }),
+            &["user", "failures", "logins_after_failure", "ips"],
+        ),
+        Kind::Check => record(
+            json!({
+                "rule": {"type": "string"},
+                "passed": {"type": "boolean"},
+                "threshold": {"description": "the threshold's key in the rules file, e.g. max_ratio", "type": "string"},
+                "limit": {"type": "number", "minimum": 0},
+                "observed": {"description": "null when the rule had nothing to measure", "type": ["number", "null"]},
+                "group": {"description": "where the observed value was worst, e.g. ip=10.0.0.7", "type": "string"},
+                "window_start": timestamp(),
+            }),
+            &["rule", "passed", "threshold", "limit", "observed"],
+        ),
+        Kind::Cardinality => json!({
+            "type": "object",
+            "properties": {"distinct": counter(), "error": {"description": "one standard error", "type": "integer", "minimum": 0}},
+            "required": ["distinct", "error"],
+            "additionalProperties": {"description": "the group's value of each --by key", "type": "string"},
+        }),
+        Kind::Top => {
+            let share = json!({"description": "percent of all matches", "type": "number", "minimum": 0, "maximum": 100});
+            json!({
+                "type": "object",
+                "properties": {
+                    "count": counter(),
+                    "error": {"description": "with --approximate, how much more the count may be than the true one", "type": "integer", "minimum": 0},
+                    "share": share,
+                    "baseline_count": counter(),
+                    "baseline_share": share,
+                    "delta": {"description": "percentage points", "type": "number"},
+                    "ratio": nullable("number"),
+                    "new": {"type": "boolean"},
+                    "examples": {"description": "lines picked at random from the value's, in input order", "type": "array", "items": {"type": "string"}},
+                },
+                "required": ["count", "share"],
+                "dependentRequired": {"baseline_count": ["baseline_share", "delta", "ratio", "new"]},
+                "additionalProperties": {"description": "the value of the key", "type": "string"},
+            })
+        }
+        Kind::Report => {
+            record(
+                json!({
+                    "section": {"enum": ["days", "pages", "referrers", "agents", "peak_hour"]},
+                    "key": {
+                        "description": "the day in --tz, - for requests without a timestamp; the page, referring domain or user-agent family; the hour, e.g. 14:00",
+                        "type": "string",
+                    },
+                    "requests": counter(),
+                    "bytes": counter(),
+                    "errors": {"description": "5xx responses", "type": "integer", "minimum": 0},
+                    "error_rate": {"description": "percent of the day's requests that were 5xx responses, null without any", "type": ["number", "null"], "minimum": 0, "maximum": 100},
+                    "share": {"description": "percent of all requests, of those with a timestamp for the peak hour", "type": "number", "minimum": 0, "maximum": 100},
+                }),
+                &["section", "key", "requests"],
+            )
+        }
+        Kind::Bench => {
+            let seconds = json!({"type": "number", "minimum": 0});
+            let iteration = record(
+                json!({
+                    "iteration": {"type": "integer", "minimum": 1},
+                    "lines": counter(),
+                    "bytes": counter(),
+                    "matched": counter(),
+                    "wall_secs": seconds,
+                    "lines_per_sec": seconds,
+                    "mb_per_sec": seconds,
+                    "read_secs": seconds,
+                    "parse_secs": seconds,
+                    "match_secs": seconds,
+                }),
+                &[
+                    "iteration", "lines", "bytes", "matched", "wall_secs", "lines_per_sec", "mb_per_sec", "read_secs",
+                    "parse_secs", "match_secs",
+                ],
+            );
+            record(json!({"iterations": {"type": "array", "items": iteration}}), &["iterations"])
+        }
+        Kind::Batch => record(
+            json!({
+                "job": {"type": "string"},
+                "lines": {"description": "lines read in the one pass, the same for every job", "type": "integer", "minimum": 0},
+                "matched": counter(),
+                "file": {"type": "string"},
+            }),
+            &["job", "lines", "matched", "file"],
+        ),
+        Kind::Field => {
+            let kinds: Vec<&str> = crate::filter::FILTER_KINDS.iter().map(|kind| kind.kind).collect();
+            record(
+                json!({
+                    "field": {"type": "string"},
+                    "filter": nullable("string"),
+                    "kind": {"enum": kinds.into_iter().map(Value::from).chain([Value::Null]).collect::<Vec<_>>()},
+                }),
+                &["field", "filter", "kind"],
+            )
+        }
+        Kind::Operators => {
+            let strings = json!({"type": "array", "items": {"type": "string"}});
+            record(
+                json!({"kind": {"type": "string"}, "options": strings, "operators": strings, "example": {"type": "string"}}),
+                &["kind", "options", "operators", "example"],
+            )
+        }
+        Kind::SelfTest => {
+            let platform = record(
+                json!({
+                    "os": {"type": "string"},
+                    "arch": {"type": "string"},
+                    "family": {"type": "string"},
+                    "endian": {"enum": ["little", "big"]},
+                    "pointer_width": {"type": "integer"},
+                }),
+                &["os", "arch", "family", "endian", "pointer_width"],
+            );
+            let case = record(
+                json!({
+                    "suite": {"enum": ["parse", "filter", "output"]},
+                    "case": {"type": "string"},
+                    "passed": {"type": "boolean"},
+                    "detail": {"type": "string", "description": "what went wrong, for a case that failed"},
+                }),
+                &["suite", "case", "passed"],
+            );
+            record(
+                json!({
+                    "version": {"type": "string"},
+                    "platform": platform,
+                    "passed": counter(),
+                    "failed": counter(),
+                    "cases": {"type": "array", "items": case},
+                }),
+                &["version", "platform", "passed", "failed", "cases"],
+            )
+        }
+        Kind::Diagnostic => json!({
+            "type": "object",
+            "properties": {
+                "type": {"enum": ["error", "warning", "notice", "summary", "breakdown", "snapshot", "schema"]},
+                "kind": {"type": "string", "description": "what went wrong, for errors, warnings and notices"},
+            },
+            "required": ["type"],
+            "allOf": [
+                {
+                    "if": {"properties": {"type": {"const": "summary"}}},
+                    "then": record(
+                        json!({
+                            "type": {"const": "summary"},
+                            "file": {"type": "string"},
+                            "lines": counter(),
+                            "bytes": counter(),
+                            "compressed_bytes": {
+                                "type": "integer",
+                                "minimum": 0,
+                                "description": "size on disk of a gzipped file, whose bytes are decompressed",
+                            },
+                            "matched": counter(),
+                            "matched_bytes": counter(),
+                            "missing_bytes": counter(),
+                            "malformed": counter(),
+                            "wrapper_errors": {"type": "integer", "minimum": 0, "description": "of the malformed lines, those that weren't --unwrap records"},
+                            "malformed_requests": {
+                                "type": "integer",
+                                "minimum": 0,
+                                "description": "entries whose request line isn't METHOD PATH PROTOCOL, kept as raw_request",
+                            },
+                            "regressions": counter(),
+                            "invalid_numbers": {
+                                "type": "integer",
+                                "minimum": 0,
+                                "description": "entries with a numeric field that isn't a number, kept without it",
+                            },
+                            "duplicates": {
+                                "type": "integer",
+                                "minimum": 0,
+                                "description": "lines skipped as duplicates of one just before them",
+                            },
+                            "rejected": {"type": "object", "additionalProperties": counter()},
+                            "hostname_clients": counter(),
+                            "interrupted": {"type": "boolean"},
+                        }),
+                        &["type", "file", "lines", "bytes", "matched", "malformed", "malformed_requests", "regressions"],
+                    ),
+                },
+                {
+                    "if": {"properties": {"type": {"const": "breakdown"}}},
+                    "then": {"$ref": "#/$defs/breakdown"},
+                },
+                {
+                    "if": {"properties": {"type": {"const": "snapshot"}}},
+                    "then": {"$ref": "#/$defs/snapshot"},
+                },
+                {
+                    "if": {"properties": {"type": {"const": "schema"}}},
+                    "then": {"$ref": "#/$defs/schema"},
+                },
+            ],
+            "$defs": {
+                "breakdown": {"type": "object", "properties": body(Kind::Breakdown)["properties"], "required": ["by", "counts"]},
+                "snapshot": record(
+                    json!({
+                        "type": {"const": "snapshot"},
+                        "sequence": {"type": "integer", "minimum": 1},
+                        "lines": counter(),
+                        "elapsed_secs": {"type": "number", "minimum": 0},
+                        "partial": {"const": true},
+                        "interrupted": {"type": "boolean"},
+                        "result": {
+                            "description": "the records of `stats -o json`, `histogram -o json` or `top -o json` so far",
+                            "type": "array",
+                            "items": {"type": "object"},
+                        },
+                    }),
+                    &["type", "sequence", "lines", "elapsed_secs", "partial", "result"],
+                ),
+                "schema": record(
+                    json!({"type": {"const": "schema"}, "schema_version": {"const": SCHEMA_VERSION}}),
+                    &["type", "schema_version"],
+                ),
+            },
+        }),
+    }
+}
+
+/// The JSON Schema document of one output kind.
+pub fn document(kind: Kind) -> Value {
+    let mut document = Map::new();
+    document.insert("$schema".to_string(), json!(META_SCHEMA));
+    document.insert("$id".to_string(), json!(format!("urn:log-parser:schema:{}:{}", SCHEMA_VERSION, kind.name())));
+    document.insert("title".to_string(), json!(format!("log-parser {} output, schema version {}", kind.name(), SCHEMA_VERSION)));
+    let Value::Object(body) = body(kind) else {
+        unreachable!("documents are objects");
+    };
+    document.extend(body);
+    Value::Object(document)
+}
+
+/// One kind's document, or with `None` every document keyed by kind.
+pub fn render(kind: Option<Kind>) -> Result<String, String> {
+    let value = match kind {
+        Some(kind) => document(kind),
+        None => Value::Object(Kind::value_variants().iter().map(|&kind| (kind.name().to_string(), document(kind))).collect()),
+    };
+    serde_json::to_string_pretty(&value).map(|json| json + "\n").map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::RunSummary;
+    use crate::entry::{Entry, FieldValue, Match, Provenance};
+    use crate::scanners::{Detector, Thresholds};
+    use crate::stats::{Breakdown, BreakdownKey, Count, MatchedBytes};
+    use chrono::{DateTime, TimeDelta};
+    use std::{path::PathBuf, time::Duration};
+
+    /// Checks a record's top-level keys against its document: every required key is there, and
+    /// no key the document doesn't know about, unless it takes other properties.
+    fn assert_conforms(kind: Kind, record: impl Serialize) {
+        let document = document(kind);
+        let document = match kind {
+            Kind::Diagnostic => &document["allOf"][0]["then"],
+            _ => &document,
+        };
+        let Value::Object(record) = serde_json::to_value(record).unwrap() else {
+            panic!("{} records are objects", kind.name());
+        };
+        for key in document["required"].as_array().unwrap() {
+            assert!(record.contains_key(key.as_str().unwrap()), "{} record lacks {}", kind.name(), key);
+        }
+        if document["additionalProperties"] == json!(false) {
+            for key in record.keys() {
+                assert!(document["properties"].get(key).is_some(), "{} document lacks {}", kind.name(), key);
+            }
+        }
+    }
+
+    #[test]
+    fn records_have_the_fields_their_documents_describe() {
+        let entry = || Entry {
+            client: Some("203.0.113.9".parse().unwrap()),
+            timestamp: Some(DateTime::parse_from_rfc3339("2023-02-12T14:00:00Z").unwrap()),
+            status_code: Some(http::StatusCode::NOT_FOUND),
+            duration: Some(crate::duration::HumanDuration(Duration::from_millis(5))),
+            invalid: BTreeMap::from([("bytes".to_string(), "-5".to_string())]),
+            extras: BTreeMap::from([("request_time".to_string(), FieldValue::Number(0.005))]),
+            ..Entry::default()
+        };
+        let found = Match {
+            line: String::new(),
+            entry: entry(),
+            provenance: Provenance { source_file: PathBuf::from("access.log"), line_no: 1, byte_offset: 0 },
+        };
+        assert_conforms(Kind::Entry, super::super::Located::from(&found));
+        let mut dictionary = crate::output::dictionary::Dictionary::new(
+            vec!["user_agent".to_string()],
+            crate::budget::MemoryBudget::new(1 << 20).account("--dict-fields", ""),
+        );
+        dictionary.encode("user_agent", &mut json!("curl/8.0")).unwrap();
+        assert_conforms(Kind::Dictionary, serde_json::from_str::<Value>(&dictionary.to_json()).unwrap());
+        assert_conforms(Kind::Count, Count { matched: 1, bytes: Some(MatchedBytes::default()) });
+        assert_conforms(Kind::Breakdown, Breakdown::new(BreakdownKey::Day, None));
+        let figures = Figures { min: 1.0, max: 1.0, mean: 1.0, p50: 1.0, p90: 1.0, p95: 1.0, p99: 1.0 };
+        assert_conforms(Kind::Stats, MetricSummary { count: 1, missing: 0, figures: Some(figures) });
+        let classes = ClassCounts { success: 0, redirect: 0, client_error: 1, server_error: 0, other: 0 };
+        assert_conforms(
+            Kind::Histogram,
+            HistogramBucket { bucket: String::new(), total: 1, classes: Some(classes), server_error_pct: Some(None) },
+        );
+        let baseline = Baseline { count: 2, mean: 1.0, stddev: 0.5 };
+        let group = BTreeMap::from([("path".to_string(), "/".to_string())]);
+        assert_conforms(Kind::Anomaly, Anomaly { group, baseline, count: 1, observed: 3.0, deviation: 4.0 });
+        let mut detector = Detector::new(Thresholds { not_found: 1, distinct_paths: 1, window: TimeDelta::seconds(60) });
+        detector.add(&entry());
+        assert_conforms(Kind::Scanner, &detector.finish()[0]);
+        let mut audit = crate::auth::Audit::new(TimeDelta::seconds(60));
+        audit.add(&Entry { user: Some("admin".to_string()), status_code: Some(http::StatusCode::UNAUTHORIZED), ..entry() });
+        let report = audit.finish(0, crate::order::Order::default());
+        assert_conforms(Kind::AuthClient, &report.clients[0]);
+        assert_conforms(Kind::AuthUser, &report.users[0]);
+        let outcome = crate::rules::Outcome {
+            rule: "abuse".to_string(),
+            passed: false,
+            threshold: "max_count".to_string(),
+            limit: 1.0,
+            observed: Some(2.0),
+            group: Some("ip=203.0.113.9".to_string()),
+            window_start: entry().timestamp,
+        };
+        assert_conforms(Kind::Check, outcome);
+        let group = BTreeMap::from([("path".to_string(), "/".to_string())]);
+        assert_conforms(Kind::Cardinality, Cardinality { group, distinct: 1, error: 0 });
+        let group = BTreeMap::from([("path".to_string(), "/".to_string())]);
+        let change = ShareChange { baseline_count: 0, baseline_share: 0.0, delta: 50.0, ratio: None, new: true };
+        assert_conforms(Kind::Top, Top { group, count: 1, error: None, share: 50.0, change: Some(change), examples: None });
+        let row = ReportRow {
+            section: "days",
+            key: "2023-02-12".to_string(),
+            requests: 1,
+            bytes: Some(0),
+            errors: Some(0),
+            error_rate: Some(Some(0.0)),
+            share: None,
+        };
+        assert_conforms(Kind::Report, row);
+        let times = crate::bench::StageTimes::default();
+        let iterations = [crate::bench::Iteration::new(1, 1, 1, 1, Duration::from_secs(1), times)];
+        assert_conforms(Kind::Bench, BenchReport { iterations: &iterations });
+        let job = BatchJob { job: "errors".to_string(), lines: 8, matched: 1, file: "errors.json".to_string() };
+        assert_conforms(Kind::Batch, job);
+        assert_conforms(Kind::Field, Field { field: "ip".to_string(), filter: Some("--ip".to_string()), kind: Some("ip") });
+        assert_conforms(Kind::Operators, &crate::filter::FILTER_KINDS[0]);
+        assert_conforms(Kind::SelfTest, crate::self_test::run());
+        let summary = RunSummary {
+            file: PathBuf::from("access.log"),
+            lines: 1,
+            bytes: 1,
+            compressed_bytes: Some(1),
+            matched: 1,
+            matched_bytes: Some(MatchedBytes::default()),
+            malformed: 0,
+            wrapper_errors: Some(0),
+            malformed_requests: 0,
+            regressions: 0,
+            invalid_numbers: 0,
+            duplicates: None,
+            rejected: Some(BTreeMap::new()),
+            hostname_clients: Some(0),
+            interrupted: true,
+        };
+        let mut summary = serde_json::to_value(summary).unwrap();
+        summary["type"] = json!("summary");
+        assert_conforms(Kind::Diagnostic, summary);
+    }
+
+    #[test]
+    fn the_preamble_names_the_version() {
+        assert_eq!(preamble(), format!("{{\"schema_version\":{}}}\n", SCHEMA_VERSION));
+        assert_eq!(document(Kind::Count)["$id"], json!(format!("urn:log-parser:schema:{}:count", SCHEMA_VERSION)));
+    }
+}
@@ -0,0 +1,324 @@
+use clap::ValueEnum;
+use http::StatusCode;
+use std::{ffi::OsString, sync::OnceLock};
+
+// Every colored piece of output asks this module how to paint it: filter lines by status class,
+// the header row of aligned tables, histogram bars and --annotate's markers. What color each
+// element gets is the theme's: --theme picks a palette and the config file's `[theme]` section
+// can override single elements with a named ANSI color such as `bright-red` or a 256-color
+// index. Whether to color at all is --color's: `auto` colors a terminal unless NO_COLOR is
+// set, and CLICOLOR_FORCE colors whatever stdout is.
+
+/// Whether output is colored.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorMode {
+    /// Color a terminal that understands ANSI escapes, unless NO_COLOR is set; CLICOLOR_FORCE
+    /// colors pipes and files too
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Theme {
+    /// Colors for a dark background
+    #[default]
+    Dark,
+    /// Darker colors that read on a light background
+    Light,
+    /// Bold and dim text only, no colors
+    Mono,
+}
+
+impl Theme {
+    fn palette(self) -> [Paint; ELEMENTS.len()] {
+        let plain = Paint::default();
+        let color = |color| Paint { color: Some(color), ..plain };
+        let bold = Paint { bold: true, ..plain };
+        let dim = Paint { dim: true, ..plain };
+        match self {
+            Theme::Dark => [
+                color(Color::Named(2)),
+                color(Color::Named(6)),
+                color(Color::Named(3)),
+                Paint { bold: true, ..color(Color::Bright(1)) },
+                bold,
+                color(Color::Named(2)),
+                dim,
+                color(Color::Named(1)),
+                color(Color::Named(4)),
+            ],
+            Theme::Light => [
+                color(Color::Index(28)),
+                color(Color::Index(25)),
+                color(Color::Index(130)),
+                Paint { bold: true, ..color(Color::Index(124)) },
+                bold,
+                color(Color::Index(28)),
+                dim,
+                color(Color::Index(124)),
+                color(Color::Index(25)),
+            ],
+            Theme::Mono => [plain, plain, plain, bold, bold, bold, dim, bold, plain],
+        }
+    }
+}
+
+/// What gets painted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Element {
+    Status2xx,
+    Status3xx,
+    Status4xx,
+    Status5xx,
+    /// The header row of aligned tables.
+    Header,
+    /// --annotate's matching lines.
+    Highlight,
+    /// --annotate's lines that don't match.
+    Miss,
+    /// --annotate's lines that can't be parsed.
+    Malformed,
+    /// Histogram bars without status classes.
+    Bar,
+}
+
+const ELEMENTS: [(Element, &str); 9] = [
+    (Element::Status2xx, "status_2xx"),
+    (Element::Status3xx, "status_3xx"),
+    (Element::Status4xx, "status_4xx"),
+    (Element::Status5xx, "status_5xx"),
+    (Element::Header, "header"),
+    (Element::Highlight, "highlight"),
+    (Element::Miss, "miss"),
+    (Element::Malformed, "malformed"),
+    (Element::Bar, "bar"),
+];
+
+impl Element {
+    /// The element of a status code's class, none for 1xx or without one.
+    pub fn status(status: Option<StatusCode>) -> Option<Element> {
+        match status?.as_u16() {
+            200..=299 => Some(Element::Status2xx),
+            300..=399 => Some(Element::Status3xx),
+            400..=499 => Some(Element::Status4xx),
+            500..=599 => Some(Element::Status5xx),
+            _ => None,
+        }
+    }
+
+    fn index(self) -> usize {
+        ELEMENTS.iter().position(|&(element, _)| element == self).unwrap_or_default()
+    }
+}
+
+const COLOR_NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Color {
+    /// One of the eight ANSI colors, in COLOR_NAMES order.
+    Named(u8),
+    /// Its bright variant.
+    Bright(u8),
+    /// One of the 256 colors of xterm's palette.
+    Index(u8),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Paint {
+    color: Option<Color>,
+    bold: bool,
+    dim: bool,
+}
+
+impl Paint {
+    /// The SGR parameters, e.g. `1;31`, empty when the paint changes nothing.
+    fn parameters(self) -> String {
+        let color = self.color.map(|color| match color {
+            Color::Named(n) => format!("3{}", n),
+            Color::Bright(n) => format!("9{}", n),
+            Color::Index(n) => format!("38;5;{}", n),
+        });
+        [self.bold.then(|| "1".to_string()), self.dim.then(|| "2".to_string()), color].into_iter().flatten().collect::<Vec<_>>().join(";")
+    }
+}
+
+/// How output is painted for the rest of the run.
+#[derive(Clone, Debug)]
+pub struct Style {
+    enabled: bool,
+    palette: [Paint; ELEMENTS.len()],
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style::new(Theme::default(), false)
+    }
+}
+
+impl Style {
+    pub fn new(theme: Theme, enabled: bool) -> Self {
+        Style { enabled, palette: theme.palette() }
+    }
+
+    /// Sets one element's color from the config file's `[theme]` section: `key` names the
+    /// element, e.g. `status_5xx`, and `value` is a color name such as `red` or `bright-red`,
+    /// `none`, or a 256-color index.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let Some(&(element, _)) = ELEMENTS.iter().find(|(_, name)| *name == key) else {
+            let names: Vec<&str> = ELEMENTS.iter().map(|(_, name)| *name).collect();
+            return Err(format!("unknown theme element {}; expected one of {}", key, names.join(", ")));
+        };
+        let (bright, name) = match value.strip_prefix("bright-") {
+            Some(name) => (true, name),
+            None => (false, value),
+        };
+        let color = match (value.parse::<u8>(), COLOR_NAMES.iter().position(|&known| known == name)) {
+            (Ok(index), _) => Some(Color::Index(index)),
+            _ if value == "none" => None,
+            (_, Some(n)) if bright => Some(Color::Bright(n as u8)),
+            (_, Some(n)) => Some(Color::Named(n as u8)),
+            (_, None) => {
+                return Err(format!(
+                    "{}: {:?} isn't a color; use one of {}, bright- before any of those, none, or 0 to 255",
+                    key,
+                    value,
+                    COLOR_NAMES.join(", ")
+                ))
+            }
+        };
+        self.palette[element.index()].color = color;
+        Ok(())
+    }
+
+    /// `text` in the element's colors, or as it is when output isn't colored.
+    pub fn paint(&self, element: Element, text: &str) -> String {
+        let parameters = self.palette[element.index()].parameters();
+        match self.enabled && !parameters.is_empty() && !text.is_empty() {
+            true => format!("\x1b[{}m{}\x1b[0m", parameters, text),
+            false => text.to_string(),
+        }
+    }
+
+    /// A log line in the color of its status class.
+    pub fn paint_status(&self, status: Option<StatusCode>, line: &str) -> String {
+        match Element::status(status) {
+            Some(element) => self.paint(element, line),
+            None => line.to_string(),
+        }
+    }
+}
+
+/// Whether `mode` colors output, given whether stdout is a terminal and the environment as
+/// `var` reads it. Older Windows consoles print escape codes literally unless virtual terminal
+/// processing is switched on, so there `auto` only colors terminals known to handle them.
+pub fn enabled(mode: ColorMode, terminal: bool, var: impl Fn(&str) -> Option<OsString>) -> bool {
+    let set = |name: &str| var(name).is_some_and(|value| !value.is_empty());
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto if set("NO_COLOR") => false,
+        ColorMode::Auto if set("CLICOLOR_FORCE") && var("CLICOLOR_FORCE").is_some_and(|force| force != "0") => true,
+        ColorMode::Auto if !terminal => false,
+        ColorMode::Auto => {
+            !cfg!(windows) || set("WT_SESSION") || set("ANSICON") || var("TERM").is_some_and(|term| term != "dumb")
+        }
+    }
+}
+
+static STYLE: OnceLock<Style> = OnceLock::new();
+
+/// Sets how output is painted for the rest of the run; only the first call has an effect.
+pub fn set_style(style: Style) {
+    let _ = STYLE.set(style);
+}
+
+/// How output is painted: not at all until `set_style`.
+pub fn style() -> &'static Style {
+    STYLE.get_or_init(Style::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5xx line, a 2xx line, a table header, a bar and an annotated match, painted.
+    fn sample(style: &Style) -> Vec<String> {
+        vec![
+            style.paint_status(StatusCode::from_u16(503).ok(), "GET /api 503"),
+            style.paint_status(StatusCode::from_u16(200).ok(), "GET / 200"),
+            style.paint(Element::Header, "bucket"),
+            style.paint(Element::Bar, "###"),
+            style.paint(Element::Highlight, "MATCH line"),
+        ]
+    }
+
+    #[test]
+    fn each_theme_paints_the_sample_its_own_way() {
+        assert_eq!(
+            sample(&Style::new(Theme::Dark, true)),
+            [
+                "\x1b[1;91mGET /api 503\x1b[0m",
+                "\x1b[32mGET / 200\x1b[0m",
+                "\x1b[1mbucket\x1b[0m",
+                "\x1b[34m###\x1b[0m",
+                "\x1b[32mMATCH line\x1b[0m",
+            ]
+        );
+        assert_eq!(
+            sample(&Style::new(Theme::Light, true)),
+            [
+                "\x1b[1;38;5;124mGET /api 503\x1b[0m",
+                "\x1b[38;5;28mGET / 200\x1b[0m",
+                "\x1b[1mbucket\x1b[0m",
+                "\x1b[38;5;25m###\x1b[0m",
+                "\x1b[38;5;28mMATCH line\x1b[0m",
+            ]
+        );
+        assert_eq!(
+            sample(&Style::new(Theme::Mono, true)),
+            ["\x1b[1mGET /api 503\x1b[0m", "GET / 200", "\x1b[1mbucket\x1b[0m", "###", "\x1b[1mMATCH line\x1b[0m"]
+        );
+        for theme in [Theme::Dark, Theme::Light, Theme::Mono] {
+            assert_eq!(sample(&Style::new(theme, false)), ["GET /api 503", "GET / 200", "bucket", "###", "MATCH line"]);
+        }
+        // A line without a status, or with a 1xx one, stays as it is.
+        let style = Style::new(Theme::Dark, true);
+        assert_eq!(style.paint_status(None, "-"), "-");
+        assert_eq!(style.paint_status(StatusCode::from_u16(101).ok(), "upgrade"), "upgrade");
+    }
+
+    #[test]
+    fn overrides_change_one_element_and_keep_its_weight() {
+        let mut style = Style::new(Theme::Dark, true);
+        style.set("status_5xx", "magenta").unwrap();
+        style.set("status_2xx", "208").unwrap();
+        style.set("header", "bright-cyan").unwrap();
+        style.set("bar", "none").unwrap();
+        assert_eq!(
+            sample(&style)[..4],
+            ["\x1b[1;35mGET /api 503\x1b[0m", "\x1b[38;5;208mGET / 200\x1b[0m", "\x1b[1;96mbucket\x1b[0m", "###"]
+        );
+        assert_eq!(
+            style.set("status_6xx", "red").unwrap_err(),
+            "unknown theme element status_6xx; expected one of status_2xx, status_3xx, status_4xx, status_5xx, header, highlight, miss, malformed, bar"
+        );
+        assert!(style.set("header", "256").unwrap_err().starts_with("header: \"256\" isn't a color;"));
+        assert!(style.set("header", "bright-none").is_err());
+    }
+
+    #[test]
+    fn no_color_wins_over_clicolor_force_which_wins_over_the_terminal_check() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| OsString::from(value))
+        };
+        assert!(!enabled(ColorMode::Auto, false, env(&[("TERM", "xterm")])));
+        assert!(enabled(ColorMode::Auto, false, env(&[("CLICOLOR_FORCE", "1")])));
+        assert!(!enabled(ColorMode::Auto, false, env(&[("CLICOLOR_FORCE", "0")])));
+        assert!(!enabled(ColorMode::Auto, true, env(&[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1"), ("TERM", "xterm")])));
+        assert!(enabled(ColorMode::Auto, true, env(&[("NO_COLOR", ""), ("TERM", "xterm")])));
+        assert!(enabled(ColorMode::Always, false, env(&[("NO_COLOR", "1")])));
+        assert!(!enabled(ColorMode::Never, true, env(&[("CLICOLOR_FORCE", "1")])));
+    }
+}
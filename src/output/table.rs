@@ -0,0 +1,409 @@
+use super::{
+    delimiter, escape,
+    style::{style, Element},
+    Delimiter,
+};
+use clap::ValueEnum;
+use std::sync::OnceLock;
+
+// Tables from stats, histogram, bench and the other reports. Unless aligned they're rows joined
+// with --delimiter, for cut, awk and spreadsheets. Aligned, as on a terminal by default, columns
+// are padded to line up: numbers to the right, text to the left, with values wider than
+// MAX_WIDTH shortened in the middle unless --no-truncate, and the header row is painted as the
+// theme says. Widths are in terminal columns, so wide characters count two and combining marks
+// and ANSI color codes nothing.
+
+/// Columns a value may take before it's shortened.
+pub const MAX_WIDTH: usize = 48;
+
+/// Spaces between aligned columns.
+const GAP: &str = "  ";
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Align {
+    /// Align tables printed to a terminal, unless --delimiter picks another separator than tab
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Layout {
+    pub aligned: bool,
+    pub truncate: bool,
+}
+
+static LAYOUT: OnceLock<Layout> = OnceLock::new();
+
+/// Sets how tables are laid out for the rest of the run; only the first call has an effect.
+pub fn set_layout(layout: Layout) {
+    let _ = LAYOUT.set(layout);
+}
+
+fn layout() -> Layout {
+    LAYOUT.get().copied().unwrap_or_default()
+}
+
+/// A header, if any, and rows of cells, with lines such as example log lines printed under a row
+/// as they are.
+#[derive(Default)]
+pub struct Table {
+    header: Option<Vec<String>>,
+    rows: Vec<Row>,
+}
+
+enum Row {
+    Cells(Vec<String>),
+    Note(String),
+}
+
+impl Table {
+    pub fn new<S: ToString>(header: &[S]) -> Self {
+        Table { header: Some(header.iter().map(S::to_string).collect()), rows: Vec::new() }
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(Row::Cells(cells));
+    }
+
+    /// A line printed under the last row, outside the columns.
+    pub fn note(&mut self, line: String) {
+        self.rows.push(Row::Note(line));
+    }
+
+    /// Laid out as --align and --no-truncate ask.
+    pub fn render(&self) -> String {
+        let layout = layout();
+        match layout.aligned {
+            true => self.aligned(layout.truncate),
+            false => self.delimited(delimiter()),
+        }
+    }
+
+    /// Each row joined with `delimiter`, however the run lays out tables.
+    pub fn delimited(&self, delimiter: &Delimiter) -> String {
+        let mut out = self.header.as_ref().map(|header| delimiter.row(header)).unwrap_or_default();
+        for row in &self.rows {
+            match row {
+                Row::Cells(cells) => out += &delimiter.row(cells),
+                Row::Note(line) => out += &format!("{}\n", line),
+            }
+        }
+        out
+    }
+
+    /// A GitHub-flavored Markdown table, numbers aligned right, with the notes as paragraphs
+    /// after it.
+    pub fn markdown(&self) -> String {
+        let numeric = self.numeric();
+        let row = |cells: &[String]| -> String {
+            let cells = (0..numeric.len()).map(|column| markdown_cell(cells.get(column).map_or("", String::as_str)));
+            format!("|{}\n", cells.map(|cell| format!(" {} |", cell)).collect::<String>())
+        };
+        // A Markdown table can't do without a header.
+        let mut out = row(self.header.as_deref().unwrap_or_default());
+        out += &format!("|{}\n", numeric.iter().map(|&numeric| if numeric { " ---: |" } else { " --- |" }).collect::<String>());
+        let mut notes = String::new();
+        for line in &self.rows {
+            match line {
+                Row::Cells(cells) => out += &row(cells),
+                Row::Note(line) => notes += &format!("\n{}\n", markdown_cell(line.trim())),
+            }
+        }
+        out + &notes
+    }
+
+    /// An HTML table, numbers aligned right, with the notes in rows of their own across the
+    /// columns. Every cell is escaped.
+    pub fn html(&self) -> String {
+        let numeric = self.numeric();
+        let row = |tag: &str, cells: &[String]| -> String {
+            let cells = (0..numeric.len()).map(|column| {
+                let class = if numeric[column] { " class=\"number\"" } else { "" };
+                format!("<{0}{1}>{2}</{0}>", tag, class, escape::html(cells.get(column).map_or("", String::as_str)))
+            });
+            format!("<tr>{}</tr>\n", cells.collect::<String>())
+        };
+        let mut out = String::from("<table>\n");
+        if let Some(header) = &self.header {
+            out += &format!("<thead>\n{}</thead>\n", row("th", header));
+        }
+        out += "<tbody>\n";
+        for line in &self.rows {
+            match line {
+                Row::Cells(cells) => out += &row("td", cells),
+                Row::Note(line) => {
+                    let columns = numeric.len().max(1);
+                    out += &format!("<tr class=\"note\"><td colspan=\"{}\">{}</td></tr>\n", columns, escape::html(line.trim()))
+                }
+            }
+        }
+        out + "</tbody>\n</table>\n"
+    }
+
+    /// Whether each column holds numbers, and so lines up to the right.
+    fn numeric(&self) -> Vec<bool> {
+        let body: Vec<&Vec<String>> = self
+            .rows
+            .iter()
+            .filter_map(|row| match row {
+                Row::Cells(cells) => Some(cells),
+                Row::Note(_) => None,
+            })
+            .collect();
+        let columns = self.header.iter().chain(body.iter().copied()).map(Vec::len).max().unwrap_or(0);
+        (0..columns)
+            .map(|column| {
+                let mut cells = body.iter().filter_map(|row| row.get(column)).filter(|cell| cell.as_str() != "-").peekable();
+                cells.peek().is_some() && cells.all(|cell| is_number(cell))
+            })
+            .collect()
+    }
+
+    fn aligned(&self, truncate: bool) -> String {
+        let numeric = self.numeric();
+        let columns = numeric.len();
+        let fit = |cell: &str| match truncate {
+            true => shorten(cell, MAX_WIDTH),
+            false => cell.to_string(),
+        };
+        // Notes stay as they are, outside the columns.
+        let lines: Vec<Result<Vec<String>, &str>> = self
+            .header
+            .iter()
+            .map(|header| Ok(header.iter().map(|cell| fit(cell)).collect()))
+            .chain(self.rows.iter().map(|row| match row {
+                Row::Cells(cells) => Ok(cells.iter().map(|cell| fit(cell)).collect()),
+                Row::Note(line) => Err(line.as_str()),
+            }))
+            .collect();
+        let mut widths = vec![0; columns];
+        for row in lines.iter().flatten() {
+            for (column, cell) in row.iter().enumerate() {
+                widths[column] = widths[column].max(width(cell));
+            }
+        }
+
+        let mut out = String::new();
+        for (index, line) in lines.iter().enumerate() {
+            let row = match line {
+                Ok(row) => row,
+                Err(note) => {
+                    out += &format!("{}\n", note);
+                    continue;
+                }
+            };
+            let mut line = String::new();
+            for (column, cell) in row.iter().enumerate() {
+                if column > 0 {
+                    line += GAP;
+                }
+                let padding = " ".repeat(widths[column] - width(cell));
+                match numeric[column] {
+                    true => line += &format!("{}{}", padding, cell),
+                    false => line += &format!("{}{}", cell, padding),
+                }
+            }
+            match index == 0 && self.header.is_some() {
+                true => out += &style().paint(Element::Header, line.trim_end()),
+                false => out += line.trim_end(),
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// `text` as a Markdown table cell: on one line, with the characters that would end the cell or
+/// format its text escaped.
+fn markdown_cell(text: &str) -> String {
+    let mut cell = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '|' | '*' | '_' | '`' | '[' | ']' | '<' | '>' => {
+                cell.push('\\');
+                cell.push(c);
+            }
+            '\n' | '\r' => cell.push(' '),
+            c => cell.push(c),
+        }
+    }
+    cell
+}
+
+/// Counts, measurements, percentages and `±` errors.
+fn is_number(cell: &str) -> bool {
+    let cell = cell.strip_prefix('±').unwrap_or(cell);
+    let cell = cell.strip_suffix('%').unwrap_or(cell);
+    cell.parse::<f64>().is_ok()
+}
+
+/// Columns `c` takes on a terminal: two for East Asian wide and fullwidth characters and emoji,
+/// none for combining marks, zero-width characters and controls, otherwise one.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0..=0x1f | 0x7f..=0x9f => 0,
+        0x0300..=0x036f | 0x0483..=0x0489 | 0x0591..=0x05bd | 0x0610..=0x061a | 0x064b..=0x065f => 0,
+        0x1ab0..=0x1aff | 0x1dc0..=0x1dff | 0x200b..=0x200f | 0x20d0..=0x20ff | 0xfe00..=0xfe0f | 0xfe20..=0xfe2f => 0,
+        0x1100..=0x115f | 0x2e80..=0x303e | 0x3041..=0x33ff | 0x3400..=0x4dbf | 0x4e00..=0x9fff | 0xa000..=0xa4cf => 2,
+        0xac00..=0xd7a3 | 0xf900..=0xfaff | 0xfe30..=0xfe4f | 0xff00..=0xff60 | 0xffe0..=0xffe6 => 2,
+        0x1f300..=0x1f64f | 0x1f900..=0x1f9ff | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+/// `text` split into pieces that stay whole: ANSI escape sequences, and characters along with
+/// the combining marks after them. Each comes with its width.
+fn pieces(text: &str) -> Vec<(&str, usize)> {
+    let mut pieces: Vec<(&str, usize)> = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        if c == '\x1b' && chars.peek().is_some_and(|&(_, next)| next == '[') {
+            // CSI: ESC [, parameters, then a final byte from @ to ~.
+            chars.next();
+            end += 1;
+            for (i, c) in chars.by_ref() {
+                end = i + c.len_utf8();
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+            pieces.push((&text[start..end], 0));
+            continue;
+        }
+        let width = char_width(c);
+        match pieces.last_mut() {
+            Some((piece, piece_width)) if width == 0 && !piece.starts_with('\x1b') && *piece_width > 0 => {
+                let piece_start = piece.as_ptr() as usize - text.as_ptr() as usize;
+                *piece = &text[piece_start..end];
+            }
+            _ => pieces.push((&text[start..end], width)),
+        }
+    }
+    pieces
+}
+
+/// Columns `text` takes on a terminal.
+pub fn width(text: &str) -> usize {
+    pieces(text).iter().map(|&(_, width)| width).sum()
+}
+
+/// `text` if it fits in `max` columns, otherwise its start and end around an ellipsis. Color
+/// codes from the part left out are kept, so colors still switch off.
+pub fn shorten(text: &str, max: usize) -> String {
+    if width(text) <= max {
+        return text.to_string();
+    }
+    let pieces = pieces(text);
+    let budget = max.saturating_sub(1);
+    let (head_budget, mut tail_budget) = (budget - budget / 2, budget / 2);
+    let (mut head, mut used) = (0, 0);
+    while head < pieces.len() && used + pieces[head].1 <= head_budget {
+        used += pieces[head].1;
+        head += 1;
+    }
+    let mut tail = pieces.len();
+    while tail > head && pieces[tail - 1].1 <= tail_budget {
+        tail_budget -= pieces[tail - 1].1;
+        tail -= 1;
+    }
+    let mut out: String = pieces[..head].iter().map(|&(piece, _)| piece).collect();
+    out.push('…');
+    out.extend(pieces[head..tail].iter().filter(|&&(piece, _)| piece.starts_with('\x1b')).map(|&(piece, _)| piece));
+    out.extend(pieces[tail..].iter().map(|&(piece, _)| piece));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        let mut table = Table::new(&["path", "count", "error"]);
+        table.row(vec!["/".to_string(), "1200".to_string(), "±3".to_string()]);
+        table.row(vec!["/api/login".to_string(), "7".to_string(), "-".to_string()]);
+        table.note("  an example".to_string());
+        table
+    }
+
+    #[test]
+    fn numbers_align_right_and_text_left() {
+        assert_eq!(
+            table().aligned(true),
+            "path        count  error\n\
+             /            1200     ±3\n\
+             /api/login      7      -\n  an example\n"
+        );
+        assert_eq!(table().delimited(&Delimiter::default()), "path\tcount\terror\n/\t1200\t±3\n/api/login\t7\t-\n  an example\n");
+    }
+
+    #[test]
+    fn markdown_tables_escape_their_cells() {
+        assert_eq!(
+            table().markdown(),
+            "| path | count | error |\n| --- | ---: | ---: |\n| / | 1200 | ±3 |\n| /api/login | 7 | - |\n\nan example\n"
+        );
+        let mut table = Table::new(&["agent", "share"]);
+        table.row(vec!["a|b *c* [d](e) <f>\nnext_line".to_string(), "12.5%".to_string()]);
+        table.row(vec!["short".to_string()]);
+        assert_eq!(
+            table.markdown(),
+            "| agent | share |\n| --- | ---: |\n| a\\|b \\*c\\* \\[d\\](e) \\<f\\> next\\_line | 12.5% |\n| short |  |\n"
+        );
+    }
+
+    #[test]
+    fn html_tables_escape_their_cells() {
+        assert_eq!(
+            table().html(),
+            "<table>\n<thead>\n<tr><th>path</th><th class=\"number\">count</th><th class=\"number\">error</th></tr>\n</thead>\n\
+             <tbody>\n<tr><td>/</td><td class=\"number\">1200</td><td class=\"number\">±3</td></tr>\n\
+             <tr><td>/api/login</td><td class=\"number\">7</td><td class=\"number\">-</td></tr>\n\
+             <tr class=\"note\"><td colspan=\"3\">an example</td></tr>\n</tbody>\n</table>\n"
+        );
+        let mut table = Table::default();
+        table.row(vec!["<script>alert('x')</script>".to_string(), "a & b".to_string()]);
+        assert_eq!(
+            table.html(),
+            "<table>\n<tbody>\n<tr><td>&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;</td><td>a &amp; b</td></tr>\n\
+             </tbody>\n</table>\n"
+        );
+    }
+
+    #[test]
+    fn widths_count_terminal_columns() {
+        assert_eq!(width("/検索"), 5);
+        assert_eq!(width("cafe\u{301}"), 4);
+        assert_eq!(width("\x1b[31m500\x1b[0m"), 3);
+        assert_eq!(width("👍 ok"), 5);
+
+        let mut table = Table::new(&["agent", "count"]);
+        table.row(vec!["日本語".to_string(), "1".to_string()]);
+        table.row(vec!["e\u{301}te\u{301}".to_string(), "22".to_string()]);
+        table.row(vec!["\x1b[1mbold\x1b[0m".to_string(), "3".to_string()]);
+        assert_eq!(
+            table.aligned(true),
+            "agent   count\n日本語      1\ne\u{301}te\u{301}        22\n\x1b[1mbold\x1b[0m        3\n"
+        );
+    }
+
+    #[test]
+    fn long_values_are_shortened_in_the_middle() {
+        assert_eq!(shorten("/very/long/path/here", 20), "/very/long/path/here");
+        assert_eq!(shorten("/very/long/path/here", 12), "/very/…/here");
+        assert_eq!(width(&shorten("/a/日本語日本語/b", 10)), 10);
+        // A wide character that doesn't fit leaves the value a column short.
+        assert_eq!(shorten("/日本語日本語", 8), "/日…語");
+        // Combining marks stay with their letter, and color codes survive.
+        assert_eq!(shorten("e\u{301}e\u{301}e\u{301}e\u{301}e\u{301}", 4), "e\u{301}e\u{301}…e\u{301}");
+        assert_eq!(shorten("\x1b[31mabcdefghij\x1b[0m", 5), "\x1b[31mab…ij\x1b[0m");
+
+        let mut table = Table::new(&["path"]);
+        let long = format!("/{}", "x".repeat(MAX_WIDTH + 10));
+        table.row(vec![long.clone()]);
+        assert_eq!(width(table.aligned(true).lines().nth(1).unwrap()), MAX_WIDTH);
+        assert_eq!(table.aligned(false).lines().nth(1).unwrap(), long);
+    }
+}
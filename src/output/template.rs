@@ -0,0 +1,144 @@
+use super::escape::Escape;
+use crate::entry::Match;
+use crate::registry;
+use serde_json::Value;
+
+// `filter --format` prints each match through a template such as `{ip} {path:sh}`. A placeholder
+// names a field as JSON output has it, or by an alias the registry knows, such as `{ua}`,
+// optionally followed by how its value is escaped; `{{` and `}}` are literal braces. Templates
+// are compiled once, so a bad modifier fails before any input is read. Missing values are `-`,
+// escaped like any other, except in JSON, where they're null.
+
+enum Piece {
+    Text(String),
+    Field { name: String, escape: Escape },
+}
+
+pub struct Template {
+    pieces: Vec<Piece>,
+    /// Whether the fields were checked against the first match.
+    checked: bool,
+}
+
+impl Template {
+    /// Compiles `template`, escaping placeholders without a modifier with `default`.
+    pub fn compile(template: &str, default: Escape) -> Result<Self, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' | '}' if chars.peek() == Some(&c) => {
+                    chars.next();
+                    text.push(c);
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(format!("unclosed placeholder {{{}", placeholder)),
+                        }
+                    }
+                    let (name, escape) = match placeholder.split_once(':') {
+                        Some((name, modifier)) => (name, Escape::modifier(modifier)?),
+                        None => (placeholder.as_str(), default),
+                    };
+                    if name.is_empty() || name.contains('{') {
+                        return Err(format!("{{{}}} doesn't name a field", placeholder));
+                    }
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    pieces.push(Piece::Field { name: registry::canonical(name), escape });
+                }
+                '}' => return Err("unmatched }; write }} for a literal brace".to_string()),
+                _ => text.push(c),
+            }
+        }
+        pieces.push(Piece::Text(text));
+        pieces.retain(|piece| !matches!(piece, Piece::Text(text) if text.is_empty()));
+        Ok(Template { pieces, checked: false })
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.pieces
+            .iter()
+            .filter_map(|piece| match piece {
+                Piece::Field { name, .. } => Some(name.clone()),
+                Piece::Text(_) => None,
+            })
+            .collect()
+    }
+
+    pub fn render(&mut self, found: &Match) -> Result<String, String> {
+        let values = super::fields(found)?;
+        if !self.checked {
+            super::check_fields(&self.names(), &values)?;
+            self.checked = true;
+        }
+        let mut out = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Field { name, escape: Escape::Json } => out.push_str(&values.get(name).unwrap_or(&Value::Null).to_string()),
+                Piece::Field { name, escape } => {
+                    out.push_str(&escape.apply(&super::text(values.get(name)).unwrap_or_else(|| "-".to_string())))
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::{Entry, Provenance};
+    use std::path::PathBuf;
+
+    fn found(user_agent: &str) -> Match {
+        Match {
+            line: String::new(),
+            entry: Entry {
+                path: Some("/search?q=<b>".to_string()),
+                status_code: Some(http::StatusCode::OK),
+                user_agent: Some(user_agent.to_string()),
+                ..Entry::default()
+            },
+            provenance: Provenance { source_file: PathBuf::from("access.log"), line_no: 3, byte_offset: 0 },
+        }
+    }
+
+    fn render(template: &str, default: Escape, user_agent: &str) -> Result<String, String> {
+        Template::compile(template, default)?.render(&found(user_agent))
+    }
+
+    #[test]
+    fn placeholders_are_escaped_by_their_modifier() {
+        let agent = "$(rm -rf /) 'x'";
+        assert_eq!(render("{status_code} {user_agent}", Escape::Raw, agent).unwrap(), "200 $(rm -rf /) 'x'");
+        assert_eq!(render("agent={user_agent:sh}", Escape::Raw, agent).unwrap(), "agent='$(rm -rf /) '\\''x'\\'''");
+        assert_eq!(render("<td>{path:html}</td>", Escape::Raw, agent).unwrap(), "<td>/search?q=&lt;b&gt;</td>");
+        assert_eq!(
+            render("{{\"ua\": {user_agent:json}, \"status\": {status_code:json}, \"ip\": {ip:json}}}", Escape::Raw, "\"x\"").unwrap(),
+            "{\"ua\": \"\\\"x\\\"\", \"status\": 200, \"ip\": null}"
+        );
+        assert_eq!(render("{ip}:{line_no}", Escape::Raw, agent).unwrap(), "-:3");
+    }
+
+    #[test]
+    fn a_default_escaping_applies_where_none_is_given() {
+        assert_eq!(render("{user_agent} {path:raw}", Escape::Html, "<script>").unwrap(), "&lt;script&gt; /search?q=<b>");
+        assert_eq!(render("{ip} {status_code}", Escape::Sh, "").unwrap(), "'-' '200'");
+    }
+
+    #[test]
+    fn bad_templates_fail_to_compile() {
+        assert!(Template::compile("{path:url}", Escape::Raw).is_err_and(|e| e.contains(":url")));
+        assert!(Template::compile("{path", Escape::Raw).is_err_and(|e| e.contains("unclosed")));
+        assert!(Template::compile("{} {:sh}", Escape::Raw).is_err());
+        assert!(Template::compile("a } b", Escape::Raw).is_err_and(|e| e.contains("}}")));
+        assert!(Template::compile("{{literal}}", Escape::Raw).is_ok());
+        assert!(render("{referer}", Escape::Raw, "").is_err_and(|e| e.contains("Unknown field referer")));
+    }
+}
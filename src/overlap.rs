@@ -0,0 +1,98 @@
+use crate::diagnostics::{Error, Notice, Reporter};
+use crate::input::{LineReader, ReadLine, Source};
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+};
+
+// --dedupe-overlap: when logrotate copies a log and then truncates it, lines written in between
+// can end up both at the end of one file and at the start of the next, and would be counted
+// twice. Before reading, the last lines of each file are compared with the first lines of the
+// file after it, and only a run of lines that repeats the previous file's end exactly, line for
+// line, is skipped. Finding the last lines reads each file but the last an extra time.
+
+/// Lines compared at each boundary between files.
+pub const WINDOW: usize = 1000;
+
+/// A hash per line; `None` for lines too long to read, which never count as repeated.
+fn hash(line: std::io::Result<ReadLine>) -> Result<Option<u64>, std::io::Error> {
+    Ok(match line? {
+        ReadLine::Line { mut text, .. } => {
+            text.trim_line_ending();
+            let mut hasher = DefaultHasher::new();
+            text.into_string().hash(&mut hasher);
+            Some(hasher.finish())
+        }
+        ReadLine::TooLong { .. } => None,
+    })
+}
+
+/// The hash of each line of `source`.
+fn hashes(source: &Source, max_line_length: usize) -> Result<impl Iterator<Item = Result<Option<u64>, Error>> + '_, Error> {
+    let io_error = |e: std::io::Error| Error::Io { file: source.path().to_path_buf(), message: e.to_string() };
+    let reader = source.open().map_err(io_error)?;
+    Ok(LineReader::new(reader, max_line_length).map(move |line| hash(line).map_err(io_error)))
+}
+
+/// The length of the longest run of lines that ends `tail` and starts `head`.
+pub fn overlap(tail: &[Option<u64>], head: &[Option<u64>]) -> usize {
+    (1..=tail.len().min(head.len()))
+        .rev()
+        .find(|&length| {
+            let repeated = &head[..length];
+            repeated.iter().all(Option::is_some) && tail[tail.len() - length..] == *repeated
+        })
+        .unwrap_or(0)
+}
+
+/// How many lines to skip at the start of each file, keyed by path; files without an overlap,
+/// and files given more than once, are left out. Each skip is reported as a notice.
+pub fn find(sources: &[Source], max_line_length: usize, reporter: &Reporter) -> Result<HashMap<PathBuf, usize>, Error> {
+    let mut skips = HashMap::new();
+    for pair in sources.windows(2) {
+        let (previous, next) = (&pair[0], &pair[1]);
+        if sources.iter().filter(|source| source.path() == next.path()).count() > 1 {
+            continue;
+        }
+        let mut tail = VecDeque::with_capacity(WINDOW + 1);
+        for line in hashes(previous, max_line_length)? {
+            tail.push_back(line?);
+            if tail.len() > WINDOW {
+                tail.pop_front();
+            }
+        }
+        let head = hashes(next, max_line_length)?.take(WINDOW).collect::<Result<Vec<_>, _>>()?;
+        let repeated = overlap(tail.make_contiguous(), &head);
+        if repeated > 0 {
+            reporter.notice(Notice::Deduplicated {
+                file: next.path().to_path_buf(),
+                previous: previous.path().to_path_buf(),
+                lines: repeated,
+            });
+            skips.insert(next.path().to_path_buf(), repeated);
+        }
+    }
+    Ok(skips)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(values: &[u64]) -> Vec<Option<u64>> {
+        values.iter().map(|&value| Some(value)).collect()
+    }
+
+    #[test]
+    fn only_a_repeated_end_overlaps() {
+        assert_eq!(overlap(&lines(&[1, 2, 3, 4]), &lines(&[3, 4, 5])), 2);
+        assert_eq!(overlap(&lines(&[1, 2, 3, 4]), &lines(&[1, 2, 3, 4])), 4);
+        assert_eq!(overlap(&lines(&[1, 2, 3, 4]), &lines(&[4, 4, 5])), 1);
+        // Lines from the middle of the previous file aren't its end.
+        assert_eq!(overlap(&lines(&[1, 2, 3, 4]), &lines(&[2, 3, 5])), 0);
+        assert_eq!(overlap(&lines(&[1, 2]), &lines(&[5, 1, 2])), 0);
+        assert_eq!(overlap(&[], &lines(&[1])), 0);
+        assert_eq!(overlap(&[Some(1), None], &[Some(1), None]), 0);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::duration::HumanDuration;
+use crate::entry::{Client, Entry, FieldValue};
+use crate::tls::TlsVersion;
+use chrono::DateTime;
+
+// https://docs.aws.amazon.com/elasticloadbalancing/latest/application/load-balancer-access-logs.html
+// Columns are space separated; some are quoted and may contain spaces. Failed
+// connections log `-` for the target and `-1` for the processing times.
+const COLUMNS: &[&str] = &[
+    "type",
+    "time",
+    "elb",
+    "client",
+    "target",
+    "request_processing_time",
+    "target_processing_time",
+    "response_processing_time",
+    "elb_status_code",
+    "target_status_code",
+    "received_bytes",
+    "sent_bytes",
+    "request",
+    "user_agent",
+    "ssl_cipher",
+    "ssl_protocol",
+    "target_group_arn",
+    "trace_id",
+    "domain_name",
+    "chosen_cert_arn",
+    "matched_rule_priority",
+    "request_creation_time",
+    "actions_executed",
+    "redirect_url",
+    "error_reason",
+    "target_port_list",
+    "target_status_code_list",
+    "classification",
+    "classification_reason",
+    "conn_trace_id",
+];
+
+const REQUIRED_COLUMNS: usize = 14;
+
+/// Splits on spaces, keeping double-quoted columns (with `\"` escapes) together.
+fn split_columns(line: &str) -> Result<Vec<&str>, String> {
+    let mut columns = Vec::new();
+    let mut rest = line.trim_end();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut end = None;
+            let mut escaped = false;
+            for (i, c) in quoted.char_indices() {
+                match c {
+                    '\\' if !escaped => escaped = true,
+                    '"' if !escaped => {
+                        end = Some(i);
+                        break;
+                    }
+                    _ => escaped = false,
+                }
+            }
+            let end = end.ok_or_else(|| format!("Unterminated quoted column in: {}", line))?;
+            columns.push(&quoted[..end]);
+            rest = quoted[end + 1..].trim_start_matches(' ');
+        } else {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            columns.push(&rest[..end]);
+            rest = rest[end..].trim_start_matches(' ');
+        }
+    }
+    Ok(columns)
+}
+
+fn split_host_port(value: &str) -> Option<(&str, &str)> {
+    let (host, port) = value.rsplit_once(':')?;
+    Some((host.trim_start_matches('[').trim_end_matches(']'), port))
+}
+
+/// Processing times are `-1` when the load balancer couldn't dispatch the request.
+fn timing(value: &str) -> Option<FieldValue> {
+    FieldValue::parse(value).filter(|v| v.as_number() != Some(-1.0))
+}
+
+fn set_request(entry: &mut Entry, request: &str) {
+    entry.set_request(request);
+    // ALB logs the absolute URL; keep only the path and query so path filters behave like other formats.
+    if let Some(uri) = entry.path.as_deref().and_then(|p| p.parse::<http::Uri>().ok()) {
+        if let Some(authority) = uri.authority() {
+            entry.extras.insert("host".to_string(), FieldValue::Text(authority.to_string()));
+        }
+        entry.scheme = uri.scheme_str().map(str::to_string);
+        entry.port = uri.port_u16();
+        entry.path = uri.path_and_query().map(|p| p.to_string());
+    }
+}
+
+/// The fields entries can have, in column order: `parse` files some columns under entry fields
+/// of their own and keeps the rest under their column names.
+pub fn fields() -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    for &column in COLUMNS {
+        match column {
+            "time" => fields.push("timestamp"),
+            "client" => fields.extend(["ip", "client_port"]),
+            "elb_status_code" => fields.extend(["status_code", "elb_status_code"]),
+            "sent_bytes" => fields.push("bytes"),
+            "request" => fields.extend(["method", "path", "protocol", "host", "scheme", "port"]),
+            "ssl_protocol" => fields.extend(["ssl_protocol", "tls_protocol"]),
+            column => fields.push(column),
+        }
+    }
+    fields.push("duration");
+    fields
+}
+
+pub fn parse(line: &str) -> Result<Entry, String> {
+    let columns = split_columns(line)?;
+    if columns.len() < REQUIRED_COLUMNS {
+        return Err(format!(
+            "Expected at least {} ALB columns, found {}: {}",
+            REQUIRED_COLUMNS,
+            columns.len(),
+            line
+        ));
+    }
+    let mut entry = Entry::default();
+    for (&name, &value) in COLUMNS.iter().zip(columns.iter()) {
+        match name {
+            "time" => {
+                entry.timestamp =
+                    Some(DateTime::parse_from_rfc3339(value).map_err(|_| format!("Invalid ALB time: {}", value))?)
+            }
+            "client" => {
+                let (ip, port) = split_host_port(value).ok_or_else(|| format!("Invalid ALB client: {}", value))?;
+                entry.client = Some(Client::Ip(ip.parse().map_err(|_| format!("Invalid ALB client ip: {}", ip))?));
+                entry.extras.extend(FieldValue::parse(port).map(|p| ("client_port".to_string(), p)));
+            }
+            "request_processing_time" | "target_processing_time" | "response_processing_time" => {
+                entry.extras.extend(timing(value).map(|v| (name.to_string(), v)));
+            }
+            "elb_status_code" => {
+                entry.status_code = match value {
+                    "-" => None,
+                    _ => Some(value.parse().map_err(|_| format!("Invalid ALB status: {}", value))?),
+                };
+                entry.extras.extend(FieldValue::parse(value).map(|v| (name.to_string(), v)));
+            }
+            "sent_bytes" => {
+                if value != "-" {
+                    entry.set_bytes(value, || format!("Invalid ALB sent_bytes: {}", value))?;
+                }
+            }
+            "request" => set_request(&mut entry, value),
+            "user_agent" => entry.user_agent = (value != "-" && !value.is_empty()).then(|| value.to_string()),
+            "ssl_protocol" => {
+                entry.tls_protocol = TlsVersion::parse_logged(value);
+                entry.extras.extend(FieldValue::parse(value).map(|v| (name.to_string(), v)));
+            }
+            _ => entry.extras.extend(FieldValue::parse(value).map(|v| (name.to_string(), v))),
+        }
+    }
+    let timings: Option<Vec<f64>> = ["request_processing_time", "target_processing_time", "response_processing_time"]
+        .iter()
+        .map(|name| entry.extras.get(*name).and_then(FieldValue::as_number))
+        .collect();
+    entry.duration = timings.and_then(|t| HumanDuration::from_secs_f64(t.iter().sum()));
+    Ok(entry)
+}
@@ -0,0 +1,233 @@
+use crate::entry::{Entry, FieldValue};
+use crate::log_time;
+use crate::registry;
+use chrono::{DateTime, FixedOffset};
+use std::{borrow::Cow, sync::OnceLock};
+
+// CSV with a header row, such as a SIEM export or the rows of `--delimiter , filter --header
+// --fields ...`. A column named for an entry field, in any case, is read into it: ip (or
+// client), timestamp, method, path, protocol, user, status (or status_code), bytes, referrer
+// and user_agent. --csv-map names the column of a field an export calls something else, e.g.
+// `ip=src_addr`. Every other column is a format-specific field, for --field and --where.
+// Values are read as RFC 4180 has them: one in double quotes may hold commas, line breaks and
+// quotes, doubled, so a record whose quote is still open at the end of a line goes on to the
+// next. `-` and empty values are missing. Timestamps are RFC 3339, or the access log's
+// `12/Feb/2023:14:03:45 +0000` with or without its brackets. The header row may repeat, as
+// when exports are concatenated.
+
+/// The entry fields a column can be read into.
+pub const FIELDS: &[&str] =
+    &["ip", "timestamp", "method", "path", "protocol", "user", "status_code", "bytes", "referrer", "user_agent"];
+
+/// Lines a quoted value may span before its record is given up as malformed.
+const MAX_RECORD_LINES: usize = 100;
+
+static MAP: OnceLock<Vec<(&'static str, String)>> = OnceLock::new();
+
+/// Sets the --csv-map pairs for the rest of the run; only the first call has an effect.
+pub fn set_map(map: Vec<(&'static str, String)>) {
+    let _ = MAP.set(map);
+}
+
+/// Parses a --csv-map pair, `FIELD=COLUMN`.
+pub fn parse_mapping(s: &str) -> Result<(&'static str, String), String> {
+    let (field, column) = s.split_once('=').ok_or_else(|| format!("expected FIELD=COLUMN, e.g. ip=src_addr, got {:?}", s))?;
+    let field = canonical(&registry::canonical(field.trim()))
+        .ok_or_else(|| format!("unknown field {:?}; expected one of {}", field, FIELDS.join(", ")))?;
+    Ok((field, column.trim().to_string()))
+}
+
+/// The entry field a column name stands for, e.g. `status_code` for `Status`.
+fn canonical(name: &str) -> Option<&'static str> {
+    match name.to_ascii_lowercase().as_str() {
+        "client" => Some("ip"),
+        "status" => Some("status_code"),
+        name => FIELDS.iter().find(|field| **field == name).copied(),
+    }
+}
+
+enum Column {
+    Field(&'static str),
+    Other(String),
+}
+
+#[derive(Default)]
+pub struct CsvParser {
+    header: Vec<String>,
+    columns: Vec<Column>,
+    /// The lines so far of a record whose quoted value is still open, and how many there are.
+    pending: String,
+    pending_lines: usize,
+}
+
+impl CsvParser {
+    /// The fields entries can have under the header row, `None` before it.
+    pub fn fields(&self) -> Option<Vec<String>> {
+        if self.columns.is_empty() {
+            return None;
+        }
+        let name = |column: &Column| match column {
+            Column::Field(field) => field.to_string(),
+            Column::Other(name) => name.clone(),
+        };
+        Some(self.columns.iter().map(name).collect())
+    }
+
+    pub fn parse(&mut self, line: &str) -> Result<Option<Entry>, String> {
+        if self.pending_lines == 0 && line.is_empty() {
+            return Ok(None);
+        }
+        let record = match self.pending_lines {
+            0 => Cow::Borrowed(line),
+            _ => {
+                self.pending.push('\n');
+                self.pending.push_str(line);
+                Cow::Owned(std::mem::take(&mut self.pending))
+            }
+        };
+        let Some(values) = split(&record) else {
+            self.pending_lines += 1;
+            if self.pending_lines == MAX_RECORD_LINES {
+                self.pending_lines = 0;
+                return Err(format!("CSV quoted value still open after {} lines", MAX_RECORD_LINES));
+            }
+            self.pending = record.into_owned();
+            return Ok(None);
+        };
+        self.pending_lines = 0;
+        if self.columns.is_empty() {
+            self.read_header(values);
+            return Ok(None);
+        }
+        if values == self.header {
+            return Ok(None);
+        }
+        if values.len() != self.columns.len() {
+            return Err(format!("Expected {} CSV columns, found {}: {}", self.columns.len(), values.len(), record));
+        }
+
+        let mut entry = Entry::default();
+        for (column, value) in self.columns.iter().zip(values) {
+            let field = match column {
+                Column::Field(_) if value.is_empty() || value == "-" => continue,
+                Column::Field(field) => *field,
+                Column::Other(name) => {
+                    if let Some(value) = FieldValue::parse(&value) {
+                        entry.extras.insert(name.clone(), value);
+                    }
+                    continue;
+                }
+            };
+            let invalid = || format!("Invalid CSV {}: {}", field, value);
+            match field {
+                "ip" => entry.client = Some(value.parse().map_err(|_| invalid())?),
+                "timestamp" => entry.timestamp = Some(timestamp(&value).ok_or_else(invalid)?),
+                "status_code" => entry.status_code = Some(value.parse().map_err(|_| invalid())?),
+                "bytes" => entry.set_bytes(&value, invalid)?,
+                "method" => entry.method = Some(value),
+                "path" => entry.path = Some(value),
+                "protocol" => entry.protocol = Some(value),
+                "user" => entry.user = Some(value),
+                "referrer" => entry.referrer = Some(value),
+                "user_agent" => entry.user_agent = Some(value),
+                _ => unreachable!("{} isn't in FIELDS", field),
+            }
+        }
+        Ok(Some(entry))
+    }
+
+    /// Takes the header row's names, a UTF-8 byte order mark off the first, as the columns.
+    fn read_header(&mut self, mut names: Vec<String>) {
+        if let Some(first) = names.first_mut() {
+            *first = first.trim_start_matches('\u{feff}').to_string();
+        }
+        let map = MAP.get().map(Vec::as_slice).unwrap_or_default();
+        self.columns = names
+            .iter()
+            .map(|name| {
+                let mapped = map.iter().find(|(_, column)| column.eq_ignore_ascii_case(name.trim())).map(|(field, _)| *field);
+                // A column named for a field that --csv-map reads from another column stays as it is.
+                let named = canonical(name.trim()).filter(|field| !map.iter().any(|(mapped, _)| mapped == field));
+                match mapped.or(named) {
+                    Some(field) => Column::Field(field),
+                    None => Column::Other(name.trim().to_string()),
+                }
+            })
+            .collect();
+        self.header = names;
+    }
+}
+
+fn timestamp(value: &str) -> Option<DateTime<FixedOffset>> {
+    match value.contains('/') {
+        true => log_time::parse(value.trim_start_matches('[').trim_end_matches(']')).ok(),
+        false => DateTime::parse_from_rfc3339(value).ok(),
+    }
+}
+
+/// The values of a record, `None` while a quoted value is still open at its end.
+fn split(record: &str) -> Option<Vec<String>> {
+    let mut values = Vec::new();
+    let mut value = String::new();
+    let (mut quoted, mut at_start) = (false, true);
+    let mut chars = record.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                value.push('"');
+            }
+            (true, '"') => quoted = false,
+            (false, '"') if at_start => quoted = true,
+            (false, ',') => {
+                values.push(std::mem::take(&mut value));
+                at_start = true;
+                continue;
+            }
+            (_, c) => value.push(c),
+        }
+        at_start = false;
+    }
+    if quoted {
+        return None;
+    }
+    values.push(value);
+    Some(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_values_hold_commas_quotes_and_line_breaks() {
+        let split = |record: &str| split(record).map(|values| values.join("|"));
+        assert_eq!(split("a,,b").as_deref(), Some("a||b"));
+        assert_eq!(split(r#""a,b","say ""hi""",c"#).as_deref(), Some(r#"a,b|say "hi"|c"#));
+        assert_eq!(split(r#""""#).as_deref(), Some(""));
+        assert_eq!(split(r#"a"b,c"#).as_deref(), Some(r#"a"b|c"#));
+        assert_eq!(split("\"one\ntwo\",x").as_deref(), Some("one\ntwo|x"));
+        assert_eq!(split("x,\"open"), None);
+    }
+
+    #[test]
+    fn records_are_read_under_the_header() {
+        let mut parser = CsvParser::default();
+        let lines = [
+            "\u{feff}Client,Status,time,Agent,latency_ms",
+            r#"192.0.2.1,404,[12/Feb/2023:14:03:45 +0000],"Mozilla/5.0 (X11, Linux)",12"#,
+            r#"192.0.2.2,200,2023-02-12T14:04:00Z,"two"#,
+            r#"lines",-"#,
+            "Client,Status,time,Agent,latency_ms",
+        ];
+        let entries: Vec<Entry> = lines.iter().filter_map(|line| parser.parse(line).unwrap()).collect();
+        assert_eq!(parser.fields().unwrap(), ["ip", "status_code", "time", "Agent", "latency_ms"]);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].status_code.map(|status| status.as_u16()), Some(404));
+        assert_eq!(entries[0].extras["Agent"].as_text(), "Mozilla/5.0 (X11, Linux)");
+        assert_eq!(entries[0].extras["time"].as_text(), "[12/Feb/2023:14:03:45 +0000]");
+        assert_eq!(entries[1].extras["Agent"].as_text(), "two\nlines");
+        assert!(!entries[1].extras.contains_key("latency_ms"));
+        assert!(parser.parse("192.0.2.3,200").unwrap_err().starts_with("Expected 5 CSV columns, found 2"));
+    }
+}
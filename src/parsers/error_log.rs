@@ -0,0 +1,137 @@
+use crate::entry::{Client, Entry, FieldValue};
+use chrono::NaiveDateTime;
+use std::net::IpAddr;
+
+// nginx: 2023/02/12 14:34:20 [error] 1234#0: *5678 connect() failed (111: Connection refused) while connecting
+//        to upstream, client: 1.2.3.4, server: example.com, request: "GET /x HTTP/1.1", host: "example.com"
+// Apache 2.4: [Sun Feb 12 14:34:20.123456 2023] [proxy:error] [pid 1234:tid 5678] [client 1.2.3.4:5678] AH00957: ...
+// Apache 2.2: [Sun Feb 12 14:34:20 2023] [error] [client 1.2.3.4] File does not exist: /var/www/favicon.ico
+// Neither format records a UTC offset, so timestamps are interpreted as UTC.
+
+/// The fields nginx entries can have. The context after the message is kept under its own keys,
+/// of which `server`, `upstream` and `host` are the usual ones besides `client` and `request`.
+pub const NGINX_FIELDS: &[&str] =
+    &["timestamp", "severity", "pid", "tid", "connection", "message", "ip", "server", "method", "path", "protocol", "upstream", "host", "referrer"];
+
+/// The fields Apache entries can have.
+pub const APACHE_FIELDS: &[&str] = &["timestamp", "module", "severity", "pid", "tid", "ip", "referrer", "error_code", "message"];
+
+fn bracketed(rest: &str) -> Option<(&str, &str)> {
+    let inner = rest.trim_start().strip_prefix('[')?;
+    let end = inner.find(']')?;
+    Some((&inner[..end], &inner[end + 1..]))
+}
+
+fn insert_text(entry: &mut Entry, name: &str, value: &str) {
+    if let Some(value) = FieldValue::parse(value) {
+        entry.extras.insert(name.to_string(), value);
+    }
+}
+
+fn parse_client(value: &str) -> Option<IpAddr> {
+    value.parse().ok().or_else(|| {
+        let (ip, _port) = value.rsplit_once(':')?;
+        ip.trim_start_matches('[').trim_end_matches(']').parse().ok()
+    })
+}
+
+/// Splits nginx's trailing `, key: value` context (values may be quoted) from the message.
+fn nginx_context(message: &str) -> (&str, Vec<(&str, &str)>) {
+    let Some(start) = [", client: ", ", server: "].iter().filter_map(|key| message.find(key)).min() else {
+        return (message, Vec::new());
+    };
+    let mut pairs = Vec::new();
+    let mut rest = &message[start + 2..];
+    while let Some((key, value)) = rest.split_once(": ") {
+        let (value, tail) = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => value.split_once(", ").map_or((value, ""), |(v, t)| (v, t)),
+        };
+        pairs.push((key, value));
+        rest = tail.trim_start_matches(", ");
+        if rest.is_empty() {
+            break;
+        }
+    }
+    (&message[..start], pairs)
+}
+
+pub fn parse_nginx(line: &str) -> Result<Entry, String> {
+    let invalid = || format!("Invalid nginx error log line: {}", line);
+    let mut entry = Entry::default();
+    let timestamp = line.get(..19).ok_or_else(invalid)?;
+    let timestamp = NaiveDateTime::parse_from_str(timestamp, "%Y/%m/%d %H:%M:%S").map_err(|_| invalid())?;
+    entry.timestamp = Some(timestamp.and_utc().fixed_offset());
+
+    let (level, rest) = bracketed(&line[19..]).ok_or_else(invalid)?;
+    entry.severity = Some(level.parse()?);
+    let (process, rest) = rest.trim_start().split_once(": ").ok_or_else(invalid)?;
+    let (pid, tid) = process.split_once('#').ok_or_else(invalid)?;
+    insert_text(&mut entry, "pid", pid);
+    insert_text(&mut entry, "tid", tid);
+    let rest = match rest.strip_prefix('*').and_then(|r| r.split_once(' ')) {
+        Some((connection, rest)) => {
+            insert_text(&mut entry, "connection", connection);
+            rest
+        }
+        None => rest,
+    };
+
+    let (message, context) = nginx_context(rest);
+    insert_text(&mut entry, "message", message);
+    for (key, value) in context {
+        match key {
+            "client" => entry.client = parse_client(value).map(Client::Ip),
+            "request" => entry.set_request(value),
+            "referrer" => entry.referrer = (value != "-").then(|| value.to_string()),
+            _ => insert_text(&mut entry, key, value),
+        }
+    }
+    Ok(entry)
+}
+
+pub fn parse_apache(line: &str) -> Result<Entry, String> {
+    let invalid = || format!("Invalid Apache error log line: {}", line);
+    let mut entry = Entry::default();
+    let (timestamp, mut rest) = bracketed(line).ok_or_else(invalid)?;
+    let timestamp = NaiveDateTime::parse_from_str(timestamp, "%a %b %d %H:%M:%S%.f %Y").map_err(|_| invalid())?;
+    entry.timestamp = Some(timestamp.and_utc().fixed_offset());
+
+    while let Some((tag, tail)) = bracketed(rest) {
+        if let Some(pid) = tag.strip_prefix("pid ") {
+            let (pid, tid) = pid.split_once(":tid ").unwrap_or((pid, "-"));
+            insert_text(&mut entry, "pid", pid);
+            insert_text(&mut entry, "tid", tid);
+        } else if let Some(client) = tag.strip_prefix("client ") {
+            entry.client = parse_client(client).map(Client::Ip);
+        } else if let Some((module, level)) = tag.split_once(':') {
+            insert_text(&mut entry, "module", module);
+            entry.severity = Some(level.parse()?);
+        } else if entry.severity.is_none() {
+            entry.severity = Some(tag.parse()?);
+        } else {
+            break;
+        }
+        rest = tail;
+    }
+    if entry.severity.is_none() {
+        return Err(invalid());
+    }
+
+    let message = rest.trim();
+    let message = match message.rsplit_once(", referer: ") {
+        Some((message, referrer)) => {
+            entry.referrer = Some(referrer.to_string());
+            message
+        }
+        None => message,
+    };
+    if let Some((code, _)) = message.split_once(": ").filter(|(code, _)| code.starts_with("AH")) {
+        insert_text(&mut entry, "error_code", code);
+    }
+    insert_text(&mut entry, "message", message);
+    Ok(entry)
+}
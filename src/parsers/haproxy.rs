@@ -0,0 +1,111 @@
+use super::syslog;
+use crate::duration::HumanDuration;
+use crate::entry::{Client, Entry, FieldValue};
+use crate::log_time;
+use chrono::NaiveDateTime;
+
+// HAProxy HTTP log format, optionally preceded by a syslog prefix:
+// 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 {1wt.eu} {} "GET /index.html HTTP/1.1"
+// Timers are in milliseconds and are -1 when the corresponding phase never happened.
+const TIMERS: &[&str] = &["tq", "tw", "tc", "tr", "tt"];
+const CONNECTIONS: &[&str] = &["actconn", "feconn", "beconn", "srv_conn", "retries"];
+const QUEUES: &[&str] = &["srv_queue", "backend_queue"];
+
+fn next_word<'a>(rest: &mut &'a str, name: &str) -> Result<&'a str, String> {
+    let trimmed = rest.trim_start_matches(' ');
+    if trimmed.is_empty() {
+        return Err(format!("Missing HAProxy {}", name));
+    }
+    let end = trimmed.find(' ').unwrap_or(trimmed.len());
+    *rest = &trimmed[end..];
+    Ok(&trimmed[..end])
+}
+
+fn delimited<'a>(rest: &mut &'a str, open: char, close: char) -> Option<&'a str> {
+    let trimmed = rest.trim_start_matches(' ');
+    let inner = trimmed.strip_prefix(open)?;
+    let end = inner.find(close)?;
+    *rest = &inner[end + close.len_utf8()..];
+    Some(&inner[..end])
+}
+
+fn insert_split(entry: &mut Entry, names: &[&str], value: &str) -> Result<(), String> {
+    let values: Vec<&str> = value.split('/').collect();
+    if values.len() != names.len() {
+        return Err(format!("Expected {} values in {}", names.join("/"), value));
+    }
+    for (name, value) in names.iter().zip(values) {
+        if let Some(value) = FieldValue::parse(value).filter(|v| v.as_number() != Some(-1.0)) {
+            entry.extras.insert(name.to_string(), value);
+        }
+    }
+    Ok(())
+}
+
+fn insert_text(entry: &mut Entry, name: &str, value: &str) {
+    if let Some(value) = FieldValue::parse(value) {
+        entry.extras.insert(name.to_string(), value);
+    }
+}
+
+/// The fields entries can have, in the order the line gives them.
+pub fn fields() -> Vec<&'static str> {
+    let mut fields = vec!["ip", "client_port", "timestamp", "frontend", "backend", "server"];
+    fields.extend(TIMERS);
+    fields.extend(["duration", "status_code", "bytes", "captured_request_cookie", "captured_response_cookie", "termination_state"]);
+    fields.extend(CONNECTIONS);
+    fields.extend(QUEUES);
+    fields.extend(["captured_request_headers", "captured_response_headers", "method", "path", "protocol"]);
+    fields
+}
+
+pub fn parse(line: &str) -> Result<Entry, String> {
+    let mut rest = syslog::strip(line).map_or(line, |syslog| syslog.payload);
+    let mut entry = Entry::default();
+
+    let client = next_word(&mut rest, "client")?;
+    let (ip, port) = client
+        .rsplit_once(':')
+        .ok_or_else(|| format!("Invalid HAProxy client: {}", client))?;
+    entry.client = Some(Client::Ip(ip.parse().map_err(|_| format!("Invalid HAProxy client ip: {}", ip))?));
+    insert_text(&mut entry, "client_port", port);
+
+    // HAProxy logs local time without an offset; it is interpreted as UTC.
+    let date = delimited(&mut rest, '[', ']').ok_or("Missing HAProxy accept date")?;
+    let invalid = || format!("Invalid HAProxy accept date: {}", date);
+    let date = log_time::numeric(date).ok_or_else(invalid)?;
+    let date = NaiveDateTime::parse_from_str(&date, "%d/%m/%Y:%H:%M:%S%.f").map_err(|_| invalid())?;
+    entry.timestamp = Some(date.and_utc().fixed_offset());
+
+    insert_text(&mut entry, "frontend", next_word(&mut rest, "frontend")?);
+    let backend = next_word(&mut rest, "backend")?;
+    let (backend, server) = backend.split_once('/').unwrap_or((backend, "-"));
+    insert_text(&mut entry, "backend", backend);
+    insert_text(&mut entry, "server", server);
+    insert_split(&mut entry, TIMERS, next_word(&mut rest, "timers")?)?;
+    entry.duration = entry.extras.get("tr").and_then(FieldValue::as_number).and_then(HumanDuration::from_millis_f64);
+
+    let status = next_word(&mut rest, "status")?;
+    entry.status_code = match status {
+        "-1" => None,
+        _ => Some(status.parse().map_err(|_| format!("Invalid HAProxy status: {}", status))?),
+    };
+    let bytes = next_word(&mut rest, "bytes_read")?;
+    entry.set_bytes(bytes.trim_start_matches('+'), || format!("Invalid HAProxy bytes: {}", bytes))?;
+
+    insert_text(&mut entry, "captured_request_cookie", next_word(&mut rest, "request cookie")?);
+    insert_text(&mut entry, "captured_response_cookie", next_word(&mut rest, "response cookie")?);
+    insert_text(&mut entry, "termination_state", next_word(&mut rest, "termination state")?);
+    insert_split(&mut entry, CONNECTIONS, next_word(&mut rest, "connection counts")?.trim_start_matches('+'))?;
+    insert_split(&mut entry, QUEUES, next_word(&mut rest, "queues")?)?;
+
+    if let Some(headers) = delimited(&mut rest, '{', '}') {
+        insert_text(&mut entry, "captured_request_headers", headers);
+        if let Some(headers) = delimited(&mut rest, '{', '}') {
+            insert_text(&mut entry, "captured_response_headers", headers);
+        }
+    }
+    let request = delimited(&mut rest, '"', '"').ok_or("Missing HAProxy request line")?;
+    entry.set_request(request);
+    Ok(entry)
+}
@@ -0,0 +1,307 @@
+mod alb;
+mod csv;
+mod error_log;
+mod haproxy;
+mod syslog;
+mod unwrap;
+mod w3c;
+
+use crate::classify::{self, Derived};
+use crate::entry::{Entry, FieldValue};
+use crate::extract::Extractions;
+use crate::format::LogFormat;
+use crate::gap::{self, Gaps};
+use crate::normalize;
+use access_log_parser::{parse, LogEntry, LogType};
+use clap::ValueEnum;
+use csv::CsvParser;
+pub use csv::{parse_mapping as parse_csv_mapping, set_map as set_csv_map};
+use std::sync::{Arc, OnceLock};
+pub use unwrap::{Unwrapper, Wrapper};
+use w3c::W3cParser;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogKind {
+    #[default]
+    Combined,
+    Common,
+    /// AWS Application Load Balancer access logs
+    Alb,
+    /// HAProxy HTTP logs (`option httplog`)
+    Haproxy,
+    NginxError,
+    ApacheError,
+    /// W3C extended log format (CloudFront, IIS) with `#Fields:` headers
+    W3c,
+    /// CSV with a header row naming the columns, e.g. exported from a SIEM; see --csv-map
+    Csv,
+    /// Detected per file from its first lines
+    Auto,
+}
+
+const COMBINED_FORMAT: &str = r#"%h %l %u %t "%r" %>s %b "%{Referer}i" "%{User-agent}i""#;
+const COMMON_FORMAT: &str = r#"%h %l %u %t "%r" %>s %b"#;
+
+/// `access_log_parser` rejects referrers that aren't valid URIs, cuts values short at escaped
+/// quotes and keeps escaped backslashes doubled; those lines are parsed with the equivalent
+/// format string instead.
+fn parse_lenient(log_type: LogType, line: &str) -> Result<Entry, String> {
+    static COMBINED: OnceLock<LogFormat> = OnceLock::new();
+    static COMMON: OnceLock<LogFormat> = OnceLock::new();
+    let (cell, format) = match log_type {
+        LogType::CommonLog => (&COMMON, COMMON_FORMAT),
+        _ => (&COMBINED, COMBINED_FORMAT),
+    };
+    let fallback = || cell.get_or_init(|| format.parse().expect("built-in format is valid"));
+    if line.contains('\\') {
+        return fallback().parse(line);
+    }
+    match parse(log_type, line) {
+        Ok(LogEntry::CombinedLog(entry)) => Ok(entry.into()),
+        Ok(LogEntry::CommonLog(entry)) => Ok(entry.into()),
+        Ok(_) => unreachable!("access_log_parser returned another log type"),
+        Err(_) => fallback().parse(line),
+    }
+}
+
+/// Turns raw lines into entries for the selected log type.
+pub enum LineParser {
+    Kind(LogKind),
+    Custom(LogFormat),
+    W3c(W3cParser),
+    Csv(CsvParser),
+    /// Removes RFC3164/RFC5424 prefixes before handing the payload to the inner parser.
+    Syslog(Box<LineParser>),
+    /// Decodes container runtime records before handing the payload to the inner parser; with
+    /// the flag, entries without a timestamp take the record's.
+    Unwrap(Box<LineParser>, Unwrapper, bool),
+    /// Adds the fields --extract captures to the inner parser's entries.
+    Extract(Box<LineParser>, Arc<Extractions>),
+    /// Sets `path_normalized` on the inner parser's entries, lowercased with the flag.
+    NormalizePaths(Box<LineParser>, bool),
+    /// Adds `class` and `path_depth`, those of them a command reads, to the inner parser's entries.
+    Derive(Box<LineParser>, Derived),
+    /// Adds `gap` to the inner parser's entries, from the latest request of each client so far.
+    Gaps(Box<LineParser>, Gaps),
+}
+
+/// What surrounds each line's payload: a container runtime's record, then a syslog prefix.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Wrapping {
+    pub unwrap: Wrapper,
+    /// Fall back on the record's time for entries without a timestamp.
+    pub wrapper_time: bool,
+    pub strip_syslog: bool,
+}
+
+impl Wrapping {
+    /// `parser` behind the layers that take the wrapping off.
+    pub fn wrap(self, mut parser: LineParser) -> LineParser {
+        if self.strip_syslog {
+            parser = LineParser::Syslog(Box::new(parser));
+        }
+        if self.unwrap != Wrapper::None {
+            parser = LineParser::Unwrap(Box::new(parser), Unwrapper::new(self.unwrap), self.wrapper_time);
+        }
+        parser
+    }
+}
+
+impl From<LogKind> for LineParser {
+    fn from(kind: LogKind) -> Self {
+        match kind {
+            LogKind::W3c => LineParser::W3c(W3cParser::default()),
+            LogKind::Csv => LineParser::Csv(CsvParser::default()),
+            kind => LineParser::Kind(kind),
+        }
+    }
+}
+
+impl LineParser {
+    /// Whether entries from this parser can carry a request duration.
+    pub fn records_duration(&self) -> bool {
+        match self {
+            LineParser::Kind(kind) => matches!(kind, LogKind::Alb | LogKind::Haproxy | LogKind::W3c),
+            LineParser::Custom(format) => format.records_duration(),
+            LineParser::W3c(_) => true,
+            LineParser::Csv(_) => false,
+            LineParser::Syslog(inner)
+            | LineParser::Unwrap(inner, ..)
+            | LineParser::Extract(inner, _)
+            | LineParser::NormalizePaths(inner, _)
+            | LineParser::Derive(inner, _)
+            | LineParser::Gaps(inner, _) => inner.records_duration(),
+        }
+    }
+
+    /// Whether parsing a line depends on the lines before it, as with W3C's `#Fields:`
+    /// directives, a CSV header row, the parts of a split container record or each client's previous request, so
+    /// the input can't be parsed from the middle.
+    pub fn carries_state(&self) -> bool {
+        match self {
+            LineParser::Kind(_) | LineParser::Custom(_) => false,
+            LineParser::W3c(_) | LineParser::Csv(_) | LineParser::Unwrap(..) | LineParser::Gaps(..) => true,
+            LineParser::Syslog(inner)
+            | LineParser::Extract(inner, _)
+            | LineParser::NormalizePaths(inner, _)
+            | LineParser::Derive(inner, _) => inner.carries_state(),
+        }
+    }
+
+    /// The fields entries from this parser can have, `None` when only the input can tell, as
+    /// with W3C logs before their `#Fields:` directive or CSV before its header row.
+    pub fn fields(&self) -> Option<Vec<String>> {
+        let fields = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        let builtin = |format: &str| format.parse::<LogFormat>().expect("built-in format is valid").fields();
+        Some(match self {
+            LineParser::Kind(LogKind::Combined) => builtin(COMBINED_FORMAT),
+            LineParser::Kind(LogKind::Common) => builtin(COMMON_FORMAT),
+            LineParser::Kind(LogKind::Alb) => fields(&alb::fields()),
+            LineParser::Kind(LogKind::Haproxy) => fields(&haproxy::fields()),
+            LineParser::Kind(LogKind::NginxError) => fields(error_log::NGINX_FIELDS),
+            LineParser::Kind(LogKind::ApacheError) => fields(error_log::APACHE_FIELDS),
+            LineParser::Kind(LogKind::W3c) => unreachable!("W3C logs are parsed by LineParser::W3c"),
+            LineParser::Kind(LogKind::Csv) => unreachable!("CSV is parsed by LineParser::Csv"),
+            LineParser::Kind(LogKind::Auto) => return None,
+            LineParser::Custom(format) => format.fields(),
+            LineParser::W3c(parser) => parser.fields()?,
+            LineParser::Csv(parser) => parser.fields()?,
+            LineParser::Syslog(inner) => {
+                let mut fields = inner.fields()?;
+                fields.push("syslog_host".to_string());
+                fields
+            }
+            LineParser::Unwrap(inner, _, wrapper_time) => {
+                let mut fields = inner.fields()?;
+                if *wrapper_time && !fields.iter().any(|field| field == "timestamp") {
+                    fields.push("timestamp".to_string());
+                }
+                fields
+            }
+            LineParser::Extract(inner, extractions) => {
+                let mut fields = inner.fields()?;
+                fields.extend(extractions.names().map(str::to_string));
+                fields
+            }
+            LineParser::NormalizePaths(inner, _) => {
+                let mut fields = inner.fields()?;
+                let at = fields.iter().position(|field| field == "path").map_or(fields.len(), |at| at + 1);
+                fields.insert(at, "path_normalized".to_string());
+                fields
+            }
+            LineParser::Derive(inner, derived) => {
+                let mut fields = inner.fields()?;
+                let derived: Vec<String> = derived
+                    .names()
+                    .filter(|name| fields.iter().any(|field| field == classify::source(name)))
+                    .map(str::to_string)
+                    .collect();
+                fields.extend(derived);
+                fields
+            }
+            LineParser::Gaps(inner, _) => {
+                let mut fields = inner.fields()?;
+                if fields.iter().any(|field| field == "ip") {
+                    fields.push(gap::FIELD.to_string());
+                }
+                fields
+            }
+        })
+    }
+
+    /// The unwrapping layer, if lines are unwrapped, for its counts.
+    pub fn unwrapper(&self) -> Option<&Unwrapper> {
+        match self {
+            LineParser::Kind(_) | LineParser::Custom(_) | LineParser::W3c(_) | LineParser::Csv(_) => None,
+            LineParser::Unwrap(_, unwrapper, _) => Some(unwrapper),
+            LineParser::Syslog(inner)
+            | LineParser::Extract(inner, _)
+            | LineParser::NormalizePaths(inner, _)
+            | LineParser::Derive(inner, _)
+            | LineParser::Gaps(inner, _) => inner.unwrapper(),
+        }
+    }
+
+    /// The layer adding `gap`, if it's added, for its counts.
+    pub fn gaps(&self) -> Option<&Gaps> {
+        match self {
+            LineParser::Kind(_) | LineParser::Custom(_) | LineParser::W3c(_) | LineParser::Csv(_) => None,
+            LineParser::Gaps(_, gaps) => Some(gaps),
+            LineParser::Syslog(inner)
+            | LineParser::Unwrap(inner, ..)
+            | LineParser::Extract(inner, _)
+            | LineParser::NormalizePaths(inner, _)
+            | LineParser::Derive(inner, _) => inner.gaps(),
+        }
+    }
+
+    /// Parses one line; `Ok(None)` marks lines that carry no entry, such as W3C directives.
+    pub fn parse(&mut self, line: &str) -> Result<Option<Entry>, String> {
+        let entry = match self {
+            LineParser::Kind(LogKind::Combined) => parse_lenient(LogType::CombinedLog, line)?,
+            LineParser::Kind(LogKind::Common) => parse_lenient(LogType::CommonLog, line)?,
+            LineParser::Kind(LogKind::Alb) => alb::parse(line)?,
+            LineParser::Kind(LogKind::Haproxy) => haproxy::parse(line)?,
+            LineParser::Kind(LogKind::NginxError) => error_log::parse_nginx(line)?,
+            LineParser::Kind(LogKind::ApacheError) => error_log::parse_apache(line)?,
+            LineParser::Kind(LogKind::W3c) => unreachable!("W3C logs are parsed by LineParser::W3c"),
+            LineParser::Kind(LogKind::Csv) => unreachable!("CSV is parsed by LineParser::Csv"),
+            LineParser::Kind(LogKind::Auto) => unreachable!("--log-type auto is resolved per file before parsing"),
+            LineParser::Custom(format) => format.parse(line)?,
+            LineParser::W3c(parser) => return parser.parse(line),
+            LineParser::Csv(parser) => return parser.parse(line),
+            LineParser::Syslog(inner) => {
+                let syslog = syslog::strip(line).ok_or_else(|| format!("No syslog prefix in: {}", line))?;
+                let Some(mut entry) = inner.parse(syslog.payload)? else {
+                    return Ok(None);
+                };
+                if let Some(host) = syslog.host {
+                    entry.extras.insert("syslog_host".to_string(), FieldValue::Text(host.to_string()));
+                }
+                entry
+            }
+            LineParser::Unwrap(inner, unwrapper, wrapper_time) => {
+                // A part of a split line, or a blank line the container wrote.
+                if !unwrapper.decode(line)? || unwrapper.payload().is_empty() {
+                    return Ok(None);
+                }
+                let Some(mut entry) = inner.parse(unwrapper.payload())? else {
+                    return Ok(None);
+                };
+                if *wrapper_time && entry.timestamp.is_none() {
+                    entry.timestamp = unwrapper.time();
+                }
+                entry
+            }
+            LineParser::Extract(inner, extractions) => {
+                let Some(mut entry) = inner.parse(line)? else {
+                    return Ok(None);
+                };
+                extractions.apply(&mut entry);
+                entry
+            }
+            LineParser::NormalizePaths(inner, lowercase) => {
+                let Some(mut entry) = inner.parse(line)? else {
+                    return Ok(None);
+                };
+                entry.path_normalized = entry.path.as_deref().map(|path| normalize::normalize(path, *lowercase));
+                entry
+            }
+            LineParser::Derive(inner, derived) => {
+                let Some(mut entry) = inner.parse(line)? else {
+                    return Ok(None);
+                };
+                derived.apply(&mut entry);
+                entry
+            }
+            LineParser::Gaps(inner, gaps) => {
+                let Some(mut entry) = inner.parse(line)? else {
+                    return Ok(None);
+                };
+                gaps.apply(&mut entry);
+                entry
+            }
+        };
+        Ok(Some(entry))
+    }
+}
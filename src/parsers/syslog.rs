@@ -0,0 +1,123 @@
+// Recognizes the prefixes syslog daemons put in front of forwarded messages:
+// RFC3164: `<34>Feb 12 14:34:20 web01 nginx[123]: payload` (the priority is optional)
+// RFC5424: `<34>1 2023-02-12T14:34:20.003Z web01 nginx 123 - - payload`
+
+pub struct SyslogLine<'a> {
+    pub host: Option<&'a str>,
+    pub payload: &'a str,
+}
+
+const MONTHS: &[&str] = &["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+fn strip_priority(line: &str) -> &str {
+    line.strip_prefix('<')
+        .and_then(|rest| rest.split_once('>'))
+        .filter(|(pri, _)| !pri.is_empty() && pri.len() <= 3 && pri.bytes().all(|b| b.is_ascii_digit()))
+        .map_or(line, |(_, rest)| rest)
+}
+
+fn word(rest: &str) -> Option<(&str, &str)> {
+    let rest = rest.trim_start_matches(' ');
+    if rest.is_empty() {
+        return None;
+    }
+    let end = rest.find(' ').unwrap_or(rest.len());
+    Some((&rest[..end], &rest[end..]))
+}
+
+fn nil(value: &str) -> Option<&str> {
+    (value != "-").then_some(value)
+}
+
+fn rfc3164(rest: &str) -> Option<SyslogLine<'_>> {
+    let (month, rest) = word(rest)?;
+    let (day, rest) = word(rest)?;
+    let (time, rest) = word(rest)?;
+    let valid_time = time.len() == 8 && time.bytes().enumerate().all(|(i, b)| if i % 3 == 2 { b == b':' } else { b.is_ascii_digit() });
+    if !MONTHS.contains(&month) || day.is_empty() || day.len() > 2 || !day.bytes().all(|b| b.is_ascii_digit()) || !valid_time {
+        return None;
+    }
+    let (host, rest) = word(rest)?;
+    let payload = match word(rest) {
+        Some((tag, payload)) if tag.ends_with(':') => payload,
+        _ => rest,
+    };
+    Some(SyslogLine {
+        host: nil(host),
+        payload: payload.trim_start_matches(' '),
+    })
+}
+
+fn structured_data(rest: &str) -> Option<&str> {
+    let rest = rest.trim_start_matches(' ');
+    if let Some(rest) = rest.strip_prefix('-') {
+        return Some(rest);
+    }
+    let mut rest = rest;
+    while let Some(element) = rest.strip_prefix('[') {
+        let mut escaped = false;
+        let end = element.char_indices().find_map(|(i, c)| match c {
+            '\\' if !escaped => {
+                escaped = true;
+                None
+            }
+            ']' if !escaped => Some(i),
+            _ => {
+                escaped = false;
+                None
+            }
+        })?;
+        rest = &element[end + 1..];
+    }
+    Some(rest)
+}
+
+fn rfc5424(rest: &str) -> Option<SyslogLine<'_>> {
+    let rest = rest.strip_prefix("1 ")?;
+    let (_timestamp, rest) = word(rest)?;
+    let (host, rest) = word(rest)?;
+    let (_app, rest) = word(rest)?;
+    let (_procid, rest) = word(rest)?;
+    let (_msgid, rest) = word(rest)?;
+    let payload = structured_data(rest)?;
+    Some(SyslogLine {
+        host: nil(host),
+        payload: payload.trim_start_matches(' ').trim_start_matches('\u{feff}'),
+    })
+}
+
+/// Splits a syslog-wrapped line into its hostname and payload, or returns `None`
+/// when the line carries no recognizable syslog prefix.
+pub fn strip(line: &str) -> Option<SyslogLine<'_>> {
+    let rest = strip_priority(line);
+    rfc5424(rest).or_else(|| rfc3164(rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(line: &str) -> Option<(Option<&str>, &str)> {
+        strip(line).map(|s| (s.host, s.payload))
+    }
+
+    #[test]
+    fn rfc3164() {
+        assert_eq!(split("Feb 12 14:34:20 web01 nginx: payload"), Some((Some("web01"), "payload")));
+        assert_eq!(split("<34>Feb  2 14:34:21 web02 nginx[812]: payload"), Some((Some("web02"), "payload")));
+    }
+
+    #[test]
+    fn rfc5424_with_structured_data() {
+        assert_eq!(
+            split(r#"<165>1 2023-02-12T14:34:22.003Z web03 nginx 812 - [id@1 a="x\]y"] payload"#),
+            Some((Some("web03"), "payload"))
+        );
+        assert_eq!(split("<165>1 2023-02-12T14:34:22.003Z - nginx - - - payload"), Some((None, "payload")));
+    }
+
+    #[test]
+    fn lines_without_a_prefix() {
+        assert_eq!(split(r#"1.2.3.4 - - [12/Feb/2023:14:03:45 +0000] "GET / HTTP/1.1" 200 5"#), None);
+    }
+}
@@ -0,0 +1,225 @@
+use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+// Decodes the records container runtimes wrap each line a container writes in:
+// docker-json: `{"log":"<payload>\n","stream":"stdout","time":"2023-02-12T14:34:20.123456789Z"}`
+// cri:         `2023-02-12T14:34:20.123456789Z stdout F <payload>`
+// Both split long lines over several records: docker leaves the newline off all but the last
+// part, and CRI tags the parts `P` and the last `F`. Parts are joined per stream, since stdout
+// and stderr records interleave, and the payload is parsed once the last part arrives.
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Wrapper {
+    /// Lines as they are
+    #[default]
+    None,
+    /// Docker's json-file logging driver, one JSON object per line
+    DockerJson,
+    /// The CRI log format kubelet writes, used by containerd and CRI-O
+    Cri,
+}
+
+#[derive(Deserialize)]
+struct DockerRecord {
+    log: String,
+    #[serde(default)]
+    stream: String,
+    time: Option<String>,
+}
+
+/// The part of a split line read so far.
+struct Partial {
+    stream: String,
+    payload: String,
+    time: Option<DateTime<FixedOffset>>,
+}
+
+/// Decodes one file's records, holding the parts of split lines until the last one.
+pub struct Unwrapper {
+    wrapper: Wrapper,
+    partials: Vec<Partial>,
+    /// The line the last record ended, empty while it was a part.
+    payload: String,
+    time: Option<DateTime<FixedOffset>>,
+    /// Lines that weren't records.
+    errors: usize,
+}
+
+fn timestamp(time: &str) -> Result<DateTime<FixedOffset>, String> {
+    DateTime::parse_from_rfc3339(time).map_err(|e| format!("invalid record time {:?}: {}", time, e))
+}
+
+impl Unwrapper {
+    pub fn new(wrapper: Wrapper) -> Self {
+        Unwrapper { wrapper, partials: Vec::new(), payload: String::new(), time: None, errors: 0 }
+    }
+
+    /// Lines so far that failed to decode.
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// Split lines whose last part hasn't arrived, e.g. at the end of the input.
+    pub fn unfinished(&self) -> usize {
+        self.partials.len()
+    }
+
+    /// The line the container wrote that the last record decoded ended, as the input had it
+    /// without the wrapping; empty after a part of a split line.
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+
+    /// When the runtime logged the first record of `payload`.
+    pub fn time(&self) -> Option<DateTime<FixedOffset>> {
+        self.time
+    }
+
+    /// Decodes `line`, returning whether it ended a line the container wrote, which is then the
+    /// `payload`; `false` for a part of a split line, held until the last.
+    pub fn decode(&mut self, line: &str) -> Result<bool, String> {
+        self.payload.clear();
+        self.time = None;
+        let decoded = match self.wrapper {
+            Wrapper::None => Ok((Cow::Borrowed(""), Cow::Borrowed(line), None, true)),
+            Wrapper::DockerJson => docker(line),
+            Wrapper::Cri => cri(line),
+        };
+        let (stream, payload, time, last) = decoded.inspect_err(|_| self.errors += 1)?;
+        let at = self.partials.iter().position(|partial| partial.stream == stream);
+        match (at, last) {
+            (None, true) => {
+                self.payload.push_str(&payload);
+                self.time = time;
+            }
+            (None, false) => self.partials.push(Partial { stream: stream.into_owned(), payload: payload.into_owned(), time }),
+            (Some(at), false) => self.partials[at].payload.push_str(&payload),
+            (Some(at), true) => {
+                let partial = self.partials.remove(at);
+                self.payload = partial.payload + &payload;
+                self.time = partial.time;
+            }
+        }
+        Ok(last)
+    }
+}
+
+/// A decoded line: its stream, its payload, the record's time, and whether it ends the line.
+type Decoded<'a> = (Cow<'a, str>, Cow<'a, str>, Option<DateTime<FixedOffset>>, bool);
+
+fn docker(line: &str) -> Result<Decoded<'_>, String> {
+    let record: DockerRecord =
+        serde_json::from_str(line).map_err(|e| format!("not a docker json-file record ({}): {}", e, line))?;
+    let time = record.time.as_deref().map(timestamp).transpose()?;
+    let mut payload = record.log;
+    let last = payload.ends_with('\n');
+    if last {
+        payload.pop();
+        if payload.ends_with('\r') {
+            payload.pop();
+        }
+    }
+    Ok((Cow::Owned(record.stream), Cow::Owned(payload), time, last))
+}
+
+fn cri(line: &str) -> Result<Decoded<'_>, String> {
+    let invalid = || format!("not a CRI record, `<time> <stream> <P|F> <log>`: {}", line);
+    let mut fields = line.splitn(4, ' ');
+    let (Some(time), Some(stream), Some(tags)) = (fields.next(), fields.next(), fields.next()) else {
+        return Err(invalid());
+    };
+    let last = match tags.split(':').next() {
+        Some("F") => true,
+        Some("P") => false,
+        _ => return Err(invalid()),
+    };
+    if stream.is_empty() {
+        return Err(invalid());
+    }
+    let time = timestamp(time)?;
+    Ok((Cow::Borrowed(stream), Cow::Borrowed(fields.next().unwrap_or_default()), Some(time), last))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(wrapper: Wrapper, lines: &[&str]) -> Vec<Result<Option<String>, String>> {
+        let mut unwrapper = Unwrapper::new(wrapper);
+        lines.iter().map(|line| unwrapper.decode(line).map(|last| last.then(|| unwrapper.payload().to_string()))).collect()
+    }
+
+    #[test]
+    fn docker_records_hold_the_line_in_log() {
+        let decoded = decode_all(
+            Wrapper::DockerJson,
+            &[
+                r#"{"log":"1.2.3.4 - - \"GET / HTTP/1.1\" 200 5\n","stream":"stdout","time":"2023-02-12T14:34:20.123456789Z"}"#,
+                r#"{"log":"crlf\r\n","stream":"stdout"}"#,
+                r#"{"log":"part one, ","stream":"stdout","time":"2023-02-12T14:34:21Z"}"#,
+                r#"{"log":"an error\n","stream":"stderr","time":"2023-02-12T14:34:21Z"}"#,
+                r#"{"log":"part two\n","stream":"stdout","time":"2023-02-12T14:34:21Z"}"#,
+            ],
+        );
+        let expected = [
+            Ok(Some("1.2.3.4 - - \"GET / HTTP/1.1\" 200 5".to_string())),
+            Ok(Some("crlf".to_string())),
+            Ok(None),
+            Ok(Some("an error".to_string())),
+            Ok(Some("part one, part two".to_string())),
+        ];
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn cri_records_join_partial_lines() {
+        let decoded = decode_all(
+            Wrapper::Cri,
+            &[
+                "2023-02-12T14:34:20.123456789Z stdout F a whole line",
+                "2023-02-12T14:34:21Z stdout P first, ",
+                "2023-02-12T14:34:21Z stderr F interleaved",
+                "2023-02-12T14:34:21Z stdout P second, ",
+                "2023-02-12T14:34:22Z stdout F last",
+                "2023-02-12T14:34:22Z stdout F",
+            ],
+        );
+        let expected = [
+            Ok(Some("a whole line".to_string())),
+            Ok(None),
+            Ok(Some("interleaved".to_string())),
+            Ok(None),
+            Ok(Some("first, second, last".to_string())),
+            Ok(Some(String::new())),
+        ];
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn records_keep_the_time_of_their_first_part() {
+        let mut unwrapper = Unwrapper::new(Wrapper::Cri);
+        assert_eq!(unwrapper.decode("2023-02-12T14:34:21Z stdout P a"), Ok(false));
+        assert_eq!((unwrapper.payload(), unwrapper.time(), unwrapper.unfinished()), ("", None, 1));
+        assert_eq!(unwrapper.decode("2023-02-12T14:34:29+02:00 stdout F b"), Ok(true));
+        assert_eq!(unwrapper.payload(), "ab");
+        assert_eq!(unwrapper.time(), Some(DateTime::parse_from_rfc3339("2023-02-12T14:34:21Z").unwrap()));
+        assert_eq!(unwrapper.unfinished(), 0);
+    }
+
+    #[test]
+    fn lines_that_arent_records_are_counted() {
+        let mut unwrapper = Unwrapper::new(Wrapper::Cri);
+        for line in ["1.2.3.4 - - [12/Feb/2023:14:03:45 +0000]", "yesterday stdout F x", "2023-02-12T14:34:20Z stdout X x"] {
+            assert!(unwrapper.decode(line).is_err(), "{}", line);
+        }
+        let mut docker = Unwrapper::new(Wrapper::DockerJson);
+        for line in ["{\"log\":", "{\"stream\":\"stdout\"}", r#"{"log":"x\n","time":"now"}"#] {
+            assert!(docker.decode(line).is_err(), "{}", line);
+        }
+        assert_eq!((unwrapper.errors(), docker.errors()), (3, 3));
+        let mut none = Unwrapper::new(Wrapper::None);
+        assert_eq!((none.decode("as is"), none.payload()), (Ok(true), "as is"));
+    }
+}
@@ -0,0 +1,153 @@
+use crate::duration::HumanDuration;
+use crate::entry::{Client, Entry, FieldValue};
+use crate::tls::TlsVersion;
+use chrono::NaiveDateTime;
+
+// W3C extended log format as written by CloudFront (tab separated) and IIS (space
+// separated). `#Fields:` directives declare the columns of the lines that follow and
+// may repeat mid-stream when shards are concatenated.
+
+#[derive(Default)]
+pub struct W3cParser {
+    columns: Vec<String>,
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn optional(value: &str) -> Option<String> {
+    (value != "-" && !value.is_empty()).then(|| percent_decode(value))
+}
+
+impl W3cParser {
+    /// The fields entries can have under the last `#Fields:` directive, `None` before one.
+    pub fn fields(&self) -> Option<Vec<String>> {
+        if self.columns.is_empty() {
+            return None;
+        }
+        let mut fields = Vec::new();
+        for column in &self.columns {
+            let field = match column.as_str() {
+                "date" | "time" => "timestamp",
+                "c-ip" => "ip",
+                "cs-method" => "method",
+                "cs-uri-stem" | "cs-uri-query" => "path",
+                "cs-protocol-version" | "cs-version" => "protocol",
+                "cs-username" => "user",
+                "sc-status" => "status_code",
+                "sc-bytes" => "bytes",
+                "cs(Referer)" | "cs(Referrer)" => "referrer",
+                "cs(User-Agent)" => "user_agent",
+                column => column,
+            };
+            let typed = match field {
+                "time-taken" => Some("duration"),
+                "cs-protocol" => Some("scheme"),
+                "s-port" => Some("port"),
+                "ssl-protocol" => Some("tls_protocol"),
+                _ => None,
+            };
+            for field in std::iter::once(field).chain(typed) {
+                if !fields.iter().any(|known| known == field) {
+                    fields.push(field.to_string());
+                }
+            }
+        }
+        Some(fields)
+    }
+
+    pub fn parse(&mut self, line: &str) -> Result<Option<Entry>, String> {
+        if let Some(directive) = line.strip_prefix('#') {
+            if let Some(fields) = directive.strip_prefix("Fields:") {
+                self.columns = fields.split_whitespace().map(str::to_string).collect();
+            }
+            return Ok(None);
+        }
+        if self.columns.is_empty() {
+            return Err("W3C log line before any #Fields: directive".to_string());
+        }
+        let values: Vec<&str> = if line.contains('\t') {
+            line.split('\t').collect()
+        } else {
+            line.split_whitespace().collect()
+        };
+        if values.len() != self.columns.len() {
+            return Err(format!("Expected {} W3C columns, found {}: {}", self.columns.len(), values.len(), line));
+        }
+
+        let mut entry = Entry::default();
+        let (mut date, mut time) = (None, None);
+        let mut query = None;
+        for (column, &value) in self.columns.iter().zip(&values) {
+            match column.as_str() {
+                "date" => date = Some(value),
+                "time" => time = Some(value),
+                "c-ip" => entry.client = Some(Client::Ip(value.parse().map_err(|_| format!("Invalid W3C c-ip: {}", value))?)),
+                "cs-method" => entry.method = optional(value),
+                "cs-uri-stem" => entry.path = optional(value),
+                "cs-uri-query" => query = optional(value),
+                "cs-protocol-version" | "cs-version" => entry.protocol = optional(value),
+                "cs-username" => entry.user = optional(value),
+                "sc-status" => {
+                    entry.status_code = match value {
+                        "-" | "000" => None,
+                        _ => Some(value.parse().map_err(|_| format!("Invalid W3C sc-status: {}", value))?),
+                    }
+                }
+                "sc-bytes" => {
+                    if value != "-" {
+                        entry.set_bytes(value, || format!("Invalid W3C sc-bytes: {}", value))?;
+                    }
+                }
+                "cs(Referer)" | "cs(Referrer)" => entry.referrer = optional(value),
+                "cs(User-Agent)" => entry.user_agent = optional(value).map(|ua| ua.replace('+', " ")),
+                _ => {
+                    match column.as_str() {
+                        "cs-protocol" => entry.scheme = optional(value),
+                        "s-port" => entry.port = value.parse().ok(),
+                        "ssl-protocol" => entry.tls_protocol = TlsVersion::parse_logged(value),
+                        _ => {}
+                    }
+                    if let Some(value) = FieldValue::parse(value) {
+                        entry.extras.insert(column.clone(), value);
+                    }
+                }
+            }
+        }
+        // CloudFront logs time-taken in seconds, IIS in milliseconds.
+        let cloudfront = self.columns.iter().any(|c| c == "x-edge-location");
+        if let Some(taken) = entry.extras.get("time-taken").and_then(FieldValue::as_number) {
+            entry.duration = match cloudfront {
+                true => HumanDuration::from_secs_f64(taken),
+                false => HumanDuration::from_millis_f64(taken),
+            };
+        }
+        if let (Some(path), Some(query)) = (entry.path.as_mut(), query) {
+            path.push('?');
+            path.push_str(&query);
+        }
+        if let (Some(date), Some(time)) = (date, time) {
+            let timestamp = NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M:%S%.f")
+                .map_err(|_| format!("Invalid W3C date/time: {} {}", date, time))?;
+            entry.timestamp = Some(timestamp.and_utc().fixed_offset());
+        }
+        Ok(Some(entry))
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    ops::Range,
+    path::Path,
+};
+
+// Byte ranges of one large file for --jobs, each read by its own thread. The file is cut into
+// equal parts and each cut moved forward to the start of the next line, so every line lies in
+// exactly one range: a line straddling a cut belongs to the range it starts in. Only plain files
+// are split; gzip can't be read from the middle, and pipes can't seek.
+
+/// The smallest range worth a thread of its own.
+pub const MIN_RANGE: u64 = 1 << 20;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The first line start at or after `at`, which is past the start of the input, or `len` when
+/// no line starts there.
+fn line_start(reader: &mut (impl BufRead + Seek), at: u64, len: u64) -> io::Result<u64> {
+    // A line starts wherever the byte before it is a newline, so look from the byte before `at`.
+    let mut position = reader.seek(SeekFrom::Start(at - 1))?;
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(len);
+        }
+        if let Some(i) = available.iter().position(|&b| b == b'\n') {
+            return Ok(position + i as u64 + 1);
+        }
+        let length = available.len();
+        reader.consume(length);
+        position += length as u64;
+    }
+}
+
+/// `len` bytes split into `parts` ranges that start at line starts. A line longer than a part
+/// leaves the ranges whose cuts fall inside it empty.
+fn boundaries(reader: &mut (impl BufRead + Seek), len: u64, parts: u64) -> io::Result<Vec<Range<u64>>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for part in 1..parts {
+        let cut = len * part / parts;
+        let end = match cut > start {
+            true => line_start(reader, cut, len)?,
+            false => start,
+        };
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges.push(start..len);
+    Ok(ranges)
+}
+
+/// Up to `jobs` ranges of the plain file at `path`, or `None` when it's compressed, not a
+/// regular file, or too small to be worth splitting.
+pub fn ranges(path: &Path, jobs: usize) -> io::Result<Option<Vec<Range<u64>>>> {
+    // Looked up before opening, which would block on a pipe until it has a writer.
+    let metadata = fs::metadata(path)?;
+    let parts = (jobs as u64).min(metadata.len() / MIN_RANGE);
+    if !metadata.is_file() || parts < 2 {
+        return Ok(None);
+    }
+    let mut file = File::open(path)?;
+    let mut magic = [0; 2];
+    file.read_exact(&mut magic)?;
+    if magic == GZIP_MAGIC {
+        return Ok(None);
+    }
+    boundaries(&mut BufReader::new(file), metadata.len(), parts).map(Some)
+}
+
+/// Reads just `range` of the file at `path`.
+pub fn open(path: &Path, range: &Range<u64>) -> io::Result<Box<dyn BufRead>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(range.start))?;
+    Ok(Box::new(BufReader::new(file.take(range.end - range.start))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// The lines of each range of `text` split in `parts`.
+    fn split(text: &str, parts: u64) -> Vec<Vec<&str>> {
+        let ranges = boundaries(&mut Cursor::new(text.as_bytes()), text.len() as u64, parts).unwrap();
+        assert_eq!(ranges.len() as u64, parts);
+        ranges.iter().map(|range| text[range.start as usize..range.end as usize].lines().collect()).collect()
+    }
+
+    #[test]
+    fn lines_straddling_a_cut_belong_to_the_range_they_start_in() {
+        // Cuts at 6 and 12, both inside a line.
+        assert_eq!(split("aaaa\nbbbb\ncccc\ndd\n", 3), vec![vec!["aaaa", "bbbb"], vec!["cccc"], vec!["dd"]]);
+        // Without a final newline the last line still ends the last range.
+        assert_eq!(split("aaaa\nbbbb\ncc", 2), vec![vec!["aaaa", "bbbb"], vec!["cc"]]);
+    }
+
+    #[test]
+    fn cuts_at_a_line_start_or_on_a_newline_keep_each_line_once() {
+        // The cut at 5 is right at the start of "bbbb".
+        assert_eq!(split("aaaa\nbbbb\n", 2), vec![vec!["aaaa"], vec!["bbbb"]]);
+        // The cut at 4 is on the newline ending "aaaa", which stays with its line.
+        assert_eq!(split("aaaa\nbbb\n", 2), vec![vec!["aaaa"], vec!["bbb"]]);
+    }
+
+    #[test]
+    fn a_line_longer_than_a_part_leaves_the_ranges_it_covers_empty() {
+        let text = format!("{}\nb\n", "a".repeat(40));
+        assert_eq!(split(&text, 4), vec![vec![&text[..40]], vec![], vec![], vec!["b"]]);
+        assert_eq!(split("", 3), vec![Vec::<&str>::new(); 3]);
+
+        // However the text is cut, every line is read exactly once.
+        let text: String = (0..50).map(|i| format!("{}\n", "x".repeat(i % 7))).collect();
+        for parts in 1..20 {
+            assert_eq!(split(&text, parts).concat(), text.lines().collect::<Vec<_>>());
+        }
+    }
+}
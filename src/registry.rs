@@ -0,0 +1,272 @@
+use crate::entry::{Client, Entry, FieldValue};
+use crate::{gap, referrer, suspicious};
+use std::{borrow::Cow, sync::OnceLock};
+
+// The field registry: every field known by name whatever the log type, with the names it's
+// also known by, the type of its values, the field whose presence in a log type brings it and
+// how to read it from an entry. --fields, --format templates, --field, --sort, --by,
+// --group-by, --latest-per and --extract all resolve names here, so `user-agent`, `ua` and
+// `user_agent` name the same field everywhere. Names it doesn't know are format-specific
+// fields, such as nginx's `request_time`, which only the log type or the input can vouch for.
+//
+// A field is available for a log type that declares the field it comes with; `hostname`
+// comes with `ip`, since a client is logged as one or the other, and a derived field with
+// the one it's computed from. When the log type declares no fields, as with --log-type auto,
+// every field may turn up and names are checked against the first line instead.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Type {
+    Text,
+    Integer,
+    Number,
+    Time,
+    /// Several values, such as the reasons a path is suspicious.
+    List,
+}
+
+impl Type {
+    /// Whether values of the type have an order beyond that of their text.
+    pub fn is_ordered(self) -> bool {
+        matches!(self, Type::Integer | Type::Number | Type::Time)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Access {
+    /// A slot of `Entry`.
+    Entry(fn(&Entry) -> Option<FieldValue>),
+    /// Kept among the entry's extras: derived, or brought by a few log types only.
+    Extra,
+    /// Where the line was read, known for a match but not for an entry.
+    Provenance,
+}
+
+/// The option that filters on a field and the kind of filter it is.
+type Filter = Option<(&'static str, &'static str)>;
+
+pub struct Field {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub kind: Type,
+    /// The field a log type declares when it has this one.
+    pub source: &'static str,
+    /// The option that filters on the field and the kind of filter it is; `--field` otherwise.
+    pub option: Filter,
+    pub access: Access,
+}
+
+fn text(value: &Option<String>) -> Option<FieldValue> {
+    value.clone().map(FieldValue::Text)
+}
+
+const fn entry(name: &'static str, kind: Type, option: Filter, read: fn(&Entry) -> Option<FieldValue>) -> Field {
+    Field { name, aliases: &[], kind, source: name, option, access: Access::Entry(read) }
+}
+
+const fn extra(name: &'static str, kind: Type, source: &'static str, option: Filter) -> Field {
+    Field { name, aliases: &[], kind, source, option, access: Access::Extra }
+}
+
+const fn provenance(name: &'static str, kind: Type) -> Field {
+    Field { name, aliases: &[], kind, source: "", option: None, access: Access::Provenance }
+}
+
+pub const FIELDS: &[Field] = &[
+    Field {
+        aliases: &["client_ip", "remote_addr"],
+        ..entry("ip", Type::Text, Some(("--ip", "ip")), |entry| entry.ip().map(|ip| FieldValue::Text(ip.to_string())))
+    },
+    Field {
+        source: "ip",
+        ..entry("hostname", Type::Text, Some(("--client", "string")), |entry| match &entry.client {
+            Some(Client::Host(host)) => Some(FieldValue::Text(host.clone())),
+            _ => None,
+        })
+    },
+    Field { aliases: &["remote_user"], ..entry("user", Type::Text, None, |entry| text(&entry.user)) },
+    Field {
+        aliases: &["ts"],
+        ..entry("timestamp", Type::Time, Some(("--timestamp", "ord")), |entry| {
+            entry.timestamp.map(|t| FieldValue::Text(t.to_rfc3339()))
+        })
+    },
+    entry("method", Type::Text, None, |entry| text(&entry.method)),
+    entry("path", Type::Text, Some(("--path", "text")), |entry| text(entry.match_path())),
+    Field {
+        source: "path",
+        ..entry("path_normalized", Type::Text, Some(("--path", "text")), |entry| text(&entry.path_normalized))
+    },
+    entry("protocol", Type::Text, None, |entry| text(&entry.protocol)),
+    Field {
+        source: "method",
+        ..entry("raw_request", Type::Text, Some(("--raw-request", "text")), |entry| text(&entry.raw_request))
+    },
+    Field {
+        aliases: &["status"],
+        ..entry("status_code", Type::Integer, Some(("--status-code", "ord")), |entry| {
+            entry.status_code.map(|status| FieldValue::Integer(status.as_u16().into()))
+        })
+    },
+    Field {
+        aliases: &["body_bytes_sent"],
+        ..entry("bytes", Type::Integer, Some(("--bytes", "ord")), |entry| {
+            entry.bytes.map(|bytes| FieldValue::Integer(bytes.min(i64::MAX as u64) as i64))
+        })
+    },
+    entry("referrer", Type::Text, Some(("--referrer", "text")), |entry| text(&entry.referrer)),
+    Field {
+        aliases: &["ua"],
+        ..entry("user_agent", Type::Text, Some(("--user-agent", "text")), |entry| text(&entry.user_agent))
+    },
+    entry("severity", Type::Text, Some(("--severity", "ord")), |entry| entry.severity.map(|s| FieldValue::Text(s.to_string()))),
+    entry("duration", Type::Number, Some(("--duration", "ord")), |entry| {
+        entry.duration.map(|duration| FieldValue::Number(duration.as_secs_f64()))
+    }),
+    entry("scheme", Type::Text, Some(("--scheme", "string")), |entry| text(&entry.scheme)),
+    entry("port", Type::Integer, Some(("--port", "ord")), |entry| entry.port.map(|port| FieldValue::Integer(port.into()))),
+    entry("tls_protocol", Type::Text, Some(("--tls-protocol", "ord")), |entry| {
+        entry.tls_protocol.map(|version| FieldValue::Text(version.to_string()))
+    }),
+    extra("class", Type::Text, "path", Some(("--class", "choice"))),
+    extra("path_depth", Type::Integer, "path", None),
+    extra(suspicious::FIELD, Type::List, "path", Some(("--suspicious-paths", "flag"))),
+    extra(referrer::CLASS_FIELD, Type::Text, "referrer", Some(("--referrer-class", "choice"))),
+    extra(referrer::DOMAIN_FIELD, Type::Text, "referrer", None),
+    extra(gap::FIELD, Type::Number, "ip", None),
+    extra("backend", Type::Text, "backend", Some(("--backend", "string"))),
+    extra("tr", Type::Integer, "tr", Some(("--response-time", "ord"))),
+    extra("termination_state", Type::Text, "termination_state", Some(("--termination-state", "string"))),
+    extra("syslog_host", Type::Text, "syslog_host", Some(("--host", "string"))),
+    provenance("source_file", Type::Text),
+    provenance("line_no", Type::Integer),
+    provenance("byte_offset", Type::Integer),
+];
+
+/// The field `name` names, by its own name, with `-` for `_`, or by an alias.
+pub fn find(name: &str) -> Option<&'static Field> {
+    let same = |known: &str| known.len() == name.len();
+    let named = |known: &str| same(known) && known.bytes().zip(name.bytes()).all(|(k, n)| k == n || (k, n) == (b'_', b'-'));
+    FIELDS.iter().find(|field| named(field.name) || field.aliases.iter().any(|alias| named(alias)))
+}
+
+/// The name a field is known by: the registry's for one it knows, else `name` as given, since a
+/// format-specific field is named as the format has it.
+pub fn canonical(name: &str) -> String {
+    find(name).map_or_else(|| name.to_string(), |field| field.name.to_string())
+}
+
+/// `canonical` as a clap value parser.
+pub fn parse(name: &str) -> Result<String, String> {
+    Ok(canonical(name))
+}
+
+/// The value of the field `name` in `entry`: a format-specific or derived field's from its
+/// extras, else through the registry. `None` for provenance, which the entry doesn't hold.
+pub fn value<'a>(entry: &'a Entry, name: &str) -> Option<Cow<'a, FieldValue>> {
+    if let Some(value) = entry.extras.get(name) {
+        return Some(Cow::Borrowed(value));
+    }
+    match find(name)?.access {
+        Access::Entry(read) => read(entry).map(Cow::Owned),
+        Access::Extra | Access::Provenance => None,
+    }
+}
+
+static DECLARED: OnceLock<Option<Vec<String>>> = OnceLock::new();
+
+/// Records the fields the log type declares, `None` when only the input can tell, for the rest
+/// of the run; only the first call has an effect.
+pub fn set_declared(fields: Option<Vec<String>>) {
+    let _ = DECLARED.set(fields);
+}
+
+fn declared() -> Option<&'static [String]> {
+    DECLARED.get().and_then(Option::as_deref)
+}
+
+impl Field {
+    /// Whether only a few log formats bring the field, which is then one of their own.
+    pub fn is_format(&self) -> bool {
+        matches!(self.access, Access::Extra) && self.source == self.name
+    }
+
+    /// Whether a log type with the fields `fields` has this one.
+    fn within(&self, fields: &[String]) -> bool {
+        self.source.is_empty() || fields.iter().any(|field| field == self.source)
+    }
+}
+
+/// The fields of the log type: the registry's it has, then the others it declares and then any
+/// more that an entry turned up, or with a log type that declares none, those `found`.
+fn available<'a>(found: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let found: Vec<String> = found.into_iter().map(str::to_string).collect();
+    let fields = declared().unwrap_or(&found);
+    let mut names: Vec<String> = FIELDS.iter().filter(|field| field.within(fields)).map(|field| field.name.to_string()).collect();
+    for name in fields.iter().chain(&found) {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    names
+}
+
+/// The error for a field that's neither the registry's nor among those `found` in an entry.
+pub fn unknown<'a>(name: &str, found: impl IntoIterator<Item = &'a str>) -> String {
+    format!("Unknown field {}; fields of this log type: {}", name, available(found).join(", "))
+}
+
+/// Whether the log type has the field `name`, whether or not a given entry does: it declares it,
+/// or it's one of the registry's that the log type has; with one that declares no fields, any of
+/// the registry's.
+pub fn known(name: &str) -> bool {
+    match (find(name), declared()) {
+        (Some(field), fields) => fields.is_none_or(|fields| field.within(fields)),
+        (None, fields) => fields.is_some_and(|fields| fields.iter().any(|field| field == name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_aliases_resolve_to_one_field() {
+        for (name, canonical_name) in [
+            ("user_agent", "user_agent"),
+            ("user-agent", "user_agent"),
+            ("ua", "user_agent"),
+            ("status", "status_code"),
+            ("status-code", "status_code"),
+            ("referrer-class", "referrer_class"),
+            ("request_time", "request_time"),
+            ("time-taken", "time-taken"),
+        ] {
+            assert_eq!(canonical(name), canonical_name, "{}", name);
+        }
+        let names = FIELDS.iter().flat_map(|field| std::iter::once(field.name).chain(field.aliases.iter().copied()));
+        let mut names: Vec<&str> = names.collect();
+        let count = names.len();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), count, "a name or alias names two fields");
+    }
+
+    #[test]
+    fn fields_are_read_from_the_entry_or_its_extras() {
+        let mut entry = Entry {
+            client: Some(Client::Host("crawler.example.com".to_string())),
+            status_code: Some(http::StatusCode::NOT_FOUND),
+            path: Some("/a".to_string()),
+            path_normalized: Some("/{id}".to_string()),
+            ..Entry::default()
+        };
+        entry.extras.insert("class".to_string(), FieldValue::Text("dynamic".to_string()));
+        let value = |name| value(&entry, name).map(|value| value.as_text());
+        assert_eq!(value("status"), Some("404".to_string()));
+        assert_eq!(value("hostname"), Some("crawler.example.com".to_string()));
+        assert_eq!(value("ip"), None);
+        assert_eq!(value("path"), Some("/{id}".to_string()));
+        assert_eq!(value("class"), Some("dynamic".to_string()));
+        assert_eq!(value("line_no"), None);
+    }
+}
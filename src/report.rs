@@ -0,0 +1,442 @@
+use crate::budget::Account;
+use crate::classify::{self, Class};
+use crate::entry::Entry;
+use crate::output::{html::Page, schema::ReportRow, table::Table};
+use crate::referrer;
+use crate::zone::Zone;
+use chrono::{NaiveDate, Timelike};
+use clap::ValueEnum;
+use std::collections::{BTreeMap, HashMap};
+
+// `report` reads a day or a week of logs once and prints what a daily or weekly traffic report
+// is made of, each section a table:
+//
+//     days       requests, bytes and 5xx responses per day, with days that had no requests
+//     pages      the 10 most requested pages: dynamic paths, see the classify module, without
+//                their query strings
+//     referrers  the 10 domains external referrers came from most, see the referrer module
+//     agents     the 10 user-agent families most requests came from, e.g. Chrome or Googlebot
+//     peak hour  the hour of the day with the most requests, over all the days
+//
+// Days and hours are local to --tz, which with a zone such as Europe/Berlin follows daylight
+// saving time, so the days the clocks change have 23 and 25 hours; without --tz they're those
+// of the offset each timestamp was logged with. Only matches count, so the filter options
+// narrow the report, e.g. to one virtual host. Tables are printed as the other commands print
+// them, as Markdown with `-o markdown`, as JSON records with `-o json`, or with `-o html` as a
+// page to pass around, the requests per day drawn as columns and the rankings as bars.
+
+/// Rows in a ranked section.
+const TOP: usize = 10;
+
+/// The most days the days section fills in between the first and the last; past that, a
+/// timestamp far off in another year would print a row for every day in between.
+const MAX_FILLED_DAYS: i64 = 1000;
+
+/// User-agent families by a token their user agents carry, tried in order: bots and tools, then
+/// browsers that name the browser they're built on after their own.
+const FAMILIES: &[(&str, &str)] = &[
+    ("Googlebot", "Googlebot"),
+    ("bingbot", "Bingbot"),
+    ("YandexBot", "YandexBot"),
+    ("Baiduspider", "Baiduspider"),
+    ("DuckDuckBot", "DuckDuckBot"),
+    ("Applebot", "Applebot"),
+    ("facebookexternalhit", "Facebook"),
+    ("Twitterbot", "Twitterbot"),
+    ("GPTBot", "GPTBot"),
+    ("curl/", "curl"),
+    ("Wget/", "Wget"),
+    ("python-requests/", "python-requests"),
+    ("Go-http-client/", "Go"),
+    ("okhttp/", "OkHttp"),
+    ("Edg/", "Edge"),
+    ("Edge/", "Edge"),
+    ("OPR/", "Opera"),
+    ("Opera", "Opera"),
+    ("SamsungBrowser/", "Samsung Internet"),
+    ("YaBrowser/", "Yandex Browser"),
+    ("Firefox/", "Firefox"),
+    ("FxiOS/", "Firefox"),
+    ("CriOS/", "Chrome"),
+    ("Chrome/", "Chrome"),
+    ("Chromium/", "Chrome"),
+    ("Safari/", "Safari"),
+    ("MSIE ", "Internet Explorer"),
+    ("Trident/", "Internet Explorer"),
+];
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum ReportOutput {
+    #[default]
+    Table,
+    /// Headed sections of Markdown tables, for a wiki page or an email
+    Markdown,
+    Json,
+    /// A self-contained HTML page of the sections, the days and rankings drawn as bars
+    Html,
+}
+
+/// The family of a user agent, e.g. `Chrome` for any version on any platform; `other bot` for
+/// an unknown one that calls itself a bot, crawler or spider.
+pub fn family(user_agent: Option<&str>) -> &'static str {
+    let Some(user_agent) = user_agent.filter(|agent| !agent.is_empty() && *agent != "-") else {
+        return "-";
+    };
+    if let Some((_, family)) = FAMILIES.iter().find(|(token, _)| user_agent.contains(token)) {
+        return family;
+    }
+    let lowercase = user_agent.to_ascii_lowercase();
+    match ["bot", "crawler", "spider"].iter().any(|word| lowercase.contains(word)) {
+        true => "other bot",
+        false => "other",
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Day {
+    requests: u64,
+    bytes: u64,
+    errors: u64,
+}
+
+/// The report, while the matches are read.
+pub struct Report {
+    tz: Option<Zone>,
+    requests: u64,
+    days: BTreeMap<NaiveDate, Day>,
+    untimed: Day,
+    hours: [u64; 24],
+    pages: HashMap<String, u64>,
+    referrers: HashMap<String, u64>,
+    agents: HashMap<&'static str, u64>,
+    memory: Account,
+}
+
+impl Report {
+    pub fn new(tz: Option<Zone>, memory: Account) -> Self {
+        Report {
+            tz,
+            requests: 0,
+            days: BTreeMap::new(),
+            untimed: Day::default(),
+            hours: [0; 24],
+            pages: HashMap::new(),
+            referrers: HashMap::new(),
+            agents: HashMap::new(),
+            memory,
+        }
+    }
+
+    pub fn add(&mut self, entry: &Entry) -> Result<(), String> {
+        self.requests += 1;
+        let day = match entry.timestamp {
+            Some(timestamp) => {
+                let local = self.tz.map_or(timestamp, |tz| tz.local(timestamp));
+                self.hours[local.hour() as usize] += 1;
+                self.days.entry(local.date_naive()).or_default()
+            }
+            None => &mut self.untimed,
+        };
+        day.requests += 1;
+        day.bytes += entry.bytes.unwrap_or(0);
+        day.errors += entry.status_code.is_some_and(|status| status.is_server_error()) as u64;
+
+        if let Some(path) = entry.match_path().as_deref().filter(|path| classify::classifier().classify(path) == Class::Dynamic) {
+            tally(&mut self.pages, &path[..path.find(['?', '#']).unwrap_or(path.len())], &mut self.memory)?;
+        }
+        if referrer::class(entry) == referrer::Class::External {
+            if let Some(domain) = entry.referrer.as_deref().and_then(referrer::domain) {
+                tally(&mut self.referrers, &domain, &mut self.memory)?;
+            }
+        }
+        *self.agents.entry(family(entry.user_agent.as_deref())).or_default() += 1;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Vec<Section> {
+        let mut days = Section::new("days", "Requests per day", &["day", "requests", "bytes", "5xx", "5xx rate"]);
+        let filled = match (self.days.first_key_value(), self.days.last_key_value()) {
+            (Some((&first, _)), Some((&last, _))) if (last - first).num_days() < MAX_FILLED_DAYS => {
+                let day = |date| (date, self.days.get(&date).copied().unwrap_or_default());
+                first.iter_days().take_while(|date| *date <= last).map(day).collect()
+            }
+            _ => self.days.into_iter().collect::<Vec<_>>(),
+        };
+        let untimed = (self.untimed.requests > 0).then(|| ("-".to_string(), self.untimed));
+        let mut total = Day::default();
+        for (key, day) in filled.into_iter().map(|(date, day)| (date.to_string(), day)).chain(untimed) {
+            total.requests += day.requests;
+            total.bytes += day.bytes;
+            total.errors += day.errors;
+            days.rows.push(day_row(key, day));
+        }
+        days.total = (!days.rows.is_empty()).then(|| day_row("total".to_string(), total));
+
+        let ranked = |name, title, header, counts: Vec<(String, u64)>| {
+            let mut section = Section::new(name, title, header);
+            section.rows = top(counts).into_iter().map(|(key, requests)| share_row(name, key, requests, self.requests)).collect();
+            section
+        };
+        let pages = ranked("pages", "Top pages", &["page", "requests", "share"], self.pages.into_iter().collect());
+        let referrers =
+            ranked("referrers", "Top external referrers", &["domain", "requests", "share"], self.referrers.into_iter().collect());
+        let agents = self.agents.into_iter().map(|(family, count)| (family.to_string(), count)).collect();
+        let agents = ranked("agents", "Top user-agent families", &["family", "requests", "share"], agents);
+
+        let mut peak = Section::new("peak_hour", "Peak hour", &["hour", "requests", "share"]);
+        let timed = self.hours.iter().sum();
+        // The earliest of the busiest hours.
+        let busiest =
+            self.hours.iter().enumerate().filter(|(_, &count)| count > 0).max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(&a.0)));
+        if let Some((hour, &requests)) = busiest {
+            peak.rows.push(share_row("peak_hour", format!("{:02}:00", hour), requests, timed));
+        }
+        vec![days, pages, referrers, agents, peak]
+    }
+}
+
+/// Counts one more request for `key`, charging its memory when it's new.
+fn tally(counts: &mut HashMap<String, u64>, key: &str, memory: &mut Account) -> Result<(), String> {
+    match counts.get_mut(key) {
+        Some(count) => *count += 1,
+        None => {
+            memory.charge(key.len() + size_of::<(String, u64)>())?;
+            counts.insert(key.to_string(), 1);
+        }
+    }
+    Ok(())
+}
+
+/// The TOP keys with the most requests, ties in key order.
+fn top(mut counts: Vec<(String, u64)>) -> Vec<(String, u64)> {
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(TOP);
+    counts
+}
+
+fn percent(part: u64, whole: u64) -> Option<f64> {
+    (whole > 0).then(|| part as f64 * 100.0 / whole as f64)
+}
+
+fn day_row(key: String, day: Day) -> ReportRow {
+    ReportRow {
+        section: "days",
+        key,
+        requests: day.requests,
+        bytes: Some(day.bytes),
+        errors: Some(day.errors),
+        error_rate: Some(percent(day.errors, day.requests)),
+        share: None,
+    }
+}
+
+fn share_row(section: &'static str, key: String, requests: u64, whole: u64) -> ReportRow {
+    ReportRow { section, key, requests, bytes: None, errors: None, error_rate: None, share: percent(requests, whole) }
+}
+
+/// A part of the report: a titled table whose rows are also its JSON records, and a total row
+/// for the table alone.
+pub struct Section {
+    name: &'static str,
+    title: &'static str,
+    header: &'static [&'static str],
+    rows: Vec<ReportRow>,
+    total: Option<ReportRow>,
+}
+
+impl Section {
+    fn new(name: &'static str, title: &'static str, header: &'static [&'static str]) -> Self {
+        Section { name, title, header, rows: Vec::new(), total: None }
+    }
+
+    fn table(&self) -> Table {
+        let mut table = Table::new(self.header);
+        for row in self.rows.iter().chain(&self.total) {
+            table.row(cells(row));
+        }
+        if self.rows.is_empty() {
+            table.note(format!("no {}", self.name.replace('_', " ")));
+        }
+        table
+    }
+}
+
+/// A row's cells in the order of its section's header: the key, the requests, and the figures
+/// the section has.
+fn cells(row: &ReportRow) -> Vec<String> {
+    let percent = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |value| format!("{:.1}%", value));
+    let mut cells = vec![row.key.clone(), row.requests.to_string()];
+    cells.extend(row.bytes.map(|bytes| bytes.to_string()));
+    cells.extend(row.errors.map(|errors| errors.to_string()));
+    cells.extend(row.error_rate.map(percent));
+    cells.extend(row.share.map(Some).map(percent));
+    cells
+}
+
+pub fn render(sections: &[Section], output: ReportOutput) -> Result<String, String> {
+    if matches!(output, ReportOutput::Html) {
+        return Ok(page(sections));
+    }
+    let mut out = String::new();
+    for (index, section) in sections.iter().enumerate() {
+        match output {
+            ReportOutput::Table => {
+                if index > 0 {
+                    out.push('\n');
+                }
+                out += &format!("{}\n{}", section.title, section.table().render());
+            }
+            ReportOutput::Markdown => {
+                if index > 0 {
+                    out.push('\n');
+                }
+                out += &format!("## {}\n\n{}", section.title, section.table().markdown());
+            }
+            ReportOutput::Json => {
+                for row in &section.rows {
+                    out += &serde_json::to_string(row).map_err(|e| e.to_string())?;
+                    out.push('\n');
+                }
+            }
+            ReportOutput::Html => unreachable!("HTML is rendered as one page"),
+        }
+    }
+    Ok(out)
+}
+
+fn page(sections: &[Section]) -> String {
+    let mut page = Page::default();
+    for section in sections {
+        page.heading(section.title);
+        let counts = section.rows.iter().map(|row| (row.key.clone(), row.requests));
+        match section.name {
+            "days" => {
+                let days: Vec<(String, f64)> = counts.map(|(day, requests)| (day, requests as f64)).collect();
+                page.columns(&days, days.iter().map(|&(_, requests)| requests).fold(0.0, f64::max));
+            }
+            "peak_hour" => {}
+            _ => page.bars(&counts.collect::<Vec<_>>()),
+        }
+        page.table(&section.table());
+    }
+    page.render("Traffic report")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::MemoryBudget;
+    use chrono::DateTime;
+
+    fn request(time: &str, path: &str, status: u16, referrer: Option<&str>, user_agent: &str) -> Entry {
+        Entry {
+            timestamp: Some(DateTime::parse_from_rfc3339(time).unwrap()),
+            path: Some(path.to_string()),
+            status_code: Some(http::StatusCode::from_u16(status).unwrap()),
+            bytes: Some(1000),
+            referrer: referrer.map(str::to_string),
+            user_agent: Some(user_agent.to_string()),
+            ..Entry::default()
+        }
+    }
+
+    fn report(tz: &str, entries: &[Entry]) -> Vec<Section> {
+        let mut report = Report::new(Some(crate::zone::parse(tz).unwrap()), MemoryBudget::new(1 << 20).account("report", ""));
+        for entry in entries {
+            report.add(entry).unwrap();
+        }
+        report.finish()
+    }
+
+    fn keys(section: &Section) -> Vec<(&str, u64)> {
+        section.rows.iter().map(|row| (row.key.as_str(), row.requests)).collect()
+    }
+
+    #[test]
+    fn days_run_from_local_midnight_to_midnight() {
+        let chrome = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36";
+        let entries = [
+            // 23:30 in Berlin on the 30th, winter time.
+            request("2024-03-30T22:30:00Z", "/", 200, None, chrome),
+            // The 31st has 23 hours in Berlin: from 23:00 UTC to 22:00 UTC.
+            request("2024-03-30T23:00:00Z", "/", 200, None, chrome),
+            request("2024-03-31T21:59:59Z", "/", 503, None, chrome),
+            request("2024-03-31T22:00:00Z", "/", 200, None, chrome),
+            // Nothing on April 1st or 2nd.
+            request("2024-04-03T10:00:00Z", "/", 200, None, chrome),
+        ];
+        let sections = report("CET-1CEST,M3.5.0,M10.5.0/3", &entries);
+        assert_eq!(
+            keys(&sections[0]),
+            [("2024-03-30", 1), ("2024-03-31", 2), ("2024-04-01", 1), ("2024-04-02", 0), ("2024-04-03", 1)]
+        );
+        assert_eq!(sections[0].rows[1].error_rate, Some(Some(50.0)));
+        assert_eq!(sections[0].rows[3].error_rate, Some(None));
+        assert_eq!(sections[0].total.as_ref().map(|total| (total.requests, total.bytes)), Some((5, Some(5000))));
+        // In UTC the same requests fall on other days.
+        let sections = report("UTC", &entries);
+        assert_eq!(keys(&sections[0])[..2], [("2024-03-30", 2), ("2024-03-31", 2)]);
+    }
+
+    #[test]
+    fn sections_rank_pages_referrers_and_agents() {
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0";
+        let entries = [
+            request("2024-06-01T08:00:00Z", "/pricing?plan=pro", 200, Some("https://news.example.net/a"), firefox),
+            request("2024-06-01T08:10:00Z", "/pricing", 200, Some("https://www.google.com/"), firefox),
+            request("2024-06-01T08:20:00Z", "/static/app.css", 200, Some("https://news.example.net/b"), firefox),
+            request("2024-06-01T09:00:00Z", "/docs", 200, None, "curl/8.4.0"),
+            request("2024-06-01T09:30:00Z", "/about", 200, None, "ExampleCrawler/1.0"),
+        ];
+        let sections = report("+02:00", &entries);
+        let names: Vec<&str> = sections.iter().map(|section| section.name).collect();
+        assert_eq!(names, ["days", "pages", "referrers", "agents", "peak_hour"]);
+        assert_eq!(keys(&sections[1]), [("/pricing", 2), ("/about", 1), ("/docs", 1)]);
+        assert_eq!(sections[1].rows[0].share, Some(40.0));
+        assert_eq!(keys(&sections[2]), [("news.example.net", 2)]);
+        assert_eq!(keys(&sections[3]), [("Firefox", 3), ("curl", 1), ("other bot", 1)]);
+        assert_eq!(keys(&sections[4]), [("10:00", 3)]);
+
+        let empty = report("UTC", &[]);
+        assert_eq!(render(&empty, ReportOutput::Json).unwrap(), "");
+        assert_eq!(empty[2].table().delimited(&Default::default()), "domain\trequests\tshare\nno referrers\n");
+    }
+
+    #[test]
+    fn sections_render_as_markdown() {
+        let entries = [request("2024-06-01T08:00:00Z", "/a_b", 200, None, "-")];
+        let sections = report("UTC", &entries);
+        let markdown = render(&sections[..2], ReportOutput::Markdown).unwrap();
+        assert_eq!(
+            markdown,
+            "## Requests per day\n\n\
+             | day | requests | bytes | 5xx | 5xx rate |\n\
+             | --- | ---: | ---: | ---: | ---: |\n\
+             | 2024-06-01 | 1 | 1000 | 0 | 0.0% |\n\
+             | total | 1 | 1000 | 0 | 0.0% |\n\
+             \n\
+             ## Top pages\n\n\
+             | page | requests | share |\n\
+             | --- | ---: | ---: |\n\
+             | /a\\_b | 1 | 100.0% |\n"
+        );
+    }
+
+    #[test]
+    fn user_agents_are_told_apart_by_family() {
+        let cases = [
+            (Some("Mozilla/5.0 (Windows NT 10.0) AppleWebKit/537.36 Chrome/120.0 Safari/537.36 Edg/120.0"), "Edge"),
+            (Some("Mozilla/5.0 (iPhone) AppleWebKit/605.1.15 Version/17.0 Mobile/15E148 Safari/604.1"), "Safari"),
+            (Some("Mozilla/5.0 (iPhone) AppleWebKit/605.1.15 CriOS/120.0 Mobile/15E148 Safari/604.1"), "Chrome"),
+            (Some("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)"), "Googlebot"),
+            (Some("python-requests/2.31.0"), "python-requests"),
+            (Some("Mozilla/5.0 (compatible; SomeSpider/1.0)"), "other bot"),
+            (Some("Lynx/2.9"), "other"),
+            (Some("-"), "-"),
+            (None, "-"),
+        ];
+        for (user_agent, expected) in cases {
+            assert_eq!(family(user_agent), expected, "family of {:?}", user_agent);
+        }
+    }
+}
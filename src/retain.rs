@@ -0,0 +1,164 @@
+use crate::budget::{self, Account};
+use crate::entry::Match;
+use crate::group::{self, GroupBy};
+use crate::zone::Zone;
+use std::collections::{btree_map, BTreeMap};
+
+// --latest-per and --oldest-per hold back the matches and keep one per key, e.g. each client's
+// most recent request, printing them when the input ends. Memory is one match per distinct key,
+// so --max-keys stops a key like path from keeping most of the input. Timestamps are compared as
+// parsed, and on a tie the later line in input order is kept either way. An entry without a
+// timestamp is only kept while its key has none with one.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Keep {
+    Latest,
+    Oldest,
+}
+
+impl Keep {
+    fn flag(self) -> &'static str {
+        match self {
+            Keep::Latest => "--latest-per",
+            Keep::Oldest => "--oldest-per",
+        }
+    }
+
+    /// Whether `found` takes the place of `kept`, the match held for the same key.
+    fn replaces(self, found: &Match, kept: &Match) -> bool {
+        match (found.entry.timestamp, kept.entry.timestamp) {
+            (Some(found), Some(kept)) => match self {
+                Keep::Latest => found >= kept,
+                Keep::Oldest => found <= kept,
+            },
+            (found, kept) => found.is_some() || kept.is_none(),
+        }
+    }
+}
+
+pub struct Retained {
+    keep: Keep,
+    by: Vec<GroupBy>,
+    tz: Option<Zone>,
+    max_keys: usize,
+    kept: BTreeMap<Vec<String>, Match>,
+    /// What `kept` holds of the run's --max-memory.
+    memory: Option<Account>,
+}
+
+impl Retained {
+    pub fn new(keep: Keep, by: Vec<GroupBy>, tz: Option<Zone>, max_keys: usize) -> Self {
+        Retained { keep, by, tz, max_keys, kept: BTreeMap::new(), memory: None }
+    }
+
+    /// Charges each match held to `memory`.
+    pub fn budgeted(self, memory: Account) -> Self {
+        Retained { memory: Some(memory), ..self }
+    }
+
+    /// Holds `found` if it's the newest, or oldest, of its key so far, failing once there would
+    /// be more than --max-keys.
+    pub fn add(&mut self, found: Match) -> Result<(), String> {
+        let key = group::key(&self.by, &found.entry, self.tz);
+        if !self.kept.contains_key(&key) && self.kept.len() >= self.max_keys {
+            return Err(format!(
+                "{} {} has more than {} distinct keys; narrow the filter or raise --max-keys",
+                self.keep.flag(),
+                group::names(&self.by).join("+"),
+                self.max_keys
+            ));
+        }
+        let held = match self.kept.entry(key) {
+            btree_map::Entry::Vacant(slot) => {
+                let held = budget::strings(slot.key()) + found.line.len() + std::mem::size_of::<Match>();
+                slot.insert(found);
+                held
+            }
+            btree_map::Entry::Occupied(mut slot) => {
+                if !self.keep.replaces(&found, slot.get()) {
+                    return Ok(());
+                }
+                // Only a longer line holds more; a shorter one is left charged as it was.
+                let grown = found.line.len().saturating_sub(slot.get().line.len());
+                slot.insert(found);
+                grown
+            }
+        };
+        match &mut self.memory {
+            Some(memory) => memory.charge(held),
+            None => Ok(()),
+        }
+    }
+
+    /// The matches kept, ordered by key.
+    pub fn into_matches(self) -> impl Iterator<Item = Match> {
+        self.kept.into_values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::{Entry, Provenance};
+    use std::path::PathBuf;
+
+    fn found(ip: &str, timestamp: Option<&str>, line_no: usize) -> Match {
+        Match {
+            line: String::new(),
+            entry: Entry {
+                client: Some(ip.parse().unwrap()),
+                timestamp: timestamp.map(|t| t.parse().unwrap()),
+                ..Entry::default()
+            },
+            provenance: Provenance { source_file: PathBuf::from("access.log"), line_no, byte_offset: 0 },
+        }
+    }
+
+    fn kept(keep: Keep, matches: Vec<Match>) -> Vec<(String, usize)> {
+        let mut retained = Retained::new(keep, vec![GroupBy::Ip], None, 10);
+        for found in matches {
+            retained.add(found).unwrap();
+        }
+        retained.into_matches().map(|found| (found.entry.ip().unwrap().to_string(), found.provenance.line_no)).collect()
+    }
+
+    fn input() -> Vec<Match> {
+        vec![
+            found("10.0.0.2", None, 1),
+            found("10.0.0.1", Some("2023-02-12T14:05:00Z"), 2),
+            found("10.0.0.2", Some("2023-02-12T14:06:00Z"), 3),
+            found("10.0.0.1", Some("2023-02-12T14:04:00Z"), 4),
+            found("10.0.0.2", None, 5),
+            found("10.0.0.1", Some("2023-02-12T14:05:00Z"), 6),
+            found("10.0.0.3", Some("2023-02-12T14:07:00+02:00"), 7),
+            found("10.0.0.3", Some("2023-02-12T12:07:00Z"), 8),
+        ]
+    }
+
+    #[test]
+    fn the_newest_match_per_key_is_kept() {
+        assert_eq!(kept(Keep::Latest, input()), [
+            ("10.0.0.1".to_string(), 6),
+            ("10.0.0.2".to_string(), 3),
+            ("10.0.0.3".to_string(), 8)
+        ]);
+    }
+
+    #[test]
+    fn the_oldest_match_per_key_is_kept() {
+        assert_eq!(kept(Keep::Oldest, input()), [
+            ("10.0.0.1".to_string(), 4),
+            ("10.0.0.2".to_string(), 3),
+            ("10.0.0.3".to_string(), 8)
+        ]);
+    }
+
+    #[test]
+    fn keys_are_capped() {
+        let mut retained = Retained::new(Keep::Latest, vec![GroupBy::Ip, GroupBy::Path], None, 1);
+        retained.add(found("10.0.0.1", None, 1)).unwrap();
+        retained.add(found("10.0.0.1", None, 2)).unwrap();
+        let error = retained.add(found("10.0.0.2", None, 3)).unwrap_err();
+        assert_eq!(error, "--latest-per ip+path has more than 1 distinct keys; narrow the filter or raise --max-keys");
+    }
+}
@@ -0,0 +1,614 @@
+use crate::budget::Account;
+use crate::diagnostics::Error;
+use crate::duration::HumanDuration;
+use crate::entry::Entry;
+use crate::expression::Expression;
+use crate::group::GroupBy;
+use crate::output::{self, table::Table};
+use crate::stats::{percentile, Metric};
+use crate::zone::Zone;
+use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+// `check --rules` evaluates service level objectives over the matches in one pass and fails the
+// run when any is missed, for cron or an alert router. A rules file holds named rules, each a
+// block of `key = value` pairs separated by commas or newlines, with `#` comments:
+//
+//     error_rate { filter = 'status >= 500', max_ratio = 0.01 }
+//     p99_bytes { metric = 'bytes', by = 'path-prefix', max_p99 = 5000000 }
+//     abuse { group = 'ip', window = '1m', max_count = 300 }
+//
+// What a rule checks follows from its keys: `metric` summarizes a metric, overall or per `by`
+// group, `group` counts matches per key value in windows of log time, and otherwise `filter`
+// counts the matches it selects, as a share of all matches with `max_ratio`. A `filter`, a
+// `--where` expression, narrows what the other two look at. Each rule has one threshold.
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum CheckOutput {
+    #[default]
+    Table,
+    Json,
+}
+
+/// What a metric rule compares with its threshold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Statistic {
+    Mean,
+    Percentile(u8),
+}
+
+#[derive(Debug)]
+enum Threshold {
+    /// Matches of the filter as a share of all matches, from 0 to 1.
+    Ratio(f64),
+    /// Matches of the filter.
+    Count(u64),
+    /// The statistic of a metric, overall or in the worst group.
+    Metric { metric: Metric, by: Option<GroupBy>, statistic: Statistic, max: f64 },
+    /// Matches of one key value within one window of log time.
+    Rate { group: GroupBy, window: i64, max: u64 },
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    name: String,
+    filter: Option<Expression>,
+    threshold: Threshold,
+}
+
+impl Rule {
+    /// The threshold's key in the rules file, e.g. `max_p99`.
+    fn key(&self) -> String {
+        match &self.threshold {
+            Threshold::Ratio(_) => "max_ratio".to_string(),
+            Threshold::Count(_) | Threshold::Rate { .. } => "max_count".to_string(),
+            Threshold::Metric { statistic: Statistic::Mean, .. } => "max_mean".to_string(),
+            Threshold::Metric { statistic: Statistic::Percentile(p), .. } => format!("max_p{}", p),
+        }
+    }
+
+    fn limit(&self) -> f64 {
+        match self.threshold {
+            Threshold::Ratio(max) | Threshold::Metric { max, .. } => max,
+            Threshold::Count(max) | Threshold::Rate { max, .. } => max as f64,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Text(String),
+    Number(f64),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Open,
+    Close,
+    Equals,
+    Comma,
+}
+
+/// The tokens of a rules file, each with its line.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, (usize, String)> {
+    let mut tokens = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let line_no = number + 1;
+        let mut chars = line.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            let token = match c {
+                '#' => break,
+                c if c.is_whitespace() => continue,
+                '{' => Token::Open,
+                '}' => Token::Close,
+                '=' => Token::Equals,
+                ',' => Token::Comma,
+                '\'' | '"' => {
+                    let text: String = chars.by_ref().map(|(_, c)| c).take_while(|&d| d != c).collect();
+                    if !line[start + 1..].contains(c) {
+                        return Err((line_no, format!("unterminated string {}{}", c, text)));
+                    }
+                    Token::Text(text)
+                }
+                _ => {
+                    let mut end = start + c.len_utf8();
+                    while let Some(&(at, d)) = chars.peek() {
+                        if d.is_whitespace() || "{}=,#'\"".contains(d) {
+                            break;
+                        }
+                        end = at + d.len_utf8();
+                        chars.next();
+                    }
+                    Token::Word(line[start..end].to_string())
+                }
+            };
+            tokens.push((line_no, token));
+        }
+    }
+    Ok(tokens)
+}
+
+/// A rule as written: its name, line and keys in order.
+struct Block {
+    name: String,
+    line: usize,
+    pairs: Vec<(String, Value)>,
+}
+
+fn blocks(source: &str) -> Result<Vec<Block>, (usize, String)> {
+    let tokens = tokenize(source)?;
+    let last = source.lines().count().max(1);
+    let mut tokens = tokens.into_iter().peekable();
+    let mut blocks = Vec::new();
+    let expected = |what: &str, found: Option<(usize, Token)>| match found {
+        Some((line, token)) => (line, format!("expected {}, found {}", what, describe(&token))),
+        None => (last, format!("expected {}, found the end of the file", what)),
+    };
+    while let Some((line, token)) = tokens.next() {
+        let Token::Word(name) = token else {
+            return Err(expected("a rule name", Some((line, token))));
+        };
+        if !is_name(&name) {
+            return Err((line, format!("rule names are letters, digits, `_` and `-`, not {:?}", name)));
+        }
+        match tokens.next() {
+            Some((_, Token::Open)) => {}
+            other => return Err(expected(&format!("`{{` after {}", name), other)),
+        }
+        let mut pairs = Vec::new();
+        loop {
+            let key = match tokens.next() {
+                Some((_, Token::Close)) => break,
+                Some((_, Token::Comma)) if !pairs.is_empty() => continue,
+                Some((_, Token::Word(key))) if is_name(&key) => key,
+                other => return Err(expected(&format!("a key or `}}` in {}", name), other)),
+            };
+            match tokens.next() {
+                Some((_, Token::Equals)) => {}
+                other => return Err(expected(&format!("`=` after {}", key), other)),
+            }
+            let value = match tokens.next() {
+                Some((_, Token::Text(text))) => Value::Text(text),
+                Some((line, Token::Word(word))) => match word.parse::<f64>() {
+                    Ok(number) if number.is_finite() => Value::Number(number),
+                    _ => return Err((line, format!("{} = {}: quote text, e.g. {} = '{}'", key, word, key, word))),
+                },
+                other => return Err(expected(&format!("a value for {}", key), other)),
+            };
+            if pairs.iter().any(|(k, _)| *k == key) {
+                return Err((line, format!("{} sets {} twice", name, key)));
+            }
+            pairs.push((key, value));
+        }
+        blocks.push(Block { name, line, pairs });
+    }
+    Ok(blocks)
+}
+
+fn is_name(word: &str) -> bool {
+    word.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(word) => format!("{:?}", word),
+        Token::Text(text) => format!("'{}'", text),
+        Token::Open => "`{`".to_string(),
+        Token::Close => "`}`".to_string(),
+        Token::Equals => "`=`".to_string(),
+        Token::Comma => "`,`".to_string(),
+    }
+}
+
+const KEYS: &str = "filter, max_ratio, max_count, metric, by, max_mean, max_p<N>, group, window";
+
+impl Block {
+    fn text(&self, key: &str) -> Result<Option<&str>, String> {
+        match self.pairs.iter().find(|(k, _)| k == key) {
+            Some((_, Value::Text(text))) => Ok(Some(text)),
+            Some((_, Value::Number(number))) => Err(format!("{} = {}: expected quoted text", key, number)),
+            None => Ok(None),
+        }
+    }
+
+    fn number(&self, key: &str) -> Result<Option<f64>, String> {
+        match self.pairs.iter().find(|(k, _)| k == key) {
+            Some((_, Value::Number(number))) if *number >= 0.0 => Ok(Some(*number)),
+            Some((_, Value::Number(number))) => Err(format!("{} = {}: expected a number of at least 0", key, number)),
+            Some((_, Value::Text(text))) => Err(format!("{} = '{}': expected a number, unquoted", key, text)),
+            None => Ok(None),
+        }
+    }
+
+    fn count(&self, key: &str) -> Result<Option<u64>, String> {
+        match self.number(key)? {
+            Some(number) if number.fract() != 0.0 => Err(format!("{} = {}: expected a whole number", key, number)),
+            number => Ok(number.map(|number| number as u64)),
+        }
+    }
+
+    fn rule(&self) -> Result<Rule, String> {
+        let thresholds: Vec<&str> = self
+            .pairs
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .filter(|key| key.starts_with("max_"))
+            .collect();
+        if let Some((key, _)) = self.pairs.iter().find(|(key, _)| !known(key)) {
+            return Err(format!("unknown key {}; rules take {}", key, KEYS));
+        }
+        let threshold = match thresholds[..] {
+            [threshold] => threshold,
+            [] => return Err("no threshold; give one of max_ratio, max_count, max_mean or max_p<N>".to_string()),
+            _ => return Err(format!("one threshold per rule, not {}", thresholds.join(" and "))),
+        };
+        let filter = self.text("filter")?.map(|source| source.parse::<Expression>().map_err(|e| format!("filter: {}", e))).transpose()?;
+        let value_enum = |key: &str| -> Result<Option<GroupBy>, String> {
+            self.text(key)?.map(|name| GroupBy::from_str(name, true).map_err(|_| format!("{} = '{}': {}", key, name, choices::<GroupBy>()))).transpose()
+        };
+        let unused = |keys: &[&str]| match keys.iter().find(|key| self.pairs.iter().any(|(k, _)| k == *key)) {
+            Some(key) => Err(format!("{} doesn't apply to a {} rule", key, threshold)),
+            None => Ok(()),
+        };
+        let threshold = match (self.text("metric")?, value_enum("group")?) {
+            (Some(_), Some(_)) => return Err("a rule checks a metric or a group's rate, not both".to_string()),
+            (Some(name), None) => {
+                unused(&["window", "max_ratio", "max_count"])?;
+                let metric = Metric::from_str(name, true).map_err(|_| format!("metric = '{}': {}", name, choices::<Metric>()))?;
+                let statistic = match &threshold["max_".len()..] {
+                    "mean" => Statistic::Mean,
+                    p => match p.strip_prefix('p').and_then(|p| p.parse::<u8>().ok()) {
+                        Some(p @ 1..=100) => Statistic::Percentile(p),
+                        _ => return Err(format!("{} isn't a statistic; use max_mean or max_p<N> with N from 1 to 100", threshold)),
+                    },
+                };
+                let max = self.number(threshold)?.expect("the threshold is set");
+                Threshold::Metric { metric, by: value_enum("by")?, statistic, max }
+            }
+            (None, Some(group)) => {
+                unused(&["by", "max_ratio"])?;
+                if threshold != "max_count" {
+                    return Err(format!("a group rule counts matches per window; use max_count, not {}", threshold));
+                }
+                let window = self.text("window")?.ok_or("a group rule needs a window, e.g. window = '1m'")?;
+                let window = window.parse::<HumanDuration>().map_err(|e| format!("window = '{}': {}", window, e))?;
+                if window.0.as_secs() == 0 {
+                    return Err("window must be at least 1s".to_string());
+                }
+                Threshold::Rate { group, window: window.0.as_secs() as i64, max: self.count("max_count")?.expect("the threshold is set") }
+            }
+            (None, None) => {
+                unused(&["by", "window"])?;
+                match threshold {
+                    "max_ratio" if filter.is_none() => return Err("max_ratio needs a filter to take the share of".to_string()),
+                    "max_ratio" => match self.number("max_ratio")?.expect("the threshold is set") {
+                        ratio if ratio <= 1.0 => Threshold::Ratio(ratio),
+                        ratio => return Err(format!("max_ratio = {}: a share of the matches, from 0 to 1", ratio)),
+                    },
+                    "max_count" => Threshold::Count(self.count("max_count")?.expect("the threshold is set")),
+                    _ => return Err(format!("{} needs a metric, e.g. metric = 'bytes'", threshold)),
+                }
+            }
+        };
+        Ok(Rule { name: self.name.clone(), filter, threshold })
+    }
+}
+
+fn known(key: &str) -> bool {
+    let percentile = key.strip_prefix("max_p").is_some_and(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()));
+    percentile || matches!(key, "filter" | "metric" | "by" | "group" | "window" | "max_ratio" | "max_count" | "max_mean")
+}
+
+pub fn choices<T: ValueEnum>() -> String {
+    let names: Vec<String> = T::value_variants()
+        .iter()
+        .filter_map(|v| v.to_possible_value())
+        .map(|v| v.get_name().to_string())
+        .collect();
+    format!("expected one of {}", names.join(", "))
+}
+
+/// Reads the rules in `path`, reporting every malformed rule at once, or the first syntax error.
+pub fn load(path: &Path) -> Result<Vec<Rule>, Vec<Error>> {
+    let source = std::fs::read_to_string(path).map_err(|e| vec![Error::Io { file: path.to_path_buf(), message: e.to_string() }])?;
+    let at = |line: usize, message: String| Error::Line { file: path.to_path_buf(), line, message };
+    let blocks = blocks(&source).map_err(|(line, message)| vec![at(line, message)])?;
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    for (index, block) in blocks.iter().enumerate() {
+        if blocks[..index].iter().any(|earlier| earlier.name == block.name) {
+            errors.push(at(block.line, format!("{}: a rule of that name comes earlier", block.name)));
+            continue;
+        }
+        match block.rule() {
+            Ok(rule) => rules.push(rule),
+            Err(message) => errors.push(at(block.line, format!("{}: {}", block.name, message))),
+        }
+    }
+    match (errors.is_empty(), rules.is_empty()) {
+        (true, false) => Ok(rules),
+        (true, true) => Err(vec![Error::Other { message: format!("{}: no rules", path.display()) }]),
+        (false, _) => Err(errors),
+    }
+}
+
+/// What a rule has seen so far.
+enum Tally {
+    Matches { selected: u64, all: u64 },
+    Values(BTreeMap<String, Vec<f64>>),
+    Windows(HashMap<(String, i64), u64>),
+}
+
+/// Every rule's tally, fed each match once.
+pub struct Checks {
+    rules: Vec<(Rule, Tally)>,
+    tz: Option<Zone>,
+    memory: Account,
+}
+
+impl Checks {
+    pub fn new(rules: Vec<Rule>, tz: Option<Zone>, memory: Account) -> Self {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                let tally = match rule.threshold {
+                    Threshold::Ratio(_) | Threshold::Count(_) => Tally::Matches { selected: 0, all: 0 },
+                    Threshold::Metric { .. } => Tally::Values(BTreeMap::new()),
+                    Threshold::Rate { .. } => Tally::Windows(HashMap::new()),
+                };
+                (rule, tally)
+            })
+            .collect();
+        Checks { rules, tz, memory }
+    }
+
+    /// Checks the filters' format-specific fields against the first match.
+    pub fn check_fields(&self, entry: &Entry) -> Result<(), String> {
+        self.rules.iter().filter_map(|(rule, _)| rule.filter.as_ref()).try_for_each(|filter| filter.check_fields(entry))
+    }
+
+    pub fn add(&mut self, entry: &Entry) -> Result<(), String> {
+        for (rule, tally) in &mut self.rules {
+            let selected = rule.filter.as_ref().is_none_or(|filter| filter.is_match(entry));
+            match (tally, &rule.threshold) {
+                (Tally::Matches { selected: count, all }, _) => {
+                    *all += 1;
+                    *count += selected as u64;
+                }
+                (_, _) if !selected => {}
+                (Tally::Values(groups), Threshold::Metric { metric, by, .. }) => {
+                    let Some(value) = metric.value(entry) else {
+                        continue;
+                    };
+                    let key = by.map(|by| by.value(entry, self.tz)).unwrap_or_default();
+                    let held = if groups.contains_key(&key) { 0 } else { key.len() + size_of::<(String, Vec<f64>)>() };
+                    self.memory.charge(held + size_of::<f64>())?;
+                    groups.entry(key).or_default().push(value);
+                }
+                (Tally::Windows(windows), Threshold::Rate { group, window, .. }) => {
+                    let Some(timestamp) = entry.timestamp else {
+                        continue;
+                    };
+                    let start = timestamp.timestamp().div_euclid(*window) * *window;
+                    let key = (group.value(entry, self.tz), start);
+                    if !windows.contains_key(&key) {
+                        self.memory.charge(key.0.len() + size_of::<((String, i64), u64)>())?;
+                    }
+                    *windows.entry(key).or_default() += 1;
+                }
+                _ => unreachable!("tallies are made for their rule's threshold"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Each rule's outcome, in the order of the rules file.
+    pub fn finish(self) -> Vec<Outcome> {
+        let tz = self.tz.unwrap_or_else(Zone::utc);
+        self.rules
+            .into_iter()
+            .map(|(rule, tally)| {
+                let (observed, group, window_start) = match (tally, &rule.threshold) {
+                    (Tally::Matches { selected, all }, Threshold::Ratio(_)) => {
+                        ((all > 0).then(|| selected as f64 / all as f64), None, None)
+                    }
+                    (Tally::Matches { selected, .. }, _) => (Some(selected as f64), None, None),
+                    (Tally::Values(groups), Threshold::Metric { by, statistic, .. }) => {
+                        let worst = groups
+                            .into_iter()
+                            .map(|(key, mut values)| {
+                                values.sort_by(f64::total_cmp);
+                                let value = match statistic {
+                                    Statistic::Mean => values.iter().sum::<f64>() / values.len() as f64,
+                                    Statistic::Percentile(p) => percentile(&values, *p as f64),
+                                };
+                                (value, key)
+                            })
+                            .max_by(|a, b| a.0.total_cmp(&b.0).then(b.1.cmp(&a.1)));
+                        match worst {
+                            Some((value, key)) => (Some(value), by.map(|by| format!("{}={}", by.name(), key)), None),
+                            None => (None, None, None),
+                        }
+                    }
+                    (Tally::Windows(windows), Threshold::Rate { group, .. }) => {
+                        let busiest = windows.into_iter().max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)));
+                        match busiest {
+                            Some(((key, start), count)) => {
+                                let start = Some(tz.at(start));
+                                (Some(count as f64), Some(format!("{}={}", group.name(), key)), start)
+                            }
+                            None => (None, None, None),
+                        }
+                    }
+                    _ => unreachable!("tallies are made for their rule's threshold"),
+                };
+                Outcome {
+                    passed: observed.is_none_or(|observed| observed <= rule.limit()),
+                    threshold: rule.key(),
+                    limit: rule.limit(),
+                    observed,
+                    group,
+                    window_start,
+                    rule: rule.name,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A rule's verdict. A rule with nothing to measure, such as a metric no match had, passes.
+#[derive(Serialize)]
+pub struct Outcome {
+    pub rule: String,
+    pub passed: bool,
+    /// The threshold's key, e.g. `max_ratio`.
+    pub threshold: String,
+    pub limit: f64,
+    pub observed: Option<f64>,
+    /// Where the observed value was worst: the --by group of a metric rule or the key value of
+    /// a group rule, e.g. `ip=10.0.0.7`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// The start of a group rule's busiest window.
+    #[serde(serialize_with = "output::serialize_timestamp", skip_serializing_if = "Option::is_none")]
+    pub window_start: Option<DateTime<FixedOffset>>,
+}
+
+/// `0.0123` rather than `0.012345678`; whole numbers without a fraction.
+fn number(value: f64) -> String {
+    let rounded = (value * 10_000.0).round() / 10_000.0;
+    format!("{}", rounded)
+}
+
+pub fn render(outcomes: &[Outcome], output: CheckOutput) -> Result<String, String> {
+    let mut out = String::new();
+    match output {
+        CheckOutput::Json => {
+            for outcome in outcomes {
+                out += &serde_json::to_string(outcome).map_err(|e| e.to_string())?;
+                out.push('\n');
+            }
+        }
+        CheckOutput::Table => {
+            let mut table = Table::new(&["rule", "result", "observed", "threshold", "where"]);
+            for outcome in outcomes {
+                let place = match (&outcome.group, outcome.window_start) {
+                    (Some(group), Some(start)) => format!("{} from {}", group, start.to_rfc3339()),
+                    (Some(group), None) => group.clone(),
+                    (None, _) => "-".to_string(),
+                };
+                table.row(vec![
+                    outcome.rule.clone(),
+                    if outcome.passed { "pass" } else { "FAIL" }.to_string(),
+                    outcome.observed.map_or_else(|| "-".to_string(), number),
+                    format!("{} {}", outcome.threshold, number(outcome.limit)),
+                    place,
+                ]);
+            }
+            out += &table.render();
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::MemoryBudget;
+
+    fn rules(source: &str) -> Result<Vec<Rule>, Vec<String>> {
+        let path = std::env::temp_dir().join(format!("log-parser-rules-{}-{}", std::process::id(), source.len()));
+        std::fs::write(&path, source).unwrap();
+        let loaded = load(&path).map_err(|errors| errors.iter().map(|e| e.to_string().replace(&path.display().to_string(), "rules")).collect());
+        std::fs::remove_file(&path).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn blocks_span_lines_and_take_comments() {
+        let loaded = rules(
+            "# Service level objectives\n\
+             error_rate { filter = 'status >= 500', max_ratio = 0.01 }\n\
+             p99_bytes {\n  metric = \"bytes\"  # per prefix\n  by = 'path-prefix'\n  max_p99 = 5000000\n}\n\
+             abuse { group = 'ip', window = '1m', max_count = 300, }\n",
+        )
+        .unwrap();
+        let keys: Vec<(String, String, f64)> = loaded.iter().map(|rule| (rule.name.clone(), rule.key(), rule.limit())).collect();
+        assert_eq!(
+            keys,
+            [
+                ("error_rate".to_string(), "max_ratio".to_string(), 0.01),
+                ("p99_bytes".to_string(), "max_p99".to_string(), 5_000_000.0),
+                ("abuse".to_string(), "max_count".to_string(), 300.0),
+            ]
+        );
+        assert!(matches!(loaded[2].threshold, Threshold::Rate { group: GroupBy::Ip, window: 60, max: 300 }));
+    }
+
+    #[test]
+    fn malformed_rules_say_what_is_wrong_and_where() {
+        let errors = rules(
+            "a { filter = 'status >= 500', max_rati = 0.01 }\n\
+             b { metric = 'latency', max_p99 = 1 }\n\
+             c { group = 'ip', max_count = 3 }\n\
+             d { filter = 'status', max_ratio = 0.1 }\n\
+             e { metric = 'bytes', max_p99 = 1, max_mean = 2 }\n\
+             a { max_count = 1 }\n",
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                "rules: line 1: a: unknown key max_rati; rules take filter, max_ratio, max_count, metric, by, max_mean, max_p<N>, group, window",
+                "rules: line 2: b: metric = 'latency': expected one of duration, bytes, gap",
+                "rules: line 3: c: a group rule needs a window, e.g. window = '1m'",
+                "rules: line 4: d: filter: `status` is a number, not a condition; compare it, e.g. `status > 0`",
+                "rules: line 5: e: one threshold per rule, not max_p99 and max_mean",
+                "rules: line 6: a: a rule of that name comes earlier",
+            ]
+        );
+        assert_eq!(rules("a { max_count = 1\n").unwrap_err(), ["rules: line 1: expected a key or `}` in a, found the end of the file"]);
+        assert_eq!(rules("a { max_count = 1O }").unwrap_err(), ["rules: line 1: max_count = 1O: quote text, e.g. max_count = '1O'"]);
+        assert_eq!(rules("a { filter = 'status }").unwrap_err(), ["rules: line 1: unterminated string 'status }"]);
+    }
+
+    #[test]
+    fn rules_pass_or_fail_on_the_worst_value() {
+        let loaded = rules(
+            "errors { filter = 'status >= 500', max_ratio = 0.25 }\n\
+             big { metric = 'bytes', by = 'path', max_p50 = 100 }\n\
+             busy { group = 'ip', window = '1m', max_count = 1 }\n",
+        )
+        .unwrap();
+        let mut checks = Checks::new(loaded, None, MemoryBudget::new(usize::MAX).account("check", ""));
+        let entry = |ip: &str, time: &str, path: &str, status: u16, bytes: u64| Entry {
+            client: Some(ip.parse().unwrap()),
+            timestamp: Some(time.parse().unwrap()),
+            path: Some(path.to_string()),
+            status_code: Some(status.try_into().unwrap()),
+            bytes: Some(bytes),
+            ..Entry::default()
+        };
+        checks.add(&entry("10.0.0.1", "2023-02-12T14:00:10Z", "/a", 200, 50)).unwrap();
+        checks.add(&entry("10.0.0.1", "2023-02-12T14:00:50Z", "/b", 503, 500)).unwrap();
+        checks.add(&entry("10.0.0.2", "2023-02-12T14:01:10Z", "/a", 200, 70)).unwrap();
+        checks.add(&entry("10.0.0.1", "2023-02-12T14:01:20Z", "/a", 200, 60)).unwrap();
+        let outcomes: Vec<(bool, Option<f64>, Option<String>)> =
+            checks.finish().into_iter().map(|o| (o.passed, o.observed, o.group)).collect();
+        assert_eq!(
+            outcomes,
+            [
+                (true, Some(0.25), None),
+                (false, Some(500.0), Some("path=/b".to_string())),
+                (false, Some(2.0), Some("ip=10.0.0.1".to_string())),
+            ]
+        );
+    }
+}
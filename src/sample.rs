@@ -0,0 +1,122 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Reservoir sampling for `stats` and `top --examples`: each group keeps up to N of its lines, every
+// line of the group equally likely to be among them however many there are (Vitter's algorithm
+// R). The generator is seedable so a run can be repeated with the same picks.
+
+/// SplitMix64, small and fast; fine for picking samples, not for anything secret.
+#[derive(Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    /// Seeded from the clock and the process id.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_nanos() as u64);
+        Rng(nanos ^ (u64::from(std::process::id()) << 32))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `n`, which must not be 0. The bias is below n / 2^64.
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(n)) >> 64) as u64
+    }
+}
+
+/// Up to `capacity` items, a uniform sample of all those offered.
+#[derive(Clone)]
+pub struct Reservoir {
+    capacity: usize,
+    /// Items offered so far.
+    seen: u64,
+    /// The items kept, with the order they were offered in.
+    items: Vec<(u64, String)>,
+}
+
+impl Reservoir {
+    pub fn new(capacity: usize) -> Self {
+        Reservoir { capacity, seen: 0, items: Vec::new() }
+    }
+
+    /// Offers the next item, made only if it's kept.
+    pub fn offer(&mut self, rng: &mut Rng, item: impl FnOnce() -> String) {
+        let index = self.seen;
+        self.seen += 1;
+        if self.items.len() < self.capacity {
+            self.items.push((index, item()));
+            return;
+        }
+        let slot = rng.below(self.seen) as usize;
+        if slot < self.capacity {
+            self.items[slot] = (index, item());
+        }
+    }
+
+    /// The items kept, in the order they were offered.
+    pub fn into_items(mut self) -> Vec<String> {
+        self.items.sort_by_key(|&(index, _)| index);
+        self.items.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(seed: u64, capacity: usize, count: usize) -> Vec<String> {
+        let (mut rng, mut reservoir) = (Rng::new(seed), Reservoir::new(capacity));
+        for i in 0..count {
+            reservoir.offer(&mut rng, || i.to_string());
+        }
+        reservoir.into_items()
+    }
+
+    #[test]
+    fn small_inputs_are_kept_whole_in_order() {
+        assert_eq!(sample(1, 5, 3), ["0", "1", "2"]);
+        assert!(sample(1, 0, 3).is_empty());
+    }
+
+    #[test]
+    fn samples_are_bounded_ordered_and_reproducible() {
+        let picked = sample(7, 10, 10_000);
+        assert_eq!(picked.len(), 10);
+        let indices: Vec<usize> = picked.iter().map(|i| i.parse().unwrap()).collect();
+        assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(picked, sample(7, 10, 10_000));
+        assert_ne!(picked, sample(8, 10, 10_000));
+    }
+
+    #[test]
+    fn every_item_is_equally_likely_to_be_picked() {
+        // 4000 samples of 10 from 1000 items: each tenth of the items should get a tenth of the
+        // 40000 picks, 4000 give or take a standard deviation of about 60.
+        let mut tenths = [0; 10];
+        for seed in 0..4000 {
+            for item in sample(seed, 10, 1000) {
+                tenths[item.parse::<usize>().unwrap() / 100] += 1;
+            }
+        }
+        for (tenth, &picks) in tenths.iter().enumerate() {
+            assert!((3700..=4300).contains(&picks), "items {}00.. were picked {} times", tenth, picks);
+        }
+    }
+
+    #[test]
+    fn below_stays_in_range() {
+        let mut rng = Rng::new(3);
+        for n in [1, 2, 3, 1000, u64::MAX] {
+            assert!((0..100).all(|_| rng.below(n) < n));
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use crate::entry::Entry;
+use crate::output::{self, table::Table};
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use clap::ValueEnum;
+use http::StatusCode;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+};
+
+// Flags clients that request many missing paths in a short time, the usual sign of a
+// vulnerability scanner. Each client's recent 404s are kept in a sliding window of log time,
+// with a count per path, so both thresholds are checked exactly and memory stays bounded by
+// what a window holds.
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum ScannerOutput {
+    #[default]
+    Table,
+    Json,
+}
+
+/// Probed paths listed per finding.
+const SAMPLE: usize = 5;
+
+pub struct Thresholds {
+    pub not_found: usize,
+    pub distinct_paths: usize,
+    pub window: TimeDelta,
+}
+
+/// A client's 404s within the last window.
+#[derive(Default)]
+struct Client {
+    recent: VecDeque<(DateTime<FixedOffset>, String)>,
+    paths: HashMap<String, usize>,
+}
+
+impl Client {
+    fn push(&mut self, now: DateTime<FixedOffset>, path: String, window: TimeDelta) {
+        while let Some((time, _)) = self.recent.front() {
+            if *time > now - window {
+                break;
+            }
+            let (_, expired) = self.recent.pop_front().expect("front exists");
+            if let Some(count) = self.paths.get_mut(&expired) {
+                *count -= 1;
+                if *count == 0 {
+                    self.paths.remove(&expired);
+                }
+            }
+        }
+        *self.paths.entry(path.clone()).or_default() += 1;
+        self.recent.push_back((now, path));
+    }
+
+    fn finding(&self, ip: IpAddr) -> Finding {
+        let mut sample_paths: Vec<String> = Vec::new();
+        for (_, path) in &self.recent {
+            if sample_paths.len() == SAMPLE {
+                break;
+            }
+            if !sample_paths.contains(path) {
+                sample_paths.push(path.clone());
+            }
+        }
+        Finding {
+            ip,
+            not_found: self.recent.len(),
+            distinct_paths: self.paths.len(),
+            window_start: self.recent.front().map(|(time, _)| *time),
+            window_end: self.recent.back().map(|(time, _)| *time),
+            sample_paths,
+        }
+    }
+}
+
+/// The busiest window in which a client passed both thresholds.
+#[derive(Serialize)]
+pub struct Finding {
+    ip: IpAddr,
+    not_found: usize,
+    distinct_paths: usize,
+    #[serde(serialize_with = "output::serialize_timestamp")]
+    window_start: Option<DateTime<FixedOffset>>,
+    #[serde(serialize_with = "output::serialize_timestamp")]
+    window_end: Option<DateTime<FixedOffset>>,
+    sample_paths: Vec<String>,
+}
+
+/// Watches matched entries for scanners. 404s without a client address or timestamp are
+/// ignored, and timestamps are assumed to be roughly in order.
+pub struct Detector {
+    thresholds: Thresholds,
+    clients: HashMap<IpAddr, Client>,
+    /// Findings in the order clients were first flagged, and each client's position.
+    findings: Vec<Finding>,
+    flagged: HashMap<IpAddr, usize>,
+    next_prune: Option<DateTime<FixedOffset>>,
+}
+
+impl Detector {
+    pub fn new(thresholds: Thresholds) -> Self {
+        Detector { thresholds, clients: HashMap::new(), findings: Vec::new(), flagged: HashMap::new(), next_prune: None }
+    }
+
+    pub fn add(&mut self, entry: &Entry) {
+        let (Some(ip), Some(now), Some(StatusCode::NOT_FOUND)) = (entry.ip(), entry.timestamp, entry.status_code) else {
+            return;
+        };
+        let window = self.thresholds.window;
+        if self.next_prune.is_some_and(|next| now >= next) {
+            // Clients whose latest 404 has left the window hold nothing worth keeping.
+            self.clients.retain(|_, client| client.recent.back().is_some_and(|(time, _)| *time > now - window));
+            self.next_prune = Some(now + window);
+        }
+        self.next_prune.get_or_insert(now + window);
+
+        let client = self.clients.entry(ip).or_default();
+        client.push(now, entry.match_path().clone().unwrap_or_else(|| "-".to_string()), window);
+        if client.recent.len() < self.thresholds.not_found || client.paths.len() < self.thresholds.distinct_paths {
+            return;
+        }
+        let finding = client.finding(ip);
+        match self.flagged.get(&ip) {
+            Some(&i) if self.findings[i].not_found >= finding.not_found => {}
+            Some(&i) => self.findings[i] = finding,
+            None => {
+                self.flagged.insert(ip, self.findings.len());
+                self.findings.push(finding);
+            }
+        }
+    }
+
+    pub fn finish(self) -> Vec<Finding> {
+        self.findings
+    }
+}
+
+/// Renders findings, or nothing when there are none so cron only mails about scanners.
+pub fn render(findings: &[Finding], output: ScannerOutput) -> Result<String, String> {
+    let mut out = String::new();
+    match output {
+        ScannerOutput::Json => {
+            for finding in findings {
+                out += &serde_json::to_string(finding).map_err(|e| e.to_string())?;
+                out.push('\n');
+            }
+        }
+        ScannerOutput::Table if findings.is_empty() => {}
+        ScannerOutput::Table => {
+            let mut table = Table::new(&["ip", "not_found", "distinct_paths", "window_start", "window_end", "sample_paths"]);
+            let time = |t: Option<DateTime<FixedOffset>>| t.map_or_else(|| "-".to_string(), |t| t.to_rfc3339());
+            for f in findings {
+                table.row(vec![
+                    f.ip.to_string(),
+                    f.not_found.to_string(),
+                    f.distinct_paths.to_string(),
+                    time(f.window_start),
+                    time(f.window_end),
+                    f.sample_paths.join(","),
+                ]);
+            }
+            out += &table.render();
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn not_found(ip: &str, path: &str, time: &str) -> Entry {
+        Entry {
+            client: Some(ip.parse().unwrap()),
+            path: Some(path.to_string()),
+            status_code: Some(StatusCode::NOT_FOUND),
+            timestamp: Some(DateTime::parse_from_rfc3339(time).unwrap()),
+            ..Entry::default()
+        }
+    }
+
+    fn detector() -> Detector {
+        Detector::new(Thresholds { not_found: 3, distinct_paths: 2, window: TimeDelta::seconds(60) })
+    }
+
+    #[test]
+    fn flags_clients_over_both_thresholds_in_one_window() {
+        let mut detector = detector();
+        for (ip, path, time) in [
+            ("192.0.2.1", "/.env", "2023-02-12T14:00:00Z"),
+            ("192.0.2.2", "/a", "2023-02-12T14:00:01Z"),
+            ("192.0.2.1", "/wp-login.php", "2023-02-12T14:00:10Z"),
+            ("192.0.2.2", "/a", "2023-02-12T14:00:02Z"),
+            ("192.0.2.2", "/a", "2023-02-12T14:00:03Z"),
+            ("192.0.2.1", "/.git/config", "2023-02-12T14:00:20Z"),
+        ] {
+            detector.add(&not_found(ip, path, time));
+        }
+        let findings = detector.finish();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].ip, "192.0.2.1".parse::<IpAddr>().unwrap());
+        assert_eq!((findings[0].not_found, findings[0].distinct_paths), (3, 3));
+        assert_eq!(findings[0].sample_paths, vec!["/.env", "/wp-login.php", "/.git/config"]);
+    }
+
+    #[test]
+    fn table_rows_quote_hostile_paths() {
+        let mut detector = Detector::new(Thresholds { not_found: 2, distinct_paths: 2, window: TimeDelta::seconds(60) });
+        detector.add(&not_found("192.0.2.1", "/a\tb", "2023-02-12T14:00:00Z"));
+        detector.add(&not_found("192.0.2.1", "/c\nd", "2023-02-12T14:00:01Z"));
+        let table = render(&detector.finish(), ScannerOutput::Table).unwrap();
+        assert_eq!(
+            table.lines().nth(1),
+            Some("192.0.2.1\t2\t2\t2023-02-12T14:00:00+00:00\t2023-02-12T14:00:01+00:00\t\"/a\tb,/c")
+        );
+        assert!(table.ends_with("d\"\n"));
+    }
+
+    #[test]
+    fn old_404s_leave_the_window() {
+        let mut detector = detector();
+        for (path, time) in [("/a", "2023-02-12T14:00:00Z"), ("/b", "2023-02-12T14:00:30Z"), ("/c", "2023-02-12T14:01:00Z")] {
+            detector.add(&not_found("192.0.2.1", path, time));
+        }
+        assert!(detector.finish().is_empty());
+    }
+
+    #[test]
+    fn keeps_the_busiest_window() {
+        let mut detector = detector();
+        for second in 0..5 {
+            detector.add(&not_found("192.0.2.1", &format!("/{}", second), &format!("2023-02-12T14:00:0{}Z", second)));
+        }
+        let findings = detector.finish();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].not_found, 5);
+        assert_eq!(findings[0].window_end, Some(DateTime::parse_from_rfc3339("2023-02-12T14:00:04Z").unwrap()));
+    }
+}
@@ -0,0 +1,573 @@
+use crate::classify::Derived;
+use crate::detect;
+use crate::entry::{Entry, Match, Provenance};
+use crate::filter::{EntryFilter, FilterArgs};
+use crate::output::{self, table::Table, OutputFormat};
+use crate::parsers::{LineParser, LogKind, Wrapping};
+use crate::rules::CheckOutput;
+use chrono::Timelike;
+use clap::{Parser, ValueEnum};
+use rs_filter::Filterable;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+
+// `self-test` runs golden cases built into the binary, for a build on a platform the test suite
+// never ran on: a sample line of every log type with the values it must parse to, filter options
+// with whether they match such an entry, and each output format read back. The unit tests below
+// run the same tables, and check that they cover every log type, operator and output format, so
+// adding one without its cases fails the build's tests.
+
+/// How a case's lines are parsed.
+#[derive(Clone, Copy, Debug)]
+pub enum Syntax {
+    Kind(LogKind),
+    /// A --format-string.
+    Format(&'static str),
+    /// A log type behind syslog prefixes, with --strip-syslog.
+    Syslog(LogKind),
+}
+
+/// Lines of one log type and what the last of them parses to.
+pub struct ParseCase {
+    pub name: &'static str,
+    pub syntax: Syntax,
+    /// The entry is the last line's; those before it are read first, such as W3C directives.
+    pub lines: &'static [&'static str],
+    /// Fields of the entry and their values: strings as they are, anything else as JSON.
+    pub fields: &'static [(&'static str, &'static str)],
+}
+
+/// Filter options and whether they match the entry of a parse case.
+pub struct FilterCase {
+    /// The parse case whose entry is filtered.
+    pub on: &'static str,
+    pub args: &'static [&'static str],
+    pub matches: bool,
+}
+
+/// An output format whose rendering of a parse case's entry must read back the same.
+pub struct OutputCase {
+    pub on: &'static str,
+    pub output: OutputFormat,
+}
+
+const CUSTOM_FORMAT: &str = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent" $request_time $upstream_addr"#;
+
+pub const PARSE_CASES: &[ParseCase] = &[
+    ParseCase {
+        name: "combined",
+        syntax: Syntax::Kind(LogKind::Combined),
+        lines: &[
+            r#"197.78.33.109 - - [12/Feb/2023:14:04:10 +0000] "POST /api/login HTTP/1.1" 401 540 "http://example.com/login" "Mozilla/5.0 (Linux; Android 10) Chrome/88.0.4324.181 Mobile Safari/537.36""#,
+        ],
+        fields: &[
+            ("ip", "197.78.33.109"),
+            ("user", "null"),
+            ("timestamp", "2023-02-12T14:04:10Z"),
+            ("method", "POST"),
+            ("path", "/api/login"),
+            ("protocol", "HTTP/1.1"),
+            ("status_code", "401"),
+            ("bytes", "540"),
+            ("referrer", "http://example.com/login"),
+            ("user_agent", "Mozilla/5.0 (Linux; Android 10) Chrome/88.0.4324.181 Mobile Safari/537.36"),
+        ],
+    },
+    ParseCase {
+        name: "common",
+        syntax: Syntax::Kind(LogKind::Common),
+        lines: &[r#"197.78.33.109 - bob [12/Feb/2023:14:04:10 +0000] "POST /api/login HTTP/1.1" 401 540"#],
+        fields: &[
+            ("ip", "197.78.33.109"),
+            ("user", "bob"),
+            ("timestamp", "2023-02-12T14:04:10Z"),
+            ("path", "/api/login"),
+            ("status_code", "401"),
+            ("bytes", "540"),
+            ("referrer", "null"),
+            ("user_agent", "null"),
+        ],
+    },
+    ParseCase {
+        name: "alb",
+        syntax: Syntax::Kind(LogKind::Alb),
+        lines: &[
+            r#"https 2018-07-02T22:23:00.186641Z app/my-loadbalancer/50dc6c495c0c9188 192.168.131.39:2817 10.0.0.1:80 0.086 0.048 0.037 200 200 0 57 "GET https://www.example.com:443/ HTTP/1.1" "curl/7.46.0" ECDHE-RSA-AES128-GCM-SHA256 TLSv1.2 arn:aws:elasticloadbalancing:us-east-2:123456789012:targetgroup/my-targets/73e2d6bc24d8a067 "Root=1-58337281-1d84f3d73c47ec4e58577259" "www.example.com" "arn:aws:acm:us-east-2:123456789012:certificate/12345678-1234-1234-1234-123456789012" 1 2018-07-02T22:22:48.364000Z "authenticate,forward" "-" "-" "10.0.0.1:80" "200" "-" "-""#,
+        ],
+        fields: &[
+            ("ip", "192.168.131.39"),
+            ("timestamp", "2018-07-02T22:23:00.186641Z"),
+            ("method", "GET"),
+            ("path", "/"),
+            ("status_code", "200"),
+            ("bytes", "57"),
+            ("duration", "0.171"),
+            ("scheme", "https"),
+            ("port", "443"),
+            ("tls_protocol", "TLSv1.2"),
+            ("target_processing_time", "0.048"),
+            ("elb_status_code", "200"),
+            ("domain_name", "www.example.com"),
+        ],
+    },
+    ParseCase {
+        name: "haproxy",
+        syntax: Syntax::Kind(LogKind::Haproxy),
+        lines: &[
+            r#"Feb  6 12:14:15 localhost haproxy[14389]: 10.0.1.3:33318 [06/Feb/2009:12:14:15.001] http-in api_servers/api2 0/0/1/2500/2502 504 194 - - sH-- 3/3/2/1/0 0/0 "POST /api/slow HTTP/1.1""#,
+        ],
+        fields: &[
+            ("ip", "10.0.1.3"),
+            ("timestamp", "2009-02-06T12:14:15.001Z"),
+            ("method", "POST"),
+            ("path", "/api/slow"),
+            ("status_code", "504"),
+            ("bytes", "194"),
+            ("duration", "2.5"),
+            ("backend", "api_servers"),
+            ("server", "api2"),
+            ("termination_state", "sH--"),
+            ("tq", "0"),
+            ("tr", "2500"),
+            ("tt", "2502"),
+        ],
+    },
+    ParseCase {
+        name: "nginx-error",
+        syntax: Syntax::Kind(LogKind::NginxError),
+        lines: &[
+            r#"2023/02/12 14:34:20 [error] 1234#0: *5678 connect() failed (111: Connection refused) while connecting to upstream, client: 1.2.3.4, server: example.com, request: "GET /x HTTP/1.1", upstream: "http://127.0.0.1:8080/x", host: "example.com""#,
+        ],
+        fields: &[
+            ("ip", "1.2.3.4"),
+            ("timestamp", "2023-02-12T14:34:20Z"),
+            ("severity", "error"),
+            ("method", "GET"),
+            ("path", "/x"),
+            ("pid", "1234"),
+            ("connection", "5678"),
+            ("message", "connect() failed (111: Connection refused) while connecting to upstream"),
+            ("upstream", "http://127.0.0.1:8080/x"),
+        ],
+    },
+    ParseCase {
+        name: "apache-error",
+        syntax: Syntax::Kind(LogKind::ApacheError),
+        lines: &[
+            "[Sun Feb 12 14:34:20.123456 2023] [proxy:error] [pid 1234:tid 5678] [client 1.2.3.4:5678] AH00957: HTTP: attempt to connect to 127.0.0.1:8080 (*) failed",
+        ],
+        fields: &[
+            ("ip", "1.2.3.4"),
+            ("timestamp", "2023-02-12T14:34:20.123456Z"),
+            ("severity", "error"),
+            ("module", "proxy"),
+            ("pid", "1234"),
+            ("tid", "5678"),
+            ("error_code", "AH00957"),
+        ],
+    },
+    ParseCase {
+        name: "w3c",
+        syntax: Syntax::Kind(LogKind::W3c),
+        lines: &[
+            "#Version: 1.0",
+            "#Fields: date time x-edge-location sc-bytes c-ip cs-method cs(Host) cs-uri-stem sc-status cs(Referer) cs(User-Agent) cs-uri-query time-taken",
+            "2019-12-04\t21:02:31\tLAX1\t392\t192.0.2.100\tGET\td111111abcdef8.cloudfront.net\t/index.html\t200\t-\tMozilla/5.0%20(Windows%20NT%2010.0)\t-\t0.001",
+        ],
+        fields: &[
+            ("ip", "192.0.2.100"),
+            ("timestamp", "2019-12-04T21:02:31Z"),
+            ("method", "GET"),
+            ("path", "/index.html"),
+            ("status_code", "200"),
+            ("bytes", "392"),
+            ("referrer", "null"),
+            ("user_agent", "Mozilla/5.0 (Windows NT 10.0)"),
+            ("duration", "0.001"),
+            ("x-edge-location", "LAX1"),
+        ],
+    },
+    ParseCase {
+        name: "csv",
+        syntax: Syntax::Kind(LogKind::Csv),
+        lines: &[
+            "ip,timestamp,method,path,status,bytes,user_agent,region",
+            r#"192.0.2.7,2023-02-12T14:34:20Z,GET,/search,200,512,"Mozilla/5.0 (X11; Linux x86_64), ""quoted""",eu-west"#,
+        ],
+        fields: &[
+            ("ip", "192.0.2.7"),
+            ("timestamp", "2023-02-12T14:34:20Z"),
+            ("method", "GET"),
+            ("path", "/search"),
+            ("status_code", "200"),
+            ("bytes", "512"),
+            ("user_agent", r#"Mozilla/5.0 (X11; Linux x86_64), "quoted""#),
+            ("region", "eu-west"),
+        ],
+    },
+    ParseCase {
+        name: "auto",
+        syntax: Syntax::Kind(LogKind::Auto),
+        lines: &[r#"64.228.32.14 - - [12/Feb/2023:14:04:33 +0000] "GET /css/styles.css HTTP/1.1" 304 0"#],
+        fields: &[("ip", "64.228.32.14"), ("path", "/css/styles.css"), ("status_code", "304"), ("bytes", "0")],
+    },
+    ParseCase {
+        name: "format-string",
+        syntax: Syntax::Format(CUSTOM_FORMAT),
+        lines: &[r#"10.0.0.1 - bob [12/Feb/2023:14:03:45 +0000] "GET /a HTTP/1.1" 200 123 "-" "curl/8 \"x\"" 0.250 10.1.0.5:80"#],
+        fields: &[
+            ("ip", "10.0.0.1"),
+            ("user", "bob"),
+            ("path", "/a"),
+            ("referrer", "null"),
+            ("user_agent", r#"curl/8 "x""#),
+            ("duration", "0.25"),
+            ("request_time", "0.25"),
+            ("upstream_addr", "10.1.0.5:80"),
+        ],
+    },
+    ParseCase {
+        name: "syslog",
+        syntax: Syntax::Syslog(LogKind::Combined),
+        lines: &[
+            r#"<34>Feb  2 14:34:21 web02 nginx[812]: 197.78.33.109 - - [12/Feb/2023:14:04:10 +0000] "POST /api/login HTTP/1.1" 401 540 "http://example.com/login" "curl/8""#,
+        ],
+        fields: &[("ip", "197.78.33.109"), ("path", "/api/login"), ("status_code", "401"), ("syslog_host", "web02")],
+    },
+];
+
+pub const FILTER_CASES: &[FilterCase] = &[
+    // Text filters.
+    FilterCase { on: "combined", args: &["--path", "eq", "/api/login"], matches: true },
+    FilterCase { on: "combined", args: &["--path", "eq", "/api"], matches: false },
+    FilterCase { on: "combined", args: &["--user-agent", "contains", "Android"], matches: true },
+    FilterCase { on: "combined", args: &["--referrer", "starts_with", "http://example.com/"], matches: true },
+    FilterCase { on: "combined", args: &["--path", "ends_with", ".php"], matches: false },
+    FilterCase { on: "combined", args: &["--path", "in", "/api/login"], matches: true },
+    FilterCase { on: "combined", args: &["--user-agent", "contains_any", "Chrome"], matches: true },
+    FilterCase { on: "combined", args: &["--user-agent", "not_contains_any", "Chrome"], matches: false },
+    FilterCase { on: "combined", args: &["--path", "fuzzy", "api/logn"], matches: true },
+    FilterCase { on: "combined", args: &["--path", "fuzzy_eq", "/api/logn:0"], matches: false },
+    FilterCase { on: "combined", args: &["--user-agent", "Android"], matches: true },
+    // String filters.
+    FilterCase { on: "haproxy", args: &["--backend", "eq", "api_servers"], matches: true },
+    FilterCase { on: "haproxy", args: &["--termination-state", "contains", "sH"], matches: true },
+    FilterCase { on: "syslog", args: &["--host", "starts_with", "web"], matches: true },
+    FilterCase { on: "alb", args: &["--scheme", "ends_with", "s"], matches: true },
+    FilterCase { on: "combined", args: &["--client", "eq", "10.0.0.1"], matches: false },
+    // Ordered filters.
+    FilterCase { on: "combined", args: &["--status-code", "eq", "401"], matches: true },
+    FilterCase { on: "combined", args: &["--status-code", "gte", "internal_server_error"], matches: false },
+    FilterCase { on: "combined", args: &["--timestamp", "lt", "2023-02-12T15:00:00Z"], matches: true },
+    FilterCase { on: "combined", args: &["--timestamp", "gt", "12/Feb/2023:14:04:10 +0000"], matches: false },
+    FilterCase { on: "haproxy", args: &["--duration", "gt", "1s"], matches: true },
+    FilterCase { on: "nginx-error", args: &["--severity", "gte", "warn"], matches: true },
+    FilterCase { on: "combined", args: &["--bytes", "lte", "512"], matches: false },
+    FilterCase { on: "haproxy", args: &["--response-time", "gt", "2000"], matches: true },
+    FilterCase { on: "alb", args: &["--port", "neq", "443"], matches: false },
+    FilterCase { on: "alb", args: &["--tls-protocol", "lt", "TLSv1.3"], matches: true },
+    // Address filters.
+    FilterCase { on: "combined", args: &["--ip", "eq", "197.78.33.109"], matches: true },
+    FilterCase { on: "combined", args: &["--ip", "neq", "197.78.33.109"], matches: false },
+    FilterCase { on: "combined", args: &["--ip", "in", "197.78.0.0/16"], matches: true },
+    FilterCase { on: "nginx-error", args: &["--ip", "in", "10.0.0.0/8,2001:db8::/32"], matches: false },
+    // Format-specific fields.
+    FilterCase { on: "format-string", args: &["--field", "request_time", "gt", "0.1"], matches: true },
+    FilterCase { on: "format-string", args: &["--field", "request_time", "lte", "0.1"], matches: false },
+    FilterCase { on: "format-string", args: &["--field", "upstream_addr", "starts_with", "10.1."], matches: true },
+    FilterCase { on: "format-string", args: &["--field", "upstream_addr", "ends_with", ":443"], matches: false },
+    FilterCase { on: "format-string", args: &["--field", "upstream_addr", "contains", "0.5"], matches: true },
+    FilterCase { on: "haproxy", args: &["--field", "tr", "eq", "2500"], matches: true },
+    FilterCase { on: "haproxy", args: &["--field", "tr", "neq", "2500"], matches: false },
+    FilterCase { on: "haproxy", args: &["--field", "tq", "lt", "1"], matches: true },
+    FilterCase { on: "haproxy", args: &["--field", "tq", "gte", "1"], matches: false },
+    // Presence.
+    FilterCase { on: "combined", args: &["--user-agent", "none"], matches: false },
+    FilterCase { on: "combined", args: &["--referrer", "some"], matches: true },
+    FilterCase { on: "alb", args: &["--referrer", "none"], matches: true },
+    // Choices and flags.
+    FilterCase { on: "combined", args: &["--class", "dynamic"], matches: true },
+    FilterCase { on: "auto", args: &["--class", "static"], matches: true },
+    FilterCase { on: "combined", args: &["--suspicious-paths"], matches: false },
+    // Expressions.
+    FilterCase { on: "combined", args: &["--where", "bytes / 2 == 270"], matches: true },
+    FilterCase { on: "combined", args: &["--where", "status_code != 401 or bytes < 100"], matches: false },
+    FilterCase { on: "haproxy", args: &["--where", "tr - tq > 2000 and tt >= tr"], matches: true },
+    FilterCase { on: "combined", args: &["--where", "bytes * 2 <= 1000 + 79"], matches: false },
+    // Exclusions.
+    FilterCase { on: "combined", args: &["--exclude", "status eq 401, path starts_with /api/"], matches: false },
+];
+
+pub const OUTPUT_CASES: &[OutputCase] = &[
+    OutputCase { on: "combined", output: OutputFormat::Line },
+    OutputCase { on: "combined", output: OutputFormat::Json },
+    OutputCase { on: "haproxy", output: OutputFormat::Json },
+    OutputCase { on: "w3c", output: OutputFormat::JsonArray },
+    OutputCase { on: "combined", output: OutputFormat::Combined },
+    OutputCase { on: "alb", output: OutputFormat::Combined },
+    OutputCase { on: "format-string", output: OutputFormat::Combined },
+];
+
+/// The fields a combined line carries besides the timestamp, which converting to one keeps.
+const COMBINED_FIELDS: &[&str] = &["ip", "user", "method", "path", "protocol", "status_code", "bytes", "referrer", "user_agent"];
+
+/// A case's verdict, with what went wrong when it failed.
+#[derive(Debug, Serialize)]
+pub struct Outcome {
+    /// `parse`, `filter` or `output`.
+    pub suite: &'static str,
+    pub case: String,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// What the build runs on, for a bug report.
+#[derive(Serialize)]
+pub struct Platform {
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub family: &'static str,
+    pub endian: &'static str,
+    pub pointer_width: usize,
+}
+
+impl Platform {
+    fn current() -> Self {
+        Platform {
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            family: std::env::consts::FAMILY,
+            endian: if cfg!(target_endian = "big") { "big" } else { "little" },
+            pointer_width: std::mem::size_of::<usize>() * 8,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub version: &'static str,
+    pub platform: Platform,
+    pub passed: usize,
+    pub failed: usize,
+    pub cases: Vec<Outcome>,
+}
+
+/// Filter options as they'd follow `filter` on the command line.
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct FilterOptions {
+    #[command(flatten)]
+    filter: FilterArgs,
+}
+
+fn parse_case(name: &str) -> Result<&'static ParseCase, String> {
+    PARSE_CASES.iter().find(|case| case.name == name).ok_or_else(|| format!("there's no parse case {}", name))
+}
+
+/// The entry of the case's last line, with the derived fields `derived` names.
+fn parse(case: &ParseCase, derived: Derived) -> Result<Entry, String> {
+    let mut parser = match case.syntax {
+        Syntax::Kind(LogKind::Auto) => {
+            let lines: Vec<String> = case.lines.iter().map(|line| line.to_string()).collect();
+            let kind = detect::detect(&lines, Wrapping::default()).ok_or("no log type parses the lines")?;
+            LineParser::from(kind)
+        }
+        Syntax::Kind(kind) => LineParser::from(kind),
+        Syntax::Format(format) => LineParser::Custom(format.parse()?),
+        Syntax::Syslog(kind) => LineParser::Syslog(Box::new(LineParser::from(kind))),
+    };
+    if !derived.is_empty() {
+        parser = LineParser::Derive(Box::new(parser), derived);
+    }
+    let (last, before) = case.lines.split_last().ok_or("the case has no lines")?;
+    for line in before {
+        parser.parse(line)?;
+    }
+    parser.parse(last)?.ok_or_else(|| "the last line carries no entry".to_string())
+}
+
+/// A JSON value as the cases write it: strings without their quotes.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Fails for the first of `fields` whose value in `record` isn't the one expected.
+fn compare<'a>(record: &Value, fields: impl IntoIterator<Item = (&'a str, String)>) -> Result<(), String> {
+    for (field, expected) in fields {
+        let found = text(record.get(field).unwrap_or(&Value::Null));
+        if found != expected {
+            return Err(format!("{} is {:?}, expected {:?}", field, found, expected));
+        }
+    }
+    Ok(())
+}
+
+fn check_parse(case: &ParseCase) -> Result<(), String> {
+    let entry = parse(case, Derived::default())?;
+    let record = serde_json::to_value(&entry).map_err(|e| e.to_string())?;
+    compare(&record, case.fields.iter().map(|&(field, value)| (field, value.to_string())))
+}
+
+fn check_filter(case: &FilterCase) -> Result<(), String> {
+    let options = FilterOptions::try_parse_from(case.args).map_err(|e| e.to_string().trim_end().to_string())?;
+    let (filter, _) = EntryFilter::build(options.filter)
+        .map_err(|errors| errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))?;
+    let entry = parse(parse_case(case.on)?, Derived::used(|name| filter.reads(name)))?;
+    filter.check_fields(&entry)?;
+    match (entry.is_match(&filter), case.matches) {
+        (true, false) => Err("matched, expected no match".to_string()),
+        (false, true) => Err(format!("no match, rejected by {}", filter.rejection(&entry).unwrap_or_default())),
+        _ => Ok(()),
+    }
+}
+
+fn check_output(case: &OutputCase) -> Result<(), String> {
+    let source = parse_case(case.on)?;
+    let line = source.lines.last().ok_or("the case has no lines")?;
+    let found = Match {
+        line: line.to_string(),
+        entry: parse(source, Derived::default())?,
+        provenance: Provenance { source_file: PathBuf::from("self-test.log"), line_no: 1, byte_offset: 0 },
+    };
+    let rendered = output::render_match(&found, case.output)?;
+    let original = serde_json::to_value(&found.entry).map_err(|e| e.to_string())?;
+    match case.output {
+        OutputFormat::Line if rendered == *line => Ok(()),
+        OutputFormat::Line => Err(format!("printed {:?}, not the line as read", rendered)),
+        OutputFormat::Json | OutputFormat::JsonArray => {
+            let record: Value = serde_json::from_str(&rendered).map_err(|e| format!("{:?} isn't JSON: {}", rendered, e))?;
+            let Value::Object(fields) = &original else {
+                return Err("the entry isn't a JSON object".to_string());
+            };
+            compare(&record, fields.iter().map(|(field, value)| (field.as_str(), text(value))))?;
+            compare(&record, [("source_file", "self-test.log".to_string()), ("line_no", "1".to_string())])
+        }
+        OutputFormat::Combined => {
+            let reparsed = LineParser::from(LogKind::Combined)
+                .parse(&rendered)
+                .map_err(|e| format!("{:?} doesn't parse back: {}", rendered, e))?
+                .ok_or("the line carries no entry")?;
+            // Combined lines log whole seconds.
+            let whole = found.entry.timestamp.and_then(|t| t.with_nanosecond(0));
+            if reparsed.timestamp != whole {
+                return Err(format!("timestamp is {:?}, expected {:?}", reparsed.timestamp, whole));
+            }
+            let reparsed = serde_json::to_value(&reparsed).map_err(|e| e.to_string())?;
+            compare(&reparsed, COMBINED_FIELDS.iter().map(|&field| (field, text(&original[field]))))
+        }
+    }
+}
+
+fn outcome(suite: &'static str, case: String, result: Result<(), String>) -> Outcome {
+    Outcome { suite, case, passed: result.is_ok(), detail: result.err() }
+}
+
+fn name<T: ValueEnum>(value: T) -> String {
+    value.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+}
+
+/// Runs every case.
+pub fn run() -> Report {
+    let mut cases = Vec::new();
+    for case in PARSE_CASES {
+        cases.push(outcome("parse", case.name.to_string(), check_parse(case)));
+    }
+    for case in FILTER_CASES {
+        let expected = if case.matches { "matches" } else { "rejects" };
+        cases.push(outcome("filter", format!("{} {} {}", case.args.join(" "), expected, case.on), check_filter(case)));
+    }
+    for case in OUTPUT_CASES {
+        cases.push(outcome("output", format!("-o {} of {}", name(case.output), case.on), check_output(case)));
+    }
+    let passed = cases.iter().filter(|case| case.passed).count();
+    Report { version: env!("CARGO_PKG_VERSION"), platform: Platform::current(), passed, failed: cases.len() - passed, cases }
+}
+
+/// A line naming the build and platform, a row per case and a count of each verdict; with JSON
+/// the report as one document.
+pub fn render(report: &Report, output: CheckOutput) -> Result<String, String> {
+    match output {
+        CheckOutput::Json => serde_json::to_string(report).map(|json| json + "\n").map_err(|e| e.to_string()),
+        CheckOutput::Table => {
+            let platform = &report.platform;
+            let mut out = format!(
+                "log-parser {} on {} {} ({}-endian, {}-bit)\n",
+                report.version, platform.os, platform.arch, platform.endian, platform.pointer_width
+            );
+            let mut table = Table::new(&["suite", "case", "result", "detail"]);
+            for case in &report.cases {
+                let result = if case.passed { "pass" } else { "FAIL" };
+                let detail = case.detail.clone().unwrap_or_default();
+                table.row(vec![case.suite.to_string(), case.case.clone(), result.to_string(), detail]);
+            }
+            out += &table.render();
+            out += &format!("{} passed, {} failed\n", report.passed, report.failed);
+            Ok(out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FILTER_KINDS;
+
+    #[test]
+    fn every_case_passes() {
+        let report = run();
+        let failed: Vec<String> = report
+            .cases
+            .iter()
+            .filter(|case| !case.passed)
+            .map(|case| format!("{} {}: {:?}", case.suite, case.case, case.detail))
+            .collect();
+        assert!(failed.is_empty(), "failing cases:\n{}", failed.join("\n"));
+        assert_eq!(report.passed, PARSE_CASES.len() + FILTER_CASES.len() + OUTPUT_CASES.len());
+    }
+
+    #[test]
+    fn every_log_type_has_a_parse_case() {
+        for &kind in LogKind::value_variants() {
+            let covered = PARSE_CASES.iter().any(|case| matches!(case.syntax, Syntax::Kind(of) if of == kind));
+            assert!(covered, "--log-type {} has no parse case", name(kind));
+        }
+        assert!(PARSE_CASES.iter().any(|case| matches!(case.syntax, Syntax::Format(_))));
+        assert!(PARSE_CASES.iter().any(|case| matches!(case.syntax, Syntax::Syslog(_))));
+        for case in FILTER_CASES.iter().map(|case| case.on).chain(OUTPUT_CASES.iter().map(|case| case.on)) {
+            assert!(parse_case(case).is_ok(), "there's no parse case {}", case);
+        }
+    }
+
+    #[test]
+    fn every_operator_has_a_filter_case() {
+        // The operator follows the option, or with --field the field's name.
+        let operator = |args: &[&str]| match args {
+            ["--field", _, op, ..] => op.to_string(),
+            [_, op, ..] => op.to_string(),
+            _ => String::new(),
+        };
+        for kind in FILTER_KINDS {
+            let cases: Vec<&FilterCase> = FILTER_CASES.iter().filter(|case| kind.options.contains(&case.args[0])).collect();
+            assert!(!cases.is_empty(), "{} filters have no case", kind.kind);
+            for op in kind.operators {
+                let covered = cases.iter().any(|case| match kind.kind {
+                    "expression" => case.args[1].split_whitespace().any(|token| token == *op),
+                    _ => operator(case.args) == *op,
+                });
+                assert!(covered, "{} has no case among the {} filters", op, kind.kind);
+            }
+        }
+    }
+
+    #[test]
+    fn every_output_format_has_a_round_trip_case() {
+        for &output in OutputFormat::value_variants() {
+            assert!(OUTPUT_CASES.iter().any(|case| case.output == output), "-o {} has no case", name(output));
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use crate::diagnostics::Error;
+use crate::interrupt;
+use crate::output::{json_array::JsonArray, pager::Paged, OutputFormat};
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Stdout, Write},
+    path::{Path, PathBuf},
+};
+
+// Where `filter` writes its lines: the matches to stdout or --output-file, and with
+// --unmatched-file and --malformed-file the rest of the input, so one pass splits a log in
+// three. Files whose name ends in `.gz` are gzip-compressed, and stdout may go through a pager.
+// With `-o json-array` the lines are the elements of one array, closed by `finish` or, should
+// the run fail first, when the sink is dropped.
+//
+// With --chunk-size or --chunk-rows the output file is written in parts, `results.00001.json`,
+// `results.00002.json` and so on, a new one started before a line would take the current one
+// past either bound. Each part stands on its own: it has the prologue, such as the schema
+// record, its own array and its own gzip stream. Sizes are of the output before compression.
+
+enum Writer {
+    /// Line-buffered, so matches show as they're found.
+    Stdout(Stdout),
+    Paged(Paged<Stdout>),
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+pub struct Sink {
+    /// `None` for stdout.
+    path: Option<PathBuf>,
+    writer: Writer,
+    /// Lines written so far.
+    pub lines: usize,
+    array: Option<JsonArray>,
+    /// Text that starts the output, and each part of it.
+    prologue: Option<String>,
+    chunks: Option<Chunks>,
+}
+
+/// Bounds on each part of a chunked output file; a part has at least one line whatever they are.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChunkLimits {
+    pub bytes: Option<u64>,
+    pub lines: Option<usize>,
+}
+
+/// The parts of a chunked output file.
+struct Chunks {
+    limits: ChunkLimits,
+    /// The name the parts are numbered from.
+    base: PathBuf,
+    /// Each part written so far with its lines, the last being the one written to.
+    parts: Vec<(PathBuf, usize)>,
+    /// Bytes written to the current part.
+    bytes: u64,
+}
+
+/// `results.json` numbered, as `results.00001.json`; a `.gz` after the extension stays last.
+pub fn part_path(base: &Path, number: usize) -> PathBuf {
+    let name = base.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let (rest, gz) = match name.strip_suffix(".gz") {
+        Some(rest) => (rest, ".gz"),
+        None => (name.as_str(), ""),
+    };
+    let numbered = match rest.rfind('.').filter(|&dot| dot > 0) {
+        Some(dot) => format!("{}.{:05}{}{}", &rest[..dot], number, &rest[dot..], gz),
+        None => format!("{}.{:05}{}", rest, number, gz),
+    };
+    base.with_file_name(numbered)
+}
+
+/// Checks, before any input is read, that the file given to `option` can be written: an
+/// existing file is opened for writing without truncating it, and a new one is created and
+/// removed again. Devices and pipes are left alone, since opening them may block.
+pub fn check_writable(option: &str, path: &Path) -> Result<(), Error> {
+    let error =
+        |message: String| Error::Io { file: path.to_path_buf(), message: format!("{} can't be written: {}", option, message) };
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => Err(error("it is a directory".to_string())),
+        Ok(metadata) if metadata.is_file() => {
+            OpenOptions::new().write(true).open(path).map(drop).map_err(|e| error(e.to_string()))
+        }
+        Ok(_) => Ok(()),
+        Err(_) => {
+            File::create_new(path).map_err(|e| error(e.to_string()))?;
+            fs::remove_file(path).map_err(|e| error(e.to_string()))
+        }
+    }
+}
+
+impl Sink {
+    pub fn stdout() -> Self {
+        Sink { path: None, writer: Writer::Stdout(io::stdout()), lines: 0, array: None, prologue: None, chunks: None }
+    }
+
+    /// Stdout, through the pager `command` once more than `rows` lines are written.
+    pub fn paged(command: String, rows: usize) -> Self {
+        let writer = Writer::Paged(Paged::new(command, rows, io::stdout()));
+        Sink { path: None, writer, lines: 0, array: None, prologue: None, chunks: None }
+    }
+
+    fn open(path: &Path) -> Result<Writer, Error> {
+        let file = File::create(path).map_err(|e| Sink::io_error(Some(path), e))?;
+        let file = BufWriter::new(file);
+        Ok(match path.extension().is_some_and(|extension| extension == "gz") {
+            true => Writer::Gzip(GzEncoder::new(file, Compression::default())),
+            false => Writer::Plain(file),
+        })
+    }
+
+    /// Creates or truncates `path`.
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        let writer = Sink::open(path)?;
+        Ok(Sink { path: Some(path.to_path_buf()), writer, lines: 0, array: None, prologue: None, chunks: None })
+    }
+
+    /// Creates the first part of `path` when either limit is set, or else `path` itself.
+    pub fn create_chunked(path: &Path, limits: ChunkLimits) -> Result<Self, Error> {
+        if limits.bytes.is_none() && limits.lines.is_none() {
+            return Sink::create(path);
+        }
+        let first = part_path(path, 1);
+        let mut sink = Sink::create(&first)?;
+        sink.chunks = Some(Chunks { limits, base: path.to_path_buf(), parts: vec![(first, 0)], bytes: 0 });
+        Ok(sink)
+    }
+
+    /// Stdout, or `path` when given, in parts within `limits`.
+    pub fn create_or_stdout(path: Option<&Path>, limits: ChunkLimits) -> Result<Self, Error> {
+        path.map_or_else(|| Ok(Sink::stdout()), |path| Sink::create_chunked(path, limits))
+    }
+
+    /// Writes the lines as the elements of one JSON array with `-o json-array`.
+    pub fn framed(mut self, output: OutputFormat) -> Self {
+        if matches!(output, OutputFormat::JsonArray) {
+            self.array = Some(JsonArray::default());
+        }
+        self
+    }
+
+    /// Writes `text` now, and again at the start of each later part.
+    pub fn prologue(&mut self, text: String) -> Result<(), Error> {
+        self.write_str(&text)?;
+        self.prologue = Some(text);
+        Ok(())
+    }
+
+    /// The files written with the lines of each, one per part when chunked.
+    pub fn parts(&self) -> Vec<(PathBuf, usize)> {
+        match (&self.chunks, &self.path) {
+            (Some(chunks), _) => chunks.parts.clone(),
+            (None, Some(path)) => vec![(path.clone(), self.lines)],
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// Finishes the current part and starts the next, if writing `bytes` more would take the
+    /// current one past a limit.
+    fn rotate(&mut self, bytes: u64) -> Result<(), Error> {
+        let Some(chunks) = &self.chunks else {
+            return Ok(());
+        };
+        let lines = chunks.parts.last().map_or(0, |&(_, lines)| lines);
+        let full = chunks.limits.lines.is_some_and(|limit| lines >= limit)
+            || chunks.limits.bytes.is_some_and(|limit| chunks.bytes + bytes > limit);
+        if lines == 0 || !full {
+            return Ok(());
+        }
+        let next = part_path(&chunks.base, chunks.parts.len() + 1);
+        self.finish()?;
+        self.writer = Sink::open(&next)?;
+        self.path = Some(next.clone());
+        if let Some(array) = &mut self.array {
+            *array = JsonArray::default();
+        }
+        if let Some(chunks) = &mut self.chunks {
+            chunks.parts.push((next, 0));
+            chunks.bytes = 0;
+        }
+        if let Some(prologue) = self.prologue.clone() {
+            self.write_str(&prologue)?;
+        }
+        Ok(())
+    }
+
+    fn io_error(path: Option<&Path>, error: io::Error) -> Error {
+        Error::Io { file: path.unwrap_or(Path::new("stdout")).to_path_buf(), message: error.to_string() }
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match &mut self.writer {
+            Writer::Stdout(writer) => writer,
+            Writer::Paged(writer) => writer,
+            Writer::Plain(writer) => writer,
+            Writer::Gzip(writer) => writer,
+        }
+    }
+
+    /// Writes `line` followed by `end`, or as the next element of the array. Once the pager is
+    /// quit lines are dropped, and reading stops.
+    pub fn write_line(&mut self, line: &str, end: char) -> Result<(), Error> {
+        // An array's element comes after a separator, and the closing bracket must fit too.
+        self.rotate(line.len() as u64 + if self.array.is_some() { 5 } else { end.len_utf8() as u64 })?;
+        let mut buffer = [0; 4];
+        let (before, end) = match &mut self.array {
+            Some(array) => (array.separator(), ""),
+            None => ("", &*end.encode_utf8(&mut buffer)),
+        };
+        let writer = self.writer();
+        let written = [before, line, end].iter().try_for_each(|text| writer.write_all(text.as_bytes()));
+        written.map_err(|e| Sink::io_error(self.path.as_deref(), e))?;
+        self.lines += 1;
+        if let Some(chunks) = &mut self.chunks {
+            chunks.bytes += (before.len() + line.len() + end.len()) as u64;
+            if let Some((_, lines)) = chunks.parts.last_mut() {
+                *lines += 1;
+            }
+        }
+        if matches!(&self.writer, Writer::Paged(paged) if paged.has_quit()) {
+            interrupt::stop();
+        }
+        Ok(())
+    }
+
+    /// Writes text that isn't one of the lines, such as a table of counts.
+    pub fn write_str(&mut self, text: &str) -> Result<(), Error> {
+        self.writer().write_all(text.as_bytes()).map_err(|e| Sink::io_error(self.path.as_deref(), e))?;
+        if let Some(chunks) = &mut self.chunks {
+            chunks.bytes += text.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Flushes everything written, ending the gzip stream of a compressed file or waiting for
+    /// the pager to exit.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if let Some(close) = self.array.as_mut().map(JsonArray::close) {
+            self.write_str(close)?;
+        }
+        let finished = match &mut self.writer {
+            Writer::Stdout(writer) => writer.flush(),
+            Writer::Paged(writer) => writer.finish(),
+            Writer::Plain(writer) => writer.flush(),
+            Writer::Gzip(writer) => writer.try_finish().and_then(|_| writer.get_mut().flush()),
+        };
+        finished.map_err(|e| Sink::io_error(self.path.as_deref(), e))
+    }
+}
+
+impl Drop for Sink {
+    fn drop(&mut self) {
+        if self.array.as_ref().is_some_and(|array| !array.is_closed()) {
+            let _ = self.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn gz_files_are_compressed() {
+        let path = std::env::temp_dir().join(format!("sink-test-{}.log.gz", std::process::id()));
+        let written = Sink::create(&path).and_then(|mut sink| {
+            sink.write_line("first", '\n')?;
+            sink.write_line("second", '\n')?;
+            sink.finish()?;
+            Ok(sink.lines)
+        });
+        assert_eq!(written.map_err(|e| e.to_string()), Ok(2));
+        let mut text = String::new();
+        GzDecoder::new(File::open(&path).unwrap()).read_to_string(&mut text).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(text, "first\nsecond\n");
+    }
+
+    #[test]
+    fn parts_are_numbered_before_the_extension() {
+        let part = |base: &str, number| part_path(Path::new(base), number);
+        assert_eq!(part("out/results.json", 1), Path::new("out/results.00001.json"));
+        assert_eq!(part("results.json.gz", 12), Path::new("results.00012.json.gz"));
+        assert_eq!(part("access.log.1", 2), Path::new("access.log.00002.1"));
+        assert_eq!(part("results", 3), Path::new("results.00003"));
+        assert_eq!(part(".hidden", 4), Path::new(".hidden.00004"));
+    }
+
+    #[test]
+    fn arrays_are_closed_when_a_run_stops_early() {
+        let path = std::env::temp_dir().join(format!("sink-test-{}.json", std::process::id()));
+        let written = Sink::create(&path).map(|sink| sink.framed(OutputFormat::JsonArray)).and_then(|mut sink| {
+            sink.write_line("1", '\n')?;
+            sink.write_line("2", '\n')
+        });
+        assert!(written.is_ok());
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(text, "[\n1,\n2\n]\n");
+    }
+}
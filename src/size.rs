@@ -0,0 +1,133 @@
+use std::str::FromStr;
+
+// Byte counts as people type and read them, shared by the size options and the --bytes filter.
+
+const FORMS: &str = "expected a number of bytes with an optional suffix: k, M, G or T (powers of 1024, \
+                     also written KiB, MiB, GiB, TiB) or kB, MB, GB, TB (powers of 1000), e.g. 512k, 1.5M, \
+                     100MB or 1,048,576";
+
+/// Parses a size such as `512k`, `1.5M`, `2GiB`, `100kB` or `1,048,576`. A plain suffix or one
+/// ending in `iB` counts in powers of 1024, one ending in just `B` in powers of 1000. Fractions
+/// need a suffix and are rounded down to whole bytes.
+pub fn parse_size<T: TryFrom<u64>>(s: &str) -> Result<T, String> {
+    let invalid = || format!("invalid size {:?}: {}", s, FORMS);
+    let trimmed = s.trim();
+    let end = trimmed.find(|c: char| !(c.is_ascii_digit() || c == ',' || c == '.')).unwrap_or(trimmed.len());
+    let (number, suffix) = trimmed.split_at(end);
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if !grouped_digits(whole) || !fraction.bytes().all(|b| b.is_ascii_digit()) || number.ends_with('.') {
+        return Err(invalid());
+    }
+
+    let suffix = suffix.trim().to_ascii_lowercase();
+    let unit: u128 = match suffix.as_str() {
+        "" | "b" => 1,
+        _ => {
+            let (letter, rest) = suffix.split_at(1);
+            let power = match letter {
+                "k" => 1,
+                "m" => 2,
+                "g" => 3,
+                "t" => 4,
+                _ => return Err(invalid()),
+            };
+            let base: u128 = match rest {
+                "" | "ib" => 1024,
+                "b" => 1000,
+                _ => return Err(invalid()),
+            };
+            base.pow(power)
+        }
+    };
+    if unit == 1 && !fraction.is_empty() {
+        return Err(format!("invalid size {:?}: a fraction of a byte needs a suffix such as k or M", s));
+    }
+
+    let whole: u128 = whole.replace(',', "").parse().map_err(|_| invalid())?;
+    let scale = u32::try_from(fraction.len()).ok().and_then(|len| 10u128.checked_pow(len)).ok_or_else(invalid)?;
+    let fraction: u128 = if fraction.is_empty() { 0 } else { fraction.parse().map_err(|_| invalid())? };
+    let bytes = whole.checked_mul(unit).and_then(|bytes| bytes.checked_add(fraction * unit / scale));
+    bytes
+        .and_then(|bytes| u64::try_from(bytes).ok())
+        .and_then(|bytes| T::try_from(bytes).ok())
+        .ok_or_else(|| format!("size {:?} is too large", s))
+}
+
+/// Digits, optionally with commas between groups of three, e.g. `1,048,576`.
+fn grouped_digits(s: &str) -> bool {
+    let mut groups = s.split(',');
+    let first = groups.next().unwrap_or_default();
+    let digits = |group: &str| group.bytes().all(|b| b.is_ascii_digit());
+    match s.contains(',') {
+        false => !s.is_empty() && digits(s),
+        true => (1..=3).contains(&first.len()) && digits(first) && groups.all(|g| g.len() == 3 && digits(g)),
+    }
+}
+
+/// Formats a byte count for people, e.g. `143.2 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1 << 10 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// A byte count in a filter value, see `parse_size`.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_size(s).map(ByteSize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(s: &str) -> Result<u64, String> {
+        parse_size(s)
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(size("1024"), Ok(1024));
+        assert_eq!(size("512K"), Ok(512 << 10));
+        assert_eq!(size("512k"), Ok(512 << 10));
+        assert_eq!(size("256MiB"), Ok(256 << 20));
+        assert_eq!(size("256MB"), Ok(256_000_000));
+        assert_eq!(size("100kB"), Ok(100_000));
+        assert_eq!(size("2GiB"), Ok(2 << 30));
+        assert_eq!(size("1T"), Ok(1 << 40));
+        assert_eq!(size("1.5M"), Ok(1_572_864));
+        assert_eq!(size("0.5 kB"), Ok(500));
+        assert_eq!(size("1,048,576"), Ok(1_048_576));
+        assert_eq!(size("1,024k"), Ok(1 << 20));
+        assert_eq!(size("64B"), Ok(64));
+        assert_eq!(format_size(87), "87 B");
+        assert_eq!(format_size(17467), "17.1 KiB");
+        assert_eq!(format_size(149_946_368), "143.0 MiB");
+        assert_eq!(format_size(3 << 40), "3.0 TiB");
+    }
+
+    #[test]
+    fn invalid_sizes_list_the_accepted_forms() {
+        for s in ["12X", "M", "5Mx", "1,5M", "10,00", "1.", ".5k", "1.2.3k", "", "-1", "1 KiBB"] {
+            let error = size(s).unwrap_err();
+            assert!(error.starts_with(&format!("invalid size {:?}: expected", s)), "{}", error);
+        }
+        assert_eq!(size("1.5"), Err("invalid size \"1.5\": a fraction of a byte needs a suffix such as k or M".to_string()));
+        assert_eq!(size("20000000T"), Err("size \"20000000T\" is too large".to_string()));
+        assert_eq!(parse_size::<u8>("1k"), Err("size \"1k\" is too large".to_string()));
+    }
+}
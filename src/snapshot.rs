@@ -0,0 +1,164 @@
+use crate::diagnostics::{snapshot_record, Error, Reporter};
+use crate::duration::HumanDuration;
+use crate::output::schema::Snapshot;
+use clap::Args;
+use serde_json::Value;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+// Partial results of a long `stats`, `histogram` or `top` run, so there's something to look at
+// hours before a large input ends. A snapshot is taken every --snapshot-every lines read or that
+// long on the wall clock, and once more on Ctrl-C. It goes to stderr marked as partial, or with
+// --snapshot-file is appended there as a JSON record, so stdout only ever gets the final result.
+// Counting lines rather than time makes the snapshots the same from run to run.
+
+/// `--snapshot-every`: a number of lines, or a duration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Every {
+    Lines(usize),
+    Time(Duration),
+}
+
+impl FromStr for Every {
+    type Err = String;
+
+    /// Digits alone are lines, anything else a duration such as `10s`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let every = match !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+            true => Every::Lines(s.parse().map_err(|_| format!("{} lines is too many", s))?),
+            false => Every::Time(s.parse::<HumanDuration>()?.0),
+        };
+        match every {
+            Every::Lines(0) => Err("expected at least 1 line".to_string()),
+            Every::Time(Duration::ZERO) => Err("expected a duration longer than 0".to_string()),
+            _ => Ok(every),
+        }
+    }
+}
+
+#[derive(Args, Debug, Default)]
+pub struct SnapshotArgs {
+    /// Print the result so far to stderr every this many lines read, e.g. `1000000`, or this
+    /// often, e.g. `10s`, and once more on Ctrl-C
+    #[arg(long, value_name = "LINES|DURATION")]
+    snapshot_every: Option<Every>,
+    /// Append the snapshots to this file as JSON records instead
+    #[arg(long, value_name = "FILE", requires = "snapshot_every")]
+    snapshot_file: Option<PathBuf>,
+}
+
+impl SnapshotArgs {
+    /// The file snapshots are appended to, when they're taken.
+    pub fn file(&self) -> Option<&Path> {
+        self.snapshot_file.as_deref().filter(|_| self.snapshot_every.is_some())
+    }
+}
+
+pub struct Snapshots {
+    every: Every,
+    file: Option<(PathBuf, File)>,
+    start: Instant,
+    /// Lines read when the last snapshot was taken, and when that was.
+    last: (usize, Instant),
+    taken: usize,
+}
+
+impl Snapshots {
+    /// `None` without --snapshot-every; fails when --snapshot-file can't be opened.
+    pub fn new(args: SnapshotArgs) -> Result<Option<Self>, Error> {
+        let Some(every) = args.snapshot_every else {
+            return Ok(None);
+        };
+        let file = match args.snapshot_file {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|e| Error::Io { file: path.clone(), message: e.to_string() })?;
+                Some((path, file))
+            }
+            None => None,
+        };
+        let start = Instant::now();
+        Ok(Some(Snapshots { every, file, start, last: (0, start), taken: 0 }))
+    }
+
+    /// Whether a snapshot is due with `lines` read so far. Line counts are due at each multiple
+    /// of --snapshot-every, however sparse the matches that get to check.
+    pub fn due(&self, lines: usize) -> bool {
+        match self.every {
+            Every::Lines(every) => lines / every > self.last.0 / every,
+            Every::Time(every) => self.last.1.elapsed() >= every,
+        }
+    }
+
+    /// Takes a snapshot with `lines` read so far. `render` gives the result as the run would
+    /// print it, or as JSON records when asked for them.
+    pub fn take(
+        &mut self,
+        lines: usize,
+        interrupted: bool,
+        reporter: &Reporter,
+        render: impl Fn(bool) -> Result<String, String>,
+    ) -> Result<(), Error> {
+        let now = Instant::now();
+        self.last = (lines, now);
+        self.taken += 1;
+        let records = render(true)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Value>, _>>()
+            .map_err(|e| e.to_string())?;
+        let snapshot = Snapshot {
+            sequence: self.taken,
+            lines,
+            elapsed_secs: (now - self.start).as_secs_f64(),
+            partial: true,
+            interrupted,
+            result: records,
+        };
+        match &mut self.file {
+            Some((path, file)) => writeln!(file, "{}", snapshot_record(&snapshot))
+                .map_err(|e| Error::Io { file: path.clone(), message: e.to_string() }),
+            None => {
+                reporter.snapshot(&snapshot, &render(false)?);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digits_are_lines_and_anything_else_a_duration() {
+        assert_eq!("1000000".parse(), Ok(Every::Lines(1_000_000)));
+        assert_eq!("10s".parse(), Ok(Every::Time(Duration::from_secs(10))));
+        assert_eq!("1m30s".parse(), Ok(Every::Time(Duration::from_secs(90))));
+        assert_eq!("0".parse::<Every>(), Err("expected at least 1 line".to_string()));
+        assert_eq!("0s".parse::<Every>(), Err("expected a duration longer than 0".to_string()));
+        assert!("ten".parse::<Every>().unwrap_err().starts_with("invalid duration"));
+    }
+
+    #[test]
+    fn line_snapshots_are_due_at_each_multiple() {
+        let args = SnapshotArgs { snapshot_every: Some(Every::Lines(10)), snapshot_file: None };
+        let Ok(Some(mut snapshots)) = Snapshots::new(args) else {
+            panic!("--snapshot-every is given");
+        };
+        assert!(!snapshots.due(9));
+        assert!(snapshots.due(10));
+        snapshots.last.0 = 13;
+        assert!(!snapshots.due(19));
+        assert!(snapshots.due(20));
+        assert!(snapshots.due(45));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::budget::Account;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Lines, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    vec,
+};
+
+// Sorts more items than fit in memory. Items are buffered until their approximate size takes the
+// run past --max-memory, then the buffer is sorted and written to a temporary file as one run of
+// JSON lines. `finish` merges the runs with whatever is still buffered.
+
+/// Spill directories that still exist, removed by `abandon` before exiting.
+static SPILL_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// Removes all spill directories and exits, on a second Ctrl-C. The lock is held until exit so
+/// that a run being written is not cut short with a confusing I/O error.
+pub fn abandon() {
+    let dirs = SPILL_DIRS.lock();
+    for dir in dirs.iter().flat_map(|dirs| dirs.iter()) {
+        let _ = fs::remove_dir_all(dir);
+    }
+    std::process::exit(130);
+}
+
+pub struct ExternalSorter<T> {
+    buffer: Vec<T>,
+    /// What the buffer holds of the run's --max-memory.
+    memory: Account,
+    dir: Option<PathBuf>,
+    runs: Vec<PathBuf>,
+}
+
+enum Run<T> {
+    Memory(vec::IntoIter<T>),
+    File(Lines<BufReader<File>>),
+}
+
+impl<T: DeserializeOwned> Run<T> {
+    fn next(&mut self) -> Option<Result<T, String>> {
+        match self {
+            Run::Memory(items) => items.next().map(Ok),
+            Run::File(lines) => lines.next().map(|line| {
+                let line = line.map_err(|e| format!("Could not read sort spill file: {}", e))?;
+                serde_json::from_str(&line).map_err(|e| format!("Corrupt sort spill file: {}", e))
+            }),
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Ord> ExternalSorter<T> {
+    pub fn new(memory: Account) -> Self {
+        ExternalSorter { buffer: Vec::new(), memory, dir: None, runs: Vec::new() }
+    }
+
+    /// Adds an item whose in-memory footprint is roughly `size` bytes, spilling the buffer once
+    /// the run is out of memory.
+    pub fn push(&mut self, item: T, size: usize) -> Result<(), String> {
+        self.buffer.push(item);
+        if self.memory.charge(size + std::mem::size_of::<T>()).is_err() {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill_dir(&mut self) -> Result<PathBuf, String> {
+        if let Some(dir) = &self.dir {
+            return Ok(dir.clone());
+        }
+        let dir = std::env::temp_dir().join(format!(
+            "log-parser-sort-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).map_err(|e| format!("Could not create sort spill directory: {}", e))?;
+        SPILL_DIRS.lock().map_err(|e| e.to_string())?.push(dir.clone());
+        self.dir = Some(dir.clone());
+        Ok(dir)
+    }
+
+    fn spill(&mut self) -> Result<(), String> {
+        let path = self.spill_dir()?.join(format!("run-{}.jsonl", self.runs.len()));
+        let _writing = SPILL_DIRS.lock().map_err(|e| e.to_string())?;
+        self.runs.push(path.clone());
+        self.buffer.sort();
+        let write_error = |e: std::io::Error| format!("Could not write sort spill file: {}", e);
+        let mut writer = BufWriter::new(File::create(&path).map_err(write_error)?);
+        for item in self.buffer.drain(..) {
+            serde_json::to_writer(&mut writer, &item).map_err(|e| e.to_string())?;
+            writer.write_all(b"\n").map_err(write_error)?;
+        }
+        writer.flush().map_err(write_error)?;
+        self.memory.release();
+        Ok(())
+    }
+
+    /// Calls `f` with every pushed item in ascending order.
+    pub fn finish(mut self, mut f: impl FnMut(T) -> Result<(), String>) -> Result<(), String> {
+        self.buffer.sort();
+        let mut runs = vec![Run::Memory(std::mem::take(&mut self.buffer).into_iter())];
+        for path in &self.runs {
+            let file = File::open(path).map_err(|e| format!("Could not read sort spill file: {}", e))?;
+            runs.push(Run::File(BufReader::new(file).lines()));
+        }
+
+        let mut heads = BinaryHeap::new();
+        for (index, run) in runs.iter_mut().enumerate() {
+            if let Some(item) = run.next() {
+                heads.push(Reverse((item?, index)));
+            }
+        }
+        while let Some(Reverse((item, index))) = heads.pop() {
+            if let Some(next) = runs[index].next() {
+                heads.push(Reverse((next?, index)));
+            }
+            f(item)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Drop for ExternalSorter<T> {
+    fn drop(&mut self) {
+        if let Some(dir) = self.dir.take() {
+            let _ = fs::remove_dir_all(&dir);
+            if let Ok(mut dirs) = SPILL_DIRS.lock() {
+                dirs.retain(|d| *d != dir);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::MemoryBudget;
+
+    fn sorter<T: Serialize + DeserializeOwned + Ord>(max_memory: usize) -> ExternalSorter<T> {
+        ExternalSorter::new(MemoryBudget::new(max_memory).account("--sort", ""))
+    }
+
+    fn sorted(items: &[u64], max_memory: usize) -> (Vec<u64>, usize) {
+        let mut sorter = sorter(max_memory);
+        for item in items {
+            sorter.push(*item, 8).unwrap();
+        }
+        let runs = sorter.runs.len();
+        let mut out = Vec::new();
+        sorter
+            .finish(|item| {
+                out.push(item);
+                Ok(())
+            })
+            .unwrap();
+        (out, runs)
+    }
+
+    #[test]
+    fn spilled_runs_merge_to_the_in_memory_result() {
+        let items: Vec<u64> = (0..1000u64).map(|i| (i * 7919) % 1009).collect();
+        let (in_memory, no_runs) = sorted(&items, usize::MAX);
+        let (spilled, runs) = sorted(&items, 64);
+        assert_eq!(no_runs, 0);
+        assert!(runs > 10, "expected many spill files, got {}", runs);
+        assert_eq!(in_memory, spilled);
+        assert!(in_memory.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn spill_directory_is_removed() {
+        let mut sorter = sorter(0);
+        sorter.push("b".to_string(), 1).unwrap();
+        sorter.push("a".to_string(), 1).unwrap();
+        let dir = sorter.dir.clone().expect("spilled");
+        assert!(dir.is_dir());
+        drop(sorter);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn every_threshold_merges_like_an_in_memory_sort() {
+        let words = ["delta", "alpha", "", "charlie", "alpha", "bravo", "\"quoted\"\n", "échelle"];
+        let items: Vec<(String, u64)> = (0..600u64).map(|i| (words[(i * 5 % 8) as usize].to_string(), i * 37 % 11)).collect();
+        let mut expected = items.clone();
+        expected.sort();
+        for max_memory in [0, 1, 100, 1000, usize::MAX] {
+            let mut sorter = sorter(max_memory);
+            for item in &items {
+                sorter.push(item.clone(), item.0.len()).unwrap();
+            }
+            let mut merged = Vec::new();
+            sorter
+                .finish(|item| {
+                    merged.push(item);
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(merged, expected, "with --max-memory {}", max_memory);
+        }
+    }
+
+    #[test]
+    fn each_spill_file_is_one_sorted_run() {
+        let mut sorter = sorter(100);
+        for item in (0..200u64).rev() {
+            sorter.push(item, 8).unwrap();
+        }
+        let dir = sorter.dir.clone().expect("spilled");
+        let mut files: Vec<PathBuf> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        files.sort();
+        let mut runs = sorter.runs.clone();
+        runs.sort();
+        assert_eq!(files, runs);
+        assert!(runs.len() > 5, "expected many spill files, got {}", runs.len());
+        for run in &runs {
+            let items: Vec<u64> = fs::read_to_string(run).unwrap().lines().map(|line| line.parse().unwrap()).collect();
+            assert!(!items.is_empty() && items.windows(2).all(|w| w[0] <= w[1]), "{} isn't sorted", run.display());
+        }
+    }
+
+    #[test]
+    fn nothing_pushed_or_nothing_spilled_needs_no_directory() {
+        assert_eq!(sorted(&[], 0), (vec![], 0));
+        let sorter = sorter::<u64>(0);
+        assert!(sorter.dir.is_none());
+        let (items, runs) = sorted(&[3, 1, 2], usize::MAX);
+        assert_eq!((items, runs), (vec![1, 2, 3], 0));
+    }
+
+    #[test]
+    fn spill_directory_is_removed_when_output_fails() {
+        let mut sorter = sorter(16);
+        for item in 0..50u64 {
+            sorter.push(item, 8).unwrap();
+        }
+        let dir = sorter.dir.clone().expect("spilled");
+        let mut seen = 0;
+        let result = sorter.finish(|_| {
+            seen += 1;
+            if seen == 10 {
+                return Err("Broken pipe".to_string());
+            }
+            Ok(())
+        });
+        assert_eq!(result, Err("Broken pipe".to_string()));
+        assert!(!dir.exists());
+        assert!(!SPILL_DIRS.lock().unwrap().contains(&dir));
+    }
+}
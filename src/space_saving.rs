@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// `stats --by` and `top` count every group exactly, which on a crawl-heavy log can mean tens of
+// millions of distinct paths or user agents. With --approximate they keep a SpaceSaving summary
+// instead (Metwally, Agrawal and El Abbadi, "Efficient computation of frequent and top-k
+// elements in data streams", 2005): at most --sketch-size counters, where a value without one
+// takes over the smallest, inheriting its count as the value's possible error. So a count is
+// never less than the true one and at most its error more, and no error exceeds matches divided
+// by the capacity, which means every value with more matches than that is sure to be counted.
+// The counters form a min-heap on count so the smallest is always at hand.
+
+/// A value's count, which is at most `error` more than its true count.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Counted<K> {
+    pub key: K,
+    pub count: usize,
+    pub error: usize,
+}
+
+#[derive(Clone)]
+pub struct SpaceSaving<K> {
+    capacity: usize,
+    total: usize,
+    heap: Vec<Counted<K>>,
+    /// Where each counted value is in the heap.
+    index: HashMap<K, usize>,
+}
+
+impl<K: Clone + Ord + Hash> SpaceSaving<K> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "a summary needs at least one counter");
+        SpaceSaving { capacity, total: 0, heap: Vec::new(), index: HashMap::new() }
+    }
+
+    pub fn insert(&mut self, key: K) {
+        self.total += 1;
+        if let Some(&at) = self.index.get(&key) {
+            self.heap[at].count += 1;
+            self.sift_down(at);
+        } else if self.heap.len() < self.capacity {
+            self.index.insert(key.clone(), self.heap.len());
+            self.heap.push(Counted { key, count: 1, error: 0 });
+            self.sift_up(self.heap.len() - 1);
+        } else {
+            let smallest = &mut self.heap[0];
+            self.index.remove(&smallest.key);
+            let error = smallest.count;
+            *smallest = Counted { key: key.clone(), count: error + 1, error };
+            self.index.insert(key, 0);
+            self.sift_down(0);
+        }
+    }
+
+    /// Values inserted, counted or not.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// The most a value without a counter can have been inserted, and the most any count can be
+    /// off by: the smallest count once every counter is taken, and none before.
+    pub fn bound(&self) -> usize {
+        match self.heap.len() < self.capacity {
+            true => 0,
+            false => self.heap[0].count,
+        }
+    }
+
+    /// The counted values, in no particular order.
+    pub fn counted(&self) -> impl Iterator<Item = &Counted<K>> {
+        self.heap.iter()
+    }
+
+    /// The counted values, the most frequent first, then the most certain, then in key order.
+    pub fn ranked(&self) -> Vec<&Counted<K>> {
+        let mut ranked: Vec<&Counted<K>> = self.heap.iter().collect();
+        ranked.sort_by(|a, b| b.count.cmp(&a.count).then(a.error.cmp(&b.error)).then(a.key.cmp(&b.key)));
+        ranked
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        for at in [a, b] {
+            *self.index.get_mut(&self.heap[at].key).expect("counted values are indexed") = at;
+        }
+    }
+
+    fn sift_up(&mut self, mut at: usize) {
+        while at > 0 {
+            let parent = (at - 1) / 2;
+            if self.heap[parent].count <= self.heap[at].count {
+                break;
+            }
+            self.swap(parent, at);
+            at = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut at: usize) {
+        loop {
+            let mut smallest = at;
+            for child in [2 * at + 1, 2 * at + 2] {
+                if child < self.heap.len() && self.heap[child].count < self.heap[smallest].count {
+                    smallest = child;
+                }
+            }
+            if smallest == at {
+                break;
+            }
+            self.swap(at, smallest);
+            at = smallest;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::Rng;
+
+    /// `count` draws from a Zipf-like distribution over `values` values: value `i` is drawn about
+    /// 1/(i+1) as often as value 0.
+    fn skewed(values: u64, count: usize, seed: u64) -> Vec<u64> {
+        let weights: Vec<f64> = (0..values).map(|i| 1.0 / (i + 1) as f64).collect();
+        let total: f64 = weights.iter().sum();
+        let mut rng = Rng::new(seed);
+        (0..count)
+            .map(|_| {
+                let mut pick = rng.below(1 << 53) as f64 / (1u64 << 53) as f64 * total;
+                weights.iter().position(|w| {
+                    pick -= w;
+                    pick < 0.0
+                })
+                .unwrap_or(values as usize - 1) as u64
+            })
+            .collect()
+    }
+
+    fn exact(values: &[u64]) -> HashMap<u64, usize> {
+        let mut counts = HashMap::new();
+        for &value in values {
+            *counts.entry(value).or_default() += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn counts_are_within_their_error_of_the_truth() {
+        for (capacity, seed) in [(10, 1), (50, 2), (200, 3)] {
+            let values = skewed(1_000, 20_000, seed);
+            let exact = exact(&values);
+            let mut summary = SpaceSaving::new(capacity);
+            for &value in &values {
+                summary.insert(value);
+            }
+            assert_eq!(summary.total(), values.len());
+            assert!(summary.bound() <= values.len() / capacity);
+            for counted in summary.counted() {
+                let truth = exact.get(&counted.key).copied().unwrap_or(0);
+                assert!(truth <= counted.count && counted.count - counted.error <= truth, "{:?} counted for {}", counted, truth);
+                assert!(counted.error <= summary.bound());
+            }
+            // Anything more frequent than the bound has a counter.
+            for (value, &count) in &exact {
+                assert!(count <= summary.bound() || summary.counted().any(|c| c.key == *value), "{} lost with {}", value, count);
+            }
+        }
+    }
+
+    #[test]
+    fn the_true_top_values_rank_first() {
+        let values = skewed(10_000, 100_000, 7);
+        let mut exact: Vec<(u64, usize)> = exact(&values).into_iter().collect();
+        exact.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let mut summary = SpaceSaving::new(500);
+        for &value in &values {
+            summary.insert(value);
+        }
+        // The top ten are far above the bound and apart from each other, so they come out
+        // exactly in order.
+        let ranked: Vec<u64> = summary.ranked().iter().take(10).map(|c| c.key).collect();
+        assert_eq!(ranked, exact.iter().take(10).map(|&(value, _)| value).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn small_inputs_are_counted_exactly() {
+        let mut summary = SpaceSaving::new(3);
+        for value in ["a", "b", "a", "c", "a", "b"] {
+            summary.insert(value);
+        }
+        let ranked: Vec<(&str, usize, usize)> = summary.ranked().iter().map(|c| (c.key, c.count, c.error)).collect();
+        assert_eq!(ranked, [("a", 3, 0), ("b", 2, 0), ("c", 1, 0)]);
+        assert_eq!(summary.bound(), 1);
+
+        // A fourth value takes over the smallest counter, c's.
+        summary.insert("d");
+        let ranked: Vec<(&str, usize, usize)> = summary.ranked().iter().map(|c| (c.key, c.count, c.error)).collect();
+        assert_eq!(ranked, [("a", 3, 0), ("b", 2, 0), ("d", 2, 1)]);
+    }
+}
@@ -0,0 +1,355 @@
+use crate::diagnostics::RunSummary;
+use crate::entry::{Entry, FieldValue};
+use crate::gap;
+use crate::output::{
+    json_array,
+    schema::{Figures, MetricSummary},
+    table::Table,
+};
+use crate::size::format_size;
+use crate::zone::Zone;
+use chrono::FixedOffset;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Metric {
+    /// Request duration in seconds
+    Duration,
+    /// Response size in bytes
+    Bytes,
+    /// Seconds since the client's previous request, the `gap` field
+    Gap,
+}
+
+impl Metric {
+    pub fn value(&self, entry: &Entry) -> Option<f64> {
+        match self {
+            Metric::Duration => entry.duration.map(|d| d.as_secs_f64()),
+            Metric::Bytes => entry.bytes.map(|b| b as f64),
+            Metric::Gap => entry.extras.get(gap::FIELD).and_then(FieldValue::as_number),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum StatsOutput {
+    #[default]
+    Table,
+    Json,
+    /// The JSON records as one document: an array of them, or the one object `stats` prints
+    JsonArray,
+}
+
+/// The formats of `stats`: those of the other commands printing records, and a page.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum SummaryOutput {
+    #[default]
+    Table,
+    Json,
+    /// The one object `stats` prints, or with --by the nested groups
+    JsonArray,
+    /// A self-contained HTML page of the table
+    Html,
+}
+
+impl StatsOutput {
+    /// Output rendered as JSON records, collected into one array for `-o json-array`.
+    pub fn document(self, rendered: String) -> String {
+        match self {
+            StatsOutput::JsonArray => json_array::collect(&rendered),
+            _ => rendered,
+        }
+    }
+}
+
+const PERCENTILES: &[f64] = &[50.0, 90.0, 95.0, 99.0];
+
+/// Nearest-rank percentile over already sorted values.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Collects metric values from matched entries and summarizes them.
+#[derive(Clone, Default)]
+pub struct Summary {
+    values: Vec<f64>,
+    missing: usize,
+}
+
+impl Summary {
+    pub fn add(&mut self, value: Option<f64>) {
+        match value {
+            Some(value) => self.values.push(value),
+            None => self.missing += 1,
+        }
+    }
+
+    /// The rows of `render` as one JSON record, without the figures of an empty metric.
+    pub fn into_json(mut self) -> MetricSummary {
+        let (count, missing) = (self.values.len(), self.missing);
+        if self.values.is_empty() {
+            return MetricSummary { count, missing, figures: None };
+        }
+        self.values.sort_by(f64::total_cmp);
+        let values = &self.values;
+        let figures = Figures {
+            min: values[0],
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            p50: percentile(values, 50.0),
+            p90: percentile(values, 90.0),
+            p95: percentile(values, 95.0),
+            p99: percentile(values, 99.0),
+        };
+        MetricSummary { count, missing, figures: Some(figures) }
+    }
+
+    pub fn table(mut self) -> Table {
+        let mut table = Table::default();
+        table.row(vec!["count".to_string(), self.values.len().to_string()]);
+        table.row(vec!["missing".to_string(), self.missing.to_string()]);
+        if self.values.is_empty() {
+            return table;
+        }
+        self.values.sort_by(f64::total_cmp);
+        let sum: f64 = self.values.iter().sum();
+        table.row(vec!["min".to_string(), self.values[0].to_string()]);
+        table.row(vec!["max".to_string(), self.values[self.values.len() - 1].to_string()]);
+        table.row(vec!["mean".to_string(), (sum / self.values.len() as f64).to_string()]);
+        for &p in PERCENTILES {
+            table.row(vec![format!("p{}", p), percentile(&self.values, p).to_string()]);
+        }
+        table
+    }
+}
+
+/// Running mean and variance in one pass and constant memory, by Welford's method, which
+/// stays accurate where summing squares would cancel out.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Welford {
+    pub count: usize,
+    pub mean: f64,
+    /// Sum of squared differences from the current mean.
+    m2: f64,
+}
+
+impl Welford {
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// The sample standard deviation, absent with fewer than two values.
+    pub fn stddev(&self) -> Option<f64> {
+        (self.count > 1).then(|| (self.m2 / (self.count - 1) as f64).sqrt())
+    }
+}
+
+/// Parses a fixed `--tz`: `UTC`, `Z` or an offset such as `+02:00` or `-0500`.
+pub fn parse_tz(s: &str) -> Result<FixedOffset, String> {
+    if s.eq_ignore_ascii_case("utc") || s == "Z" {
+        return Ok(FixedOffset::east_opt(0).unwrap());
+    }
+    let invalid = || format!("invalid timezone {:?}, expected UTC or an offset like +02:00", s);
+    let (sign, digits) = match s.split_at_checked(1) {
+        Some(("+", rest)) => (1, rest),
+        Some(("-", rest)) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    let digits = digits.replace(':', "");
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let hours: i32 = digits[..2].parse().map_err(|_| invalid())?;
+    let minutes: i32 = digits[2..].parse().map_err(|_| invalid())?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(invalid)
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakdownKey {
+    /// The input file an entry was read from
+    File,
+    /// The calendar day of the entry's timestamp, in --tz or else the offset it was logged with
+    Day,
+}
+
+/// Match counts per input file or per day. Each file is counted separately and merged into the
+/// run's totals once it has been read.
+#[derive(Serialize)]
+pub struct Breakdown {
+    by: BreakdownKey,
+    #[serde(skip)]
+    tz: Option<Zone>,
+    counts: BTreeMap<String, usize>,
+}
+
+impl Breakdown {
+    pub fn new(by: BreakdownKey, tz: Option<Zone>) -> Self {
+        Breakdown { by, tz, counts: BTreeMap::new() }
+    }
+
+    /// Lists a file in a per-file breakdown even if nothing in it matches.
+    pub fn start(&mut self, file: &Path) {
+        if let BreakdownKey::File = self.by {
+            self.counts.entry(file.display().to_string()).or_default();
+        }
+    }
+
+    pub fn add(&mut self, file: &Path, entry: &Entry) {
+        let key = match self.by {
+            BreakdownKey::File => file.display().to_string(),
+            BreakdownKey::Day => crate::group::day(entry, self.tz),
+        };
+        *self.counts.entry(key).or_default() += 1;
+    }
+
+    pub fn merge(&mut self, other: Breakdown) {
+        for (key, count) in other.counts {
+            *self.counts.entry(key).or_default() += count;
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let by = match self.by {
+            BreakdownKey::File => "file",
+            BreakdownKey::Day => "day",
+        };
+        let mut table = Table::new(&[by, "matched"]);
+        for (key, count) in &self.counts {
+            table.row(vec![key.clone(), count.to_string()]);
+        }
+        table.row(vec!["total".to_string(), self.counts.values().sum::<usize>().to_string()]);
+        table.render()
+    }
+}
+
+/// Total response size of matched entries for --show-bytes. Entries without a size count as 0.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct MatchedBytes {
+    #[serde(rename = "matched_bytes")]
+    pub total: u64,
+    #[serde(rename = "missing_bytes")]
+    pub missing: usize,
+}
+
+impl MatchedBytes {
+    pub fn add(&mut self, bytes: Option<u64>) {
+        match bytes {
+            Some(bytes) => self.total += bytes,
+            None => self.missing += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: MatchedBytes) {
+        self.total += other.total;
+        self.missing += other.missing;
+    }
+}
+
+/// `N matches`, followed by the matched size when it was tracked.
+pub fn describe_matches(matched: usize, bytes: Option<&MatchedBytes>) -> String {
+    let plural = if matched == 1 { "" } else { "es" };
+    let mut out = format!("{} match{}", matched, plural);
+    if let Some(bytes) = bytes {
+        out += &format!(", {}", format_size(bytes.total));
+        if bytes.missing > 0 {
+            out += &format!(" ({} without a size)", bytes.missing);
+        }
+    }
+    out
+}
+
+/// What `filter --count` prints once every input has been read.
+#[derive(Serialize, Default)]
+pub struct Count {
+    pub matched: usize,
+    #[serde(flatten)]
+    pub bytes: Option<MatchedBytes>,
+}
+
+impl Count {
+    pub fn add(&mut self, summary: &RunSummary) {
+        self.matched += summary.matched;
+        if let (Some(total), Some(bytes)) = (&mut self.bytes, summary.matched_bytes) {
+            total.merge(bytes);
+        }
+    }
+
+    pub fn render(&self) -> String {
+        format!("{}\n", describe_matches(self.matched, self.bytes.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn welford_matches_the_two_pass_formulas() {
+        let mut running = Welford::default();
+        assert_eq!(running.stddev(), None);
+        for value in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            running.add(value);
+        }
+        assert_eq!(running.count, 8);
+        assert_eq!(running.mean, 5.0);
+        assert!((running.stddev().unwrap() - (32.0f64 / 7.0).sqrt()).abs() < 1e-12);
+
+        // A large offset would wreck the naive sum of squares.
+        let mut shifted = Welford::default();
+        for value in [4.0, 7.0, 13.0, 16.0] {
+            shifted.add(1e9 + value);
+        }
+        assert_eq!(shifted.mean, 1e9 + 10.0);
+        assert!((shifted.stddev().unwrap() - 30f64.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn timezones() {
+        assert_eq!(parse_tz("UTC"), Ok(FixedOffset::east_opt(0).unwrap()));
+        assert_eq!(parse_tz("+02:00"), Ok(FixedOffset::east_opt(7200).unwrap()));
+        assert_eq!(parse_tz("-0530"), Ok(FixedOffset::west_opt(19800).unwrap()));
+        assert!(parse_tz("Europe/Berlin").is_err());
+        assert!(parse_tz("+2").is_err());
+    }
+
+    #[test]
+    fn days_follow_the_timezone() {
+        let entry = Entry {
+            timestamp: Some(chrono::DateTime::parse_from_rfc3339("2023-02-12T23:30:00+00:00").unwrap()),
+            ..Entry::default()
+        };
+        let file = Path::new("a.log");
+        let mut logged = Breakdown::new(BreakdownKey::Day, None);
+        let mut shifted = Breakdown::new(BreakdownKey::Day, parse_tz("+01:00").ok().map(Zone::fixed));
+        logged.add(file, &entry);
+        shifted.add(file, &entry);
+        shifted.add(file, &Entry::default());
+        assert_eq!(logged.render(), "day\tmatched\n2023-02-12\t1\ntotal\t1\n");
+        assert_eq!(shifted.render(), "day\tmatched\n-\t1\n2023-02-13\t1\ntotal\t2\n");
+    }
+
+    #[test]
+    fn merged_counts_add_up() {
+        let mut total = Breakdown::new(BreakdownKey::File, None);
+        for name in ["a.log", "b.log", "a.log"] {
+            let mut file = Breakdown::new(BreakdownKey::File, None);
+            file.add(Path::new(name), &Entry::default());
+            total.merge(file);
+        }
+        assert_eq!(total.render(), "file\tmatched\na.log\t2\nb.log\t1\ntotal\t3\n");
+    }
+
+    #[test]
+    fn file_names_containing_the_delimiter_are_quoted() {
+        let mut breakdown = Breakdown::new(BreakdownKey::File, None);
+        breakdown.start(Path::new("access\t1.log"));
+        assert_eq!(breakdown.render(), "file\tmatched\n\"access\t1.log\"\t0\ntotal\t0\n");
+    }
+}
@@ -0,0 +1,167 @@
+use crate::filter::edit_distance;
+use http::StatusCode;
+use std::str::FromStr;
+
+// Status codes by name for --status-code, e.g. `eq not_found` or `gte internal_server_error`,
+// so nobody has to remember whether Payload Too Large is 413 or 414 in the middle of an incident.
+// A code's name is the snake_case of its canonical reason phrase, as the http crate knows it,
+// and a few codes also go by the names of older RFCs or common shorthand.
+
+/// Names that aren't the canonical reason of their code.
+const ALIASES: &[(&str, u16)] = &[
+    ("moved_temporarily", 302),
+    ("request_entity_too_large", 413),
+    ("content_too_large", 413),
+    ("request_uri_too_long", 414),
+    ("requested_range_not_satisfiable", 416),
+    ("teapot", 418),
+    ("unprocessable_content", 422),
+    ("rate_limited", 429),
+];
+
+/// How many names an unknown one is offered instead.
+const SUGGESTIONS: usize = 3;
+
+/// `Not Found` as `not_found`, `Multi-Status` as `multi_status` and `I'm a teapot` as
+/// `im_a_teapot`.
+fn snake_case(reason: &str) -> String {
+    let words: Vec<String> = reason
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '\''))
+        .filter(|word| !word.is_empty())
+        .map(|word| word.replace('\'', "").to_ascii_lowercase())
+        .collect();
+    words.join("_")
+}
+
+/// Every name with its code: the canonical ones in code order, then the aliases.
+pub fn names() -> Vec<(String, StatusCode)> {
+    let canonical = (100..1000)
+        .filter_map(|code| StatusCode::from_u16(code).ok())
+        .filter_map(|status| status.canonical_reason().map(|reason| (snake_case(reason), status)));
+    let aliases = ALIASES
+        .iter()
+        .map(|&(name, code)| (name.to_string(), StatusCode::from_u16(code).expect("aliases name valid codes")));
+    canonical.chain(aliases).collect()
+}
+
+/// A status code given by number, e.g. `404`, or by name, e.g. `not_found`.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Status(pub StatusCode);
+
+impl FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with(|c: char| c.is_ascii_digit()) {
+            return s.parse().map(Status).map_err(|e: http::status::InvalidStatusCode| e.to_string());
+        }
+        let name = s.to_ascii_lowercase().replace(['-', ' '], "_");
+        let names = names();
+        if let Some(&(_, status)) = names.iter().find(|(known, _)| *known == name) {
+            return Ok(Status(status));
+        }
+        let mut closest: Vec<(usize, &str)> = names.iter().map(|(known, _)| (edit_distance(&name, known), known.as_str())).collect();
+        closest.sort_by_key(|&(distance, _)| distance);
+        let closest: Vec<&str> = closest.iter().take(SUGGESTIONS).map(|&(_, known)| known).collect();
+        Err(format!("unknown status name {:?}; did you mean {}?", s, closest.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(value: &str) -> Result<u16, String> {
+        value.parse::<Status>().map(|Status(status)| status.as_u16())
+    }
+
+    /// Every code the http crate has a reason phrase for.
+    const NAMES: &[(u16, &str)] = &[
+        (100, "continue"),
+        (101, "switching_protocols"),
+        (102, "processing"),
+        (200, "ok"),
+        (201, "created"),
+        (202, "accepted"),
+        (203, "non_authoritative_information"),
+        (204, "no_content"),
+        (205, "reset_content"),
+        (206, "partial_content"),
+        (207, "multi_status"),
+        (208, "already_reported"),
+        (226, "im_used"),
+        (300, "multiple_choices"),
+        (301, "moved_permanently"),
+        (302, "found"),
+        (303, "see_other"),
+        (304, "not_modified"),
+        (305, "use_proxy"),
+        (307, "temporary_redirect"),
+        (308, "permanent_redirect"),
+        (400, "bad_request"),
+        (401, "unauthorized"),
+        (402, "payment_required"),
+        (403, "forbidden"),
+        (404, "not_found"),
+        (405, "method_not_allowed"),
+        (406, "not_acceptable"),
+        (407, "proxy_authentication_required"),
+        (408, "request_timeout"),
+        (409, "conflict"),
+        (410, "gone"),
+        (411, "length_required"),
+        (412, "precondition_failed"),
+        (413, "payload_too_large"),
+        (414, "uri_too_long"),
+        (415, "unsupported_media_type"),
+        (416, "range_not_satisfiable"),
+        (417, "expectation_failed"),
+        (418, "im_a_teapot"),
+        (421, "misdirected_request"),
+        (422, "unprocessable_entity"),
+        (423, "locked"),
+        (424, "failed_dependency"),
+        (426, "upgrade_required"),
+        (428, "precondition_required"),
+        (429, "too_many_requests"),
+        (431, "request_header_fields_too_large"),
+        (451, "unavailable_for_legal_reasons"),
+        (500, "internal_server_error"),
+        (501, "not_implemented"),
+        (502, "bad_gateway"),
+        (503, "service_unavailable"),
+        (504, "gateway_timeout"),
+        (505, "http_version_not_supported"),
+        (506, "variant_also_negotiates"),
+        (507, "insufficient_storage"),
+        (508, "loop_detected"),
+        (510, "not_extended"),
+        (511, "network_authentication_required"),
+    ];
+
+    #[test]
+    fn every_registered_code_resolves_by_name_and_number() {
+        for &(code, name) in NAMES {
+            assert_eq!(status(name), Ok(code), "{}", name);
+            assert_eq!(status(&code.to_string()), Ok(code));
+        }
+        let canonical: Vec<(u16, String)> =
+            names().into_iter().take_while(|(name, _)| name != ALIASES[0].0).map(|(name, status)| (status.as_u16(), name)).collect();
+        assert_eq!(canonical, NAMES.iter().map(|&(code, name)| (code, name.to_string())).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn aliases_and_spellings_resolve() {
+        assert_eq!(status("request_entity_too_large"), Ok(413));
+        assert_eq!(status("rate_limited"), Ok(429));
+        assert_eq!(status("Not-Found"), Ok(404));
+        assert_eq!(status("Too Many Requests"), Ok(429));
+        assert_eq!(status("599"), Ok(599));
+    }
+
+    #[test]
+    fn unknown_names_get_the_closest_suggestions() {
+        assert_eq!(status("not_fund"), Err("unknown status name \"not_fund\"; did you mean not_found, found, continue?".to_string()));
+        assert_eq!(status("5000"), Err("invalid status code".to_string()));
+    }
+}
@@ -0,0 +1,201 @@
+// The `suspicious` field and --suspicious-paths: why a path, as logged rather than as
+// --normalize-paths leaves it, looks like a traversal or encoding attack. The reasons:
+//
+//     dot-dot            a `..` segment: `../`, `..\`, `..;/`, or a bare `..` at the end
+//     dot-dot-encoded    a `..` segment once percent-decoded, e.g. `..%2f` or `%2e%2e/`
+//     double-encoded     a `..` segment, NUL or overlong encoding only decoded twice, e.g. `%252e%252e/`
+//     null-byte          a NUL, raw or decoded, e.g. `%00`
+//     overlong-encoding  a character in more UTF-8 bytes than it takes, e.g. `%c0%ae` for `.`
+//     non-ascii          bytes outside ASCII as logged, rather than percent-encoded
+//
+// A `..` inside a segment name, as in `/files/v1..2/diff`, isn't a traversal. nginx logs bytes
+// outside printable ASCII as `\xHH`, so those count as the bytes they stand for. Computed only
+// when the filter or the command reads the field.
+
+pub const FIELD: &str = "suspicious";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reason {
+    DotDot,
+    DotDotEncoded,
+    DoubleEncoded,
+    NullByte,
+    OverlongEncoding,
+    NonAscii,
+}
+
+impl Reason {
+    pub fn name(self) -> &'static str {
+        match self {
+            Reason::DotDot => "dot-dot",
+            Reason::DotDotEncoded => "dot-dot-encoded",
+            Reason::DoubleEncoded => "double-encoded",
+            Reason::NullByte => "null-byte",
+            Reason::OverlongEncoding => "overlong-encoding",
+            Reason::NonAscii => "non-ascii",
+        }
+    }
+}
+
+/// Why `path` looks like an attack, in the order of `Reason`; empty when it doesn't.
+pub fn detect(path: &str) -> Vec<Reason> {
+    let raw = unescape(path);
+    let once = decode(&raw);
+    let twice = decode(&once);
+    let dots = [traversals(&raw), traversals(&once), traversals(&twice)];
+    let null = |bytes: &[u8]| bytes.contains(&0);
+    let checks = [
+        (Reason::DotDot, dots[0] > 0),
+        (Reason::DotDotEncoded, dots[1] > dots[0]),
+        (Reason::DoubleEncoded, dots[2] > dots[1] || (null(&twice) && !null(&once)) || (overlong(&twice) && !overlong(&once))),
+        (Reason::NullByte, null(&twice)),
+        (Reason::OverlongEncoding, overlong(&twice)),
+        (Reason::NonAscii, !raw.is_ascii()),
+    ];
+    checks.into_iter().filter_map(|(reason, found)| found.then_some(reason)).collect()
+}
+
+/// The bytes of a path, with nginx's `\xHH` escapes replaced by the bytes they stand for.
+fn unescape(path: &str) -> Vec<u8> {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut at = 0;
+    while at < bytes.len() {
+        match (bytes[at], bytes.get(at + 1), bytes.get(at + 2..at + 4).and_then(hex)) {
+            (b'\\', Some(b'x'), Some(byte)) => {
+                out.push(byte);
+                at += 4;
+            }
+            (byte, ..) => {
+                out.push(byte);
+                at += 1;
+            }
+        }
+    }
+    out
+}
+
+/// One round of percent-decoding; a `%` not followed by two hex digits stays as it is.
+fn decode(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut at = 0;
+    while at < bytes.len() {
+        match (bytes[at], bytes.get(at + 1..at + 3).and_then(hex)) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                at += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                at += 1;
+            }
+        }
+    }
+    out
+}
+
+fn hex(digits: &[u8]) -> Option<u8> {
+    let nibble = |digit: u8| (digit as char).to_digit(16);
+    match *digits {
+        [high, low] => Some((nibble(high)? * 16 + nibble(low)?) as u8),
+        _ => None,
+    }
+}
+
+/// The `..` segments: after the start, a separator or a query parameter's `=`, and before a
+/// separator, the `;` of a path parameter, or the end after a separator.
+fn traversals(bytes: &[u8]) -> usize {
+    let separator = |byte: Option<&u8>| matches!(byte, Some(b'/' | b'\\'));
+    (0..bytes.len().saturating_sub(1))
+        .filter(|&at| &bytes[at..at + 2] == b"..")
+        .filter(|&at| at == 0 || matches!(bytes[at - 1], b'/' | b'\\' | b'=' | b'?' | b'&'))
+        .filter(|&at| match bytes.get(at + 2) {
+            None => at > 0 && separator(bytes.get(at - 1)),
+            next => separator(next) || next == Some(&b';'),
+        })
+        .count()
+}
+
+/// Whether the bytes hold a UTF-8 lead byte for a character that takes fewer bytes: `C0` or
+/// `C1` for ASCII, `E0 80`-`E0 9F` and `F0 80`-`F0 8F`, each followed by a continuation byte.
+fn overlong(bytes: &[u8]) -> bool {
+    bytes.windows(2).any(|pair| match *pair {
+        [0xc0 | 0xc1, next] => (0x80..=0xbf).contains(&next),
+        [0xe0, next] => (0x80..=0x9f).contains(&next),
+        [0xf0, next] => (0x80..=0x8f).contains(&next),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_suspicious_for_each_trigger() {
+        use Reason::*;
+        let cases: &[(&str, &[Reason])] = &[
+            // Legitimate paths, `..` inside a segment name included.
+            ("/", &[]),
+            ("/index.html", &[]),
+            ("/files/v1..2/diff", &[]),
+            ("/compare/main...feature", &[]),
+            ("/a..", &[]),
+            ("/..a/b", &[]),
+            ("/...", &[]),
+            ("/search?q=..", &[]),
+            ("/search?q=a..b&page=2", &[]),
+            ("/caf%C3%A9", &[]),
+            ("/100%25", &[]),
+            ("/bad%zzescape", &[]),
+            ("/%2e/hidden", &[]),
+            ("/files/%2e%2e.txt", &[]),
+            // Traversal as logged.
+            ("/../etc/passwd", &[DotDot]),
+            ("/static/../../etc/passwd", &[DotDot]),
+            ("..\\windows\\win.ini", &[DotDot]),
+            ("/download?file=../../etc/passwd", &[DotDot]),
+            ("/app/..;/admin", &[DotDot]),
+            ("/a/b/..", &[DotDot]),
+            // Encoded once.
+            ("/..%2fetc/passwd", &[DotDotEncoded]),
+            ("/..%2Fetc%2Fpasswd", &[DotDotEncoded]),
+            ("/%2e%2e/etc/passwd", &[DotDotEncoded]),
+            ("/%2E%2E%2Fetc%2Fpasswd", &[DotDotEncoded]),
+            ("/.%2e/etc/passwd", &[DotDotEncoded]),
+            ("/%2e%2e%5cwindows", &[DotDotEncoded]),
+            ("/download?file=%2e%2e%2f%2e%2e%2fetc%2fpasswd", &[DotDotEncoded]),
+            ("/../static/%2e%2e/x", &[DotDot, DotDotEncoded]),
+            // Encoded twice.
+            ("/%252e%252e%252fetc/passwd", &[DoubleEncoded]),
+            ("/..%252fetc/passwd", &[DoubleEncoded]),
+            ("/%252e%252e/etc/passwd", &[DoubleEncoded]),
+            // Null bytes.
+            ("/index.php%00.jpg", &[NullByte]),
+            ("/index.php%2500.jpg", &[DoubleEncoded, NullByte]),
+            ("/..%2f..%2fetc/passwd%00", &[DotDotEncoded, NullByte]),
+            // Overlong encodings of `.` and `/`.
+            ("/%c0%ae%c0%ae/etc/passwd", &[OverlongEncoding]),
+            ("/..%c0%afetc/passwd", &[OverlongEncoding]),
+            ("/%e0%80%ae%e0%80%ae/", &[OverlongEncoding]),
+            ("/%f0%80%80%ae", &[OverlongEncoding]),
+            ("/%25c0%25ae%25c0%25ae/", &[DoubleEncoded, OverlongEncoding]),
+            ("/%c3%a9t%c3%a9", &[]),
+            // Bytes outside ASCII as logged, including nginx's escapes for them.
+            ("/café", &[NonAscii]),
+            ("/caf\\xC3\\xA9", &[NonAscii]),
+            ("/\\xC0\\xAE\\xC0\\xAE/etc/passwd", &[OverlongEncoding, NonAscii]),
+            ("/a\\x5C..\\x5Cwin.ini", &[DotDot]),
+            ("/a\\xZZ", &[]),
+        ];
+        for (path, reasons) in cases {
+            assert_eq!(detect(path), *reasons, "reasons for {:?}", path);
+        }
+    }
+
+    #[test]
+    fn reasons_have_names() {
+        let names: Vec<&str> = detect("/..%2f%00").into_iter().map(Reason::name).collect();
+        assert_eq!(names, ["dot-dot-encoded", "null-byte"]);
+    }
+}
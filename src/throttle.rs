@@ -0,0 +1,195 @@
+use crate::diagnostics::{Notice, Reporter};
+use crate::duration::HumanDuration;
+use crate::entry::Entry;
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use std::{collections::HashMap, str::FromStr};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThrottleKey {
+    Ip,
+    Path,
+    Status,
+    IpPath,
+}
+
+impl ThrottleKey {
+    fn name(&self) -> &'static str {
+        match self {
+            ThrottleKey::Ip => "ip",
+            ThrottleKey::Path => "path",
+            ThrottleKey::Status => "status",
+            ThrottleKey::IpPath => "ip+path",
+        }
+    }
+
+    fn value(&self, entry: &Entry) -> String {
+        let ip = || entry.client.as_ref().map_or_else(|| "-".to_string(), |client| client.to_string());
+        let path = || entry.match_path().clone().unwrap_or_else(|| "-".to_string());
+        match self {
+            ThrottleKey::Ip => ip(),
+            ThrottleKey::Path => path(),
+            ThrottleKey::Status => entry.status_code.map_or_else(|| "-".to_string(), |s| s.as_u16().to_string()),
+            ThrottleKey::IpPath => format!("{} {}", ip(), path()),
+        }
+    }
+}
+
+/// `--throttle <key>:<duration>`, e.g. `ip:60s`.
+#[derive(Clone, Debug)]
+pub struct ThrottleSpec {
+    key: ThrottleKey,
+    window: TimeDelta,
+}
+
+impl FromStr for ThrottleSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, window) = s.split_once(':').ok_or_else(|| format!("expected <key>:<duration>, e.g. ip:60s, got {:?}", s))?;
+        let key = match key {
+            "ip" => ThrottleKey::Ip,
+            "path" => ThrottleKey::Path,
+            "status" => ThrottleKey::Status,
+            "ip+path" => ThrottleKey::IpPath,
+            _ => return Err(format!("unknown throttle key {:?}, expected ip, path, status or ip+path", key)),
+        };
+        let window: HumanDuration = window.parse()?;
+        let window = TimeDelta::from_std(window.0).map_err(|e| e.to_string())?;
+        Ok(ThrottleSpec { key, window })
+    }
+}
+
+struct Window {
+    start: DateTime<FixedOffset>,
+    suppressed: usize,
+}
+
+/// Lets through the first match per key value in each window, measured by entry timestamps.
+/// Entries without a timestamp are always let through.
+pub struct Throttle {
+    spec: ThrottleSpec,
+    windows: HashMap<String, Window>,
+    next_prune: Option<DateTime<FixedOffset>>,
+}
+
+impl Throttle {
+    pub fn new(spec: ThrottleSpec) -> Self {
+        Throttle { spec, windows: HashMap::new(), next_prune: None }
+    }
+
+    fn note(&self, reporter: &Reporter, value: String, suppressed: usize) {
+        if suppressed > 0 {
+            reporter.notice(Notice::Suppressed { key: self.spec.key.name().to_string(), value, count: suppressed });
+        }
+    }
+
+    /// Whether to print this match. Reports suppressed counts as their windows close.
+    pub fn admit(&mut self, entry: &Entry, reporter: &Reporter) -> bool {
+        let Some(now) = entry.timestamp else {
+            return true;
+        };
+        if self.next_prune.is_some_and(|next| now >= next) {
+            self.prune(now, reporter);
+        }
+        self.next_prune.get_or_insert(now + self.spec.window);
+
+        let value = self.spec.key.value(entry);
+        match self.windows.get_mut(&value) {
+            Some(window) if now < window.start + self.spec.window => {
+                window.suppressed += 1;
+                false
+            }
+            Some(window) => {
+                let suppressed = std::mem::replace(window, Window { start: now, suppressed: 0 }).suppressed;
+                self.note(reporter, value, suppressed);
+                true
+            }
+            None => {
+                self.windows.insert(value, Window { start: now, suppressed: 0 });
+                true
+            }
+        }
+    }
+
+    /// Drops closed windows, so keys seen once don't accumulate.
+    fn prune(&mut self, now: DateTime<FixedOffset>, reporter: &Reporter) {
+        let window = self.spec.window;
+        let mut closed: Vec<(String, usize)> = Vec::new();
+        self.windows.retain(|value, w| {
+            let open = now < w.start + window;
+            if !open {
+                closed.push((value.clone(), w.suppressed));
+            }
+            open
+        });
+        closed.sort();
+        for (value, suppressed) in closed {
+            self.note(reporter, value, suppressed);
+        }
+        self.next_prune = Some(now + window);
+    }
+
+    /// Reports what is still suppressed when the input ends.
+    pub fn finish(mut self, reporter: &Reporter) {
+        let mut open: Vec<(String, usize)> = self.windows.drain().map(|(value, w)| (value, w.suppressed)).collect();
+        open.sort();
+        for (value, suppressed) in open {
+            self.note(reporter, value, suppressed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::ErrorFormat;
+
+    fn entry(ip: &str, path: &str, time: &str) -> Entry {
+        Entry {
+            client: Some(ip.parse().unwrap()),
+            path: Some(path.to_string()),
+            timestamp: Some(DateTime::parse_from_rfc3339(time).unwrap()),
+            ..Entry::default()
+        }
+    }
+
+    #[test]
+    fn parses_specs() {
+        let spec: ThrottleSpec = "ip+path:1.5s".parse().unwrap();
+        assert_eq!(spec.key, ThrottleKey::IpPath);
+        assert_eq!(spec.window, TimeDelta::milliseconds(1500));
+        assert!("ip".parse::<ThrottleSpec>().is_err());
+        assert!("host:60s".parse::<ThrottleSpec>().is_err());
+        assert!("ip:soon".parse::<ThrottleSpec>().is_err());
+    }
+
+    #[test]
+    fn first_match_per_window() {
+        let reporter = Reporter::new(ErrorFormat::Human);
+        let mut throttle = Throttle::new("ip:60s".parse().unwrap());
+        let admitted: Vec<bool> = [
+            entry("192.0.2.1", "/a", "2023-02-12T14:00:00Z"),
+            entry("192.0.2.1", "/b", "2023-02-12T14:00:30Z"),
+            entry("192.0.2.2", "/a", "2023-02-12T14:00:31Z"),
+            entry("192.0.2.1", "/a", "2023-02-12T14:01:00Z"),
+            entry("192.0.2.1", "/a", "2023-02-12T14:01:59Z"),
+            Entry { client: Some("192.0.2.1".parse().unwrap()), ..Entry::default() },
+        ]
+        .iter()
+        .map(|e| throttle.admit(e, &reporter))
+        .collect();
+        assert_eq!(admitted, vec![true, false, true, true, false, true]);
+    }
+
+    #[test]
+    fn prunes_closed_windows() {
+        let reporter = Reporter::new(ErrorFormat::Human);
+        let mut throttle = Throttle::new("path:10s".parse().unwrap());
+        for (i, path) in ["/a", "/b", "/c"].iter().enumerate() {
+            throttle.admit(&entry("192.0.2.1", path, &format!("2023-02-12T14:00:0{}Z", i)), &reporter);
+        }
+        assert_eq!(throttle.windows.len(), 3);
+        throttle.admit(&entry("192.0.2.1", "/d", "2023-02-12T14:00:20Z"), &reporter);
+        assert_eq!(throttle.windows.len(), 1);
+    }
+}
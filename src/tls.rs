@@ -0,0 +1,80 @@
+use serde::{Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+/// TLS protocol version a request was made with, ordered from oldest to newest so
+/// `--tls-protocol lt TLSv1.2` finds clients on deprecated versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    Ssl3,
+    Tls1,
+    Tls1_1,
+    Tls1_2,
+    Tls1_3,
+}
+
+impl TlsVersion {
+    /// The version of a logged protocol. `-`, for a request that wasn't made over TLS, and
+    /// names this doesn't know are `None`; the parsers keep the raw value as well.
+    pub fn parse_logged(value: &str) -> Option<TlsVersion> {
+        value.parse().ok()
+    }
+}
+
+impl FromStr for TlsVersion {
+    type Err = String;
+
+    /// Accepts the names OpenSSL logs, `TLSv1` to `TLSv1.3` and `SSLv3`, in any case, with
+    /// `TLSv1.0` for `TLSv1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sslv3" => Ok(TlsVersion::Ssl3),
+            "tlsv1" | "tlsv1.0" => Ok(TlsVersion::Tls1),
+            "tlsv1.1" => Ok(TlsVersion::Tls1_1),
+            "tlsv1.2" => Ok(TlsVersion::Tls1_2),
+            "tlsv1.3" => Ok(TlsVersion::Tls1_3),
+            _ => Err(format!("Invalid TLS protocol: {}, expected one of SSLv3, TLSv1, TLSv1.1, TLSv1.2, TLSv1.3", s)),
+        }
+    }
+}
+
+impl fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TlsVersion::Ssl3 => "SSLv3",
+            TlsVersion::Tls1 => "TLSv1",
+            TlsVersion::Tls1_1 => "TLSv1.1",
+            TlsVersion::Tls1_2 => "TLSv1.2",
+            TlsVersion::Tls1_3 => "TLSv1.3",
+        })
+    }
+}
+
+impl Serialize for TlsVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_parse_as_logged_and_print_back() {
+        for name in ["SSLv3", "TLSv1", "TLSv1.1", "TLSv1.2", "TLSv1.3"] {
+            assert_eq!(name.parse::<TlsVersion>().unwrap().to_string(), name);
+        }
+        assert_eq!("tlsv1.0".parse(), Ok(TlsVersion::Tls1));
+        assert_eq!(TlsVersion::parse_logged("-"), None);
+        assert_eq!(TlsVersion::parse_logged("TLSv1.2"), Some(TlsVersion::Tls1_2));
+        assert!("TLSv2".parse::<TlsVersion>().is_err());
+    }
+
+    #[test]
+    fn versions_order_oldest_first() {
+        assert!(TlsVersion::Ssl3 < TlsVersion::Tls1);
+        assert!(TlsVersion::Tls1 < TlsVersion::Tls1_1);
+        assert!(TlsVersion::Tls1_1 < TlsVersion::Tls1_2);
+        assert!(TlsVersion::Tls1_2 < TlsVersion::Tls1_3);
+    }
+}
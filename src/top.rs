@@ -0,0 +1,319 @@
+use crate::budget::Account;
+use crate::entry::Entry;
+use crate::group::{self, GroupBy, Groups};
+use crate::novel::Window;
+use crate::order::Order;
+use crate::sample::Rng;
+use crate::output::{
+    html::Page,
+    schema::{self, ShareChange},
+    table::Table,
+    Delimiter, DelimiterEscape,
+};
+use crate::zone::Zone;
+use clap::ValueEnum;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+
+// `top` ranks the values of a key by their matches. Over an incident window that mostly shows
+// what's always popular, so with a baseline, another log (--relative-file) or a window of log
+// time in the input (--baseline-since/--baseline-until), it ranks them instead by how much
+// their share of the matches grew. Each side is counted as `stats --by` counts groups, with
+// --approximate too, and the two are joined by value at the end. An approximate baseline only
+// knows its most frequent values, so the rest count as not in it. With --examples the target
+// keeps a reservoir sample of each value's lines, as `stats --by` does.
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum TopOutput {
+    #[default]
+    Table,
+    /// Comma-separated columns
+    Csv,
+    /// One object per value
+    Json,
+    /// A self-contained HTML page with the counts drawn as bars over the table
+    Html,
+}
+
+pub struct Top {
+    key: GroupBy,
+    /// A baseline window in the input itself, the rest of which is the target.
+    window: Option<Window>,
+    target: Groups,
+    baseline: Option<Groups>,
+}
+
+/// A value's matches and share of them, in the target and the baseline.
+pub struct Ranked {
+    value: String,
+    count: usize,
+    /// With --approximate, how much more `count` may be than the true count.
+    error: Option<usize>,
+    share: f64,
+    change: Option<ShareChange>,
+    /// With --examples, lines of the target picked at random.
+    examples: Option<Vec<String>>,
+}
+
+/// `part` of `total` in percent.
+fn share(part: usize, total: usize) -> f64 {
+    match total {
+        0 => 0.0,
+        _ => part as f64 * 100.0 / total as f64,
+    }
+}
+
+impl Top {
+    /// With `baseline`, the baseline is counted from `window` of the input or, without one,
+    /// with `add_baseline`. With `sketch`, each side keeps that many counters of its most
+    /// frequent values. Fails when the window ends before it starts.
+    pub fn new(
+        key: GroupBy,
+        tz: Option<Zone>,
+        max_groups: usize,
+        sketch: Option<usize>,
+        baseline: bool,
+        window: Option<Window>,
+    ) -> Result<Self, String> {
+        if window.is_some_and(|window| window.until <= window.since) {
+            return Err("--baseline-until must be later than --baseline-since".to_string());
+        }
+        let groups = || {
+            let groups = Groups::new(vec![key], tz, max_groups);
+            match sketch {
+                Some(capacity) => groups.approximate(capacity),
+                None => groups,
+            }
+        };
+        Ok(Top { key, window, target: groups(), baseline: (baseline || window.is_some()).then(groups) })
+    }
+
+    /// Charges the values counted, in the target and the baseline alike, to `memory`.
+    pub fn budgeted(self, memory: Account) -> Self {
+        Top {
+            target: self.target.budgeted(memory.clone()),
+            baseline: self.baseline.map(|baseline| baseline.budgeted(memory)),
+            ..self
+        }
+    }
+
+    /// Keeps up to `count` lines of each value in the target, picked by `rng`.
+    pub fn with_examples(self, count: usize, rng: Rng) -> Self {
+        Top { target: self.target.with_examples(count, rng), ..self }
+    }
+
+    /// Counts an entry of the input, read from `line`: towards the baseline when it's in the
+    /// baseline window, and otherwise the target. With a window, entries without a timestamp
+    /// count for neither.
+    pub fn add(&mut self, entry: &Entry, line: &str) -> Result<(), String> {
+        match (self.window, &mut self.baseline) {
+            (Some(window), Some(baseline)) if window.contains(entry) => baseline.add(entry, "", None),
+            (Some(_), _) if entry.timestamp.is_none() => Ok(()),
+            _ => self.target.add(entry, line, None),
+        }
+    }
+
+    /// Counts an entry of the --relative-file baseline.
+    pub fn add_baseline(&mut self, entry: &Entry) -> Result<(), String> {
+        match &mut self.baseline {
+            Some(baseline) => baseline.add(entry, "", None),
+            None => Ok(()),
+        }
+    }
+
+    /// The `limit` values with the most matches or, with a baseline, whose share grew the most,
+    /// in `order`. Values with fewer than `min_baseline` baseline matches are new, compared with
+    /// no ratio.
+    pub fn rank(&self, limit: usize, min_baseline: usize, order: Order) -> Result<Vec<Ranked>, String> {
+        let total = self.target.total();
+        let baseline: Option<HashMap<&str, usize>> =
+            self.baseline.as_ref().map(|baseline| baseline.counts().map(|(key, count, _)| (key[0].as_str(), count)).collect());
+        let baseline_total = self.baseline.as_ref().map_or(0, Groups::total);
+        if baseline.is_some() && baseline_total == 0 {
+            return Err("the baseline has no matches to compare with".to_string());
+        }
+        let mut ranked: Vec<Ranked> = self
+            .target
+            .counts()
+            .map(|(key, count, error)| {
+                let share = share(count, total);
+                let change = baseline.as_ref().map(|counts| {
+                    let baseline_count = counts.get(key[0].as_str()).copied().unwrap_or(0);
+                    let baseline_share = self::share(baseline_count, baseline_total);
+                    let new = baseline_count < min_baseline.max(1);
+                    ShareChange {
+                        baseline_count,
+                        baseline_share,
+                        delta: share - baseline_share,
+                        ratio: (!new).then(|| share / baseline_share),
+                        new,
+                    }
+                });
+                let error = self.target.approximated().then_some(error);
+                Ranked { value: key[0].clone(), count, error, share, change, examples: self.target.examples(key) }
+            })
+            .collect();
+        ranked.sort_by(|a, b| {
+            let order = match (&a.change, &b.change) {
+                (Some(a), Some(b)) => b.delta.total_cmp(&a.delta),
+                _ => b.count.cmp(&a.count),
+            };
+            order.then_with(|| a.value.cmp(&b.value))
+        });
+        ranked.truncate(limit);
+        order.sort(&mut ranked, |ranked| {
+            (ranked.change.as_ref().map_or(ranked.count as f64, |change| change.delta), ranked.value.as_str())
+        });
+        Ok(ranked)
+    }
+
+    /// With --approximate, the most matches of a value it lost, when that's as many as the
+    /// fewest some `ranked` value may have, so the ranking may not be the true one.
+    pub fn uncertain(&self, ranked: &[Ranked]) -> Option<usize> {
+        let bound = self.target.bound();
+        let fewest = ranked.iter().map(|ranked| ranked.count - ranked.error.unwrap_or(0)).min()?;
+        (self.target.approximated() && fewest <= bound).then_some(bound)
+    }
+
+    /// A table, CSV, an HTML page or one JSON object per value, in rank order, with each value's
+    /// example lines indented under its row or in its object.
+    pub fn render(&self, ranked: Vec<Ranked>, output: TopOutput) -> String {
+        let name = group::names(&[self.key]).remove(0);
+        if output == TopOutput::Json {
+            let mut out = String::new();
+            for ranked in ranked {
+                let group = BTreeMap::from([(name.clone(), ranked.value)]);
+                let top = schema::Top {
+                    group,
+                    count: ranked.count,
+                    error: ranked.error,
+                    share: ranked.share,
+                    change: ranked.change,
+                    examples: ranked.examples,
+                };
+                out += &format!("{}\n", json!(top));
+            }
+            return out;
+        }
+        let mut header = vec![name.as_str(), "count"];
+        if self.target.approximated() {
+            header.push("error");
+        }
+        header.push("share");
+        if self.baseline.is_some() {
+            header.extend(["baseline_count", "baseline_share", "delta", "ratio"]);
+        }
+        let mut table = Table::new(&header);
+        let bars: Vec<(String, u64)> = ranked.iter().map(|ranked| (ranked.value.clone(), ranked.count as u64)).collect();
+        for ranked in ranked {
+            let mut row = vec![ranked.value, ranked.count.to_string()];
+            row.extend(ranked.error.map(|error| error.to_string()));
+            row.push(format!("{:.2}", ranked.share));
+            if let Some(change) = ranked.change {
+                row.extend([
+                    change.baseline_count.to_string(),
+                    format!("{:.2}", change.baseline_share),
+                    format!("{:+.2}", change.delta),
+                    change.ratio.map_or_else(|| "new".to_string(), |ratio| format!("{:.2}", ratio)),
+                ]);
+            }
+            table.row(row);
+            for example in ranked.examples.unwrap_or_default() {
+                table.note(format!("  {}", example));
+            }
+        }
+        match output {
+            TopOutput::Csv => table.delimited(&Delimiter::new(",".to_string(), DelimiterEscape::Quote)),
+            TopOutput::Html => {
+                let mut page = Page::default();
+                page.bars(&bars);
+                page.table(&table);
+                page.render(&format!("Top {} values", name))
+            }
+            _ => table.render(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(path: &str, time: &str) -> Entry {
+        Entry {
+            path: Some(path.to_string()),
+            timestamp: Some(chrono::DateTime::parse_from_rfc3339(time).unwrap()),
+            ..Entry::default()
+        }
+    }
+
+    /// `counts` requests per path, all at `time`.
+    fn requests(counts: &[(&str, usize)], time: &str) -> Vec<Entry> {
+        counts.iter().flat_map(|&(path, count)| (0..count).map(move |_| request(path, time))).collect()
+    }
+
+    fn ranking(top: &Top, min_baseline: usize) -> Vec<(String, f64, Option<f64>)> {
+        let ranked = top.rank(10, min_baseline, Order::default()).unwrap();
+        ranked.into_iter().map(|r| (r.value, r.change.as_ref().map_or(0.0, |c| c.delta), r.change.and_then(|c| c.ratio))).collect()
+    }
+
+    #[test]
+    fn without_a_baseline_values_rank_by_count() {
+        let mut top = Top::new(GroupBy::Path, None, 100, None, false, None).unwrap();
+        for entry in requests(&[("/b", 2), ("/a", 3), ("/c", 2)], "2023-02-12T14:00:00Z") {
+            top.add(&entry, "").unwrap();
+        }
+        let ranked = top.rank(2, 10, Order::default()).unwrap();
+        assert_eq!(ranked.iter().map(|r| (r.value.as_str(), r.count)).collect::<Vec<_>>(), [("/a", 3), ("/b", 2)]);
+        assert!((ranked[0].share - 3.0 * 100.0 / 7.0).abs() < 1e-9);
+        // The order applies to the values picked, not to which are.
+        let by_key = Order { sort: crate::order::SortBy::Key, reverse: true };
+        let ranked = top.rank(2, 10, by_key).unwrap();
+        assert_eq!(ranked.iter().map(|r| r.value.as_str()).collect::<Vec<_>>(), ["/b", "/a"]);
+    }
+
+    #[test]
+    fn values_rank_by_how_much_their_share_grew() {
+        // Always-popular /home keeps its half of the traffic, /login goes from 10% to 30%
+        // while /api falls from 40% to 20%, and /new wasn't there before.
+        let mut top = Top::new(GroupBy::Path, None, 100, None, true, None).unwrap();
+        for entry in requests(&[("/home", 500), ("/api", 400), ("/login", 100)], "2023-02-05T14:00:00Z") {
+            top.add_baseline(&entry).unwrap();
+        }
+        for entry in requests(&[("/home", 500), ("/api", 190), ("/login", 300), ("/new", 10)], "2023-02-12T14:00:00Z") {
+            top.add(&entry, "").unwrap();
+        }
+        let ranking = ranking(&top, 10);
+        let order: Vec<&str> = ranking.iter().map(|(value, ..)| value.as_str()).collect();
+        assert_eq!(order, ["/login", "/new", "/home", "/api"]);
+        assert!((ranking[0].1 - 20.0).abs() < 1e-9);
+        assert!((ranking[0].2.unwrap() - 3.0).abs() < 1e-9);
+        assert_eq!(ranking[1].2, None);
+        assert!((ranking[3].1 + 21.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tiny_baselines_are_new_rather_than_divided_by() {
+        let window = Window {
+            since: chrono::DateTime::parse_from_rfc3339("2023-02-12T13:00:00Z").unwrap(),
+            until: chrono::DateTime::parse_from_rfc3339("2023-02-12T14:00:00Z").unwrap(),
+        };
+        let mut top = Top::new(GroupBy::Path, None, 100, None, false, Some(window)).unwrap();
+        let baseline = requests(&[("/a", 20), ("/b", 2)], "2023-02-12T13:30:00Z");
+        let target = requests(&[("/a", 10), ("/b", 10)], "2023-02-12T14:30:00Z");
+        for entry in baseline.iter().chain(&target).chain([&Entry::default()]) {
+            top.add(entry, "").unwrap();
+        }
+        let ranked = top.rank(10, 10, Order::default()).unwrap();
+        let b = ranked.iter().find(|r| r.value == "/b").and_then(|r| r.change.as_ref()).unwrap();
+        assert_eq!((b.baseline_count, b.new, b.ratio), (2, true, None));
+        let a = ranked.iter().find(|r| r.value == "/a").and_then(|r| r.change.as_ref()).unwrap();
+        assert_eq!(a.baseline_count, 20);
+        assert!(a.ratio.is_some());
+
+        let mut empty = Top::new(GroupBy::Path, None, 100, None, true, None).unwrap();
+        empty.add(&request("/a", "2023-02-12T14:30:00Z"), "").unwrap();
+        assert!(empty.rank(10, 10, Order::default()).is_err());
+        assert!(Top::new(GroupBy::Path, None, 100, None, false, Some(Window { since: window.until, until: window.since })).is_err());
+    }
+}
@@ -0,0 +1,372 @@
+use crate::stats;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Weekday};
+use std::path::{Component, Path, PathBuf};
+
+// --tz: UTC, an offset such as `+02:00`, or a zone such as `Europe/Berlin`, whose offset
+// changes when daylight saving time begins and ends, so that a day in a per-day breakdown runs
+// from local midnight to local midnight, 23 or 25 hours on the days the clocks change. Zones
+// are read from the system's time zone database, under $TZDIR or /usr/share/zoneinfo: the
+// transitions a TZif file lists, and for times past the last of them the POSIX TZ rule at its
+// end. Such a rule can be given instead of a name, e.g. `CET-1CEST,M3.5.0,M10.5.0/3` for
+// central Europe: standard time an hour east of UTC (POSIX offsets count west), summer time
+// from 02:00 on the last Sunday of March to 03:00 on the last Sunday of October.
+
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+
+/// A time zone for the rest of the run; its rules are read once and never freed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Zone(&'static Rules);
+
+#[derive(Debug, PartialEq)]
+struct Rules {
+    /// When each offset starts to apply, in seconds since the epoch, and the offset in seconds
+    /// east of UTC.
+    transitions: Vec<(i64, i32)>,
+    /// The offset before the first transition.
+    initial: i32,
+    /// The rule after the last transition.
+    rule: Option<Posix>,
+}
+
+static UTC: Rules = Rules { transitions: Vec::new(), initial: 0, rule: None };
+
+/// A POSIX TZ rule, its offsets in seconds east of UTC.
+#[derive(Debug, PartialEq)]
+struct Posix {
+    standard: i32,
+    /// The daylight saving offset and when it starts and ends, in the local time before each.
+    daylight: Option<(i32, Change, Change)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Change {
+    day: RuleDay,
+    /// Seconds after local midnight.
+    time: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RuleDay {
+    /// `Jn`: day 1 to 365, February 29 never counted.
+    Julian(u16),
+    /// `n`: day 0 to 365, February 29 counted in leap years.
+    Ordinal(u16),
+    /// `Mm.w.d`: weekday `d`, 0 for Sunday, of week `w` of month `m`, 5 for the last.
+    Weekday { month: u32, week: u8, weekday: Weekday },
+}
+
+impl Zone {
+    pub fn utc() -> Zone {
+        Zone(&UTC)
+    }
+
+    pub fn fixed(offset: FixedOffset) -> Zone {
+        Zone(Box::leak(Box::new(Rules { transitions: Vec::new(), initial: offset.local_minus_utc(), rule: None })))
+    }
+
+    /// The offset in effect `seconds` after the epoch.
+    pub fn offset(self, seconds: i64) -> FixedOffset {
+        let rules = self.0;
+        let at = rules.transitions.partition_point(|&(start, _)| start <= seconds);
+        let east = match (at, &rules.rule) {
+            (at, Some(rule)) if at == rules.transitions.len() => rule.offset(seconds),
+            (0, _) => rules.initial,
+            (at, _) => rules.transitions[at - 1].1,
+        };
+        FixedOffset::east_opt(east).unwrap_or(FixedOffset::east_opt(0).expect("UTC is a valid offset"))
+    }
+
+    /// `timestamp` in this zone's local time.
+    pub fn local(self, timestamp: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        timestamp.with_timezone(&self.offset(timestamp.timestamp()))
+    }
+
+    /// The time `seconds` after the epoch in this zone's local time.
+    pub fn at(self, seconds: i64) -> DateTime<FixedOffset> {
+        let utc = DateTime::from_timestamp(seconds, 0).unwrap_or_default();
+        utc.with_timezone(&self.offset(seconds))
+    }
+}
+
+/// Parses --tz: UTC, an offset, a zone name or a POSIX TZ rule.
+pub fn parse(s: &str) -> Result<Zone, String> {
+    if let Ok(offset) = stats::parse_tz(s) {
+        return Ok(Zone::fixed(offset));
+    }
+    let unknown = || format!("unknown timezone {:?}, expected UTC, an offset like +02:00 or a zone like Europe/Berlin", s);
+    let rules = match is_name(s) {
+        true => read(s)?,
+        false => None,
+    };
+    let rules =
+        rules.or_else(|| posix(s).map(|rule| Rules { transitions: Vec::new(), initial: rule.standard, rule: Some(rule) }));
+    Ok(Zone(Box::leak(Box::new(rules.ok_or_else(unknown)?))))
+}
+
+/// Whether `s` could name a file in the time zone database, e.g. `America/Argentina/Salta`.
+fn is_name(s: &str) -> bool {
+    let path = Path::new(s);
+    s.bytes().all(|b| b.is_ascii_alphanumeric() || b"/_-+".contains(&b))
+        && path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// The zone named `name` in the database, `None` if there's none by that name.
+fn read(name: &str) -> Result<Option<Rules>, String> {
+    let dir = std::env::var_os("TZDIR").filter(|dir| !dir.is_empty()).map_or_else(|| PathBuf::from(ZONEINFO), PathBuf::from);
+    let path = dir.join(name);
+    let Ok(bytes) = std::fs::read(&path) else {
+        return Ok(None);
+    };
+    tzif(&bytes).map(Some).ok_or_else(|| format!("{} isn't a TZif file", path.display()))
+}
+
+/// Reads a TZif file, RFC 8536: its 64-bit data and rule if it's version 2 or later, else the
+/// 32-bit data.
+fn tzif(bytes: &[u8]) -> Option<Rules> {
+    if bytes.get(..4)? != b"TZif" {
+        return None;
+    }
+    let (v1_length, _) = block_length(bytes, 0, 4)?;
+    if *bytes.get(4)? < b'2' {
+        return block(bytes, 0, 4).map(|(rules, _)| rules);
+    }
+    let (rules, end) = block(bytes, 44 + v1_length, 8)?;
+    let footer = bytes.get(end..)?.strip_prefix(b"\n")?;
+    let footer = std::str::from_utf8(&footer[..footer.iter().position(|&b| b == b'\n')?]).ok()?;
+    let rule = match footer {
+        "" => None,
+        footer => Some(posix(footer)?),
+    };
+    Some(Rules { rule, ..rules })
+}
+
+/// The length of the data after the header at `at`, given the size of its times, and the
+/// header's counts: isutcnt, isstdcnt, leapcnt, timecnt, typecnt and charcnt.
+fn block_length(bytes: &[u8], at: usize, time_size: usize) -> Option<(usize, [usize; 6])> {
+    let mut counts = [0; 6];
+    for (i, count) in counts.iter_mut().enumerate() {
+        let field = bytes.get(at + 20 + i * 4..at + 24 + i * 4)?;
+        *count = u32::from_be_bytes(field.try_into().ok()?) as usize;
+    }
+    let [isut, isstd, leap, time, types, chars] = counts;
+    Some((time * time_size + time + types * 6 + chars + leap * (time_size + 4) + isstd + isut, counts))
+}
+
+/// The transitions and initial offset of the header and data at `at`, and where they end.
+fn block(bytes: &[u8], at: usize, time_size: usize) -> Option<(Rules, usize)> {
+    let (length, [.., time_count, type_count, _]) = block_length(bytes, at, time_size)?;
+    let data = bytes.get(at + 44..at + 44 + length)?;
+    let time = |i: usize| -> Option<i64> {
+        let field = data.get(i * time_size..(i + 1) * time_size)?;
+        Some(match time_size {
+            4 => i32::from_be_bytes(field.try_into().ok()?) as i64,
+            _ => i64::from_be_bytes(field.try_into().ok()?),
+        })
+    };
+    let indices = &data[time_count * time_size..time_count * (time_size + 1)];
+    let types = &data[time_count * (time_size + 1)..time_count * (time_size + 1) + type_count * 6];
+    let offset = |index: usize| -> Option<i32> {
+        let field = types.get(index * 6..index * 6 + 4)?;
+        Some(i32::from_be_bytes(field.try_into().ok()?))
+    };
+    let transitions =
+        (0..time_count).map(|i| Some((time(i)?, offset(*indices.get(i)? as usize)?))).collect::<Option<Vec<_>>>()?;
+    Some((Rules { transitions, initial: offset(0)?, rule: None }, at + 44 + length))
+}
+
+impl Posix {
+    fn offset(&self, seconds: i64) -> i32 {
+        let Some((daylight, start, end)) = self.daylight else {
+            return self.standard;
+        };
+        let Some(year) = DateTime::from_timestamp(seconds + self.standard as i64, 0).map(|t| t.year()) else {
+            return self.standard;
+        };
+        let (Some(starts), Some(ends)) = (start.instant(year, self.standard), end.instant(year, daylight)) else {
+            return self.standard;
+        };
+        // South of the equator daylight saving time spans the new year.
+        let in_daylight = match starts < ends {
+            true => starts <= seconds && seconds < ends,
+            false => !(ends <= seconds && seconds < starts),
+        };
+        match in_daylight {
+            true => daylight,
+            false => self.standard,
+        }
+    }
+}
+
+impl Change {
+    /// When the change happens in `year`, in seconds since the epoch, given the offset before it.
+    fn instant(self, year: i32, offset: i32) -> Option<i64> {
+        let date = match self.day {
+            RuleDay::Julian(day) => {
+                let leap = NaiveDate::from_ymd_opt(year, 2, 29).is_some();
+                NaiveDate::from_yo_opt(year, day as u32 + (leap && day >= 60) as u32)?
+            }
+            RuleDay::Ordinal(day) => NaiveDate::from_yo_opt(year, day as u32 + 1)?,
+            RuleDay::Weekday { month, week: 5, weekday } => NaiveDate::from_weekday_of_month_opt(year, month, weekday, 5)
+                .or_else(|| NaiveDate::from_weekday_of_month_opt(year, month, weekday, 4))?,
+            RuleDay::Weekday { month, week, weekday } => NaiveDate::from_weekday_of_month_opt(year, month, weekday, week)?,
+        };
+        let midnight = date.and_hms_opt(0, 0, 0)?.and_utc().timestamp();
+        Some(midnight + self.time as i64 - offset as i64)
+    }
+}
+
+/// Parses a POSIX TZ rule, e.g. `EST5EDT,M3.2.0,M11.1.0` or `<+0330>-3:30`.
+fn posix(s: &str) -> Option<Posix> {
+    let rest = zone_name(s)?;
+    let (west, rest) = duration(rest)?;
+    let standard = -west;
+    if rest.is_empty() {
+        return Some(Posix { standard, daylight: None });
+    }
+    let rest = zone_name(rest)?;
+    let (daylight, rest) = match rest.starts_with(',') || rest.is_empty() {
+        true => (standard + 3600, rest),
+        false => duration(rest).map(|(west, rest)| (-west, rest))?,
+    };
+    // Without a rule, the US one is meant.
+    let rules = match rest {
+        "" => ",M3.2.0,M11.1.0",
+        rest => rest,
+    };
+    let (start, end) = rules.strip_prefix(',')?.split_once(',')?;
+    Some(Posix { standard, daylight: Some((daylight, change(start)?, change(end)?)) })
+}
+
+/// What follows a zone abbreviation: three or more letters, or anything in `<>`.
+fn zone_name(s: &str) -> Option<&str> {
+    if let Some(quoted) = s.strip_prefix('<') {
+        return quoted.split_once('>').map(|(_, rest)| rest);
+    }
+    let length = s.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(s.len());
+    (length >= 3).then(|| &s[length..])
+}
+
+/// `[+-]hh[:mm[:ss]]` in seconds, and what follows it.
+fn duration(s: &str) -> Option<(i32, &str)> {
+    let (sign, s) = match s.as_bytes().first()? {
+        b'-' => (-1, &s[1..]),
+        b'+' => (1, &s[1..]),
+        _ => (1, s),
+    };
+    let length = s.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(s.len());
+    let mut seconds = 0;
+    for (i, part) in s[..length].split(':').enumerate() {
+        if i > 2 || part.is_empty() || part.len() > 3 {
+            return None;
+        }
+        seconds += part.parse::<i32>().ok()? * [3600, 60, 1][i];
+    }
+    Some((sign * seconds, &s[length..]))
+}
+
+/// `Jn`, `n` or `Mm.w.d`, with an optional `/time`.
+fn change(s: &str) -> Option<Change> {
+    let (day, time) = match s.split_once('/') {
+        Some((day, time)) => (day, duration(time).filter(|(_, rest)| rest.is_empty())?.0),
+        None => (s, 2 * 3600),
+    };
+    let day = if let Some(julian) = day.strip_prefix('J') {
+        RuleDay::Julian(julian.parse().ok().filter(|day| (1..=365).contains(day))?)
+    } else if let Some(rule) = day.strip_prefix('M') {
+        let mut parts = rule.split('.').map(|part| part.parse::<u32>().ok());
+        let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+        if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=5).contains(&week) || weekday > 6 {
+            return None;
+        }
+        let weekdays = [Weekday::Sun, Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat];
+        RuleDay::Weekday { month, week: week as u8, weekday: weekdays[weekday as usize] }
+    } else {
+        RuleDay::Ordinal(day.parse().ok().filter(|day| *day <= 365)?)
+    };
+    Some(Change { day, time })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(zone: Zone, utc: &str) -> String {
+        zone.local(DateTime::parse_from_rfc3339(utc).unwrap()).to_rfc3339()
+    }
+
+    #[test]
+    fn rules_change_the_offset_on_their_days() {
+        let berlin = parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        // 2024's changes: 01:00 UTC on March 31 and October 27.
+        assert_eq!(local(berlin, "2024-03-31T00:59:59Z"), "2024-03-31T01:59:59+01:00");
+        assert_eq!(local(berlin, "2024-03-31T01:00:00Z"), "2024-03-31T03:00:00+02:00");
+        assert_eq!(local(berlin, "2024-10-27T00:59:59Z"), "2024-10-27T02:59:59+02:00");
+        assert_eq!(local(berlin, "2024-10-27T01:00:00Z"), "2024-10-27T02:00:00+01:00");
+        assert_eq!(local(berlin, "2024-01-01T00:00:00Z"), "2024-01-01T01:00:00+01:00");
+
+        // Daylight saving time over the new year, and a default time of 02:00.
+        let sydney = parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(local(sydney, "2024-01-15T00:00:00Z"), "2024-01-15T11:00:00+11:00");
+        assert_eq!(local(sydney, "2024-07-15T00:00:00Z"), "2024-07-15T10:00:00+10:00");
+        assert_eq!(local(sydney, "2024-10-05T15:59:59Z"), "2024-10-06T01:59:59+10:00");
+        assert_eq!(local(sydney, "2024-10-05T16:00:00Z"), "2024-10-06T03:00:00+11:00");
+
+        let new_york = parse("EST5EDT").unwrap();
+        assert_eq!(local(new_york, "2024-03-10T06:59:59Z"), "2024-03-10T01:59:59-05:00");
+        assert_eq!(local(new_york, "2024-03-10T07:00:00Z"), "2024-03-10T03:00:00-04:00");
+        let tehran = parse("<+0330>-3:30").unwrap();
+        assert_eq!(local(tehran, "2024-06-01T00:00:00Z"), "2024-06-01T03:30:00+03:30");
+        assert_eq!(local(parse("+05:45").unwrap(), "2024-06-01T00:00:00Z"), "2024-06-01T05:45:00+05:45");
+    }
+
+    #[test]
+    fn rules_count_days_as_posix_does() {
+        let julian = Change { day: RuleDay::Julian(60), time: 0 };
+        let ordinal = Change { day: RuleDay::Ordinal(59), time: 0 };
+        let midnight = |month, day| NaiveDate::from_ymd_opt(2024, month, day)?.and_hms_opt(0, 0, 0).map(|t| t.and_utc().timestamp());
+        // In a leap year J60 is March 1 and 59 is February 29.
+        assert_eq!(julian.instant(2024, 0), midnight(3, 1));
+        assert_eq!(ordinal.instant(2024, 0), midnight(2, 29));
+        assert!(posix("CET-1CEST,M13.1.0,M10.5.0").is_none());
+        assert!(posix("C-1").is_none());
+        assert!(parse("Not/A_Zone").is_err());
+        assert!(parse("../../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn tzif_files_list_transitions_then_a_rule() {
+        // A version 2 file with one transition, to +01:00 at 2000-01-01, and Berlin's rule after.
+        let header = |version: u8, times: u32, types: u32, chars: u32| {
+            let mut header = b"TZif".to_vec();
+            header.push(version);
+            header.extend([0; 15]);
+            for count in [0, 0, 0, times, types, chars] {
+                header.extend(count.to_be_bytes());
+            }
+            header
+        };
+        let mut file = header(b'2', 0, 1, 4);
+        file.extend([0, 0, 0, 0, 0, 0]);
+        file.extend(b"UTC\0");
+        file.extend(header(b'2', 1, 2, 8));
+        file.extend(946_684_800_i64.to_be_bytes());
+        file.push(1);
+        file.extend([0, 0, 0, 0, 0, 0]);
+        file.extend(3600_i32.to_be_bytes());
+        file.extend([0, 4]);
+        file.extend(b"UTC\0CET\0");
+        file.extend(b"\nCET-1CEST,M3.5.0,M10.5.0/3\n");
+        let zone = Zone(Box::leak(Box::new(tzif(&file).unwrap())));
+        assert_eq!(local(zone, "1999-12-31T23:00:00Z"), "1999-12-31T23:00:00+00:00");
+        assert_eq!(local(zone, "2000-01-01T00:00:00Z"), "2000-01-01T01:00:00+01:00");
+        assert_eq!(local(zone, "2024-07-01T00:00:00Z"), "2024-07-01T02:00:00+02:00");
+        assert!(tzif(b"TZif2").is_none());
+
+        // The system's own, where there is one.
+        if Path::new(ZONEINFO).join("Europe/Berlin").is_file() {
+            let berlin = parse("Europe/Berlin").unwrap();
+            assert_eq!(local(berlin, "2024-10-27T01:00:00Z"), "2024-10-27T02:00:00+01:00");
+            assert_eq!(local(berlin, "2040-07-01T00:00:00Z"), "2040-07-01T02:00:00+02:00");
+        }
+    }
+}
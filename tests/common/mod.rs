@@ -0,0 +1,29 @@
+#![allow(dead_code)]
+
+use assert_cmd::{cargo::cargo_bin_cmd, Command};
+use std::path::PathBuf;
+
+pub fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+/// The binary with the named fixture as its input file.
+pub fn log_parser(file: &str) -> Command {
+    let mut cmd = cargo_bin_cmd!("cli-parser");
+    cmd.arg(fixture(file));
+    cmd
+}
+
+/// Lines of the named fixture, to compare against filter output.
+pub fn fixture_lines(file: &str, numbers: &[usize]) -> String {
+    let content = std::fs::read_to_string(fixture(file)).expect("fixture exists");
+    let lines: Vec<&str> = content.lines().collect();
+    numbers.iter().map(|n| format!("{}\n", lines[n - 1])).collect()
+}
+
+/// The binary reading its built-in demo sample instead of a file.
+pub fn demo() -> Command {
+    let mut cmd = cargo_bin_cmd!("cli-parser");
+    cmd.arg("demo");
+    cmd
+}
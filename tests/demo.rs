@@ -0,0 +1,108 @@
+mod common;
+
+use common::{demo, log_parser};
+use predicates::prelude::*;
+
+/// Asserts how many entries of the demo sample match a filter.
+fn assert_count(args: &[&str], count: usize) {
+    demo()
+        .arg("filter")
+        .args(args)
+        .args(["--count-by", "file"])
+        .assert()
+        .success()
+        .stdout(format!("file\tmatched\ndemo.log\t{}\ntotal\t{}\n", count, count));
+}
+
+#[test]
+fn sample_covers_the_interesting_cases() {
+    assert_count(&["--all"], 305);
+    assert_count(&["-s", "eq", "101"], 4);
+    assert_count(&["-s", "eq", "503"], 8);
+    assert_count(&["-u", "contains", "bot"], 29);
+    assert_count(&["-u", "none"], 1);
+    assert_count(&["--referrer", "none"], 147);
+    assert_count(&["-i", "eq", "::/0"], 63);
+}
+
+#[test]
+fn sample_is_clean() {
+    demo()
+        .args(["--strict", "--check-order", "filter", "--all", "--count-by", "day"])
+        .assert()
+        .success()
+        .stdout("day\tmatched\n2024-03-10\t305\ntotal\t305\n")
+        .stderr("");
+}
+
+#[test]
+fn written_sample_matches_the_embedded_one() {
+    let dir = std::env::temp_dir().join(format!("log-parser-demo-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("sample.log");
+    demo().arg("--write").arg(&path).assert().success().stdout("");
+
+    let embedded = demo().args(["filter", "-s", "eq", "404", "-o", "json"]).assert().success().get_output().stdout.clone();
+    let from_file = assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+        .arg(&path)
+        .args(["filter", "-s", "eq", "404", "-o", "json"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    std::fs::remove_dir_all(&dir).unwrap();
+    // Everything but the file each match was read from.
+    let entries = |output: Vec<u8>| -> Vec<serde_json::Value> {
+        let output = String::from_utf8(output).unwrap();
+        output
+            .lines()
+            .map(|line| {
+                let mut entry: serde_json::Value = serde_json::from_str(line).unwrap();
+                entry.as_object_mut().unwrap().remove("source_file");
+                entry
+            })
+            .collect()
+    };
+    assert_eq!(entries(embedded), entries(from_file));
+}
+
+#[test]
+fn demo_and_files_are_exclusive() {
+    assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+        .args(["filter", "--all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no input files given; try the built-in sample with `log-parser demo filter --all`"));
+    log_parser("combined.log")
+        .args(["demo", "filter", "--all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("demo reads the built-in sample"));
+}
+
+#[test]
+fn write_does_not_take_a_command() {
+    demo()
+        .args(["--write", "unused.log", "filter", "--all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("demo --write only writes the sample"));
+    assert!(!std::path::Path::new("unused.log").exists());
+}
+
+#[test]
+fn scanners_flags_the_probing_clients() {
+    demo()
+        .args(["scanners", "--min-404", "20", "--min-distinct-paths", "5", "--window", "86400s", "-o", "json"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("\"ip\":\"193.105.7.171\",\"not_found\":23,\"distinct_paths\":5"))
+        .stdout(predicate::str::contains("\"ip\":\"185.220.101.4\",\"not_found\":26,\"distinct_paths\":5"));
+    demo()
+        .args(["scanners", "--min-404", "20", "--min-distinct-paths", "5", "--window", "86400s", "-i", "neq", "185.220.101.4"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("185.220.101.4").not());
+    demo().args(["scanners"]).assert().success().stdout("");
+}
@@ -0,0 +1,444 @@
+mod common;
+
+use common::{fixture, fixture_lines, log_parser};
+use predicates::prelude::*;
+use serde_json::{json, Value};
+
+fn stderr_json(cmd: &mut assert_cmd::Command) -> Vec<Value> {
+    let output = cmd.output().expect("binary runs");
+    String::from_utf8(output.stderr)
+        .expect("utf-8 stderr")
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("every stderr line is JSON"))
+        .collect()
+}
+
+#[test]
+fn fail_on_is_reported_as_a_notice() {
+    let stderr = stderr_json(log_parser("combined.log").args(["--errors", "json", "filter", "-s", "gte", "500", "--fail-on", "matches"]));
+    assert_eq!(
+        stderr.last(),
+        Some(&serde_json::json!({"type": "notice", "kind": "failed", "condition": "matches>0", "counter": "matches", "value": 1.0}))
+    );
+}
+
+#[test]
+fn malformed_lines_are_skipped_and_counted() {
+    log_parser("malformed.log")
+        .args(["filter", "--all"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("malformed.log", &[1, 5]))
+        .stderr("Skipped 2 malformed lines\n");
+}
+
+#[test]
+fn strict_aborts_on_the_first_malformed_line() {
+    log_parser("malformed.log")
+        .args(["--strict", "filter", "--all"])
+        .assert()
+        .failure()
+        .stdout(fixture_lines("malformed.log", &[1]))
+        .stderr(predicate::str::ends_with("malformed.log: line 2: Invalid value for Ip: this\n"));
+}
+
+#[test]
+fn json_warnings_and_summary() {
+    let file = fixture("malformed.log");
+    let diagnostics = stderr_json(log_parser("malformed.log").args(["--errors", "json", "filter", "--all"]));
+    assert_eq!(
+        diagnostics,
+        vec![
+            json!({"type": "warning", "kind": "malformed_line", "file": file, "line": 2, "reason": "Invalid value for Ip: this"}),
+            json!({"type": "warning", "kind": "malformed_line", "file": file, "line": 4, "reason": "Invalid value for TimeLocal: not a timestamp"}),
+            json!({
+                "type": "summary", "file": file, "lines": 5, "bytes": 344, "matched": 2, "malformed": 2, "malformed_requests": 0,
+                "regressions": 0,
+                "invalid_numbers": 0,
+            }),
+        ]
+    );
+}
+
+#[test]
+fn json_sequence_over_several_defective_files() {
+    // Each file's warnings come before its summary, in input order, and the missing file ends the run.
+    let (malformed, bad_bytes, missing) = (fixture("malformed.log"), fixture("bad-bytes.log"), fixture("missing.log"));
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("cli-parser");
+    cmd.args([&malformed, &bad_bytes, &missing]).args(["--errors", "json", "filter", "-s", "eq", "200"]);
+    let summary = |file: &std::path::PathBuf, lines: u64, bytes: u64, matched: u64, malformed: u64, invalid_numbers: u64| {
+        json!({
+            "type": "summary", "file": file, "lines": lines, "bytes": bytes, "matched": matched, "malformed": malformed,
+            "malformed_requests": 0, "regressions": 0, "invalid_numbers": invalid_numbers,
+        })
+    };
+    assert_eq!(
+        stderr_json(&mut cmd),
+        vec![
+            json!({"type": "warning", "kind": "malformed_line", "file": malformed, "line": 2, "reason": "Invalid value for Ip: this"}),
+            json!({"type": "warning", "kind": "malformed_line", "file": malformed, "line": 4, "reason": "Invalid value for TimeLocal: not a timestamp"}),
+            summary(&malformed, 5, 344, 1, 2, 0),
+            summary(&bad_bytes, 4, 360, 4, 0, 3),
+            json!({"type": "error", "kind": "io", "file": missing, "message": "No such file or directory (os error 2)"}),
+        ]
+    );
+    cmd.assert().code(2).stdout(predicate::str::contains(fixture_lines("malformed.log", &[1])));
+}
+
+#[test]
+fn json_strict_error() {
+    let diagnostics = stderr_json(log_parser("malformed.log").args(["--errors", "json", "--strict", "filter", "--all"]));
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["type"], "error");
+    assert_eq!(diagnostics[0]["kind"], "line");
+    assert_eq!(diagnostics[0]["line"], 2);
+}
+
+#[test]
+fn json_filter_errors() {
+    let diagnostics = stderr_json(
+        log_parser("combined.log").args(["--errors", "json", "filter", "-u", "contain", "x", "-t", "gt", "yesterday"]),
+    );
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(
+        diagnostics[0],
+        json!({
+            "type": "error",
+            "kind": "filter",
+            "option": "--user-agent",
+            "operator": "contain",
+            "reason": "unknown_operator",
+            "suggestion": "contains",
+            "valid_operators": [
+                "eq", "contains", "starts_with", "ends_with", "in", "contains_any", "not_contains_any", "fuzzy", "fuzzy_eq",
+                "none", "some",
+            ],
+        })
+    );
+    assert_eq!(diagnostics[1]["option"], "--timestamp");
+    assert_eq!(diagnostics[1]["reason"], "invalid_value");
+    assert_eq!(diagnostics[1]["value"], "yesterday");
+}
+
+#[test]
+fn json_missing_file() {
+    let diagnostics = stderr_json(log_parser("missing.log").args(["--errors", "json", "filter", "--all"]));
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["kind"], "io");
+    assert_eq!(diagnostics[0]["file"], json!(fixture("missing.log")));
+}
+
+#[test]
+fn json_duration_warning() {
+    let diagnostics = stderr_json(log_parser("combined.log").args(["--errors", "json", "filter", "-d", "gt", "1s"]));
+    assert_eq!(diagnostics[0]["type"], "warning");
+    assert_eq!(diagnostics[0]["kind"], "no_duration");
+    assert_eq!(diagnostics[1]["type"], "summary");
+    assert_eq!(diagnostics[1]["matched"], 0);
+}
+
+#[test]
+fn summary_counts_rejections_by_first_failing_filter() {
+    log_parser("combined.log")
+        .args(["--summary", "filter", "-s", "eq", "404", "-u", "contains", "python", "-p", "eq", "/.env"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("combined.log", &[5]))
+        .stderr(predicate::str::starts_with(format!(
+            "{}: 8 lines, 1 match; rejected by: user_agent 6; path 1\n",
+            fixture("combined.log").display()
+        )));
+    let diagnostics = stderr_json(log_parser("combined.log").args([
+        "--summary", "--errors", "json", "filter", "--all", "--exclude", "status eq 200", "--exclude", "status eq 404",
+    ]));
+    assert_eq!(diagnostics[0]["rejected"], json!({"exclude #1": 3, "exclude #2": 2}));
+}
+
+#[test]
+fn oversized_lines_are_skipped_without_losing_their_neighbours() {
+    let dir = std::env::temp_dir().join(format!("log-parser-long-line-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("huge.log");
+    let first = fixture_lines("combined.log", &[1]);
+    let rest = fixture_lines("combined.log", &[2, 3]);
+    std::fs::write(&path, format!("{}{}\n{}", first, "x".repeat(10 << 20), rest)).unwrap();
+    let offset = first.len();
+
+    assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+        .arg(&path)
+        .args(["filter", "--all"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("combined.log", &[1, 2, 3]))
+        .stderr("Skipped 1 malformed lines\n");
+    let diagnostics = stderr_json(assert_cmd::cargo::cargo_bin_cmd!("cli-parser").arg(&path).args(["--errors", "json", "filter", "--all"]));
+    assert_eq!(diagnostics[0]["line"], 2);
+    assert_eq!(
+        diagnostics[0]["reason"],
+        format!("line at byte offset {} is 10485760 bytes, over --max-line-length 65536", offset)
+    );
+    assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+        .arg(&path)
+        .args(["--strict", "filter", "--all"])
+        .assert()
+        .failure()
+        .stdout(first)
+        .stderr(predicate::str::contains(format!("line 2: line at byte offset {} is 10485760 bytes", offset)));
+    assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+        .arg(&path)
+        .args(["--max-line-length", "11M", "filter", "--all", "--count"])
+        .assert()
+        .success()
+        .stdout("3 matches\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn files_taken_as_option_values_are_pointed_out() {
+    // The file also made a bad --user-agent filter, reported after it.
+    let file = fixture("combined.log");
+    let user_agent = "Error: --user-agent: unknown operator \"Googlebot\"\n  valid operators: eq, contains, starts_with, \
+                      ends_with, in, contains_any, not_contains_any, fuzzy, fuzzy_eq, none, some\n";
+    assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+        .args(["filter", "-u", "Googlebot"])
+        .arg(&file)
+        .assert()
+        .failure()
+        .stderr(format!(
+            "Error: no input files given; {} was taken as the value of an option, pass it before the command or with --input\n{}",
+            file.display(),
+            user_agent
+        ));
+    assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+        .args(["missing.log", "filter", "-u", "Googlebot"])
+        .arg(&file)
+        .assert()
+        .failure()
+        .stderr(format!(
+            "Error: missing.log: no such file\n  did you mean {}? It was taken as the value of an option; pass it with --input\n{}",
+            file.display(),
+            user_agent
+        ));
+}
+
+#[test]
+fn every_option_error_is_reported_before_any_input_is_read() {
+    let dir = std::env::temp_dir().join(format!("log-parser-validate-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = dir.join("out.log");
+    let stderr = assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+        .args(["missing.log", "filter", "--timestamp", "gt", "yesterday", "-s", "eq", "abc", "--format", "{path"])
+        .arg("--output-file")
+        .arg(&output)
+        .assert()
+        .failure()
+        .get_output()
+        .stderr
+        .clone();
+    let stderr = String::from_utf8(stderr).unwrap();
+    assert!(stderr.contains("--timestamp"), "{}", stderr);
+    assert!(stderr.contains("--status-code"), "{}", stderr);
+    assert!(stderr.contains("unclosed placeholder"), "{}", stderr);
+    assert!(!stderr.contains("missing.log"), "{}", stderr);
+    assert!(!output.exists());
+
+    log_parser("combined.log")
+        .args(["stats", "--by", "path", "--pivot"])
+        .args(["--where", "status >"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--pivot needs exactly two --by keys").and(predicate::str::contains("--where")));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn files_that_cant_be_written_are_reported_with_the_option_errors() {
+    let dir = std::env::temp_dir().join(format!("log-parser-sinks-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let unmatched = dir.join("missing/unmatched.log");
+    let malformed = dir.join("malformed.log");
+    std::fs::write(&malformed, "kept\n").unwrap();
+    log_parser("combined.log")
+        .args(["filter", "-s", "eq", "abc", "--unmatched-file"])
+        .arg(&unmatched)
+        .arg("--malformed-file")
+        .arg(&malformed)
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr(predicate::str::contains(format!("Error: {}: --unmatched-file can't be written: ", unmatched.display())))
+        .stderr(predicate::str::contains("--status-code: invalid value \"abc\""))
+        .stderr(predicate::str::contains("--malformed-file").not());
+    assert_eq!(std::fs::read_to_string(&malformed).unwrap(), "kept\n");
+
+    log_parser("combined.log")
+        .args(["stats", "--by", "status", "--snapshot-every", "1", "--snapshot-file"])
+        .arg(&dir)
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr(format!("Error: {}: --snapshot-file can't be written: it is a directory\n", dir.display()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_stops_reading_and_prints_the_summary() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::process::{Command, Stdio};
+
+    let dir = std::env::temp_dir().join(format!("log-parser-interrupt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let fifo = dir.join("input.log");
+    let _ = std::fs::remove_file(&fifo);
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cli-parser"))
+        .arg(&fifo)
+        .args(["filter", "--all", "--fields", "path"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let lines = fixture_lines("combined.log", &[1, 2, 3]);
+    let lines: Vec<&str> = lines.split_inclusive('\n').collect();
+    let mut input = std::fs::OpenOptions::new().write(true).open(&fifo).unwrap();
+    let mut output = BufReader::new(child.stdout.take().unwrap());
+    let mut printed = String::new();
+    for line in &lines[..2] {
+        input.write_all(line.as_bytes()).unwrap();
+        output.read_line(&mut printed).unwrap();
+    }
+    assert_eq!(printed, "/images/logo.png\n/api/login\n");
+
+    assert!(Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap().success());
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    // The pending read only returns once more input arrives; that line is no longer processed.
+    input.write_all(lines[2].as_bytes()).unwrap();
+    output.read_to_string(&mut printed).unwrap();
+    let status = child.wait().unwrap();
+    let mut stderr = String::new();
+    child.stderr.take().unwrap().read_to_string(&mut stderr).unwrap();
+    drop(input);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(printed, "/images/logo.png\n/api/login\n");
+    assert_eq!(status.code(), Some(130));
+    assert_eq!(stderr, format!("{}: 2 lines, 2 matches (interrupted)\n", fifo.display()));
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_marks_the_summary_when_the_pipe_ends_first() {
+    // As with `tail -f access.log | log-parser filter /dev/stdin`: Ctrl-C reaches the writer too,
+    // which exits and ends the input before another line arrives.
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cli-parser"))
+        .args(["filter", "--all", "--fields", "path", "/dev/stdin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut input = child.stdin.take().unwrap();
+    let mut output = BufReader::new(child.stdout.take().unwrap());
+    let mut printed = String::new();
+    input.write_all(fixture_lines("combined.log", &[1]).as_bytes()).unwrap();
+    output.read_line(&mut printed).unwrap();
+    assert_eq!(printed, "/images/logo.png\n");
+
+    assert!(Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap().success());
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    drop(input);
+    output.read_to_string(&mut printed).unwrap();
+    let status = child.wait().unwrap();
+    let mut stderr = String::new();
+    child.stderr.take().unwrap().read_to_string(&mut stderr).unwrap();
+
+    assert_eq!(printed, "/images/logo.png\n");
+    assert_eq!(status.code(), Some(130));
+    assert_eq!(stderr, "/dev/stdin: 1 lines, 1 match (interrupted)\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_closes_the_last_part_of_a_chunked_output_file() {
+    use std::io::{Read, Write};
+    use std::process::{Command, Stdio};
+
+    let dir = std::env::temp_dir().join(format!("log-parser-interrupt-chunks-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let fifo = dir.join("input.log");
+    let _ = std::fs::remove_file(&fifo);
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cli-parser"))
+        .arg(&fifo)
+        .args(["filter", "--all", "-o", "json-array", "--chunk-rows", "1", "--output-file"])
+        .arg(dir.join("out.json.gz"))
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let lines = fixture_lines("combined.log", &[1, 2, 3]);
+    let lines: Vec<&str> = lines.split_inclusive('\n').collect();
+    let mut input = std::fs::OpenOptions::new().write(true).open(&fifo).unwrap();
+    for line in &lines[..2] {
+        input.write_all(line.as_bytes()).unwrap();
+    }
+    // The second part is started once the second match is written.
+    let second = dir.join("out.00002.json.gz");
+    for _ in 0..100 {
+        if second.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    assert!(Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap().success());
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    input.write_all(lines[2].as_bytes()).unwrap();
+    let status = child.wait().unwrap();
+    let mut stderr = String::new();
+    child.stderr.take().unwrap().read_to_string(&mut stderr).unwrap();
+    drop(input);
+
+    let parts: Vec<serde_json::Value> = ["out.00001.json.gz", "out.00002.json.gz"]
+        .iter()
+        .map(|part| {
+            let mut text = String::new();
+            flate2::read::GzDecoder::new(std::fs::File::open(dir.join(part)).unwrap()).read_to_string(&mut text).unwrap();
+            serde_json::from_str(&text).unwrap()
+        })
+        .collect();
+    let third = dir.join("out.00003.json.gz").exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(status.code(), Some(130));
+    assert_eq!(parts.iter().map(|part| part.as_array().unwrap().len()).collect::<Vec<_>>(), [1, 1]);
+    assert_eq!(parts[1][0]["path"], "/api/login");
+    assert!(!third);
+    assert!(stderr.contains("Wrote 1 output line to "), "{}", stderr);
+}
+
+#[test]
+fn unwrapped_lines_count_wrapper_errors_apart() {
+    let file = fixture("cri.log");
+    let diagnostics = stderr_json(log_parser("cri.log").args(["--errors", "json", "--unwrap", "cri", "filter", "--all"]));
+    let reasons: Vec<(&Value, &str)> =
+        diagnostics[..2].iter().map(|d| (&d["line"], d["reason"].as_str().unwrap().split(':').next().unwrap())).collect();
+    assert_eq!(reasons, [(&json!(3), "Invalid value for Ip"), (&json!(6), "not a CRI record, `<time> <stream> <P|F> <log>`")]);
+    assert_eq!(
+        diagnostics[2],
+        json!({
+            "type": "summary", "file": file, "lines": 7, "bytes": 798, "matched": 3, "malformed": 2, "wrapper_errors": 1,
+            "malformed_requests": 0, "regressions": 0, "invalid_numbers": 0,
+        }),
+    );
+    log_parser("cri.log")
+        .args(["--unwrap", "cri", "--strict", "filter", "--all"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::starts_with("52.176.92.5 - - [12/Feb/2023:14:03:45 +0000]"))
+        .stderr(predicate::str::ends_with("cri.log: line 3: Invalid value for Ip: 2023/02/12\n"));
+}
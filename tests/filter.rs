@@ -0,0 +1,787 @@
+mod common;
+
+use common::{fixture, fixture_lines, log_parser};
+use predicates::prelude::*;
+
+/// Runs a filter over combined.log and asserts exactly the given fixture lines are printed, and
+/// that it exits with 1 when none are.
+fn assert_matches(args: &[&str], lines: &[usize]) {
+    log_parser("combined.log")
+        .arg("filter")
+        .args(args)
+        .assert()
+        .code(if lines.is_empty() { 1 } else { 0 })
+        .stdout(fixture_lines("combined.log", lines));
+}
+
+#[test]
+fn status_code_operators() {
+    assert_matches(&["--status-code", "eq", "404"], &[4, 5]);
+    assert_matches(&["-s", "neq", "200"], &[2, 3, 4, 5, 7]);
+    assert_matches(&["-s", "lt", "400"], &[1, 3, 6, 8]);
+}
+
+#[test]
+fn status_codes_can_be_given_by_name() {
+    assert_matches(&["--status-code", "eq", "not_found"], &[4, 5]);
+    assert_matches(&["-s", "gte", "internal_server_error"], &[7]);
+    assert_matches(&["-s", "lt", "bad_request"], &[1, 3, 6, 8]);
+    log_parser("combined.log")
+        .args(["filter", "-s", "gte", "too_many_requests", "--explain-filter"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("status_code  gte 429 (Too Many Requests)"));
+}
+
+#[test]
+fn user_agent_operators() {
+    assert_matches(&["--user-agent", "contains", "Chrome"], &[1, 2, 7]);
+    assert_matches(&["-u", "eq", "python-requests/2.28.1"], &[4, 5]);
+    assert_matches(&["-u", "starts_with", "python"], &[4, 5]);
+    assert_matches(&["-u", "ends_with", "Firefox/109.0"], &[3]);
+}
+
+#[test]
+fn none_and_some_match_missing_values() {
+    assert_matches(&["--user-agent", "none"], &[6]);
+    assert_matches(&["--user-agent", "some"], &[1, 2, 3, 4, 5, 7, 8]);
+    assert_matches(&["--referrer", "none"], &[3, 4, 5, 6]);
+    assert_matches(&["--referrer", "some"], &[1, 2, 7, 8]);
+}
+
+#[test]
+fn ip_operators() {
+    assert_matches(&["--ip", "eq", "193.105.7.171"], &[4, 5]);
+    assert_matches(&["-i", "neq", "52.176.92.5"], &[2, 3, 4, 5, 6, 8]);
+    assert_matches(&["-i", "eq", "52.176.0.0/16"], &[1, 7]);
+    assert_matches(&["-i", "in", "10.0.0.0/8,203.0.113.9"], &[6, 8]);
+}
+
+#[test]
+fn timestamp_operators_compare_instants() {
+    assert_matches(&["--timestamp", "gt", "2023-02-12T14:05:00+00:00"], &[4, 5, 6, 7]);
+    assert_matches(&["-t", "gte", "2023-02-12T14:05:02+00:00"], &[4, 5, 6, 7]);
+    assert_matches(&["-t", "lt", "2023-02-12T14:04:10+00:00"], &[1, 8]);
+    assert_matches(&["-t", "lte", "2023-02-12T14:04:10+00:00"], &[1, 2, 8]);
+    assert_matches(&["-t", "eq", "2023-02-12T12:08:45Z"], &[8]);
+    assert_matches(&["-t", "neq", "2023-02-12T12:08:45Z"], &[1, 2, 3, 4, 5, 6, 7]);
+}
+
+#[test]
+fn flags_combine_with_and() {
+    assert_matches(&["-s", "eq", "404", "-u", "contains", "python"], &[4, 5]);
+    assert_matches(&["-s", "eq", "200", "-i", "eq", "52.176.92.5"], &[1]);
+    assert_matches(&["-s", "eq", "200", "-u", "contains", "Firefox"], &[]);
+}
+
+#[test]
+fn empty_result_exits_with_1() {
+    log_parser("combined.log")
+        .args(["filter", "--status-code", "eq", "418"])
+        .assert()
+        .code(1)
+        .stdout("");
+}
+
+#[test]
+fn fail_on_exits_with_3_when_a_condition_holds() {
+    let run = |args: &[&str], fail_on: &[&str]| {
+        let mut cmd = log_parser("combined.log");
+        cmd.arg("filter").args(args);
+        for condition in fail_on {
+            cmd.args(["--fail-on", condition]);
+        }
+        cmd.assert()
+    };
+    run(&["-s", "gte", "500"], &["matches"])
+        .code(3)
+        .stdout(fixture_lines("combined.log", &[7]))
+        .stderr(predicate::str::ends_with("--fail-on matches>0 holds: matches is 1\n"));
+    run(&["-s", "eq", "418"], &["matches"]).code(1).stdout("");
+    run(&["--all"], &["matches>100"]).code(0);
+    run(&["--all"], &["matches>100", "match-rate>0.5"])
+        .code(3)
+        .stderr(predicate::str::ends_with("--fail-on match-rate>0.5 holds: match-rate is 1\n"));
+    // 2 of the 8 lines are 404s.
+    run(&["-s", "eq", "404"], &["match-rate>0.25"]).code(0);
+    run(&["-s", "eq", "404"], &["match-rate>=0.25"]).code(3);
+    run(&["-s", "eq", "404"], &["matches<3"]).code(3);
+    run(&["-s", "eq", "404"], &["malformed"]).code(0);
+
+    // Conditions are checked before the no-match status.
+    log_parser("common.log")
+        .args(["filter", "--all", "--fail-on", "malformed>=3"])
+        .assert()
+        .code(3)
+        .stdout("")
+        .stderr(predicate::str::ends_with("Skipped 3 malformed lines\n--fail-on malformed>=3 holds: malformed is 3\n"));
+    log_parser("combined.log")
+        .arg(fixture("missing.log"))
+        .args(["filter", "--all", "--fail-on", "malformed"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("missing.log: No such file or directory"));
+    for condition in ["lines>3", "matches>1.5", "match-rate>5"] {
+        run(&["--all"], &[condition]).code(2).stdout("").stderr(predicate::str::contains("invalid condition"));
+    }
+}
+
+#[test]
+fn all_passes_everything_through() {
+    assert_matches(&["--all"], &[1, 2, 3, 4, 5, 6, 7, 8]);
+}
+
+#[test]
+fn no_filters_is_rejected() {
+    log_parser("combined.log")
+        .arg("filter")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("no filters specified; use --all to pass all entries"))
+        .stderr(predicate::str::contains("--status-code"))
+        .stderr(predicate::str::contains("--suspicious-paths"));
+}
+
+#[test]
+fn all_conflicts_with_filters() {
+    log_parser("combined.log")
+        .args(["filter", "--all", "-s", "eq", "200"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn invalid_filters_are_all_reported() {
+    log_parser("combined.log")
+        .args(["filter", "-s", "eq", "not_fund", "-u", "contain", "bot", "-i", "eq", "1.2.3"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "--status-code: invalid value \"not_fund\" for eq: unknown status name \"not_fund\"; did you mean not_found, found, continue?",
+        ))
+        .stderr(predicate::str::contains("--user-agent: unknown operator \"contain\"\n  did you mean contains?"))
+        .stderr(predicate::str::contains("--ip: invalid value \"1.2.3\" for eq: invalid IP address syntax"));
+}
+
+#[test]
+fn none_on_always_present_field_is_rejected() {
+    log_parser("combined.log")
+        .args(["filter", "--ip", "none"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("this field is present in every entry"));
+}
+
+#[test]
+fn missing_file_is_an_error() {
+    log_parser("does-not-exist.log")
+        .args(["filter", "--all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("does-not-exist.log"));
+}
+
+#[test]
+fn unknown_field_lists_available_fields() {
+    log_parser("custom.log")
+        .args(["--format-string", r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent" $request_time $upstream_addr"#])
+        .args(["filter", "--field", "upstream", "eq", "x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown field upstream"))
+        .stderr(predicate::str::contains("upstream_addr"));
+}
+
+#[test]
+fn every_field_is_reachable_by_name() {
+    let cases: [(&str, &[&str]); 4] = [
+        ("combined.log", &[]),
+        ("alb.log", &["--log-type", "alb"]),
+        ("haproxy.log", &["--log-type", "haproxy"]),
+        ("nginx-error.log", &["--log-type", "nginx-error"]),
+    ];
+    for (file, args) in cases {
+        let output = log_parser(file).args(args).arg("fields").assert().success();
+        let listed = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+        for field in listed.lines().skip(1).map(|row| row.split('\t').next().unwrap()) {
+            let template = format!("{{{}}}", field);
+            let filters: [&[&str]; 3] =
+                [&["--all", "--fields", field], &["--all", "--format", &template], &["--field", field, "some", ""]];
+            for filter in filters {
+                let output = log_parser(file).args(args).arg("filter").args(filter).assert();
+                let code = output.get_output().status.code();
+                assert!(matches!(code, Some(0 | 1)), "{}: filter {:?} failed: {:?}", file, filter, output.get_output());
+            }
+        }
+    }
+}
+
+#[test]
+fn fields_answer_to_their_aliases_everywhere() {
+    assert_matches(&["--field", "ua", "contains", "python"], &[4, 5]);
+    assert_matches(&["--field", "status", "eq", "404"], &[4, 5]);
+    assert_matches(&["--field", "user-agent", "contains", "python", "--field", "bytes", "gt", "0"], &[4, 5]);
+    assert_matches(&["--exclude", "ua contains Chrome, status neq 500", "-s", "gte", "400"], &[4, 5, 7]);
+    let fields = |args: &[&str]| {
+        let output = log_parser("combined.log").arg("filter").args(args).args(["-s", "eq", "404"]).assert().success();
+        String::from_utf8(output.get_output().stdout.clone()).unwrap()
+    };
+    assert_eq!(fields(&["--fields", "ua,status"]), "python-requests/2.28.1\t404\n".repeat(2));
+    assert_eq!(fields(&["--format", "{user-agent} {status-code}"]), "python-requests/2.28.1 404\n".repeat(2));
+    log_parser("combined.log")
+        .args(["stats", "--by", "ua,status_code", "-s", "eq", "404"])
+        .assert()
+        .success()
+        .stdout("user-agent\tstatus\tcount\npython-requests/2.28.1\t404\t2\n");
+    log_parser("combined.log")
+        .args(["filter", "--all", "--sort", "path"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("path has no order to sort by; sort by a time or a number: timestamp, status_code"));
+    log_parser("combined.log")
+        .args(["filter", "--all", "--fields", "severity"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown field severity; fields of this log type: ip, hostname, user, timestamp"));
+}
+
+#[test]
+fn explain_filter_does_not_read_input() {
+    log_parser("does-not-exist.log")
+        .args(["filter", "-s", "eq", "503", "--referrer", "none", "--explain-filter"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Entries must match all of:"))
+        .stdout(predicate::str::contains("status_code  eq 503 (Service Unavailable)"))
+        .stdout(predicate::str::contains("from --referrer"));
+}
+
+#[test]
+fn path_operators() {
+    assert_matches(&["--path", "eq", "/.env"], &[5]);
+    assert_matches(&["-p", "starts_with", "/api/"], &[2, 7]);
+    assert_matches(&["-p", "in", "/.env"], &[5]);
+}
+
+#[test]
+fn in_lists_load_from_files() {
+    let dir = std::env::temp_dir().join(format!("log-parser-values-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let agents = dir.join("agents.txt");
+    std::fs::write(&agents, "# scanners\npython-requests/2.28.1\n\n  Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)  \n").unwrap();
+    let agents = format!("@{}", agents.display());
+
+    assert_matches(&["-u", "in", &agents], &[4, 5, 8]);
+    log_parser("combined.log")
+        .args(["filter", "-u", "in", &agents])
+        .assert()
+        .stderr(predicate::str::starts_with(format!("Loaded 2 values for --user-agent from {}\n", &agents[1..])));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_value_file_names_the_flag() {
+    log_parser("combined.log")
+        .args(["filter", "--path", "in", "@does-not-exist.txt"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::starts_with("Error: --path: invalid value \"@does-not-exist.txt\" for in: "));
+}
+
+#[test]
+fn emitted_values_feed_a_later_run() {
+    let dir = std::env::temp_dir().join(format!("log-parser-chain-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let bad_ips = dir.join("bad_ips.txt");
+
+    let output = log_parser("combined.log")
+        .args(["filter", "--status-code", "eq", "404", "--fields", "ip", "--unique"])
+        .assert()
+        .success()
+        .stdout("193.105.7.171\n")
+        .get_output()
+        .stdout
+        .clone();
+    std::fs::write(&bad_ips, output).unwrap();
+
+    assert_matches(&["--ip", "in", &format!("@{}", bad_ips.display())], &[4, 5]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn timestamp_regressions_are_counted_and_reported() {
+    log_parser("combined.log")
+        .args(["filter", "-s", "eq", "404"])
+        .assert()
+        .success()
+        .stderr(predicate::str::ends_with("combined.log: timestamps went backwards 1 time (see --check-order)\n"));
+    log_parser("combined.log")
+        .args(["--check-order", "filter", "-s", "eq", "404"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "combined.log: line 8: timestamp 2023-02-12T14:08:45+02:00 is earlier than 2023-02-12T14:07:21+00:00 on line 7\n",
+        ));
+    log_parser("combined.log")
+        .args(["--check-order", "--order-slack", "7200s", "filter", "-s", "eq", "404"])
+        .assert()
+        .success()
+        .stderr("");
+}
+
+#[test]
+fn exclude_groups_drop_matching_entries() {
+    assert_matches(&["-s", "eq", "404", "--exclude", "path eq /.env"], &[4]);
+    assert_matches(&["--all", "--exclude", "ip eq 52.176.92.5, status eq 200"], &[2, 3, 4, 5, 6, 7, 8]);
+    assert_matches(&["--exclude", "user_agent contains Chrome"], &[3, 4, 5, 6, 8]);
+    assert_matches(&["--all", "--exclude", "status eq 404", "--exclude", "referrer none"], &[1, 2, 7, 8]);
+}
+
+#[test]
+fn exclusions_overlapping_positive_filters() {
+    // An exclusion equal to the positive filter rejects everything it would have passed.
+    assert_matches(&["-s", "eq", "404", "--exclude", "status_code eq 404"], &[]);
+    // Only entries meeting every condition of the group are dropped.
+    assert_matches(&["-i", "eq", "52.176.92.5", "--exclude", "ip eq 52.176.92.5, status eq 500"], &[1]);
+    // A group that can't match alongside the positive filter changes nothing.
+    assert_matches(&["-s", "eq", "200", "--exclude", "status eq 404, path eq /.env"], &[1, 6, 8]);
+}
+
+#[test]
+fn fuzzy_matches_values_within_an_edit_distance() {
+    assert_matches(&["-p", "fuzzy", "wp-logn"], &[4]);
+    assert_matches(&["-p", "fuzzy", "wp-login:0"], &[4]);
+    assert_matches(&["-p", "fuzzy_eq", "/.envv"], &[5]);
+    assert_matches(&["-p", "fuzzy_eq", "/.envvv"], &[]);
+    assert_matches(&["-u", "fuzzy", "Mozila/5.0"], &[1, 2, 3, 7, 8]);
+    assert_matches(&["-u", "fuzzy", "pyhton-requests:2"], &[4, 5]);
+    log_parser("combined.log")
+        .args(["filter", "-p", "fuzzy", "env:3"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--path: invalid value \"env:3\" for fuzzy: a distance of 3 leaves nothing of \"env\" to match",
+        ));
+}
+
+#[test]
+fn contains_any_loads_patterns_from_files() {
+    let dir = std::env::temp_dir().join(format!("log-parser-patterns-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, content: &str| {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        format!("@{}", path.display())
+    };
+    let agents = write("agents.txt", "# scanners\npython-requests\n\nGooglebot\n");
+    let paths = write("paths.txt", "/api/\n.env\n");
+    let empty = write("empty.txt", "# nothing yet\n\n");
+
+    assert_matches(&["-u", "contains_any", &agents], &[4, 5, 8]);
+    assert_matches(&["-u", "not_contains_any", &agents], &[1, 2, 3, 7]);
+    assert_matches(&["-p", "contains_any", &paths], &[2, 5, 7]);
+    assert_matches(&["-r", "not_contains_any", "example.com"], &[8]);
+    log_parser("combined.log")
+        .args(["filter", "-u", "contains_any", &empty])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "--user-agent: invalid value {:?} for contains_any: the file lists no values",
+            empty
+        )));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bytes_accept_size_suffixes_and_separators() {
+    assert_matches(&["--bytes", "gt", "5k"], &[1]);
+    assert_matches(&["--bytes", "gte", "5k"], &[1, 8]);
+    assert_matches(&["--bytes", "gt", "5kB"], &[1, 8]);
+    assert_matches(&["--bytes", "eq", "12,345"], &[1]);
+    assert_matches(&["--bytes", "lte", "0.1k"], &[3, 6, 7]);
+    log_parser("combined.log")
+        .args(["filter", "--bytes", "gt", "5Mx"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--bytes: invalid value \"5Mx\" for gt: invalid size \"5Mx\": expected"))
+        .stderr(predicate::str::contains("e.g. 512k, 1.5M, 100MB or 1,048,576"));
+    log_parser("combined.log")
+        .args(["filter", "--all", "--sort", "bytes", "--max-memory", "1,5M"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid size \"1,5M\""));
+}
+
+#[test]
+fn files_may_follow_the_command() {
+    let expected = fixture_lines("combined.log", &[4, 5]);
+    for args in [
+        vec!["filter".into(), fixture("combined.log"), "-s".into(), "eq".into(), "404".into()],
+        vec!["filter".into(), "-s".into(), "eq".into(), "404".into(), fixture("combined.log")],
+        vec!["filter".into(), "-s".into(), "eq".into(), "404".into(), "--input".into(), fixture("combined.log")],
+    ] {
+        assert_cmd::cargo::cargo_bin_cmd!("cli-parser").args(args).assert().success().stdout(expected.clone());
+    }
+    log_parser("combined.log")
+        .args(["filter", "-s", "eq", "404"])
+        .arg(fixture("ipv6.log"))
+        .assert()
+        .success()
+        .stdout(format!("{}{}", expected, fixture_lines("ipv6.log", &[2])));
+}
+
+#[test]
+fn where_evaluates_arithmetic_over_fields() {
+    let slow_backend = "target_processing_time / (request_processing_time + target_processing_time + response_processing_time) > 0.8";
+    log_parser("alb.log")
+        .args(["--log-type", "alb", "filter", "--where", slow_backend])
+        .assert()
+        .success()
+        .stdout(fixture_lines("alb.log", &[1]));
+    log_parser("alb.log")
+        .args(["--log-type", "alb", "filter", "--where", "received_bytes / 0 >= 0 or bytes * 2 == 114"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("alb.log", &[2]));
+    log_parser("alb.log")
+        .args(["--log-type", "alb", "filter", "--where", "target * 2 > 1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("target is text (\"10.0.0.1:80\") in the first parsed line"));
+}
+
+#[test]
+fn after_status_keeps_clients_that_recently_failed() {
+    let after = |args: &[&str]| {
+        let mut cmd = log_parser("after-status.log");
+        cmd.arg("filter").args(args).args(["--within", "10m"]);
+        cmd
+    };
+    // 203.0.113.5 gets a 401 and then succeeds, 198.51.100.7 succeeds before its 403 and after
+    // it, and the last request comes too late.
+    after(&["--after-status", "401,403"]).assert().success().stdout(fixture_lines("after-status.log", &[2, 4, 6]));
+    after(&["--summary", "-s", "eq", "200", "--after-status", "unauthorized"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("after-status.log", &[2, 4]))
+        .stderr(predicate::str::contains("2 matches; rejected by: after_status 3; status_code 2"));
+    let json = after(&["--after-status", "401", "-o", "json"]).assert().success().get_output().stdout.clone();
+    let lines: Vec<serde_json::Value> = String::from_utf8(json).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.iter().map(|entry| entry["line_no"].as_u64().unwrap()).collect::<Vec<_>>(), [2, 4]);
+
+    log_parser("combined.log")
+        .args(["filter", "--after-status", "401", "--within", "1h"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "combined.log: line 8: timestamps go backwards, but --after-status assumes the input is in time order",
+        ));
+    log_parser("after-status.log").args(["filter", "--after-status", "401"]).assert().failure();
+}
+
+#[test]
+fn connection_filters_match_scheme_port_and_tls_protocol() {
+    let alb = |args: &[&str]| {
+        let mut cmd = log_parser("alb.log");
+        cmd.args(["--log-type", "alb", "filter"]).args(args);
+        cmd
+    };
+    alb(&["--scheme", "eq", "https"]).assert().success().stdout(fixture_lines("alb.log", &[2]));
+    alb(&["--port", "eq", "80"]).assert().success().stdout(fixture_lines("alb.log", &[1, 3, 4]));
+    alb(&["--tls-protocol", "lt", "TLSv1.3"]).assert().success().stdout(fixture_lines("alb.log", &[2]));
+    alb(&["--tls-protocol", "none"]).assert().success().stdout(fixture_lines("alb.log", &[1, 3, 4]));
+    alb(&["--tls-protocol", "gt", "TLSv2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid TLS protocol: TLSv2"));
+
+    log_parser("combined.log")
+        .args(["filter", "--scheme", "eq", "https", "--tls-protocol", "gte", "TLSv1.2"])
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "Warning: --scheme, --tls-protocol never match, the selected log type records no scheme, tls_protocol",
+        ));
+}
+
+#[test]
+fn hostname_clients_match_client_but_never_ip() {
+    let matches = |args: &[&str], lines: &[usize]| {
+        log_parser("hostnames.log").arg("filter").args(args).assert().success().stdout(fixture_lines("hostnames.log", lines));
+    };
+    matches(&["--client", "eq", "crawler.example.com"], &[1, 3]);
+    matches(&["--client", "ends_with", ".example.net"], &[4]);
+    matches(&["--client", "eq", "10.0.0.7"], &[5]);
+    matches(&["--ip", "neq", "10.0.0.0/8"], &[2]);
+    log_parser("hostnames.log")
+        .args(["filter", "--ip", "in", "10.0.0.0/8"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("hostnames.log", &[5]))
+        .stderr(predicate::str::contains("3 entries have a hostname for a client, which --ip never matches"));
+    log_parser("hostnames.log")
+        .args(["filter", "--ip", "in", "10.0.0.0/8", "--errors", "json"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("\"hostname_clients\":3"));
+}
+
+#[test]
+fn undecomposable_requests_are_kept_whole_and_filtered_apart() {
+    let matches = |args: &[&str], lines: &[usize]| {
+        let expected = fixture_lines("attack-requests.log", lines);
+        log_parser("attack-requests.log").arg("filter").args(args).assert().success().stdout(expected);
+    };
+    matches(&["--only-malformed-requests"], &[2, 3, 5]);
+    matches(&["--raw-request", "contains", r"\x16\x03"], &[2]);
+    matches(&["--raw-request", "none", "--status-code", "gte", "400"], &[4]);
+    matches(&["--path", "some"], &[1, 6]);
+    log_parser("attack-requests.log")
+        .args(["filter", "--raw-request", "starts_with", "OPTIONS", "--output", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""method":null,"path":null,"protocol":null,"raw_request":"OPTIONS sip:nm SIP/2.0""#))
+        .stderr(predicate::str::contains("3 entries have a request line that isn't METHOD PATH PROTOCOL"));
+    log_parser("attack-requests.log")
+        .args(["--errors", "json", "filter", "--only-malformed-requests"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(r#""malformed":0,"malformed_requests":3"#));
+}
+
+#[test]
+fn extracted_fields_filter_as_numbers_and_are_absent_where_the_regex_misses() {
+    const VERSION: &str = r"path:^/api/v(?P<version>\d+)/(?P<resource>[a-z]+)";
+    let matches = |args: &[&str], lines: &[usize]| {
+        log_parser("api.log").args(["--extract", VERSION, "filter"]).args(args).assert().success().stdout(fixture_lines("api.log", lines));
+    };
+    // v10 is a number, so it's past 2 where the text "10" wouldn't be.
+    matches(&["--field", "version", "gt", "1"], &[2, 4, 5]);
+    matches(&["--where", "version >= 10"], &[5]);
+    matches(&["--field", "resource", "eq", "users"], &[4]);
+    matches(&["--exclude", "version some"], &[3]);
+    log_parser("api.log")
+        .args(["--extract", r"path:^/api/v(\d+)/", "filter", "--all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has no named group to take a field name from; write one as (?P<name>...)"));
+}
+
+#[test]
+fn normalized_paths_match_every_spelling_but_print_as_logged() {
+    let matches = |args: &[&str], lines: &[usize]| {
+        log_parser("paths.log").args(args).assert().success().stdout(fixture_lines("paths.log", lines));
+    };
+    matches(&["filter", "--path", "eq", "/foo/bar"], &[1]);
+    matches(&["--normalize-paths", "filter", "--path", "eq", "/foo/bar"], &[1, 2, 3, 7]);
+    matches(&["--normalize-paths", "filter", "--path", "starts_with", "/foo/bar"], &[1, 2, 3, 4, 7]);
+    matches(&["--normalize-paths", "--normalize-case", "filter", "--path", "eq", "/foo/bar"], &[1, 2, 3, 5, 7]);
+    // An escaped slash is part of its segment, not a separator.
+    matches(&["--normalize-paths", "filter", "--path", "eq", "/foo/a%2Fbar"], &[6]);
+    log_parser("paths.log")
+        .args(["--normalize-case", "filter", "--all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--normalize-paths"));
+}
+
+#[test]
+fn requests_filter_on_their_class_and_path_depth() {
+    assert_matches(&["--class", "static"], &[1, 3]);
+    assert_matches(&["--class", "dynamic"], &[2, 4, 5, 6, 7, 8]);
+    assert_matches(&["--field", "path_depth", "gt", "1"], &[1, 2, 3, 7]);
+    assert_matches(&["--where", "path_depth == 1", "--class", "dynamic"], &[4, 5, 6, 8]);
+    assert_matches(&["--exclude", "class eq static, status_code eq 200"], &[2, 3, 4, 5, 6, 7, 8]);
+    // Both look at the path filters see, normalized with --normalize-paths.
+    let matches = |args: &[&str], lines: &[usize]| {
+        log_parser("paths.log").args(args).assert().success().stdout(fixture_lines("paths.log", lines));
+    };
+    matches(&["filter", "--field", "path_depth", "eq", "2"], &[1, 2, 4, 5, 6]);
+    matches(&["--normalize-paths", "filter", "--field", "path_depth", "eq", "2"], &[1, 2, 3, 4, 5, 6, 7]);
+    matches(&["filter", "--class", "static"], &[7]);
+    log_parser("combined.log")
+        .args(["--extract", "path:^/(?P<class>[a-z]+)", "filter", "--all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("(?P<class>...) would hide the class field"));
+}
+
+#[test]
+fn suspicious_paths_name_each_trigger() {
+    let matches = |args: &[&str], lines: &[usize]| {
+        log_parser("traversal.log").args(args).assert().success().stdout(fixture_lines("traversal.log", lines));
+    };
+    // `..` inside a segment name, as in lines 1 and 6, isn't a traversal.
+    matches(&["filter", "--suspicious-paths"], &[2, 3, 4, 5, 7]);
+    // The path as logged, not as --normalize-paths resolves it.
+    matches(&["--normalize-paths", "filter", "--suspicious-paths"], &[2, 3, 4, 5, 7]);
+    log_parser("traversal.log")
+        .args(["filter", "--suspicious-paths", "--fields", "line_no,suspicious"])
+        .assert()
+        .success()
+        .stdout("2\tdot-dot\n3\tdot-dot-encoded,null-byte\n4\tdouble-encoded\n5\toverlong-encoding\n7\tdot-dot-encoded\n");
+
+    let output = log_parser("traversal.log").args(["filter", "--suspicious-paths", "--verbose-matches", "-o", "json"]).output();
+    let output = output.unwrap();
+    let entry: serde_json::Value = serde_json::from_slice(output.stdout.split(|&b| b == b'\n').nth(1).unwrap()).unwrap();
+    assert_eq!(entry["suspicious"], serde_json::json!(["dot-dot-encoded", "null-byte"]));
+    assert_eq!(entry["matched_by"]["suspicious"]["value"], serde_json::json!(["dot-dot-encoded", "null-byte"]));
+    let output = log_parser("traversal.log").args(["filter", "--all", "-o", "json"]).output().unwrap();
+    let entry: serde_json::Value = serde_json::from_slice(output.stdout.split(|&b| b == b'\n').next().unwrap()).unwrap();
+    assert!(entry.get("suspicious").is_none());
+
+    log_parser("traversal.log")
+        .args(["stats", "--by", "suspicious", "--suspicious-paths"])
+        .assert()
+        .success()
+        .stdout(concat!(
+            "suspicious\tcount\n",
+            "dot-dot\t1\ndot-dot-encoded\t1\ndot-dot-encoded,null-byte\t1\ndouble-encoded\t1\noverlong-encoding\t1\n",
+        ));
+}
+
+#[test]
+fn byte_counts_that_are_not_numbers_are_kept_as_invalid() {
+    // A negative count, one past 2^64 and text: the entries are kept, without bytes.
+    log_parser("bad-bytes.log")
+        .args(["filter", "--ip", "eq", "203.0.113.9"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("bad-bytes.log", &[1, 2, 3, 4]))
+        .stderr(predicate::str::contains("3 entries have a bytes value that isn't a number"));
+    log_parser("bad-bytes.log")
+        .args(["filter", "--bytes", "gt", "0"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("bad-bytes.log", &[1]));
+
+    let output = log_parser("bad-bytes.log").args(["filter", "--all", "-o", "json"]).output().unwrap();
+    let entry: serde_json::Value = serde_json::from_slice(output.stdout.split(|&b| b == b'\n').nth(1).unwrap()).unwrap();
+    assert_eq!(entry["bytes"], serde_json::Value::Null);
+    assert_eq!(entry["invalid"], serde_json::json!({"bytes": "-5"}));
+    let output = log_parser("bad-bytes.log").args(["--summary", "--errors", "json", "filter", "--all"]).output().unwrap();
+    let summary: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!((summary["malformed"].as_u64(), summary["invalid_numbers"].as_u64()), (Some(0), Some(3)));
+
+    log_parser("bad-bytes.log")
+        .args(["--strict-numbers", "filter", "--all"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("bad-bytes.log", &[1]))
+        .stderr(predicate::str::contains("Skipped 3 malformed lines"));
+    log_parser("bad-bytes.log")
+        .args(["--strict-numbers", "--strict", "filter", "--all"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("line 2: Invalid value for Bytes: -5"));
+}
+
+#[test]
+fn lines_repeating_those_just_before_them_are_skipped() {
+    // Lines 1-3 and 9-10 are bursts of one request, 5-6 of a malformed line, and 8 repeats 4.
+    let summary = |args: &[&str]| {
+        let output = log_parser("duplicates.log").args(["--summary", "--errors", "json"]).args(args).output().unwrap();
+        let summary = String::from_utf8(output.stderr).unwrap();
+        let summary: serde_json::Value = serde_json::from_str(summary.lines().last().unwrap()).unwrap();
+        (summary["duplicates"].as_u64(), summary["malformed"].as_u64(), summary["matched"].as_u64())
+    };
+    assert_eq!(summary(&["filter", "--all"]), (None, Some(2), Some(8)));
+    assert_eq!(summary(&["--skip-consecutive-duplicates", "filter", "--all"]), (Some(4), Some(1), Some(5)));
+    assert_eq!(summary(&["--skip-duplicates-window", "4", "filter", "--all"]), (Some(5), Some(1), Some(4)));
+
+    // The same request far from its burst is kept, with its line number in the file.
+    log_parser("duplicates.log")
+        .args(["--skip-consecutive-duplicates", "filter", "--all"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("duplicates.log", &[1, 4, 7, 8, 9]))
+        .stderr(predicate::str::contains("skipped 4 duplicate lines repeating a line just before"));
+    log_parser("duplicates.log")
+        .args(["--skip-duplicates-window", "4", "filter", "--all", "--fields", "line_no,path"])
+        .assert()
+        .success()
+        .stdout("1\t/a\n4\t/b\n7\t/c\n9\t/a\n");
+    log_parser("duplicates.log")
+        .args(["--skip-consecutive-duplicates", "--skip-duplicates-window", "4", "filter", "--all"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn the_config_file_replaces_the_static_rules() {
+    let dir = std::env::temp_dir().join(format!("log-parser-classify-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.toml");
+    let run = |contents: &str| {
+        std::fs::write(&config, contents).unwrap();
+        log_parser("combined.log").arg("--config").arg(&config).args(["filter", "--class", "static"]).assert()
+    };
+    run("[classify]\nstatic_prefixes = [\"/api/\"]  # served from the CDN\n").success().stdout(fixture_lines("combined.log", &[1, 2, 3, 7]));
+    run("[classify]\nstatic_prefixes = []\nstatic_extensions = ['.PHP', \"env\"]\n").success().stdout(fixture_lines("combined.log", &[4, 5]));
+    run("[classify]\nstatic_prefixes = \"/api/\"\nprefixes = []\n")
+        .failure()
+        .stderr(predicate::str::contains("config.toml: line 2: static_prefixes takes a list of strings"))
+        .stderr(predicate::str::contains("config.toml: line 3: unknown setting prefixes in [classify]"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reload_on_change_swaps_in_the_filter_file_while_following() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::{Command, Stdio};
+
+    let dir = std::env::temp_dir().join(format!("log-parser-reload-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let (log, filters) = (dir.join("access.log"), dir.join("filters.txt"));
+    std::fs::write(&log, "").unwrap();
+    std::fs::write(&filters, "# server errors\nstatus >= 500\n").unwrap();
+    let append = |lines: &[(&str, u16)]| {
+        let mut file = std::fs::OpenOptions::new().append(true).open(&log).unwrap();
+        for (path, status) in lines {
+            let request = format!("\"GET {} HTTP/1.1\" {}", path, status);
+            let line = format!("192.0.2.1 - - [12/Feb/2023:14:00:00 +0000] {} 5 \"-\" \"curl/8.0\"\n", request);
+            file.write_all(line.as_bytes()).unwrap();
+        }
+    };
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cli-parser"))
+        .args(["--log-type", "combined", "filter", "--fields", "path", "--limit", "2", "--poll-interval", "20ms"])
+        .arg("--follow-glob")
+        .arg(&log)
+        .arg("--filter-file")
+        .arg(&filters)
+        .arg("--reload-on-change")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let next = |reader: &mut dyn BufRead| {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line
+    };
+    // Following starts at the end of the file, once it's been opened.
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    append(&[("/a", 200), ("/b", 503)]);
+    assert_eq!(next(&mut stdout), "/b\n");
+
+    // A file that doesn't parse leaves the filter as it was.
+    std::fs::write(&filters, "status >=\n").unwrap();
+    let warning = next(&mut stderr);
+    assert!(warning.starts_with("Warning: --filter-file ") && warning.contains("filters.txt: line 1: "), "{}", warning);
+    std::fs::write(&filters, "status == 200\n").unwrap();
+    assert_eq!(next(&mut stderr), format!("Reloaded {}:\n", filters.display()));
+    assert_eq!((next(&mut stderr), next(&mut stderr)), ("  - status >= 500\n".to_string(), "  + status == 200\n".to_string()));
+    append(&[("/c", 500), ("/d", 200)]);
+    assert_eq!(next(&mut stdout), "/d\n");
+
+    assert!(child.wait().unwrap().success());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,609 @@
+mod common;
+
+use assert_cmd::cargo::cargo_bin_cmd;
+use common::{fixture, fixture_lines, log_parser};
+use predicates::prelude::*;
+
+#[test]
+fn common_log_type() {
+    log_parser("common.log")
+        .args(["--log-type", "common", "filter", "-s", "eq", "401", "-o", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""user":"bob""#))
+        .stdout(predicate::str::contains(r#""path":"/api/login""#));
+}
+
+#[test]
+fn month_names_in_any_case_and_one_digit_days() {
+    // Lines from servers that write `2/feb/2023` parse, and are written back as `02/Feb/2023`.
+    log_parser("months.log")
+        .args(["filter", "--all", "-o", "combined"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[02/Feb/2023:14:03:45 +0000]").and(predicate::str::contains("[28/Feb/2023:23:59:59 +0000]")))
+        .stdout(predicate::str::contains("[01/Mar/2023:00:00:00 +0000]"));
+    // Times given to options can take the same form, at midnight UTC without a time.
+    log_parser("months.log").args(["filter", "-t", "gte", "1/mar/2023"]).assert().success().stdout(fixture_lines("months.log", &[3]));
+    log_parser("months.log")
+        .args(["filter", "-t", "lt", "28/Feb/2023:23:59:59 +0000"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("months.log", &[1]));
+}
+
+#[test]
+fn combined_is_the_default_and_rejects_common_lines() {
+    log_parser("common.log")
+        .args(["filter", "--all"])
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr(format!(
+            "Warning: {}: input looks like common log format, but combined was selected \u{2014} \
+             use --log-type common or --log-type auto\nSkipped 3 malformed lines\n",
+            fixture("common.log").display()
+        ));
+}
+
+#[test]
+fn a_mismatched_log_type_stops_strict_runs_and_auto_detects_it() {
+    log_parser("common.log")
+        .args(["--strict", "filter", "--all"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("looks like common log format, but combined was selected"));
+    log_parser("common.log")
+        .args(["--log-type", "auto", "filter", "-s", "eq", "401", "--fields", "user"])
+        .assert()
+        .success()
+        .stdout("bob\n")
+        .stderr("");
+    log_parser("nginx-error.log").args(["--log-type", "auto", "filter", "--all"]).assert().success().stderr("");
+    log_parser("malformed.log").args(["--log-type", "auto", "filter", "--all"]).assert().failure();
+}
+
+#[test]
+fn gzip_input_is_decompressed() {
+    log_parser("combined.log.gz")
+        .args(["filter", "-s", "eq", "404"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("combined.log", &[4, 5]));
+}
+
+#[test]
+fn every_member_of_a_multi_member_gzip_file_is_read() {
+    // combined.log compressed in two halves, one member each; 404s are on lines 4 and 5.
+    let all = std::fs::read_to_string(fixture("combined.log")).unwrap();
+    log_parser("combined-members.log.gz").args(["filter", "--all"]).assert().success().stdout(all.clone());
+    log_parser("combined-members.log.gz")
+        .args(["--summary", "--errors", "json", "filter", "-s", "eq", "404"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("combined.log", &[4, 5]))
+        .stderr(predicate::str::contains(r#""lines":8,"bytes":1198,"compressed_bytes":721,"matched":2"#));
+
+    // Bytes after the last member that don't start another are left unread, with a warning.
+    let dir = std::env::temp_dir().join(format!("log-parser-members-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("access.log.gz");
+    let mut bytes = std::fs::read(fixture("combined-members.log.gz")).unwrap();
+    bytes.extend_from_slice(b"\0\0\0\0 trailing padding");
+    std::fs::write(&log, bytes).unwrap();
+    cargo_bin_cmd!("cli-parser")
+        .arg(&log)
+        .args(["filter", "--all"])
+        .assert()
+        .success()
+        .stdout(all)
+        .stderr(predicate::str::contains("gzip decoding stopped after 721 of 742 compressed bytes"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn crlf_line_endings() {
+    log_parser("crlf.log")
+        .args(["filter", "-s", "eq", "401"])
+        .assert()
+        .success()
+        .stdout("197.78.33.109 - - [12/Feb/2023:14:04:10 +0000] \"POST /api/login HTTP/1.1\" 401 540 \"-\" \"curl/8.0\"\n")
+        .stderr("");
+}
+
+#[test]
+fn escaped_quotes_in_quoted_fields() {
+    log_parser("quoted.log")
+        .args(["filter", "-u", "eq", "Agent \"quoted\" name"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("quoted.log", &[1]));
+    log_parser("quoted.log")
+        .args(["filter", "-u", "eq", "back\\slash", "-o", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""referrer":"http://example.com/?q=\"x\"""#));
+}
+
+#[test]
+fn ipv6_clients() {
+    log_parser("ipv6.log")
+        .args(["filter", "--ip", "eq", "2001:db8:0:0::2"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("ipv6.log", &[2]));
+    log_parser("ipv6.log")
+        .args(["filter", "--ip", "eq", "2001:db8::/32"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("ipv6.log", &[1, 2]));
+    log_parser("ipv6.log")
+        .args(["filter", "--ip", "in", "2001:db8::1, 198.51.100.0/24"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("ipv6.log", &[1, 5]));
+}
+
+#[test]
+fn ipv4_mapped_addresses_match_ipv4_filters() {
+    log_parser("ipv6.log")
+        .args(["filter", "--ip", "eq", "192.0.2.4"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("ipv6.log", &[3, 4]));
+}
+
+#[test]
+fn ipv6_clients_in_every_spelling() {
+    // Lines 2 and 3 are the same address, written in capitals and expanded; line 6 is v4-mapped.
+    let matching = |filter: &[&str], lines: &[usize]| {
+        log_parser("ipv6-clients.log")
+            .arg("filter")
+            .args(filter)
+            .assert()
+            .success()
+            .stdout(fixture_lines("ipv6-clients.log", lines));
+    };
+    matching(&["--ip", "eq", "2001:0db8:0000:0000:0000:0000:0000:000a"], &[2, 3]);
+    matching(&["--ip", "eq", "2001:db8:1::/48"], &[4, 8]);
+    matching(&["--ip", "neq", "2001:DB8::a"], &[1, 4, 5, 6, 7, 8]);
+    matching(&["--ip", "in", "::1, 198.51.100.0/24, fe80::/10"], &[1, 5, 6, 7]);
+    matching(&["--ip", "eq", "::ffff:198.51.100.21"], &[7]);
+    log_parser("ipv6-clients.log")
+        .args(["filter", "--ip", "eq", "2001:db8::/129"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--ip: invalid value \"2001:db8::/129\""));
+
+    // Keys and fields hold the canonical spelling, so both spellings count as one client.
+    log_parser("ipv6-clients.log")
+        .args(["top", "ip", "-n", "1"])
+        .assert()
+        .success()
+        .stdout("ip\tcount\tshare\n2001:db8::a\t2\t25.00\n");
+    log_parser("ipv6-clients.log")
+        .args(["filter", "--ip", "eq", "2001:db8::a", "--fields", "ip"])
+        .assert()
+        .success()
+        .stdout("2001:db8::a\n2001:db8::a\n");
+}
+
+#[test]
+fn alb_log_type() {
+    log_parser("alb.log")
+        .args(["--log-type", "alb", "filter", "--field", "target_processing_time", "gt", "0.04"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("alb.log", &[2]));
+    log_parser("alb.log")
+        .args(["--log-type", "alb", "filter", "-s", "eq", "503", "-o", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""client_port":2817"#))
+        .stdout(predicate::str::contains(r#""host":"www.example.com:80""#))
+        .stdout(predicate::str::contains("target_processing_time").not());
+}
+
+#[test]
+fn haproxy_log_type() {
+    log_parser("haproxy.log")
+        .args(["--log-type", "haproxy", "filter", "--backend", "eq", "api_servers", "--response-time", "gt", "2000"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("haproxy.log", &[2]));
+    log_parser("haproxy.log")
+        .args(["--log-type", "haproxy", "filter", "--termination-state", "starts_with", "SC", "-o", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""server":"<NOSRV>""#))
+        .stdout(predicate::str::contains(r#""tr":"#).not());
+}
+
+#[test]
+fn syslog_prefixes_are_stripped() {
+    log_parser("syslog.log")
+        .args(["--strip-syslog", "filter", "--host", "eq", "web02"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("syslog.log", &[2]))
+        .stderr("Skipped 1 malformed lines\n");
+    log_parser("syslog.log")
+        .args(["--strip-syslog", "filter", "--host", "none"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("syslog.log", &[4]));
+}
+
+#[test]
+fn rfc3164_and_rfc5424_wrapped_combined_logs() {
+    for file in ["syslog-rfc3164.log", "syslog-rfc5424.log"] {
+        log_parser(file)
+            .args(["--strip-syslog", "filter", "--all", "-o", "combined"])
+            .assert()
+            .success()
+            .stdout(fixture_lines("combined.log", &[1, 2, 3, 4]))
+            .stderr("");
+        log_parser(file)
+            .args(["--strip-syslog", "filter", "--host", "eq", "web01"])
+            .assert()
+            .success()
+            .stdout(fixture_lines(file, &[1, 3]));
+        log_parser(file)
+            .args(["filter", "--all"])
+            .assert()
+            .code(1)
+            .stdout("")
+            .stderr("Skipped 4 malformed lines\n");
+    }
+}
+
+#[test]
+fn mapped_files_read_like_streamed_files() {
+    let dir = std::env::temp_dir().join(format!("log-parser-mmap-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let unterminated = dir.join("unterminated.log");
+    std::fs::write(&unterminated, fixture_lines("combined.log", &[1, 2, 7]).trim_end()).unwrap();
+    let invalid = dir.join("invalid.log");
+    let mut log = fixture_lines("combined.log", &[1]).into_bytes();
+    log.extend_from_slice(b"\xff\xfe \"GET / HTTP/1.1\"\n");
+    log.extend_from_slice(fixture_lines("combined.log", &[7]).as_bytes());
+    std::fs::write(&invalid, log).unwrap();
+
+    let run = |file: &std::path::Path, threshold: &str, args: &[&str]| {
+        let output = cargo_bin_cmd!("cli-parser")
+            .arg(file)
+            .args(["--mmap-threshold", threshold, "--summary", "--errors", "json"])
+            .args(args)
+            .args(["filter", "--all", "-o", "json"])
+            .output()
+            .unwrap();
+        (output.status.code(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+    };
+    for (file, args) in [
+        (fixture("combined.log"), &[][..]),
+        (fixture("crlf.log"), &[]),
+        (fixture("combined.log.gz"), &[]),
+        (fixture("w3c.log"), &["--log-type", "w3c"]),
+        (fixture("cri.log"), &["--unwrap", "cri"]),
+        (unterminated.clone(), &[]),
+    ] {
+        let streamed = run(&file, "1G", args);
+        assert_eq!(streamed.0, Some(0), "{}", file.display());
+        assert_eq!(run(&file, "0", args), streamed, "{}", file.display());
+    }
+    assert_eq!(run(&unterminated, "0", &[]).1.lines().count(), 3);
+    // A line that isn't UTF-8 is read lossily either way, and this one is still malformed.
+    let (streamed, mapped) = (run(&invalid, "1G", &[]), run(&invalid, "0", &[]));
+    assert_eq!(mapped, streamed);
+    assert_eq!(mapped.1.lines().count(), 2);
+    assert!(mapped.2.contains(r#""kind":"malformed_line","#) && mapped.2.contains(r#""line":2,"#), "{}", mapped.2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_utf8_is_read_lossily_mapped_or_streamed() {
+    let path = std::env::temp_dir().join(format!("log-parser-invalid-utf8-{}.log", std::process::id()));
+    let mut log = fixture_lines("combined.log", &[1]).into_bytes();
+    log.extend_from_slice(b"52.176.92.5 - - [12/Feb/2023:14:03:45 +0000] \"GET / HTTP/1.1\" 200 5 \"-\" \"curl/8 \xff\"\n");
+    log.extend_from_slice(fixture_lines("combined.log", &[7]).as_bytes());
+    std::fs::write(&path, log).unwrap();
+
+    // The default threshold streams a file this small; 0 maps it. Either way the \xff becomes
+    // U+FFFD and the line parses and matches.
+    let run = |threshold: &[&str]| {
+        let output =
+            cargo_bin_cmd!("cli-parser").arg(&path).args(threshold).args(["filter", "--all", "-o", "combined"]).output().unwrap();
+        (output.status.code(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+    };
+    let expected = [
+        fixture_lines("combined.log", &[1]),
+        "52.176.92.5 - - [12/Feb/2023:14:03:45 +0000] \"GET / HTTP/1.1\" 200 5 \"-\" \"curl/8 \u{fffd}\"\n".to_string(),
+        fixture_lines("combined.log", &[7]),
+    ]
+    .concat();
+    let streamed = run(&[]);
+    assert_eq!(streamed, (Some(0), expected, String::new()));
+    assert_eq!(run(&["--mmap-threshold", "0"]), streamed);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn container_records_are_unwrapped() {
+    let login = "197.78.33.109 - - [12/Feb/2023:14:04:10 +0000] \"POST /api/login HTTP/1.1\" 401 540 \"http://example.com/login\" \"curl/8\"\n";
+    let combined = [
+        "52.176.92.5 - - [12/Feb/2023:14:03:45 +0000] \"GET /images/logo.png HTTP/1.1\" 200 12345 \"http://example.com/home\" \"Mozilla/5.0\"\n",
+        login,
+        "193.105.7.171 - - [12/Feb/2023:14:05:02 +0000] \"GET /wp-login.php HTTP/1.1\" 404 162 \"-\" \"python-requests/2.28.1\"\n",
+    ]
+    .concat();
+    for (file, wrapper) in [("docker.log", "docker-json"), ("cri.log", "cri")] {
+        log_parser(file)
+            .args(["--unwrap", wrapper, "filter", "--all", "-o", "combined"])
+            .assert()
+            .success()
+            .stdout(combined.clone())
+            .stderr("Skipped 2 malformed lines (1 wrapper errors, 1 payload parse errors)\n");
+        // A line split over records is printed whole, as the container wrote it.
+        log_parser(file)
+            .args(["--unwrap", wrapper, "filter", "-s", "eq", "401"])
+            .assert()
+            .success()
+            .stdout(login);
+    }
+    log_parser("cri.log").args(["filter", "--all"]).assert().code(1).stdout("").stderr("Skipped 7 malformed lines\n");
+}
+
+#[test]
+fn wrapper_time_stands_in_for_a_missing_timestamp() {
+    let dir = std::env::temp_dir().join(format!("log-parser-unwrap-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("app.log");
+    let records = [
+        r#"{"log":"10.0.0.1 \"GET /health HTTP/1.1\" 200\n","stream":"stdout","time":"2023-02-12T14:34:20.5Z"}"#,
+        // The last record is cut off mid-line.
+        r#"{"log":"10.0.0.2 \"GET /","stream":"stdout","time":"2023-02-12T14:34:21Z"}"#,
+    ];
+    std::fs::write(&log, records.join("\n") + "\n").unwrap();
+    let run = |args: &[&str]| {
+        cargo_bin_cmd!("cli-parser")
+            .arg(&log)
+            .args(["--format-string", r#"$remote_addr "$request" $status"#, "--unwrap", "docker-json"])
+            .args(args)
+            .args(["filter", "--all", "--fields", "ip,timestamp"])
+            .assert()
+            .success()
+            .stderr("Skipped 1 malformed lines (1 wrapper errors, 0 payload parse errors)\n")
+            .get_output()
+            .stdout
+            .clone()
+    };
+    assert_eq!(run(&[]), b"10.0.0.1\t-\n");
+    assert_eq!(run(&["--wrapper-time"]), b"10.0.0.1\t2023-02-12T14:34:20.500Z\n");
+    cargo_bin_cmd!("cli-parser").arg(&log).args(["--wrapper-time", "filter", "--all"]).assert().failure();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn nginx_error_log_type() {
+    log_parser("nginx-error.log")
+        .args(["--log-type", "nginx-error", "filter", "--severity", "gte", "error"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("nginx-error.log", &[1, 3]));
+    log_parser("nginx-error.log")
+        .args(["--log-type", "nginx-error", "filter", "--field", "upstream", "contains", "8080", "-o", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""ip":"1.2.3.4""#))
+        .stdout(predicate::str::contains(r#""path":"/x""#));
+}
+
+#[test]
+fn apache_error_log_type() {
+    log_parser("apache-error.log")
+        .args(["--log-type", "apache-error", "filter", "--severity", "eq", "error"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("apache-error.log", &[1, 2]));
+    log_parser("apache-error.log")
+        .args(["--log-type", "apache-error", "filter", "--field", "error_code", "eq", "AH00489", "-o", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""module":"mpm_event""#));
+}
+
+#[test]
+fn w3c_fields_follow_directives() {
+    log_parser("w3c.log")
+        .args(["--log-type", "w3c", "filter", "--field", "time-taken", "gt", "1", "-o", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""path":"/a.js?v=1""#))
+        .stdout(predicate::str::contains(r#""duration":1.5"#));
+    log_parser("w3c.log")
+        .args(["--log-type", "w3c", "filter", "-u", "contains", "Windows NT"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("192.0.2.100"));
+    log_parser("w3c.log")
+        .args(["--log-type", "w3c", "filter", "-s", "eq", "500"])
+        .assert()
+        .success()
+        .stdout("2019-12-05\t01:00:00\t10.0.0.1\t500\n");
+}
+
+#[test]
+fn csv_columns_are_mapped_to_fields() {
+    let columns = "timestamp=event_time,ip=src_addr,method=http_method,path=url_path,status=http_status";
+    let map = ["--log-type", "csv", "--csv-map", columns];
+    log_parser("siem.csv")
+        .args(map)
+        .args(["filter", "--field", "region", "eq", "eu-west", "--fields", "ip,timestamp,user_agent"])
+        .assert()
+        .success()
+        .stdout(concat!(
+            "52.176.92.5\t2023-02-12T14:03:45Z\tMozilla/5.0 (Windows NT 10.0; Win64; x64), Chrome\n",
+            "193.105.7.171\t2023-02-12T14:05:02Z\tpython \"requests\"\n",
+        ));
+    log_parser("siem.csv")
+        .args(map)
+        .args(["filter", "-s", "eq", "401", "-o", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""path":"/api/login""#))
+        .stdout(predicate::str::contains(r#""note":"multi\nline, note""#));
+    // Without the map, the columns are fields of their own.
+    log_parser("siem.csv")
+        .args(["--log-type", "csv", "filter", "--field", "http_status", "gte", "400", "--fields", "src_addr"])
+        .assert()
+        .success()
+        .stdout("197.78.33.109\n193.105.7.171\n");
+}
+
+#[test]
+fn csv_written_with_a_header_reads_back_to_the_same_stats() {
+    let dir = std::env::temp_dir().join(format!("log-parser-csv-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let csv = dir.join("export.csv");
+    let fields = "ip,timestamp,method,path,protocol,status_code,bytes,referrer,user_agent";
+    let mut export = log_parser("combined.log");
+    export.args(["--delimiter", ",", "filter", "--all", "--header", "--fields", fields]);
+    std::fs::write(&csv, export.output().unwrap().stdout).unwrap();
+    let stats = |mut command: assert_cmd::Command| {
+        let output = command.args(["stats", "--by", "status", "--metric", "bytes"]).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let expected = stats(log_parser("combined.log"));
+    let mut read_back = cargo_bin_cmd!("cli-parser");
+    read_back.arg(&csv).args(["--log-type", "csv"]);
+    assert_eq!(stats(read_back), expected);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(expected.starts_with("status\tcount\tmean"), "{}", expected);
+}
+
+#[test]
+fn custom_format_string() {
+    let format = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent" $request_time $upstream_addr"#;
+    log_parser("custom.log")
+        .args(["--format-string", format, "filter", "--field", "request_time", "gt", "1.5"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("custom.log", &[2]));
+    log_parser("custom.log")
+        .args(["--format-string", format, "filter", "--duration", "lt", "300ms", "-o", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""user_agent":"curl/8 \"x\"""#))
+        .stdout(predicate::str::contains(r#""upstream_addr":"10.1.0.5:80""#));
+}
+
+#[test]
+fn invalid_format_string() {
+    log_parser("custom.log")
+        .args(["--format-string", "$remote_addr$status", "filter", "--all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("must be separated by literal text"));
+}
+
+#[test]
+fn crlf_lines_filter_like_lf_lines() {
+    let dir = std::env::temp_dir().join(format!("log-parser-crlf-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("windows.log");
+    let lf = std::fs::read_to_string(fixture("combined.log")).unwrap();
+    std::fs::write(&path, lf.replace('\n', "\r\r\n")).unwrap();
+    let output = assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+        .arg(&path)
+        .args(["filter", "-u", "ends_with", "Safari/537.36", "--fields", "ip"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    std::fs::remove_dir_all(&dir).unwrap();
+    log_parser("combined.log")
+        .args(["filter", "-u", "ends_with", "Safari/537.36", "--fields", "ip"])
+        .assert()
+        .success()
+        .stdout(String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn unexpanded_wildcards_are_expanded() {
+    log_parser("combined.log*")
+        .args(["filter", "-s", "eq", "404", "--count-by", "file"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "file\tmatched\n{}\t2\n{}\t2\ntotal\t4\n",
+            fixture("combined.log").display(),
+            fixture("combined.log.gz").display()
+        ));
+    log_parser("nothing-*.log")
+        .args(["filter", "--all"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("nothing-*.log: no files match"));
+}
+
+#[test]
+fn fields_cover_what_each_log_type_parses() {
+    let format = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent" $request_time $upstream_addr"#;
+    let cases: [(&str, &[&str]); 9] = [
+        ("combined.log", &[]),
+        ("common.log", &["--log-type", "common"]),
+        ("alb.log", &["--log-type", "alb"]),
+        ("haproxy.log", &["--log-type", "haproxy"]),
+        ("nginx-error.log", &["--log-type", "nginx-error"]),
+        ("apache-error.log", &["--log-type", "apache-error"]),
+        ("syslog.log", &["--strip-syslog"]),
+        ("w3c.log", &["--log-type", "w3c"]),
+        ("custom.log", &["--format-string", format]),
+    ];
+    for (file, args) in cases {
+        // Declared without the file, except W3C fields, which only the file declares.
+        let mut fields = cargo_bin_cmd!("cli-parser");
+        if file == "w3c.log" {
+            fields.arg(fixture(file));
+        }
+        let output = fields.args(args).arg("fields").assert().success();
+        let listed = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+        let listed: Vec<&str> = listed.lines().skip(1).map(|row| row.split('\t').next().unwrap()).collect();
+        let output = log_parser(file).args(args).args(["filter", "--all", "-o", "json"]).assert();
+        for line in String::from_utf8(output.get_output().stdout.clone()).unwrap().lines() {
+            let serde_json::Value::Object(entry) = serde_json::from_str(line).unwrap() else {
+                panic!("entries are objects");
+            };
+            for (name, value) in entry {
+                let provenance = ["source_file", "line_no", "byte_offset"].contains(&name.as_str());
+                assert!(value.is_null() || provenance || listed.contains(&name.as_str()), "{}: fields lacks {}", file, name);
+            }
+        }
+    }
+}
+
+#[test]
+fn fields_and_operators_can_be_listed() {
+    cargo_bin_cmd!("cli-parser")
+        .args(["--format-string", "$remote_addr $request_time", "--list-fields"])
+        .assert()
+        .success()
+        .stdout("field\tfilter\tkind\nip\t--ip\tip\nrequest_time\t--field request_time\tfield\nduration\t--duration\tord\ngap\t--field gap\tfield\n");
+    log_parser("nginx-error.log")
+        .args(["--log-type", "nginx-error", "fields", "-o", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#"{"field":"upstream","filter":"--field upstream","kind":"field"}"#));
+    cargo_bin_cmd!("cli-parser")
+        .args(["--log-type", "w3c", "fields"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("#Fields: header"));
+    cargo_bin_cmd!("cli-parser")
+        .arg("--list-operators")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ip\t--ip\teq neq in\t--ip in 10.0.0.0/8\n"));
+}
@@ -0,0 +1,1780 @@
+mod common;
+
+use common::{fixture, fixture_lines, log_parser};
+use predicates::prelude::*;
+
+fn stdout_of(cmd: &mut assert_cmd::Command) -> String {
+    String::from_utf8(cmd.assert().success().get_output().stdout.clone()).expect("utf-8 output")
+}
+
+#[test]
+fn json_output_has_one_object_per_match() {
+    let output = stdout_of(log_parser("combined.log").args(["filter", "-s", "eq", "404", "-o", "json"]));
+    let entries: Vec<serde_json::Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["ip"], "193.105.7.171");
+    assert_eq!(entries[0]["status_code"], 404);
+    assert_eq!(entries[0]["timestamp"], "2023-02-12T14:05:02Z");
+    assert_eq!(entries[1]["path"], "/.env");
+    assert!(entries[1]["referrer"].is_null());
+}
+
+#[test]
+fn json_array_output_is_one_document() {
+    // Runs without a match exit with 1 but still print an array.
+    let array = |args: &[&str]| -> serde_json::Value {
+        let output = log_parser("combined.log").args(args).args(["-o", "json-array"]).output().unwrap();
+        assert!(output.status.code().is_some_and(|code| code <= 1));
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+    let json = stdout_of(log_parser("combined.log").args(["filter", "-s", "eq", "404", "-o", "json"]));
+    let entries: Vec<serde_json::Value> = json.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(array(&["filter", "-s", "eq", "404"]), serde_json::Value::from(entries));
+    assert_eq!(array(&["filter", "-s", "eq", "999"]), serde_json::json!([]));
+    assert_eq!(array(&["filter", "--all", "--count"]), serde_json::json!([{"matched": 8}]));
+    assert_eq!(array(&["stats", "--by", "status", "-s", "eq", "404"]), serde_json::json!({"404": 2}));
+    assert_eq!(array(&["cardinality", "--of", "ip", "--exact"]).as_array().map(Vec::len), Some(1));
+}
+
+#[test]
+fn dict_fields_are_written_once_and_expand_back_to_the_originals() {
+    let plain = stdout_of(log_parser("repeated-agents.log").args(["filter", "--all", "-o", "json"]));
+    let dict_fields = ["--dict-fields", "user_agent,referrer,referrer_domain"];
+    let encoded = stdout_of(log_parser("repeated-agents.log").args(["filter", "--all", "-o", "json"]).args(dict_fields));
+    assert!(encoded.len() < plain.len() * 4 / 5, "{} bytes encoded, {} plain", encoded.len(), plain.len());
+
+    let mut records: Vec<serde_json::Value> = encoded.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let dictionary = records.pop().unwrap()["dictionary"].take();
+    assert_eq!(dictionary["user_agent"].as_array().map(Vec::len), Some(3));
+    assert_eq!(dictionary["referrer"].as_array().map(Vec::len), Some(2));
+    // Every ID is in the dictionary, and swapping each back gives the plain output.
+    for record in &mut records {
+        for field in ["user_agent", "referrer", "referrer_domain"] {
+            if let Some(id) = record[field].as_u64() {
+                let value = dictionary[field].get(id as usize);
+                record[field] = value.unwrap_or_else(|| panic!("{} {} is missing", field, id)).clone();
+            }
+        }
+    }
+    let plain: Vec<serde_json::Value> = plain.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(records, plain);
+
+    let dir = std::env::temp_dir().join(format!("log-parser-dict-fields-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("agents.csv");
+    let rows = stdout_of(
+        log_parser("repeated-agents.log")
+            .args(["--delimiter", ",", "filter", "--all", "--fields", "ip,user_agent", "--ua-dictionary", "--dict-file"])
+            .arg(&file),
+    );
+    assert_eq!(rows.lines().take(3).collect::<Vec<_>>(), ["198.51.100.1,0", "198.51.100.2,1", "198.51.100.3,2"]);
+    let dictionary = std::fs::read_to_string(&file).unwrap();
+    assert_eq!(dictionary.lines().next(), Some("field,id,value"));
+    assert!(dictionary.lines().nth(1).unwrap().starts_with("user_agent,0,\"Mozilla/5.0 (Windows NT 10.0;"));
+    assert_eq!(dictionary.lines().count(), 4);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    log_parser("repeated-agents.log")
+        .args(["filter", "--all", "--ua-dictionary"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pass -o json or --fields"));
+}
+
+#[test]
+fn combined_output_round_trips() {
+    for (file, log_type) in [("combined.log", "combined"), ("quoted.log", "combined"), ("common.log", "common"), ("ipv6.log", "combined")] {
+        let json = stdout_of(log_parser(file).args(["--log-type", log_type, "filter", "--all", "-o", "json"]));
+        let combined = stdout_of(log_parser(file).args(["--log-type", log_type, "filter", "--all", "-o", "combined"]));
+
+        let dir = std::env::temp_dir().join(format!("log-parser-round-trip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(file);
+        std::fs::write(&path, &combined).unwrap();
+        let reparsed = stdout_of(
+            assert_cmd::cargo::cargo_bin_cmd!("cli-parser").arg(&path).args(["filter", "--all", "-o", "json"]),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        for (original, reparsed) in json.lines().zip(reparsed.lines()) {
+            let original: serde_json::Value = serde_json::from_str(original).unwrap();
+            let reparsed: serde_json::Value = serde_json::from_str(reparsed).unwrap();
+            for field in ["ip", "user", "timestamp", "method", "path", "protocol", "status_code", "bytes", "referrer", "user_agent"] {
+                assert_eq!(original[field], reparsed[field], "{} in {}", field, file);
+            }
+        }
+        assert_eq!(json.lines().count(), reparsed.lines().count(), "{}", file);
+    }
+}
+
+#[test]
+fn convert_writes_combined_lines_that_filter_like_the_original() {
+    let alb = |args: &[&str]| stdout_of(log_parser("alb.log").args(["--log-type", "alb"]).args(args));
+    let combined = alb(&["convert", "--to", "combined"]);
+    assert_eq!(combined.lines().nth(2), Some("10.0.0.140 - - [02/Jul/2018:22:23:00 +0000] \"GET / HTTP/1.1\" 101 587 \"-\" \"-\""));
+
+    let dir = std::env::temp_dir().join(format!("log-parser-convert-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("alb-combined.log");
+    std::fs::write(&path, &combined).unwrap();
+    let filter = |args: &[&str]| {
+        stdout_of(assert_cmd::cargo::cargo_bin_cmd!("cli-parser").arg(&path).args(["--log-type", "combined", "filter"]).args(args))
+    };
+    let converted = filter(&["-u", "contains", "curl", "-o", "json"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let original = alb(&["filter", "-u", "contains", "curl", "-o", "json"]);
+    assert_eq!(original.lines().count(), 3);
+    for (original, converted) in original.lines().zip(converted.lines()) {
+        let original: serde_json::Value = serde_json::from_str(original).unwrap();
+        let converted: serde_json::Value = serde_json::from_str(converted).unwrap();
+        for field in ["ip", "user", "method", "path", "protocol", "status_code", "bytes", "referrer", "user_agent"] {
+            assert_eq!(original[field], converted[field], "{}", field);
+        }
+        // Combined timestamps have whole seconds.
+        let seconds = |entry: &serde_json::Value| entry["timestamp"].as_str().unwrap()[..19].to_string();
+        assert_eq!(seconds(&original), seconds(&converted));
+    }
+    assert_eq!(converted.lines().count(), 3);
+}
+
+#[test]
+fn sort_orders_by_key_with_missing_last() {
+    log_parser("combined.log")
+        .args(["filter", "--all", "--sort", "timestamp"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("combined.log", &[8, 1, 2, 3, 4, 5, 6, 7]));
+    log_parser("haproxy.log")
+        .args(["--log-type", "haproxy", "filter", "--all", "--sort", "duration"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("haproxy.log", &[1, 2, 3]));
+}
+
+#[test]
+fn sort_spilling_to_disk_matches_in_memory_sort() {
+    let in_memory = stdout_of(log_parser("combined.log").args(["filter", "--all", "--sort", "bytes"]));
+    let spilled = stdout_of(log_parser("combined.log").args(["filter", "--all", "--sort", "bytes", "--max-memory", "100"]));
+    assert_eq!(in_memory, spilled);
+    assert_eq!(in_memory, fixture_lines("combined.log", &[3, 6, 7, 4, 5, 2, 8, 1]));
+}
+
+#[test]
+fn what_a_run_holds_fails_past_max_memory_naming_the_feature() {
+    let over = |args: &[&str], message: &str| {
+        log_parser("combined.log")
+            .args(args)
+            .args(["--max-memory", "200"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(message))
+            .stderr(predicate::str::contains("taking the run past --max-memory 200 B"));
+    };
+    over(&["filter", "--all", "--unique"], "--unique holds about");
+    over(&["filter", "--all", "--latest-per", "ip"], "--latest-per holds about");
+    over(&["filter", "--all", "--oldest-per", "path"], "--oldest-per holds about");
+    over(&["stats", "--by", "path"], "--by holds about");
+    over(&["stats", "--by", "path"], "count only the most frequent with --approximate, or raise --max-memory");
+    over(&["top", "path"], "top holds about");
+
+    // Counting approximately holds a fixed number of counters, and --sort spills instead.
+    log_parser("combined.log").args(["stats", "--by", "path", "--approximate", "--max-memory", "200"]).assert().success();
+    log_parser("combined.log").args(["filter", "--all", "--sort", "bytes", "--max-memory", "200"]).assert().success();
+}
+
+#[test]
+fn group_by_prints_each_group_behind_a_header_with_missing_values_last() {
+    log_parser("combined.log")
+        .args(["filter", "--all", "--group-by", "user", "--format", "{path}"])
+        .assert()
+        .success()
+        .stdout(concat!(
+            "== user alice (1 match) ==\n/css/styles.css\n",
+            "== user - (7 matches) ==\n/images/logo.png\n/api/login\n/wp-login.php\n/.env\n/health\n/api/orders\n/search?q=rust\n",
+        ))
+        .stderr(predicate::str::contains("Holding every --group-by user group until the input ends\n"));
+    log_parser("combined.log")
+        .args(["filter", "--all", "--group-by", "status", "-o", "json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("JSON output has no place for"));
+}
+
+#[test]
+fn sorted_time_groups_stream_the_buffered_output_in_bounded_memory() {
+    let dir = std::env::temp_dir().join(format!("log-parser-group-by-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let sorted = dir.join("sorted.log");
+    let lines: Vec<String> = (0..30 * 24 * 10)
+        .map(|i| {
+            let (day, hour, minute) = (1 + i / 240, i / 10 % 24, i % 10);
+            format!(
+                "10.0.0.{} - - [{:02}/Mar/2023:{:02}:{:02}:00 +0000] \"GET /item/{} HTTP/1.1\" 200 512 \"-\" \"curl/8.0\"",
+                i % 200,
+                day,
+                hour,
+                minute,
+                i
+            )
+        })
+        .collect();
+    std::fs::write(&sorted, lines.join("\n") + "\n").unwrap();
+    let run = |args: &[&str]| {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("cli-parser");
+        cmd.arg(&sorted).args(["filter", "--all"]).args(args);
+        cmd
+    };
+
+    for key in ["day", "hour"] {
+        let buffered = stdout_of(&mut run(&["--group-by", key]));
+        let streamed = stdout_of(&mut run(&["--group-by", key, "--assume-sorted"]));
+        assert_eq!(buffered, streamed);
+        assert_eq!(buffered.lines().count(), lines.len() + if key == "day" { 30 } else { 30 * 24 });
+    }
+    assert!(stdout_of(&mut run(&["--group-by", "day"])).starts_with("== day 2023-03-01 (240 matches) ==\n10.0.0.0 "));
+
+    // A day's matches fit in 100 KiB where the month's don't.
+    run(&["--group-by", "day", "--max-memory", "100K"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--group-by holds about"))
+        .stderr(predicate::str::contains("add --assume-sorted if the input is in time order"));
+    run(&["--group-by", "day", "--assume-sorted", "--max-memory", "100K"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Holding every").not());
+
+    // Streaming can't take back a group it has printed.
+    let unsorted = dir.join("unsorted.log");
+    std::fs::write(&unsorted, [&lines[300], &lines[0]].map(|line| format!("{}\n", line)).concat()).unwrap();
+    assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+        .arg(&unsorted)
+        .args(["filter", "--all", "--group-by", "day", "--assume-sorted"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "unsorted.log:2 has --group-by day 2023-03-01, before 2023-03-02, so the input isn't in time order",
+        ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stats_summarizes_matches() {
+    log_parser("combined.log")
+        .args(["stats", "--metric", "bytes", "-s", "eq", "200"])
+        .assert()
+        .success()
+        .stdout("count\t3\nmissing\t0\nmin\t2\nmax\t12345\nmean\t5822.333333333333\np50\t5120\np90\t12345\np95\t12345\np99\t12345\n");
+}
+
+#[test]
+fn stats_without_filters_covers_every_entry() {
+    log_parser("haproxy.log")
+        .args(["--log-type", "haproxy", "stats", "--metric", "duration"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("count\t2\nmissing\t1\n"));
+}
+
+#[test]
+fn stats_by_counts_each_combination() {
+    log_parser("combined.log")
+        .args(["stats", "--by", "method,status", "--pivot"])
+        .assert()
+        .success()
+        .stdout("method\t200\t304\t401\t404\t500\ttotal\nGET\t3\t1\t0\t2\t1\t7\nPOST\t0\t0\t1\t0\t0\t1\ntotal\t3\t1\t1\t2\t1\t8\n");
+    let json = stdout_of(log_parser("combined.log").args(["stats", "--by", "path-prefix,status", "-o", "json"]));
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json["/api"], serde_json::json!({"401": 1, "500": 1}));
+    log_parser("combined.log")
+        .args(["stats", "--by", "status", "--metric", "bytes", "-s", "eq", "404"])
+        .assert()
+        .success()
+        .stdout("status\tcount\tmean\tp50\tp95\tmax\n404\t2\t162\t162\t162\t162\n");
+}
+
+#[test]
+fn stats_by_scheme_and_tls_protocol() {
+    log_parser("alb.log")
+        .args(["--log-type", "alb", "stats", "--by", "scheme,tls-protocol"])
+        .assert()
+        .success()
+        .stdout("scheme\ttls-protocol\tcount\nhttp\t-\t3\nhttps\tTLSv1.2\t1\n");
+}
+
+#[test]
+fn stats_by_is_capped_and_pivots_need_two_keys() {
+    log_parser("combined.log")
+        .args(["stats", "--by", "path", "--max-groups", "3"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--by path makes more than 3 groups"));
+    log_parser("combined.log")
+        .args(["stats", "--by", "path", "--pivot"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--pivot needs exactly two --by keys"));
+}
+
+#[test]
+fn stats_by_approximately_counts_the_most_frequent_groups() {
+    log_parser("combined.log")
+        .args(["stats", "--by", "status", "--max-groups", "3"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("count only the most frequent with --approximate"));
+    // Plenty of counters for four statuses, so the counts are exact.
+    log_parser("combined.log")
+        .args(["stats", "--by", "status", "--max-groups", "3", "--approximate"])
+        .assert()
+        .success()
+        .stdout("status\tcount\terror\n200\t3\t0\n404\t2\t0\n304\t1\t0\n401\t1\t0\n500\t1\t0\n");
+    log_parser("combined.log")
+        .args(["stats", "--by", "status", "--approximate", "--pivot"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn fixture_helper_points_at_checked_in_files() {
+    assert!(fixture("combined.log").is_file());
+}
+
+#[test]
+fn bench_reports_each_iteration() {
+    let output = stdout_of(log_parser("combined.log").args(["bench", "--iterations", "2", "-s", "eq", "404", "-o", "json"]));
+    let report: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let iterations = report["iterations"].as_array().unwrap();
+    assert_eq!(iterations.len(), 2);
+    for (i, iteration) in iterations.iter().enumerate() {
+        assert_eq!(iteration["iteration"], i + 1);
+        assert_eq!(iteration["lines"], 8);
+        assert_eq!(iteration["matched"], 2);
+        assert_eq!(iteration["bytes"], std::fs::metadata(fixture("combined.log")).unwrap().len());
+        for stage in ["read_secs", "parse_secs", "match_secs", "wall_secs", "lines_per_sec", "mb_per_sec"] {
+            assert!(iteration[stage].as_f64().unwrap() >= 0.0, "{}", stage);
+        }
+    }
+
+    log_parser("combined.log")
+        .args(["bench", "--iterations", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("iteration\tlines\tbytes\tmatched\twall_s\tlines/s\tMB/s\tread_s\tparse_s\tmatch_s\n1\t8\t"));
+}
+
+#[test]
+fn fields_print_selected_values() {
+    log_parser("combined.log")
+        .args(["filter", "-s", "eq", "200", "--fields", "ip,user,status_code,path"])
+        .assert()
+        .success()
+        .stdout("52.176.92.5\t-\t200\t/images/logo.png\n10.0.0.7\t-\t200\t/health\n203.0.113.9\t-\t200\t/search?q=rust\n");
+    log_parser("combined.log")
+        .args(["filter", "--all", "--fields", "ip", "--unique", "--sort", "timestamp"])
+        .assert()
+        .success()
+        .stdout("203.0.113.9\n52.176.92.5\n197.78.33.109\n64.228.32.14\n193.105.7.171\n10.0.0.7\n");
+    log_parser("combined.log")
+        .args(["filter", "--all", "--fields", "client"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown field client"));
+}
+
+#[test]
+fn format_escapes_placeholders() {
+    log_parser("combined.log")
+        .args(["filter", "-s", "eq", "404", "--limit", "1", "--format", "{ip} {user_agent:sh} {referrer:json} {{{path:html}}}"])
+        .assert()
+        .success()
+        .stdout("193.105.7.171 'python-requests/2.28.1' null {/wp-login.php}\n");
+    log_parser("combined.log")
+        .args(["--escape", "sh", "filter", "-s", "eq", "304", "--format", "{user} {status_code:raw}"])
+        .assert()
+        .success()
+        .stdout("'alice' 304\n");
+    log_parser("combined.log")
+        .args(["filter", "--all", "--format", "{user_agent:url}"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown escaping :url"));
+}
+
+#[test]
+fn count_by_file_covers_every_input() {
+    log_parser("combined.log")
+        .arg(fixture("ipv6.log"))
+        .args(["filter", "-s", "eq", "404", "--count-by", "file"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "file\tmatched\n{}\t2\n{}\t1\ntotal\t3\n",
+            fixture("combined.log").display(),
+            fixture("ipv6.log").display()
+        ));
+    let run = log_parser("combined.log")
+        .arg(fixture("combined.log.gz"))
+        .args(["filter", "-s", "eq", "418", "--count-by", "file", "-o", "json"])
+        .assert()
+        .code(1);
+    let output = String::from_utf8(run.get_output().stdout.clone()).unwrap();
+    let mut counts = serde_json::Map::new();
+    for file in ["combined.log", "combined.log.gz"] {
+        counts.insert(fixture(file).display().to_string(), 0.into());
+    }
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&output).unwrap(), serde_json::json!({"by": "file", "counts": counts}));
+}
+
+#[test]
+fn count_by_day_uses_tz() {
+    log_parser("combined.log")
+        .args(["filter", "--all", "--count-by", "day"])
+        .assert()
+        .success()
+        .stdout("day\tmatched\n2023-02-12\t8\ntotal\t8\n");
+    log_parser("combined.log")
+        .args(["--tz", "+10:00", "filter", "--all", "--count-by", "day", "-o", "json"])
+        .assert()
+        .success()
+        .stdout("{\"by\":\"day\",\"counts\":{\"2023-02-12\":1,\"2023-02-13\":7}}\n");
+}
+
+#[test]
+fn summary_by_goes_to_stderr_alongside_matches() {
+    log_parser("combined.log")
+        .args(["--errors", "json", "filter", "-s", "eq", "404", "--summary-by", "day"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("combined.log", &[4, 5]))
+        .stderr(predicate::str::ends_with("{\"type\":\"breakdown\",\"by\":\"day\",\"counts\":{\"2023-02-12\":2}}\n"));
+}
+
+#[test]
+fn time_format_custom_and_unix() {
+    log_parser("combined.log")
+        .args(["--time-format", "custom:%Y-%m-%d %H:%M %z", "filter", "-s", "eq", "200", "--fields", "timestamp"])
+        .assert()
+        .success()
+        .stdout("2023-02-12 14:03 +0000\n2023-02-12 14:06 +0000\n2023-02-12 14:08 +0200\n");
+    let output = stdout_of(log_parser("combined.log").args(["--time-format", "unix", "filter", "-s", "eq", "404", "-o", "json"]));
+    let entry: serde_json::Value = serde_json::from_str(output.lines().next().unwrap()).unwrap();
+    assert_eq!(entry["timestamp"], 1676210702);
+    log_parser("combined.log")
+        .args(["--time-format", "custom:%Y-%Q", "filter", "--all"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("invalid strftime pattern \"%Y-%Q\""));
+}
+
+#[test]
+fn apache_time_format_round_trips() {
+    let apache = stdout_of(log_parser("combined.log").args(["--time-format", "apache", "filter", "--all", "--fields", "timestamp"]));
+    let rfc3339 = stdout_of(log_parser("combined.log").args(["filter", "--all", "--fields", "timestamp"]));
+
+    let dir = std::env::temp_dir().join(format!("log-parser-apache-time-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("times.log");
+    std::fs::write(&path, apache.lines().map(|t| format!("[{}] 200\n", t)).collect::<String>()).unwrap();
+    let reparsed = stdout_of(
+        assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+            .arg(&path)
+            .args(["--format-string", "[$time_local] $status", "filter", "--all", "--fields", "timestamp"]),
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(reparsed, rfc3339);
+}
+
+#[test]
+fn throttle_prints_first_match_per_key_and_window() {
+    log_parser("combined.log")
+        .args(["filter", "--all", "--throttle", "ip:60s"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("combined.log", &[1, 2, 3, 4, 6, 7, 8]))
+        .stderr(predicate::str::contains("... suppressed 1 further match from 193.105.7.171\n"));
+    log_parser("combined.log")
+        .args(["filter", "--all", "--throttle", "ip:300s", "--fields", "ip"])
+        .assert()
+        .success()
+        .stdout("52.176.92.5\n197.78.33.109\n64.228.32.14\n193.105.7.171\n10.0.0.7\n203.0.113.9\n");
+    log_parser("combined.log")
+        .args(["--errors", "json", "filter", "-s", "eq", "404", "--throttle", "ip+path:60s"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("combined.log", &[4, 5]))
+        .stderr(predicate::str::contains("suppressed").not());
+    log_parser("combined.log")
+        .args(["--errors", "json", "filter", "-s", "eq", "404", "--throttle", "status:60s"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("combined.log", &[4]))
+        .stderr(predicate::str::contains(r#"{"type":"notice","kind":"suppressed","key":"status","value":"404","count":1}"#));
+}
+
+#[test]
+fn count_with_show_bytes_sums_response_sizes() {
+    log_parser("combined.log")
+        .args(["filter", "-s", "eq", "404", "--count"])
+        .assert()
+        .success()
+        .stdout("2 matches\n");
+    log_parser("combined.log")
+        .args(["filter", "-s", "eq", "200", "--count", "--show-bytes"])
+        .assert()
+        .success()
+        .stdout("3 matches, 17.1 KiB\n")
+        .stderr(predicate::str::starts_with(format!("{}: 3 matches, 17.1 KiB\n", fixture("combined.log").display())));
+    log_parser("w3c.log")
+        .args(["--log-type", "w3c", "filter", "--all", "--count", "--show-bytes"])
+        .assert()
+        .success()
+        .stdout("3 matches, 784 B (1 without a size)\n");
+    log_parser("combined.log")
+        .arg(fixture("ipv6.log"))
+        .args(["--errors", "json", "filter", "-s", "eq", "200", "--count", "--show-bytes", "-o", "json"])
+        .assert()
+        .success()
+        .stdout("{\"matched\":7,\"matched_bytes\":17487,\"missing_bytes\":0}\n")
+        .stderr(predicate::str::contains("\"matched\":4,\"matched_bytes\":20,\"missing_bytes\":0,"));
+}
+
+#[test]
+fn scanners_prints_a_row_per_flagged_client() {
+    log_parser("combined.log")
+        .args(["scanners", "--min-404", "2", "--min-distinct-paths", "2", "--window", "60s"])
+        .assert()
+        .code(1)
+        .stdout(concat!(
+            "ip\tnot_found\tdistinct_paths\twindow_start\twindow_end\tsample_paths\n",
+            "193.105.7.171\t2\t2\t2023-02-12T14:05:02+00:00\t2023-02-12T14:05:03+00:00\t/wp-login.php,/.env\n",
+        ));
+}
+
+#[test]
+fn auth_reports_a_dictionary_attack_that_got_in() {
+    log_parser("auth.log")
+        .arg("auth")
+        .assert()
+        .code(1)
+        .stdout(concat!(
+            "ip\tfailures\tlogins_after_failure\tusers\tsample_users\tfirst_failure\tlast_failure\tflagged\n",
+            "203.0.113.5\t13\t2\t10\tadmin,root,administrator,test,guest\t2023-02-12T14:01:00+00:00\t2023-02-12T14:01:12+00:00\tyes\n",
+            "192.0.2.44\t2\t1\t1\tbob\t2023-02-12T14:01:06+00:00\t2023-02-12T14:20:00+00:00\tno\n",
+            "\n",
+            "user\tfailures\tlogins_after_failure\tips\n",
+            "admin\t3\t0\t1\n",
+            "bob\t2\t1\t1\n",
+            "root\t2\t0\t1\n",
+            "administrator\t1\t0\t1\n",
+            "backup\t1\t0\t1\n",
+            "deploy\t1\t2\t1\n",
+            "guest\t1\t0\t1\n",
+            "operator\t1\t0\t1\n",
+            "test\t1\t0\t1\n",
+            "user\t1\t0\t1\n",
+            "webadmin\t1\t0\t1\n",
+        ))
+        .stderr("");
+
+    // Filters narrow what's audited. bob's 200 came a second after his first failure, well
+    // inside the window.
+    let run = log_parser("auth.log")
+        .args(["auth", "-i", "eq", "192.0.2.44", "--window", "30s", "--max-failures", "1", "-o", "json"])
+        .assert()
+        .code(1);
+    let json = String::from_utf8(run.get_output().stdout.clone()).unwrap();
+    let records: Vec<serde_json::Value> = json.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(
+        records,
+        [
+            serde_json::json!({
+                "ip": "192.0.2.44", "failures": 2, "logins_after_failure": 1, "users": 1, "sample_users": ["bob"],
+                "first_failure": "2023-02-12T14:01:06Z", "last_failure": "2023-02-12T14:20:00Z", "flagged": true,
+            }),
+            serde_json::json!({"user": "bob", "failures": 2, "logins_after_failure": 1, "ips": 1}),
+        ]
+    );
+    log_parser("auth.log").args(["auth", "--max-failures", "13"]).assert().success();
+    log_parser("auth.log").args(["auth", "-s", "eq", "200"]).assert().success().stdout("");
+}
+
+#[test]
+fn annotate_marks_every_line() {
+    let markers = |output: String| {
+        let count = |marker: &str| output.lines().filter(|line| line.starts_with(marker)).count();
+        (count("MATCH "), count("    . "), count("ERR   "))
+    };
+    let output = stdout_of(log_parser("combined.log").args(["filter", "-s", "eq", "404", "--annotate"]));
+    assert_eq!(markers(output), (2, 6, 0));
+    let output = stdout_of(log_parser("malformed.log").args(["filter", "--all", "--annotate"]));
+    assert_eq!(output.lines().count(), 5);
+    assert_eq!(markers(output), (2, 1, 2));
+    let output = stdout_of(log_parser("combined.log").args(["filter", "-u", "contains", "Chrome", "--annotate", "--limit", "2"]));
+    assert_eq!(markers(output), (2, 0, 0));
+}
+
+#[test]
+fn color_follows_the_flag_the_environment_and_the_theme() {
+    let line = fixture_lines("combined.log", &[7]);
+    let line = line.trim_end();
+    let colored = |args: &[&str], env: &[(&str, &str)]| {
+        let mut cmd = log_parser("combined.log");
+        cmd.args(["filter", "-s", "eq", "500"]).args(args).env_remove("NO_COLOR").env_remove("CLICOLOR_FORCE");
+        env.iter().for_each(|(name, value)| {
+            cmd.env(name, value);
+        });
+        stdout_of(&mut cmd)
+    };
+    assert_eq!(colored(&[], &[]), format!("{}\n", line));
+    assert_eq!(colored(&["--color", "always"], &[]), format!("\x1b[1;91m{}\x1b[0m\n", line));
+    assert_eq!(colored(&[], &[("CLICOLOR_FORCE", "1")]), format!("\x1b[1;91m{}\x1b[0m\n", line));
+    assert_eq!(colored(&[], &[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]), format!("{}\n", line));
+    assert_eq!(colored(&["--color", "always"], &[("NO_COLOR", "1")]), format!("\x1b[1;91m{}\x1b[0m\n", line));
+    assert_eq!(colored(&["--color", "always", "--theme", "light"], &[]), format!("\x1b[1;38;5;124m{}\x1b[0m\n", line));
+    // --fields rows aren't log lines.
+    assert_eq!(colored(&["--color", "always", "--fields", "status_code"], &[]), "500\n");
+
+    log_parser("combined.log")
+        .args(["--color", "always", "--align", "always", "stats", "--by", "status"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("\x1b[1mstatus  count\x1b[0m\n   200      3\n"));
+    let output = stdout_of(log_parser("combined.log").args(["--color", "always", "filter", "-s", "eq", "404", "--annotate"]));
+    assert!(output.starts_with("\x1b[2m    . "), "{}", output);
+    assert!(output.contains("\x1b[32mMATCH "), "{}", output);
+}
+
+#[test]
+fn the_config_file_overrides_theme_elements() {
+    let dir = std::env::temp_dir().join(format!("log-parser-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.toml");
+    std::fs::write(&config, "# for a light terminal\n[theme]\nbase = \"mono\"\nstatus_5xx = 208  # orange\nstatus_4xx = 'bright-magenta'\n").unwrap();
+    let lines: Vec<String> = fixture_lines("combined.log", &[4, 5, 7]).lines().map(str::to_string).collect();
+    log_parser("combined.log")
+        .arg("--config")
+        .arg(&config)
+        .args(["--color", "always", "filter", "-s", "gte", "404"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "\x1b[95m{}\x1b[0m\n\x1b[95m{}\x1b[0m\n\x1b[1;38;5;208m{}\x1b[0m\n",
+            lines[0], lines[1], lines[2]
+        ));
+
+    let fails = |contents: &str, messages: &[&str]| {
+        std::fs::write(&config, contents).unwrap();
+        let mut assert = log_parser("combined.log").arg("--config").arg(&config).args(["filter", "--all"]).assert().failure();
+        for message in messages {
+            assert = assert.stderr(predicate::str::contains(*message));
+        }
+    };
+    fails(
+        "[theme]\nstatus_6xx = \"red\"\nheader = \"grey\"\n",
+        &["config.toml: line 2: unknown theme element status_6xx", "config.toml: line 3: header: \"grey\" isn't a color"],
+    );
+    fails("[theme]\nbase = \"solarized\"\n", &["config.toml: line 2: base: \"solarized\" isn't a theme; expected one of dark, light, mono"]);
+    fails("base = \"light\"\n[colors]\n", &["line 1: base is outside any section", "line 2: unknown section [colors]; expected [theme]"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn limit_stops_after_enough_matches_across_files() {
+    log_parser("combined.log")
+        .arg(fixture("ipv6.log"))
+        .args(["filter", "--all", "--limit", "9"])
+        .assert()
+        .success()
+        .stdout(format!("{}{}", fixture_lines("combined.log", &[1, 2, 3, 4, 5, 6, 7, 8]), fixture_lines("ipv6.log", &[1])));
+}
+
+#[test]
+fn annotate_needs_force_for_large_inputs() {
+    let dir = std::env::temp_dir().join(format!("log-parser-annotate-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("large.log");
+    std::fs::File::create(&path).unwrap().set_len(65 << 20).unwrap();
+    assert_cmd::cargo::cargo_bin_cmd!("cli-parser")
+        .arg(&path)
+        .args(["filter", "--all", "--annotate"])
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("--annotate prints every line of 65.0 MiB of input; pass --force"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn nul_delimiter_ends_rows_with_nul() {
+    log_parser("combined.log")
+        .args(["--delimiter", "\\0", "filter", "-s", "eq", "404", "--fields", "ip,path"])
+        .assert()
+        .success()
+        .stdout("193.105.7.171\0/wp-login.php\x00193.105.7.171\0/.env\0");
+    log_parser("combined.log")
+        .args(["--delimiter", ",", "stats", "--metric", "bytes", "-s", "eq", "200"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("count,3\nmissing,0\nmin,2\n"));
+}
+
+fn interleaved() -> assert_cmd::Command {
+    let mut cmd = log_parser("interleave-a.log");
+    cmd.args([fixture("interleave-b.log"), fixture("interleave-c.log")]);
+    cmd
+}
+
+#[test]
+fn interleave_merges_files_in_timestamp_order() {
+    let output = stdout_of(interleaved().args(["filter", "--all", "--interleave", "-o", "json"]));
+    let entries: Vec<serde_json::Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let paths: Vec<&str> = entries.iter().map(|e| e["path"].as_str().unwrap()).collect();
+    assert_eq!(paths, ["/c1", "/a1", "/b1", "/a2", "/c2", "/b2", "/b3", "/a3", "/c3", "/b4", "/a4"]);
+    let times: Vec<chrono::DateTime<chrono::FixedOffset>> =
+        entries.iter().map(|e| chrono::DateTime::parse_from_rfc3339(e["timestamp"].as_str().unwrap()).unwrap()).collect();
+    assert!(times.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+#[test]
+fn interleave_limits_and_counts_the_merged_matches() {
+    interleaved()
+        .args(["filter", "--all", "--interleave", "--limit", "4", "--fields", "path"])
+        .assert()
+        .success()
+        .stdout("/c1\n/a1\n/b1\n/a2\n");
+    interleaved()
+        .args(["filter", "-s", "eq", "404", "--interleave", "--limit", "2", "--count", "--show-bytes"])
+        .assert()
+        .success()
+        .stdout("2 matches, 500 B\n");
+    interleaved()
+        .args(["filter", "--all", "--interleave", "--limit", "5", "--count-by", "file"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "file\tmatched\n{}\t2\n{}\t1\n{}\t2\ntotal\t5\n",
+            fixture("interleave-a.log").display(),
+            fixture("interleave-b.log").display(),
+            fixture("interleave-c.log").display()
+        ));
+}
+
+#[test]
+fn interleave_warns_about_files_out_of_order() {
+    // A single file merges to itself, so its regression is passed on as it comes.
+    let sequential = stdout_of(log_parser("combined.log").args(["filter", "--all", "--fields", "path"]));
+    log_parser("combined.log")
+        .args(["filter", "--all", "--interleave", "--fields", "path"])
+        .assert()
+        .success()
+        .stdout(sequential)
+        .stderr(predicate::str::contains("line 8: timestamp 2023-02-12T14:08:45+02:00 is earlier than 2023-02-12T14:07:21+00:00, already printed"));
+}
+
+#[test]
+fn unmatched_and_malformed_files_partition_the_input() {
+    let dir = std::env::temp_dir().join(format!("log-parser-partition-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (out, rest, bad) = (dir.join("out.log"), dir.join("rest.log.gz"), dir.join("bad.log"));
+    log_parser("malformed.log")
+        .args(["filter", "-s", "eq", "200", "--output-file"])
+        .arg(&out)
+        .arg("--unmatched-file")
+        .arg(&rest)
+        .arg("--malformed-file")
+        .arg(&bad)
+        .assert()
+        .success()
+        .stdout("")
+        .stderr(predicate::str::contains(format!("Wrote 1 unmatched line to {}", rest.display())));
+    let mut unmatched = String::new();
+    let mut gz = flate2::read::GzDecoder::new(std::fs::File::open(&rest).unwrap());
+    std::io::Read::read_to_string(&mut gz, &mut unmatched).unwrap();
+    let parts = [std::fs::read_to_string(&out).unwrap(), unmatched, std::fs::read_to_string(&bad).unwrap()];
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(parts, [fixture_lines("malformed.log", &[1]), fixture_lines("malformed.log", &[5]), fixture_lines("malformed.log", &[2, 4])]);
+
+    // Every non-blank line lands in exactly one of them.
+    let mut lines: Vec<&str> = parts.iter().flat_map(|part| part.lines()).collect();
+    lines.sort();
+    let input = std::fs::read_to_string(fixture("malformed.log")).unwrap();
+    let mut expected: Vec<&str> = input.lines().filter(|line| !line.is_empty()).collect();
+    expected.sort();
+    assert_eq!(lines, expected);
+}
+
+#[test]
+fn chunked_output_files_are_each_whole_and_add_up_to_every_match() {
+    let dir = std::env::temp_dir().join(format!("log-parser-chunks-{}", std::process::id()));
+    let read = |path: &std::path::Path| {
+        let mut text = String::new();
+        match path.extension().is_some_and(|extension| extension == "gz") {
+            true => std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(std::fs::File::open(path).unwrap()), &mut text),
+            false => std::io::Read::read_to_string(&mut std::fs::File::open(path).unwrap(), &mut text),
+        }
+        .unwrap();
+        text
+    };
+    let chunked = |name: &str, args: &[&str]| -> Vec<String> {
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        log_parser("combined.log").args(["filter", "--all", "--output-file"]).arg(dir.join(name)).args(args).assert().success();
+        let mut parts: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        parts.sort();
+        parts.iter().map(|part| read(part)).collect()
+    };
+    let entries = |parts: &[String]| -> usize {
+        parts.iter().map(|part| serde_json::from_str::<serde_json::Value>(part).unwrap().as_array().unwrap().len()).sum()
+    };
+
+    // Each part of an array is an array, and each gzip stream is finished.
+    let parts = chunked("results.json.gz", &["-o", "json-array", "--chunk-rows", "3"]);
+    assert_eq!(parts.len(), 3);
+    assert_eq!(entries(&parts), 8);
+
+    // No part of lines goes past --chunk-size, and together they're the whole output.
+    let whole = fixture_lines("combined.log", &[1, 2, 3, 4, 5, 6, 7, 8]);
+    let parts = chunked("results.log", &["--chunk-size", "400"]);
+    assert!(parts.len() > 2 && parts.iter().all(|part| part.len() <= 400), "{:?}", parts);
+    assert_eq!(parts.concat(), whole);
+    let parts = chunked("results.json", &["-o", "json-array", "--chunk-size", "1K", "--chunk-rows", "4"]);
+    assert!(parts.len() > 2 && parts.iter().all(|part| part.len() <= 1024), "{:?}", parts);
+    assert_eq!(entries(&parts), 8);
+
+    // The schema record starts every part, and the summary names each with its lines.
+    std::fs::create_dir_all(&dir).unwrap();
+    let base = dir.join("results.json");
+    let output = log_parser("combined.log")
+        .args(["--schema-version", "filter", "--all", "-o", "json", "--chunk-rows", "5", "--output-file"])
+        .arg(&base)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(format!(
+            "Wrote 5 output lines to {}\nWrote 3 output lines to {}\n",
+            dir.join("results.00001.json").display(),
+            dir.join("results.00002.json").display()
+        )))
+        .get_output()
+        .stdout
+        .clone();
+    assert!(output.is_empty());
+    for (part, lines) in [("results.00001.json", 5), ("results.00002.json", 3)] {
+        let text = read(&dir.join(part));
+        assert!(text.starts_with("{\"schema_version\":"), "{}", text);
+        assert_eq!(text.lines().skip(1).filter(|line| serde_json::from_str::<serde_json::Value>(line).is_ok()).count(), lines);
+    }
+    assert!(!base.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn novel(baseline: [&str; 2], target: [&str; 2]) -> assert_cmd::Command {
+    let mut cmd = log_parser("combined.log");
+    cmd.args(["novel", "--key", "ip"]);
+    for (option, time) in [("--baseline-since", baseline[0]), ("--baseline-until", baseline[1])] {
+        cmd.args([option, &format!("2023-02-12T{}:00Z", time)]);
+    }
+    for (option, time) in [("--target-since", target[0]), ("--target-until", target[1])] {
+        cmd.args([option, &format!("2023-02-12T{}:00Z", time)]);
+    }
+    cmd
+}
+
+#[test]
+fn novel_prints_target_entries_with_keys_the_baseline_lacks() {
+    novel(["14:00", "14:05"], ["14:05", "14:10"]).assert().success().stdout(fixture_lines("combined.log", &[4, 5, 6]));
+    novel(["14:00", "14:05"], ["14:05", "14:10"]).arg("--ordered").assert().success().stdout(fixture_lines("combined.log", &[4, 5, 6]));
+    // A baseline after the target needs both passes.
+    novel(["14:05", "14:10"], ["14:00", "14:05"]).args(["--count", "-o", "json"]).assert().success().stdout("{\"matched\":2}\n");
+    novel(["14:05", "14:10"], ["14:00", "14:05"])
+        .arg("--ordered")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--ordered needs the baseline window to end before the target window starts"));
+}
+
+#[test]
+fn novel_ordered_fails_on_baseline_entries_after_the_target() {
+    // The last line is logged at 12:08 UTC, after the target entries before it.
+    novel(["12:00", "14:00"], ["14:00", "15:00"])
+        .arg("--ordered")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is in the baseline window but follows target entries"));
+    novel(["12:00", "14:00"], ["14:00", "15:00"])
+        .arg("--count")
+        .assert()
+        .success()
+        .stdout("7 matches\n");
+}
+
+#[test]
+fn reported_offsets_lead_back_to_the_emitted_lines() {
+    use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+
+    let files = ["crlf.log", "combined.log", "combined.log.gz"];
+    let run = |args: &[&str]| {
+        let mut cmd = log_parser(files[0]);
+        cmd.arg(fixture(files[1])).arg(fixture(files[2])).args(["filter", "--all"]).args(args);
+        stdout_of(&mut cmd)
+    };
+    let lines = run(&[]);
+    let records = run(&["-o", "json"]);
+    assert_eq!(records.lines().count(), 2 + 8 + 8);
+    for (line, record) in lines.lines().zip(records.lines()) {
+        let record: serde_json::Value = serde_json::from_str(record).unwrap();
+        let (file, offset) = (record["source_file"].as_str().unwrap(), record["byte_offset"].as_u64().unwrap());
+        fn line_at(mut reader: impl BufRead + Seek, offset: u64) -> String {
+            let mut line = String::new();
+            reader.seek(SeekFrom::Start(offset)).unwrap();
+            reader.read_line(&mut line).unwrap();
+            line
+        }
+        // Offsets into gzip inputs count decompressed bytes.
+        let found = match file.ends_with(".gz") {
+            true => {
+                let mut text = Vec::new();
+                flate2::read::GzDecoder::new(std::fs::File::open(file).unwrap()).read_to_end(&mut text).unwrap();
+                line_at(std::io::Cursor::new(text), offset)
+            }
+            false => line_at(BufReader::new(std::fs::File::open(file).unwrap()), offset),
+        };
+        assert_eq!(found.trim_end_matches(['\r', '\n']), line, "{} at {}", file, offset);
+        // combined.log.gz holds combined.log.
+        let name = std::path::Path::new(file).file_name().unwrap().to_str().unwrap().trim_end_matches(".gz");
+        let line_no = record["line_no"].as_u64().unwrap() as usize;
+        assert_eq!(fixture_lines(name, &[line_no]).trim_end_matches(['\r', '\n']), line);
+    }
+
+    let rows = run(&["--fields", "path", "--with-provenance"]);
+    assert_eq!(rows.lines().nth(1), Some(format!("/api/login\t{}\t2\t106", fixture("crlf.log").display()).as_str()));
+}
+
+fn anomalies() -> assert_cmd::Command {
+    let mut cmd = log_parser("anomalies.log");
+    cmd.args(["anomalies", "--metric", "bytes", "--by", "path"]);
+    cmd.args(["--baseline-since", "2023-02-12T13:00:00Z", "--baseline-until", "2023-02-12T14:00:00Z"]);
+    cmd
+}
+
+#[test]
+fn anomalies_flags_groups_that_strayed_from_their_baseline() {
+    // /api/users went from about 500 bytes to about 5000; /static/app.js stayed put and /health
+    // has no baseline to compare with.
+    anomalies().assert().success().stdout(
+        "path\tbaseline_count\tbaseline_mean\tbaseline_stddev\tcount\tobserved\tdeviation\n\
+         /api/users\t4\t500\t8.16\t2\t5050\t557.26\n",
+    );
+    anomalies()
+        .args(["--target-since", "2023-02-12T14:15:00Z", "--target-until", "2023-02-12T15:00:00Z", "-o", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("{\"baseline\":{\"count\":4,\"mean\":500.0,").and(predicate::str::contains("\"count\":1,")));
+    anomalies().args(["--sigma", "1000"]).assert().success().stdout(predicate::str::is_match("\\A[^\\n]*\\n\\z").unwrap());
+}
+
+#[test]
+fn anomalies_emit_entries_prints_the_deviant_lines() {
+    anomalies().arg("--emit-entries").assert().success().stdout(fixture_lines("anomalies.log", &[8, 11]));
+    anomalies().args(["--sigma", "0"]).assert().failure().stderr(predicate::str::contains("--sigma must be greater than 0"));
+}
+
+fn top() -> assert_cmd::Command {
+    let mut cmd = log_parser("anomalies.log");
+    cmd.args(["top", "path", "--baseline-since", "2023-02-12T13:00:00Z", "--baseline-until", "2023-02-12T14:00:00Z"]);
+    cmd
+}
+
+#[test]
+fn top_ranks_values_by_how_much_their_share_grew() {
+    // /health wasn't requested in the baseline hour, and the other two paths split what's left.
+    top().args(["--min-baseline", "2"]).assert().success().stdout(
+        "path\tcount\tshare\tbaseline_count\tbaseline_share\tdelta\tratio\n\
+         /health\t1\t20.00\t0\t0.00\t+20.00\tnew\n\
+         /static/app.js\t2\t40.00\t3\t42.86\t-2.86\t0.93\n\
+         /api/users\t2\t40.00\t4\t57.14\t-17.14\t0.70\n",
+    );
+    let records: Vec<serde_json::Value> =
+        stdout_of(top().args(["-n", "1", "-o", "json"])).lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(records, [serde_json::json!({
+        "path": "/health", "count": 1, "share": 20.0, "baseline_count": 0, "baseline_share": 0.0, "delta": 20.0, "ratio": null, "new": true
+    })]);
+    log_parser("combined.log").args(["top", "status", "-n", "2", "-o", "csv"]).assert().success().stdout("status,count,share\n200,3,37.50\n404,2,25.00\n");
+}
+
+#[test]
+fn top_approximate_warns_when_the_sketch_is_too_small() {
+    log_parser("anomalies.log")
+        .args(["top", "path", "--approximate", "-n", "2"])
+        .assert()
+        .success()
+        .stdout("path\tcount\terror\tshare\n/api/users\t6\t0\t50.00\n/static/app.js\t5\t0\t41.67\n")
+        .stderr("");
+    // Two counters for three paths: /health took over /static/app.js's, which took it back.
+    log_parser("anomalies.log")
+        .args(["top", "path", "--approximate", "--sketch-size", "2"])
+        .assert()
+        .success()
+        .stdout("path\tcount\terror\tshare\n/api/users\t6\t0\t50.00\n/static/app.js\t6\t5\t50.00\n")
+        .stderr(predicate::str::contains("--approximate may have lost values with up to 6 matches"));
+}
+
+#[test]
+fn top_compares_with_another_log() {
+    // Against itself every share is unchanged.
+    let anomalies = fixture("anomalies.log");
+    log_parser("anomalies.log")
+        .args(["top", "path", "--min-baseline", "1", "--relative-file"])
+        .arg(&anomalies)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("/api/users\t6\t50.00\t6\t50.00\t+0.00\t1.00\n"));
+    top().arg("--relative-file").arg(&anomalies).assert().failure().stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn top_examples_print_lines_under_each_value() {
+    let run =
+        |seed: &str| stdout_of(log_parser("combined.log").args(["top", "status", "-n", "2", "--examples", "1", "--seed", seed]));
+    let output = run("1");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 5, "{}", output);
+    assert_eq!((lines[1], lines[3]), ("200\t3\t37.50", "404\t2\t25.00"));
+    assert!([1, 6, 8].iter().any(|&n| format!("  {}", fixture_lines("combined.log", &[n])).trim_end() == lines[2]), "{}", output);
+    assert!([4, 5].iter().any(|&n| format!("  {}", fixture_lines("combined.log", &[n])).trim_end() == lines[4]), "{}", output);
+    assert_eq!(output, run("1"));
+
+    // Both of 404's lines fit, in input order.
+    let json = stdout_of(log_parser("combined.log").args(["top", "status", "-n", "2", "--examples", "2", "-o", "json"]));
+    let records: Vec<serde_json::Value> = json.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let expected: Vec<String> = [4, 5].iter().map(|&n| fixture_lines("combined.log", &[n]).trim_end().to_string()).collect();
+    assert_eq!(records[1]["examples"], serde_json::json!(expected));
+    log_parser("combined.log").args(["top", "status", "--examples", "1", "--approximate"]).assert().failure();
+}
+
+#[test]
+fn top_snapshots_rank_what_has_been_counted_so_far() {
+    let args = ["top", "status"];
+    let output = log_parser("combined.log").args(args).args(["--snapshot-every", "3"]).assert().success();
+    let output = output.get_output();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), stdout_of(log_parser("combined.log").args(args)));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let snapshots: Vec<&str> = stderr.split("Partial result after ").skip(1).collect();
+    assert_eq!(snapshots.len(), 2, "{}", stderr);
+    let first = "(snapshot 1):\nstatus\tcount\tshare\n200\t1\t33.33\n304\t1\t33.33\n401\t1\t33.33\n";
+    assert!(snapshots[0].ends_with(first), "{}", stderr);
+
+    let dir = std::env::temp_dir().join(format!("log-parser-top-snapshots-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("snapshots.jsonl");
+    log_parser("combined.log")
+        .args(["top", "status", "-n", "1", "--snapshot-every", "4", "--snapshot-file"])
+        .arg(&file)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Partial").not());
+    let records: Vec<serde_json::Value> =
+        std::fs::read_to_string(&file).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["lines"], 4);
+    assert_eq!(records[0]["result"], serde_json::json!([{"status": "200", "count": 1, "share": 25.0}]));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn pager_stays_out_of_pipes() {
+    // Test output is a pipe, so even --pager always prints directly; paging itself is covered
+    // with `cat` in output::pager.
+    log_parser("combined.log")
+        .env("PAGER", "sed s/^/paged:/")
+        .args(["filter", "--all", "--pager", "always"])
+        .assert()
+        .success()
+        .stdout(fixture_lines("combined.log", &[1, 2, 3, 4, 5, 6, 7, 8]));
+}
+
+#[test]
+fn histogram_split_status_attributes_each_class() {
+    let histogram = |args: &[&str]| {
+        let mut cmd = log_parser("status-classes.log");
+        cmd.args(["histogram", "--bucket", "60s", "--all"]).args(args);
+        stdout_of(&mut cmd)
+    };
+    assert_eq!(
+        histogram(&["--split-status", "-o", "csv"]),
+        "bucket,count,2xx,3xx,4xx,5xx,other\n\
+         2023-02-12T14:00:00+00:00,4,2,1,1,0,0\n\
+         2023-02-12T14:01:00+00:00,4,1,0,0,3,0\n\
+         2023-02-12T14:02:00+00:00,0,0,0,0,0,0\n\
+         2023-02-12T14:03:00+00:00,1,1,0,0,0,0\n"
+    );
+    let ratios = histogram(&["--only-ratio"]);
+    assert_eq!(ratios.lines().nth(2), Some(format!("2023-02-12T14:01:00+00:00\t4\t75.0\t{}", "!".repeat(30)).as_str()));
+    let split = histogram(&["--split-status", "-o", "json"]);
+    let second: serde_json::Value = serde_json::from_str(split.lines().nth(1).unwrap()).unwrap();
+    assert_eq!((second["5xx"].as_u64(), second["total"].as_u64()), (Some(3), Some(4)));
+}
+
+#[test]
+fn dedupe_overlap_skips_only_a_repeated_end() {
+    let run = |next: &str| {
+        let mut cmd = log_parser("overlap-a.log");
+        cmd.arg(fixture(next)).args(["--dedupe-overlap", "filter", "--all"]);
+        cmd.assert().success()
+    };
+    // overlap-b.log starts with the last two lines of overlap-a.log.
+    run("overlap-b.log")
+        .stdout(fixture_lines("combined.log", &[1, 2, 3, 4, 5, 6]))
+        .stderr(predicate::str::contains("Skipping 2 lines at the start of").and(predicate::str::contains("overlap-b.log")));
+    // overlap-c.log starts with a line from the middle of overlap-a.log, not its end.
+    run("overlap-c.log")
+        .stdout(fixture_lines("combined.log", &[1, 2, 3, 4, 3, 5]))
+        .stderr(predicate::str::contains("Skipping").not());
+    let mut without = log_parser("overlap-a.log");
+    without.arg(fixture("overlap-b.log")).args(["filter", "--all", "--count"]).assert().success().stdout("8 matches\n");
+}
+
+#[test]
+fn schema_documents_match_their_snapshots() {
+    let kinds = [
+        "entry", "dictionary", "count", "breakdown", "stats", "groups", "histogram", "anomaly", "scanner", "check", "cardinality", "bench", "field", "operators",
+        "diagnostic", "top", "self-test", "auth-client", "auth-user", "report",
+        "batch",
+    ];
+    for kind in kinds {
+        let document = stdout_of(assert_cmd::cargo::cargo_bin_cmd!("cli-parser").args(["schema", kind]));
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/schema").join(format!("{}.json", kind));
+        let snapshot = std::fs::read_to_string(&path).expect("snapshot exists");
+        assert_eq!(
+            document, snapshot,
+            "the {} schema changed; bump SCHEMA_VERSION in src/output/schema.rs and update {}",
+            kind,
+            path.display()
+        );
+    }
+    let all: serde_json::Value = serde_json::from_str(&stdout_of(assert_cmd::cargo::cargo_bin_cmd!("cli-parser").arg("schema"))).unwrap();
+    assert_eq!(all.as_object().unwrap().len(), kinds.len());
+}
+
+#[test]
+fn schema_version_leads_each_json_stream() {
+    let output = log_parser("combined.log")
+        .args(["--schema-version", "--errors", "json", "filter", "-s", "eq", "404", "-o", "json"])
+        .assert()
+        .success();
+    let output = output.get_output();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().next(), Some("{\"schema_version\":27}"));
+    assert_eq!(stdout.lines().count(), 3);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.lines().next(), Some("{\"type\":\"schema\",\"schema_version\":27}"));
+
+    // Lines as they were read aren't JSON, so they get no preamble.
+    let lines = stdout_of(log_parser("combined.log").args(["--schema-version", "filter", "-s", "eq", "404"]));
+    assert_eq!(lines, fixture_lines("combined.log", &[4, 5]));
+}
+
+#[test]
+fn cardinality_estimates_small_inputs_exactly() {
+    let estimated = stdout_of(log_parser("combined.log").args(["cardinality", "--of", "ip", "--by", "status"]));
+    let exact = stdout_of(log_parser("combined.log").args(["cardinality", "--of", "ip", "--by", "status", "--exact"]));
+    assert_eq!(estimated, "status\tdistinct\terror\n200\t3\t±0\n304\t1\t±0\n401\t1\t±0\n404\t1\t±0\n500\t1\t±0\n");
+    assert_eq!(estimated, exact);
+}
+
+#[test]
+fn hostname_clients_are_grouped_and_typed_apart_from_addresses() {
+    assert_eq!(
+        stdout_of(log_parser("hostnames.log").args(["stats", "--by", "client"])),
+        "client\tcount\ncrawler.example.com\t2\n10.0.0.7\t1\n52.176.92.5\t1\ndsl-77-12.isp.example.net\t1\n"
+    );
+    assert_eq!(
+        stdout_of(log_parser("hostnames.log").args(["stats", "--by", "ip"])),
+        "ip\tcount\n-\t3\n10.0.0.7\t1\n52.176.92.5\t1\n"
+    );
+    let json = stdout_of(log_parser("hostnames.log").args(["filter", "--all", "-o", "json"]));
+    let clients: Vec<(serde_json::Value, serde_json::Value)> = json
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .map(|entry| (entry["ip"].clone(), entry.get("hostname").cloned().unwrap_or_default()))
+        .collect();
+    assert_eq!(clients[0], (serde_json::Value::Null, serde_json::json!("crawler.example.com")));
+    assert_eq!(clients[1], (serde_json::json!("52.176.92.5"), serde_json::Value::Null));
+    // Converting keeps whichever form was logged.
+    assert_eq!(
+        stdout_of(log_parser("hostnames.log").args(["filter", "--all", "-o", "combined"])),
+        fixture_lines("hostnames.log", &[1, 2, 3, 4, 5])
+    );
+}
+
+#[test]
+fn latest_and_oldest_per_keep_one_match_per_key() {
+    log_parser("combined.log")
+        .args(["filter", "--all", "--latest-per", "ip", "--fields", "ip,line_no"])
+        .assert()
+        .success()
+        .stdout("10.0.0.7\t6\n193.105.7.171\t5\n197.78.33.109\t2\n203.0.113.9\t8\n52.176.92.5\t7\n64.228.32.14\t3\n");
+    log_parser("combined.log")
+        .args(["filter", "--all", "--oldest-per", "ip", "--sort", "timestamp", "--fields", "ip,line_no"])
+        .assert()
+        .success()
+        .stdout("203.0.113.9\t8\n52.176.92.5\t1\n197.78.33.109\t2\n64.228.32.14\t3\n193.105.7.171\t4\n10.0.0.7\t6\n");
+    log_parser("combined.log")
+        .args(["filter", "--all", "--latest-per", "ip+path", "--max-keys", "3"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--latest-per ip+path has more than 3 distinct keys"));
+}
+
+#[test]
+fn stats_examples_print_lines_under_each_group() {
+    let run = |seed: &str| stdout_of(log_parser("combined.log").args(["stats", "--by", "status", "--examples", "2", "--seed", seed]));
+    let output = run("1");
+    let expected_404 = format!("404\t2\n  {}  {}", fixture_lines("combined.log", &[4]), fixture_lines("combined.log", &[5]));
+    assert!(output.contains(&expected_404), "{}", output);
+    assert_eq!(output.lines().filter(|line| line.starts_with("  ")).count(), 7);
+    assert_eq!(output, run("1"));
+
+    let json = stdout_of(log_parser("combined.log").args(["stats", "--by", "status", "--examples", "1", "-o", "json"]));
+    let groups: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(groups["500"], serde_json::json!({"count": 1, "examples": [fixture_lines("combined.log", &[7]).trim_end()]}));
+    log_parser("combined.log").args(["stats", "-m", "bytes", "--examples", "1"]).assert().failure();
+}
+
+#[test]
+fn tables_can_be_aligned() {
+    let aligned = stdout_of(log_parser("combined.log").args(["--align", "always", "stats", "--by", "path", "-m", "bytes"]));
+    let lines: Vec<&str> = aligned.lines().collect();
+    assert_eq!(lines[0], "path              count   mean    p50    p95    max");
+    assert_eq!(lines[6], "/images/logo.png      1  12345  12345  12345  12345");
+    assert_eq!(
+        stdout_of(log_parser("combined.log").args(["--align", "never", "stats", "--by", "status"])).lines().next(),
+        Some("status\tcount")
+    );
+
+    let agents = |args: &[&str]| {
+        let output = stdout_of(log_parser("combined.log").args(["--align", "always"]).args(args).args(["stats", "--by", "user-agent"]));
+        output.lines().map(|line| line.chars().count()).max().unwrap()
+    };
+    assert!(agents(&[]) < agents(&["--no-truncate"]));
+}
+
+#[test]
+fn jobs_split_a_large_file_without_changing_the_output() {
+    let dir = std::env::temp_dir().join(format!("log-parser-jobs-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("large.log");
+    // Timestamps go backwards on every line, so every range boundary is also a regression, and
+    // malformed lines are spread throughout.
+    let agent = "x".repeat(300);
+    let log: String = (0..12_000)
+        .map(|i| match i % 100 {
+            99 => "garbage\n".to_string(),
+            _ => {
+                let second = 86_399 - i;
+                format!(
+                    "10.0.{}.{} - - [12/Feb/2023:{:02}:{:02}:{:02} +0000] \"GET /item/{} HTTP/1.1\" {} {} \"-\" \"{}\"\n",
+                    i / 256 % 256,
+                    i % 256,
+                    second / 3600,
+                    second / 60 % 60,
+                    second % 60,
+                    i,
+                    if i % 5 == 0 { 404 } else { 200 },
+                    i % 1000,
+                    agent
+                )
+            }
+        })
+        .collect();
+    assert!(log.len() > 4 << 20);
+    std::fs::write(&path, log).unwrap();
+
+    let run = |jobs: &str, args: &[&str]| {
+        let output = assert_cmd::cargo::cargo_bin_cmd!("cli-parser").arg(&path).args(["--jobs", jobs]).args(args).assert().success();
+        let output = output.get_output();
+        (String::from_utf8(output.stdout.clone()).unwrap(), String::from_utf8(output.stderr.clone()).unwrap())
+    };
+    let filter = ["--check-order", "--errors", "json", "--summary", "filter", "-s", "eq", "404", "--fields", "path", "--with-provenance"];
+    let (stdout, stderr) = run("4", &filter);
+    assert_eq!(stdout.lines().count(), 2_400);
+    let counts = concat!(
+        "\"malformed\":120,\"malformed_requests\":0,\"regressions\":11879,\"invalid_numbers\":0,",
+        "\"rejected\":{\"status_code\":9480}}\n",
+    );
+    assert!(stderr.ends_with(counts), "{}", stderr);
+    assert_eq!((stdout.clone(), stderr.clone()), run("1", &filter));
+    let stats = ["--summary", "stats", "--by", "status", "--metric", "bytes"];
+    assert_eq!(run("4", &stats), run("1", &stats));
+    // Mapped, whole or in ranges, the file reads the same as streamed.
+    let mapped = [&["--mmap-threshold", "0"], &filter[..]].concat();
+    assert_eq!(run("4", &mapped), (stdout.clone(), stderr.clone()));
+    assert_eq!(run("1", &mapped), (stdout, stderr));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn snapshots_show_partial_results_without_touching_stdout() {
+    let args = ["stats", "--by", "status"];
+    let output = log_parser("combined.log").args(args).args(["--snapshot-every", "3"]).assert().success();
+    let output = output.get_output();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), stdout_of(log_parser("combined.log").args(args)));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let snapshots: Vec<&str> = stderr.split("Partial result after ").skip(1).collect();
+    assert_eq!(snapshots.len(), 2, "{}", stderr);
+    assert!(snapshots[0].starts_with("3 lines"), "{}", stderr);
+    assert!(snapshots[0].ends_with("(snapshot 1):\nstatus\tcount\n200\t1\n304\t1\n401\t1\n"), "{}", stderr);
+    assert!(snapshots[1].starts_with("6 lines"), "{}", stderr);
+
+    let dir = std::env::temp_dir().join(format!("log-parser-snapshots-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("snapshots.jsonl");
+    log_parser("combined.log")
+        .args(["histogram", "--bucket", "24h", "--snapshot-every", "4", "--snapshot-file"])
+        .arg(&file)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Partial").not());
+    let records: Vec<serde_json::Value> =
+        std::fs::read_to_string(&file).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["type"], "snapshot");
+    assert_eq!(records[0]["partial"], true);
+    assert_eq!(records[0]["lines"], 4);
+    assert_eq!(records[0]["result"], serde_json::json!([{"bucket": "2023-02-12T00:00:00+00:00", "total": 4}]));
+    assert_eq!(records[1]["sequence"], 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    log_parser("combined.log").args(["stats", "--by", "status", "--snapshot-every", "0"]).assert().failure();
+}
+
+#[test]
+fn check_prints_each_rule_and_fails_if_any_does() {
+    let rules = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/rules.toml");
+    log_parser("combined.log")
+        .args(["check", "--rules"])
+        .arg(&rules)
+        .assert()
+        .code(3)
+        .stdout(concat!(
+            "rule\tresult\tobserved\tthreshold\twhere\n",
+            "error_rate\tFAIL\t0.125\tmax_ratio 0.01\t-\n",
+            "p99_bytes\tpass\t12345\tmax_p99 5000000\tpath-prefix=/images\n",
+            "abuse\tpass\t2\tmax_count 300\tip=193.105.7.171 from 2023-02-12T14:05:00+00:00\n",
+        ));
+
+    // Without the 500, every rule passes.
+    let output = stdout_of(log_parser("combined.log").args(["check", "-o", "json", "-s", "lt", "500", "--rules"]).arg(&rules));
+    let outcomes: Vec<serde_json::Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(
+        outcomes[0],
+        serde_json::json!({"rule": "error_rate", "passed": true, "threshold": "max_ratio", "limit": 0.01, "observed": 0.0})
+    );
+    assert_eq!(outcomes[2]["window_start"], "2023-02-12T14:05:00Z");
+    assert!(outcomes.iter().all(|outcome| outcome["passed"] == true));
+}
+
+#[test]
+fn batch_writes_each_job_from_one_pass() {
+    let jobs = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/jobs.toml");
+    let dir = std::env::temp_dir().join(format!("log-parser-batch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // Every job saw the 8 lines of the fixture, read once.
+    log_parser("combined.log")
+        .current_dir(&dir)
+        .args(["batch", "--jobs-file"])
+        .arg(&jobs)
+        .assert()
+        .success()
+        .stdout(concat!(
+            "job\tlines\tmatched\tfile\n",
+            "errors\t8\t1\terrors.json\n",
+            "not-found\t8\t2\tnot-found.tsv\n",
+            "large\t8\t2\tlarge.log\n",
+        ));
+    let errors = std::fs::read_to_string(dir.join("errors.json")).unwrap();
+    let errors: Vec<serde_json::Value> = errors.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(errors.len(), 1);
+    assert_eq!((&errors[0]["path"], &errors[0]["status_code"]), (&serde_json::json!("/api/orders"), &serde_json::json!(500)));
+    let not_found = std::fs::read_to_string(dir.join("not-found.tsv")).unwrap();
+    assert_eq!(not_found, "193.105.7.171\t/wp-login.php\n193.105.7.171\t/.env\n");
+    assert_eq!(std::fs::read_to_string(dir.join("large.log")).unwrap(), fixture_lines("combined.log", &[1, 8]));
+
+    // Jobs written for different log types can't share a pass.
+    let conflicting = dir.join("conflicting.toml");
+    let jobs = concat!(
+        "[alb]\nfilter = [\"--all\"]\nlog_type = \"alb\"\nfile = \"alb.log\"\n\n",
+        "[haproxy]\nfilter = [\"--all\"]\nlog_type = \"haproxy\"\nfile = \"haproxy.log\"\n",
+    );
+    std::fs::write(&conflicting, jobs).unwrap();
+    log_parser("combined.log")
+        .current_dir(&dir)
+        .args(["--log-type", "auto", "batch", "--jobs-file"])
+        .arg(&conflicting)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("line 6: haproxy: log_type haproxy conflicts with job alb's alb"));
+    assert!(!dir.join("alb.log").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn check_reports_every_malformed_rule() {
+    let dir = std::env::temp_dir().join(format!("log-parser-rules-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rules = dir.join("rules.toml");
+    std::fs::write(&rules, "slow { metric = 'duration', max_p95 = 2 }\nerrors { filter = 'status >= 500' }\nabuse { group = 'ip', window = 'soon', max_count = 300 }\n").unwrap();
+    log_parser("combined.log")
+        .args(["check", "--rules"])
+        .arg(&rules)
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(format!(
+            "Error: {0}: line 2: errors: no threshold; give one of max_ratio, max_count, max_mean or max_p<N>\n\
+             Error: {0}: line 3: abuse: window = 'soon': invalid duration \"soon\": expected a number and unit such as 250ms, 1.5s, 2m or 1h30m\n",
+            rules.display()
+        ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn extracted_fields_print_and_group_like_any_other() {
+    const VERSION: &str = r"path:^/api/v(?P<version>\d+)/(?P<resource>[a-z]+)";
+    let fields = stdout_of(log_parser("api.log").args(["--extract", VERSION, "filter", "--all", "--fields", "version,resource,status_code"]));
+    assert_eq!(fields, "1\torders\t200\n2\torders\t201\n-\t-\t200\n2\tusers\t500\n10\torders\t404\n");
+    let groups = stdout_of(log_parser("api.log").args(["--extract", VERSION, "stats", "--by", "resource,version"]));
+    assert_eq!(groups, "resource\tversion\tcount\n-\t-\t1\norders\t1\t1\norders\t10\t1\norders\t2\t1\nusers\t2\t1\n");
+    log_parser("api.log")
+        .args(["stats", "--by", "version"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::starts_with("Error: version is neither a key (status, method, path, "));
+}
+
+#[test]
+fn normalized_paths_group_together_and_print_beside_the_logged_path() {
+    let groups = stdout_of(log_parser("paths.log").args(["--normalize-paths", "stats", "--by", "path-prefix,status"]));
+    assert_eq!(groups, "path-prefix\tstatus\tcount\n/foo\t200\t5\n/Foo\t200\t1\n/foo\t404\t1\n");
+    let fields = stdout_of(log_parser("paths.log").args(["--normalize-paths", "filter", "--all", "--fields", "path,path_normalized"]));
+    assert_eq!(fields.lines().nth(3), Some("/foo/%62ar?page=2\t/foo/bar?page=2"));
+    let json = stdout_of(log_parser("paths.log").args(["filter", "--all", "-o", "json"]));
+    assert!(!json.contains("path_normalized"));
+}
+
+#[test]
+fn class_and_path_depth_group_and_print_like_other_fields() {
+    let groups = stdout_of(log_parser("combined.log").args(["stats", "--by", "class,status"]));
+    assert_eq!(groups, "class\tstatus\tcount\ndynamic\t200\t2\ndynamic\t404\t2\ndynamic\t401\t1\ndynamic\t500\t1\nstatic\t200\t1\nstatic\t304\t1\n");
+    let depths = stdout_of(log_parser("combined.log").args(["stats", "--by", "path_depth"]));
+    assert_eq!(depths, "path_depth\tcount\n1\t4\n2\t4\n");
+    let fields = stdout_of(log_parser("combined.log").args(["filter", "-s", "eq", "200", "--fields", "path,class,path_depth"]));
+    assert_eq!(fields, "/images/logo.png\tstatic\t2\n/health\tdynamic\t1\n/search?q=rust\tdynamic\t1\n");
+    let json = stdout_of(log_parser("combined.log").args(["filter", "-s", "eq", "304", "-o", "json"]));
+    let entry: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!((&entry["class"], &entry["path_depth"]), (&serde_json::json!("static"), &serde_json::json!(2)));
+    // Nothing reads them here, so they aren't derived.
+    let converted = stdout_of(log_parser("combined.log").args(["filter", "--all", "--fields", "path"]));
+    assert!(!converted.contains("static"));
+}
+
+#[test]
+fn referrers_are_classified_and_their_domains_ranked() {
+    let site = ["--site-host", "example.com"];
+    let classes = stdout_of(log_parser("referrers.log").args(site).args(["stats", "--by", "referrer-class"]));
+    assert_eq!(classes, "referrer-class\tcount\nexternal\t4\ninternal\t2\nnone\t1\nsearch\t1\nsocial\t1\n");
+    // Without --site-host the site's own pages are just another referrer.
+    let classes = stdout_of(log_parser("referrers.log").args(["stats", "--by", "referrer-class"]));
+    assert!(classes.contains("external\t6\n"), "{}", classes);
+
+    let top = stdout_of(log_parser("referrers.log").args(site).args(["top", "referrer-domain", "--referrer-class", "external"]));
+    assert_eq!(top, "referrer-domain\tcount\tshare\npartner.org\t2\t50.00\nkagi.com\t1\t25.00\nnews.partner.org\t1\t25.00\n");
+    let fields = ["filter", "--all", "--fields", "ip,referrer_class,referrer_domain"];
+    let fields = stdout_of(log_parser("referrers.log").args(site).args(fields));
+    assert_eq!(
+        [0, 1, 2, 4].map(|row| fields.lines().nth(row).unwrap()),
+        ["192.0.2.10\tsearch\tgoogle.com", "192.0.2.11\tinternal\texample.com", "192.0.2.12\tsocial\tt.co", "192.0.2.14\t-\t-"]
+    );
+    let none = stdout_of(log_parser("referrers.log").args(["filter", "--referrer-class", "none"]));
+    assert_eq!(none, fixture_lines("referrers.log", &[5]));
+    let json = stdout_of(log_parser("referrers.log").args(site).args(["filter", "--path", "eq", "/pricing", "-o", "json"]));
+    let entry: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!((entry["referrer_class"].as_str(), entry["referrer_domain"].as_str()), (Some("internal"), Some("example.com")));
+
+    // The config file adds to the known search engines.
+    let dir = std::env::temp_dir().join(format!("log-parser-referrers-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.toml");
+    std::fs::write(&config, "[referrers]\nsearch_domains = [\"kagi.com\"]\n").unwrap();
+    let search = stdout_of(log_parser("referrers.log").arg("--config").arg(&config).args(["filter", "--referrer-class=search"]));
+    assert_eq!(search, fixture_lines("referrers.log", &[1, 8]));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn gaps_are_the_time_since_the_same_clients_previous_request() {
+    // 10.0.0.1 requests every second or so, interleaved with 10.0.0.2's slower requests.
+    let gaps = stdout_of(log_parser("two-clients.log").args(["filter", "--all", "--fields", "ip,gap,path"]));
+    let expected = [
+        "10.0.0.1\t-\t/",
+        "10.0.0.2\t-\t/",
+        "10.0.0.1\t1.0\t/a",
+        "10.0.0.1\t0.0\t/b",
+        "10.0.0.1\t1.0\t/c",
+        "10.0.0.2\t30.0\t/about",
+        "10.0.0.1\t33.0\t/d",
+        "10.0.0.2\t120.0\t/contact",
+    ];
+    assert_eq!(gaps.lines().collect::<Vec<_>>(), expected);
+    // Lines that don't match still count as the client's previous request.
+    let quick = stdout_of(log_parser("two-clients.log").args(["filter", "-s", "eq", "404", "--field", "gap", "lt", "1500ms"]));
+    assert_eq!(quick, fixture_lines("two-clients.log", &[4, 5]));
+    let stats = stdout_of(log_parser("two-clients.log").args(["stats", "--by", "ip", "--metric", "gap"]));
+    assert_eq!(stats, "ip\tcount\tmean\tp50\tp95\tmax\n10.0.0.1\t5\t8.75\t1\t33\t33\n10.0.0.2\t3\t75\t30\t120\t120\n");
+    // Nothing names it here, so it isn't computed.
+    let json = stdout_of(log_parser("two-clients.log").args(["filter", "--all", "-o", "json"]));
+    assert!(!json.contains("\"gap\""));
+
+    log_parser("two-clients.log")
+        .args(["--max-clients", "1", "filter", "--field", "gap", "gte", "0", "--fields", "ip,gap"])
+        .assert()
+        .success()
+        .stdout("10.0.0.1\t1.0\n10.0.0.1\t0.0\n10.0.0.1\t1.0\n10.0.0.1\t33.0\n")
+        .stderr(predicate::str::contains("no gap for 3 entries of clients past --max-clients 1"));
+}
+
+#[test]
+fn self_test_passes_every_case_and_reports_the_platform() {
+    let table = stdout_of(assert_cmd::cargo::cargo_bin_cmd!("cli-parser").arg("self-test"));
+    assert!(table.starts_with("log-parser "), "{}", table);
+    assert!(table.contains("\nparse\tw3c\tpass\t\n"), "{}", table);
+    assert!(table.ends_with(" passed, 0 failed\n"), "{}", table);
+    let json = stdout_of(assert_cmd::cargo::cargo_bin_cmd!("cli-parser").args(["--schema-version", "self-test", "-o", "json"]));
+    let mut lines = json.lines();
+    assert_eq!(lines.next(), Some("{\"schema_version\":27}"));
+    let report: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+    assert_eq!(report["failed"], 0);
+    assert_eq!(report["platform"]["os"], std::env::consts::OS);
+    assert!(report["cases"].as_array().unwrap().iter().any(|case| case["suite"] == "filter"));
+    log_parser("combined.log").arg("self-test").assert().failure().stderr(predicate::str::contains("self-test takes no input files"));
+}
+
+#[test]
+fn verbose_matches_name_each_given_filter_and_its_value() {
+    let args = [
+        "filter", "-s", "gte", "400", "--ip", "in", "193.105.7.0/24", "--path", "starts_with", "/wp", "--where", "bytes > 100",
+        "--exclude", "path contains .env", "--verbose-matches",
+    ];
+    let lines = stdout_of(log_parser("combined.log").args(args));
+    let expected = format!(
+        "{} [matched: status_code=404 (gte 400), ip=193.105.7.171 (in 193.105.7.0/24), path=/wp-login.php (starts_with \"/wp\"), \
+         bytes=162 (where bytes > 100)]\n",
+        fixture_lines("combined.log", &[4]).trim_end()
+    );
+    assert_eq!(lines, expected);
+    let json = stdout_of(log_parser("combined.log").args(args).args(["-o", "json"]));
+    let entry: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        entry["matched_by"],
+        serde_json::json!({
+            "status_code": {"value": 404, "condition": "gte 400"},
+            "ip": {"value": "193.105.7.171", "condition": "in 193.105.7.0/24"},
+            "path": {"value": "/wp-login.php", "condition": "starts_with \"/wp\""},
+            "bytes": {"value": 162, "condition": "where bytes > 100"},
+        })
+    );
+    // Without the flag, nothing is added.
+    let plain = stdout_of(log_parser("combined.log").args(&args[..args.len() - 1]));
+    assert_eq!(plain, fixture_lines("combined.log", &[4]));
+
+    // A fuzzy match comes with its distance.
+    let fuzzy = ["filter", "--path", "fuzzy", "wp-logn:2", "--verbose-matches"];
+    let lines = stdout_of(log_parser("combined.log").args(fuzzy));
+    assert!(lines.ends_with(" [matched: path=/wp-login.php (fuzzy \"wp-logn\":2, distance 1)]\n"), "{}", lines);
+    let json = stdout_of(log_parser("combined.log").args(fuzzy).args(["-o", "json"]));
+    let entry: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        entry["matched_by"],
+        serde_json::json!({"path": {"value": "/wp-login.php", "condition": "fuzzy \"wp-logn\":2", "distance": 1}})
+    );
+}
+
+#[test]
+fn aggregations_print_the_same_bytes_however_the_input_is_shuffled() {
+    let dir = std::env::temp_dir().join(format!("log-parser-shuffled-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let original = [fixture("combined.log"), fixture("auth.log")].map(|file| std::fs::read_to_string(file).unwrap()).concat();
+    let lines: Vec<&str> = original.lines().collect();
+    // Every third line from each of the first three, then all of it backwards.
+    let shuffled: Vec<&str> = (0..3).flat_map(|start| lines.iter().skip(start).step_by(3)).rev().copied().collect();
+    let (ordered, reordered) = (dir.join("ordered.log"), dir.join("shuffled.log"));
+    std::fs::write(&ordered, lines.join("\n") + "\n").unwrap();
+    std::fs::write(&reordered, shuffled.join("\n") + "\n").unwrap();
+
+    for args in [
+        &["top", "path", "-n", "100"][..],
+        &["top", "ip", "--sort", "key", "--reverse", "-o", "json"],
+        &["stats", "--by", "status,path-prefix", "--metric", "bytes"],
+        &["stats", "--by", "ip,status", "--pivot"],
+        &["cardinality", "--of", "path", "--by", "ip"],
+        &["cardinality", "--of", "path", "--by", "status", "--exact", "--sort", "key", "-o", "json"],
+    ] {
+        let run = |file: &std::path::Path| stdout_of(assert_cmd::cargo::cargo_bin_cmd!("cli-parser").arg(file).args(args));
+        let expected = run(&ordered);
+        // A second process hashes with other random keys, which mustn't show in the output.
+        assert_eq!(run(&ordered), expected, "{:?}", args);
+        assert_eq!(run(&reordered), expected, "{:?}", args);
+    }
+    let top = stdout_of(assert_cmd::cargo::cargo_bin_cmd!("cli-parser").arg(&ordered).args(["top", "status"]));
+    assert_eq!(top, "status\tcount\tshare\n401\t16\t57.14\n200\t8\t28.57\n404\t2\t7.14\n304\t1\t3.57\n500\t1\t3.57\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn inspect_prints_one_match_a_field_per_line() {
+    let path = fixture("api.log");
+    let expected = format!(
+        "match 2
+  ip           197.78.33.109
+  timestamp    2023-02-12T14:04:10Z
+  method       POST
+  path         /api/v2/orders
+  protocol     HTTP/1.1
+  status_code  201
+  bytes        128
+  user_agent   python-requests/2.28.1
+  source_file  {}
+  line_no      2
+  byte_offset  100
+  line         {}",
+        path.display(),
+        fixture_lines("api.log", &[2])
+    );
+    assert_eq!(stdout_of(log_parser("api.log").args(["filter", "--all", "--inspect", "2"])), expected);
+
+    // Counting back from the last match, here the fifth of five.
+    let last = stdout_of(log_parser("api.log").args(["filter", "--all", "--inspect", "-1"]));
+    assert!(last.starts_with("match 5\n") && last.contains("  line_no      5\n"), "{}", last);
+    // Fields particular to the input, such as CSV columns with no entry field, are listed too.
+    let csv = stdout_of(log_parser("siem.csv").args(["--log-type", "csv", "filter", "--all", "--inspect", "1"]));
+    assert!(csv.contains("\n  region       eu-west\n"), "{}", csv);
+
+    for index in ["6", "-6"] {
+        log_parser("api.log")
+            .args(["filter", "--all", "--inspect", index])
+            .assert()
+            .code(2)
+            .stdout("")
+            .stderr(predicate::str::contains(format!("--inspect {} is out of range, there were 5 matches", index)));
+    }
+    log_parser("api.log").args(["filter", "--all", "--inspect", "0"]).assert().code(2);
+    log_parser("api.log").args(["filter", "--all", "--inspect", "1", "-o", "json"]).assert().code(2);
+}
+
+#[test]
+fn reports_count_days_in_the_timezone_across_a_clock_change() {
+    // Central European time, as Europe/Berlin has it; October 27 2024 has 25 hours.
+    let berlin = ["--tz", "CET-1CEST,M3.5.0,M10.5.0/3", "--site-host", "example.com"];
+    let report = stdout_of(log_parser("week.log").args(berlin).arg("report"));
+    assert_eq!(
+        report,
+        "Requests per day\nday\trequests\tbytes\t5xx\t5xx rate\n\
+         2024-10-26\t1\t1000\t0\t0.0%\n2024-10-27\t5\t9800\t1\t20.0%\n2024-10-28\t3\t2000\t1\t33.3%\ntotal\t9\t12800\t2\t22.2%\n\
+         \n\
+         Top pages\npage\trequests\tshare\n/pricing\t5\t55.6%\n/docs\t2\t22.2%\n/\t1\t11.1%\n\
+         \n\
+         Top external referrers\ndomain\trequests\tshare\nnews.example.net\t2\t22.2%\nblog.other.org\t1\t11.1%\n\
+         \n\
+         Top user-agent families\nfamily\trequests\tshare\nChrome\t3\t33.3%\nFirefox\t3\t33.3%\n\
+         Googlebot\t1\t11.1%\nSafari\t1\t11.1%\ncurl\t1\t11.1%\n\
+         \n\
+         Peak hour\nhour\trequests\tshare\n15:00\t3\t33.3%\n"
+    );
+    // Neither of the zone's offsets on its own puts the same requests on the same days.
+    for (tz, days) in [("+01:00", [2, 4, 3]), ("+02:00", [1, 4, 4])] {
+        let json = stdout_of(log_parser("week.log").args(["--tz", tz, "report", "-o", "json"]));
+        let requests: Vec<u64> = json
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|row| row["section"] == "days")
+            .map(|row| row["requests"].as_u64().unwrap())
+            .collect();
+        assert_eq!(requests, days, "days in {}", tz);
+    }
+    // The zone from the system's database, where it has one, and stats keys follow it too.
+    if std::path::Path::new("/usr/share/zoneinfo/Europe/Berlin").is_file() {
+        let named = stdout_of(log_parser("week.log").args(["--tz", "Europe/Berlin", "--site-host", "example.com", "report"]));
+        assert_eq!(named, report);
+        let days = stdout_of(log_parser("week.log").args(["--tz", "Europe/Berlin", "stats", "--by", "day"]));
+        assert_eq!(days, "day\tcount\n2024-10-27\t5\n2024-10-28\t3\n2024-10-26\t1\n");
+    }
+
+    // Filters narrow the report, and Markdown heads each section.
+    let errors = ["report", "--status-code", "gte", "500", "-o", "markdown"];
+    let markdown = stdout_of(log_parser("week.log").args(berlin).args(errors));
+    assert!(
+        markdown.starts_with(
+            "## Requests per day\n\n| day | requests | bytes | 5xx | 5xx rate |\n| --- | ---: | ---: | ---: | ---: |\n\
+             | 2024-10-27 | 1 | 300 | 1 | 100.0% |\n| 2024-10-28 | 1 | 200 | 1 | 100.0% |\n\
+             | total | 2 | 500 | 2 | 100.0% |\n\n## Top pages\n"
+        ),
+        "{}",
+        markdown
+    );
+    let referrers = "## Top external referrers\n\n| domain | requests | share |\n| --- | --- | --- |\n\nno referrers\n";
+    assert!(markdown.contains(referrers), "{}", markdown);
+
+    log_parser("week.log").args(["--tz", "Mars/Olympus_Mons", "report"]).assert().failure().stderr(predicate::str::contains(
+        "unknown timezone \"Mars/Olympus_Mons\", expected UTC, an offset like +02:00 or a zone like Europe/Berlin",
+    ));
+}
+
+#[test]
+fn html_pages_hold_the_tables_with_every_value_escaped() {
+    // Just enough parsing for the tests: the rows of each table body, and the bars of charts.
+    let body_rows = |page: &str| -> Vec<usize> {
+        page.split("<tbody>").skip(1).map(|body| body.split("</tbody>").next().unwrap().matches("<tr").count()).collect()
+    };
+    let hostile = "&lt;script&gt;alert(&#39;agent&#39;)&lt;/script&gt;";
+
+    let top = stdout_of(log_parser("hostile-agents.log").args(["top", "user-agent", "-o", "html"]));
+    assert!(top.starts_with("<!DOCTYPE html>\n") && top.ends_with("</html>\n"), "{}", top);
+    assert_eq!(body_rows(&top), [3]);
+    assert_eq!(top.matches("<rect").count(), 3);
+    assert!(top.contains(&format!("<tr><td>{}</td><td class=\"number\">2</td>", hostile)), "{}", top);
+    assert!(top.contains("<td>Mozilla/5.0 &amp; friends</td>"));
+    // The script stays text, and nothing is loaded from elsewhere.
+    assert!(!top.contains("<script") && !top.contains("src="), "{}", top);
+    assert!(top.contains("by <code>") && top.contains("top user-agent -o html</code></footer>"));
+
+    // Example lines are escaped too.
+    let examples = ["stats", "--by", "user-agent", "--examples", "1", "-o", "html"];
+    let stats = stdout_of(log_parser("hostile-agents.log").args(examples));
+    assert_eq!(body_rows(&stats), [6]);
+    assert!(stats.contains(&format!("<td>{}</td>", hostile)));
+    assert!(stats.contains(&format!(" &quot;{}&quot;</td></tr>", hostile)), "{}", stats);
+    assert!(stats.contains("<title>Matches by user-agent</title>"));
+
+    // A bucket an hour, the one in between empty, drawn as a column each.
+    let histogram = stdout_of(log_parser("hostile-agents.log").args(["histogram", "-o", "html"]));
+    assert_eq!(body_rows(&histogram), [2]);
+    assert_eq!(histogram.matches("<rect").count(), 2);
+    assert!(!histogram.contains("<th>bar</th>"));
+
+    // --output-file takes the page, leaving stdout empty.
+    let dir = std::env::temp_dir().join(format!("log-parser-html-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("report.html");
+    let args = ["report", "-o", "html", "--output-file", path.to_str().unwrap()];
+    log_parser("hostile-agents.log").args(args).assert().success().stdout("");
+    let report = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(report.matches("<h2>").count(), 5);
+    assert_eq!(body_rows(&report), [2, 3, 1, 2, 1]);
+    assert!(!report.contains("<script"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    log_parser("hostile-agents.log").args(["cardinality", "--of", "ip", "-o", "html"]).assert().failure();
+}